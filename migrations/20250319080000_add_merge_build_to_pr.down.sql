@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE pull_request DROP CONSTRAINT IF EXISTS fk_merge_build_id;
+ALTER TABLE pull_request DROP COLUMN merge_build_id;
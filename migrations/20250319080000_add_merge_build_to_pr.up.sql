@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE pull_request ADD COLUMN merge_build_id INT;
+ALTER TABLE pull_request
+    ADD CONSTRAINT fk_merge_build_id FOREIGN KEY (merge_build_id) REFERENCES build(id);
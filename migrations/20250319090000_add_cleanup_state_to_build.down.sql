@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE build DROP COLUMN branches_deleted;
+ALTER TABLE build DROP COLUMN completed_at;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE build ADD COLUMN completed_at TIMESTAMPTZ;
+ALTER TABLE build ADD COLUMN branches_deleted BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE build
+SET completed_at = created_at
+WHERE status <> 'pending';
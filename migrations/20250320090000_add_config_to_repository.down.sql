@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE repository DROP COLUMN config_sha;
+ALTER TABLE repository DROP COLUMN config;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE repository ADD COLUMN config TEXT;
+ALTER TABLE repository ADD COLUMN config_sha TEXT;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS try_queue_pull_request_id_idx;
+
+DROP TABLE IF EXISTS try_queue;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE try_queue
+(
+    id              SERIAL PRIMARY KEY,
+    pull_request_id INT         NOT NULL REFERENCES pull_request (id) ON DELETE CASCADE,
+    parent          TEXT,
+    jobs            TEXT[]      NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS try_queue_pull_request_id_idx ON try_queue (pull_request_id);
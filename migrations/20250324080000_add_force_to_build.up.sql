@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE build ADD COLUMN force BOOLEAN NOT NULL DEFAULT FALSE;
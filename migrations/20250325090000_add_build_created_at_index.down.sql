@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS build_repository_status_created_at_idx;
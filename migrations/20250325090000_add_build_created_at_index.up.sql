@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE INDEX IF NOT EXISTS build_repository_status_created_at_idx ON build (repository, status, created_at);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE status_comment
+(
+    pull_request_id INT         NOT NULL PRIMARY KEY REFERENCES pull_request (id) ON DELETE CASCADE,
+    comment_id      BIGINT      NOT NULL,
+    history         TEXT[]      NOT NULL,
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE pull_request ADD COLUMN delegated BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE pull_request SET delegated = TRUE WHERE delegated_permission = 'review';
+ALTER TABLE pull_request DROP COLUMN delegated_permission;
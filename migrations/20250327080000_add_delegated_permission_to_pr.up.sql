@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE pull_request ADD COLUMN delegated_permission TEXT;
+UPDATE pull_request SET delegated_permission = 'review' WHERE delegated;
+ALTER TABLE pull_request DROP COLUMN delegated;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE outdatable_comment
+(
+    pull_request_id INT         NOT NULL REFERENCES pull_request (id) ON DELETE CASCADE,
+    kind            TEXT        NOT NULL,
+    node_id         TEXT        NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (pull_request_id, kind)
+);
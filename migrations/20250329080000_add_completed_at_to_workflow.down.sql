@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE workflow DROP COLUMN completed_at;
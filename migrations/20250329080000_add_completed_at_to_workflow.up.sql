@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE workflow ADD COLUMN completed_at TIMESTAMPTZ;
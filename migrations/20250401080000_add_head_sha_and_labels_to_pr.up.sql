@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE pull_request ADD COLUMN head_sha TEXT;
+ALTER TABLE pull_request ADD COLUMN labels TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE build DROP COLUMN pull_request_id;
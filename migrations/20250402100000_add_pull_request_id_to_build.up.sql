@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE build ADD COLUMN pull_request_id INT REFERENCES pull_request(id) ON DELETE SET NULL;
+
+UPDATE build
+SET pull_request_id = pr.id
+FROM pull_request AS pr
+WHERE pr.build_id = build.id OR pr.merge_build_id = build.id;
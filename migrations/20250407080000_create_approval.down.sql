@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS approval_pull_request_id_approver_idx;
+
+DROP TABLE IF EXISTS approval;
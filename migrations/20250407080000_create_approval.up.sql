@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE approval
+(
+    id              SERIAL PRIMARY KEY,
+    pull_request_id INT         NOT NULL REFERENCES pull_request (id) ON DELETE CASCADE,
+    approver        TEXT        NOT NULL,
+    sha             TEXT        NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS approval_pull_request_id_approver_idx ON approval (pull_request_id, approver);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+ALTER TABLE pull_request ADD COLUMN approved_by TEXT;
+
+UPDATE pull_request AS pr
+SET approved_by = (
+    SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+    FROM approval
+    WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+)
+WHERE pr.approved_sha IS NOT NULL;
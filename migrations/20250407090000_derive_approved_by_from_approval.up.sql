@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- The reviewers of a PR are now derived from its approvals
+INSERT INTO approval (pull_request_id, approver, sha)
+SELECT pr.id, TRIM(approver), pr.approved_sha
+FROM pull_request AS pr, regexp_split_to_table(pr.approved_by, ',') AS approver
+WHERE pr.approved_by IS NOT NULL AND pr.approved_sha IS NOT NULL
+ON CONFLICT (pull_request_id, approver) DO UPDATE SET sha = EXCLUDED.sha;
+
+ALTER TABLE pull_request DROP COLUMN approved_by;
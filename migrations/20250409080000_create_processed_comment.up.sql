@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE processed_comment
+(
+    comment_id        BIGINT PRIMARY KEY,
+    body_hash         TEXT        NOT NULL,
+    executed_commands INT         NOT NULL,
+    processed_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
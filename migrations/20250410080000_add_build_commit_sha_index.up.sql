@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE INDEX IF NOT EXISTS build_repository_commit_sha_idx ON build (repository, commit_sha);
@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE event_queue
+(
+    id           SERIAL PRIMARY KEY,
+    repository   TEXT        NOT NULL,
+    delivery_id  TEXT        NOT NULL,
+    event_type   TEXT        NOT NULL,
+    payload      BYTEA       NOT NULL,
+    status       TEXT        NOT NULL DEFAULT 'pending',
+    attempts     INT         NOT NULL DEFAULT 0,
+    error        TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    processed_at TIMESTAMPTZ,
+    UNIQUE (delivery_id)
+);
+
+CREATE INDEX IF NOT EXISTS event_queue_status_idx ON event_queue (status, id);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE repository ADD COLUMN paused BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE build ADD COLUMN jobs TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE pull_request DROP COLUMN delegated_at;
+ALTER TABLE pull_request DROP COLUMN delegated_by;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE pull_request ADD COLUMN delegated_by TEXT;
+ALTER TABLE pull_request ADD COLUMN delegated_at TIMESTAMPTZ;
+-- The expiration period of existing delegations starts now
+UPDATE pull_request SET delegated_at = NOW() WHERE delegated_permission IS NOT NULL;
@@ -1,96 +1,469 @@
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::io::IsTerminal;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
 use bors::{
-    BorsContext, BorsGlobalEvent, CommandParser, PgDbClient, ServerState, TeamApiClient,
-    WebhookSecret, create_app, create_bors_process, create_github_client, load_repositories,
+    BorsContext, BorsGlobalEvent, CommandParser, DEFAULT_WEBHOOK_BODY_LIMIT, GithubRepoName,
+    HttpPermissionClient, PermissionSource, PgDbClient, ServerState, TeamApiClient, WebhookSecret,
+    create_app, create_bors_process, create_github_client, load_command_prefix, load_repositories,
+    load_repository_client, parse_private_key, read_private_key_file, sync_pull_requests,
 };
-use clap::Parser;
-use sqlx::postgres::PgConnectOptions;
+use clap::{CommandFactory, Parser};
+use secrecy::SecretString;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::{ConnectOptions, PgPool};
+use tokio::net::TcpListener;
+use tracing::Subscriber;
 use tracing::log::LevelFilter;
 use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, fmt};
 
 /// How often should the bot check DB state, e.g. for handling timeouts.
 const PERIODIC_REFRESH: Duration = Duration::from_secs(120);
 
 #[derive(clap::Parser)]
-struct Opts {
+struct Cli {
+    /// Format of the log output.
+    #[arg(
+        long,
+        env = "LOG_FORMAT",
+        value_enum,
+        default_value_t = LogFormat::Text,
+        global = true
+    )]
+    log_format: LogFormat,
+
+    /// Minimum level of the logged events (e.g. `debug` or `warn`).
+    /// Directives in the `RUST_LOG` environment variable take precedence over it.
+    #[arg(
+        long,
+        env = "LOG_LEVEL",
+        default_value_t = tracing::Level::INFO,
+        global = true
+    )]
+    log_level: tracing::Level,
+
+    /// Sentry DSN to which handler errors and panics are reported.
+    /// Errors are not reported if it is not set.
+    #[cfg(feature = "sentry")]
+    #[arg(long, env = "SENTRY_DSN", global = true)]
+    sentry_dsn: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, including the fields of all active spans.
+    Json,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run the bot (this is the default if no subcommand is given).
+    Serve(ServeOpts),
+    /// Apply pending database migrations and exit.
+    Migrate(DatabaseOpts),
+    /// Check that the bot can be started with the given configuration, without starting it.
+    Check(CheckOpts),
+    /// Store the open pull requests of a repository in the database, without posting comments.
+    Sync(SyncOpts),
+}
+
+#[derive(clap::Args)]
+struct GithubAppOpts {
     /// Github App ID.
     #[arg(long, env = "APP_ID")]
     app_id: u64,
 
     /// Private key used to authenticate as a Github App.
-    #[arg(long, env = "PRIVATE_KEY")]
-    private_key: String,
+    /// Escaped newlines (`\n`) are replaced with real newlines.
+    #[arg(
+        long,
+        env = "PRIVATE_KEY",
+        required_unless_present = "private_key_file"
+    )]
+    private_key: Option<String>,
 
-    /// Secret used to authenticate webhooks.
-    #[arg(long, env = "WEBHOOK_SECRET")]
-    webhook_secret: String,
+    /// Path to a file with the private key used to authenticate as a Github App.
+    /// Takes precedence over `--private-key`.
+    #[arg(long, env = "PRIVATE_KEY_FILE")]
+    private_key_file: Option<PathBuf>,
+
+    /// Base URL of the GitHub REST API.
+    /// For GitHub Enterprise Server, this is usually `https://<host>/api/v3`.
+    #[arg(long, env = "GITHUB_API_URL", default_value = "https://api.github.com")]
+    github_api_url: String,
+}
+
+impl GithubAppOpts {
+    /// Loads and validates the private key, preferring the key file if it was configured.
+    fn private_key(&self) -> anyhow::Result<SecretString> {
+        match (&self.private_key_file, &self.private_key) {
+            (Some(path), _) => read_private_key_file(path),
+            (None, Some(key)) => parse_private_key(key),
+            (None, None) => Err(anyhow::anyhow!(
+                "Either `--private-key` or `--private-key-file` has to be set"
+            )),
+        }
+    }
+}
 
+#[derive(clap::Args)]
+struct DatabaseOpts {
     /// Database connection string.
     #[arg(long, env = "DATABASE_URL")]
     db: String,
 
-    /// Prefix used for bot commands in PR comments.
-    #[arg(long, env = "CMD_PREFIX", default_value = "@bors")]
-    cmd_prefix: String,
+    /// Maximum number of connections in the database pool.
+    #[arg(long, env = "DB_MAX_CONNECTIONS", default_value_t = 10)]
+    db_max_connections: u32,
+
+    /// How long (in seconds) does an operation wait for a connection from the database pool
+    /// before it fails. The bot also fails to start if it cannot connect within this time.
+    #[arg(long, env = "DB_ACQUIRE_TIMEOUT", default_value_t = 30)]
+    db_acquire_timeout: u64,
+
+    /// How long (in seconds) can a single database statement run before it is cancelled by
+    /// the database. Statements are not limited if it is not set.
+    #[arg(long, env = "DB_STATEMENT_TIMEOUT")]
+    db_statement_timeout: Option<u64>,
+}
+
+impl DatabaseOpts {
+    fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.db_max_connections)
+            .acquire_timeout(Duration::from_secs(self.db_acquire_timeout))
+    }
+
+    fn connect_options(&self) -> anyhow::Result<PgConnectOptions> {
+        let mut opts: PgConnectOptions = self
+            .db
+            .parse()
+            .context("Cannot parse database connection string")?;
+        opts = opts.log_statements(LevelFilter::Trace);
+        if let Some(timeout) = self.db_statement_timeout {
+            opts = opts.options([("statement_timeout", format!("{timeout}s"))]);
+        }
+        Ok(opts)
+    }
+}
+
+#[derive(clap::Args)]
+struct ServeOpts {
+    #[command(flatten)]
+    app: GithubAppOpts,
+
+    #[command(flatten)]
+    database: DatabaseOpts,
+
+    /// Secret used to authenticate webhooks.
+    #[arg(long, env = "WEBHOOK_SECRET")]
+    webhook_secret: String,
+
+    /// Prefix used for bot commands in PR comments, e.g. `@bors`.
+    /// If it is not set, the mention of the bot account of the GitHub App is used.
+    #[arg(long, env = "CMD_PREFIX")]
+    cmd_prefix: Option<String>,
+
+    /// Base URL of the GitHub web UI, used to generate links.
+    #[arg(long, env = "GITHUB_HTML_URL", default_value = "https://github.com")]
+    github_html_url: String,
+
+    /// Address on which the HTTP server listens.
+    #[arg(long = "bind", env = "BIND_ADDR", default_value = "0.0.0.0:8080")]
+    bind_addr: SocketAddr,
+
+    /// Maximum size (in bytes) of a webhook body, larger webhooks are rejected with 413.
+    #[arg(long, env = "WEBHOOK_BODY_LIMIT", default_value_t = DEFAULT_WEBHOOK_BODY_LIMIT)]
+    webhook_body_limit: usize,
+
+    /// Maximum number of webhook events that are handled at once.
+    /// Events of a single PR are always handled one after another.
+    #[arg(long, env = "MAX_PARALLEL_EVENTS", default_value_t = 16)]
+    max_parallel_events: usize,
+
+    /// Bearer token used to authenticate requests to the admin endpoints.
+    /// Admin endpoints are disabled if it is not set.
+    #[arg(long, env = "ADMIN_TOKEN")]
+    admin_token: Option<String>,
+
+    /// Base URL of an HTTP endpoint that serves review and try permissions of repositories at
+    /// `<url>/permissions/<repo>.json`. If it is not set, permissions are loaded from the Rust
+    /// Team API.
+    #[arg(long, env = "PERMISSIONS_URL")]
+    permissions_url: Option<String>,
+
+    /// How long (in seconds) are permissions loaded from `PERMISSIONS_URL` cached before they
+    /// are refreshed.
+    #[arg(long, env = "PERMISSIONS_TTL", default_value = "300")]
+    permissions_ttl: u64,
+
+    /// Handle webhooks without modifying repositories on GitHub.
+    /// Comments, label changes, branch updates and workflow cancellations are only logged,
+    /// while the database is updated as usual.
+    #[arg(long, env = "DRY_RUN")]
+    dry_run: bool,
+
+    /// Do not apply pending database migrations on startup.
+    /// Migrations can be applied separately with the `migrate` subcommand.
+    #[arg(long, env = "SKIP_MIGRATIONS")]
+    skip_migrations: bool,
+}
+
+#[derive(clap::Args)]
+struct CheckOpts {
+    #[command(flatten)]
+    app: GithubAppOpts,
+
+    #[command(flatten)]
+    database: DatabaseOpts,
+
+    /// Secret used to authenticate webhooks.
+    #[arg(long, env = "WEBHOOK_SECRET")]
+    webhook_secret: String,
+}
+
+#[derive(clap::Args)]
+struct SyncOpts {
+    #[command(flatten)]
+    app: GithubAppOpts,
+
+    #[command(flatten)]
+    database: DatabaseOpts,
+
+    /// Repository whose pull requests should be synchronized, e.g. `rust-lang/rust`.
+    repo: GithubRepoName,
+
+    /// Base URL of the GitHub web UI, used to generate links.
+    #[arg(long, env = "GITHUB_HTML_URL", default_value = "https://github.com")]
+    github_html_url: String,
 }
 
 /// Starts a server that receives GitHub webhooks and generates events into a queue
 /// that is then handled by the Bors process.
-async fn webhook_server(state: ServerState) -> anyhow::Result<()> {
+async fn webhook_server(state: ServerState, listener: TcpListener) -> anyhow::Result<()> {
     let app = create_app(state);
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .context("Cannot create TCP/IP server socket")?;
-
-    tracing::info!("Listening on 0.0.0.0:{}", listener.local_addr()?.port());
+    tracing::info!("Listening on {}", listener.local_addr()?);
 
     axum::serve(listener, app).await?;
     Ok(())
 }
 
-async fn initialize_db(connection_string: &str) -> anyhow::Result<PgDbClient> {
-    let mut opts: PgConnectOptions = connection_string.parse()?;
-    opts = opts.log_statements(LevelFilter::Trace);
-    let db = PgPool::connect_with(opts)
-        .await
-        .context("Cannot connect to database")?;
+/// Creates the database pool and makes sure that it can get at least a single connection
+/// within the acquire timeout, so that an unreachable database is reported on startup.
+async fn connect_db(opts: &DatabaseOpts) -> anyhow::Result<PgPool> {
+    let pool = opts
+        .pool_options()
+        .connect_lazy_with(opts.connect_options()?);
+    let deadline = Duration::from_secs(opts.db_acquire_timeout);
+    match tokio::time::timeout(deadline, pool.acquire()).await {
+        Ok(Ok(_)) => Ok(pool),
+        Ok(Err(error)) => Err(error).context("Cannot connect to database"),
+        Err(_) => Err(anyhow::anyhow!(
+            "Cannot connect to database: no connection was established within {}s",
+            opts.db_acquire_timeout
+        )),
+    }
+}
 
+async fn run_migrations(db: &PgPool) -> anyhow::Result<()> {
     sqlx::migrate!()
-        .run(&db)
+        .run(db)
         .await
-        .context("Cannot run database migrations")?;
+        .context("Cannot run database migrations")
+}
 
-    Ok(PgDbClient::new(db))
+async fn initialize_db(opts: &DatabaseOpts, skip_migrations: bool) -> anyhow::Result<PgPool> {
+    let db = connect_db(opts).await?;
+    if skip_migrations {
+        tracing::info!("Skipping database migrations");
+    } else {
+        run_migrations(&db).await?;
+    }
+    Ok(db)
 }
 
-fn try_main(opts: Opts) -> anyhow::Result<()> {
+fn try_main(cli: Cli) -> anyhow::Result<()> {
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .context("Cannot build tokio runtime")?;
 
-    let db = runtime
-        .block_on(initialize_db(&opts.db))
+    match cli.command {
+        Command::Serve(opts) => serve(&runtime, opts),
+        Command::Migrate(opts) => runtime.block_on(migrate(opts)),
+        Command::Check(opts) => runtime.block_on(check(opts)),
+        Command::Sync(opts) => runtime.block_on(sync(opts)),
+    }
+}
+
+/// Applies pending database migrations.
+async fn migrate(opts: DatabaseOpts) -> anyhow::Result<()> {
+    let db = connect_db(&opts).await?;
+    run_migrations(&db).await?;
+    tracing::info!("Database migrations were applied");
+    Ok(())
+}
+
+/// Backfills the open pull requests of a single repository into the database.
+async fn sync(opts: SyncOpts) -> anyhow::Result<()> {
+    let private_key = opts.app.private_key()?;
+    let db = PgDbClient::new(connect_db(&opts.database).await?);
+    let client =
+        create_github_client(opts.app.app_id.into(), opts.app.github_api_url, private_key)?;
+    let repo_client = load_repository_client(&client, &opts.repo, &opts.github_html_url).await?;
+
+    let summary = sync_pull_requests(&repo_client, &db).await?;
+    println!(
+        "Synchronized pull requests of {}: {} created, {} updated",
+        opts.repo, summary.created, summary.updated
+    );
+    Ok(())
+}
+
+/// Result of a single check performed by the `check` subcommand.
+struct CheckResult {
+    name: &'static str,
+    /// Details of a successful check, or the reason why it failed.
+    result: anyhow::Result<String>,
+}
+
+/// Checks that the configuration of the bot is usable and prints the results as a table.
+/// Fails if any of the checks has failed.
+async fn check(opts: CheckOpts) -> anyhow::Result<()> {
+    let mut results = vec![];
+
+    let client = opts.app.private_key().and_then(|private_key| {
+        create_github_client(
+            opts.app.app_id.into(),
+            opts.app.github_api_url.clone(),
+            private_key,
+        )
+    });
+    match client {
+        Ok(client) => {
+            results.push(CheckResult {
+                name: "Private key",
+                result: Ok("parsed".to_string()),
+            });
+            let app = client
+                .current()
+                .app()
+                .await
+                .with_context(|| format!("Cannot authenticate as app {}", opts.app.app_id));
+            results.push(CheckResult {
+                name: "GitHub App",
+                result: app.map(|app| format!("authenticated as `{}`", app.name)),
+            });
+        }
+        Err(error) => {
+            results.push(CheckResult {
+                name: "Private key",
+                result: Err(error),
+            });
+            results.push(CheckResult {
+                name: "GitHub App",
+                result: Err(anyhow::anyhow!("skipped, the private key is invalid")),
+            });
+        }
+    }
+
+    results.push(CheckResult {
+        name: "Webhook secret",
+        result: if opts.webhook_secret.trim().is_empty() {
+            Err(anyhow::anyhow!("the secret is empty"))
+        } else {
+            Ok("set".to_string())
+        },
+    });
+
+    let db = async {
+        let db = connect_db(&opts.database).await?;
+        sqlx::query("SELECT 1")
+            .execute(&db)
+            .await
+            .context("Cannot query database")?;
+        Ok("reachable".to_string())
+    };
+    results.push(CheckResult {
+        name: "Database",
+        result: db.await,
+    });
+
+    print_check_results(&results);
+    let failed = results.iter().filter(|r| r.result.is_err()).count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{failed} check(s) failed"));
+    }
+    Ok(())
+}
+
+fn print_check_results(results: &[CheckResult]) {
+    let name_width = results.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    for CheckResult { name, result } in results {
+        let (status, details) = match result {
+            Ok(details) => ("OK", details.clone()),
+            Err(error) => ("FAILED", format!("{error:#}")),
+        };
+        println!("{name:<name_width$}  {status:<6}  {details}");
+    }
+}
+
+fn serve(runtime: &tokio::runtime::Runtime, opts: ServeOpts) -> anyhow::Result<()> {
+    // Validate the private key before doing anything else, so that a misconfigured key
+    // is reported before any network connection is made.
+    let private_key = opts.app.private_key()?;
+
+    // Bind the server socket early, so that we fail fast if the address is not usable.
+    let listener = runtime
+        .block_on(TcpListener::bind(opts.bind_addr))
+        .with_context(|| format!("Cannot bind HTTP server to {}", opts.bind_addr))?;
+
+    let db_pool = runtime
+        .block_on(initialize_db(&opts.database, opts.skip_migrations))
         .context("Cannot initialize database")?;
-    let team_api = TeamApiClient::default();
-    let (client, loaded_repos) = runtime.block_on(async {
-        let client = create_github_client(
-            opts.app_id.into(),
-            "https://api.github.com".to_string(),
-            opts.private_key.into(),
-        )?;
-        let repos = load_repositories(&client, &team_api).await?;
-        Ok::<_, anyhow::Error>((client, repos))
+    let db = PgDbClient::new(db_pool.clone());
+    let permission_source: PermissionSource = match opts.permissions_url {
+        Some(url) => {
+            HttpPermissionClient::new(url, Duration::from_secs(opts.permissions_ttl)).into()
+        }
+        None => TeamApiClient::default().into(),
+    };
+    let (client, loaded_repos, cmd_prefix) = runtime.block_on(async {
+        let client =
+            create_github_client(opts.app.app_id.into(), opts.app.github_api_url, private_key)?;
+        let repos = load_repositories(
+            &client,
+            &permission_source,
+            &opts.github_html_url,
+            Some(&db),
+            opts.dry_run,
+        )
+        .await?;
+        let cmd_prefix = match opts.cmd_prefix {
+            Some(prefix) => prefix,
+            None => load_command_prefix(&client).await?,
+        };
+        Ok::<_, anyhow::Error>((client, repos, cmd_prefix))
     })?;
+    tracing::info!("Using command prefix {cmd_prefix}");
+    if opts.dry_run {
+        tracing::warn!("Running in dry-run mode, repositories on GitHub will not be modified");
+    }
 
     let mut repos = HashMap::default();
     for (name, repo) in loaded_repos {
@@ -108,8 +481,16 @@ fn try_main(opts: Opts) -> anyhow::Result<()> {
         repos.insert(name, Arc::new(repo));
     }
 
-    let ctx = BorsContext::new(CommandParser::new(opts.cmd_prefix), Arc::new(db), repos);
-    let (repository_tx, global_tx, bors_process) = create_bors_process(ctx, client, team_api);
+    let ctx = BorsContext::new(
+        CommandParser::new(cmd_prefix),
+        Arc::new(db),
+        repos,
+        opts.github_html_url,
+        opts.dry_run,
+    );
+    let repositories = ctx.repositories.clone();
+    let (repository_tx, global_tx, bors_process) =
+        create_bors_process(ctx, client, permission_source, opts.max_parallel_events);
 
     let refresh_tx = global_tx.clone();
     let refresh_process = async move {
@@ -123,8 +504,13 @@ fn try_main(opts: Opts) -> anyhow::Result<()> {
         repository_tx,
         global_tx,
         WebhookSecret::new(opts.webhook_secret),
-    );
-    let server_process = webhook_server(state);
+        opts.webhook_body_limit,
+        opts.admin_token,
+        db_pool,
+        opts.dry_run,
+    )
+    .with_repositories(repositories);
+    let server_process = webhook_server(state, listener);
 
     let fut = async move {
         tokio::select! {
@@ -148,20 +534,208 @@ fn try_main(opts: Opts) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Returns the command line arguments, with the `serve` subcommand inserted if no subcommand
+/// was given, so that the bot can still be started without specifying a subcommand.
+fn args_with_default_subcommand() -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let command = Cli::command();
+    // Global options, like `--log-format`, can be passed before the subcommand.
+    let has_subcommand = args
+        .iter()
+        .skip(1)
+        .filter_map(|arg| arg.to_str())
+        .any(|arg| {
+            matches!(arg, "-h" | "--help" | "help") || command.find_subcommand(arg).is_some()
+        });
+    if !has_subcommand {
+        args.insert(1.min(args.len()), "serve".into());
+    }
+    args
+}
+
+/// Creates the subscriber that writes logs of events with at least the given `level` to `writer`.
+/// If `sentry` is set, errors are also reported to Sentry through its tracing integration.
+#[cfg_attr(not(feature = "sentry"), allow(unused_variables))]
+fn create_subscriber<W>(
+    format: LogFormat,
+    level: tracing::Level,
+    sentry: bool,
+    writer: W,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env()
+        .expect("Cannot load RUST_LOG");
+    let fmt_layer = match format {
+        LogFormat::Text => fmt::layer()
+            .with_writer(writer)
+            .with_ansi(std::io::stdout().is_terminal())
+            .boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(writer)
+            .boxed(),
+    };
+    let subscriber = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    #[cfg(feature = "sentry")]
+    let subscriber = subscriber.with(sentry.then(sentry::integrations::tracing::layer));
+
+    Box::new(subscriber)
+}
+
 fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(tracing::Level::INFO.into())
-                .from_env()
-                .expect("Cannot load RUST_LOG"),
-        )
-        .with_ansi(std::io::stdout().is_terminal())
-        .init();
+    let cli = Cli::parse_from(args_with_default_subcommand());
 
-    let opts = Opts::parse();
-    if let Err(error) = try_main(opts) {
+    // The guard has to be kept alive until the end of the program, so that pending events
+    // are sent to Sentry before exiting.
+    #[cfg(feature = "sentry")]
+    let _sentry = cli.sentry_dsn.as_deref().map(|dsn| {
+        sentry::init((
+            dsn,
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                ..Default::default()
+            },
+        ))
+    });
+    #[cfg(feature = "sentry")]
+    let sentry_enabled = cli.sentry_dsn.is_some();
+    #[cfg(not(feature = "sentry"))]
+    let sentry_enabled = false;
+
+    create_subscriber(
+        cli.log_format,
+        cli.log_level,
+        sentry_enabled,
+        std::io::stdout,
+    )
+    .init();
+
+    if let Err(error) = try_main(cli) {
         tracing::error!("Error: {error:?}");
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use clap::Parser;
+
+    use crate::{Cli, Command, DatabaseOpts, LogFormat, create_subscriber};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn logging_works_with_error_reporting() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            create_subscriber(LogFormat::Json, tracing::Level::INFO, true, move || {
+                writer.clone()
+            });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(repo = "foo/bar", "Hello from bors");
+        });
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("Hello from bors"));
+        assert!(logs.contains(r#""repo":"foo/bar""#));
+    }
+
+    #[test]
+    fn log_level_filters_events() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            create_subscriber(LogFormat::Text, tracing::Level::WARN, false, move || {
+                writer.clone()
+            });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Filtered out");
+            tracing::warn!("Kept");
+        });
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(!logs.contains("Filtered out"));
+        assert!(logs.contains("Kept"));
+    }
+
+    fn parse_database_opts(args: &[&str]) -> DatabaseOpts {
+        let cli = Cli::try_parse_from(
+            ["bors", "migrate", "--db", "postgres://localhost/bors"]
+                .iter()
+                .chain(args),
+        )
+        .unwrap();
+        match cli.command {
+            Command::Migrate(opts) => opts,
+            _ => panic!("Unexpected subcommand"),
+        }
+    }
+
+    #[test]
+    fn database_pool_defaults() {
+        let opts = parse_database_opts(&[]);
+        let pool = opts.pool_options();
+        assert_eq!(pool.get_max_connections(), 10);
+        assert_eq!(pool.get_acquire_timeout(), Duration::from_secs(30));
+        assert_eq!(opts.connect_options().unwrap().get_options(), None);
+    }
+
+    #[test]
+    fn database_pool_options() {
+        let opts = parse_database_opts(&[
+            "--db-max-connections",
+            "4",
+            "--db-acquire-timeout",
+            "5",
+            "--db-statement-timeout",
+            "60",
+        ]);
+        let pool = opts.pool_options();
+        assert_eq!(pool.get_max_connections(), 4);
+        assert_eq!(pool.get_acquire_timeout(), Duration::from_secs(5));
+        assert_eq!(
+            opts.connect_options().unwrap().get_options(),
+            Some("-c statement_timeout=60s")
+        );
+    }
+
+    #[test]
+    fn invalid_database_pool_options() {
+        for args in [
+            ["--db-max-connections", "-1"],
+            ["--db-acquire-timeout", "soon"],
+        ] {
+            assert!(
+                Cli::try_parse_from(
+                    ["bors", "migrate", "--db", "postgres://localhost/bors"]
+                        .iter()
+                        .chain(&args),
+                )
+                .is_err()
+            );
+        }
+    }
+}
@@ -2,6 +2,7 @@ mod parser;
 use std::fmt;
 use std::str::FromStr;
 
+use crate::database::DelegatedPermission;
 use crate::github::CommitSha;
 pub use parser::{CommandParseError, CommandParser};
 
@@ -73,13 +74,19 @@ pub enum BorsCommand {
         priority: Option<Priority>,
         // Rollup status of the commit.
         rollup: Option<RollupMode>,
+        /// Failures of workflows that are not required should not fail the build.
+        /// Only repository admins can force an approval.
+        force: bool,
     },
     /// Unapprove a commit.
     Unapprove,
     /// Print help.
     Help,
     /// Ping the bot.
-    Ping,
+    Ping {
+        /// Report how long it took to process the command since the comment was posted.
+        latency: bool,
+    },
     /// Perform a try build.
     Try {
         /// Parent commit which should be used as the merge base.
@@ -93,14 +100,42 @@ pub enum BorsCommand {
     SetPriority(Priority),
     /// Get information about the current PR.
     Info,
-    /// Delegate approval authority to the pull request author.
-    Delegate,
+    /// Delegate approval (`delegate+`) or try (`delegate=try`) authority to the pull request
+    /// author.
+    Delegate(DelegatedPermission),
     /// Revoke any previously granted delegation.
     Undelegate,
+    /// List the delegations of the open PRs of the repository.
+    ListDelegations,
     /// Set the rollup mode of a PRstatus.
     SetRollupMode(RollupMode),
+    /// Set whether the PR is squashed when it is landed, regardless of the merge style of the
+    /// repository.
+    SetSquash(bool),
     /// Open the repository tree for merging.
     OpenTree,
     /// Set the tree closed with a priority level.
     TreeClosed(Priority),
+    /// Delete branches created by bors for builds that have already finished.
+    Clean,
+    /// Cancel all running builds of the repository.
+    CancelAll,
+    /// Stop acting on the repository until it is resumed.
+    Pause,
+    /// Resume a paused repository.
+    Resume,
+}
+
+impl BorsCommand {
+    /// Returns true if the command only reports information, without changing the state of the
+    /// PR or of the repository.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            BorsCommand::Help
+                | BorsCommand::Ping { .. }
+                | BorsCommand::Info
+                | BorsCommand::ListDelegations
+        )
+    }
 }
@@ -4,6 +4,7 @@ use std::collections::HashSet;
 use std::str::FromStr;
 
 use crate::bors::command::{Approver, BorsCommand, Parent};
+use crate::database::DelegatedPermission;
 use crate::github::CommitSha;
 
 use super::{Priority, RollupMode};
@@ -26,12 +27,18 @@ enum CommandPart<'a> {
 }
 
 pub struct CommandParser {
+    /// Lowercase prefix, e.g. `@bors`.
     prefix: String,
 }
 
 impl CommandParser {
+    /// Creates a parser of commands addressed to `prefix`, which is usually the mention of the
+    /// bot account (e.g. `@bors`).
+    /// The prefix is matched case-insensitively.
     pub fn new(prefix: String) -> Self {
-        Self { prefix }
+        Self {
+            prefix: prefix.to_ascii_lowercase(),
+        }
     }
 
     /// Parses bors commands from the given string.
@@ -43,15 +50,41 @@ impl CommandParser {
         text: &'a str,
     ) -> Vec<Result<BorsCommand, CommandParseError<'a>>> {
         text.lines()
-            .filter_map(|line| match line.find(&self.prefix) {
-                Some(index) => {
-                    let input = &line[index + self.prefix.len()..];
-                    parse_command(input)
-                }
-                None => None,
-            })
+            .filter_map(|line| self.strip_prefix(line).and_then(parse_command))
             .collect()
     }
+
+    /// Returns the text that follows the first mention of the prefix in `line`.
+    ///
+    /// The prefix has to be a separate word, so it is found in `@BORS: r+` or `(@bors r+)`,
+    /// but not in `@borsbot r+`. Punctuation around the command is ignored.
+    fn strip_prefix<'a>(&self, line: &'a str) -> Option<&'a str> {
+        // ASCII lowercasing keeps byte offsets the same as in `line`.
+        let lowercase = line.to_ascii_lowercase();
+        let mut offset = 0;
+        while let Some(position) = lowercase[offset..].find(&self.prefix) {
+            let start = offset + position;
+            let end = start + self.prefix.len();
+            let separated_before = line[..start]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !is_name_char(c));
+            let separated_after = line[end..].chars().next().is_none_or(|c| !is_name_char(c));
+            if separated_before && separated_after {
+                let command = line[end..]
+                    .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, ':' | ','))
+                    .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '.' | '!' | ')'));
+                return Some(command);
+            }
+            offset = end;
+        }
+        None
+    }
+}
+
+/// Characters that can be a part of a GitHub user name.
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
 }
 
 type ParseResult<'a, T = BorsCommand> = Option<Result<T, CommandParseError<'a>>>;
@@ -61,6 +94,7 @@ const PARSERS: &[for<'b> fn(&CommandPart<'b>, &[CommandPart<'b>]) -> ParseResult
     parser_approval,
     parser_unapprove,
     parser_rollup,
+    parser_squash,
     parser_priority,
     parser_try_cancel,
     parser_try,
@@ -69,6 +103,9 @@ const PARSERS: &[for<'b> fn(&CommandPart<'b>, &[CommandPart<'b>]) -> ParseResult
     parser_help,
     parser_ping,
     parser_tree_ops,
+    parser_clean,
+    parser_cancel_all,
+    parser_pause,
 ];
 
 fn parse_command(input: &str) -> ParseResult {
@@ -120,8 +157,8 @@ fn parse_parts(input: &str) -> Result<Vec<CommandPart>, CommandParseError> {
 }
 
 /// Parses:
-/// - "@bors r+ [p=<priority>] [rollup=<never|iffy|maybe|always>]"
-/// - "@bors r=<user> [p=<priority>] [rollup=<never|iffy|maybe|always>]"
+/// - "@bors r+ [p=<priority>] [rollup=<never|iffy|maybe|always>] [force]"
+/// - "@bors r=<user> [p=<priority>] [rollup=<never|iffy|maybe|always>] [force]"
 fn parser_approval<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
     let approver = match command {
         CommandPart::Bare("r+") => Approver::Myself,
@@ -144,10 +181,14 @@ fn parser_approval<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) ->
         Some(Err(e)) => return Some(Err(e)),
         None => None,
     };
+    let force = parts
+        .iter()
+        .any(|part| matches!(part, CommandPart::Bare("force")));
     Some(Ok(BorsCommand::Approve {
         approver,
         priority,
         rollup,
+        force,
     }))
 }
 
@@ -169,23 +210,19 @@ fn parser_help<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> Par
     }
 }
 
-/// Parses "@bors ping".
-fn parser_ping<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+/// Parses "@bors ping [latency]".
+fn parser_ping<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
     if let CommandPart::Bare("ping") = command {
-        Some(Ok(BorsCommand::Ping))
+        let latency = matches!(parts.first(), Some(CommandPart::Bare("latency")));
+        Some(Ok(BorsCommand::Ping { latency }))
     } else {
         None
     }
 }
 
-fn parse_sha(input: &str) -> Result<CommitSha, String> {
-    if input.len() != 40 {
-        return Err("SHA must have exactly 40 characters".to_string());
-    }
-    Ok(CommitSha(input.to_string()))
-}
-
 /// Parses "@bors try <parent=sha>".
+/// The parent can be a full or an abbreviated SHA, or `last` for the parent of the previous try
+/// build.
 fn parser_try<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
     if *command != CommandPart::Bare("try") {
         return None;
@@ -201,7 +238,7 @@ fn parser_try<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) -> Parse
             }
             CommandPart::KeyValue { key, value } => match (*key, *value) {
                 ("parent", "last") => parent = Some(Parent::Last),
-                ("parent", value) => match parse_sha(value) {
+                ("parent", value) => match CommitSha::parse_abbreviated(value) {
                     Ok(sha) => parent = Some(Parent::CommitSha(sha)),
                     Err(error) => {
                         return Some(Err(CommandParseError::ValidationError(format!(
@@ -211,9 +248,10 @@ fn parser_try<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) -> Parse
                 },
                 ("jobs", value) => {
                     let raw_jobs: Vec<_> = value.split(',').map(|s| s.to_string()).collect();
-                    if raw_jobs.is_empty() {
+                    // e.g. `jobs=ci,,lint` or `jobs=ci,`
+                    if raw_jobs.iter().any(|job| job.is_empty()) {
                         return Some(Err(CommandParseError::ValidationError(
-                            "Try jobs must not be empty".to_string(),
+                            "Try job names must not be empty".to_string(),
                         )));
                     }
 
@@ -244,11 +282,24 @@ fn parser_try_cancel<'a>(command: &CommandPart<'a>, parts: &[CommandPart<'a>]) -
     }
 }
 
-/// Parses "@bors delegate+" and "@bors delegate-".
+/// Parses "@bors delegate+", "@bors delegate=try", "@bors delegate-" and "@bors delegate?".
 fn parser_delegation<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
     match command {
-        CommandPart::Bare("delegate+") => Some(Ok(BorsCommand::Delegate)),
+        CommandPart::Bare("delegate+") => {
+            Some(Ok(BorsCommand::Delegate(DelegatedPermission::Review)))
+        }
+        CommandPart::KeyValue {
+            key: "delegate",
+            value: "try",
+        } => Some(Ok(BorsCommand::Delegate(DelegatedPermission::Try))),
+        CommandPart::KeyValue {
+            key: "delegate",
+            value,
+        } => Some(Err(CommandParseError::ValidationError(format!(
+            "Invalid delegation `{value}`. Use `delegate+` to delegate approval or `delegate=try` to delegate try builds"
+        )))),
         CommandPart::Bare("delegate-") => Some(Ok(BorsCommand::Undelegate)),
+        CommandPart::Bare("delegate?") => Some(Ok(BorsCommand::ListDelegations)),
         _ => None,
     }
 }
@@ -305,6 +356,15 @@ fn parser_rollup<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> P
     parse_rollup(std::slice::from_ref(command)).map(|res| res.map(BorsCommand::SetRollupMode))
 }
 
+/// Parses "@bors squash" and "@bors squash-"
+fn parser_squash<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    match command {
+        CommandPart::Bare("squash") => Some(Ok(BorsCommand::SetSquash(true))),
+        CommandPart::Bare("squash-") => Some(Ok(BorsCommand::SetSquash(false))),
+        _ => None,
+    }
+}
+
 /// Parses "@bors info"
 fn parser_info<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
     if *command == CommandPart::Bare("info") {
@@ -332,10 +392,38 @@ fn parser_tree_ops<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) ->
     }
 }
 
+/// Parses "@bors clean"
+fn parser_clean<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if *command == CommandPart::Bare("clean") {
+        Some(Ok(BorsCommand::Clean))
+    } else {
+        None
+    }
+}
+
+/// Parses "@bors cancel-all"
+fn parser_cancel_all<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if *command == CommandPart::Bare("cancel-all") {
+        Some(Ok(BorsCommand::CancelAll))
+    } else {
+        None
+    }
+}
+
+/// Parses "@bors pause" and "@bors resume"
+fn parser_pause<'a>(command: &CommandPart<'a>, _parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    match command {
+        CommandPart::Bare("pause") => Some(Ok(BorsCommand::Pause)),
+        CommandPart::Bare("resume") => Some(Ok(BorsCommand::Resume)),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::bors::command::parser::{CommandParseError, CommandParser};
     use crate::bors::command::{Approver, BorsCommand, Parent, RollupMode};
+    use crate::database::DelegatedPermission;
     use crate::github::CommitSha;
 
     #[test]
@@ -344,6 +432,46 @@ mod tests {
         assert_eq!(cmds.len(), 0);
     }
 
+    #[test]
+    fn prefix_case_insensitive() {
+        let cmds = parse_commands("@BORS ping");
+        assert_eq!(cmds, vec![Ok(BorsCommand::Ping { latency: false })]);
+    }
+
+    #[test]
+    fn prefix_with_punctuation() {
+        let cmds = parse_commands("Thanks! (@bors: r-)");
+        assert_eq!(cmds, vec![Ok(BorsCommand::Unapprove)]);
+    }
+
+    #[test]
+    fn prefix_followed_by_comma() {
+        let cmds = parse_commands("  @bors,  ping.  ");
+        assert_eq!(cmds, vec![Ok(BorsCommand::Ping { latency: false })]);
+    }
+
+    #[test]
+    fn prefix_must_be_a_separate_word() {
+        assert!(parse_commands("@borsbot ping").is_empty());
+        assert!(parse_commands("foo@bors ping").is_empty());
+    }
+
+    #[test]
+    fn prefix_found_after_other_mention() {
+        let cmds = parse_commands("@borsbot @bors ping");
+        assert_eq!(cmds, vec![Ok(BorsCommand::Ping { latency: false })]);
+    }
+
+    #[test]
+    fn custom_prefix() {
+        let parser = CommandParser::new("@My-Merge-Bot".to_string());
+        assert_eq!(
+            parser.parse_commands("@my-merge-bot ping"),
+            vec![Ok(BorsCommand::Ping { latency: false })]
+        );
+        assert!(parser.parse_commands("@bors ping").is_empty());
+    }
+
     #[test]
     fn missing_command() {
         let cmds = parse_commands("@bors");
@@ -388,6 +516,7 @@ mod tests {
                 approver: Approver::Myself,
                 priority: None,
                 rollup: None,
+                force: false,
             })
         );
     }
@@ -404,6 +533,7 @@ mod tests {
                 ),
                 priority: None,
                 rollup: None,
+                force: false,
             },
         )
         "#);
@@ -421,6 +551,7 @@ mod tests {
                 ),
                 priority: None,
                 rollup: None,
+                force: false,
             },
         )
         "#);
@@ -435,7 +566,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Myself,
                 priority: Some(1),
-                rollup: None
+                rollup: None,
+                force: false
             })
         )
     }
@@ -449,7 +581,23 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user1".to_string()),
                 priority: Some(2),
-                rollup: None
+                rollup: None,
+                force: false
+            })
+        )
+    }
+
+    #[test]
+    fn parse_approve_force() {
+        let cmds = parse_commands("@bors r+ force p=3");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Approve {
+                approver: Approver::Myself,
+                priority: Some(3),
+                rollup: None,
+                force: true
             })
         )
     }
@@ -468,7 +616,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Myself,
                 priority: Some(1),
-                rollup: None
+                rollup: None,
+                force: false
             })
         );
         assert_eq!(
@@ -476,7 +625,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user2".to_string()),
                 priority: Some(2),
-                rollup: None
+                rollup: None,
+                force: false
             })
         );
     }
@@ -503,7 +653,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user1".to_string()),
                 priority: Some(2),
-                rollup: None
+                rollup: None,
+                force: false
             })
         )
     }
@@ -623,7 +774,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Myself,
                 priority: None,
-                rollup: Some(RollupMode::Always)
+                rollup: Some(RollupMode::Always),
+                force: false
             })
         )
     }
@@ -637,7 +789,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user1".to_string()),
                 priority: None,
-                rollup: Some(RollupMode::Never)
+                rollup: Some(RollupMode::Never),
+                force: false
             })
         )
     }
@@ -651,7 +804,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user1".to_string()),
                 priority: None,
-                rollup: Some(RollupMode::Always)
+                rollup: Some(RollupMode::Always),
+                force: false
             })
         )
     }
@@ -665,7 +819,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user1".to_string()),
                 priority: None,
-                rollup: Some(RollupMode::Maybe)
+                rollup: Some(RollupMode::Maybe),
+                force: false
             })
         )
     }
@@ -684,7 +839,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Myself,
                 priority: None,
-                rollup: Some(RollupMode::Always)
+                rollup: Some(RollupMode::Always),
+                force: false
             })
         );
         assert_eq!(
@@ -692,7 +848,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Specified("user2".to_string()),
                 priority: None,
-                rollup: Some(RollupMode::Iffy)
+                rollup: Some(RollupMode::Iffy),
+                force: false
             })
         );
     }
@@ -786,7 +943,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Myself,
                 priority: Some(1),
-                rollup: Some(RollupMode::Always)
+                rollup: Some(RollupMode::Always),
+                force: false
             })
         );
     }
@@ -800,7 +958,8 @@ mod tests {
             Ok(BorsCommand::Approve {
                 approver: Approver::Myself,
                 priority: Some(1),
-                rollup: Some(RollupMode::Iffy)
+                rollup: Some(RollupMode::Iffy),
+                force: false
             })
         );
     }
@@ -830,14 +989,20 @@ mod tests {
     fn parse_ping() {
         let cmds = parse_commands("@bors ping");
         assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], Ok(BorsCommand::Ping)));
+        assert!(matches!(cmds[0], Ok(BorsCommand::Ping { latency: false })));
+    }
+
+    #[test]
+    fn parse_ping_latency() {
+        let cmds = parse_commands("@bors ping latency");
+        assert_eq!(cmds, vec![Ok(BorsCommand::Ping { latency: true })]);
     }
 
     #[test]
     fn parse_ping_unknown_arg() {
         let cmds = parse_commands("@bors ping a");
         assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], Ok(BorsCommand::Ping)));
+        assert!(matches!(cmds[0], Ok(BorsCommand::Ping { latency: false })));
     }
 
     #[test]
@@ -902,6 +1067,19 @@ line two
         );
     }
 
+    #[test]
+    fn parse_try_parent_abbreviated() {
+        let cmds = parse_commands("@bors try parent=ea9c1b0");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Try {
+                parent: Some(Parent::CommitSha(CommitSha("ea9c1b0".to_string()))),
+                jobs: Vec::new()
+            })
+        );
+    }
+
     #[test]
     fn parse_try_parent_invalid() {
         let cmds = parse_commands("@bors try parent=foo");
@@ -909,7 +1087,7 @@ line two
         insta::assert_debug_snapshot!(cmds[0], @r###"
         Err(
             ValidationError(
-                "Try parent has to be a valid commit SHA: SHA must have exactly 40 characters",
+                "Try parent has to be a valid commit SHA: SHA must have between 7 and 64 characters",
             ),
         )
         "###);
@@ -941,6 +1119,31 @@ line two
         "###);
     }
 
+    #[test]
+    fn parse_try_jobs_empty_name() {
+        let cmds = parse_commands("@bors try jobs=ci,,lint");
+        assert_eq!(cmds.len(), 1);
+        insta::assert_debug_snapshot!(cmds[0], @r###"
+        Err(
+            ValidationError(
+                "Try job names must not be empty",
+            ),
+        )
+        "###);
+    }
+
+    #[test]
+    fn parse_try_jobs_trailing_comma() {
+        let cmds = parse_commands("@bors try jobs=ci,");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Err(CommandParseError::ValidationError(
+                "Try job names must not be empty".to_string()
+            ))
+        );
+    }
+
     #[test]
     fn parse_try_jobs_too_many() {
         let cmds =
@@ -1012,7 +1215,30 @@ line two
     fn parse_delegate_author() {
         let cmds = parse_commands("@bors delegate+");
         assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], Ok(BorsCommand::Delegate)));
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Delegate(DelegatedPermission::Review))
+        );
+    }
+
+    #[test]
+    fn parse_delegate_try() {
+        let cmds = parse_commands("@bors delegate=try");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::Delegate(DelegatedPermission::Try)));
+    }
+
+    #[test]
+    fn parse_delegate_invalid_value() {
+        let cmds = parse_commands("@bors delegate=foo");
+        assert_eq!(cmds.len(), 1);
+        insta::assert_debug_snapshot!(cmds[0], @r#"
+        Err(
+            ValidationError(
+                "Invalid delegation `foo`. Use `delegate+` to delegate approval or `delegate=try` to delegate try builds",
+            ),
+        )
+        "#);
     }
 
     #[test]
@@ -1022,11 +1248,21 @@ line two
         assert_eq!(cmds[0], Ok(BorsCommand::Undelegate));
     }
 
+    #[test]
+    fn parse_list_delegations() {
+        let cmds = parse_commands("@bors delegate?");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::ListDelegations));
+    }
+
     #[test]
     fn parse_delegate_author_unknown_arg() {
         let cmds = parse_commands("@bors delegate+ a");
         assert_eq!(cmds.len(), 1);
-        assert_eq!(cmds[0], Ok(BorsCommand::Delegate));
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Delegate(DelegatedPermission::Review))
+        );
     }
 
     #[test]
@@ -1073,6 +1309,48 @@ line two
         ));
     }
 
+    #[test]
+    fn parse_clean() {
+        let cmds = parse_commands("@bors clean");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::Clean));
+    }
+
+    #[test]
+    fn parse_cancel_all() {
+        let cmds = parse_commands("@bors cancel-all");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::CancelAll));
+    }
+
+    #[test]
+    fn parse_pause() {
+        let cmds = parse_commands("@bors pause");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::Pause));
+    }
+
+    #[test]
+    fn parse_resume() {
+        let cmds = parse_commands("@bors resume");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::Resume));
+    }
+
+    #[test]
+    fn parse_squash() {
+        let cmds = parse_commands("@bors squash");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::SetSquash(true)));
+    }
+
+    #[test]
+    fn parse_squash_reset() {
+        let cmds = parse_commands("@bors squash-");
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::SetSquash(false)));
+    }
+
     fn parse_commands(text: &str) -> Vec<Result<BorsCommand, CommandParseError>> {
         CommandParser::new("@bors".to_string()).parse_commands(text)
     }
@@ -1,17 +1,40 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
 use crate::{
-    database::{WorkflowModel, WorkflowStatus},
+    database::{DelegatedPermission, DelegationModel, WorkflowModel, WorkflowStatus},
     github::CommitSha,
 };
 
+/// Maximum length of a comment body accepted by GitHub.
+pub const MAX_COMMENT_LENGTH: usize = 65536;
+
+/// Maximum length of the table of workflows in a build result comment, which leaves space for
+/// the rest of the comment.
+const MAX_WORKFLOW_TABLE_LENGTH: usize = MAX_COMMENT_LENGTH - 4096;
+
 /// A comment that can be posted to a pull request.
+#[derive(Debug)]
 pub struct Comment {
     text: String,
     metadata: Option<CommentMetadata>,
+    kind: Option<CommentKind>,
 }
 
-#[derive(Serialize)]
+/// Kind of a comment that becomes outdated once a newer comment of the same kind is posted.
+#[derive(Clone, Copy, Debug, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+#[sqlx(rename_all = "snake_case")]
+pub enum CommentKind {
+    /// A try build was started.
+    TryBuildStarted,
+    /// A try build has finished.
+    TryBuildResult,
+}
+
+#[derive(Serialize, Debug)]
 #[serde(tag = "type")]
 pub enum CommentMetadata {
     TryBuildCompleted { merge_sha: String },
@@ -22,54 +45,201 @@ impl Comment {
         Self {
             text,
             metadata: None,
+            kind: None,
         }
     }
 
+    /// Marks the comment as being of the given kind, so that it can be minimized once a newer
+    /// comment of the same kind is posted.
+    pub fn with_kind(mut self, kind: CommentKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn kind(&self) -> Option<CommentKind> {
+        self.kind
+    }
+
+    /// Returns the first line of the comment, which summarizes it.
+    pub fn summary(&self) -> &str {
+        self.text.lines().next().unwrap_or_default()
+    }
+
+    /// Appends a collapsed section that lists the given previous states of the PR.
+    pub fn with_history(mut self, history: &[String]) -> Self {
+        if !history.is_empty() {
+            self.text
+                .push_str("\n\n<details><summary>History</summary>\n\n");
+            for entry in history {
+                self.text.push_str(&format!("- {entry}\n"));
+            }
+            self.text.push_str("</details>");
+        }
+        self
+    }
+
     pub fn render(&self) -> String {
+        self.render_text(&self.text)
+    }
+
+    /// Renders the comment so that it fits into [`MAX_COMMENT_LENGTH`].
+    /// If the comment is too long, its text is cut off and ends with a marker that links to
+    /// `link`, where the full details can be found. Metadata is never truncated.
+    pub fn render_truncated(&self, link: &str) -> String {
+        let rendered = self.render();
+        if rendered.len() <= MAX_COMMENT_LENGTH {
+            return rendered;
+        }
+        let marker = format!("\n\n... (truncated, see {link} for the full details)");
+        let metadata_len = rendered.len() - self.text.len();
+        let mut end = MAX_COMMENT_LENGTH.saturating_sub(metadata_len + marker.len());
+        while !self.text.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.render_text(&format!("{}{marker}", &self.text[..end]))
+    }
+
+    fn render_text(&self, text: &str) -> String {
         if let Some(metadata) = &self.metadata {
             return format!(
                 "{}\n<!-- homu: {} -->",
-                self.text,
+                text,
                 serde_json::to_string(metadata).unwrap()
             );
         }
-        self.text.clone()
+        text.to_string()
     }
 }
 
-pub fn try_build_succeeded_comment(workflows: &[WorkflowModel], commit_sha: CommitSha) -> Comment {
-    let workflows_status = list_workflows_status(workflows);
+/// Success comment of a try build. If only some CI `jobs` were requested, they are listed.
+pub fn try_build_succeeded_comment(
+    workflows: &[WorkflowModel],
+    commit_sha: CommitSha,
+    jobs: &[String],
+) -> Comment {
+    let workflows_status = workflows_summary(workflows);
+    let requested_jobs = if jobs.is_empty() {
+        String::new()
+    } else {
+        let jobs = jobs
+            .iter()
+            .map(|job| format!("`{job}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("Requested jobs: {jobs}\n")
+    };
     Comment {
         text: format!(
             r#":sunny: Try build successful
 {}
-Build commit: {} (`{}`)"#,
-            workflows_status, commit_sha, commit_sha
+{}Build commit: {} (`{}`)"#,
+            workflows_status, requested_jobs, commit_sha, commit_sha
         ),
         metadata: Some(CommentMetadata::TryBuildCompleted {
             merge_sha: commit_sha.to_string(),
         }),
+        kind: Some(CommentKind::TryBuildResult),
     }
 }
 
-pub fn try_build_in_progress_comment() -> Comment {
-    Comment::new(":exclamation: A try build is currently in progress. You can cancel it using @bors try cancel.".to_string())
+/// Success comment of a build whose approval was forced, which lists the ignored failures.
+pub fn forced_try_build_succeeded_comment(
+    workflows: &[WorkflowModel],
+    commit_sha: CommitSha,
+    jobs: &[String],
+) -> Comment {
+    let mut comment = try_build_succeeded_comment(workflows, commit_sha, jobs);
+    comment.text += "\n:warning: **This build was forced**, failures of checks that are not required were ignored.";
+    append_ignored_failures(&mut comment, workflows);
+    comment
+}
+
+fn append_ignored_failures(comment: &mut Comment, workflows: &[WorkflowModel]) {
+    let ignored = workflows
+        .iter()
+        .filter(|w| {
+            matches!(
+                w.status,
+                WorkflowStatus::Failure | WorkflowStatus::ActionRequired
+            )
+        })
+        .map(|w| format!("\n- [{}]({})", w.name, w.url))
+        .collect::<String>();
+    if !ignored.is_empty() {
+        comment.text += "\nIgnored failures:";
+        comment.text += &ignored;
+    }
 }
 
 pub fn cant_find_last_parent_comment() -> Comment {
     Comment::new(":exclamation: There was no previous build. Please set an explicit parent or remove the `parent=last` argument to use the default parent.".to_string())
 }
 
+pub fn try_parent_not_found_comment(parent: &CommitSha) -> Comment {
+    Comment::new(format!(
+        ":exclamation: The try parent `{parent}` does not exist in this repository."
+    ))
+}
+
+pub fn try_parent_unreachable_comment(parent: &CommitSha, base_branch: &str) -> Comment {
+    Comment::new(format!(
+        ":exclamation: The try parent `{parent}` is not a part of the history of the `{base_branch}` branch."
+    ))
+}
+
+pub fn try_build_from_fork_forbidden_comment() -> Comment {
+    Comment::new(
+        ":no_entry: Try builds of PRs from forks are disabled in this repository, because the try workflows would run the code of the fork with access to the secrets of this repository.".to_string(),
+    )
+}
+
 pub fn no_try_build_in_progress_comment() -> Comment {
     Comment::new(":exclamation: There is currently no try build in progress.".to_string())
 }
 
+pub fn command_rate_limited_comment(username: &str, retry_after: Duration) -> Comment {
+    Comment::new(format!(
+        ":snail: @{username}: You are issuing commands too quickly, please slow down. You can issue the next command in {}.",
+        format_duration(retry_after)
+    ))
+}
+
+pub fn try_build_queued_comment(builds_ahead: u64) -> Comment {
+    Comment::new(format!(
+        ":hourglass_flowing_sand: Try build queued behind {builds_ahead} try build(s), it will be started once a try build finishes."
+    ))
+}
+
+pub fn queued_try_build_cancelled_comment() -> Comment {
+    Comment::new("Queued try build cancelled.".to_string())
+}
+
 pub fn unclean_try_build_cancelled_comment() -> Comment {
     Comment::new(
         "Try build was cancelled. It was not possible to cancel some workflows.".to_string(),
     )
 }
 
+pub fn try_build_superseded_comment(
+    build_sha: &str,
+    cancelled_workflow_urls: Option<Vec<String>>,
+) -> Comment {
+    let mut comment = format!(
+        ":warning: The previous try build (`{build_sha}`) was superseded by a new try build and cancelled."
+    );
+    match cancelled_workflow_urls {
+        Some(urls) if urls.is_empty() => {}
+        Some(urls) => {
+            comment.push_str("\nCancelled workflows:");
+            for url in urls {
+                comment.push_str(&format!("\n- {url}"));
+            }
+        }
+        None => comment.push_str(" It was not possible to cancel some of its workflows."),
+    }
+    Comment::new(comment)
+}
+
 pub fn try_build_cancelled_comment(workflow_urls: impl Iterator<Item = String>) -> Comment {
     let mut try_build_cancelled_comment = r#"Try build cancelled.
 Cancelled workflows:"#
@@ -80,30 +250,611 @@ Cancelled workflows:"#
     Comment::new(try_build_cancelled_comment)
 }
 
-pub fn workflow_failed_comment(workflows: &[WorkflowModel]) -> Comment {
-    let workflows_status = list_workflows_status(workflows);
-    Comment::new(format!(
+/// Excerpt of the log of a failed CI job.
+pub struct JobLogExcerpt {
+    pub job_name: String,
+    pub excerpt: String,
+}
+
+pub fn workflow_failed_comment(workflows: &[WorkflowModel], logs: &[JobLogExcerpt]) -> Comment {
+    let workflows_status = workflows_summary(workflows);
+    let mut text = format!(
         r#":broken_heart: Test failed
 {}"#,
         workflows_status
-    ))
+    );
+    for workflow in workflows
+        .iter()
+        .filter(|w| w.status == WorkflowStatus::ActionRequired)
+    {
+        text += &format!(
+            "\n:raised_hand: [{}]({}) is waiting for a maintainer to approve the workflow run. Approve it on GitHub and then retry the build.\n",
+            workflow.name, workflow.url
+        );
+    }
+    for log in logs {
+        let fence = code_fence(&log.excerpt);
+        text += &format!(
+            "\n<details><summary>Log of the failed job <code>{}</code></summary>\n\n{fence}\n{}\n{fence}\n\n</details>\n",
+            escape_html(&log.job_name),
+            log.excerpt
+        );
+    }
+    Comment::new(text).with_kind(CommentKind::TryBuildResult)
+}
+
+pub fn build_timed_out_comment(elapsed: Duration, pending_workflows: &[WorkflowModel]) -> Comment {
+    let mut text = format!(":boom: Test timed out after {}", format_duration(elapsed));
+    if !pending_workflows.is_empty() {
+        text += "\nPending workflows:";
+        for workflow in pending_workflows {
+            text += &format!("\n- [{}]({})", workflow.name, workflow.url);
+        }
+    }
+    Comment::new(text).with_kind(CommentKind::TryBuildResult)
 }
 
-fn list_workflows_status(workflows: &[WorkflowModel]) -> String {
-    workflows
+pub fn external_ci_timed_out_comment(timeout: Duration, workflows: &[WorkflowModel]) -> Comment {
+    let mut text = format!(
+        ":boom: Test timed out after {} waiting for external CI",
+        format_duration(timeout)
+    );
+    for workflow in workflows {
+        text += &format!(
+            "\n- [{}]({}): timed out waiting for external CI",
+            workflow.name, workflow.url
+        );
+    }
+    Comment::new(text).with_kind(CommentKind::TryBuildResult)
+}
+
+pub fn required_checks_not_started_comment(missing_checks: &[&str]) -> Comment {
+    let checks = missing_checks
         .iter()
-        .map(|w| {
-            format!(
-                "- [{}]({}) {}",
-                w.name,
-                w.url,
-                if w.status == WorkflowStatus::Success {
-                    ":white_check_mark:"
-                } else {
-                    ":x:"
-                }
-            )
-        })
+        .map(|check| format!("`{check}`"))
         .collect::<Vec<_>>()
-        .join("\n")
+        .join(", ");
+    Comment::new(format!(
+        ":broken_heart: Test failed, required check(s) never started: {checks}"
+    ))
+    .with_kind(CommentKind::TryBuildResult)
+}
+
+pub fn merge_build_started_comment(head_sha: &CommitSha, merge_sha: &CommitSha) -> Comment {
+    Comment::new(format!(
+        ":hourglass: Testing commit {head_sha} with merge {merge_sha}…"
+    ))
+}
+
+pub fn merge_build_succeeded_comment(
+    workflows: &[WorkflowModel],
+    approver: &str,
+    merge_sha: &CommitSha,
+    base_branch: &str,
+) -> Comment {
+    Comment::new(format!(
+        r#":sunny: Test successful
+{}
+Approved by: `{approver}`
+Pushed {merge_sha} to `{base_branch}`."#,
+        workflows_summary(workflows)
+    ))
+}
+
+/// Success comment of a merge build whose approval was forced, which lists the ignored failures.
+pub fn forced_merge_build_succeeded_comment(
+    workflows: &[WorkflowModel],
+    approver: &str,
+    merge_sha: &CommitSha,
+    base_branch: &str,
+) -> Comment {
+    let mut comment = merge_build_succeeded_comment(workflows, approver, merge_sha, base_branch);
+    comment.text += "\n:warning: **This pull request was force-merged**, failures of checks that are not required were ignored.";
+    append_ignored_failures(&mut comment, workflows);
+    comment
+}
+
+/// The base branch of a PR has moved while its merge build was running, so the tested merge
+/// commit can no longer be fast-forwarded to. The PR stays approved and is tested again.
+pub fn base_branch_moved_comment(base_branch: &str) -> Comment {
+    Comment::new(format!(
+        ":arrows_counterclockwise: `{base_branch}` has changed while this pull request was being tested, so it was not merged. It will be tested again on top of the latest `{base_branch}`."
+    ))
+}
+
+pub fn merge_push_failed_comment(merge_sha: &CommitSha, base_branch: &str) -> Comment {
+    Comment::new(format!(
+        ":x: Could not push {merge_sha} to `{base_branch}`. The pull request was unapproved, approve it again to retry the merge."
+    ))
+}
+
+/// The tested merge commit could not be turned into the commits that land the PR with the merge
+/// style of the repository (e.g. a commit of the PR could not be rebased).
+pub fn merge_style_failed_comment(merge_style: &str, reason: &str, base_branch: &str) -> Comment {
+    Comment::new(format!(
+        ":x: Could not {merge_style} this pull request onto `{base_branch}`: {reason}. The pull request was unapproved, approve it again to retry the merge."
+    ))
+}
+
+pub fn branches_cleaned_comment(deleted: &[String], skipped: &[String]) -> Comment {
+    let format_branches = |branches: &[String]| {
+        branches
+            .iter()
+            .map(|branch| format!("`{branch}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut text = if deleted.is_empty() {
+        ":broom: There were no branches to clean.".to_string()
+    } else {
+        format!(":broom: Deleted branch(es): {}", format_branches(deleted))
+    };
+    if !skipped.is_empty() {
+        text += &format!(
+            "\nSkipped branch(es) with a running build: {}",
+            format_branches(skipped)
+        );
+    }
+    Comment::new(text)
+}
+
+pub fn build_cancelled_by_admin_comment(
+    admin: &str,
+    workflow_urls: impl Iterator<Item = String>,
+) -> Comment {
+    let mut text = format!(
+        ":stop_sign: Build cancelled by `{admin}`, who has cancelled all running builds of this repository."
+    );
+    let workflow_urls: Vec<_> = workflow_urls.collect();
+    if !workflow_urls.is_empty() {
+        text += "\nCancelled workflows:";
+        for url in workflow_urls {
+            text += &format!("\n- {url}");
+        }
+    }
+    Comment::new(text)
+}
+
+pub fn all_builds_cancelled_comment(cancelled: u32, failed: u32) -> Comment {
+    let mut text = if cancelled == 0 {
+        ":stop_sign: There were no running builds to cancel.".to_string()
+    } else {
+        format!(":stop_sign: Cancelled {cancelled} running build(s).")
+    };
+    if failed > 0 {
+        text += &format!("\n{failed} build(s) could not be cancelled, see the logs for details.");
+    }
+    Comment::new(text)
+}
+
+pub fn approval_expired_comment(sha: &str, days: u32) -> Comment {
+    Comment::new(format!(
+        ":hourglass: The approval of commit {sha} has expired after {days} day(s), the PR has to be reviewed again."
+    ))
+}
+
+pub fn delegation_expired_comment(permission: DelegatedPermission, days: u32) -> Comment {
+    Comment::new(format!(
+        ":hourglass: The delegation of the `{}` permission to the PR author has expired after {days} day(s).",
+        delegated_permission_name(permission)
+    ))
+}
+
+/// Lists the delegations of a repository, together with the time when they expire if
+/// `expiry_days` is set.
+pub fn delegations_comment(delegations: &[DelegationModel], expiry_days: Option<u32>) -> Comment {
+    if delegations.is_empty() {
+        return Comment::new("There are no delegations in this repository.".to_string());
+    }
+    let mut text = "Delegations in this repository:\n".to_string();
+    for delegation in delegations {
+        let delegated_by = delegation
+            .delegated_by
+            .as_deref()
+            .map(|user| format!("@{user}"))
+            .unwrap_or_else(|| "an unknown user".to_string());
+        let expiration = match expiry_days {
+            Some(days) => format!(
+                "expires on {}",
+                format_date(delegation.delegated_at + chrono::Duration::days(days as i64))
+            ),
+            None => "does not expire".to_string(),
+        };
+        text += &format!(
+            "\n- #{}: `{}` delegated by {delegated_by} on {}, {expiration}",
+            delegation.pr_number,
+            delegated_permission_name(delegation.permission),
+            format_date(delegation.delegated_at)
+        );
+    }
+    Comment::new(text)
+}
+
+fn delegated_permission_name(permission: DelegatedPermission) -> &'static str {
+    match permission {
+        DelegatedPermission::Review => "review",
+        DelegatedPermission::Try => "try",
+    }
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+pub fn merge_conflict_comment(branch: &str) -> Comment {
+    let message = format!(
+        r#":lock: Merge conflict
+
+This pull request and the master branch diverged in a way that cannot
+ be automatically merged. Please rebase on top of the latest master
+ branch, and let the reviewer approve again.
+
+<details><summary>How do I rebase?</summary>
+
+Assuming `self` is your fork and `upstream` is this repository,
+ you can resolve the conflict following these steps:
+
+1. `git checkout {branch}` *(switch to your branch)*
+2. `git fetch upstream master` *(retrieve the latest master)*
+3. `git rebase upstream/master -p` *(rebase on top of it)*
+4. Follow the on-screen instruction to resolve conflicts (check `git status` if you got lost).
+5. `git push self {branch} --force-with-lease` *(update this PR)*
+
+You may also read
+ [*Git Rebasing to Resolve Conflicts* by Drew Blessing](http://blessing.io/git/git-rebase/open-source/2015/08/23/git-rebasing-to-resolve-conflicts.html)
+ for a short tutorial.
+
+Please avoid the ["**Resolve conflicts**" button](https://help.github.com/articles/resolving-a-merge-conflict-on-github/) on GitHub.
+ It uses `git merge` instead of `git rebase` which makes the PR commit history more difficult to read.
+
+Sometimes step 4 will complete without asking for resolution. This is usually due to difference between how `Cargo.lock` conflict is
+handled during merge and rebase. This is normal, and you should still perform step 5 to update this PR.
+
+</details>
+"#
+    );
+    Comment::new(message)
+}
+
+pub fn repository_paused_comment(author: &str) -> Comment {
+    Comment::new(format!(
+        ":pause_button: Bors has been paused on this repository by @{author}. Running builds are left alone, but commands that change the state of PRs are rejected and no new builds are started until `resume` is issued."
+    ))
+}
+
+pub fn repository_resumed_comment(author: &str) -> Comment {
+    Comment::new(format!(
+        ":arrow_forward: Bors has been resumed on this repository by @{author}."
+    ))
+}
+
+pub fn repository_paused_command_comment() -> Comment {
+    Comment::new(
+        ":pause_button: Bors is paused on this repository, the command was not executed."
+            .to_string(),
+    )
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m {secs}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {secs}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Returns a code fence that is longer than any sequence of backticks in `text`, so that the
+/// text cannot terminate the code block prematurely.
+fn code_fence(text: &str) -> String {
+    let longest_backticks = text
+        .split(|c| c != '`')
+        .map(|backticks| backticks.len())
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest_backticks.max(2) + 1)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Renders a table with the status and duration of each workflow, with failed workflows listed
+/// first. The table is surrounded by empty lines, so that it is not merged with the
+/// neighbouring text.
+fn workflows_summary(workflows: &[WorkflowModel]) -> String {
+    if workflows.is_empty() {
+        return ":warning: No workflows were reported for this build, CI might not be configured correctly.".to_string();
+    }
+
+    let mut workflows = workflows.iter().collect::<Vec<_>>();
+    workflows.sort_by_key(|w| {
+        let order = match w.status {
+            WorkflowStatus::Failure | WorkflowStatus::ActionRequired => 0,
+            WorkflowStatus::Pending => 1,
+            WorkflowStatus::Success => 2,
+            WorkflowStatus::Skipped => 3,
+        };
+        (order, w.created_at)
+    });
+
+    let mut table = "\n| Workflow | Status | Duration |\n|---|---|---|\n".to_string();
+    for (index, workflow) in workflows.iter().enumerate() {
+        let row = format!(
+            "| [{}]({}) | {} | {} |\n",
+            workflow.name,
+            workflow.url,
+            match workflow.status {
+                WorkflowStatus::Success => ":white_check_mark:",
+                WorkflowStatus::Failure => ":x:",
+                WorkflowStatus::Pending => ":hourglass_flowing_sand:",
+                WorkflowStatus::Skipped => ":fast_forward:",
+                WorkflowStatus::ActionRequired => ":raised_hand:",
+            },
+            workflow
+                .completed_at
+                .and_then(|completed_at| (completed_at - workflow.created_at).to_std().ok())
+                .map(format_duration)
+                .unwrap_or_else(|| "-".to_string())
+        );
+        if table.len() + row.len() > MAX_WORKFLOW_TABLE_LENGTH {
+            table += &format!(
+                "| ...and {} more workflow(s) | | |\n",
+                workflows.len() - index
+            );
+            break;
+        }
+        table += &row;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use crate::bors::comment::{
+        Comment, JobLogExcerpt, MAX_COMMENT_LENGTH, delegations_comment,
+        try_build_succeeded_comment, workflow_failed_comment,
+    };
+    use crate::database::{
+        BuildModel, BuildStatus, DelegatedPermission, DelegationModel, RunId, WorkflowModel,
+        WorkflowStatus, WorkflowType,
+    };
+    use crate::github::{CommitSha, GithubRepoName, PullRequestNumber};
+
+    fn start_time() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    /// Creates a workflow that was started `offset` seconds after the start of the build and
+    /// ran for `duration` seconds (or is still running if `duration` is `None`).
+    fn workflow(
+        run_id: u64,
+        status: WorkflowStatus,
+        offset: i64,
+        duration: Option<i64>,
+    ) -> WorkflowModel {
+        let created_at = start_time() + TimeDelta::seconds(offset);
+        WorkflowModel {
+            id: run_id as i32,
+            build: BuildModel {
+                id: 1,
+                repository: GithubRepoName::new("rust-lang", "borstest"),
+                branch: "automation/bors/try".to_string(),
+                commit_sha: "sha".to_string(),
+                status: BuildStatus::Pending,
+                parent: "parent".to_string(),
+                created_at: start_time(),
+                completed_at: None,
+                branches_deleted: false,
+                force: false,
+                pull_request_id: None,
+                check_run_id: None,
+                head_sha: None,
+                jobs: vec![],
+            },
+            name: format!("Workflow{run_id}"),
+            url: format!("https://github.com/workflows/{run_id}"),
+            run_id: RunId(run_id),
+            run_attempt: 1,
+            workflow_type: WorkflowType::Github,
+            status,
+            created_at,
+            completed_at: duration.map(|duration| created_at + TimeDelta::seconds(duration)),
+        }
+    }
+
+    #[test]
+    fn try_build_succeeded_summary() {
+        let workflows = [
+            workflow(1, WorkflowStatus::Success, 0, Some(65)),
+            workflow(2, WorkflowStatus::Success, 1, Some(3700)),
+        ];
+        insta::assert_snapshot!(
+            try_build_succeeded_comment(&workflows, CommitSha("sha".to_string()), &[]).render(),
+            @r#"
+        :sunny: Try build successful
+
+        | Workflow | Status | Duration |
+        |---|---|---|
+        | [Workflow1](https://github.com/workflows/1) | :white_check_mark: | 1m 5s |
+        | [Workflow2](https://github.com/workflows/2) | :white_check_mark: | 1h 1m 40s |
+
+        Build commit: sha (`sha`)
+        <!-- homu: {"type":"TryBuildCompleted","merge_sha":"sha"} -->
+        "#
+        );
+    }
+
+    #[test]
+    fn try_build_succeeded_requested_jobs() {
+        let workflows = [workflow(1, WorkflowStatus::Success, 0, Some(65))];
+        let jobs = ["ci".to_string(), "lint".to_string()];
+        insta::assert_snapshot!(
+            try_build_succeeded_comment(&workflows, CommitSha("sha".to_string()), &jobs).render(),
+            @r#"
+        :sunny: Try build successful
+
+        | Workflow | Status | Duration |
+        |---|---|---|
+        | [Workflow1](https://github.com/workflows/1) | :white_check_mark: | 1m 5s |
+
+        Requested jobs: `ci`, `lint`
+        Build commit: sha (`sha`)
+        <!-- homu: {"type":"TryBuildCompleted","merge_sha":"sha"} -->
+        "#
+        );
+    }
+
+    #[test]
+    fn delegations_list() {
+        let delegations = [
+            DelegationModel {
+                pull_request_id: 1,
+                pr_number: PullRequestNumber(3),
+                permission: DelegatedPermission::Review,
+                delegated_by: Some("reviewer".to_string()),
+                delegated_at: start_time(),
+            },
+            DelegationModel {
+                pull_request_id: 2,
+                pr_number: PullRequestNumber(5),
+                permission: DelegatedPermission::Try,
+                delegated_by: None,
+                delegated_at: start_time() + TimeDelta::hours(1),
+            },
+        ];
+        insta::assert_snapshot!(delegations_comment(&delegations, Some(7)).render(), @r"
+        Delegations in this repository:
+
+        - #3: `review` delegated by @reviewer on 2023-11-14 22:13 UTC, expires on 2023-11-21 22:13 UTC
+        - #5: `try` delegated by an unknown user on 2023-11-14 23:13 UTC, expires on 2023-11-21 23:13 UTC
+        ");
+        insta::assert_snapshot!(
+            delegations_comment(&delegations[..1], None).render(),
+            @r"
+        Delegations in this repository:
+
+        - #3: `review` delegated by @reviewer on 2023-11-14 22:13 UTC, does not expire
+        "
+        );
+        insta::assert_snapshot!(
+            delegations_comment(&[], Some(7)).render(),
+            @"There are no delegations in this repository."
+        );
+    }
+
+    #[test]
+    fn workflow_failed_summary_lists_failures_first() {
+        let workflows = [
+            workflow(1, WorkflowStatus::Success, 0, Some(10)),
+            workflow(2, WorkflowStatus::Pending, 1, None),
+            workflow(3, WorkflowStatus::Failure, 2, Some(30)),
+        ];
+        insta::assert_snapshot!(workflow_failed_comment(&workflows, &[]).render(), @r"
+        :broken_heart: Test failed
+
+        | Workflow | Status | Duration |
+        |---|---|---|
+        | [Workflow3](https://github.com/workflows/3) | :x: | 30s |
+        | [Workflow2](https://github.com/workflows/2) | :hourglass_flowing_sand: | - |
+        | [Workflow1](https://github.com/workflows/1) | :white_check_mark: | 10s |
+        ");
+    }
+
+    #[test]
+    fn workflow_failed_with_log_excerpt() {
+        let workflows = [workflow(1, WorkflowStatus::Failure, 0, Some(30))];
+        let logs = [JobLogExcerpt {
+            job_name: "test <linux>".to_string(),
+            excerpt: "error: expected `;`\n```rust\nlet x = 1\n```\n##[error]Process completed with exit code 1.".to_string(),
+        }];
+        insta::assert_snapshot!(workflow_failed_comment(&workflows, &logs).render(), @r"
+        :broken_heart: Test failed
+
+        | Workflow | Status | Duration |
+        |---|---|---|
+        | [Workflow1](https://github.com/workflows/1) | :x: | 30s |
+
+        <details><summary>Log of the failed job <code>test &lt;linux&gt;</code></summary>
+
+        ````
+        error: expected `;`
+        ```rust
+        let x = 1
+        ```
+        ##[error]Process completed with exit code 1.
+        ````
+
+        </details>
+        ");
+    }
+
+    #[test]
+    fn try_build_succeeded_without_workflows() {
+        insta::assert_snapshot!(
+            try_build_succeeded_comment(&[], CommitSha("sha".to_string()), &[]).render(),
+            @r#"
+        :sunny: Try build successful
+        :warning: No workflows were reported for this build, CI might not be configured correctly.
+        Build commit: sha (`sha`)
+        <!-- homu: {"type":"TryBuildCompleted","merge_sha":"sha"} -->
+        "#
+        );
+    }
+
+    #[test]
+    fn workflow_summary_truncated() {
+        let workflows = (0..2000)
+            .map(|id| workflow(id, WorkflowStatus::Failure, 0, Some(1)))
+            .collect::<Vec<_>>();
+        let comment = workflow_failed_comment(&workflows, &[]);
+        let rendered = comment.render();
+        assert!(rendered.len() <= MAX_COMMENT_LENGTH);
+        assert!(rendered.contains("more workflow(s) | | |"));
+    }
+
+    const LINK: &str = "https://github.com/rust-lang/borstest/pull/1/checks";
+
+    #[test]
+    fn render_short_comment_unchanged() {
+        let comment = Comment::new("Hello".to_string());
+        assert_eq!(comment.render_truncated(LINK), "Hello");
+    }
+
+    #[test]
+    fn render_oversized_comment_truncated() {
+        let comment = Comment::new("a".repeat(MAX_COMMENT_LENGTH * 2));
+        let rendered = comment.render_truncated(LINK);
+        assert_eq!(rendered.len(), MAX_COMMENT_LENGTH);
+        assert!(rendered.starts_with("aaa"));
+        assert!(rendered.ends_with(&format!(
+            "\n\n... (truncated, see {LINK} for the full details)"
+        )));
+    }
+
+    #[test]
+    fn render_truncated_keeps_char_boundaries() {
+        let comment = Comment::new("ö".repeat(MAX_COMMENT_LENGTH));
+        let rendered = comment.render_truncated(LINK);
+        assert!(rendered.len() <= MAX_COMMENT_LENGTH);
+        assert!(rendered.contains("... (truncated"));
+    }
+
+    #[test]
+    fn render_truncated_keeps_metadata() {
+        let mut comment = try_build_succeeded_comment(&[], CommitSha("sha".to_string()), &[]);
+        comment.text.push_str(&"a".repeat(MAX_COMMENT_LENGTH));
+        let rendered = comment.render_truncated(LINK);
+        assert!(rendered.len() <= MAX_COMMENT_LENGTH);
+        assert!(
+            rendered.ends_with(r#"<!-- homu: {"type":"TryBuildCompleted","merge_sha":"sha"} -->"#)
+        );
+    }
 }
@@ -7,10 +7,18 @@ use crate::{PgDbClient, bors::command::CommandParser, github::GithubRepoName};
 
 use super::RepositoryState;
 
+/// Repositories managed by bors, shared between the bors process and the web server.
+pub type RepositoryMap = Arc<RwLock<HashMap<GithubRepoName, Arc<RepositoryState>>>>;
+
 pub struct BorsContext {
     pub parser: CommandParser,
     pub db: Arc<PgDbClient>,
-    pub repositories: RwLock<HashMap<GithubRepoName, Arc<RepositoryState>>>,
+    pub repositories: RepositoryMap,
+    /// Base URL of the GitHub web UI (e.g. `https://github.com`).
+    pub github_html_url: String,
+    /// If set, bors does not modify the state of repositories on GitHub, it only logs what it
+    /// would do.
+    pub dry_run: bool,
 }
 
 impl BorsContext {
@@ -18,12 +26,16 @@ impl BorsContext {
         parser: CommandParser,
         db: Arc<PgDbClient>,
         repositories: HashMap<GithubRepoName, Arc<RepositoryState>>,
+        github_html_url: String,
+        dry_run: bool,
     ) -> Self {
-        let repositories = RwLock::new(repositories);
+        let repositories = Arc::new(RwLock::new(repositories));
         Self {
             parser,
             db,
             repositories,
+            github_html_url,
+            dry_run,
         }
     }
 }
@@ -0,0 +1,129 @@
+//! Errors returned by the handlers of bors commands and events, classified by how the failure
+//! should be dealt with.
+use thiserror::Error;
+
+use crate::bors::Comment;
+use crate::database::DbError;
+use crate::github::api::retry::is_retryable_github_error;
+
+pub type BorsResult<T> = Result<T, BorsError>;
+
+/// Error returned by a handler.
+///
+/// Errors of other operations are converted into it using `?`, see the `From<anyhow::Error>`
+/// implementation for how they are classified.
+#[derive(Error, Debug)]
+pub enum BorsError {
+    /// The user has made a mistake that they can fix. The comment is posted to the PR instead of
+    /// being reported as a failure of bors.
+    #[error("{}", comment.summary())]
+    UserFacing { comment: Comment },
+    /// A request to the GitHub API has failed.
+    /// If the failure is `retryable`, handling of the event can be retried later.
+    #[error("GitHub API error: {error:#}")]
+    GithubApi {
+        retryable: bool,
+        error: anyhow::Error,
+    },
+    #[error(transparent)]
+    Database(#[from] DbError),
+    /// An unexpected error, most likely a bug.
+    #[error(transparent)]
+    Internal(anyhow::Error),
+}
+
+impl BorsError {
+    pub fn user_facing(message: impl Into<String>) -> Self {
+        Comment::new(message.into()).into()
+    }
+
+    /// Returns true if the operation might succeed if it is retried later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BorsError::GithubApi { retryable, .. } => *retryable,
+            BorsError::Database(error) => error.is_transient(),
+            BorsError::UserFacing { .. } | BorsError::Internal(_) => false,
+        }
+    }
+}
+
+impl From<Comment> for BorsError {
+    fn from(comment: Comment) -> Self {
+        BorsError::UserFacing { comment }
+    }
+}
+
+impl From<anyhow::Error> for BorsError {
+    fn from(error: anyhow::Error) -> Self {
+        // Errors of nested handlers have already been classified
+        let error = match error.downcast::<BorsError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<DbError>() {
+            Ok(error) => return BorsError::Database(error),
+            Err(error) => error,
+        };
+        let github_error = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<octocrab::Error>())
+            .map(is_retryable_github_error);
+        match github_error {
+            Some(retryable) => BorsError::GithubApi { retryable, error },
+            None => BorsError::Internal(error),
+        }
+    }
+}
+
+/// Returns true if an operation that has failed with the given error might succeed if it is
+/// retried later.
+pub fn is_retryable_error(error: &anyhow::Error) -> bool {
+    if let Some(error) = error.downcast_ref::<BorsError>() {
+        return error.is_retryable();
+    }
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<octocrab::Error>()
+            .is_some_and(is_retryable_github_error)
+            || cause
+                .downcast_ref::<DbError>()
+                .is_some_and(DbError::is_transient)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::{BorsError, is_retryable_error};
+    use crate::bors::Comment;
+    use crate::database::DbError;
+
+    #[test]
+    fn keep_classified_error() {
+        let error = anyhow::Error::from(BorsError::user_facing("Wrong SHA"))
+            .context("Cannot execute command");
+        assert!(matches!(
+            BorsError::from(error),
+            BorsError::UserFacing { comment } if comment.summary() == "Wrong SHA"
+        ));
+    }
+
+    #[test]
+    fn classify_database_error() {
+        let error = Err::<(), _>(DbError::from(sqlx::Error::PoolTimedOut))
+            .context("Cannot load PR")
+            .unwrap_err();
+        assert!(is_retryable_error(&error));
+        let error = BorsError::from(error);
+        assert!(matches!(error, BorsError::Database(_)));
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn classify_other_error() {
+        let error = BorsError::from(anyhow::anyhow!("Unexpected state"));
+        assert!(matches!(error, BorsError::Internal(_)));
+        assert!(!error.is_retryable());
+    }
+}
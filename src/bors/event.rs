@@ -1,12 +1,18 @@
 use crate::database::{WorkflowStatus, WorkflowType};
 use crate::github::{CommitSha, GithubRepoName, GithubUser, PullRequest, PullRequestNumber};
-use chrono::Duration;
-use octocrab::models::RunId;
+use chrono::{DateTime, Duration, Utc};
+use octocrab::models::{CommentId, RunId};
+use tokio::sync::oneshot;
+use tracing::Span;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum BorsRepositoryEvent {
     /// A comment was posted on a pull request.
     Comment(PullRequestComment),
+    /// The text of a comment on a pull request was edited.
+    CommentEdited(PullRequestCommentEdited),
+    /// A pull request was approved or changes were requested through a GitHub review.
+    PullRequestReviewed(PullRequestReviewed),
     /// When a new commit is pushed to the pull request branch.
     PullRequestCommitPushed(PullRequestPushed),
     /// When the pull request is edited by its author
@@ -23,6 +29,10 @@ pub enum BorsRepositoryEvent {
     PullRequestConvertedToDraft(PullRequestConvertedToDraft),
     // When a pull request is ready for review
     PullRequestReadyForReview(PullRequestReadyForReview),
+    /// When a label is added to a pull request.
+    PullRequestLabeled(PullRequestLabeled),
+    /// When a label is removed from a pull request.
+    PullRequestUnlabeled(PullRequestUnlabeled),
     /// When there is a push to a branch. This includes when a commit is pushed, when a commit tag is pushed,
     /// when a branch is deleted or when a tag is deleted.
     PushToBranch(PushToBranch),
@@ -33,12 +43,17 @@ pub enum BorsRepositoryEvent {
     /// A check suite has been completed, either as a workflow run on Github Actions, or as a
     /// workflow from some external CI system.
     CheckSuiteCompleted(CheckSuiteCompleted),
+    /// A check run reported through the GitHub Checks API (by something else than Github Actions)
+    /// has been completed.
+    CheckRunCompleted(CheckRunCompleted),
 }
 
 impl BorsRepositoryEvent {
     pub fn repository(&self) -> &GithubRepoName {
         match self {
             BorsRepositoryEvent::Comment(comment) => &comment.repository,
+            BorsRepositoryEvent::CommentEdited(payload) => &payload.comment.repository,
+            BorsRepositoryEvent::PullRequestReviewed(payload) => &payload.repository,
             BorsRepositoryEvent::PullRequestCommitPushed(payload) => &payload.repository,
             BorsRepositoryEvent::PullRequestEdited(payload) => &payload.repository,
             BorsRepositoryEvent::PullRequestOpened(payload) => &payload.repository,
@@ -47,20 +62,103 @@ impl BorsRepositoryEvent {
             BorsRepositoryEvent::PullRequestReopened(payload) => &payload.repository,
             BorsRepositoryEvent::PullRequestConvertedToDraft(payload) => &payload.repository,
             BorsRepositoryEvent::PullRequestReadyForReview(payload) => &payload.repository,
+            BorsRepositoryEvent::PullRequestLabeled(payload) => &payload.repository,
+            BorsRepositoryEvent::PullRequestUnlabeled(payload) => &payload.repository,
             BorsRepositoryEvent::PushToBranch(payload) => &payload.repository,
             BorsRepositoryEvent::WorkflowStarted(workflow) => &workflow.repository,
             BorsRepositoryEvent::WorkflowCompleted(workflow) => &workflow.repository,
             BorsRepositoryEvent::CheckSuiteCompleted(payload) => &payload.repository,
+            BorsRepositoryEvent::CheckRunCompleted(payload) => &payload.repository,
+        }
+    }
+
+    /// Returns the number of the pull request that the event concerns, if there is one.
+    pub fn pr_number(&self) -> Option<PullRequestNumber> {
+        match self {
+            BorsRepositoryEvent::Comment(comment) => Some(comment.pr_number),
+            BorsRepositoryEvent::CommentEdited(payload) => Some(payload.comment.pr_number),
+            BorsRepositoryEvent::PullRequestReviewed(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestCommitPushed(payload) => {
+                Some(payload.pull_request.number)
+            }
+            BorsRepositoryEvent::PullRequestEdited(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestOpened(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestClosed(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestMerged(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestReopened(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestConvertedToDraft(payload) => {
+                Some(payload.pull_request.number)
+            }
+            BorsRepositoryEvent::PullRequestReadyForReview(payload) => {
+                Some(payload.pull_request.number)
+            }
+            BorsRepositoryEvent::PullRequestLabeled(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PullRequestUnlabeled(payload) => Some(payload.pull_request.number),
+            BorsRepositoryEvent::PushToBranch(_)
+            | BorsRepositoryEvent::WorkflowStarted(_)
+            | BorsRepositoryEvent::WorkflowCompleted(_)
+            | BorsRepositoryEvent::CheckSuiteCompleted(_)
+            | BorsRepositoryEvent::CheckRunCompleted(_) => None,
         }
     }
 }
 
+/// A repository event along with the span of the webhook delivery that has produced it.
+/// Handling of the event is instrumented with the span, so that all log messages of a single
+/// delivery can be correlated.
+#[derive(Debug)]
+pub struct RepositoryEventDelivery {
+    pub event: BorsRepositoryEvent,
+    /// GUID of the webhook delivery, from the `X-GitHub-Delivery` header.
+    pub delivery_id: String,
+    /// Type of the webhook event, from the `X-GitHub-Event` header.
+    pub event_type: String,
+    /// ID of the event in the event queue, it is marked as processed once it is handled.
+    pub queue_id: i32,
+    pub span: Span,
+}
+
 #[derive(Debug)]
 pub enum BorsGlobalEvent {
     /// The configuration of some repository has been changed for the bot's Github App.
     InstallationsChanged,
     /// Periodic event that serves for checking e.g. timeouts.
     Refresh,
+    /// A repository was renamed or transferred to another owner on GitHub.
+    RepositoryRenamed {
+        old_name: GithubRepoName,
+        new_name: GithubRepoName,
+    },
+    /// Reload the configuration and permissions of a single repository.
+    /// The reply is `None` if the repository is not known to the bot.
+    ReloadRepository {
+        repository: GithubRepoName,
+        reply: oneshot::Sender<Option<anyhow::Result<RepositoryReloadSummary>>>,
+    },
+    /// Backfill the open pull requests of a single repository from GitHub into the database.
+    /// The reply is `None` if the repository is not known to the bot.
+    SyncRepository {
+        repository: GithubRepoName,
+        reply: oneshot::Sender<Option<anyhow::Result<PullRequestSyncSummary>>>,
+    },
+}
+
+/// Describes what has changed after a repository was reloaded.
+#[derive(Debug, serde::Serialize)]
+pub struct RepositoryReloadSummary {
+    pub config_hash_before: String,
+    pub config_hash_after: String,
+    pub reviewer_count_before: usize,
+    pub reviewer_count_after: usize,
+}
+
+/// Describes how many pull requests were stored by a synchronization with GitHub.
+#[derive(Debug, serde::Serialize)]
+pub struct PullRequestSyncSummary {
+    /// Pull requests that were not known to the database before.
+    pub created: usize,
+    /// Pull requests that already existed in the database.
+    pub updated: usize,
 }
 
 #[derive(Debug)]
@@ -72,93 +170,161 @@ pub enum BorsEvent {
     Global(BorsGlobalEvent),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestComment {
     pub repository: GithubRepoName,
     pub author: GithubUser,
     pub pr_number: PullRequestNumber,
     pub text: String,
     pub html_url: String,
+    /// ID of the issue comment that contained the text.
+    /// It is `None` for comments attached to a review, which cannot be reacted to through the
+    /// issue comments API.
+    pub comment_id: Option<CommentId>,
+    /// When was the comment posted. It is `None` if GitHub did not report it.
+    pub created_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+pub struct PullRequestCommentEdited {
+    /// The comment with its new text.
+    pub comment: PullRequestComment,
+    /// Text of the comment before it was edited.
+    pub previous_text: String,
+}
+
+/// Verdict of a review submitted through GitHub.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewVerdict {
+    Approved,
+    ChangesRequested,
+}
+
+#[derive(Clone, Debug)]
+pub struct PullRequestReviewed {
+    pub repository: GithubRepoName,
+    pub pull_request: PullRequest,
+    pub verdict: ReviewVerdict,
+    /// The commit that was reviewed. It is `None` if GitHub did not report it.
+    pub commit_sha: Option<CommitSha>,
+    /// The body of the review, which can contain bors commands.
+    pub comment: PullRequestComment,
+}
+
+#[derive(Clone, Debug)]
 pub struct PullRequestPushed {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestEdited {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
     pub from_base_sha: Option<CommitSha>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestOpened {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
     pub draft: bool,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestClosed {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestMerged {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestReopened {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestConvertedToDraft {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PullRequestReadyForReview {
     pub repository: GithubRepoName,
     pub pull_request: PullRequest,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+pub struct PullRequestLabeled {
+    pub repository: GithubRepoName,
+    pub pull_request: PullRequest,
+    /// Name of the added label.
+    pub label: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct PullRequestUnlabeled {
+    pub repository: GithubRepoName,
+    pub pull_request: PullRequest,
+    /// Name of the removed label.
+    pub label: String,
+}
+
+#[derive(Clone, Debug)]
 pub struct PushToBranch {
     pub repository: GithubRepoName,
     pub branch: String,
+    /// Whether the push was made to the default branch of the repository.
+    pub is_default_branch: bool,
+    /// SHA of the most recent commit on the branch after the push.
+    pub commit_sha: CommitSha,
+    /// Files that were added, modified or removed by the pushed commits.
+    pub changed_files: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct WorkflowStarted {
     pub repository: GithubRepoName,
     pub name: String,
     pub branch: String,
     pub commit_sha: CommitSha,
     pub run_id: RunId,
+    /// Attempt of the run, increased by GitHub Actions each time the workflow is re-run.
+    pub run_attempt: u32,
     pub workflow_type: WorkflowType,
     pub url: String,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct WorkflowCompleted {
     pub repository: GithubRepoName,
     pub branch: String,
     pub commit_sha: CommitSha,
     pub run_id: RunId,
+    pub run_attempt: u32,
     pub status: WorkflowStatus,
     pub running_time: Option<Duration>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+pub struct CheckRunCompleted {
+    pub repository: GithubRepoName,
+    pub name: String,
+    pub branch: String,
+    pub commit_sha: CommitSha,
+    pub run_id: RunId,
+    pub url: String,
+    pub status: WorkflowStatus,
+}
+
+#[derive(Clone, Debug)]
 pub struct CheckSuiteCompleted {
     pub repository: GithubRepoName,
     pub branch: String,
@@ -0,0 +1,1359 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, anyhow};
+use chrono::Utc;
+
+use crate::PgDbClient;
+use crate::bors::PullRequestStatus;
+use crate::bors::RepositoryState;
+use crate::bors::comment::{
+    base_branch_moved_comment, forced_merge_build_succeeded_comment, merge_build_started_comment,
+    merge_build_succeeded_comment, merge_conflict_comment, merge_push_failed_comment,
+    merge_style_failed_comment, workflow_failed_comment,
+};
+use crate::bors::handlers::check_run::start_build_check_run;
+use crate::bors::handlers::commit_status::report_build_started;
+use crate::bors::handlers::job_logs::load_failed_job_logs;
+use crate::bors::handlers::labels::handle_label_trigger;
+use crate::bors::handlers::status_comment::post_status_comment;
+use crate::bors::handlers::trybuild::{MergeResult, attempt_merge, auto_merge_commit_message};
+use crate::bors::merge_queue::{select_next_pr, select_next_pr_fair};
+use crate::bors::template::{MessageKind, truncate_text};
+use crate::config::{MergeStyle, QueueOrdering};
+use crate::database::{
+    BuildModel, BuildStatus, MergeableState, PullRequestModel, TreeState, WorkflowModel,
+};
+use crate::github::api::client::GitAuthor;
+use crate::github::{BranchUpdateError, CommitSha, LabelTrigger, MergeError, PullRequest};
+
+// This branch serves for preparing the merge commit of an approved PR, like the try merge branch.
+// It should not run CI checks.
+pub(super) const AUTO_MERGE_BRANCH_NAME: &str = "automation/bors/auto-merge";
+
+// This branch runs CI checks of merge builds. Its commit is pushed to the base branch of the PR
+// once the build succeeds.
+pub(super) const AUTO_BRANCH_NAME: &str = "automation/bors/auto";
+
+/// How far into the past are merge builds of PR authors counted with the `fair` queue ordering.
+const FAIR_QUEUE_WINDOW: chrono::Duration = chrono::Duration::days(1);
+
+/// Starts a merge build of the next PR in the merge queue, unless the merge queue is disabled,
+/// the repository is paused or a merge build is already running.
+pub(super) async fn process_merge_queue(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+) -> anyhow::Result<()> {
+    let _guard = repo.merge_queue_lock.lock().await;
+    process_merge_queue_locked(repo, db).await
+}
+
+/// Same as [process_merge_queue], but the caller has to hold the merge queue lock of the
+/// repository.
+/// PRs that cannot be tested anymore when their build is being started are skipped, and the
+/// next PR is tried instead. PRs that turn out to have merge conflicts are also removed from the
+/// queue. If the tree is closed, only PRs with at least the priority of the tree closure are
+/// tested.
+/// PRs are selected according to the `queue_ordering` of the repository.
+pub(super) async fn process_merge_queue_locked(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+) -> anyhow::Result<()> {
+    if !repo.config.load().merge_queue_enabled || repo.is_paused() {
+        return Ok(());
+    }
+    if db
+        .count_pending_builds(repo.repository(), AUTO_BRANCH_NAME)
+        .await?
+        > 0
+    {
+        return Ok(());
+    }
+
+    let mut prs = db.get_open_prs(repo.repository()).await?;
+    if let Some(TreeState::Closed { priority, .. }) = db
+        .repo_db(repo.repository())
+        .await?
+        .map(|repo| repo.tree_state)
+    {
+        prs.retain(|pr| i64::from(pr.priority.unwrap_or(0)) >= i64::from(priority));
+    }
+    let builds_per_author = match repo.config.load().queue_ordering {
+        QueueOrdering::Fifo => None,
+        QueueOrdering::Fair => Some(
+            db.count_recent_builds_by_author(
+                repo.repository(),
+                AUTO_BRANCH_NAME,
+                Utc::now() - FAIR_QUEUE_WINDOW,
+            )
+            .await?,
+        ),
+    };
+    while let Some(pr) = next_pr(&prs, builds_per_author.as_ref()) {
+        let number = pr.number;
+        if start_merge_build(repo, db, pr).await? {
+            return Ok(());
+        }
+        prs.retain(|pr| pr.number.0 != number.0);
+    }
+    Ok(())
+}
+
+fn next_pr<'a>(
+    prs: &'a [PullRequestModel],
+    builds_per_author: Option<&HashMap<String, u64>>,
+) -> Option<&'a PullRequestModel> {
+    match builds_per_author {
+        Some(builds_per_author) => select_next_pr_fair(prs, builds_per_author),
+        None => select_next_pr(prs),
+    }
+}
+
+/// Starts a merge build of an approved PR.
+/// Returns `false` if the build could not be started, because the PR cannot be merged anymore.
+async fn start_merge_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_model: &PullRequestModel,
+) -> anyhow::Result<bool> {
+    let pr = repo.client.get_pull_request(pr_model.number).await?;
+    // The PR might have been pushed to after it was approved, which invalidates the approval
+    let Some(approver) = pr_model
+        .approver()
+        .filter(|_| pr.status == PullRequestStatus::Open && pr_model.is_approved(&pr.head.sha))
+    else {
+        tracing::info!(
+            "PR {} is no longer open and approved at its head commit, not testing it",
+            pr.number
+        );
+        return Ok(false);
+    };
+    if MergeableState::from(pr.mergeable_state.clone()) == MergeableState::HasConflicts {
+        mark_merge_conflict(repo, db, pr_model, &pr).await?;
+        return Ok(false);
+    }
+
+    let base_sha = repo
+        .client
+        .get_branch_sha(&pr.base.name)
+        .await
+        .context(format!("Cannot get SHA for branch {}", pr.base.name))?;
+    match attempt_merge(
+        &repo.client,
+        AUTO_MERGE_BRANCH_NAME,
+        &pr.head.sha,
+        &base_sha,
+        &auto_merge_commit_message(
+            &repo.config.load(),
+            &pr,
+            repo.client.repository(),
+            approver,
+            &base_sha,
+            &[],
+        ),
+    )
+    .await?
+    {
+        MergeResult::Success(merge_sha) => {
+            repo.client
+                .set_branch_to_sha(AUTO_BRANCH_NAME, &merge_sha)
+                .await
+                .map_err(|error| anyhow!("Cannot set auto branch to {merge_sha}: {error:?}"))?;
+            db.attach_merge_build(
+                pr_model,
+                AUTO_BRANCH_NAME.to_string(),
+                merge_sha.clone(),
+                base_sha,
+                pr.head.sha.clone(),
+            )
+            .await?;
+            tracing::info!("Merge build of PR {} started", pr.number);
+
+            start_build_check_run(repo, db, AUTO_BRANCH_NAME, &pr.head.sha, &merge_sha).await;
+            report_build_started(repo, AUTO_BRANCH_NAME, pr.number, &pr.head.sha, &merge_sha).await;
+            post_status_comment(
+                repo,
+                db,
+                pr.number,
+                merge_build_started_comment(&pr.head.sha, &merge_sha),
+            )
+            .await?;
+            handle_label_trigger(repo, pr.number, LabelTrigger::AutoBuildStarted).await?;
+            Ok(true)
+        }
+        MergeResult::Conflict => {
+            mark_merge_conflict(repo, db, pr_model, &pr).await?;
+            Ok(false)
+        }
+    }
+}
+
+/// Removes a PR with merge conflicts from the merge queue and asks its author to rebase it.
+/// The PR returns to the queue once GitHub reports that the conflicts were resolved.
+async fn mark_merge_conflict(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_model: &PullRequestModel,
+    pr: &PullRequest,
+) -> anyhow::Result<()> {
+    tracing::info!("PR {} has merge conflicts, skipping it", pr.number);
+    db.set_mergeable_state(pr_model, MergeableState::HasConflicts)
+        .await?;
+    post_status_comment(repo, db, pr.number, merge_conflict_comment(&pr.head.name)).await
+}
+
+/// Finishes a completed merge build of `pr`. The PR is merged into its base branch if
+/// the build has succeeded, and unapproved otherwise. Then the next PR in the merge queue is
+/// tested.
+/// The caller has to hold the merge queue lock of the repository.
+pub(super) async fn complete_merge_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    pr: &PullRequestModel,
+    status: BuildStatus,
+    workflows: &[WorkflowModel],
+) -> anyhow::Result<()> {
+    if status == BuildStatus::Success {
+        merge_pr(repo, db, build, pr, workflows).await?;
+    } else {
+        tracing::info!("Merge build of PR {} failed", pr.number);
+        let logs = load_failed_job_logs(repo, workflows).await;
+        post_status_comment(
+            repo,
+            db,
+            pr.number,
+            workflow_failed_comment(workflows, &logs),
+        )
+        .await?;
+        merge_build_failed(repo, db, pr).await?;
+    }
+    process_merge_queue_locked(repo, db).await
+}
+
+/// Fast-forwards the base branch of the PR to the tested merge commit, or to the squashed or
+/// rebased commits with the same tree, depending on the merge style of the repository and of
+/// the PR (`@bors squash`).
+/// If the base branch has moved in the meantime, the PR is left approved, so that it is tested
+/// again on top of the new base.
+async fn merge_pr(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    pr: &PullRequestModel,
+    workflows: &[WorkflowModel],
+) -> anyhow::Result<()> {
+    // The PR might have been unapproved while it was being tested
+    let Some(approver) = pr.approver() else {
+        tracing::info!("PR {} is no longer approved, not merging it", pr.number);
+        return Ok(());
+    };
+
+    let merge_sha = CommitSha(build.commit_sha.clone());
+    let merge_style = if pr.squash {
+        MergeStyle::Squash
+    } else {
+        repo.config.load().merge_style
+    };
+    let landed_sha = match create_landed_commit(repo, build, pr, approver, &merge_sha, merge_style)
+        .await
+    {
+        Ok(LandedCommit::Ready(sha)) => sha,
+        Ok(LandedCommit::Failed(reason)) => {
+            tracing::info!(
+                "PR {} cannot be landed with merge style {merge_style:?}: {reason}",
+                pr.number
+            );
+            let merge_style = match merge_style {
+                MergeStyle::Merge => "merge",
+                MergeStyle::Squash => "squash",
+                MergeStyle::Rebase => "rebase",
+            };
+            post_status_comment(
+                repo,
+                db,
+                pr.number,
+                merge_style_failed_comment(merge_style, &reason, &pr.base_branch),
+            )
+            .await?;
+            return merge_build_failed(repo, db, pr).await;
+        }
+        Err(error) => {
+            tracing::error!(
+                "Cannot create the commits of PR {} with merge style {merge_style:?}: {error:?}",
+                pr.number
+            );
+            post_status_comment(
+                repo,
+                db,
+                pr.number,
+                merge_push_failed_comment(&merge_sha, &pr.base_branch),
+            )
+            .await?;
+            return merge_build_failed(repo, db, pr).await;
+        }
+    };
+
+    match repo
+        .client
+        .fast_forward_branch(&pr.base_branch, &landed_sha)
+        .await
+    {
+        Ok(()) => {
+            tracing::info!("PR {} merged into {}", pr.number, pr.base_branch);
+            db.set_pr_status(repo.repository(), pr.number, PullRequestStatus::Merged)
+                .await?;
+            // GitHub only recognizes a PR as merged if its head commit is reachable from the
+            // base branch, so squashed and rebased PRs have to be closed
+            if merge_style != MergeStyle::Merge {
+                if let Err(error) = repo.client.close_pull_request(pr.number).await {
+                    tracing::error!("Cannot close PR {}: {error:?}", pr.number);
+                }
+            }
+            let comment = if build.force {
+                forced_merge_build_succeeded_comment(
+                    workflows,
+                    approver,
+                    &landed_sha,
+                    &pr.base_branch,
+                )
+            } else {
+                merge_build_succeeded_comment(workflows, approver, &landed_sha, &pr.base_branch)
+            };
+            post_status_comment(repo, db, pr.number, comment).await?;
+            handle_label_trigger(repo, pr.number, LabelTrigger::MergeSucceeded).await
+        }
+        Err(BranchUpdateError::NotFastForward(_)) => {
+            tracing::info!(
+                "Base branch {} of PR {} has moved, testing it again",
+                pr.base_branch,
+                pr.number
+            );
+            post_status_comment(
+                repo,
+                db,
+                pr.number,
+                base_branch_moved_comment(&pr.base_branch),
+            )
+            .await
+        }
+        Err(error) => {
+            tracing::error!("Cannot push {landed_sha} to {}: {error:?}", pr.base_branch);
+            post_status_comment(
+                repo,
+                db,
+                pr.number,
+                merge_push_failed_comment(&landed_sha, &pr.base_branch),
+            )
+            .await?;
+            merge_build_failed(repo, db, pr).await
+        }
+    }
+}
+
+/// Commit to which the base branch of a PR is fast-forwarded when the PR is landed.
+enum LandedCommit {
+    Ready(CommitSha),
+    /// The PR cannot be landed with the merge style of the repository, for the given reason.
+    Failed(String),
+}
+
+/// Creates the commit to which the base branch of `pr` is fast-forwarded, according to the
+/// merge style. Squashed and rebased commits are created through the git database API on top of
+/// the tested base commit, and they have the same tree as the tested merge commit, so that the
+/// base branch ends up with exactly the tested contents.
+async fn create_landed_commit(
+    repo: &RepositoryState,
+    build: &BuildModel,
+    pr: &PullRequestModel,
+    approver: &str,
+    merge_sha: &CommitSha,
+    merge_style: MergeStyle,
+) -> anyhow::Result<LandedCommit> {
+    match merge_style {
+        MergeStyle::Merge => Ok(LandedCommit::Ready(merge_sha.clone())),
+        MergeStyle::Squash => squash_pr(repo, build, pr, approver, merge_sha)
+            .await
+            .map(LandedCommit::Ready),
+        MergeStyle::Rebase => rebase_pr(repo, build, pr, merge_sha).await,
+    }
+}
+
+/// Creates a single commit with the tree of the tested merge commit, whose only parent is the
+/// tested base commit. The commit is authored by the PR author.
+async fn squash_pr(
+    repo: &RepositoryState,
+    build: &BuildModel,
+    pr: &PullRequestModel,
+    approver: &str,
+    merge_sha: &CommitSha,
+) -> anyhow::Result<CommitSha> {
+    let merge_commit = repo.client.get_git_commit(merge_sha).await?;
+    let gh_pr = repo.client.get_pull_request(pr.number).await?;
+    let config = repo.config.load();
+    let body = truncate_text(&gh_pr.message, config.merge_commit_body_limit);
+    let message = config.render_message(
+        MessageKind::SquashCommit,
+        &[
+            ("repo", &repo.repository().to_string()),
+            ("pr", &pr.number.to_string()),
+            ("approver", approver),
+            ("title", &gh_pr.title),
+            ("body", &body),
+        ],
+    );
+    // GitHub attributes commits with the no-reply address of a user to their account
+    let author = GitAuthor {
+        name: gh_pr.author.username.clone(),
+        email: format!(
+            "{}+{}@users.noreply.github.com",
+            gh_pr.author.id, gh_pr.author.username
+        ),
+        date: None,
+    };
+    repo.client
+        .create_git_commit(
+            &message,
+            &merge_commit.tree,
+            &[CommitSha(build.parent.clone())],
+            Some(&author),
+        )
+        .await
+}
+
+/// Replays the commits of the PR on top of the tested base commit, keeping their messages and
+/// authors, and returns the last replayed commit.
+/// GitHub has no API for cherry-picking, so each commit is replayed by merging it into
+/// a temporary commit that has the tree of the already replayed commits, but the parent of the
+/// replayed commit. The merge then applies exactly the changes of the replayed commit, and its
+/// tree is committed on top of the already replayed commits.
+async fn rebase_pr(
+    repo: &RepositoryState,
+    build: &BuildModel,
+    pr: &PullRequestModel,
+    merge_sha: &CommitSha,
+) -> anyhow::Result<LandedCommit> {
+    let commits = repo.client.get_pull_request_commits(pr.number).await?;
+    if commits.last().map(|commit| &commit.sha.0) != build.head_sha.as_ref() {
+        return Ok(LandedCommit::Failed(
+            "its commits have changed since it was tested".to_string(),
+        ));
+    }
+    if commits.iter().any(|commit| commit.parents.len() != 1) {
+        return Ok(LandedCommit::Failed(
+            "it contains merge commits".to_string(),
+        ));
+    }
+
+    let mut head = CommitSha(build.parent.clone());
+    let mut tree = repo.client.get_git_commit(&head).await?.tree;
+    for commit in commits {
+        let base = repo
+            .client
+            .create_git_commit(
+                &format!("Base of {}", commit.sha),
+                &tree,
+                &commit.parents,
+                None,
+            )
+            .await?;
+        repo.client
+            .set_branch_to_sha(AUTO_MERGE_BRANCH_NAME, &base)
+            .await?;
+        let replayed = match repo
+            .client
+            .merge_branches(
+                AUTO_MERGE_BRANCH_NAME,
+                &commit.sha,
+                &format!("Replay {}", commit.sha),
+            )
+            .await
+        {
+            Ok(sha) => sha,
+            Err(MergeError::Conflict) => {
+                return Ok(LandedCommit::Failed(format!(
+                    "commit {} does not apply cleanly",
+                    commit.sha
+                )));
+            }
+            Err(error) => return Err(error.into()),
+        };
+        tree = repo.client.get_git_commit(&replayed).await?.tree;
+        head = repo
+            .client
+            .create_git_commit(&commit.message, &tree, &[head], Some(&commit.author))
+            .await?;
+    }
+
+    // Replaying the commits one by one can resolve differently than the single tested merge,
+    // in which case the result was not tested
+    if tree != repo.client.get_git_commit(merge_sha).await?.tree {
+        return Ok(LandedCommit::Failed(
+            "the rebased commits differ from the tested merge commit".to_string(),
+        ));
+    }
+    Ok(LandedCommit::Ready(head))
+}
+
+/// Unapproves a PR whose merge build has failed or timed out, or which could not be merged,
+/// which removes it from the merge queue until it is approved again.
+pub(super) async fn merge_build_failed(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr: &PullRequestModel,
+) -> anyhow::Result<()> {
+    db.unapprove(pr).await?;
+    handle_label_trigger(repo, pr.number, LabelTrigger::MergeFailed).await?;
+    handle_label_trigger(repo, pr.number, LabelTrigger::Unapproved).await
+}
+
+#[cfg(test)]
+mod tests {
+    use octocrab::models::pulls::MergeableState as OctocrabMergeableState;
+
+    use crate::bors::PullRequestStatus;
+    use crate::bors::handlers::auto_build::{AUTO_BRANCH_NAME, AUTO_MERGE_BRANCH_NAME};
+    use crate::database::{BuildStatus, MergeableState};
+    use crate::github::CommitSha;
+    use crate::tests::mocks::{
+        BorsBuilder, Comment, GitHubState, PullRequest, User, Workflow, default_branch_name,
+        default_pr_number, default_repo_name, mask_durations, run_test,
+    };
+
+    fn gh_state_with_merge_queue() -> GitHubState {
+        GitHubState::default().with_default_config("merge_queue_enabled = true")
+    }
+
+    #[sqlx::test]
+    async fn merge_queue_disabled_by_default(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+            assert_eq!(
+                tester
+                    .db()
+                    .count_pending_builds(&default_repo_name(), AUTO_BRANCH_NAME)
+                    .await?,
+                0
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approval_starts_merge_build(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: Testing commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+                );
+                assert_eq!(
+                    tester.get_branch(AUTO_BRANCH_NAME).sha,
+                    "merge-main-sha1-pr-1-sha-0"
+                );
+                assert_eq!(
+                    tester
+                        .db()
+                        .count_pending_builds(&default_repo_name(), AUTO_BRANCH_NAME)
+                        .await?,
+                    1
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_success_merges_pr(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                insta::assert_snapshot!(
+                    mask_durations(&tester.get_comment().await?),
+                    @r"
+                :sunny: Test successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+
+                Approved by: `default-user`
+                Pushed merge-main-sha1-pr-1-sha-0 to `main`.
+                "
+                );
+                assert_eq!(
+                    tester.get_branch(default_branch_name()).sha,
+                    "merge-main-sha1-pr-1-sha-0"
+                );
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().pr_status,
+                    PullRequestStatus::Merged
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    fn gh_state_with_merge_style(merge_style: &str) -> GitHubState {
+        GitHubState::default().with_default_config(&format!(
+            r#"
+merge_queue_enabled = true
+merge_style = "{merge_style}"
+"#
+        ))
+    }
+
+    #[sqlx::test]
+    async fn merge_style_squash_lands_single_commit(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_style("squash"))
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                insta::assert_snapshot!(
+                    mask_durations(&tester.get_comment().await?),
+                    @r"
+                :sunny: Test successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+
+                Approved by: `default-user`
+                Pushed commit-0-main-sha1 to `main`.
+                "
+                );
+                assert_eq!(
+                    tester.get_branch(default_branch_name()).sha,
+                    "commit-0-main-sha1"
+                );
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().pr_status,
+                    PullRequestStatus::Merged
+                );
+
+                let repo = tester.default_repo();
+                let repo = repo.lock();
+                let commit = &repo.git_commits["commit-0-main-sha1"];
+                assert_eq!(commit.parents, vec!["main-sha1"]);
+                assert_eq!(commit.tree, repo.get_tree("merge-main-sha1-pr-1-sha-0"));
+                assert_eq!(commit.author_name, "default-user");
+                assert_eq!(
+                    commit.author_email,
+                    "101+default-user@users.noreply.github.com"
+                );
+                insta::assert_snapshot!(commit.message, @r"
+                PR #1 (#1)
+
+                Description of PR #1
+
+                r=default-user
+                ");
+                assert_eq!(
+                    repo.get_pr(default_pr_number()).status,
+                    PullRequestStatus::Closed
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn squash_command_overrides_merge_style(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors squash").await?;
+                tester
+                    .wait_for(|| async {
+                        Ok(tester.default_pr_db().await?.is_some_and(|pr| pr.squash))
+                    })
+                    .await?;
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                assert_eq!(
+                    tester.get_branch(default_branch_name()).sha,
+                    "commit-0-main-sha1"
+                );
+                assert_eq!(
+                    tester.default_repo().lock().git_commits["commit-0-main-sha1"].parents,
+                    vec!["main-sha1"]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_style_rebase_replays_commits(pool: sqlx::PgPool) {
+        let gh = gh_state_with_merge_style("rebase");
+        gh.default_repo()
+            .lock()
+            .get_pr_mut(default_pr_number())
+            .previous_commits = vec!["pr-1-commit-1".to_string()];
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+
+                let main_sha = tester.get_branch(default_branch_name()).sha;
+                let repo = tester.default_repo();
+                let repo = repo.lock();
+                let last = &repo.git_commits[&main_sha];
+                assert_eq!(last.message, "Commit pr-1-sha");
+                assert_eq!(last.author_name, "default-user");
+                assert_eq!(last.tree, repo.get_tree("merge-main-sha1-pr-1-sha-0"));
+                assert_eq!(last.parents.len(), 1);
+
+                let first = &repo.git_commits[&last.parents[0]];
+                assert_eq!(first.message, "Commit pr-1-commit-1");
+                assert_eq!(first.author_name, "default-user");
+                assert_eq!(first.tree, "main-sha1+pr-1-commit-1");
+                assert_eq!(first.parents, vec!["main-sha1"]);
+                assert_eq!(
+                    repo.get_pr(default_pr_number()).status,
+                    PullRequestStatus::Closed
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_style_rebase_conflict_unapproves_pr(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_style("rebase"))
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester.create_branch(AUTO_MERGE_BRANCH_NAME).merge_conflict = true;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":x: Could not rebase this pull request onto `main`: commit pr-1-sha does not apply cleanly. The pull request was unapproved, approve it again to retry the merge."
+                );
+                tester.default_pr().await.expect_unapproved();
+                assert_eq!(tester.get_branch(default_branch_name()).sha, "main-sha1");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_check_run(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                {
+                    let repo = tester.default_repo();
+                    let repo = repo.lock();
+                    assert_eq!(repo.check_runs.len(), 1);
+                    assert_eq!(repo.check_runs[0].head_sha, "pr-1-sha");
+                    assert_eq!(repo.check_runs[0].status, "in_progress");
+                }
+
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                let check_run = tester.default_repo().lock().check_runs[0].clone();
+                assert_eq!(check_run.status, "completed");
+                assert_eq!(check_run.conclusion, "success");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_commit_status(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_failure(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                let statuses = tester
+                    .default_repo()
+                    .lock()
+                    .commit_statuses
+                    .iter()
+                    .map(|status| {
+                        assert_eq!(status.context, "bors");
+                        (status.sha.clone(), status.description.clone())
+                    })
+                    .collect::<Vec<_>>();
+                let status =
+                    |sha: &str, description: &str| (sha.to_string(), description.to_string());
+                assert_eq!(
+                    statuses,
+                    vec![
+                        status("pr-1-sha", "Merge build in progress"),
+                        status("merge-main-sha1-pr-1-sha-0", "Merge build in progress"),
+                        status("pr-1-sha", "Merge build failed"),
+                        status("merge-main-sha1-pr-1-sha-0", "Merge build failed"),
+                    ]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn base_branch_moved_during_merge_build(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .push_to_branch(default_branch_name(), &["README.md"])
+                    .await?;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":arrows_counterclockwise: `main` has changed while this pull request was being tested, so it was not merged. It will be tested again on top of the latest `main`."
+                );
+                // The PR is tested again on top of the new `main`
+                let comment = tester.get_comment().await?;
+                assert!(comment.starts_with(
+                    ":hourglass: Testing commit pr-1-sha with merge merge-main-commit-"
+                ));
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().pr_status,
+                    PullRequestStatus::Open
+                );
+                assert_eq!(
+                    tester
+                        .db()
+                        .count_pending_builds(&default_repo_name(), AUTO_BRANCH_NAME)
+                        .await?,
+                    1
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn forced_merge_build_ignores_optional_failure(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+merge_queue_enabled = true
+required_checks = ["CI"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester
+                    .default_repo()
+                    .lock()
+                    .admins
+                    .push(User::default_pr_author().name);
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(2);
+                tester.post_comment("@bors r+ force").await?;
+                tester.expect_comments(2).await;
+                let build = tester
+                    .db()
+                    .find_build(
+                        &default_repo_name(),
+                        AUTO_BRANCH_NAME.to_string(),
+                        CommitSha("merge-main-sha1-pr-1-sha-0".to_string()),
+                    )
+                    .await?
+                    .unwrap();
+                assert!(build.force);
+                tester
+                    .workflow_failure(
+                        Workflow::from(tester.get_branch(AUTO_BRANCH_NAME))
+                            .with_name("Docs")
+                            .with_run_id(1),
+                    )
+                    .await?;
+                tester
+                    .workflow_success(
+                        Workflow::from(tester.get_branch(AUTO_BRANCH_NAME))
+                            .with_name("CI")
+                            .with_run_id(2),
+                    )
+                    .await?;
+                insta::assert_snapshot!(
+                    mask_durations(&tester.get_comment().await?),
+                    @r"
+                :sunny: Test successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Docs](https://github.com/workflows/Docs/1) | :x: | [duration] |
+                | [CI](https://github.com/workflows/CI/2) | :white_check_mark: | [duration] |
+
+                Approved by: `default-user`
+                Pushed merge-main-sha1-pr-1-sha-0 to `main`.
+                :warning: **This pull request was force-merged**, failures of checks that are not required were ignored.
+                Ignored failures:
+                - [Docs](https://github.com/workflows/Docs/1)
+                "
+                );
+                assert_eq!(
+                    tester.get_branch(default_branch_name()).sha,
+                    "merge-main-sha1-pr-1-sha-0"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_failure_unapproves_pr(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_failure(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                insta::assert_snapshot!(
+                    mask_durations(&tester.get_comment().await?),
+                    @r"
+                :broken_heart: Test failed
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Workflow1](https://github.com/workflows/Workflow1/1) | :x: | [duration] |
+                "
+                );
+                tester.default_pr().await.expect_unapproved();
+                assert_eq!(tester.get_branch(default_branch_name()).sha, "main-sha1");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_start_modify_labels(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+merge_queue_enabled = true
+
+[labels]
+auto = ["+foo", "-baz"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                let pr = tester
+                    .default_repo()
+                    .lock()
+                    .get_pr(default_pr_number())
+                    .clone();
+                pr.check_added_labels(&["foo"]);
+                pr.check_removed_labels(&["baz"]);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_succeeded_modify_labels(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+merge_queue_enabled = true
+
+[labels]
+merge_succeed = ["+merged", "-waiting"]
+merge_failed = ["+failed"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .default_repo()
+                    .lock()
+                    .get_pr(default_pr_number())
+                    .check_added_labels(&[]);
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                let pr = tester
+                    .default_repo()
+                    .lock()
+                    .get_pr(default_pr_number())
+                    .clone();
+                pr.check_added_labels(&["merged"]);
+                pr.check_removed_labels(&["waiting"]);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_build_failed_modify_labels(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+merge_queue_enabled = true
+
+[labels]
+merge_succeed = ["+merged"]
+merge_failed = ["+failed", "-waiting"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_failure(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                let pr = tester
+                    .default_repo()
+                    .lock()
+                    .get_pr(default_pr_number())
+                    .clone();
+                pr.check_added_labels(&["failed"]);
+                pr.check_removed_labels(&["waiting"]);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn merge_conflict_removes_pr_from_queue(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_MERGE_BRANCH_NAME).merge_conflict = true;
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                let comment = tester.get_comment().await?;
+                assert!(comment.starts_with(":lock: Merge conflict"));
+
+                let pr = tester.default_pr_db().await?.unwrap();
+                assert_eq!(pr.mergeable_state, MergeableState::HasConflicts);
+                assert!(pr.merge_build.is_none());
+                // The PR stays approved, so that it returns to the queue once it is fixed
+                tester.default_pr().await.expect_approved_sha("pr-1-sha");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn resolved_conflict_requeues_pr(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_merge_queue())
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_MERGE_BRANCH_NAME).merge_conflict = true;
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+
+                tester.get_branch_mut(AUTO_MERGE_BRANCH_NAME).merge_conflict = false;
+                tester
+                    .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                        pr.mergeable_state = OctocrabMergeableState::Clean;
+                    })
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: Testing commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+                );
+                let pr = tester.default_pr_db().await?.unwrap();
+                assert_eq!(pr.mergeable_state, MergeableState::Mergeable);
+                assert_eq!(pr.merge_build.unwrap().status, BuildStatus::Pending);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn conflicting_pr_is_skipped(pool: sqlx::PgPool) {
+        let gh = gh_state_with_merge_queue();
+        gh.default_repo().lock().pull_requests.insert(
+            2,
+            PullRequest::new(default_repo_name(), 2, User::default_pr_author(), false),
+        );
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors r+"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+
+                // The merge of PR 1 moves `main`, and PR 2 no longer merges cleanly with it
+                tester.create_branch(AUTO_MERGE_BRANCH_NAME).merge_conflict = true;
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                let comment = tester.get_pr_comment(2).await?;
+                assert!(comment.starts_with(":lock: Merge conflict"));
+                let pr = tester.pr_db(default_repo_name(), 2).await?.unwrap();
+                assert_eq!(pr.mergeable_state, MergeableState::HasConflicts);
+                assert!(pr.merge_build.is_none());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn pr_approved_at_older_commit_is_skipped(pool: sqlx::PgPool) {
+        let gh = gh_state_with_merge_queue();
+        gh.default_repo().lock().pull_requests.insert(
+            2,
+            PullRequest::new(default_repo_name(), 2, User::default_pr_author(), false),
+        );
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors r+"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+                // The head of the PR has moved since it was approved
+                tester
+                    .default_repo()
+                    .lock()
+                    .pull_requests
+                    .get_mut(&2)
+                    .unwrap()
+                    .head_sha = "pr-2-new-sha".to_string();
+
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                assert_eq!(
+                    tester
+                        .db()
+                        .count_pending_builds(&default_repo_name(), AUTO_BRANCH_NAME)
+                        .await?,
+                    0
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn next_pr_is_tested_after_merge(pool: sqlx::PgPool) {
+        let gh = gh_state_with_merge_queue();
+        gh.default_repo().lock().pull_requests.insert(
+            2,
+            PullRequest::new(default_repo_name(), 2, User::default_pr_author(), false),
+        );
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                // Only a single merge build runs at a time
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors r+"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+                assert_eq!(
+                    tester
+                        .db()
+                        .count_pending_builds(&default_repo_name(), AUTO_BRANCH_NAME)
+                        .await?,
+                    1
+                );
+
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(2).await?,
+                    @":hourglass: Testing commit pr-2-sha with merge merge-merge-main-sha1-pr-1-sha-0-pr-2-sha-1…"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn priority_change_reorders_queue(pool: sqlx::PgPool) {
+        let gh = gh_state_with_merge_queue();
+        {
+            let repo = gh.default_repo();
+            let mut repo = repo.lock();
+            for number in [2, 3] {
+                repo.pull_requests.insert(
+                    number,
+                    PullRequest::new(
+                        default_repo_name(),
+                        number,
+                        User::default_pr_author(),
+                        false,
+                    ),
+                );
+            }
+        }
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                for number in [2, 3] {
+                    tester
+                        .post_comment(Comment::new(default_repo_name(), number, "@bors r+"))
+                        .await?;
+                    tester.get_pr_comment(number).await?;
+                }
+
+                // Bumping the priority of PR 3 moves it in front of PR 2
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 3, "@bors p=5"))
+                    .await?;
+                tester
+                    .wait_for(|| async {
+                        let pr = tester.pr_db(default_repo_name(), 3).await?.unwrap();
+                        Ok(pr.priority == Some(5))
+                    })
+                    .await?;
+
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(3).await?,
+                    @":hourglass: Testing commit pr-3-sha with merge merge-merge-main-sha1-pr-1-sha-0-pr-3-sha-1…"
+                );
+                assert!(
+                    tester
+                        .pr_db(default_repo_name(), 2)
+                        .await?
+                        .unwrap()
+                        .merge_build
+                        .is_none()
+                );
+
+                // PR 2 is tested once PR 3 has been merged
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.get_pr_comment(3).await?;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(2).await?,
+                    @":hourglass: Testing commit pr-2-sha with merge merge-merge-merge-main-sha1-pr-1-sha-0-pr-3-sha-1-pr-2-sha-2…"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn fair_ordering_interleaves_authors(pool: sqlx::PgPool) {
+        let gh = GitHubState::default().with_default_config(
+            r#"
+merge_queue_enabled = true
+queue_ordering = "fair"
+"#,
+        );
+        {
+            let repo = gh.default_repo();
+            let mut repo = repo.lock();
+            repo.pull_requests.insert(
+                2,
+                PullRequest::new(default_repo_name(), 2, User::default_pr_author(), false),
+            );
+            repo.pull_requests.insert(
+                3,
+                PullRequest::new(default_repo_name(), 3, User::unprivileged(), false),
+            );
+        }
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors r+"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 3, "@bors r+"))
+                    .await?;
+                tester.get_pr_comment(3).await?;
+
+                // The author of PR 1 already had a merge build, so PR 3 goes before PR 2
+                tester
+                    .workflow_success(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(3).await?,
+                    @":hourglass: Testing commit pr-3-sha with merge merge-merge-main-sha1-pr-1-sha-0-pr-3-sha-1…"
+                );
+                assert!(
+                    tester
+                        .pr_db(default_repo_name(), 2)
+                        .await?
+                        .unwrap()
+                        .merge_build
+                        .is_none()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use crate::PgDbClient;
+use crate::bors::comment::{all_builds_cancelled_comment, build_cancelled_by_admin_comment};
+use crate::bors::handlers::check_run::complete_build_check_run;
+use crate::bors::handlers::commit_status::report_build_completed;
+use crate::bors::handlers::notification::notify_build_completed;
+use crate::bors::handlers::status_comment::post_status_comment;
+use crate::bors::handlers::trybuild::cancel_build_workflows;
+use crate::bors::{BorsError, BorsResult, RepositoryState};
+use crate::database::{BuildModel, BuildStatus, Pagination};
+use crate::github::{GithubUser, PullRequest};
+
+/// Number of running builds that are loaded from the database at once.
+const CANCEL_ALL_BATCH_SIZE: u32 = 50;
+/// Maximum number of batches that are processed by a single `cancel-all` command, so that
+/// builds that cannot be cancelled (and thus stay running) cannot make the command loop forever.
+const CANCEL_ALL_MAX_BATCHES: u32 = 20;
+
+/// Cancels all running builds of the repository and reports how many builds were cancelled.
+/// Only repository admins can do that.
+pub(super) async fn command_cancel_all(
+    repo: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    pr: &PullRequest,
+    author: &GithubUser,
+) -> BorsResult<()> {
+    if !repo.is_admin(author).await? {
+        tracing::warn!("Cancelling all builds denied for {}", author.username);
+        return Err(BorsError::user_facing(format!(
+            ":lock: @{}: You don't have permission to cancel all builds: only repository admins can do that",
+            author.username
+        )));
+    }
+
+    let _guard = repo.try_builds_lock.lock().await;
+    let mut cancelled: u32 = 0;
+    // Builds that could not be cancelled stay running, so they are skipped when loading the
+    // next batch.
+    let mut failed: u32 = 0;
+    for _ in 0..CANCEL_ALL_MAX_BATCHES {
+        let builds = db
+            .get_running_builds_page(
+                repo.repository(),
+                Pagination {
+                    limit: Some(CANCEL_ALL_BATCH_SIZE),
+                    offset: failed,
+                },
+            )
+            .await?;
+        if builds.is_empty() {
+            break;
+        }
+        for build in builds {
+            match cancel_running_build(&repo, &db, &build, author).await {
+                Ok(()) => cancelled += 1,
+                Err(error) => {
+                    tracing::error!("Could not cancel build {}: {error:?}", build.commit_sha);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    tracing::info!("Cancelled {cancelled} build(s), {failed} build(s) could not be cancelled");
+
+    repo.client
+        .post_comment(pr.number, all_builds_cancelled_comment(cancelled, failed))
+        .await?;
+    Ok(())
+}
+
+/// Cancels the workflows of a running build, marks it as cancelled and lets its PR know.
+/// Failures to cancel the workflows or to notify the PR are only logged, because the build
+/// has already been cancelled at that point.
+async fn cancel_running_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    author: &GithubUser,
+) -> anyhow::Result<()> {
+    let workflow_ids = match cancel_build_workflows(&repo.client, db, build).await {
+        Ok(workflow_ids) => workflow_ids,
+        Err(error) => {
+            tracing::error!(
+                "Could not cancel workflows for SHA {}: {error:?}",
+                build.commit_sha
+            );
+            vec![]
+        }
+    };
+    db.update_build_status(build, BuildStatus::Cancelled)
+        .await?;
+
+    let Some(pr) = db.find_pr_by_build(build).await? else {
+        tracing::warn!("No PR found for build {}", build.commit_sha);
+        return Ok(());
+    };
+    notify_build_completed(repo, build, pr.number, BuildStatus::Cancelled);
+    complete_build_check_run(repo, db, build, BuildStatus::Cancelled).await;
+    report_build_completed(repo, build, pr.number, BuildStatus::Cancelled).await;
+    if let Err(error) = post_status_comment(
+        repo,
+        db,
+        pr.number,
+        build_cancelled_by_admin_comment(
+            &author.username,
+            repo.client.get_workflow_urls(workflow_ids.into_iter()),
+        ),
+    )
+    .await
+    {
+        tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::BuildStatus;
+    use crate::tests::mocks::{
+        BorsBuilder, Comment, GitHubState, PullRequest, User, default_repo_name, run_test,
+    };
+
+    fn gh_state_with_two_prs() -> GitHubState {
+        let state = GitHubState::default();
+        state.default_repo().lock().pull_requests.insert(
+            2,
+            PullRequest::new(default_repo_name(), 2, User::default_pr_author(), false),
+        );
+        state
+            .default_repo()
+            .lock()
+            .admins
+            .push(User::default_pr_author().name);
+        state
+    }
+
+    #[sqlx::test]
+    async fn cancel_all_requires_admin(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors cancel-all").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @default-user: You don't have permission to cancel all builds: only repository admins can do that"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn cancel_all_without_running_builds(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_two_prs())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors cancel-all").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":stop_sign: There were no running builds to cancel."
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn cancel_all_running_builds(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_two_prs())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+
+                tester.post_comment("@bors cancel-all").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":stop_sign: Build cancelled by `default-user`, who has cancelled all running builds of this repository."
+                );
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(2).await?,
+                    @":stop_sign: Build cancelled by `default-user`, who has cancelled all running builds of this repository."
+                );
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":stop_sign: Cancelled 2 running build(s)."
+                );
+                assert!(
+                    tester
+                        .db()
+                        .get_running_builds(&default_repo_name())
+                        .await?
+                        .is_empty()
+                );
+                let pr = tester.default_pr_db().await?.unwrap();
+                assert_eq!(pr.try_build.unwrap().status, BuildStatus::Cancelled);
+                Ok(tester)
+            })
+            .await;
+    }
+}
@@ -0,0 +1,432 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use url::Url;
+
+use crate::bors::RepositoryState;
+use crate::bors::comment::format_duration;
+use crate::bors::handlers::notification::{
+    NOTIFICATION_RETRY_DELAY, NOTIFICATION_TIMEOUT, deliver_with_retries,
+};
+use crate::config::ZulipConfig;
+use crate::database::{WorkflowModel, WorkflowStatus};
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+/// Environment variable with the URL of the Slack incoming webhook that receives chat
+/// notifications.
+const SLACK_WEBHOOK_URL_ENV: &str = "SLACK_WEBHOOK_URL";
+
+/// Environment variable with the API key of the Zulip bot that posts chat notifications.
+const ZULIP_API_KEY_ENV: &str = "ZULIP_API_KEY";
+
+/// Event about the health of the queue that is reported to the chat channels of a repository.
+pub(super) enum ChatEvent<'a> {
+    /// A merge build of a PR has failed. Its failed `workflows` are listed in the message.
+    MergeBuildFailed {
+        pr: PullRequestNumber,
+        workflows: &'a [WorkflowModel],
+    },
+    /// A build of a PR has not finished in time.
+    BuildTimedOut {
+        pr: PullRequestNumber,
+        branch: &'a str,
+        elapsed: Duration,
+    },
+    TreeClosed {
+        priority: u32,
+        author: &'a str,
+        source: &'a str,
+    },
+    TreeOpened {
+        author: &'a str,
+    },
+}
+
+impl ChatEvent<'_> {
+    fn message(
+        &self,
+        repo: &GithubRepoName,
+        pr_url: impl Fn(PullRequestNumber) -> String,
+    ) -> String {
+        match self {
+            ChatEvent::MergeBuildFailed { pr, workflows } => {
+                let mut text = format!(
+                    ":broken_heart: Merge build of {repo}#{pr} failed: {}",
+                    pr_url(*pr)
+                );
+                let mut failed = workflows
+                    .iter()
+                    .filter(|w| {
+                        matches!(
+                            w.status,
+                            WorkflowStatus::Failure | WorkflowStatus::ActionRequired
+                        )
+                    })
+                    .peekable();
+                if failed.peek().is_some() {
+                    text += "\nFailed jobs:";
+                    for workflow in failed {
+                        text += &format!("\n- {}: {}", workflow.name, workflow.url);
+                    }
+                }
+                text
+            }
+            ChatEvent::BuildTimedOut {
+                pr,
+                branch,
+                elapsed,
+            } => format!(
+                ":boom: Build of {repo}#{pr} on `{branch}` timed out after {}: {}",
+                format_duration(*elapsed),
+                pr_url(*pr)
+            ),
+            ChatEvent::TreeClosed {
+                priority,
+                author,
+                source,
+            } => format!(
+                ":lock: The tree of {repo} was closed for PRs with priority less than {priority} by {author}: {source}"
+            ),
+            ChatEvent::TreeOpened { author } => {
+                format!(":unlock: The tree of {repo} was opened by {author}")
+            }
+        }
+    }
+}
+
+/// Sends a message about `event` to the chat channels configured in the `[notifications]`
+/// table of the repository, if any.
+/// Messages are sent in the background, so that an unavailable chat does not block the
+/// handling of the event. Failed deliveries are retried a few times and then logged.
+pub(super) fn notify_chat(repo: &RepositoryState, event: ChatEvent<'_>) {
+    let config = repo.config.load().notifications.clone();
+    if config.is_empty() {
+        return;
+    }
+    let text = event.message(repo.repository(), |pr| repo.client.get_pr_url(pr));
+
+    if config.slack {
+        match std::env::var(SLACK_WEBHOOK_URL_ENV).map(|url| Url::parse(&url)) {
+            Ok(Ok(url)) => {
+                let text = text.clone();
+                tokio::spawn(async move {
+                    let client = reqwest::Client::new();
+                    if let Err(error) =
+                        send_slack_message(&client, &url, &text, NOTIFICATION_RETRY_DELAY).await
+                    {
+                        tracing::error!("Could not send chat notification to Slack: {error:?}");
+                    }
+                });
+            }
+            Ok(Err(error)) => tracing::error!(
+                "Not sending chat notification to Slack, {SLACK_WEBHOOK_URL_ENV} is not a valid URL: {error}"
+            ),
+            Err(_) => tracing::warn!(
+                "Not sending chat notification to Slack, {SLACK_WEBHOOK_URL_ENV} is not set"
+            ),
+        }
+    }
+    if let Some(zulip) = config.zulip {
+        let Ok(api_key) = std::env::var(ZULIP_API_KEY_ENV) else {
+            tracing::warn!(
+                "Not sending chat notification to Zulip, {ZULIP_API_KEY_ENV} is not set"
+            );
+            return;
+        };
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            if let Err(error) =
+                send_zulip_message(&client, &zulip, &api_key, &text, NOTIFICATION_RETRY_DELAY).await
+            {
+                tracing::error!("Could not send chat notification to Zulip: {error:?}");
+            }
+        });
+    }
+}
+
+#[derive(Serialize)]
+struct SlackMessage<'a> {
+    text: &'a str,
+}
+
+async fn send_slack_message(
+    client: &reqwest::Client,
+    url: &Url,
+    text: &str,
+    retry_delay: Duration,
+) -> anyhow::Result<()> {
+    deliver_with_retries(url.as_str(), retry_delay, || {
+        client
+            .post(url.clone())
+            .timeout(NOTIFICATION_TIMEOUT)
+            .json(&SlackMessage { text })
+            .send()
+    })
+    .await
+}
+
+async fn send_zulip_message(
+    client: &reqwest::Client,
+    zulip: &ZulipConfig,
+    api_key: &str,
+    text: &str,
+    retry_delay: Duration,
+) -> anyhow::Result<()> {
+    let url = zulip.url.join("api/v1/messages")?;
+    deliver_with_retries(url.as_str(), retry_delay, || {
+        client
+            .post(url.clone())
+            .timeout(NOTIFICATION_TIMEOUT)
+            .basic_auth(&zulip.bot_email, Some(api_key))
+            .form(&[
+                ("type", "stream"),
+                ("to", zulip.stream.as_str()),
+                ("topic", zulip.topic.as_str()),
+                ("content", text),
+            ])
+            .send()
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use url::Url;
+    use wiremock::matchers::{body_json, body_string, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::bors::handlers::auto_build::AUTO_BRANCH_NAME;
+    use crate::bors::handlers::chat::{
+        ChatEvent, SLACK_WEBHOOK_URL_ENV, send_slack_message, send_zulip_message,
+    };
+    use crate::bors::handlers::notification::NOTIFICATION_ATTEMPTS;
+    use crate::config::ZulipConfig;
+    use crate::database::{
+        BuildModel, BuildStatus, RunId, WorkflowModel, WorkflowStatus, WorkflowType,
+    };
+    use crate::github::{GithubRepoName, PullRequestNumber};
+    use crate::tests::mocks::{BorsBuilder, GitHubState};
+
+    fn message(event: ChatEvent) -> String {
+        event.message(&GithubRepoName::new("rust-lang", "borstest"), |pr| {
+            format!("https://github.com/rust-lang/borstest/pull/{pr}")
+        })
+    }
+
+    fn workflow(name: &str, run_id: u64, status: WorkflowStatus) -> WorkflowModel {
+        WorkflowModel {
+            id: run_id as i32,
+            build: BuildModel {
+                id: 1,
+                repository: GithubRepoName::new("rust-lang", "borstest"),
+                branch: "automation/bors/auto".to_string(),
+                commit_sha: "merge-sha".to_string(),
+                status: BuildStatus::Failure,
+                parent: "main-sha".to_string(),
+                created_at: Utc::now(),
+                completed_at: None,
+                branches_deleted: false,
+                force: false,
+                pull_request_id: None,
+                check_run_id: None,
+                head_sha: None,
+                jobs: vec![],
+            },
+            name: name.to_string(),
+            url: format!("https://github.com/rust-lang/borstest/actions/runs/{run_id}"),
+            run_id: RunId(run_id),
+            run_attempt: 1,
+            workflow_type: WorkflowType::Github,
+            status,
+            created_at: Utc::now(),
+            completed_at: None,
+        }
+    }
+
+    #[test]
+    fn merge_build_failed_message() {
+        let workflows = [
+            workflow("CI", 1, WorkflowStatus::Failure),
+            workflow("Docs", 2, WorkflowStatus::Failure),
+            workflow("Lint", 3, WorkflowStatus::Success),
+            workflow("Deploy", 4, WorkflowStatus::ActionRequired),
+        ];
+        insta::assert_snapshot!(message(ChatEvent::MergeBuildFailed {
+            pr: PullRequestNumber(1),
+            workflows: &workflows,
+        }), @r"
+        :broken_heart: Merge build of rust-lang/borstest#1 failed: https://github.com/rust-lang/borstest/pull/1
+        Failed jobs:
+        - CI: https://github.com/rust-lang/borstest/actions/runs/1
+        - Docs: https://github.com/rust-lang/borstest/actions/runs/2
+        - Deploy: https://github.com/rust-lang/borstest/actions/runs/4
+        ");
+    }
+
+    #[test]
+    fn build_timed_out_message() {
+        insta::assert_snapshot!(message(ChatEvent::BuildTimedOut {
+            pr: PullRequestNumber(2),
+            branch: "automation/bors/try",
+            elapsed: Duration::from_secs(3 * 3600 + 60),
+        }), @":boom: Build of rust-lang/borstest#2 on `automation/bors/try` timed out after 3h 1m 0s: https://github.com/rust-lang/borstest/pull/2");
+    }
+
+    #[test]
+    fn tree_messages() {
+        insta::assert_snapshot!(message(ChatEvent::TreeClosed {
+            priority: 100,
+            author: "reviewer",
+            source: "https://github.com/rust-lang/borstest/pull/1#issuecomment-1",
+        }), @":lock: The tree of rust-lang/borstest was closed for PRs with priority less than 100 by reviewer: https://github.com/rust-lang/borstest/pull/1#issuecomment-1");
+        insta::assert_snapshot!(message(ChatEvent::TreeOpened {
+            author: "reviewer",
+        }), @":unlock: The tree of rust-lang/borstest was opened by reviewer");
+    }
+
+    #[tokio::test]
+    async fn send_slack_message_payload() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/slack"))
+            .and(body_json(serde_json::json!({ "text": "Hello" })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url: Url = format!("{}/slack", server.uri()).parse().unwrap();
+        send_slack_message(&reqwest::Client::new(), &url, "Hello", Duration::ZERO)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_slack_message_give_up() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(u64::from(NOTIFICATION_ATTEMPTS))
+            .mount(&server)
+            .await;
+
+        let url: Url = server.uri().parse().unwrap();
+        assert!(
+            send_slack_message(&reqwest::Client::new(), &url, "Hello", Duration::ZERO)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn send_zulip_message_payload() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/messages"))
+            .and(header(
+                "authorization",
+                "Basic Ym9ycy1ib3RAZXhhbXBsZS5jb206c2VjcmV0",
+            ))
+            .and(header("content-type", "application/x-www-form-urlencoded"))
+            .and(body_string(
+                "type=stream&to=t-infra&topic=bors&content=Hello+%3Aunlock%3A",
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let zulip = ZulipConfig {
+            url: server.uri().parse().unwrap(),
+            bot_email: "bors-bot@example.com".to_string(),
+            stream: "t-infra".to_string(),
+            topic: "bors".to_string(),
+        };
+        send_zulip_message(
+            &reqwest::Client::new(),
+            &zulip,
+            "secret",
+            "Hello :unlock:",
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Waits until the chat endpoint has received `count` requests, because notifications are
+    /// sent in the background.
+    async fn wait_for_requests(server: &MockServer, count: usize) -> Vec<serde_json::Value> {
+        for _ in 0..500 {
+            let requests = server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests
+                    .iter()
+                    .map(|request| request.body_json().unwrap())
+                    .collect();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Chat endpoint has not received {count} request(s)");
+    }
+
+    // The URL of the Slack webhook is read from the environment of the process, which is shared
+    // by all tests, so the chat notifications of bors are tested by this single test.
+    #[sqlx::test]
+    async fn notify_chat_events(pool: sqlx::PgPool) {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/slack"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        // SAFETY: Other tests do not enable Slack notifications, so they never read the variable.
+        unsafe {
+            std::env::set_var(SLACK_WEBHOOK_URL_ENV, format!("{}/slack", server.uri()));
+        }
+        let config = r#"
+merge_queue_enabled = true
+
+[notifications]
+slack = true
+"#;
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(config))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors treeclosed=5").await?;
+                tester.expect_comments(1).await;
+                tester.post_comment("@bors treeclosed-").await?;
+                tester.expect_comments(1).await;
+
+                tester.create_branch(AUTO_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(2).await;
+                tester
+                    .workflow_failure(tester.get_branch(AUTO_BRANCH_NAME))
+                    .await?;
+                tester.expect_comments(1).await;
+
+                let messages = wait_for_requests(&server, 3).await;
+                insta::assert_snapshot!(
+                    messages[0]["text"].as_str().unwrap(),
+                    @":lock: The tree of rust-lang/borstest was closed for PRs with priority less than 5 by default-user: https://github.com/rust-lang/borstest/pull/1#issuecomment-1"
+                );
+                insta::assert_snapshot!(
+                    messages[1]["text"].as_str().unwrap(),
+                    @":unlock: The tree of rust-lang/borstest was opened by default-user"
+                );
+                insta::assert_snapshot!(
+                    messages[2]["text"].as_str().unwrap(),
+                    @r"
+                :broken_heart: Merge build of rust-lang/borstest#1 failed: https://github.com/rust-lang/borstest/pull/1
+                Failed jobs:
+                - Workflow1: https://github.com/workflows/Workflow1/1
+                "
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+}
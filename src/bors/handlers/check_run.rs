@@ -0,0 +1,135 @@
+use crate::PgDbClient;
+use crate::bors::RepositoryState;
+use crate::database::{BuildModel, BuildStatus, WorkflowStatus};
+use crate::github::{CheckRunConclusion, CommitSha};
+
+/// Name of the check run that reports the result of a build on the head commit of its PR.
+pub(super) const BUILD_CHECK_RUN_NAME: &str = "bors";
+
+/// Creates an in-progress check run for a build that has just been started on `branch` for
+/// `build_sha`, and stores its ID on the build, so that it can be completed once the build
+/// finishes.
+/// Does nothing if reporting check runs is disabled in the repository. Errors are only logged,
+/// because the check run is informative and should not prevent the build from running.
+pub(super) async fn start_build_check_run(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    branch: &str,
+    head_sha: &CommitSha,
+    build_sha: &CommitSha,
+) {
+    if !repo.config.load().report_check_run {
+        return;
+    }
+    if let Err(error) = create_check_run(repo, db, branch, head_sha, build_sha).await {
+        tracing::error!("Cannot create check run for build {build_sha}: {error:?}");
+    }
+}
+
+async fn create_check_run(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    branch: &str,
+    head_sha: &CommitSha,
+    build_sha: &CommitSha,
+) -> anyhow::Result<()> {
+    let Some(build) = db
+        .find_build(repo.repository(), branch.to_string(), build_sha.clone())
+        .await?
+    else {
+        anyhow::bail!("Build was not found");
+    };
+    let summary = format!(
+        "Testing commit [{build_sha}]({}).",
+        repo.client.get_commit_url(&build_sha.0)
+    );
+    if let Some(check_run_id) = repo
+        .client
+        .start_check_run(
+            BUILD_CHECK_RUN_NAME,
+            head_sha,
+            "Build in progress",
+            &summary,
+        )
+        .await?
+    {
+        db.set_build_check_run_id(&build, check_run_id).await?;
+    }
+    Ok(())
+}
+
+/// Completes the check run of `build` (if it has one) according to the final `status` of the
+/// build. The summary links to the build commit and to the workflows that have failed.
+/// Errors are only logged.
+pub(super) async fn complete_build_check_run(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    status: BuildStatus,
+) {
+    let Some(check_run_id) = build.check_run_id else {
+        return;
+    };
+    let (conclusion, title) = match status {
+        BuildStatus::Pending => {
+            tracing::warn!(
+                "Not completing the check run of unfinished build {}",
+                build.commit_sha
+            );
+            return;
+        }
+        BuildStatus::Success => (CheckRunConclusion::Success, "Build succeeded"),
+        BuildStatus::Failure => (CheckRunConclusion::Failure, "Build failed"),
+        BuildStatus::Cancelled => (CheckRunConclusion::Cancelled, "Build cancelled"),
+        BuildStatus::Timeouted => (CheckRunConclusion::TimedOut, "Build timed out"),
+    };
+    let summary = match build_summary(repo, db, build).await {
+        Ok(summary) => summary,
+        Err(error) => {
+            tracing::error!(
+                "Cannot load workflows of build {}: {error:?}",
+                build.commit_sha
+            );
+            return;
+        }
+    };
+    if let Err(error) = repo
+        .client
+        .complete_check_run(check_run_id as u64, conclusion, title, &summary)
+        .await
+    {
+        tracing::error!(
+            "Cannot complete check run of build {}: {error:?}",
+            build.commit_sha
+        );
+    }
+}
+
+async fn build_summary(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+) -> anyhow::Result<String> {
+    let mut summary = format!(
+        "Tested commit [{}]({}).",
+        build.commit_sha,
+        repo.client.get_commit_url(&build.commit_sha)
+    );
+    let workflows = db.get_workflows_for_build(build).await?;
+    let failed: Vec<_> = workflows
+        .iter()
+        .filter(|workflow| {
+            matches!(
+                workflow.status,
+                WorkflowStatus::Failure | WorkflowStatus::ActionRequired
+            )
+        })
+        .collect();
+    if !failed.is_empty() {
+        summary.push_str("\n\nFailed workflows:\n");
+        for workflow in failed {
+            summary.push_str(&format!("- [{}]({})\n", workflow.name, workflow.url));
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use crate::PgDbClient;
+use crate::bors::RepositoryState;
+use crate::bors::comment::branches_cleaned_comment;
+use crate::bors::handlers::auto_build::{AUTO_BRANCH_NAME, AUTO_MERGE_BRANCH_NAME};
+use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, TRY_MERGE_BRANCH_NAME};
+use crate::bors::{BorsError, BorsResult};
+use crate::database::{BuildModel, BuildStatus};
+use crate::github::{GithubUser, PullRequest};
+
+/// Branches that were cleaned (or not) by [delete_build_branches].
+#[derive(Default)]
+pub(super) struct CleanedBranches {
+    pub deleted: Vec<String>,
+    /// Branches that were not deleted, because a build is still running on them.
+    pub skipped: Vec<String>,
+}
+
+/// Deletes all branches created by bors for builds that have already finished.
+/// Only repository admins can do that.
+pub(super) async fn command_clean(
+    repo: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    pr: &PullRequest,
+    author: &GithubUser,
+) -> BorsResult<()> {
+    if !repo.is_admin(author).await? {
+        tracing::warn!("Cleaning of branches denied for {}", author.username);
+        return Err(BorsError::user_facing(format!(
+            ":lock: @{}: You don't have permission to clean up bors branches: only repository admins can do that",
+            author.username
+        )));
+    }
+
+    let builds = db.get_latest_build_per_branch(repo.repository()).await?;
+    let cleaned = delete_build_branches(&repo, &db, &builds).await?;
+    repo.client
+        .post_comment(
+            pr.number,
+            branches_cleaned_comment(&cleaned.deleted, &cleaned.skipped),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Deletes the branches that bors has created for the given builds.
+/// Branches of builds that are still running are skipped, and builds whose branches were
+/// already deleted are ignored.
+/// `builds` should only contain the latest build of each branch.
+pub(super) async fn delete_build_branches(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    builds: &[BuildModel],
+) -> anyhow::Result<CleanedBranches> {
+    let mut cleaned = CleanedBranches::default();
+    for build in builds.iter().filter(|build| !build.branches_deleted) {
+        let branches = created_branches(&build.branch);
+        if build.status == BuildStatus::Pending {
+            cleaned
+                .skipped
+                .extend(branches.into_iter().map(|b| b.to_string()));
+            continue;
+        }
+        for branch in branches {
+            tracing::info!("Deleting branch {branch}");
+            if repo.client.delete_branch(branch).await? {
+                cleaned.deleted.push(branch.to_string());
+            }
+        }
+        db.mark_build_branches_deleted(build).await?;
+    }
+    Ok(cleaned)
+}
+
+/// Returns all branches that bors has created in order to perform a build on `build_branch`.
+fn created_branches(build_branch: &str) -> Vec<&str> {
+    match build_branch {
+        TRY_BRANCH_NAME => vec![TRY_MERGE_BRANCH_NAME, TRY_BRANCH_NAME],
+        AUTO_BRANCH_NAME => vec![AUTO_MERGE_BRANCH_NAME, AUTO_BRANCH_NAME],
+        _ => vec![build_branch],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, TRY_MERGE_BRANCH_NAME};
+    use crate::tests::mocks::{BorsBuilder, GitHubState, User, run_test};
+
+    fn gh_state_with_admin() -> GitHubState {
+        let state = GitHubState::default();
+        state
+            .default_repo()
+            .lock()
+            .admins
+            .push(User::default_pr_author().name);
+        state
+    }
+
+    #[sqlx::test]
+    async fn clean_requires_admin(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors clean").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @default-user: You don't have permission to clean up bors branches: only repository admins can do that"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn clean_nothing_to_clean(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_admin())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors clean").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @":broom: There were no branches to clean.");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn clean_finished_try_build(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_admin())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+
+                tester.post_comment("@bors clean").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @":broom: Deleted branch(es): `automation/bors/try-merge`, `automation/bors/try`");
+                let mut repo = tester.default_repo().lock().clone();
+                assert!(repo.get_branch_by_name(TRY_BRANCH_NAME).is_none());
+                assert!(repo.get_branch_by_name(TRY_MERGE_BRANCH_NAME).is_none());
+                assert!(repo.get_branch_by_name("main").is_some());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn clean_twice(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_admin())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+
+                tester.post_comment("@bors clean").await?;
+                tester.expect_comments(1).await;
+                // The branches of the build were already deleted, a branch with the same name
+                // that was created by someone else is left alone
+                tester.create_branch(TRY_BRANCH_NAME).reset_suites();
+                tester.post_comment("@bors clean").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @":broom: There were no branches to clean.");
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_some()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn clean_skip_running_build(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_admin())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+
+                tester.post_comment("@bors clean").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @r"
+                :broom: There were no branches to clean.
+                Skipped branch(es) with a running build: `automation/bors/try-merge`, `automation/bors/try`
+                ");
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_some()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+}
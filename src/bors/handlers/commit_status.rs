@@ -0,0 +1,181 @@
+use crate::bors::RepositoryState;
+use crate::bors::handlers::auto_build::AUTO_BRANCH_NAME;
+use crate::bors::handlers::trybuild::TRY_BRANCH_NAME;
+use crate::database::{BuildModel, BuildStatus};
+use crate::github::{CommitSha, CommitStatusState, PullRequestNumber};
+
+/// Sets the bors commit status on the head commit of a PR whose try build was queued.
+pub(super) async fn report_build_queued(repo: &RepositoryState, head_sha: &CommitSha) {
+    set_commit_status(
+        repo,
+        TRY_BRANCH_NAME,
+        head_sha,
+        CommitStatusState::Pending,
+        "Try build queued",
+        None,
+    )
+    .await;
+}
+
+/// Sets the bors commit status on the head commit of a PR whose queued try build was
+/// cancelled before it was started.
+pub(super) async fn report_queued_build_cancelled(repo: &RepositoryState, head_sha: &CommitSha) {
+    set_commit_status(
+        repo,
+        TRY_BRANCH_NAME,
+        head_sha,
+        CommitStatusState::Error,
+        "Queued try build cancelled",
+        None,
+    )
+    .await;
+}
+
+/// Sets the bors commit status on the head commit of a PR whose build of `build_sha` was
+/// started on `branch`, and on the build commit itself.
+pub(super) async fn report_build_started(
+    repo: &RepositoryState,
+    branch: &str,
+    pr: PullRequestNumber,
+    head_sha: &CommitSha,
+    build_sha: &CommitSha,
+) {
+    let description = format!("{} in progress", build_name(branch));
+    let target_url = repo.client.get_commit_url(&build_sha.0);
+    set_commit_status(
+        repo,
+        branch,
+        head_sha,
+        CommitStatusState::Pending,
+        &description,
+        Some(&target_url),
+    )
+    .await;
+    let target_url = queue_entry_url(repo, pr);
+    set_commit_status(
+        repo,
+        branch,
+        build_sha,
+        CommitStatusState::Pending,
+        &description,
+        Some(&target_url),
+    )
+    .await;
+}
+
+/// Sets the bors commit status on the build commit and on the head commit of the PR tested by
+/// `build`, according to the final `status` of the build.
+pub(super) async fn report_build_completed(
+    repo: &RepositoryState,
+    build: &BuildModel,
+    pr: PullRequestNumber,
+    status: BuildStatus,
+) {
+    let Some((state, description)) = completed_build_status(build, status) else {
+        return;
+    };
+    if let Some(head_sha) = &build.head_sha {
+        let target_url = repo.client.get_commit_url(&build.commit_sha);
+        set_commit_status(
+            repo,
+            &build.branch,
+            &CommitSha(head_sha.clone()),
+            state,
+            &description,
+            Some(&target_url),
+        )
+        .await;
+    }
+    let target_url = queue_entry_url(repo, pr);
+    set_commit_status(
+        repo,
+        &build.branch,
+        &CommitSha(build.commit_sha.clone()),
+        state,
+        &description,
+        Some(&target_url),
+    )
+    .await;
+}
+
+/// Sets the bors commit status on the commit of a try build that was superseded by a newer try
+/// build of the PR. The status of the head commit of the PR belongs to the newer build.
+pub(super) async fn report_superseded_build_completed(
+    repo: &RepositoryState,
+    build: &BuildModel,
+    pr: PullRequestNumber,
+    status: BuildStatus,
+) {
+    let Some((state, description)) = completed_build_status(build, status) else {
+        return;
+    };
+    let target_url = queue_entry_url(repo, pr);
+    set_commit_status(
+        repo,
+        &build.branch,
+        &CommitSha(build.commit_sha.clone()),
+        state,
+        &description,
+        Some(&target_url),
+    )
+    .await;
+}
+
+fn completed_build_status(
+    build: &BuildModel,
+    status: BuildStatus,
+) -> Option<(CommitStatusState, String)> {
+    let (state, outcome) = match status {
+        BuildStatus::Pending => return None,
+        BuildStatus::Success => (CommitStatusState::Success, "succeeded"),
+        BuildStatus::Failure => (CommitStatusState::Failure, "failed"),
+        BuildStatus::Cancelled => (CommitStatusState::Error, "cancelled"),
+        BuildStatus::Timeouted => (CommitStatusState::Error, "timed out"),
+    };
+    Some((state, format!("{} {outcome}", build_name(&build.branch))))
+}
+
+/// Name of the builds performed on the given branch, used in the descriptions of statuses.
+fn build_name(branch: &str) -> &'static str {
+    if branch == AUTO_BRANCH_NAME {
+        "Merge build"
+    } else {
+        "Try build"
+    }
+}
+
+/// Link to the entry of the PR on the queue page of the repository, or to the PR itself if the
+/// repository has no queue page.
+fn queue_entry_url(repo: &RepositoryState, pr: PullRequestNumber) -> String {
+    match &repo.config.load().queue_url {
+        Some(url) => format!("{url}#{pr}"),
+        None => repo.client.get_pr_url(pr),
+    }
+}
+
+/// The commit status is informative only, so failures to set it are logged and otherwise
+/// ignored.
+///
+/// Try builds do not test a PR for merging, so their status uses a separate `<context>/try`
+/// context, which cannot satisfy a branch protection rule that requires the gating context.
+/// Only merge builds (on `branch` [AUTO_BRANCH_NAME]) are reported under the gating context.
+async fn set_commit_status(
+    repo: &RepositoryState,
+    branch: &str,
+    sha: &CommitSha,
+    state: CommitStatusState,
+    description: &str,
+    target_url: Option<&str>,
+) {
+    let mut context = repo.config.load().commit_status_context.clone();
+    if branch != AUTO_BRANCH_NAME {
+        context.push_str("/try");
+    }
+    if let Err(error) = repo
+        .client
+        .set_commit_status(sha, &context, state, description, target_url)
+        .await
+    {
+        tracing::error!("Cannot set commit status of {sha}: {error:?}");
+    }
+}
@@ -1,39 +1,48 @@
+use crate::bors::BorsResult;
 use crate::bors::Comment;
 use crate::bors::RepositoryState;
 use crate::bors::command::{Approver, BorsCommand, RollupMode};
+use crate::database::DelegatedPermission;
 use crate::github::PullRequest;
 use std::sync::Arc;
 
-pub(super) async fn command_help(
-    repo: Arc<RepositoryState>,
-    pr: &PullRequest,
-) -> anyhow::Result<()> {
+pub(super) async fn command_help(repo: Arc<RepositoryState>, pr: &PullRequest) -> BorsResult<()> {
     let help = [
         BorsCommand::Approve {
             approver: Approver::Myself,
             priority: None,
             rollup: None,
+            force: false,
         },
         BorsCommand::Approve {
             approver: Approver::Specified("".to_string()),
             priority: None,
             rollup: None,
+            force: false,
         },
         BorsCommand::Unapprove,
         BorsCommand::SetPriority(0),
-        BorsCommand::Delegate,
+        BorsCommand::Delegate(DelegatedPermission::Review),
+        BorsCommand::Delegate(DelegatedPermission::Try),
         BorsCommand::Undelegate,
+        BorsCommand::ListDelegations,
         BorsCommand::Try {
             parent: None,
             jobs: vec![],
         },
         BorsCommand::TryCancel,
         BorsCommand::SetRollupMode(RollupMode::Always),
+        BorsCommand::SetSquash(true),
+        BorsCommand::SetSquash(false),
         BorsCommand::Info,
-        BorsCommand::Ping,
+        BorsCommand::Ping { latency: false },
         BorsCommand::Help,
         BorsCommand::OpenTree,
         BorsCommand::TreeClosed(0),
+        BorsCommand::Clean,
+        BorsCommand::CancelAll,
+        BorsCommand::Pause,
+        BorsCommand::Resume,
     ]
     .into_iter()
     .map(|help| format!("- {}", get_command_help(help)))
@@ -53,7 +62,7 @@ fn get_command_help(command: BorsCommand) -> String {
             approver: Approver::Myself,
             ..
         } => {
-            "`r+ [p=<priority>] [rollup=<never/iffy/maybe/always>]`: Approve this PR. Optionally, you can specify `<priority>`, `<rollup>`."
+            "`r+ [p=<priority>] [rollup=<never/iffy/maybe/always>] [force]`: Approve this PR. Optionally, you can specify `<priority>`, `<rollup>`. With `force`, failures of non-required checks are ignored (admins only)."
         }
         BorsCommand::Approve {
             approver: Approver::Specified(_),
@@ -63,10 +72,20 @@ fn get_command_help(command: BorsCommand) -> String {
         }
         BorsCommand::Unapprove => "`r-`: Unapprove this PR",
         BorsCommand::SetPriority(_) => "`p=<priority>`: Set the priority of this PR",
-        BorsCommand::Delegate => "`delegate+`: Delegate approval authority to the PR author",
+        BorsCommand::Delegate(DelegatedPermission::Review) => {
+            "`delegate+`: Delegate approval authority to the PR author"
+        }
+        BorsCommand::Delegate(DelegatedPermission::Try) => {
+            "`delegate=try`: Delegate try build authority to the PR author"
+        }
         BorsCommand::Undelegate => "`delegate-`: Remove any previously granted delegation",
+        BorsCommand::ListDelegations => {
+            "`delegate?`: List the delegations of the open PRs of this repository"
+        }
         BorsCommand::Help => "`help`: Print this help message",
-        BorsCommand::Ping => "`ping`: Check if the bot is alive",
+        BorsCommand::Ping { .. } => {
+            "`ping [latency]`: Check if the bot is alive. With `latency`, also show how long it took to process the command"
+        }
         BorsCommand::Try { .. } => {
             "`try [parent=<parent>] [jobs=<jobs>]`: Start a try build. Optionally, you can specify a `<parent>` SHA or a list of `<jobs>` to run"
         }
@@ -74,6 +93,12 @@ fn get_command_help(command: BorsCommand) -> String {
         BorsCommand::SetRollupMode(_) => {
             "`rollup=<never/iffy/maybe/always>`: Mark the rollup status of the PR"
         }
+        BorsCommand::SetSquash(true) => {
+            "`squash`: Squash the commits of this PR into a single commit when it is merged"
+        }
+        BorsCommand::SetSquash(false) => {
+            "`squash-`: Merge this PR with the merge style of the repository"
+        }
         BorsCommand::Info => {
             "`info`: Get information about the current PR including delegation, priority, merge status, and try build status"
         }
@@ -81,6 +106,16 @@ fn get_command_help(command: BorsCommand) -> String {
         BorsCommand::TreeClosed(_) => {
             "`treeclosed=<priority>`: Close the tree for PRs with priority less than `<priority>`"
         }
+        BorsCommand::Clean => {
+            "`clean`: Delete branches created by bors for builds that have already finished"
+        }
+        BorsCommand::CancelAll => {
+            "`cancel-all`: Cancel all running builds of this repository (admins only)"
+        }
+        BorsCommand::Pause => {
+            "`pause`: Stop executing commands and starting builds in this repository until it is resumed"
+        }
+        BorsCommand::Resume => "`resume`: Resume a paused repository",
     };
     help.to_string()
 }
@@ -94,20 +129,28 @@ mod tests {
         run_test(pool, |mut tester| async {
             tester.post_comment("@bors help").await?;
             insta::assert_snapshot!(tester.get_comment().await?, @r"
-            - `r+ [p=<priority>] [rollup=<never/iffy/maybe/always>]`: Approve this PR. Optionally, you can specify `<priority>`, `<rollup>`.
+            - `r+ [p=<priority>] [rollup=<never/iffy/maybe/always>] [force]`: Approve this PR. Optionally, you can specify `<priority>`, `<rollup>`. With `force`, failures of non-required checks are ignored (admins only).
             - `r=<user> [p=<priority>]`: Approve this PR on behalf of `<user>`. Optionally, you can specify a `<priority>`.
             - `r-`: Unapprove this PR
             - `p=<priority>`: Set the priority of this PR
             - `delegate+`: Delegate approval authority to the PR author
+            - `delegate=try`: Delegate try build authority to the PR author
             - `delegate-`: Remove any previously granted delegation
+            - `delegate?`: List the delegations of the open PRs of this repository
             - `try [parent=<parent>] [jobs=<jobs>]`: Start a try build. Optionally, you can specify a `<parent>` SHA or a list of `<jobs>` to run
             - `try cancel`: Cancel a running try build
             - `rollup=<never/iffy/maybe/always>`: Mark the rollup status of the PR
+            - `squash`: Squash the commits of this PR into a single commit when it is merged
+            - `squash-`: Merge this PR with the merge style of the repository
             - `info`: Get information about the current PR including delegation, priority, merge status, and try build status
-            - `ping`: Check if the bot is alive
+            - `ping [latency]`: Check if the bot is alive. With `latency`, also show how long it took to process the command
             - `help`: Print this help message
             - `treeclosed-`: Open the repository tree for merging
             - `treeclosed=<priority>`: Close the tree for PRs with priority less than `<priority>`
+            - `clean`: Delete branches created by bors for builds that have already finished
+            - `cancel-all`: Cancel all running builds of this repository (admins only)
+            - `pause`: Stop executing commands and starting builds in this repository until it is resumed
+            - `resume`: Resume a paused repository
             ");
             Ok(tester)
         })
@@ -1,3 +1,4 @@
+use crate::bors::BorsResult;
 use crate::bors::Comment;
 use crate::bors::RepositoryState;
 use crate::database::ApprovalStatus;
@@ -9,7 +10,7 @@ pub(super) async fn command_info(
     repo: Arc<RepositoryState>,
     pr: &PullRequest,
     db: Arc<PgDbClient>,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     // Geting PR info from database
     let pr_model = db
         .get_or_create_pull_request(
@@ -18,6 +19,7 @@ pub(super) async fn command_info(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
@@ -0,0 +1,126 @@
+use chrono::DateTime;
+
+use crate::bors::RepositoryState;
+use crate::bors::comment::JobLogExcerpt;
+use crate::database::{WorkflowModel, WorkflowStatus, WorkflowType};
+
+/// Logs that are longer than this are truncated from the top before an excerpt is extracted.
+const MAX_LOG_SIZE: usize = 1024 * 1024;
+/// Maximum number of failed jobs whose logs are included in a single failure comment.
+const MAX_FAILED_JOBS: usize = 5;
+/// Prefix of lines that report an error in Github Actions logs.
+const ERROR_MARKER: &str = "##[error]";
+
+/// Loads excerpts of the logs of the failed jobs of the given Github Actions workflows.
+/// Logs that cannot be loaded are skipped, so that the failure comment can be posted anyway.
+pub(super) async fn load_failed_job_logs(
+    repo: &RepositoryState,
+    workflows: &[WorkflowModel],
+) -> Vec<JobLogExcerpt> {
+    let line_count = repo.config.load().failed_job_log_lines;
+    let mut excerpts = vec![];
+    if line_count == 0 {
+        return excerpts;
+    }
+
+    let failed_workflows = workflows.iter().filter(|workflow| {
+        workflow.status == WorkflowStatus::Failure && workflow.workflow_type == WorkflowType::Github
+    });
+    for workflow in failed_workflows {
+        let jobs = match repo.client.get_failed_jobs(workflow.run_id).await {
+            Ok(jobs) => jobs,
+            Err(error) => {
+                tracing::warn!("Cannot load jobs of workflow {}: {error:?}", workflow.url);
+                continue;
+            }
+        };
+        for job in jobs {
+            if excerpts.len() >= MAX_FAILED_JOBS {
+                return excerpts;
+            }
+            match repo.client.get_job_log(job.id).await {
+                Ok(log) => excerpts.push(JobLogExcerpt {
+                    job_name: job.name,
+                    excerpt: extract_excerpt(&log, line_count),
+                }),
+                Err(error) => {
+                    tracing::warn!("Cannot load log of job {}: {error:?}", job.name);
+                }
+            }
+        }
+    }
+    excerpts
+}
+
+/// Returns `line_count` lines of the log that end with the first error line.
+/// If the log does not contain any error line, its last `line_count` lines are returned.
+fn extract_excerpt(log: &str, line_count: usize) -> String {
+    let log = truncate_from_top(log, MAX_LOG_SIZE);
+    let lines = log.lines().map(strip_timestamp).collect::<Vec<_>>();
+    let end = lines
+        .iter()
+        .position(|line| line.starts_with(ERROR_MARKER))
+        .map(|index| index + 1)
+        .unwrap_or(lines.len());
+    lines[end.saturating_sub(line_count)..end].join("\n")
+}
+
+/// Keeps at most `max_size` bytes from the end of the log, starting at a line boundary.
+fn truncate_from_top(log: &str, max_size: usize) -> &str {
+    if log.len() <= max_size {
+        return log;
+    }
+    let mut start = log.len() - max_size;
+    while !log.is_char_boundary(start) {
+        start += 1;
+    }
+    let truncated = &log[start..];
+    // The first line is most likely cut in the middle
+    match truncated.split_once('\n') {
+        Some((_, rest)) => rest,
+        None => truncated,
+    }
+}
+
+/// Github Actions prefixes each line of the log with a timestamp, which is just noise in
+/// the excerpt.
+fn strip_timestamp(line: &str) -> &str {
+    match line.split_once(' ') {
+        Some((timestamp, rest)) if DateTime::parse_from_rfc3339(timestamp).is_ok() => rest,
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::handlers::job_logs::{extract_excerpt, truncate_from_top};
+
+    #[test]
+    fn excerpt_ends_with_first_error() {
+        let log = "line 1\nline 2\nerror: foo\n##[error]Process completed with exit code 1.\ncleanup\n##[error]second error";
+        assert_eq!(
+            extract_excerpt(log, 2),
+            "error: foo\n##[error]Process completed with exit code 1."
+        );
+    }
+
+    #[test]
+    fn excerpt_without_error_marker() {
+        let log = "line 1\nline 2\nline 3";
+        assert_eq!(extract_excerpt(log, 2), "line 2\nline 3");
+        assert_eq!(extract_excerpt(log, 10), log);
+    }
+
+    #[test]
+    fn excerpt_strips_timestamps() {
+        let log = "2024-05-01T10:00:00.1234567Z error: foo\n2024-05-01T10:00:01.0000000Z ##[error]failed\nnot a timestamp";
+        assert_eq!(extract_excerpt(log, 5), "error: foo\n##[error]failed");
+    }
+
+    #[test]
+    fn truncate_long_log_from_top() {
+        let log = "first line\nsecond line\nthird line";
+        assert_eq!(truncate_from_top(log, 15), "third line");
+        assert_eq!(truncate_from_top(log, 100), log);
+    }
+}
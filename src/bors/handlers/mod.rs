@@ -1,48 +1,96 @@
 use std::sync::Arc;
 
 use crate::bors::command::{BorsCommand, CommandParseError};
-use crate::bors::event::{BorsGlobalEvent, BorsRepositoryEvent, PullRequestComment};
+use crate::bors::comment::{command_rate_limited_comment, repository_paused_command_comment};
+use crate::bors::event::{
+    BorsGlobalEvent, BorsRepositoryEvent, PullRequestComment, PullRequestReviewed,
+    WorkflowCompleted,
+};
+use crate::bors::handlers::auto_build::AUTO_BRANCH_NAME;
+use crate::bors::handlers::cancel_all::command_cancel_all;
+use crate::bors::handlers::clean::command_clean;
 use crate::bors::handlers::help::command_help;
 use crate::bors::handlers::info::command_info;
+use crate::bors::handlers::pause::command_pause;
 use crate::bors::handlers::ping::command_ping;
-use crate::bors::handlers::refresh::refresh_repository;
+use crate::bors::handlers::refresh::{refresh_repository, reload_repository};
 use crate::bors::handlers::review::{
     command_approve, command_close_tree, command_open_tree, command_unapprove,
+    handle_pull_request_reviewed,
 };
+pub use crate::bors::handlers::sync::sync_pull_requests;
 use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, command_try_build, command_try_cancel};
 use crate::bors::handlers::workflow::{
-    handle_check_suite_completed, handle_workflow_completed, handle_workflow_started,
+    handle_check_run_completed, handle_check_suite_completed, handle_workflow_completed,
+    handle_workflow_started, handle_workflows_completed,
+};
+use crate::bors::{
+    BorsContext, BorsError, Comment, PullRequestStatus, RepositoryState, is_retryable_error,
 };
-use crate::bors::{BorsContext, Comment, RepositoryState};
-use crate::github::{GithubUser, PullRequest};
+use crate::github::{GithubRepoName, GithubUser, PullRequest};
 use crate::permissions::PermissionType;
-use crate::{PgDbClient, TeamApiClient, load_repositories};
+use crate::{PermissionSource, PgDbClient, load_repositories};
 use anyhow::Context;
 use octocrab::Octocrab;
+use octocrab::models::CommentId;
+use octocrab::models::reactions::ReactionContent;
 use pr_events::{
     handle_pull_request_closed, handle_pull_request_converted_to_draft, handle_pull_request_edited,
-    handle_pull_request_merged, handle_pull_request_opened, handle_pull_request_ready_for_review,
-    handle_pull_request_reopened, handle_push_to_branch, handle_push_to_pull_request,
+    handle_pull_request_labeled, handle_pull_request_merged, handle_pull_request_opened,
+    handle_pull_request_ready_for_review, handle_pull_request_reopened,
+    handle_pull_request_unlabeled, handle_push_to_branch, handle_push_to_pull_request,
+};
+use review::{
+    command_delegate, command_list_delegations, command_set_priority, command_set_rollup,
+    command_set_squash, command_undelegate,
 };
-use review::{command_delegate, command_set_priority, command_set_rollup, command_undelegate};
+use sha2::{Digest, Sha256};
 use tracing::Instrument;
 
 #[cfg(test)]
 use crate::tests::util::TestSyncMarker;
 
+mod auto_build;
+mod cancel_all;
+mod chat;
+mod check_run;
+mod clean;
+mod commit_status;
 mod help;
 mod info;
+mod job_logs;
 mod labels;
+mod notification;
+mod pause;
 mod ping;
 mod pr_events;
 mod refresh;
 mod review;
+mod status_comment;
+mod sync;
 mod trybuild;
 mod workflow;
 
 #[cfg(test)]
 pub static WAIT_FOR_WORKFLOW_STARTED: TestSyncMarker = TestSyncMarker::new();
 
+/// Handles several completed workflows of a single build of the given repository at once.
+pub async fn handle_bors_workflows_completed(
+    repository: &GithubRepoName,
+    payloads: Vec<WorkflowCompleted>,
+    ctx: Arc<BorsContext>,
+) -> anyhow::Result<()> {
+    let Some(repo) = ctx.repositories.read().unwrap().get(repository).cloned() else {
+        return Err(anyhow::anyhow!(
+            "Repository {repository} not found in the bot state"
+        ));
+    };
+    let span = tracing::info_span!("Workflows completed", count = payloads.len());
+    handle_workflows_completed(repo, Arc::clone(&ctx.db), payloads)
+        .instrument(span)
+        .await
+}
+
 /// This function executes a single BORS repository event
 pub async fn handle_bors_repository_event(
     event: BorsRepositoryEvent,
@@ -64,41 +112,42 @@ pub async fn handle_bors_repository_event(
 
     match event {
         BorsRepositoryEvent::Comment(comment) => {
-            // We want to ignore comments made by this bot
-            if repo.client.is_comment_internal(&comment).await? {
-                tracing::trace!("Ignoring comment {comment:?} because it was authored by this bot");
-                return Ok(());
-            }
-
-            let span = tracing::info_span!(
-                "Comment",
-                pr = format!("{}#{}", comment.repository, comment.pr_number),
-                author = comment.author.username
-            );
-            let pr_number = comment.pr_number;
-            if let Err(error) = handle_comment(Arc::clone(&repo), db, ctx, comment)
+            handle_comment_event(repo, db, ctx, comment, None).await?;
+        }
+        BorsRepositoryEvent::CommentEdited(payload) => {
+            handle_comment_event(repo, db, ctx, payload.comment, Some(payload.previous_text))
+                .await?;
+        }
+        BorsRepositoryEvent::PullRequestReviewed(payload) => {
+            let PullRequestReviewed {
+                pull_request,
+                verdict,
+                commit_sha,
+                comment,
+                ..
+            } = payload;
+            let span =
+                tracing::info_span!("Pull request reviewed", author = comment.author.username);
+            // Commands in the body of the review take precedence over its verdict
+            let has_commands = !ctx.parser.parse_commands(&comment.text).is_empty();
+            let author = comment.author.clone();
+            handle_comment_event(Arc::clone(&repo), Arc::clone(&db), ctx, comment, None).await?;
+            if !has_commands {
+                handle_pull_request_reviewed(
+                    repo,
+                    db,
+                    &pull_request,
+                    &author,
+                    verdict,
+                    commit_sha.as_ref(),
+                )
                 .instrument(span.clone())
-                .await
-            {
-                repo.client
-                    .post_comment(
-                        pr_number,
-                        Comment::new(
-                            ":x: Encountered an error while executing command".to_string(),
-                        ),
-                    )
-                    .await
-                    .context("Cannot send comment reacting to an error")?;
-                return Err(error.context("Cannot perform command"));
+                .await?;
             }
         }
         BorsRepositoryEvent::WorkflowStarted(payload) => {
-            let span = tracing::info_span!(
-                "Workflow started",
-                repo = payload.repository.to_string(),
-                id = payload.run_id.into_inner()
-            );
-            handle_workflow_started(db, payload)
+            let span = tracing::info_span!("Workflow started", id = payload.run_id.into_inner());
+            handle_workflow_started(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
 
@@ -106,97 +155,95 @@ pub async fn handle_bors_repository_event(
             WAIT_FOR_WORKFLOW_STARTED.mark();
         }
         BorsRepositoryEvent::WorkflowCompleted(payload) => {
-            let span = tracing::info_span!(
-                "Workflow completed",
-                repo = payload.repository.to_string(),
-                id = payload.run_id.into_inner()
-            );
+            let span = tracing::info_span!("Workflow completed", id = payload.run_id.into_inner());
             handle_workflow_completed(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::CheckSuiteCompleted(payload) => {
-            let span = tracing::info_span!(
-                "Check suite completed",
-                repo = payload.repository.to_string(),
-            );
+            let span = tracing::info_span!("Check suite completed");
             handle_check_suite_completed(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
+        BorsRepositoryEvent::CheckRunCompleted(payload) => {
+            let span = tracing::info_span!("Check run completed", id = payload.run_id.into_inner());
+            handle_check_run_completed(repo, db, payload)
+                .instrument(span.clone())
+                .await?;
+        }
         BorsRepositoryEvent::PullRequestEdited(payload) => {
-            let span =
-                tracing::info_span!("Pull request edited", repo = payload.repository.to_string());
+            let span = tracing::info_span!("Pull request edited");
 
             handle_pull_request_edited(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestCommitPushed(payload) => {
-            let span =
-                tracing::info_span!("Pull request pushed", repo = payload.repository.to_string());
+            let span = tracing::info_span!("Pull request pushed");
 
             handle_push_to_pull_request(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestOpened(payload) => {
-            let span =
-                tracing::info_span!("Pull request opened", repo = payload.repository.to_string());
+            let span = tracing::info_span!("Pull request opened");
 
             handle_pull_request_opened(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestClosed(payload) => {
-            let span =
-                tracing::info_span!("Pull request closed", repo = payload.repository.to_string());
+            let span = tracing::info_span!("Pull request closed");
 
             handle_pull_request_closed(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestMerged(payload) => {
-            let span =
-                tracing::info_span!("Pull request merged", repo = payload.repository.to_string());
+            let span = tracing::info_span!("Pull request merged");
 
             handle_pull_request_merged(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestReopened(payload) => {
-            let span = tracing::info_span!(
-                "Pull request reopened",
-                repo = payload.repository.to_string()
-            );
+            let span = tracing::info_span!("Pull request reopened");
 
             handle_pull_request_reopened(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestConvertedToDraft(payload) => {
-            let span = tracing::info_span!(
-                "Pull request converted to draft",
-                repo = payload.repository.to_string()
-            );
+            let span = tracing::info_span!("Pull request converted to draft");
 
             handle_pull_request_converted_to_draft(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
         BorsRepositoryEvent::PullRequestReadyForReview(payload) => {
-            let span = tracing::info_span!(
-                "Pull request ready for review",
-                repo = payload.repository.to_string()
-            );
+            let span = tracing::info_span!("Pull request ready for review");
 
             handle_pull_request_ready_for_review(repo, db, payload)
                 .instrument(span.clone())
                 .await?;
         }
+        BorsRepositoryEvent::PullRequestLabeled(payload) => {
+            let span = tracing::info_span!("Pull request labeled");
+
+            handle_pull_request_labeled(repo, db, payload)
+                .instrument(span.clone())
+                .await?;
+        }
+        BorsRepositoryEvent::PullRequestUnlabeled(payload) => {
+            let span = tracing::info_span!("Pull request unlabeled");
+
+            handle_pull_request_unlabeled(repo, db, payload)
+                .instrument(span.clone())
+                .await?;
+        }
         BorsRepositoryEvent::PushToBranch(payload) => {
-            let span =
-                tracing::info_span!("Pushed to branch", repo = payload.repository.to_string());
+            let span = tracing::info_span!("Pushed to branch");
 
             handle_push_to_branch(repo, db, payload)
                 .instrument(span.clone())
@@ -214,25 +261,24 @@ pub async fn handle_bors_global_event(
     event: BorsGlobalEvent,
     ctx: Arc<BorsContext>,
     gh_client: &Octocrab,
-    team_api_client: &TeamApiClient,
+    permission_source: &PermissionSource,
 ) -> anyhow::Result<()> {
     let db = Arc::clone(&ctx.db);
     match event {
         BorsGlobalEvent::InstallationsChanged => {
             let span = tracing::info_span!("Installations changed");
-            reload_repos(ctx, gh_client, team_api_client)
+            reload_repos(ctx, gh_client, permission_source)
                 .instrument(span)
                 .await?;
         }
         BorsGlobalEvent::Refresh => {
             let span = tracing::info_span!("Refresh");
-            let repos: Vec<Arc<RepositoryState>> =
-                ctx.repositories.read().unwrap().values().cloned().collect();
+            let repos = refreshed_repositories(&ctx, &db).await;
             futures::future::join_all(repos.into_iter().map(|repo| {
                 let repo = Arc::clone(&repo);
                 async {
                     let subspan = tracing::info_span!("Repo", repo = repo.repository().to_string());
-                    refresh_repository(repo, Arc::clone(&db), team_api_client)
+                    refresh_repository(repo, Arc::clone(&db), permission_source)
                         .instrument(subspan)
                         .await
                 }
@@ -243,6 +289,97 @@ pub async fn handle_bors_global_event(
             #[cfg(test)]
             WAIT_FOR_REFRESH.mark();
         }
+        BorsGlobalEvent::RepositoryRenamed { old_name, new_name } => {
+            let span = tracing::info_span!("Repository renamed", repo = old_name.to_string());
+            rename_repository(ctx, gh_client, permission_source, &old_name, &new_name)
+                .instrument(span)
+                .await?;
+        }
+        BorsGlobalEvent::ReloadRepository { repository, reply } => {
+            let span = tracing::info_span!("Reload repository", repo = repository.to_string());
+            let repo = ctx.repositories.read().unwrap().get(&repository).cloned();
+            let result = match repo {
+                Some(repo) => {
+                    let result = reload_repository(&repo, &db, permission_source)
+                        .instrument(span)
+                        .await;
+                    if let Err(error) = &result {
+                        tracing::error!("Failed to reload repository {repository}: {error:?}");
+                    }
+                    Some(result)
+                }
+                None => None,
+            };
+            // The requester might have gone away in the meantime, which is fine.
+            let _ = reply.send(result);
+        }
+        BorsGlobalEvent::SyncRepository { repository, reply } => {
+            let span = tracing::info_span!("Sync repository", repo = repository.to_string());
+            let repo = ctx.repositories.read().unwrap().get(&repository).cloned();
+            let result = match repo {
+                Some(repo) => {
+                    let result = sync_pull_requests(&repo.client, &db).instrument(span).await;
+                    if let Err(error) = &result {
+                        tracing::error!("Failed to sync repository {repository}: {error:?}");
+                    }
+                    Some(result)
+                }
+                None => None,
+            };
+            // The requester might have gone away in the meantime, which is fine.
+            let _ = reply.send(result);
+        }
+    }
+    Ok(())
+}
+
+/// Executes the commands of a comment, and lets the PR know if that has failed.
+/// For an edited comment, `previous_text` is its text before the edit.
+async fn handle_comment_event(
+    repo: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    ctx: Arc<BorsContext>,
+    comment: PullRequestComment,
+    previous_text: Option<String>,
+) -> anyhow::Result<()> {
+    // We want to ignore comments made by this bot
+    if repo.client.is_comment_internal(&comment).await? {
+        tracing::trace!("Ignoring comment {comment:?} because it was authored by this bot");
+        return Ok(());
+    }
+    let ignored = repo
+        .config
+        .load()
+        .ignored_users
+        .iter()
+        .any(|user| user.eq_ignore_ascii_case(&comment.author.username));
+    if ignored {
+        tracing::debug!(
+            "Ignoring comment of {} because the user is ignored",
+            comment.author.username
+        );
+        return Ok(());
+    }
+
+    let span = tracing::info_span!("Comment", author = comment.author.username);
+    let pr_number = comment.pr_number;
+    if let Err(error) = handle_comment(Arc::clone(&repo), db, ctx, comment, previous_text)
+        .instrument(span.clone())
+        .await
+    {
+        // The comment will be handled again, the user only learns about the failure if that
+        // does not help
+        if is_retryable_error(&error) {
+            return Err(error.context("Cannot perform command"));
+        }
+        repo.client
+            .post_comment(
+                pr_number,
+                Comment::new(":x: Encountered an error while executing command".to_string()),
+            )
+            .await
+            .context("Cannot send comment reacting to an error")?;
+        return Err(error.context("Cannot perform command"));
     }
     Ok(())
 }
@@ -252,27 +389,113 @@ async fn handle_comment(
     database: Arc<PgDbClient>,
     ctx: Arc<BorsContext>,
     comment: PullRequestComment,
+    previous_text: Option<String>,
 ) -> anyhow::Result<()> {
     let pr_number = comment.pr_number;
-    let commands = ctx.parser.parse_commands(&comment.text);
+    // Commands are numbered by their position in the comment, to record which of them were
+    // executed
+    let mut commands = ctx
+        .parser
+        .parse_commands(&comment.text)
+        .into_iter()
+        .enumerate()
+        .collect::<Vec<_>>();
+
+    // Commands that were already in the comment before it was edited have been executed
+    if let Some(previous_text) = &previous_text {
+        let previous_commands = ctx.parser.parse_commands(previous_text);
+        commands.retain(|(_, command)| !previous_commands.contains(command));
+    }
 
     // Bail if no commands
     if commands.is_empty() {
         return Ok(());
     }
 
+    // GitHub can deliver the same comment or edit more than once, and stored events are
+    // replayed after a restart, each command of a version of a comment is only executed once
+    let mut processed_comment = None;
+    if let Some(comment_id) = comment.comment_id {
+        let body_hash = hex::encode(Sha256::digest(comment.text.as_bytes()));
+        let executed = database
+            .get_executed_command_count(comment_id, &body_hash)
+            .await?;
+        commands.retain(|(index, _)| *index >= executed);
+        if commands.is_empty() {
+            tracing::info!("Commands of comment {comment_id} were already executed");
+            return Ok(());
+        }
+        processed_comment = Some((comment_id, body_hash));
+    }
+
     tracing::debug!("Commands: {commands:?}");
     tracing::trace!("Text: {}", comment.text);
 
+    // A comment with several commands counts as a single command
+    if let Some(limit) = repo.config.load().command_rate_limit {
+        let changes_state = commands.iter().any(|(_, command)| {
+            command
+                .as_ref()
+                .is_ok_and(|command| !command.is_read_only())
+        });
+        if changes_state {
+            if let Err(retry_after) = repo.command_rate_limiter.check(comment.author.id, &limit) {
+                tracing::warn!(
+                    "Command of {} rejected by the rate limit, retry after {retry_after:?}",
+                    comment.author.username
+                );
+                repo.client
+                    .post_comment(
+                        pr_number,
+                        command_rate_limited_comment(&comment.author.username, retry_after),
+                    )
+                    .await
+                    .context("Could not reply to PR comment")?;
+                return Ok(());
+            }
+        }
+    }
+
     let pull_request = repo
         .client
         .get_pull_request(pr_number)
         .await
         .with_context(|| format!("Cannot get information about PR {pr_number}"))?;
 
-    for command in commands {
+    for (index, command) in commands {
         match command {
             Ok(command) => {
+                if repo.is_paused()
+                    && !command.is_read_only()
+                    && !matches!(command, BorsCommand::Pause | BorsCommand::Resume)
+                {
+                    tracing::info!("Command {command:?} rejected, the repository is paused");
+                    repo.client
+                        .post_comment(pull_request.number, repository_paused_command_comment())
+                        .await
+                        .context("Could not reply to PR comment")?;
+                    record_executed_command(&database, processed_comment.as_ref(), index).await?;
+                    continue;
+                }
+
+                // Approving or trying a draft PR is most likely a mistake
+                if pull_request.status == PullRequestStatus::Draft
+                    && matches!(
+                        command,
+                        BorsCommand::Approve { .. } | BorsCommand::Try { .. }
+                    )
+                {
+                    repo.client
+                        .post_comment(
+                            pull_request.number,
+                            Comment::new("This PR is a draft; mark it ready first.".to_string()),
+                        )
+                        .await
+                        .context("Could not reply to PR comment")?;
+                    record_executed_command(&database, processed_comment.as_ref(), index).await?;
+                    continue;
+                }
+
                 let repo = Arc::clone(&repo);
                 let database = Arc::clone(&database);
                 let result = match command {
@@ -280,6 +503,7 @@ async fn handle_comment(
                         approver,
                         priority,
                         rollup,
+                        force,
                     } => {
                         let span = tracing::info_span!("Approve");
                         command_approve(
@@ -290,6 +514,7 @@ async fn handle_comment(
                             &approver,
                             priority,
                             rollup,
+                            force,
                         )
                         .instrument(span)
                         .await
@@ -327,19 +552,32 @@ async fn handle_comment(
                             &pull_request,
                             &comment.author,
                             priority,
+                            comment.comment_id,
                         )
                         .instrument(span)
                         .await
                     }
-                    BorsCommand::Delegate => {
+                    BorsCommand::Delegate(permission) => {
                         let span = tracing::info_span!("Delegate");
-                        command_delegate(repo, database, &pull_request, &comment.author)
+                        command_delegate(repo, database, &pull_request, &comment.author, permission)
                             .instrument(span)
                             .await
                     }
                     BorsCommand::Undelegate => {
                         let span = tracing::info_span!("Undelegate");
-                        command_undelegate(repo, database, &pull_request, &comment.author)
+                        command_undelegate(
+                            repo,
+                            database,
+                            &pull_request,
+                            &comment.author,
+                            comment.comment_id,
+                        )
+                        .instrument(span)
+                        .await
+                    }
+                    BorsCommand::ListDelegations => {
+                        let span = tracing::info_span!("List delegations");
+                        command_list_delegations(repo, database, &pull_request, &comment.author)
                             .instrument(span)
                             .await
                     }
@@ -347,9 +585,12 @@ async fn handle_comment(
                         let span = tracing::info_span!("Help");
                         command_help(repo, &pull_request).instrument(span).await
                     }
-                    BorsCommand::Ping => {
+                    BorsCommand::Ping { latency } => {
                         let span = tracing::info_span!("Ping");
-                        command_ping(repo, &pull_request).instrument(span).await
+                        let created_at = latency.then_some(comment.created_at).flatten();
+                        command_ping(repo, &pull_request, created_at)
+                            .instrument(span)
+                            .await
                     }
                     BorsCommand::Try { parent, jobs } => {
                         let span = tracing::info_span!("Try");
@@ -376,15 +617,71 @@ async fn handle_comment(
                             .instrument(span)
                             .await
                     }
-                    BorsCommand::SetRollupMode(rollup) => {
-                        let span = tracing::info_span!("Rollup");
-                        command_set_rollup(repo, database, &pull_request, &comment.author, rollup)
+                    BorsCommand::Clean => {
+                        let span = tracing::info_span!("Clean");
+                        command_clean(repo, database, &pull_request, &comment.author)
+                            .instrument(span)
+                            .await
+                    }
+                    BorsCommand::CancelAll => {
+                        let span = tracing::info_span!("Cancel all");
+                        command_cancel_all(repo, database, &pull_request, &comment.author)
+                            .instrument(span)
+                            .await
+                    }
+                    BorsCommand::Pause => {
+                        let span = tracing::info_span!("Pause");
+                        command_pause(repo, database, &pull_request, &comment.author, true)
                             .instrument(span)
                             .await
                     }
+                    BorsCommand::Resume => {
+                        let span = tracing::info_span!("Resume");
+                        command_pause(repo, database, &pull_request, &comment.author, false)
+                            .instrument(span)
+                            .await
+                    }
+                    BorsCommand::SetRollupMode(rollup) => {
+                        let span = tracing::info_span!("Rollup");
+                        command_set_rollup(
+                            repo,
+                            database,
+                            &pull_request,
+                            &comment.author,
+                            rollup,
+                            comment.comment_id,
+                        )
+                        .instrument(span)
+                        .await
+                    }
+                    BorsCommand::SetSquash(squash) => {
+                        let span = tracing::info_span!("Squash");
+                        command_set_squash(
+                            repo,
+                            database,
+                            &pull_request,
+                            &comment.author,
+                            squash,
+                            comment.comment_id,
+                        )
+                        .instrument(span)
+                        .await
+                    }
                 };
-                if result.is_err() {
-                    return result.context("Cannot execute Bors command");
+                match result {
+                    Ok(()) => {}
+                    Err(BorsError::UserFacing { comment }) => {
+                        tracing::info!("Command rejected: {}", comment.summary());
+                        repo.client
+                            .post_comment(pull_request.number, comment)
+                            .await
+                            .context("Could not reply to PR comment")?;
+                    }
+                    Err(error) => {
+                        return Err(
+                            anyhow::Error::from(error).context("Cannot execute Bors command")
+                        );
+                    }
                 }
             }
             Err(error) => {
@@ -413,16 +710,63 @@ async fn handle_comment(
                     .context("Could not reply to PR comment")?;
             }
         }
+        record_executed_command(&database, processed_comment.as_ref(), index).await?;
+    }
+    Ok(())
+}
+
+/// Records that the command at `index` of the comment (and the commands before it) were
+/// executed. A command is only recorded once it has been executed, so that it is executed again
+/// if the comment is handled again after a failure, but the commands before it are not.
+async fn record_executed_command(
+    database: &PgDbClient,
+    processed_comment: Option<&(CommentId, String)>,
+    index: usize,
+) -> anyhow::Result<()> {
+    if let Some((comment_id, body_hash)) = processed_comment {
+        database
+            .record_processed_comment(*comment_id, body_hash, index + 1)
+            .await?;
     }
     Ok(())
 }
 
+/// Returns the repositories that should be refreshed periodically, i.e. the repositories stored
+/// in the database whose state is loaded.
+/// Falls back to all loaded repositories if the database cannot be queried.
+async fn refreshed_repositories(ctx: &BorsContext, db: &PgDbClient) -> Vec<Arc<RepositoryState>> {
+    let loaded = ctx.repositories.read().unwrap().clone();
+    match db.get_repositories().await {
+        Ok(names) => names
+            .into_iter()
+            .filter_map(|name| {
+                let repo = loaded.get(&name).cloned();
+                if repo.is_none() {
+                    tracing::debug!("Repository {name} is not loaded, skipping refresh");
+                }
+                repo
+            })
+            .collect(),
+        Err(error) => {
+            tracing::error!("Could not load repositories from the database: {error:?}");
+            loaded.into_values().collect()
+        }
+    }
+}
+
 async fn reload_repos(
     ctx: Arc<BorsContext>,
     gh_client: &Octocrab,
-    team_api_client: &TeamApiClient,
+    permission_source: &PermissionSource,
 ) -> anyhow::Result<()> {
-    let reloaded_repos = load_repositories(gh_client, team_api_client).await?;
+    let reloaded_repos = load_repositories(
+        gh_client,
+        permission_source,
+        &ctx.github_html_url,
+        Some(ctx.db.as_ref()),
+        ctx.dry_run,
+    )
+    .await?;
     let mut repositories = ctx.repositories.write().unwrap();
     for repo in repositories.values() {
         if !reloaded_repos.contains_key(repo.repository()) {
@@ -447,31 +791,88 @@ async fn reload_repos(
     Ok(())
 }
 
+/// Moves the stored state of a repository that was renamed or transferred on GitHub to its
+/// new name, and then reloads the repositories, so that the repository is known under the new
+/// name.
+async fn rename_repository(
+    ctx: Arc<BorsContext>,
+    gh_client: &Octocrab,
+    permission_source: &PermissionSource,
+    old_name: &GithubRepoName,
+    new_name: &GithubRepoName,
+) -> anyhow::Result<()> {
+    let summary = ctx
+        .db
+        .rename_repository(old_name, new_name)
+        .await
+        .with_context(|| format!("Cannot rename repository {old_name} to {new_name}"))?;
+    tracing::info!(
+        "Repository {old_name} was renamed to {new_name}, moved {} pull request(s) and {} build(s)",
+        summary.pull_requests,
+        summary.builds
+    );
+    ctx.repositories.write().unwrap().remove(old_name);
+    reload_repos(ctx, gh_client, permission_source).await
+}
+
 /// Is this branch interesting for the bot?
 fn is_bors_observed_branch(branch: &str) -> bool {
-    branch == TRY_BRANCH_NAME
+    branch == TRY_BRANCH_NAME || branch == AUTO_BRANCH_NAME
 }
 
 /// Deny permission for a request.
+/// The returned error is replied to the PR, it states which `action` (e.g. `approve pull
+/// requests`) the user tried to perform, which permission was missing and which permissions
+/// the user has.
+/// The request must be denied before it modifies any state.
 async fn deny_request(
     repo: &RepositoryState,
-    pr: &PullRequest,
     author: &GithubUser,
     permission_type: PermissionType,
-) -> anyhow::Result<()> {
-    tracing::warn!(
-        "Permission denied for request command by {}",
-        author.username
+    action: &str,
+) -> BorsError {
+    tracing::warn!("Permission to {action} denied for {}", author.username);
+    let mut granted = vec![];
+    for permission in [PermissionType::Review, PermissionType::Try] {
+        if repo.user_has_permission(author, permission.clone()).await {
+            granted.push(format!("`{permission}`"));
+        }
+    }
+    let granted = match granted.as_slice() {
+        [] => "you have no permissions".to_string(),
+        [permission] => format!("you only have the {permission} permission"),
+        permissions => format!(
+            "you only have the {} permissions",
+            permissions.join(" and ")
+        ),
+    };
+    let mut message = format!(
+        ":lock: @{}: You don't have permission to {action}: the `{}` permission is required, {}",
+        author.username, permission_type, granted
     );
-    repo.client
-        .post_comment(
-            pr.number,
-            Comment::new(format!(
-                "@{}: :key: Insufficient privileges: not in {} users",
-                author.username, permission_type
-            )),
-        )
+    if permission_type == PermissionType::Try && !repo.config.load().author_can_try {
+        message.push_str(
+            "\nPR authors can start try builds of their own PRs if `author_can_try` is enabled in the repository configuration.",
+        );
+    }
+    BorsError::user_facing(message)
+}
+
+/// Acknowledge a command that has succeeded without having anything to report, by reacting
+/// with :+1: on the comment that contained it.
+/// Does nothing unless `reaction_ack` is enabled in the repository configuration.
+async fn acknowledge_command(repo: &RepositoryState, comment_id: Option<CommentId>) {
+    let Some(comment_id) = comment_id.filter(|_| repo.config.load().reaction_ack) else {
+        return;
+    };
+    // The reaction is only a courtesy, so a failure to add it does not fail the command
+    if let Err(error) = repo
+        .client
+        .add_reaction(comment_id, ReactionContent::PlusOne)
         .await
+    {
+        tracing::warn!("Cannot acknowledge command from comment {comment_id}: {error:?}");
+    }
 }
 
 /// Check if a user has specified permission or has been delegated.
@@ -482,11 +883,10 @@ async fn has_permission(
     db: &PgDbClient,
     permission: PermissionType,
 ) -> anyhow::Result<bool> {
-    if repo_state
-        .permissions
-        .load()
-        .has_permission(author.id, permission.clone())
-    {
+    if permission == PermissionType::Try && author_can_try(repo_state, author, pr) {
+        return Ok(true);
+    }
+    if repo_state.user_has_permission(author, permission).await {
         return Ok(true);
     }
 
@@ -497,16 +897,42 @@ async fn has_permission(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
-    let is_delegated = pr_model.delegated && author.id == pr.author.id;
+    let is_delegated = author.id == pr.author.id
+        && pr_model
+            .delegated_permission
+            .is_some_and(|delegated| delegated.grants(permission));
 
     Ok(is_delegated)
 }
 
+/// Checks if the user is the author of the PR and the repository allows authors to start try
+/// builds of their own PRs.
+fn author_can_try(repo_state: &RepositoryState, author: &GithubUser, pr: &PullRequest) -> bool {
+    let config = repo_state.config.load();
+    if !config.author_can_try || author.id != pr.author.id {
+        return false;
+    }
+    !pr.is_fork(repo_state.repository()) || config.author_can_try_from_forks
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::tests::mocks::{Comment, User, run_test};
+    use crate::bors::PullRequestStatus;
+    use crate::github::api::retry::IDEMPOTENT_ATTEMPTS;
+    use crate::tests::mocks::{
+        BorsBuilder, BorsTester, Comment, GitHubState, User, default_pr_number, run_test,
+    };
+
+    fn make_default_pr_draft(tester: &BorsTester) {
+        tester
+            .default_repo()
+            .lock()
+            .get_pr_mut(default_pr_number())
+            .status = PullRequestStatus::Draft;
+    }
 
     #[sqlx::test]
     async fn ignore_bot_comment(pool: sqlx::PgPool) {
@@ -520,6 +946,36 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn ignore_app_bot_user_comment(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors ping").with_author(User::bors_app_bot()))
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn ignore_configured_user_comment(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(
+                GitHubState::default()
+                    .with_default_config(r#"ignored_users = ["Unprivileged-User"]"#),
+            )
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(Comment::from("@bors ping").with_author(User::unprivileged()))
+                    .await?;
+                // Comments of other users are still handled
+                tester.post_comment("@bors ping").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @"Pong 🏓!");
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn do_not_load_pr_on_unrelated_comment(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -529,4 +985,169 @@ mod tests {
         })
         .await;
     }
+
+    #[sqlx::test]
+    async fn retry_comment_after_transient_github_error(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            // All attempts of the first request to load the PR fail
+            tester.default_repo().lock().pull_request_failures = IDEMPOTENT_ATTEMPTS;
+            tester.post_comment("@bors ping").await?;
+            assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+            // The failures outlast the retries of the request, so the event had to be retried
+            assert_eq!(tester.default_repo().lock().pull_request_failures, 0);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn draft_pr_reject_approve(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            make_default_pr_draft(&tester);
+            tester.post_comment("@bors r+").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"This PR is a draft; mark it ready first."
+            );
+            tester.default_pr().await.expect_unapproved();
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn draft_pr_reject_try(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            make_default_pr_draft(&tester);
+            tester.post_comment("@bors try").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"This PR is a draft; mark it ready first."
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn draft_pr_allow_ping(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            make_default_pr_draft(&tester);
+            tester.post_comment("@bors ping").await?;
+            assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn edited_comment_executes_new_command(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r-+").await?;
+            tester.expect_comments(1).await;
+            tester.edit_comment("@bors r+", "@bors r-+").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `default-user`"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn edited_comment_does_not_repeat_commands(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors ping").await?;
+            tester.expect_comments(1).await;
+            tester
+                .edit_comment("@bors ping\n@bors r+", "@bors ping")
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `default-user`"
+            );
+            // Returning here will make sure that ping was not answered again
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn edited_comment_without_new_commands(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors ping").await?;
+            tester.expect_comments(1).await;
+            tester
+                .edit_comment("Let's see: @bors ping", "@bors ping")
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn edit_by_other_user_is_ignored(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("hello").with_author(User::reviewer()))
+                .await?;
+            tester
+                .edit_comment_as(
+                    Comment::from("@bors r+").with_author(User::reviewer()),
+                    "hello",
+                    User::unprivileged(),
+                )
+                .await?;
+            tester.post_comment("@bors ping").await?;
+            assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+            tester.default_pr().await.expect_unapproved();
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn duplicate_comment_edit(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("hello").await?;
+            for _ in 0..2 {
+                tester.edit_comment("@bors ping", "hello").await?;
+            }
+            assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+            // Returning here will make sure that the second delivery was ignored
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn repeated_comment_edit_skips_executed_commands(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async move {
+            tester
+                .post_comment(Comment::from("hello").with_id(10))
+                .await?;
+            tester
+                .edit_comment(Comment::from("@bors ping\n@bors r+").with_id(10), "hello")
+                .await?;
+            tester.expect_comments(2).await;
+            tester.post_comment("@bors r-").await?;
+            tester.expect_comments(1).await;
+
+            // Simulate that bors has stopped after executing the first command of the edit
+            sqlx::query("UPDATE processed_comment SET executed_commands = 1")
+                .execute(&pool)
+                .await?;
+            tester
+                .edit_comment(Comment::from("@bors ping\n@bors r+").with_id(10), "hello")
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `default-user`"
+            );
+            // Returning here will make sure that ping was not answered again
+            Ok(tester)
+        })
+        .await;
+    }
 }
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use url::Url;
+
+use crate::bors::RepositoryState;
+use crate::bors::handlers::refresh::elapsed_time;
+use crate::database::{BuildModel, BuildStatus};
+use crate::github::PullRequestNumber;
+
+/// How many times is the delivery of a notification attempted before giving up.
+pub(super) const NOTIFICATION_ATTEMPTS: u32 = 3;
+/// Delay before the first retry of a failed delivery. It is doubled after each failed attempt.
+pub(super) const NOTIFICATION_RETRY_DELAY: Duration = Duration::from_secs(5);
+pub(super) const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// JSON payload that is POSTed to the `notification_url` of a repository when a build reaches
+/// a terminal status.
+///
+/// The schema is stable: existing fields are never renamed, removed or changed in meaning,
+/// new fields may be added in the future.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BuildCompletedNotification {
+    /// Repository in the `<owner>/<name>` format.
+    pub repository: String,
+    /// Number of the PR that the build belongs to.
+    pub pr_number: u64,
+    /// Branch on which the build was performed, e.g. `automation/bors/try`.
+    pub branch: String,
+    /// Final status of the build.
+    pub status: NotificationBuildStatus,
+    /// SHA of the commit that was tested.
+    pub commit_sha: String,
+    /// Time between the start and the end of the build, in whole seconds.
+    pub duration_secs: u64,
+}
+
+/// Terminal status of a build, serialized in lowercase (e.g. `"success"`).
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationBuildStatus {
+    Success,
+    Failure,
+    Cancelled,
+    Timeouted,
+}
+
+impl NotificationBuildStatus {
+    /// Returns `None` for builds that have not finished yet.
+    fn from_build_status(status: BuildStatus) -> Option<Self> {
+        match status {
+            BuildStatus::Pending => None,
+            BuildStatus::Success => Some(Self::Success),
+            BuildStatus::Failure => Some(Self::Failure),
+            BuildStatus::Cancelled => Some(Self::Cancelled),
+            BuildStatus::Timeouted => Some(Self::Timeouted),
+        }
+    }
+}
+
+/// Notifies the `notification_url` of the repository (if configured) that `build` has finished
+/// with the given `status`.
+/// The notification is delivered in the background, so that a slow or unavailable receiver
+/// does not block the handling of the build. Failed deliveries are retried a few times and
+/// then logged.
+pub(super) fn notify_build_completed(
+    repo: &RepositoryState,
+    build: &BuildModel,
+    pr: PullRequestNumber,
+    status: BuildStatus,
+) {
+    let Some(url) = repo.config.load().notification_url.clone() else {
+        return;
+    };
+    let Some(status) = NotificationBuildStatus::from_build_status(status) else {
+        tracing::warn!(
+            "Not sending a notification for unfinished build {}",
+            build.commit_sha
+        );
+        return;
+    };
+    let payload = BuildCompletedNotification {
+        repository: repo.repository().to_string(),
+        pr_number: pr.0,
+        branch: build.branch.clone(),
+        status,
+        commit_sha: build.commit_sha.clone(),
+        duration_secs: elapsed_time(build.created_at).as_secs(),
+    };
+
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        if let Err(error) =
+            send_notification(&client, &url, &payload, NOTIFICATION_RETRY_DELAY).await
+        {
+            tracing::error!(
+                "Could not deliver notification about build {} to {url}: {error:?}",
+                payload.commit_sha
+            );
+        }
+    });
+}
+
+async fn send_notification(
+    client: &reqwest::Client,
+    url: &Url,
+    payload: &BuildCompletedNotification,
+    retry_delay: Duration,
+) -> anyhow::Result<()> {
+    deliver_with_retries(url.as_str(), retry_delay, || {
+        client
+            .post(url.clone())
+            .timeout(NOTIFICATION_TIMEOUT)
+            .json(payload)
+            .send()
+    })
+    .await
+}
+
+/// Performs the delivery `request` until it succeeds, at most [NOTIFICATION_ATTEMPTS] times.
+/// The delay between two attempts starts at `retry_delay` and is doubled after each attempt.
+pub(super) async fn deliver_with_retries<F, Fut>(
+    target: &str,
+    retry_delay: Duration,
+    mut request: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = reqwest::Result<reqwest::Response>>,
+{
+    let mut delay = retry_delay;
+    let mut attempt = 1;
+    loop {
+        let result = request()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return Ok(()),
+            Err(error) if attempt < NOTIFICATION_ATTEMPTS => {
+                tracing::warn!(
+                    "Delivery of notification to {target} failed (attempt {attempt}/{NOTIFICATION_ATTEMPTS}): {error:?}"
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::bors::handlers::notification::{
+        BuildCompletedNotification, NOTIFICATION_ATTEMPTS, NotificationBuildStatus,
+        send_notification,
+    };
+
+    fn payload() -> BuildCompletedNotification {
+        BuildCompletedNotification {
+            repository: "rust-lang/borstest".to_string(),
+            pr_number: 1,
+            branch: "automation/bors/try".to_string(),
+            status: NotificationBuildStatus::Success,
+            commit_sha: "merge-main-sha1-pr-1-sha-0".to_string(),
+            duration_secs: 120,
+        }
+    }
+
+    fn notification_url(server: &MockServer) -> Url {
+        format!("{}/notify", server.uri()).parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_notification_payload() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/notify"))
+            .and(body_json(serde_json::json!({
+                "repository": "rust-lang/borstest",
+                "pr_number": 1,
+                "branch": "automation/bors/try",
+                "status": "success",
+                "commit_sha": "merge-main-sha1-pr-1-sha-0",
+                "duration_secs": 120
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        send_notification(
+            &reqwest::Client::new(),
+            &notification_url(&server),
+            &payload(),
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_notification_retry_after_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/notify"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/notify"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        send_notification(
+            &reqwest::Client::new(),
+            &notification_url(&server),
+            &payload(),
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_notification_give_up() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/notify"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(u64::from(NOTIFICATION_ATTEMPTS))
+            .mount(&server)
+            .await;
+
+        assert!(
+            send_notification(
+                &reqwest::Client::new(),
+                &notification_url(&server),
+                &payload(),
+                Duration::ZERO,
+            )
+            .await
+            .is_err()
+        );
+    }
+}
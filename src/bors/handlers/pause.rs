@@ -0,0 +1,226 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use crate::PgDbClient;
+use crate::bors::comment::{repository_paused_comment, repository_resumed_comment};
+use crate::bors::handlers::auto_build::process_merge_queue;
+use crate::bors::handlers::deny_request;
+use crate::bors::handlers::trybuild::start_queued_try_builds;
+use crate::bors::{BorsResult, RepositoryState};
+use crate::github::{GithubUser, PullRequest};
+use crate::permissions::PermissionType;
+
+/// Pauses (`paused = true`) or resumes bors on the repository.
+/// While the repository is paused, commands that change its state are rejected and queued
+/// builds are not started, but running builds are still tracked until they finish.
+/// Only reviewers can pause or resume a repository.
+pub(super) async fn command_pause(
+    repo: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    pr: &PullRequest,
+    author: &GithubUser,
+    paused: bool,
+) -> BorsResult<()> {
+    if !repo
+        .user_has_permission(author, PermissionType::Review)
+        .await
+    {
+        let action = if paused {
+            "pause the repository"
+        } else {
+            "resume the repository"
+        };
+        return Err(deny_request(&repo, author, PermissionType::Review, action).await);
+    }
+
+    db.set_repository_paused(repo.repository(), paused).await?;
+    repo.paused.store(paused, Ordering::Relaxed);
+    if paused {
+        tracing::warn!(
+            "Bors was paused on {} by {}",
+            repo.repository(),
+            author.username
+        );
+        repo.client
+            .post_comment(pr.number, repository_paused_comment(&author.username))
+            .await?;
+    } else {
+        tracing::info!(
+            "Bors was resumed on {} by {}",
+            repo.repository(),
+            author.username
+        );
+        repo.client
+            .post_comment(pr.number, repository_resumed_comment(&author.username))
+            .await?;
+        // Try builds might have been queued before the repository was paused
+        if let Err(error) = start_queued_try_builds(&repo, &db).await {
+            tracing::error!("Could not start queued try builds: {error:?}");
+        }
+        if let Err(error) = process_merge_queue(&repo, &db).await {
+            tracing::error!("Could not process the merge queue: {error:?}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use crate::database::BuildStatus;
+    use crate::tests::mocks::{
+        BorsBuilder, Comment, GitHubState, User, default_pr_number, default_repo_name, run_test,
+    };
+
+    #[sqlx::test]
+    async fn pause_and_resume(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors pause").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":pause_button: Bors has been paused on this repository by @default-user. Running builds are left alone, but commands that change the state of PRs are rejected and no new builds are started until `resume` is issued."
+            );
+            assert!(tester.db().is_repository_paused(&default_repo_name()).await?);
+
+            tester.post_comment("@bors resume").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":arrow_forward: Bors has been resumed on this repository by @default-user."
+            );
+            assert!(!tester.db().is_repository_paused(&default_repo_name()).await?);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn report_pause_state(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors pause").await?;
+            tester.expect_comments(1).await;
+
+            let (status, body) = tester.get_endpoint("/health").await?;
+            assert_eq!(status, StatusCode::OK);
+            let health: serde_json::Value = serde_json::from_str(&body)?;
+            assert_eq!(health["repositories"]["rust-lang/borstest"]["paused"], true);
+
+            let (status, headers, _) = tester
+                .get_endpoint_with_headers("/queue/rust-lang/borstest")
+                .await?;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(headers["x-bors-paused"], "true");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn pause_insufficient_permission(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors pause").with_author(User::unprivileged()))
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @unprivileged-user: You don't have permission to pause the repository: the `review` permission is required, you have no permissions"
+            );
+            assert!(!tester.db().is_repository_paused(&default_repo_name()).await?);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn paused_repository_rejects_state_changing_commands(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors pause").await?;
+            tester.expect_comments(1).await;
+
+            tester.post_comment("@bors r+").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":pause_button: Bors is paused on this repository, the command was not executed."
+            );
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            let pr = tester.default_pr_db().await?;
+            assert!(pr.is_none_or(|pr| !pr.has_approval() && pr.try_build.is_none()));
+
+            // Read-only commands keep working
+            tester.post_comment("@bors ping").await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @"Pong 🏓!");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn paused_repository_ignores_review_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("approve_on_review = true"))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors pause").await?;
+                tester.expect_comments(1).await;
+
+                tester
+                    .submit_review(default_pr_number(), User::reviewer(), "approved", "")
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":pause_button: Bors is paused on this repository, the command was not executed."
+                );
+                let pr = tester.default_pr_db().await?;
+                assert!(pr.is_none_or(|pr| !pr.has_approval()));
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn paused_repository_records_build_completion(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.post_comment("@bors pause").await?;
+            tester.expect_comments(1).await;
+
+            tester.workflow_success(tester.try_branch()).await?;
+            tester.expect_comments(1).await;
+            assert_eq!(
+                tester
+                    .default_pr_db()
+                    .await?
+                    .unwrap()
+                    .try_build
+                    .unwrap()
+                    .status,
+                BuildStatus::Success
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn pause_survives_restart(pool: sqlx::PgPool) {
+        let gh = run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors pause").await?;
+            tester.expect_comments(1).await;
+            Ok(tester)
+        })
+        .await;
+
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":pause_button: Bors is paused on this repository, the command was not executed."
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+}
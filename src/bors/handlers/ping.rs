@@ -1,15 +1,31 @@
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
+
+use crate::bors::BorsResult;
 use crate::bors::Comment;
 use crate::bors::RepositoryState;
 use crate::github::PullRequest;
 
+/// Replies to a ping.
+/// If `created_at` is set, the reply also contains the time elapsed since the ping comment was
+/// posted, which includes the delay of the webhook delivery and of the event queue.
 pub(super) async fn command_ping(
     repo: Arc<RepositoryState>,
     pr: &PullRequest,
-) -> anyhow::Result<()> {
+    created_at: Option<DateTime<Utc>>,
+) -> BorsResult<()> {
+    let mut text = "Pong 🏓!".to_string();
+    if let Some(created_at) = created_at {
+        // The clocks of GitHub and bors might not be perfectly synchronized
+        let latency = (Utc::now() - created_at).max(chrono::Duration::zero());
+        text.push_str(&format!(" (processed in {}ms)", latency.num_milliseconds()));
+    }
+    if repo.client.is_dry_run() {
+        text.push_str(" (dry-run mode is enabled, GitHub is not modified)");
+    }
     repo.client
-        .post_comment(pr.number, Comment::new("Pong 🏓!".to_string()))
+        .post_comment(pr.number, Comment::new(text))
         .await?;
     Ok(())
 }
@@ -27,4 +43,16 @@ mod tests {
         })
         .await;
     }
+
+    #[sqlx::test]
+    async fn ping_latency(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors ping latency").await?;
+            let comment = tester.get_comment().await?;
+            let regex = regex::Regex::new(r"^Pong 🏓! \(processed in \d+ms\)$").unwrap();
+            assert!(regex.is_match(&comment), "Unexpected comment: {comment}");
+            Ok(tester)
+        })
+        .await;
+    }
 }
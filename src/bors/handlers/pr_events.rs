@@ -1,22 +1,37 @@
 use crate::PgDbClient;
+use crate::bors::comment::merge_conflict_comment;
 use crate::bors::event::{
-    PullRequestClosed, PullRequestConvertedToDraft, PullRequestEdited, PullRequestMerged,
-    PullRequestOpened, PullRequestPushed, PullRequestReadyForReview, PullRequestReopened,
-    PushToBranch,
+    PullRequestClosed, PullRequestConvertedToDraft, PullRequestEdited, PullRequestLabeled,
+    PullRequestMerged, PullRequestOpened, PullRequestPushed, PullRequestReadyForReview,
+    PullRequestReopened, PullRequestUnlabeled, PushToBranch,
 };
+use crate::bors::handlers::auto_build::process_merge_queue;
+use crate::bors::handlers::check_run::complete_build_check_run;
+use crate::bors::handlers::clean::delete_build_branches;
+use crate::bors::handlers::commit_status::report_build_completed;
 use crate::bors::handlers::labels::handle_label_trigger;
-use crate::bors::{Comment, PullRequestStatus, RepositoryState};
-use crate::database::MergeableState;
-use crate::github::{CommitSha, LabelTrigger, PullRequestNumber};
+use crate::bors::handlers::notification::notify_build_completed;
+use crate::bors::handlers::refresh::replace_config;
+use crate::bors::handlers::status_comment::post_status_comment_best_effort;
+use crate::bors::handlers::trybuild::{cancel_build_workflows, start_queued_try_builds_locked};
+use crate::bors::template::MessageKind;
+use crate::bors::{BorsResult, Comment, PullRequestStatus, RepositoryState, RollupMode};
+use crate::config::{CONFIG_FILE_PATH, RepositoryConfig, find_unknown_config_keys};
+use crate::database::{BuildStatus, MergeableState};
+use crate::github::{CheckRunConclusion, CommitSha, LabelTrigger, PullRequestNumber};
 use std::sync::Arc;
 
 pub(super) async fn handle_pull_request_edited(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestEdited,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     let pr = &payload.pull_request;
     let pr_number = pr.number;
+    let previous_mergeable_state = db
+        .get_pull_request(repo_state.repository(), pr_number)
+        .await?
+        .map(|pr| pr.mergeable_state);
     let pr_model = db
         .get_or_create_pull_request(
             repo_state.repository(),
@@ -24,28 +39,52 @@ pub(super) async fn handle_pull_request_edited(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
+    db.update_pr_details(&pr_model, &pr.title, &pr.author.username, &pr.head.sha)
+        .await?;
 
     // If the base branch has changed, unapprove the PR
     let Some(_) = payload.from_base_sha else {
+        // An approved PR with conflicts is skipped by the merge queue until they are resolved
+        if pr_model.mergeable_state == MergeableState::HasConflicts
+            && previous_mergeable_state != Some(MergeableState::HasConflicts)
+            && pr_model.is_approved(&pr.head.sha)
+        {
+            post_status_comment_best_effort(
+                &repo_state,
+                &db,
+                pr_number,
+                merge_conflict_comment(&pr.head.name),
+            )
+            .await;
+        }
+        // A PR that was skipped by the merge queue because of conflicts returns to it
+        if previous_mergeable_state == Some(MergeableState::HasConflicts)
+            && pr_model.mergeable_state != MergeableState::HasConflicts
+            && pr_model.is_approved(&pr.head.sha)
+        {
+            process_merge_queue(&repo_state, &db).await?;
+        }
         return Ok(());
     };
 
-    if !pr_model.is_approved() {
+    if !pr_model.has_approval() {
         return Ok(());
     }
 
     db.unapprove(&pr_model).await?;
+    notify_of_edited_pr(&repo_state, &db, pr_number, &payload.pull_request.base.name).await;
     handle_label_trigger(&repo_state, pr_number, LabelTrigger::Unapproved).await?;
-    notify_of_edited_pr(&repo_state, pr_number, &payload.pull_request.base.name).await
+    Ok(())
 }
 
 pub(super) async fn handle_push_to_pull_request(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestPushed,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     let pr = &payload.pull_request;
     let pr_number = pr.number;
     let pr_model = db
@@ -55,23 +94,27 @@ pub(super) async fn handle_push_to_pull_request(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
+    db.update_pr_details(&pr_model, &pr.title, &pr.author.username, &pr.head.sha)
+        .await?;
 
-    if !pr_model.is_approved() {
+    if !pr_model.has_approval() {
         return Ok(());
     }
 
     db.unapprove(&pr_model).await?;
+    notify_of_pushed_pr(&repo_state, &db, pr_number, pr.head.sha.clone()).await;
     handle_label_trigger(&repo_state, pr_number, LabelTrigger::Unapproved).await?;
-    notify_of_pushed_pr(&repo_state, pr_number, pr.head.sha.clone()).await
+    Ok(())
 }
 
 pub(super) async fn handle_pull_request_opened(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestOpened,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     let pr_status = if payload.draft {
         PullRequestStatus::Draft
     } else {
@@ -82,130 +125,377 @@ pub(super) async fn handle_pull_request_opened(
         payload.pull_request.number,
         &payload.pull_request.base.name,
         pr_status,
+        &payload.pull_request.author.username,
+        &payload.pull_request.title,
+        &payload.pull_request.head.sha.0,
     )
-    .await
+    .await?;
+    Ok(())
 }
 
 pub(super) async fn handle_pull_request_closed(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestClosed,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
+    let pr_number = payload.pull_request.number;
     db.set_pr_status(
         repo_state.repository(),
-        payload.pull_request.number,
+        pr_number,
         PullRequestStatus::Closed,
     )
-    .await
+    .await?;
+    cancel_try_build_of_closed_pr(&repo_state, &db, pr_number).await?;
+    Ok(())
+}
+
+/// Cancels the queued or running try build of a PR that was closed, so that it does not waste
+/// CI resources. If `delete_try_branch_on_close` is enabled, the branches of the cancelled build
+/// are also deleted, unless a newer build already uses them.
+async fn cancel_try_build_of_closed_pr(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_number: PullRequestNumber,
+) -> anyhow::Result<()> {
+    let _guard = repo.try_builds_lock.lock().await;
+    let Some(pr) = db.get_pull_request(repo.repository(), pr_number).await? else {
+        return Ok(());
+    };
+    if db.remove_queued_try_build(pr.id).await? {
+        tracing::info!("Queued try build of closed PR {pr_number} removed");
+    }
+    let Some(build) = pr
+        .try_build
+        .as_ref()
+        .filter(|build| build.status == BuildStatus::Pending)
+    else {
+        return Ok(());
+    };
+
+    tracing::info!("Cancelling try build of closed PR {pr_number}");
+    if let Err(error) = cancel_build_workflows(&repo.client, db, build).await {
+        tracing::error!(
+            "Could not cancel workflows for SHA {}: {error:?}",
+            build.commit_sha
+        );
+    }
+    db.update_build_status(build, BuildStatus::Cancelled)
+        .await?;
+    notify_build_completed(repo, build, pr_number, BuildStatus::Cancelled);
+    complete_build_check_run(repo, db, build, BuildStatus::Cancelled).await;
+    report_build_completed(repo, build, pr_number, BuildStatus::Cancelled).await;
+
+    if repo.config.load().delete_try_branch_on_close {
+        let builds = db
+            .get_latest_build_per_branch(repo.repository())
+            .await?
+            .into_iter()
+            .filter(|latest| latest.id == build.id)
+            .collect::<Vec<_>>();
+        let cleaned = delete_build_branches(repo, db, &builds).await?;
+        if !cleaned.deleted.is_empty() {
+            tracing::info!("Deleted branches {:?}", cleaned.deleted);
+        }
+    }
+
+    // A try build slot was freed, so a queued try build can be started
+    if let Err(error) = start_queued_try_builds_locked(repo, db).await {
+        tracing::error!("Could not start queued try builds: {error:?}");
+    }
+    Ok(())
 }
 
 pub(super) async fn handle_pull_request_merged(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestMerged,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     db.set_pr_status(
         repo_state.repository(),
         payload.pull_request.number,
         PullRequestStatus::Merged,
     )
-    .await
+    .await?;
+    Ok(())
 }
 
 pub(super) async fn handle_pull_request_reopened(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestReopened,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     db.set_pr_status(
         repo_state.repository(),
         payload.pull_request.number,
         PullRequestStatus::Open,
     )
-    .await
+    .await?;
+    Ok(())
 }
 
 pub(super) async fn handle_pull_request_converted_to_draft(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestConvertedToDraft,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     db.set_pr_status(
         repo_state.repository(),
         payload.pull_request.number,
         PullRequestStatus::Draft,
     )
-    .await
+    .await?;
+    Ok(())
 }
 
 pub(super) async fn handle_pull_request_ready_for_review(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PullRequestReadyForReview,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     db.set_pr_status(
         repo_state.repository(),
         payload.pull_request.number,
         PullRequestStatus::Open,
     )
-    .await
+    .await?;
+    Ok(())
+}
+
+pub(super) async fn handle_pull_request_labeled(
+    repo_state: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    payload: PullRequestLabeled,
+) -> BorsResult<()> {
+    if !is_no_rollup_label(&repo_state, &payload.label) {
+        return Ok(());
+    }
+    if repo_state.is_paused() {
+        tracing::info!(
+            "Label `{}` was added, but the repository is paused",
+            payload.label
+        );
+        return Ok(());
+    }
+    let pr = &payload.pull_request;
+    let pr_model = db
+        .get_or_create_pull_request(
+            repo_state.repository(),
+            pr.number,
+            &pr.base.name,
+            pr.mergeable_state.clone().into(),
+            &pr.status,
+            &pr.author.username,
+        )
+        .await?;
+    tracing::info!(
+        "Label `{}` was added, setting rollup to never",
+        payload.label
+    );
+    db.set_rollup(&pr_model, RollupMode::Never).await?;
+    Ok(())
+}
+
+pub(super) async fn handle_pull_request_unlabeled(
+    repo_state: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    payload: PullRequestUnlabeled,
+) -> BorsResult<()> {
+    if !is_no_rollup_label(&repo_state, &payload.label) {
+        return Ok(());
+    }
+    if repo_state.is_paused() {
+        tracing::info!(
+            "Label `{}` was removed, but the repository is paused",
+            payload.label
+        );
+        return Ok(());
+    }
+    let Some(pr_model) = db
+        .get_pull_request(repo_state.repository(), payload.pull_request.number)
+        .await?
+    else {
+        return Ok(());
+    };
+    // The rollup mode might have been changed by a command after the label was added
+    if pr_model.rollup == Some(RollupMode::Never) {
+        tracing::info!("Label `{}` was removed, resetting rollup", payload.label);
+        db.reset_rollup(&pr_model).await?;
+    }
+    Ok(())
+}
+
+/// Returns true if the label is the `no_rollup_label` of the repository.
+fn is_no_rollup_label(repo_state: &RepositoryState, label: &str) -> bool {
+    repo_state
+        .config
+        .load()
+        .no_rollup_label
+        .as_deref()
+        .is_some_and(|no_rollup| no_rollup.eq_ignore_ascii_case(label))
 }
 
 pub(super) async fn handle_push_to_branch(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: PushToBranch,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     let rows = db
         .update_mergeable_states_by_base_branch(
             repo_state.repository(),
             &payload.branch,
+            &payload.commit_sha,
             MergeableState::Unknown,
         )
         .await?;
 
-    tracing::info!("Updated mergeable_state to `unknown` for {} PR(s)", rows);
+    tracing::info!(
+        "Updated mergeable_state to `unknown` for {} PR(s) whose base has moved",
+        rows
+    );
+
+    if payload.is_default_branch
+        && payload
+            .changed_files
+            .iter()
+            .any(|file| file == CONFIG_FILE_PATH)
+    {
+        update_config(&repo_state, &db, &payload.commit_sha).await?;
+    }
 
     Ok(())
 }
 
-async fn notify_of_edited_pr(
+/// Name of the check run that reports problems with the bors configuration file.
+const CONFIG_CHECK_RUN_NAME: &str = "bors config";
+
+/// Validates the configuration file at the given commit and reports the result as a check run.
+/// If the configuration is valid, it replaces the configuration that is currently in effect.
+async fn update_config(
     repo: &RepositoryState,
-    pr_number: PullRequestNumber,
-    base_name: &str,
+    db: &PgDbClient,
+    commit_sha: &CommitSha,
 ) -> anyhow::Result<()> {
+    let content = match repo
+        .client
+        .load_config_content(Some(commit_sha.as_ref()))
+        .await
+    {
+        Ok(content) => content,
+        Err(error) => {
+            tracing::warn!("Could not load configuration at {commit_sha}: {error:?}");
+            return repo
+                .client
+                .create_check_run(
+                    CONFIG_CHECK_RUN_NAME,
+                    commit_sha,
+                    CheckRunConclusion::Failure,
+                    "Missing bors configuration",
+                    &format!(
+                        "Could not load `{CONFIG_FILE_PATH}`, the previous configuration stays in effect."
+                    ),
+                )
+                .await;
+        }
+    };
+
+    let unknown_keys = find_unknown_config_keys(&content);
+    let unknown_keys_summary = if unknown_keys.is_empty() {
+        String::new()
+    } else {
+        let keys = unknown_keys
+            .iter()
+            .map(|key| format!("- `{key}`"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("\n\nThe following keys are unknown and will be ignored:\n{keys}")
+    };
+
+    let config = RepositoryConfig::parse(&content);
+    let (conclusion, title, summary) = match &config {
+        Ok(_) if unknown_keys.is_empty() => (
+            CheckRunConclusion::Success,
+            "Valid bors configuration",
+            format!("`{CONFIG_FILE_PATH}` is valid."),
+        ),
+        Ok(_) => (
+            CheckRunConclusion::Neutral,
+            "bors configuration contains unknown keys",
+            format!("`{CONFIG_FILE_PATH}` is valid.{unknown_keys_summary}"),
+        ),
+        Err(error) => {
+            tracing::warn!("Invalid configuration at {commit_sha}: {error}");
+            (
+                CheckRunConclusion::Failure,
+                "Invalid bors configuration",
+                format!(
+                    "Could not parse `{CONFIG_FILE_PATH}`, the previous configuration stays in effect.\n\n```\n{}\n```{unknown_keys_summary}",
+                    error.to_string().trim_end()
+                ),
+            )
+        }
+    };
+    if let Ok(config) = config {
+        replace_config(repo, db, config, &content).await?;
+    }
+
     repo.client
-        .post_comment(
-            pr_number,
-            Comment::new(format!(
-                r#":warning: The base branch changed to `{base_name}`, and the
-PR will need to be re-approved."#,
-            )),
+        .create_check_run(
+            CONFIG_CHECK_RUN_NAME,
+            commit_sha,
+            conclusion,
+            title,
+            &summary,
         )
         .await
 }
 
+async fn notify_of_edited_pr(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_number: PullRequestNumber,
+    base_name: &str,
+) {
+    post_status_comment_best_effort(
+        repo,
+        db,
+        pr_number,
+        Comment::new(
+            repo.config
+                .load()
+                .render_message(MessageKind::BaseChanged, &[("branch", base_name)]),
+        ),
+    )
+    .await
+}
+
 async fn notify_of_pushed_pr(
     repo: &RepositoryState,
+    db: &PgDbClient,
     pr_number: PullRequestNumber,
     head_sha: CommitSha,
-) -> anyhow::Result<()> {
-    repo.client
-        .post_comment(
-            pr_number,
-            Comment::new(format!(
-                r#":warning: A new commit `{}` was pushed to the branch, the
-PR will need to be re-approved."#,
-                head_sha
-            )),
-        )
-        .await
+) {
+    post_status_comment_best_effort(
+        repo,
+        db,
+        pr_number,
+        Comment::new(
+            repo.config
+                .load()
+                .render_message(MessageKind::Pushed, &[("sha", head_sha.short())]),
+        ),
+    )
+    .await
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::bors::PullRequestStatus;
+    use crate::PgDbClient;
+    use crate::bors::handlers::WAIT_FOR_WORKFLOW_STARTED;
+    use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, TRY_MERGE_BRANCH_NAME};
+    use crate::bors::{PullRequestStatus, RollupMode};
+    use crate::database::BuildStatus;
     use crate::tests::mocks::default_pr_number;
+    use crate::tests::mocks::{BorsBuilder, BorsTester, CheckRunData, GitHubState, WorkflowEvent};
     use crate::{
         database::MergeableState,
         tests::mocks::{User, default_branch_name, default_repo_name, run_test},
@@ -236,6 +526,44 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn unapprove_custom_messages(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+[messages]
+base_changed = "Base changed to {branch}, please approve again."
+pushed = "New commit {sha}, please approve again."
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                let branch = tester.create_branch("beta").clone();
+                tester
+                    .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                        pr.base_branch = branch;
+                    })
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Base changed to beta, please approve again."
+                );
+
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .push_to_pr(default_repo_name(), default_pr_number())
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"New commit pr-1-co, please approve again."
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn edit_pr_do_nothing_when_base_not_edited(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -270,6 +598,65 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn store_pr_details_on_open(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let pr = tester.open_pr(default_repo_name(), false).await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr_db) = tester.pr_db(default_repo_name(), pr.number.0).await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr_db.title.as_ref() == Some(&pr.title)
+                        && pr_db.author.as_ref() == Some(&pr.author.name)
+                        && pr_db.head_sha.as_ref() == Some(&pr.head_sha))
+                })
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn update_title_on_edit(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                    pr.title = "Renamed PR".to_string();
+                })
+                .await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.title.as_deref() == Some("Renamed PR"))
+                })
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn update_head_sha_on_push(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .push_to_pr(default_repo_name(), default_pr_number())
+                .await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.head_sha.as_deref() == Some("pr-1-commit-1"))
+                })
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
     #[sqlx::test]
     async fn unapprove_on_push(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -282,7 +669,7 @@ mod tests {
             insta::assert_snapshot!(
                 tester.get_comment().await?,
                 @r"
-            :warning: A new commit `pr-1-commit-1` was pushed to the branch, the
+            :warning: A new commit `pr-1-co` was pushed to the branch, the
             PR will need to be re-approved.
             "
             );
@@ -366,6 +753,66 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn comment_on_conflicts_of_approved_pr(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+            tester
+                .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                    pr.mergeable_state = octocrab::models::pulls::MergeableState::Dirty;
+                })
+                .await?;
+            let comment = tester.get_comment().await?;
+            assert!(comment.starts_with(":lock: Merge conflict"));
+
+            // Further edits of the conflicting PR do not repeat the comment
+            tester
+                .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                    pr.title = "Edited".to_string();
+                })
+                .await?;
+            tester
+                .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                    pr.mergeable_state = octocrab::models::pulls::MergeableState::Clean;
+                })
+                .await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.mergeable_state == MergeableState::Mergeable && pr.has_approval())
+                })
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn no_conflict_comment_for_unapproved_pr(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .edit_pr(default_repo_name(), default_pr_number(), |pr| {
+                    pr.mergeable_state = octocrab::models::pulls::MergeableState::Dirty;
+                })
+                .await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.mergeable_state == MergeableState::HasConflicts)
+                })
+                .await?;
+            tester.post_comment("@bors ping").await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @"Pong 🏓!");
+            Ok(tester)
+        })
+        .await;
+    }
+
     #[sqlx::test]
     async fn open_close_and_reopen_pr(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -429,6 +876,73 @@ mod tests {
         .await;
     }
 
+    fn gh_state_with_no_rollup_label() -> GitHubState {
+        GitHubState::default().with_default_config(r#"no_rollup_label = "rollup=never""#)
+    }
+
+    #[sqlx::test]
+    async fn no_rollup_label_sets_rollup_never(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_no_rollup_label())
+            .run_test(|mut tester| async {
+                tester
+                    .add_pr_label(default_repo_name(), default_pr_number(), "rollup=never")
+                    .await?;
+                tester
+                    .wait_for(|| async {
+                        let Some(pr) = tester.default_pr_db().await? else {
+                            return Ok(false);
+                        };
+                        Ok(pr.rollup == Some(RollupMode::Never))
+                    })
+                    .await?;
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn removing_no_rollup_label_resets_rollup(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_no_rollup_label())
+            .run_test(|mut tester| async {
+                tester
+                    .add_pr_label(default_repo_name(), default_pr_number(), "rollup=never")
+                    .await?;
+                tester
+                    .remove_pr_label(default_repo_name(), default_pr_number(), "rollup=never")
+                    .await?;
+                tester.post_comment("@bors ping").await?;
+                tester.expect_comments(1).await;
+                assert_eq!(tester.default_pr_db().await?.unwrap().rollup, None);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn ignore_other_labels(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_no_rollup_label())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors rollup=iffy").await?;
+                tester
+                    .add_pr_label(default_repo_name(), default_pr_number(), "T-compiler")
+                    .await?;
+                tester
+                    .remove_pr_label(default_repo_name(), default_pr_number(), "T-compiler")
+                    .await?;
+                tester.post_comment("@bors ping").await?;
+                tester.expect_comments(1).await;
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().rollup,
+                    Some(RollupMode::Iffy)
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn open_pr_and_convert_to_draft(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -457,6 +971,78 @@ mod tests {
         .await;
     }
 
+    async fn start_try_workflow_and_close_pr(tester: &mut BorsTester) -> anyhow::Result<()> {
+        tester.post_comment("@bors try").await?;
+        tester.expect_comments(1).await;
+        tester
+            .workflow_event(WorkflowEvent::started(tester.try_branch()))
+            .await?;
+        WAIT_FOR_WORKFLOW_STARTED.sync().await;
+        tester
+            .close_pr(default_repo_name(), default_pr_number())
+            .await?;
+        tester
+            .wait_for(|| async {
+                let Some(pr) = tester.default_pr_db().await? else {
+                    return Ok(false);
+                };
+                Ok(pr
+                    .try_build
+                    .is_some_and(|build| build.status == BuildStatus::Cancelled))
+            })
+            .await
+    }
+
+    #[sqlx::test]
+    async fn close_pr_cancels_try_build(pool: sqlx::PgPool) {
+        let gh = run_test(pool, |mut tester| async {
+            start_try_workflow_and_close_pr(&mut tester).await?;
+            tester
+                .wait_for(|| async {
+                    Ok(tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_none())
+                })
+                .await?;
+            assert!(
+                tester
+                    .default_repo()
+                    .lock()
+                    .get_branch_by_name(TRY_MERGE_BRANCH_NAME)
+                    .is_none()
+            );
+            Ok(tester)
+        })
+        .await;
+        gh.check_cancelled_workflows(default_repo_name(), &[1]);
+    }
+
+    #[sqlx::test]
+    async fn close_pr_keeps_try_branch(pool: sqlx::PgPool) {
+        let gh = BorsBuilder::new(pool)
+            .github(
+                GitHubState::default().with_default_config("delete_try_branch_on_close = false"),
+            )
+            .run_test(|mut tester| async {
+                start_try_workflow_and_close_pr(&mut tester).await?;
+                // Wait until the closed event is fully handled
+                tester.post_comment("@bors ping").await?;
+                tester.expect_comments(1).await;
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_some()
+                );
+                Ok(tester)
+            })
+            .await;
+        gh.check_cancelled_workflows(default_repo_name(), &[1]);
+    }
+
     #[sqlx::test]
     async fn open_and_merge_pr(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -482,4 +1068,177 @@ mod tests {
         })
         .await;
     }
+
+    async fn push_config(
+        tester: &mut BorsTester,
+        branch: &str,
+        config: &str,
+    ) -> anyhow::Result<()> {
+        tester.default_repo().lock().config = config.to_string();
+        tester.push_to_branch(branch, &["rust-bors.toml"]).await
+    }
+
+    async fn wait_for_check_run(tester: &BorsTester) -> anyhow::Result<CheckRunData> {
+        tester
+            .wait_for(|| async { Ok(!tester.default_repo().lock().check_runs.is_empty()) })
+            .await?;
+        Ok(tester.default_repo().lock().check_runs[0].clone())
+    }
+
+    #[sqlx::test]
+    async fn push_valid_config(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            push_config(&mut tester, default_branch_name(), "timeout = 1000").await?;
+            let check_run = wait_for_check_run(&tester).await?;
+            assert_eq!(check_run.name, "bors config");
+            assert_eq!(check_run.head_sha, "main-commit-1");
+            assert_eq!(check_run.conclusion, "success");
+            insta::assert_snapshot!(check_run.output.summary, @"`rust-bors.toml` is valid.");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn push_valid_config_replaces_config(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let config = r#"
+[labels]
+approve = ["+ready"]
+"#;
+            push_config(&mut tester, default_branch_name(), config).await?;
+            wait_for_check_run(&tester).await?;
+
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+            tester
+                .default_repo()
+                .lock()
+                .get_pr(default_pr_number())
+                .check_added_labels(&["ready"]);
+
+            let stored = tester
+                .db()
+                .get_repository_config(&default_repo_name())
+                .await?;
+            assert_eq!(stored.as_deref(), Some(config));
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn restore_stored_config(pool: sqlx::PgPool) {
+        PgDbClient::new(pool.clone())
+            .set_repository_config(
+                &default_repo_name(),
+                "[labels]\napprove = [\"+ready\"]",
+                "stored-hash",
+            )
+            .await
+            .unwrap();
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+            tester
+                .default_repo()
+                .lock()
+                .get_pr(default_pr_number())
+                .check_added_labels(&["ready"]);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn push_config_with_unknown_keys(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            push_config(
+                &mut tester,
+                default_branch_name(),
+                r#"
+timeout = 1000
+timout = 1000
+
+[labels]
+approved = ["+approved"]
+"#,
+            )
+            .await?;
+            let check_run = wait_for_check_run(&tester).await?;
+            assert_eq!(check_run.conclusion, "neutral");
+            insta::assert_snapshot!(check_run.output.summary, @r"
+            `rust-bors.toml` is valid.
+
+            The following keys are unknown and will be ignored:
+            - `labels.approved`
+            - `timout`
+            ");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn push_invalid_config(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            push_config(
+                &mut tester,
+                default_branch_name(),
+                r#"
+timeout = "foo"
+timout = 1000
+"#,
+            )
+            .await?;
+            let check_run = wait_for_check_run(&tester).await?;
+            assert_eq!(check_run.conclusion, "failure");
+            assert_eq!(check_run.output.title, "Invalid bors configuration");
+            let summary = check_run.output.summary;
+            assert!(summary.starts_with(
+                "Could not parse `rust-bors.toml`, the previous configuration stays in effect."
+            ));
+            assert!(summary.contains("timeout"));
+            assert!(summary.ends_with("- `timout`"));
+
+            // The previous configuration should still be used
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+            tester
+                .default_repo()
+                .lock()
+                .get_pr(default_pr_number())
+                .check_added_labels(&["approved"]);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn push_without_config_change(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .push_to_branch(default_branch_name(), &["README.md"])
+                .await?;
+            // Wait until the push event is handled
+            tester.post_comment("@bors ping").await?;
+            tester.expect_comments(1).await;
+            assert!(tester.default_repo().lock().check_runs.is_empty());
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn push_config_to_non_default_branch(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.create_branch("beta");
+            push_config(&mut tester, "beta", "timeout = \"foo\"").await?;
+            tester.post_comment("@bors ping").await?;
+            tester.expect_comments(1).await;
+            assert!(tester.default_repo().lock().check_runs.is_empty());
+            Ok(tester)
+        })
+        .await;
+    }
 }
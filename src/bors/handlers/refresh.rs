@@ -4,22 +4,59 @@ use std::time::Duration;
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 
-use crate::bors::Comment;
 use crate::bors::RepositoryState;
-use crate::bors::handlers::trybuild::cancel_build_workflows;
-use crate::database::BuildStatus;
-use crate::{PgDbClient, TeamApiClient};
+use crate::bors::comment::{
+    approval_expired_comment, build_timed_out_comment, delegation_expired_comment,
+    external_ci_timed_out_comment, merge_conflict_comment, required_checks_not_started_comment,
+};
+use crate::bors::event::RepositoryReloadSummary;
+use crate::bors::handlers::auto_build::{
+    AUTO_BRANCH_NAME, merge_build_failed, process_merge_queue_locked,
+};
+use crate::bors::handlers::chat::{ChatEvent, notify_chat};
+use crate::bors::handlers::check_run::complete_build_check_run;
+use crate::bors::handlers::clean::delete_build_branches;
+use crate::bors::handlers::commit_status::report_build_completed;
+use crate::bors::handlers::labels::handle_label_trigger;
+use crate::bors::handlers::notification::notify_build_completed;
+use crate::bors::handlers::status_comment::post_status_comment;
+use crate::bors::handlers::trybuild::{cancel_build_workflows, start_queued_try_builds_locked};
+use crate::config::RepositoryConfig;
+use crate::database::{
+    BuildModel, BuildStatus, DelegationModel, MergeableState, PullRequestModel, WorkflowStatus,
+    WorkflowType,
+};
+use crate::github::LabelTrigger;
+use crate::permissions::PermissionType;
+use crate::{PermissionSource, PgDbClient};
 
 pub async fn refresh_repository(
     repo: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
-    team_api_client: &TeamApiClient,
+    permission_source: &PermissionSource,
 ) -> anyhow::Result<()> {
     let repo = repo.as_ref();
-    if let (Ok(_), _, Ok(_)) = tokio::join!(
-        cancel_timed_out_builds(repo, db.as_ref()),
-        reload_permission(repo, team_api_client),
-        reload_config(repo)
+    if let (Ok(_), _, Ok(_), Ok(_), Ok(_), Ok(_), Ok(_)) = tokio::join!(
+        async {
+            // Running builds of a paused repository are left alone
+            if repo.is_paused() {
+                return Ok(());
+            }
+            let _guard = repo.try_builds_lock.lock().await;
+            let _merge_queue_guard = repo.merge_queue_lock.lock().await;
+            cancel_timed_out_builds(repo, db.as_ref()).await?;
+            // Timed out merge builds free the merge queue
+            process_merge_queue_locked(repo, db.as_ref()).await?;
+            // Timed out try builds free try build slots, and queued try builds might also have
+            // been left behind if starting them failed before
+            start_queued_try_builds_locked(repo, db.as_ref()).await
+        },
+        reload_permission(repo, permission_source),
+        reload_config(repo, db.as_ref()),
+        clean_stale_branches(repo, db.as_ref()),
+        refresh_unknown_mergeable_states(repo, db.as_ref()),
+        expire_stale_approvals(repo, db.as_ref()),
+        expire_stale_delegations(repo, db.as_ref())
     ) {
         Ok(())
     } else {
@@ -32,41 +69,370 @@ async fn cancel_timed_out_builds(repo: &RepositoryState, db: &PgDbClient) -> any
     let running_builds = db.get_running_builds(repo.repository()).await?;
     tracing::info!("Found {} running build(s)", running_builds.len());
 
-    let timeout = repo.config.load().timeout;
+    let config = repo.config.load_full();
     for build in running_builds {
-        if elapsed_time(build.created_at) >= timeout {
-            tracing::info!("Cancelling build {}", build.commit_sha);
+        // A failure for a single build should not stop the remaining builds from timing out
+        if let Err(error) = cancel_timed_out_build(repo, db, &build, &config).await {
+            tracing::error!(
+                "Could not check timeout of build {}: {error:?}",
+                build.commit_sha
+            );
+        }
+    }
+    Ok(())
+}
 
-            db.update_build_status(&build, BuildStatus::Cancelled)
+async fn cancel_timed_out_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    config: &RepositoryConfig,
+) -> anyhow::Result<()> {
+    let elapsed = elapsed_time(build.created_at);
+    if elapsed < config.timeout {
+        if fail_build_with_stale_external_ci(repo, db, build, config).await? {
+            return Ok(());
+        }
+        if !config.required_checks.is_empty() && elapsed >= config.required_checks_start_timeout {
+            fail_build_with_missing_required_checks(repo, db, build, &config.required_checks)
                 .await?;
-            if let Some(pr) = db.find_pr_by_build(&build).await? {
-                if let Err(error) = cancel_build_workflows(&repo.client, db, &build).await {
-                    tracing::error!(
-                        "Could not cancel workflows for SHA {}: {error:?}",
-                        build.commit_sha
-                    );
-                }
-
-                if let Err(error) = repo
-                    .client
-                    .post_comment(pr.number, Comment::new(":boom: Test timed out".to_string()))
-                    .await
-                {
-                    tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
-                }
-            } else {
-                tracing::warn!("No PR found for build {}", build.commit_sha);
+        }
+    } else {
+        tracing::info!("Build {} has timed out", build.commit_sha);
+
+        db.update_build_status(build, BuildStatus::Timeouted)
+            .await?;
+        if let Some(pr) = db.find_pr_by_build(build).await? {
+            notify_build_completed(repo, build, pr.number, BuildStatus::Timeouted);
+            notify_chat(
+                repo,
+                ChatEvent::BuildTimedOut {
+                    pr: pr.number,
+                    branch: &build.branch,
+                    elapsed,
+                },
+            );
+            complete_build_check_run(repo, db, build, BuildStatus::Timeouted).await;
+            report_build_completed(repo, build, pr.number, BuildStatus::Timeouted).await;
+            let pending_workflows = db
+                .get_workflows_for_build(build)
+                .await?
+                .into_iter()
+                .filter(|w| w.status == WorkflowStatus::Pending)
+                .collect::<Vec<_>>();
+            if let Err(error) = cancel_build_workflows(&repo.client, db, build).await {
+                tracing::error!(
+                    "Could not cancel workflows for SHA {}: {error:?}",
+                    build.commit_sha
+                );
+            }
+
+            if let Err(error) = handle_failed_build(repo, db, build, &pr).await {
+                tracing::error!("Could not modify labels of PR {}: {error:?}", pr.number);
+            }
+
+            if let Err(error) = post_status_comment(
+                repo,
+                db,
+                pr.number,
+                build_timed_out_comment(elapsed, &pending_workflows),
+            )
+            .await
+            {
+                tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
+            }
+        } else {
+            tracing::warn!("No PR found for build {}", build.commit_sha);
+        }
+    }
+    Ok(())
+}
+
+/// Fails the build if the only workflows it is still waiting for are external CI checks, which
+/// have been pending for longer than `external_ci_timeout`.
+/// Builds that also wait for GitHub workflows are left to the general build timeout.
+/// Returns true if the build was failed.
+async fn fail_build_with_stale_external_ci(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    config: &RepositoryConfig,
+) -> anyhow::Result<bool> {
+    let Some(timeout) = config.external_ci_timeout else {
+        return Ok(false);
+    };
+    let pending_workflows = db
+        .get_workflows_for_build(build)
+        .await?
+        .into_iter()
+        .filter(|w| w.status == WorkflowStatus::Pending)
+        .collect::<Vec<_>>();
+    if pending_workflows.is_empty()
+        || pending_workflows.iter().any(|w| {
+            w.workflow_type != WorkflowType::External || elapsed_time(w.created_at) < timeout
+        })
+    {
+        return Ok(false);
+    }
+
+    tracing::info!(
+        "Build {} has timed out waiting for external CI",
+        build.commit_sha
+    );
+    for workflow in &pending_workflows {
+        db.update_workflow_status(workflow.run_id.0, WorkflowStatus::Failure)
+            .await?;
+    }
+    db.update_build_status(build, BuildStatus::Failure).await?;
+    let Some(pr) = db.find_pr_by_build(build).await? else {
+        tracing::warn!("No PR found for build {}", build.commit_sha);
+        return Ok(true);
+    };
+    notify_build_completed(repo, build, pr.number, BuildStatus::Failure);
+    complete_build_check_run(repo, db, build, BuildStatus::Failure).await;
+    report_build_completed(repo, build, pr.number, BuildStatus::Failure).await;
+    if let Err(error) = handle_failed_build(repo, db, build, &pr).await {
+        tracing::error!("Could not modify labels of PR {}: {error:?}", pr.number);
+    }
+
+    if let Err(error) = post_status_comment(
+        repo,
+        db,
+        pr.number,
+        external_ci_timed_out_comment(timeout, &pending_workflows),
+    )
+    .await
+    {
+        tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
+    }
+    Ok(true)
+}
+
+/// Fails the build if some of the required checks have not been started yet.
+async fn fail_build_with_missing_required_checks(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    required_checks: &[String],
+) -> anyhow::Result<()> {
+    let workflows = db.get_workflows_for_build(build).await?;
+    let missing_checks = required_checks
+        .iter()
+        .filter(|check| !workflows.iter().any(|w| &&w.name == check))
+        .map(|check| check.as_str())
+        .collect::<Vec<_>>();
+    if missing_checks.is_empty() {
+        return Ok(());
+    }
+
+    tracing::info!(
+        "Required checks {missing_checks:?} of build {} were never started",
+        build.commit_sha
+    );
+    db.update_build_status(build, BuildStatus::Failure).await?;
+    let Some(pr) = db.find_pr_by_build(build).await? else {
+        tracing::warn!("No PR found for build {}", build.commit_sha);
+        return Ok(());
+    };
+    notify_build_completed(repo, build, pr.number, BuildStatus::Failure);
+    complete_build_check_run(repo, db, build, BuildStatus::Failure).await;
+    report_build_completed(repo, build, pr.number, BuildStatus::Failure).await;
+    if let Err(error) = cancel_build_workflows(&repo.client, db, build).await {
+        tracing::error!(
+            "Could not cancel workflows for SHA {}: {error:?}",
+            build.commit_sha
+        );
+    }
+    if let Err(error) = handle_failed_build(repo, db, build, &pr).await {
+        tracing::error!("Could not modify labels of PR {}: {error:?}", pr.number);
+    }
+
+    if let Err(error) = post_status_comment(
+        repo,
+        db,
+        pr.number,
+        required_checks_not_started_comment(&missing_checks),
+    )
+    .await
+    {
+        tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
+    }
+    Ok(())
+}
+
+/// Updates the PR of a build that has failed or timed out. A PR whose merge build has failed is
+/// unapproved, which removes it from the merge queue.
+async fn handle_failed_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    build: &BuildModel,
+    pr: &PullRequestModel,
+) -> anyhow::Result<()> {
+    if build.branch == AUTO_BRANCH_NAME {
+        merge_build_failed(repo, db, pr).await
+    } else {
+        handle_label_trigger(repo, pr.number, LabelTrigger::TryBuildFailed).await
+    }
+}
+
+/// Deletes branches of finished builds once the configured amount of time has passed since the
+/// builds have finished.
+async fn clean_stale_branches(repo: &RepositoryState, db: &PgDbClient) -> anyhow::Result<()> {
+    let Some(clean_after) = repo.config.load().clean_branches_after else {
+        return Ok(());
+    };
+    let builds = db
+        .get_latest_build_per_branch(repo.repository())
+        .await?
+        .into_iter()
+        .filter(|build| {
+            !build.branches_deleted
+                && build
+                    .completed_at
+                    .is_some_and(|completed_at| elapsed_time(completed_at) >= clean_after)
+        })
+        .collect::<Vec<_>>();
+    let cleaned = delete_build_branches(repo, db, &builds)
+        .await
+        .map_err(|error| {
+            tracing::error!("Could not clean branches: {error:?}");
+            error
+        })?;
+    if !cleaned.deleted.is_empty() {
+        tracing::info!("Deleted stale branches {:?}", cleaned.deleted);
+    }
+    Ok(())
+}
+
+/// Re-checks the mergeable state of pull requests whose state is unknown, e.g. because their
+/// base branch has moved. At most `mergeable_refresh_limit` PRs are checked per refresh.
+/// Approved PRs that turned out to have conflicts are notified with a comment.
+async fn refresh_unknown_mergeable_states(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+) -> anyhow::Result<()> {
+    let limit = repo.config.load().mergeable_refresh_limit;
+    if limit == 0 {
+        return Ok(());
+    }
+    let prs = db
+        .get_prs_with_unknown_mergeable_state(repo.repository(), limit)
+        .await?;
+    for pr in prs {
+        // PRs whose state stays unknown make room for the other PRs in the next refresh
+        db.mark_mergeable_state_checked(&pr).await?;
+        let gh_pr = match repo
+            .client
+            .get_pull_request_with_mergeable_state(pr.number)
+            .await
+        {
+            Ok(gh_pr) => gh_pr,
+            Err(error) => {
+                tracing::warn!(
+                    "Could not resolve mergeable state of PR {}: {error:?}",
+                    pr.number
+                );
+                continue;
             }
+        };
+        let mergeable_state = MergeableState::from(gh_pr.mergeable_state);
+        if mergeable_state == MergeableState::Unknown {
+            continue;
+        }
+
+        let has_conflicts = mergeable_state == MergeableState::HasConflicts;
+        tracing::info!(
+            "Mergeable state of PR {} resolved to {mergeable_state:?}",
+            pr.number
+        );
+        db.set_mergeable_state(&pr, mergeable_state).await?;
+        if has_conflicts && pr.has_approval() {
+            let comment = merge_conflict_comment(&gh_pr.head.name);
+            if let Err(error) = repo.client.post_comment(pr.number, comment).await {
+                tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unapproves PRs whose approval is older than the configured approval expiry.
+async fn expire_stale_approvals(repo: &RepositoryState, db: &PgDbClient) -> anyhow::Result<()> {
+    let Some(days) = repo.config.load().approval_expiry_days else {
+        return Ok(());
+    };
+    let approved_before = now() - chrono::Duration::days(days as i64);
+    let prs = db
+        .get_prs_approved_before(repo.repository(), approved_before)
+        .await?;
+    for pr in prs {
+        // A failure for a single PR should not stop the remaining approvals from expiring
+        if let Err(error) = expire_approval(repo, db, &pr, days).await {
+            tracing::error!("Could not expire approval of PR {}: {error:?}", pr.number);
+        }
+    }
+    Ok(())
+}
+
+async fn expire_approval(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr: &PullRequestModel,
+    days: u32,
+) -> anyhow::Result<()> {
+    let Some(sha) = pr.approved_sha().map(|sha| sha.to_string()) else {
+        return Ok(());
+    };
+    tracing::info!("Approval of PR {} has expired", pr.number);
+    db.unapprove(pr).await?;
+    handle_label_trigger(repo, pr.number, LabelTrigger::Unapproved).await?;
+    post_status_comment(repo, db, pr.number, approval_expired_comment(&sha, days)).await?;
+    Ok(())
+}
+
+async fn expire_stale_delegations(repo: &RepositoryState, db: &PgDbClient) -> anyhow::Result<()> {
+    let Some(days) = repo.config.load().delegation_expiry_days else {
+        return Ok(());
+    };
+    let delegated_before = now() - chrono::Duration::days(days as i64);
+    let delegations = db.get_delegations(repo.repository()).await?;
+    for delegation in delegations
+        .iter()
+        .filter(|delegation| delegation.delegated_at < delegated_before)
+    {
+        if let Err(error) = expire_delegation(repo, db, delegation, days).await {
+            tracing::error!(
+                "Could not expire delegation of PR {}: {error:?}",
+                delegation.pr_number
+            );
         }
     }
     Ok(())
 }
 
+async fn expire_delegation(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    delegation: &DelegationModel,
+    days: u32,
+) -> anyhow::Result<()> {
+    tracing::info!("Delegation of PR {} has expired", delegation.pr_number);
+    db.remove_delegation(delegation).await?;
+    post_status_comment(
+        repo,
+        db,
+        delegation.pr_number,
+        delegation_expired_comment(delegation.permission, days),
+    )
+    .await?;
+    Ok(())
+}
+
 async fn reload_permission(
     repo: &RepositoryState,
-    team_api_client: &TeamApiClient,
+    permission_source: &PermissionSource,
 ) -> anyhow::Result<()> {
-    let permissions = team_api_client
+    let permissions = permission_source
         .load_permissions(repo.repository())
         .await
         .with_context(|| {
@@ -79,10 +445,62 @@ async fn reload_permission(
     Ok(())
 }
 
-async fn reload_config(repo: &RepositoryState) -> anyhow::Result<()> {
-    let config = repo.client.load_config().await?;
-    repo.config.store(Arc::new(config));
-    Ok(())
+async fn reload_config(repo: &RepositoryState, db: &PgDbClient) -> anyhow::Result<()> {
+    let (config, content) = repo.client.load_config().await?;
+    replace_config(repo, db, config, &content).await
+}
+
+/// Atomically replaces the configuration of the repository.
+/// If the configuration has changed, its contents are also stored in the database, so that it
+/// can be restored after a restart.
+/// Returns the previous configuration.
+pub(super) async fn replace_config(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    config: RepositoryConfig,
+    content: &str,
+) -> anyhow::Result<Arc<RepositoryConfig>> {
+    let new_hash = config.source_hash.clone();
+    let old_config = repo.config.swap(Arc::new(config));
+    if old_config.source_hash != new_hash {
+        // Cached lookups might depend on the teams referenced by the old configuration
+        repo.permission_cache.invalidate();
+        tracing::info!(
+            "Configuration of {} changed: {} -> {new_hash}",
+            repo.repository(),
+            old_config.source_hash
+        );
+        db.set_repository_config(repo.repository(), content, &new_hash)
+            .await?;
+    }
+    Ok(old_config)
+}
+
+/// Reloads both the configuration and the permissions of the repository.
+/// The repository state is only updated if both of them were loaded successfully.
+pub async fn reload_repository(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    permission_source: &PermissionSource,
+) -> anyhow::Result<RepositoryReloadSummary> {
+    let ((config, content), permissions) = tokio::try_join!(
+        repo.client.load_config(),
+        permission_source.load_permissions(repo.repository())
+    )?;
+
+    let config_hash_after = config.source_hash.clone();
+    let reviewer_count_after = permissions.user_count(PermissionType::Review);
+    let old_config = replace_config(repo, db, config, &content).await?;
+    let old_permissions = repo.permissions.swap(Arc::new(permissions));
+    // An explicit reload should make permission changes on GitHub visible right away
+    repo.permission_cache.invalidate();
+
+    Ok(RepositoryReloadSummary {
+        config_hash_before: old_config.source_hash.clone(),
+        config_hash_after,
+        reviewer_count_before: old_permissions.user_count(PermissionType::Review),
+        reviewer_count_after,
+    })
 }
 
 #[cfg(not(test))]
@@ -100,7 +518,7 @@ fn now() -> DateTime<Utc> {
     MOCK_TIME.with(|time| time.borrow_mut().unwrap_or_else(Utc::now))
 }
 
-fn elapsed_time(date: DateTime<Utc>) -> Duration {
+pub(super) fn elapsed_time(date: DateTime<Utc>) -> Duration {
     let time: DateTime<Utc> = now();
     (time - date).to_std().unwrap_or(Duration::ZERO)
 }
@@ -109,10 +527,14 @@ fn elapsed_time(date: DateTime<Utc>) -> Duration {
 mod tests {
     use crate::bors::handlers::WAIT_FOR_WORKFLOW_STARTED;
     use crate::bors::handlers::refresh::MOCK_TIME;
+    use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, TRY_MERGE_BRANCH_NAME};
+    use crate::database::{BuildStatus, DelegatedPermission, MergeableState, WorkflowStatus};
     use crate::tests::mocks::{
-        BorsBuilder, GitHubState, WorkflowEvent, default_repo_name, run_test,
+        BorsBuilder, BorsTester, GitHubState, Workflow, WorkflowEvent, default_branch_name,
+        default_pr_number, default_repo_name, run_test,
     };
     use chrono::Utc;
+    use octocrab::models::pulls::MergeableState as OctocrabMergeableState;
     use std::future::Future;
     use std::time::Duration;
     use tokio::runtime::RuntimeFlavor;
@@ -126,6 +548,176 @@ mod tests {
         .await;
     }
 
+    /// Moves the base branch of the default PR, which makes its mergeable state unknown.
+    async fn move_base_branch(tester: &mut BorsTester) -> anyhow::Result<()> {
+        tester
+            .push_to_branch(default_branch_name(), &["README.md"])
+            .await?;
+        tester
+            .wait_for(|| async {
+                let Some(pr) = tester.default_pr_db().await? else {
+                    return Ok(false);
+                };
+                Ok(pr.mergeable_state == MergeableState::Unknown)
+            })
+            .await
+    }
+
+    fn set_gh_mergeable_state(tester: &BorsTester, state: OctocrabMergeableState) {
+        tester
+            .default_repo()
+            .lock()
+            .get_pr_mut(default_pr_number())
+            .mergeable_state = state;
+    }
+
+    fn gh_state_with_approval_expiry() -> GitHubState {
+        GitHubState::default().with_default_config("approval_expiry_days = 3")
+    }
+
+    #[sqlx::test]
+    async fn refresh_expires_old_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approval_expiry())
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                with_mocked_time(Duration::from_secs(4 * 24 * 3600), async {
+                    tester.refresh().await;
+                })
+                .await;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: The approval of commit pr-1-sha has expired after 3 day(s), the PR has to be reviewed again."
+                );
+                assert!(!tester.default_pr_db().await?.unwrap().has_approval());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_keeps_recent_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approval_expiry())
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                with_mocked_time(Duration::from_secs(2 * 24 * 3600), async {
+                    tester.refresh().await;
+                })
+                .await;
+                assert!(tester.default_pr_db().await?.unwrap().has_approval());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    fn gh_state_with_delegation_expiry() -> GitHubState {
+        GitHubState::default().with_default_config("delegation_expiry_days = 7")
+    }
+
+    #[sqlx::test]
+    async fn refresh_expires_old_delegation(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_delegation_expiry())
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors delegate=try").await?;
+                tester.expect_comments(1).await;
+                with_mocked_time(Duration::from_secs(8 * 24 * 3600), async {
+                    tester.refresh().await;
+                })
+                .await;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: The delegation of the `try` permission to the PR author has expired after 7 day(s)."
+                );
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().delegated_permission,
+                    None
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_keeps_recent_delegation(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_delegation_expiry())
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors delegate=try").await?;
+                tester.expect_comments(1).await;
+                with_mocked_time(Duration::from_secs(6 * 24 * 3600), async {
+                    tester.refresh().await;
+                })
+                .await;
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().delegated_permission,
+                    Some(DelegatedPermission::Try)
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_resolves_unknown_mergeable_state(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async move {
+            tester.post_comment("@bors info").await?;
+            tester.expect_comments(1).await;
+            move_base_branch(&mut tester).await?;
+            tester.refresh().await;
+            assert_eq!(
+                tester.default_pr_db().await?.unwrap().mergeable_state,
+                MergeableState::Mergeable
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_notifies_approved_pr_with_conflicts(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async move {
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+            set_gh_mergeable_state(&tester, OctocrabMergeableState::Dirty);
+            move_base_branch(&mut tester).await?;
+            tester.refresh().await;
+            assert!(
+                tester
+                    .get_comment()
+                    .await?
+                    .starts_with(":lock: Merge conflict")
+            );
+            assert_eq!(
+                tester.default_pr_db().await?.unwrap().mergeable_state,
+                MergeableState::HasConflicts
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_mergeable_state_disabled(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("mergeable_refresh_limit = 0"))
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors info").await?;
+                tester.expect_comments(1).await;
+                move_base_branch(&mut tester).await?;
+                tester.refresh().await;
+                assert_eq!(
+                    tester.default_pr_db().await?.unwrap().mergeable_state,
+                    MergeableState::Unknown
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     fn gh_state_with_long_timeout() -> GitHubState {
         GitHubState::default().with_default_config(
             r#"
@@ -169,7 +761,17 @@ timeout = 3600
                     tester.refresh().await;
                 })
                 .await;
-                insta::assert_snapshot!(tester.get_comment().await?, @":boom: Test timed out");
+                insta::assert_snapshot!(tester.get_comment().await?, @":boom: Test timed out after 1h 6m 40s");
+                assert_eq!(
+                    tester
+                        .default_pr_db()
+                        .await?
+                        .unwrap()
+                        .try_build
+                        .unwrap()
+                        .status,
+                    BuildStatus::Timeouted
+                );
                 assert_eq!(
                     tester
                         .db()
@@ -200,13 +802,251 @@ timeout = 3600
                     tester.refresh().await;
                 })
                 .await;
-                tester.expect_comments(1).await;
+                insta::assert_snapshot!(tester.get_comment().await?, @r"
+                :boom: Test timed out after 1h 6m 40s
+                Pending workflows:
+                - [Workflow1](https://github.com/workflows/Workflow1/1)
+                ");
                 Ok(tester)
             })
             .await;
         gh.check_cancelled_workflows(default_repo_name(), &[1]);
     }
 
+    #[sqlx::test]
+    async fn refresh_fail_build_with_missing_required_check(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+timeout = 3600
+required_checks = ["CI", "Docs"]
+required_checks_start_timeout = 600
+"#,
+            ))
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .workflow_event(WorkflowEvent::started(
+                        Workflow::from(tester.try_branch()).with_name("CI"),
+                    ))
+                    .await?;
+                WAIT_FOR_WORKFLOW_STARTED.sync().await;
+
+                with_mocked_time(Duration::from_secs(700), async {
+                    tester.refresh().await;
+                })
+                .await;
+                insta::assert_snapshot!(tester.get_comment().await?, @":broken_heart: Test failed, required check(s) never started: `Docs`");
+                assert_eq!(
+                    tester
+                        .default_pr_db()
+                        .await?
+                        .unwrap()
+                        .try_build
+                        .unwrap()
+                        .status,
+                    BuildStatus::Failure
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    fn gh_state_with_external_ci_timeout() -> GitHubState {
+        GitHubState::default().with_default_config(
+            r#"
+timeout = 3600
+external_ci_timeout = 600
+"#,
+        )
+    }
+
+    #[sqlx::test]
+    async fn refresh_fail_build_with_stale_external_ci(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_external_ci_timeout())
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .check_run_created(Workflow::from(tester.try_branch()).make_external())
+                    .await?;
+                WAIT_FOR_WORKFLOW_STARTED.sync().await;
+
+                with_mocked_time(Duration::from_secs(700), async {
+                    tester.refresh().await;
+                })
+                .await;
+                insta::assert_snapshot!(tester.get_comment().await?, @r"
+                :boom: Test timed out after 10m 0s waiting for external CI
+                - [Workflow1](https://external-ci.com/workflows/1): timed out waiting for external CI
+                ");
+                let build = tester.default_pr_db().await?.unwrap().try_build.unwrap();
+                assert_eq!(build.status, BuildStatus::Failure);
+                let workflows = tester.db().get_workflows_for_build(&build).await?;
+                assert_eq!(workflows[0].status, WorkflowStatus::Failure);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_external_ci_timeout_ignores_pending_github_workflows(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_external_ci_timeout())
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .check_run_created(Workflow::from(tester.try_branch()).make_external())
+                    .await?;
+                WAIT_FOR_WORKFLOW_STARTED.sync().await;
+                tester
+                    .workflow_event(WorkflowEvent::started(
+                        Workflow::from(tester.try_branch()).with_run_id(2),
+                    ))
+                    .await?;
+                WAIT_FOR_WORKFLOW_STARTED.sync().await;
+
+                with_mocked_time(Duration::from_secs(700), async {
+                    tester.refresh().await;
+                })
+                .await;
+                assert_eq!(
+                    tester
+                        .default_pr_db()
+                        .await?
+                        .unwrap()
+                        .try_build
+                        .unwrap()
+                        .status,
+                    BuildStatus::Pending
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_timeout_modify_labels(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+timeout = 3600
+
+[labels]
+try = ["+testing"]
+try_failed = ["-testing", "+failed"]
+"#,
+            ))
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                with_mocked_time(Duration::from_secs(4000), async {
+                    tester.refresh().await;
+                })
+                .await;
+                tester.expect_comments(1).await;
+                let pr = tester
+                    .default_repo()
+                    .lock()
+                    .get_pr(default_pr_number())
+                    .clone();
+                pr.check_added_labels(&["testing", "failed"]);
+                pr.check_removed_labels(&["testing"]);
+                assert_eq!(pr.labels, vec!["failed"]);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_clean_stale_branches(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+timeout = 3600
+clean_branches_after = 600
+"#,
+            ))
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+
+                // Not enough time has passed yet
+                tester.refresh().await;
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_some()
+                );
+
+                with_mocked_time(Duration::from_secs(700), async {
+                    tester.refresh().await;
+                })
+                .await;
+                let mut repo = tester.default_repo().lock().clone();
+                assert!(repo.get_branch_by_name(TRY_BRANCH_NAME).is_none());
+                assert!(repo.get_branch_by_name(TRY_MERGE_BRANCH_NAME).is_none());
+
+                // The deleted branches are remembered, so they are not deleted again
+                tester.create_branch(TRY_BRANCH_NAME).reset_suites();
+                with_mocked_time(Duration::from_secs(800), async {
+                    tester.refresh().await;
+                })
+                .await;
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_some()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn refresh_clean_branches_after_build_completion(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool.clone())
+            .github(GitHubState::default().with_default_config(
+                r#"
+timeout = 7200
+clean_branches_after = 600
+"#,
+            ))
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                // The build has been running for an hour
+                sqlx::query("UPDATE build SET created_at = NOW() - INTERVAL '1 hour'")
+                    .execute(&pool)
+                    .await?;
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+
+                with_mocked_time(Duration::from_secs(300), async {
+                    tester.refresh().await;
+                })
+                .await;
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_branch_by_name(TRY_BRANCH_NAME)
+                        .is_some()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     async fn with_mocked_time<Fut: Future<Output = ()>>(in_future: Duration, future: Fut) {
         // It is important to use this function only with a single threaded runtime,
         // otherwise the `MOCK_TIME` variable might get mixed up between different threads.
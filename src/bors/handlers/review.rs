@@ -1,22 +1,38 @@
 use std::sync::Arc;
 
 use crate::PgDbClient;
+use crate::bors::BorsError;
+use crate::bors::BorsResult;
 use crate::bors::Comment;
 use crate::bors::RepositoryState;
 use crate::bors::command::Approver;
 use crate::bors::command::RollupMode;
+use crate::bors::comment::{delegations_comment, repository_paused_command_comment};
+use crate::bors::event::ReviewVerdict;
+use crate::bors::handlers::acknowledge_command;
+use crate::bors::handlers::auto_build::process_merge_queue;
+use crate::bors::handlers::chat::{ChatEvent, notify_chat};
 use crate::bors::handlers::deny_request;
 use crate::bors::handlers::has_permission;
 use crate::bors::handlers::labels::handle_label_trigger;
+use crate::bors::handlers::status_comment::post_status_comment_best_effort;
+use crate::bors::template::MessageKind;
 use crate::database::ApprovalInfo;
+use crate::database::BuildStatus;
+use crate::database::DbError;
+use crate::database::DelegatedPermission;
 use crate::database::TreeState;
+use crate::github::CommitSha;
 use crate::github::GithubUser;
 use crate::github::LabelTrigger;
 use crate::github::PullRequest;
 use crate::permissions::PermissionType;
+use octocrab::models::CommentId;
 
 /// Approve a pull request.
 /// A pull request can only be approved by a user of sufficient authority.
+/// If the repository requires several approvals, the approval of the reviewer is recorded and
+/// the pull request is approved once enough distinct reviewers have approved its head commit.
 pub(super) async fn command_approve(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
@@ -25,20 +41,30 @@ pub(super) async fn command_approve(
     approver: &Approver,
     priority: Option<u32>,
     rollup: Option<RollupMode>,
-) -> anyhow::Result<()> {
+    force: bool,
+) -> BorsResult<()> {
     tracing::info!("Approving PR {}", pr.number);
     if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "approve pull requests",
+        )
+        .await);
     };
+    if force && !repo_state.is_admin(author).await? {
+        return Err(deny_force_request(author));
+    }
+    if force && repo_state.config.load().required_checks.is_empty() {
+        return Err(BorsError::user_facing(
+            ":warning: An approval cannot be forced without `required_checks` in the configuration: every check is required.".to_string(),
+        ));
+    }
     let approver = match approver {
         Approver::Myself => author.username.clone(),
         Approver::Specified(approver) => approver.clone(),
     };
-    let approval_info = ApprovalInfo {
-        approver: approver.clone(),
-        sha: pr.head.sha.to_string(),
-    };
     let pr_model = db
         .get_or_create_pull_request(
             repo_state.repository(),
@@ -46,27 +72,92 @@ pub(super) async fn command_approve(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
+    // The merge queue only tests PRs that are approved at their known head commit
+    db.update_pr_details(&pr_model, &pr.title, &pr.author.username, &pr.head.sha)
+        .await?;
 
-    db.approve(&pr_model, approval_info, priority, rollup)
+    let required_approvals = repo_state.config.load().required_approvals.max(1) as usize;
+    let head_sha = pr.head.sha.to_string();
+    let base_sha = pr.base.sha.to_string();
+    let reviewer = approver.clone();
+    // The approval and the forcing of the pending build have to be applied together
+    let approvers = db
+        .transaction(|tx| {
+            Box::pin(async move {
+                // A repeated approval of the same reviewer replaces their previous one
+                let mut approvers = tx.get_approvers(&pr_model, &head_sha).await?;
+                approvers.retain(|approver| approver != &reviewer);
+                approvers.push(reviewer.clone());
+                if approvers.len() < required_approvals {
+                    tx.add_approval(&pr_model, &reviewer, &head_sha).await?;
+                    return Ok::<_, DbError>(approvers);
+                }
+                let approval_info = ApprovalInfo {
+                    approver: reviewer,
+                    sha: head_sha,
+                    base_sha: Some(base_sha),
+                };
+                tx.approve(&pr_model, approval_info, priority, rollup)
+                    .await?;
+                tx.set_approval_force(&pr_model, force).await?;
+                if force {
+                    if let Some(build) = pr_model
+                        .try_build
+                        .as_ref()
+                        .filter(|build| build.status == BuildStatus::Pending)
+                    {
+                        tracing::info!("Forcing build {}", build.commit_sha);
+                        tx.set_build_force(build, true).await?;
+                    }
+                }
+                Ok(approvers)
+            })
+        })
         .await?;
+    if approvers.len() < required_approvals {
+        tracing::info!(
+            "PR {} has {}/{required_approvals} approvals",
+            pr.number,
+            approvers.len()
+        );
+        let text = format!(
+            ":white_check_mark: Approval of `{approver}` recorded ({}/{required_approvals} approvals).",
+            approvers.len()
+        );
+        post_status_comment_best_effort(&repo_state, &db, pr.number, Comment::new(text)).await;
+        return Ok(());
+    }
+    notify_of_approval(&repo_state, &db, pr, &approvers.join(", "), force).await;
     handle_label_trigger(&repo_state, pr.number, LabelTrigger::Approved).await?;
-    notify_of_approval(&repo_state, pr, approver.as_str()).await
+    if let Err(error) = process_merge_queue(&repo_state, &db).await {
+        tracing::error!("Could not process the merge queue: {error:?}");
+    }
+    Ok(())
 }
 
 /// Unapprove a pull request.
 /// Pull request's author can also unapprove the pull request.
+/// Only the approval of the user who unapproves the pull request is withdrawn, the pull request
+/// stays approved if it still has enough approvals. A user without an approval of their own
+/// unapproves the pull request, but the approvals of the reviewers are kept.
 pub(super) async fn command_unapprove(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     pr: &PullRequest,
     author: &GithubUser,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     tracing::info!("Unapproving PR {}", pr.number);
     if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "unapprove pull requests",
+        )
+        .await);
     };
     let pr_model = db
         .get_or_create_pull_request(
@@ -75,12 +166,114 @@ pub(super) async fn command_unapprove(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
-    db.unapprove(&pr_model).await?;
-    handle_label_trigger(&repo_state, pr.number, LabelTrigger::Unapproved).await?;
-    notify_of_unapproval(&repo_state, pr).await
+    let required_approvals = repo_state.config.load().required_approvals.max(1) as usize;
+    let head_sha = pr.head.sha.to_string();
+    let reviewer = author.username.clone();
+    // Returns the number of remaining approvals, or `None` if the PR was unapproved
+    let remaining = db
+        .transaction(|tx| {
+            Box::pin(async move {
+                if !tx.remove_approval(&pr_model, &reviewer).await? {
+                    tx.unapprove(&pr_model).await?;
+                    return Ok::<_, DbError>(None);
+                }
+                let approvers = tx.get_approvers(&pr_model, &head_sha).await?;
+                if pr_model.has_approval() && approvers.len() < required_approvals {
+                    tx.unapprove(&pr_model).await?;
+                    return Ok(None);
+                }
+                Ok(Some(approvers.len()))
+            })
+        })
+        .await?;
+    let Some(remaining) = remaining else {
+        notify_of_unapproval(&repo_state, &db, pr).await;
+        handle_label_trigger(&repo_state, pr.number, LabelTrigger::Unapproved).await?;
+        return Ok(());
+    };
+    let text = format!(
+        "Approval of `{}` has been withdrawn ({remaining}/{required_approvals} approvals).",
+        author.username
+    );
+    post_status_comment_best_effort(&repo_state, &db, pr.number, Comment::new(text)).await;
+    Ok(())
+}
+
+/// Applies a GitHub review as if its author has posted `r+` (for an approving review) or `r-`
+/// (for a review that requests changes), if the repository has enabled `approve_on_review`.
+/// Reviews of users without the review permission and reviews of an outdated commit are ignored
+/// without a comment, as the reviewer might not expect bors to react to them.
+pub(super) async fn handle_pull_request_reviewed(
+    repo_state: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    pr: &PullRequest,
+    author: &GithubUser,
+    verdict: ReviewVerdict,
+    commit_sha: Option<&CommitSha>,
+) -> BorsResult<()> {
+    if !repo_state.config.load().approve_on_review {
+        return Ok(());
+    }
+    if let Some(sha) = commit_sha.filter(|sha| **sha != pr.head.sha) {
+        tracing::info!("Ignoring review of outdated commit {sha}");
+        return Ok(());
+    }
+    if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
+        tracing::info!(
+            "Ignoring review of {}, who does not have the review permission",
+            author.username
+        );
+        return Ok(());
+    }
+    if repo_state.is_paused() {
+        tracing::info!(
+            "Review of {} ignored, the repository is paused",
+            author.username
+        );
+        repo_state
+            .client
+            .post_comment(pr.number, repository_paused_command_comment())
+            .await?;
+        return Ok(());
+    }
+
+    match verdict {
+        ReviewVerdict::Approved => {
+            command_approve(
+                repo_state,
+                db,
+                pr,
+                author,
+                &Approver::Myself,
+                None,
+                None,
+                false,
+            )
+            .await
+        }
+        ReviewVerdict::ChangesRequested => {
+            // Do not report an unapproval of a PR that has not been approved at all
+            let Some(pr_model) = db
+                .get_pull_request(repo_state.repository(), pr.number)
+                .await?
+            else {
+                return Ok(());
+            };
+            if !pr_model.has_approval()
+                && db
+                    .get_approvers(&pr_model, &pr.head.sha.to_string())
+                    .await?
+                    .is_empty()
+            {
+                return Ok(());
+            }
+            command_unapprove(repo_state, db, pr, author).await
+        }
+    }
 }
 
 /// Set the priority of a pull request.
@@ -91,10 +284,16 @@ pub(super) async fn command_set_priority(
     pr: &PullRequest,
     author: &GithubUser,
     priority: u32,
-) -> anyhow::Result<()> {
+    comment_id: Option<CommentId>,
+) -> BorsResult<()> {
     if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "set the priority of pull requests",
+        )
+        .await);
     };
     let pr_model = db
         .get_or_create_pull_request(
@@ -103,23 +302,38 @@ pub(super) async fn command_set_priority(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
-    db.set_priority(&pr_model, priority).await
+    db.set_priority(&pr_model, priority).await?;
+    acknowledge_command(&repo_state, comment_id).await;
+
+    // The priority changes the order of the merge queue (and can let the PR pass a closed tree),
+    // so re-evaluate it right away
+    if let Err(error) = process_merge_queue(&repo_state, &db).await {
+        tracing::error!("Could not process the merge queue: {error:?}");
+    }
+    Ok(())
 }
 
-/// Delegate approval authority of a pull request to its author.
+/// Delegate approval or try authority of a pull request to its author.
 pub(super) async fn command_delegate(
     repo_state: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     pr: &PullRequest,
     author: &GithubUser,
-) -> anyhow::Result<()> {
-    tracing::info!("Delegating PR {} approval", pr.number);
-    if !sufficient_delegate_permission(repo_state.clone(), author) {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+    permission: DelegatedPermission,
+) -> BorsResult<()> {
+    tracing::info!("Delegating PR {} {permission:?} permission", pr.number);
+    if !sufficient_delegate_permission(repo_state.clone(), author).await {
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "delegate approvals",
+        )
+        .await);
     }
 
     let pr_model = db
@@ -129,11 +343,13 @@ pub(super) async fn command_delegate(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
-    db.delegate(&pr_model).await?;
-    notify_of_delegation(&repo_state, pr, &pr.author.username).await
+    db.delegate(&pr_model, permission, &author.username).await?;
+    notify_of_delegation(&repo_state, pr, &pr.author.username, permission).await;
+    Ok(())
 }
 
 /// Revoke any previously granted delegation.
@@ -142,11 +358,17 @@ pub(super) async fn command_undelegate(
     db: Arc<PgDbClient>,
     pr: &PullRequest,
     author: &GithubUser,
-) -> anyhow::Result<()> {
+    comment_id: Option<CommentId>,
+) -> BorsResult<()> {
     tracing::info!("Undelegating PR {} approval", pr.number);
     if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "remove delegations",
+        )
+        .await);
     }
     let pr_model = db
         .get_or_create_pull_request(
@@ -155,10 +377,40 @@ pub(super) async fn command_undelegate(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
-    db.undelegate(&pr_model).await
+    db.undelegate(&pr_model).await?;
+    acknowledge_command(&repo_state, comment_id).await;
+    Ok(())
+}
+
+/// Lists the delegations of the open pull requests of the repository, including who has
+/// delegated them and when they expire.
+/// The delegations can only be listed by a user of sufficient authority.
+pub(super) async fn command_list_delegations(
+    repo_state: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    pr: &PullRequest,
+    author: &GithubUser,
+) -> BorsResult<()> {
+    if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "list delegations",
+        )
+        .await);
+    }
+    let delegations = db.get_delegations(repo_state.repository()).await?;
+    let expiry_days = repo_state.config.load().delegation_expiry_days;
+    repo_state
+        .client
+        .post_comment(pr.number, delegations_comment(&delegations, expiry_days))
+        .await?;
+    Ok(())
 }
 
 /// Set the rollup of a pull request.
@@ -169,10 +421,52 @@ pub(super) async fn command_set_rollup(
     pr: &PullRequest,
     author: &GithubUser,
     rollup: RollupMode,
-) -> anyhow::Result<()> {
+    comment_id: Option<CommentId>,
+) -> BorsResult<()> {
     if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "set the rollup mode of pull requests",
+        )
+        .await);
+    }
+    let pr_model = db
+        .get_or_create_pull_request(
+            repo_state.repository(),
+            pr.number,
+            &pr.base.name,
+            pr.mergeable_state.clone().into(),
+            &pr.status,
+            &pr.author.username,
+        )
+        .await?;
+
+    db.set_rollup(&pr_model, rollup).await?;
+    acknowledge_command(&repo_state, comment_id).await;
+    Ok(())
+}
+
+/// Set whether a pull request is squashed when it is landed, regardless of the merge style of
+/// the repository.
+/// It can only be set by a user of sufficient authority.
+pub(super) async fn command_set_squash(
+    repo_state: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    pr: &PullRequest,
+    author: &GithubUser,
+    squash: bool,
+    comment_id: Option<CommentId>,
+) -> BorsResult<()> {
+    if !has_permission(&repo_state, author, pr, &db, PermissionType::Review).await? {
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "set the merge style of pull requests",
+        )
+        .await);
     }
     let pr_model = db
         .get_or_create_pull_request(
@@ -181,10 +475,13 @@ pub(super) async fn command_set_rollup(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
-    db.set_rollup(&pr_model, rollup).await
+    db.set_squash(&pr_model, squash).await?;
+    acknowledge_command(&repo_state, comment_id).await;
+    Ok(())
 }
 
 pub(super) async fn command_close_tree(
@@ -194,10 +491,15 @@ pub(super) async fn command_close_tree(
     author: &GithubUser,
     priority: u32,
     comment_url: &str,
-) -> anyhow::Result<()> {
-    if !sufficient_approve_permission(repo_state.clone(), author) {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+) -> BorsResult<()> {
+    if !sufficient_approve_permission(repo_state.clone(), author).await {
+        return Err(deny_request(
+            &repo_state,
+            author,
+            PermissionType::Review,
+            "close the tree",
+        )
+        .await);
     };
     db.upsert_repository(
         repo_state.repository(),
@@ -207,7 +509,16 @@ pub(super) async fn command_close_tree(
         },
     )
     .await?;
-    notify_of_tree_closed(&repo_state, pr, priority).await
+    notify_chat(
+        &repo_state,
+        ChatEvent::TreeClosed {
+            priority,
+            author: &author.username,
+            source: comment_url,
+        },
+    );
+    notify_of_tree_closed(&repo_state, pr, priority).await;
+    Ok(())
 }
 
 pub(super) async fn command_open_tree(
@@ -215,36 +526,42 @@ pub(super) async fn command_open_tree(
     db: Arc<PgDbClient>,
     pr: &PullRequest,
     author: &GithubUser,
-) -> anyhow::Result<()> {
-    if !sufficient_delegate_permission(repo_state.clone(), author) {
-        deny_request(&repo_state, pr, author, PermissionType::Review).await?;
-        return Ok(());
+) -> BorsResult<()> {
+    if !sufficient_delegate_permission(repo_state.clone(), author).await {
+        return Err(
+            deny_request(&repo_state, author, PermissionType::Review, "open the tree").await,
+        );
     }
 
     db.upsert_repository(repo_state.repository(), TreeState::Open)
         .await?;
-    notify_of_tree_open(&repo_state, pr).await
+    notify_chat(
+        &repo_state,
+        ChatEvent::TreeOpened {
+            author: &author.username,
+        },
+    );
+    notify_of_tree_open(&repo_state, pr).await;
+    // PRs below the priority of the tree closure can be tested again
+    if let Err(error) = process_merge_queue(&repo_state, &db).await {
+        tracing::error!("Could not process the merge queue: {error:?}");
+    }
+    Ok(())
 }
 
-fn sufficient_approve_permission(repo: Arc<RepositoryState>, author: &GithubUser) -> bool {
-    repo.permissions
-        .load()
-        .has_permission(author.id, PermissionType::Review)
+async fn sufficient_approve_permission(repo: Arc<RepositoryState>, author: &GithubUser) -> bool {
+    repo.user_has_permission(author, PermissionType::Review)
+        .await
 }
 
-fn sufficient_delegate_permission(repo: Arc<RepositoryState>, author: &GithubUser) -> bool {
-    repo.permissions
-        .load()
-        .has_permission(author.id, PermissionType::Review)
+async fn sufficient_delegate_permission(repo: Arc<RepositoryState>, author: &GithubUser) -> bool {
+    repo.user_has_permission(author, PermissionType::Review)
+        .await
 }
 
-async fn notify_of_tree_closed(
-    repo: &RepositoryState,
-    pr: &PullRequest,
-    priority: u32,
-) -> anyhow::Result<()> {
+async fn notify_of_tree_closed(repo: &RepositoryState, pr: &PullRequest, priority: u32) {
     repo.client
-        .post_comment(
+        .post_comment_best_effort(
             pr.number,
             Comment::new(format!(
                 "Tree closed for PRs with priority less than {}",
@@ -254,64 +571,83 @@ async fn notify_of_tree_closed(
         .await
 }
 
-async fn notify_of_tree_open(repo: &RepositoryState, pr: &PullRequest) -> anyhow::Result<()> {
+async fn notify_of_tree_open(repo: &RepositoryState, pr: &PullRequest) {
     repo.client
-        .post_comment(
+        .post_comment_best_effort(
             pr.number,
             Comment::new("Tree is now open for merging".to_string()),
         )
         .await
 }
 
-async fn notify_of_unapproval(repo: &RepositoryState, pr: &PullRequest) -> anyhow::Result<()> {
-    repo.client
-        .post_comment(
-            pr.number,
-            Comment::new(format!("Commit {} has been unapproved", pr.head.sha)),
-        )
-        .await
+async fn notify_of_unapproval(repo: &RepositoryState, db: &PgDbClient, pr: &PullRequest) {
+    post_status_comment_best_effort(
+        repo,
+        db,
+        pr.number,
+        Comment::new(format!("Commit {} has been unapproved", pr.head.sha)),
+    )
+    .await
 }
 
 async fn notify_of_approval(
     repo: &RepositoryState,
+    db: &PgDbClient,
     pr: &PullRequest,
     approver: &str,
-) -> anyhow::Result<()> {
-    repo.client
-        .post_comment(
-            pr.number,
-            Comment::new(format!(
-                "Commit {} has been approved by `{}`",
-                pr.head.sha, approver
-            )),
-        )
-        .await
+    force: bool,
+) {
+    let mut text = repo.config.load().render_message(
+        MessageKind::Approved,
+        &[("sha", pr.head.sha.as_ref()), ("approver", approver)],
+    );
+    if force {
+        text.push_str(
+            "\n:warning: **Forced approval**: failures of checks that are not required will be ignored.",
+        );
+    }
+    post_status_comment_best_effort(repo, db, pr.number, Comment::new(text)).await
+}
+
+fn deny_force_request(author: &GithubUser) -> BorsError {
+    tracing::warn!("Forced approval denied for {}", author.username);
+    BorsError::user_facing(format!(
+        ":lock: @{}: You don't have permission to force an approval: only repository admins can do that",
+        author.username
+    ))
 }
 
 async fn notify_of_delegation(
     repo: &RepositoryState,
     pr: &PullRequest,
     delegatee: &str,
-) -> anyhow::Result<()> {
+    permission: DelegatedPermission,
+) {
+    let message = match permission {
+        DelegatedPermission::Review => format!("@{} can now approve this pull request", delegatee),
+        DelegatedPermission::Try => {
+            format!(
+                "@{} can now perform try builds on this pull request",
+                delegatee
+            )
+        }
+    };
     repo.client
-        .post_comment(
-            pr.number,
-            Comment::new(format!("@{} can now approve this pull request", delegatee)),
-        )
+        .post_comment_best_effort(pr.number, Comment::new(message))
         .await
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::database::TreeState;
+    use crate::database::{ApprovalStatus, DelegatedPermission, TreeState};
     use crate::{
         bors::{
             RollupMode,
             handlers::{TRY_BRANCH_NAME, trybuild::TRY_MERGE_BRANCH_NAME},
         },
         tests::mocks::{
-            BorsBuilder, Comment, GitHubState, Permissions, User, default_pr_number,
-            default_repo_name, run_test,
+            BorsBuilder, Comment, GitHubState, Permissions, TEST_ADMIN_TOKEN, User,
+            default_pr_number, default_repo_name, run_test,
         },
     };
 
@@ -334,6 +670,143 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn approve_stores_base_sha(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r+").await?;
+            tester.expect_comments(1).await;
+
+            let base_sha = tester
+                .default_repo()
+                .lock()
+                .get_pr(default_pr_number())
+                .base_branch
+                .get_sha()
+                .to_string();
+            let pr = tester.default_pr_db().await?.unwrap();
+            let ApprovalStatus::Approved(info) = pr.approval_status else {
+                panic!("PR should be approved");
+            };
+            assert_eq!(info.base_sha, Some(base_sha));
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_custom_message(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+[messages]
+approved = ":pushpin: {approver} hat {sha} freigegeben {{:tada:}}"
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":pushpin: default-user hat pr-1-sha freigegeben {:tada:}"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    fn gh_state_with_required_checks() -> GitHubState {
+        GitHubState::default().with_default_config(
+            r#"
+required_checks = ["CI"]
+"#,
+        )
+    }
+
+    #[sqlx::test]
+    async fn approve_force(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_required_checks())
+            .run_test(|mut tester| async {
+                tester
+                    .default_repo()
+                    .lock()
+                    .admins
+                    .push(User::default_pr_author().name);
+                tester.post_comment("@bors r+ force").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @r"
+                Commit pr-1-sha has been approved by `default-user`
+                :warning: **Forced approval**: failures of checks that are not required will be ignored.
+                "
+                );
+                tester.default_pr().await.expect_approved_by("default-user");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_force_without_required_checks(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .default_repo()
+                .lock()
+                .admins
+                .push(User::default_pr_author().name);
+            tester.post_comment("@bors r+ force").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":warning: An approval cannot be forced without `required_checks` in the configuration: every check is required."
+            );
+            tester.default_pr().await.expect_unapproved();
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_force_admin_granted_and_revoked(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_required_checks())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+ force").await?;
+                tester.expect_comments(1).await;
+
+                // The admin permission is not cached, changes take effect immediately
+                tester
+                    .default_repo()
+                    .lock()
+                    .admins
+                    .push(User::default_pr_author().name);
+                tester.post_comment("@bors r+ force").await?;
+                tester.expect_comments(1).await;
+                tester.default_pr().await.expect_approved_by("default-user");
+
+                tester.default_repo().lock().admins.clear();
+                tester.post_comment("@bors r+ force").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to force an approval: only repository admins can do that"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_force_requires_admin(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r+ force").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @default-user: You don't have permission to force an approval: only repository admins can do that"
+            );
+            tester.default_pr().await.expect_unapproved();
+            Ok(tester)
+        })
+        .await;
+    }
+
     #[sqlx::test]
     async fn approve_on_behalf(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -360,53 +833,377 @@ mod tests {
                 .await?;
             insta::assert_snapshot!(
                 tester.get_comment().await?,
-                @"@unprivileged-user: :key: Insufficient privileges: not in try users"
+                @r"
+                :lock: @unprivileged-user: You don't have permission to start try builds: the `try` permission is required, you have no permissions
+                PR authors can start try builds of their own PRs if `author_can_try` is enabled in the repository configuration.
+                "
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_user_cannot_approve(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors r+").with_author(User::try_user()))
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @user-with-try-privileges: You don't have permission to approve pull requests: the `review` permission is required, you only have the `try` permission"
+            );
+            tester.default_pr().await.expect_unapproved();
+
+            tester
+                .post_comment(Comment::from("@bors try").with_author(User::try_user()))
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_configured_reviewer(pool: sqlx::PgPool) {
+        let gh = GitHubState::unauthorized_pr_author()
+            .with_default_config(r#"reviewers = ["default-user"]"#);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `default-user`"
+                );
+                tester.default_pr().await.expect_approved_by("default-user");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_configured_reviewer_matched_by_id(pool: sqlx::PgPool) {
+        let gh = GitHubState::unauthorized_pr_author()
+            .with_default_config(r#"reviewers = ["default-user"]"#);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                // A different account that uses the configured username, e.g. after a rename
+                tester
+                    .post_comment(
+                        Comment::from("@bors r+").with_author(User::new(999, "default-user")),
+                    )
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+                );
+                tester.default_pr().await.expect_unapproved();
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_configured_reviewer_unknown_user(pool: sqlx::PgPool) {
+        let gh = GitHubState::unauthorized_pr_author()
+            .with_default_config(r#"reviewers = ["nonexistent-user"]"#);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn unauthorized_approve_does_not_modify_pr(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(
+                    Comment::from("@bors r+ p=5 rollup=never").with_author(User::unprivileged()),
+                )
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @unprivileged-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+            );
+            tester
+                .default_pr()
+                .await
+                .expect_unapproved()
+                .expect_priority(None)
+                .expect_rollup(None);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    fn gh_state_with_reviewer_team() -> GitHubState {
+        GitHubState::unauthorized_pr_author().with_default_config(
+            r#"
+reviewers = ["@rust-lang/compiler"]
+team_members_ttl = 0
+"#,
+        )
+    }
+
+    #[sqlx::test]
+    async fn approve_team_member(pool: sqlx::PgPool) {
+        let gh = gh_state_with_reviewer_team();
+        gh.default_repo()
+            .lock()
+            .teams
+            .insert("compiler".to_string(), vec![User::default_pr_author()]);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `default-user`"
+                );
+                tester.default_pr().await.expect_approved_by("default-user");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_team_membership_removed(pool: sqlx::PgPool) {
+        let gh = gh_state_with_reviewer_team();
+        gh.default_repo()
+            .lock()
+            .teams
+            .insert("compiler".to_string(), vec![User::default_pr_author()]);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .default_repo()
+                    .lock()
+                    .teams
+                    .insert("compiler".to_string(), vec![User::reviewer()]);
+                tester.post_comment("@bors r-").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to unapprove pull requests: the `review` permission is required, you have no permissions"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_team_membership_cached_until_reload(pool: sqlx::PgPool) {
+        let gh = GitHubState::unauthorized_pr_author()
+            .with_default_config(r#"reviewers = ["@rust-lang/compiler"]"#);
+        gh.default_repo()
+            .lock()
+            .teams
+            .insert("compiler".to_string(), vec![User::default_pr_author()]);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .default_repo()
+                    .lock()
+                    .teams
+                    .insert("compiler".to_string(), vec![User::reviewer()]);
+
+                // The team members are still cached
+                tester.post_comment("@bors r-").await?;
+                tester.expect_comments(1).await;
+                tester.default_pr().await.expect_unapproved();
+
+                tester
+                    .admin_reload_repository(default_repo_name(), Some(TEST_ADMIN_TOKEN))
+                    .await?;
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_unresolved_team(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_reviewer_team())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+                );
+                tester.default_pr().await.expect_unapproved();
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn insufficient_permission_set_priority(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors p=2").with_author(User::unprivileged()))
+                .await?;
+            tester.post_comment("@bors p=2").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @unprivileged-user: You don't have permission to set the priority of pull requests: the `review` permission is required, you have no permissions"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn unapprove(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors r+").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `default-user`",
+            );
+            tester
+                .default_pr()
+                .await
+                .expect_approved_by(&User::default_pr_author().name);
+            tester.post_comment("@bors r-").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been unapproved"
             );
+
+            tester.default_pr().await.expect_unapproved();
             Ok(tester)
         })
         .await;
     }
 
+    fn gh_state_with_two_approvals() -> GitHubState {
+        GitHubState::default().with_default_config("required_approvals = 2")
+    }
+
+    #[sqlx::test]
+    async fn approve_requires_multiple_approvals(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_two_approvals())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":white_check_mark: Approval of `default-user` recorded (1/2 approvals)."
+                );
+                tester.default_pr().await.expect_unapproved();
+
+                // A repeated approval of the same reviewer does not count twice
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":white_check_mark: Approval of `default-user` recorded (1/2 approvals)."
+                );
+                tester.default_pr().await.expect_unapproved();
+
+                tester.post_comment(review_comment("@bors r+")).await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `default-user, reviewer`"
+                );
+                tester
+                    .default_pr()
+                    .await
+                    .expect_approved_by("default-user, reviewer");
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
-    async fn insufficient_permission_set_priority(pool: sqlx::PgPool) {
-        run_test(pool, |mut tester| async {
-            tester
-                .post_comment(Comment::from("@bors p=2").with_author(User::unprivileged()))
-                .await?;
-            tester.post_comment("@bors p=2").await?;
-            insta::assert_snapshot!(
-                tester.get_comment().await?,
-                @"@unprivileged-user: :key: Insufficient privileges: not in review users"
-            );
-            Ok(tester)
-        })
-        .await;
+    async fn unapprove_withdraws_own_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_two_approvals())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester.post_comment(review_comment("@bors r+")).await?;
+                tester.expect_comments(1).await;
+
+                tester.post_comment(review_comment("@bors r-")).await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been unapproved"
+                );
+                tester.default_pr().await.expect_unapproved();
+
+                // The approval of the other reviewer is kept
+                tester.post_comment(review_comment("@bors r+")).await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `default-user, reviewer`"
+                );
+                Ok(tester)
+            })
+            .await;
     }
 
     #[sqlx::test]
-    async fn unapprove(pool: sqlx::PgPool) {
+    async fn unapprove_keeps_approvals_of_others(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
-            tester.post_comment("@bors r+").await?;
-            insta::assert_snapshot!(
-                tester.get_comment().await?,
-                @"Commit pr-1-sha has been approved by `default-user`",
-            );
-            tester
-                .default_pr()
-                .await
-                .expect_approved_by(&User::default_pr_author().name);
+            tester.post_comment("@bors r=user1").await?;
+            tester.expect_comments(1).await;
+
+            // `default-user` has not approved the PR, so only the PR is unapproved
             tester.post_comment("@bors r-").await?;
             insta::assert_snapshot!(
                 tester.get_comment().await?,
                 @"Commit pr-1-sha has been unapproved"
             );
-
             tester.default_pr().await.expect_unapproved();
+            let pr = tester.default_pr_db().await?.unwrap();
+            assert_eq!(
+                tester.db().get_approvers(&pr, "pr-1-sha").await?,
+                vec!["user1".to_string()]
+            );
             Ok(tester)
         })
         .await;
     }
 
+    #[sqlx::test]
+    async fn unapprove_pending_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_two_approvals())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester.post_comment("@bors r-").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Approval of `default-user` has been withdrawn (0/2 approvals)."
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn approve_with_priority(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -456,6 +1253,85 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn set_priority_no_reaction_by_default(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors p=5").await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.priority == Some(5))
+                })
+                .await?;
+            assert!(tester.default_repo().lock().comment_reactions.is_empty());
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn set_priority_reaction_ack(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("reaction_ack = true"))
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(Comment::from("@bors p=5").with_id(42))
+                    .await?;
+                tester
+                    .wait_for(|| async {
+                        Ok(tester.default_repo().lock().comment_reactions
+                            == [(42, "+1".to_string())])
+                    })
+                    .await?;
+                tester.default_pr().await.expect_priority(Some(5));
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn set_rollup_reaction_ack(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("reaction_ack = true"))
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(Comment::from("@bors rollup=never").with_id(7))
+                    .await?;
+                tester
+                    .wait_for(|| async {
+                        Ok(tester.default_repo().lock().comment_reactions
+                            == [(7, "+1".to_string())])
+                    })
+                    .await?;
+                tester
+                    .default_pr()
+                    .await
+                    .expect_rollup(Some(RollupMode::Never));
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn insufficient_permission_no_reaction_ack(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("reaction_ack = true"))
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(Comment::from("@bors p=2").with_author(User::unprivileged()))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @unprivileged-user: You don't have permission to set the priority of pull requests: the `review` permission is required, you have no permissions"
+                );
+                assert!(tester.default_repo().lock().comment_reactions.is_empty());
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn priority_preserved_after_approve(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -539,7 +1415,7 @@ mod tests {
                 tester.post_comment("@bors treeclosed=5").await?;
                 insta::assert_snapshot!(
                     tester.get_comment().await?,
-                    @"@default-user: :key: Insufficient privileges: not in review users"
+                    @":lock: @default-user: You don't have permission to close the tree: the `review` permission is required, you have no permissions"
                 );
                 Ok(tester)
             })
@@ -558,7 +1434,7 @@ mod tests {
                 tester.post_comment("@bors r+").await?;
                 insta::assert_snapshot!(
                     tester.get_comment().await?,
-                    @"@default-user: :key: Insufficient privileges: not in review users"
+                    @":lock: @default-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
                 );
                 Ok(tester)
             })
@@ -578,7 +1454,10 @@ mod tests {
                     @"@default-user can now approve this pull request"
                 );
 
-                tester.default_pr().await.expect_delegated();
+                tester
+                    .default_pr()
+                    .await
+                    .expect_delegated(DelegatedPermission::Review);
                 Ok(tester)
             })
             .await;
@@ -666,7 +1545,7 @@ mod tests {
                 tester.post_comment("@bors delegate+").await?;
                 insta::assert_snapshot!(
                     tester.get_comment().await?,
-                    @"@default-user: :key: Insufficient privileges: not in review users"
+                    @":lock: @default-user: You don't have permission to delegate approvals: the `review` permission is required, you have no permissions"
                 );
 
                 assert!(tester.default_pr_db().await?.is_none());
@@ -675,6 +1554,51 @@ mod tests {
             .await;
     }
 
+    #[sqlx::test]
+    async fn list_delegations(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::unauthorized_pr_author())
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(review_comment("@bors delegate?"))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"There are no delegations in this repository."
+                );
+
+                tester
+                    .post_comment(review_comment("@bors delegate+"))
+                    .await?;
+                tester.expect_comments(1).await;
+                tester
+                    .post_comment(review_comment("@bors delegate?"))
+                    .await?;
+                let comment = tester.get_comment().await?;
+                assert!(comment.starts_with(
+                    "Delegations in this repository:\n\n- #1: `review` delegated by @reviewer on "
+                ));
+                assert!(comment.ends_with(", does not expire"));
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn list_delegations_insufficient_permission(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::unauthorized_pr_author())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors delegate?").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to list delegations: the `review` permission is required, you have no permissions"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn undelegate_by_reviewer(pool: sqlx::PgPool) {
         BorsBuilder::new(pool)
@@ -684,7 +1608,10 @@ mod tests {
                     .post_comment(review_comment("@bors delegate+"))
                     .await?;
                 tester.expect_comments(1).await;
-                tester.default_pr().await.expect_delegated();
+                tester
+                    .default_pr()
+                    .await
+                    .expect_delegated(DelegatedPermission::Review);
 
                 tester
                     .post_comment(review_comment("@bors delegate-"))
@@ -694,7 +1621,32 @@ mod tests {
                         let Some(pr) = tester.default_pr_db().await? else {
                             return Ok(false);
                         };
-                        Ok(!pr.delegated)
+                        Ok(pr.delegated_permission.is_none())
+                    })
+                    .await?;
+
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn undelegate_by_delegatee(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::unauthorized_pr_author())
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(review_comment("@bors delegate+"))
+                    .await?;
+                tester.expect_comments(1).await;
+
+                tester.post_comment("@bors delegate-").await?;
+                tester
+                    .wait_for(|| async {
+                        let Some(pr) = tester.default_pr_db().await? else {
+                            return Ok(false);
+                        };
+                        Ok(pr.delegated_permission.is_none())
                     })
                     .await?;
 
@@ -704,91 +1656,156 @@ mod tests {
     }
 
     #[sqlx::test]
-    async fn undelegate_by_delegatee(pool: sqlx::PgPool) {
+    async fn reviewer_unapprove_delegated_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::unauthorized_pr_author())
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(review_comment("@bors delegate+"))
+                    .await?;
+                tester.expect_comments(1).await;
+
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .default_pr()
+                    .await
+                    .expect_approved_by(&User::default_pr_author().name);
+
+                tester.post_comment(review_comment("@bors r-")).await?;
+                tester.expect_comments(1).await;
+
+                tester.default_pr().await.expect_unapproved();
+
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn non_author_cannot_use_delegation(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::unauthorized_pr_author())
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(review_comment("@bors delegate+"))
+                    .await?;
+                tester.expect_comments(1).await;
+
+                tester
+                    .post_comment(Comment::from("@bors r+").with_author(User::unprivileged()))
+                    .await?;
+                tester.expect_comments(1).await;
+
+                tester.default_pr().await.expect_unapproved();
+
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn delegate_insufficient_permission_try_user(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(Comment::from("@bors delegate+").with_author(User::try_user()))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @user-with-try-privileges: You don't have permission to delegate approvals: the `review` permission is required, you only have the `try` permission"
+                );
+                assert!(tester.default_pr_db().await?.is_none());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn delegate_try(pool: sqlx::PgPool) {
         BorsBuilder::new(pool)
             .github(GitHubState::unauthorized_pr_author())
             .run_test(|mut tester| async {
                 tester
-                    .post_comment(review_comment("@bors delegate+"))
+                    .post_comment(review_comment("@bors delegate=try"))
                     .await?;
-                tester.expect_comments(1).await;
-
-                tester.post_comment("@bors delegate-").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"@default-user can now perform try builds on this pull request"
+                );
                 tester
-                    .wait_for(|| async {
-                        let Some(pr) = tester.default_pr_db().await? else {
-                            return Ok(false);
-                        };
-                        Ok(!pr.delegated)
-                    })
-                    .await?;
-
+                    .default_pr()
+                    .await
+                    .expect_delegated(DelegatedPermission::Try);
                 Ok(tester)
             })
             .await;
     }
 
     #[sqlx::test]
-    async fn reviewer_unapprove_delegated_approval(pool: sqlx::PgPool) {
-        BorsBuilder::new(pool)
+    async fn try_delegatee_can_try(pool: sqlx::PgPool) {
+        let gh = BorsBuilder::new(pool)
             .github(GitHubState::unauthorized_pr_author())
             .run_test(|mut tester| async {
                 tester
-                    .post_comment(review_comment("@bors delegate+"))
+                    .post_comment(review_comment("@bors delegate=try"))
                     .await?;
                 tester.expect_comments(1).await;
-
-                tester.post_comment("@bors r+").await?;
-                tester.expect_comments(1).await;
-                tester
-                    .default_pr()
-                    .await
-                    .expect_approved_by(&User::default_pr_author().name);
-
-                tester.post_comment(review_comment("@bors r-")).await?;
+                tester.post_comment("@bors try").await?;
                 tester.expect_comments(1).await;
 
-                tester.default_pr().await.expect_unapproved();
-
                 Ok(tester)
             })
             .await;
+        gh.check_sha_history(
+            default_repo_name(),
+            TRY_BRANCH_NAME,
+            &["merge-main-sha1-pr-1-sha-0"],
+        );
     }
 
     #[sqlx::test]
-    async fn non_author_cannot_use_delegation(pool: sqlx::PgPool) {
+    async fn try_delegatee_cannot_approve(pool: sqlx::PgPool) {
         BorsBuilder::new(pool)
             .github(GitHubState::unauthorized_pr_author())
             .run_test(|mut tester| async {
                 tester
-                    .post_comment(review_comment("@bors delegate+"))
-                    .await?;
-                tester.expect_comments(1).await;
-
-                tester
-                    .post_comment(Comment::from("@bors r+").with_author(User::unprivileged()))
+                    .post_comment(review_comment("@bors delegate=try"))
                     .await?;
                 tester.expect_comments(1).await;
-
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+                );
                 tester.default_pr().await.expect_unapproved();
-
                 Ok(tester)
             })
             .await;
     }
 
     #[sqlx::test]
-    async fn delegate_insufficient_permission_try_user(pool: sqlx::PgPool) {
+    async fn undelegate_try_delegation(pool: sqlx::PgPool) {
         BorsBuilder::new(pool)
+            .github(GitHubState::unauthorized_pr_author())
             .run_test(|mut tester| async {
                 tester
-                    .post_comment(Comment::from("@bors delegate+").with_author(User::try_user()))
+                    .post_comment(review_comment("@bors delegate=try"))
                     .await?;
-                insta::assert_snapshot!(
-                    tester.get_comment().await?,
-                    @"@user-with-try-privileges: :key: Insufficient privileges: not in review users"
-                );
-                assert!(tester.default_pr_db().await?.is_none());
+                tester.expect_comments(1).await;
+
+                tester
+                    .post_comment(review_comment("@bors delegate-"))
+                    .await?;
+                tester
+                    .wait_for(|| async {
+                        let Some(pr) = tester.default_pr_db().await? else {
+                            return Ok(false);
+                        };
+                        Ok(pr.delegated_permission.is_none())
+                    })
+                    .await?;
+
                 Ok(tester)
             })
             .await;
@@ -940,6 +1957,43 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn set_squash(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors squash").await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.squash)
+                })
+                .await?;
+
+            tester.post_comment("@bors squash-").await?;
+            tester
+                .wait_for(|| async { Ok(!tester.default_pr_db().await?.unwrap().squash) })
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn insufficient_permission_set_squash(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors squash").with_author(User::unprivileged()))
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @unprivileged-user: You don't have permission to set the merge style of pull requests: the `review` permission is required, you have no permissions"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
     #[sqlx::test]
     async fn rollup_preserved_after_approve(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -1034,4 +2088,241 @@ mod tests {
         })
         .await;
     }
+
+    fn gh_state_with_approve_on_review() -> GitHubState {
+        GitHubState::default().with_default_config(
+            r#"
+approve_on_review = true
+
+[labels]
+approve = ["+approved"]
+"#,
+        )
+    }
+
+    #[sqlx::test]
+    async fn review_approve_disabled_by_default(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .submit_review(default_pr_number(), User::reviewer(), "approved", "")
+                .await?;
+            tester.post_comment("@bors ping").await?;
+            assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+            tester.default_pr().await.expect_unapproved();
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn review_approve(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approve_on_review())
+            .run_test(|mut tester| async {
+                tester
+                    .submit_review(default_pr_number(), User::reviewer(), "approved", "")
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `reviewer`"
+                );
+                tester.default_pr().await.expect_approved_by("reviewer");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn review_approve_unauthorized_ignored(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approve_on_review())
+            .run_test(|mut tester| async {
+                tester
+                    .submit_review(default_pr_number(), User::unprivileged(), "approved", "")
+                    .await?;
+                tester.post_comment("@bors ping").await?;
+                assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+                tester.default_pr().await.expect_unapproved();
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn review_approve_with_command_in_body(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approve_on_review())
+            .run_test(|mut tester| async {
+                tester
+                    .submit_review(
+                        default_pr_number(),
+                        User::reviewer(),
+                        "approved",
+                        "@bors r=user1",
+                    )
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `user1`"
+                );
+                // The review itself is not applied on top of the command
+                tester.post_comment("@bors ping").await?;
+                assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+                tester.default_pr().await.expect_approved_by("user1");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn review_changes_requested_unapproves(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approve_on_review())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .submit_review(
+                        default_pr_number(),
+                        User::reviewer(),
+                        "changes_requested",
+                        "Please fix the tests",
+                    )
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been unapproved"
+                );
+                tester.default_pr().await.expect_unapproved();
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn review_changes_requested_without_approval(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_approve_on_review())
+            .run_test(|mut tester| async {
+                tester
+                    .submit_review(
+                        default_pr_number(),
+                        User::reviewer(),
+                        "changes_requested",
+                        "",
+                    )
+                    .await?;
+                tester.post_comment("@bors ping").await?;
+                assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_in_review_body(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .submit_review(
+                    default_pr_number(),
+                    User::reviewer(),
+                    "commented",
+                    "Thanks!\n@bors r+",
+                )
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `reviewer`"
+            );
+            tester.default_pr().await.expect_approved_by("reviewer");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_in_review_comment(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_review_comment(default_pr_number(), User::reviewer(), "@bors r+ p=3")
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `reviewer`"
+            );
+            tester
+                .default_pr()
+                .await
+                .expect_approved_by("reviewer")
+                .expect_priority(Some(3));
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_in_review_comment_unauthorized(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_review_comment(default_pr_number(), User::unprivileged(), "@bors r+")
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":lock: @unprivileged-user: You don't have permission to approve pull requests: the `review` permission is required, you have no permissions"
+            );
+            tester.default_pr().await.expect_unapproved();
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_locked_pr_runs_remaining_commands(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .default_repo()
+                .lock()
+                .get_pr_mut(default_pr_number())
+                .locked = true;
+            tester.post_comment("@bors r+\n@bors p=5").await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.priority == Some(5))
+                })
+                .await?;
+            assert!(tester.default_pr_db().await?.unwrap().has_approval());
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn approve_comment_failure_is_reported(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.default_repo().lock().comment_error = true;
+            tester.post_comment("@bors r+").await?;
+            tester
+                .wait_for(|| async {
+                    let Some(pr) = tester.default_pr_db().await? else {
+                        return Ok(false);
+                    };
+                    Ok(pr.has_approval())
+                })
+                .await?;
+            tester
+                .wait_for(|| async {
+                    Ok(!tester.default_repo().lock().rejected_comments.is_empty())
+                })
+                .await?;
+            insta::assert_snapshot!(
+                tester.default_repo().lock().rejected_comments.join("\n"),
+                @"Commit pr-1-sha has been approved by `default-user`"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
 }
@@ -0,0 +1,284 @@
+use octocrab::models::CommentId;
+
+use crate::PgDbClient;
+use crate::bors::{Comment, RepositoryState};
+use crate::database::StatusCommentModel;
+use crate::github::PullRequestNumber;
+
+/// Maximum number of previous states listed in a status comment.
+const MAX_HISTORY_ENTRIES: usize = 20;
+
+/// Posts a comment about a change of the state of a PR (e.g. approval or a finished build).
+///
+/// If `rolling_status_comment` is enabled for the repository, the PR has a single status
+/// comment, which is edited in place to show the current state, together with a history of the
+/// previous states. If the status comment was deleted, a new one is created.
+///
+/// Otherwise, a new comment is posted. If `minimize_outdated_comments` is enabled, the previous
+/// comment of the same [kind](crate::bors::comment::CommentKind) is minimized as outdated.
+pub(super) async fn post_status_comment(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_number: PullRequestNumber,
+    comment: Comment,
+) -> anyhow::Result<()> {
+    if !repo.config.load().rolling_status_comment {
+        return post_comment_minimizing_outdated(repo, db, pr_number, comment).await;
+    }
+    let Some(pr) = db.get_pull_request(repo.repository(), pr_number).await? else {
+        return repo.client.post_comment(pr_number, comment).await;
+    };
+
+    let previous = db.get_status_comment(&pr).await?;
+    let mut history = previous
+        .as_ref()
+        .map(|status| status.history.clone())
+        .unwrap_or_default();
+    let summary = comment.summary().to_string();
+    let comment = comment.with_history(&history);
+
+    let edited_id = match &previous {
+        Some(status) => {
+            let id = CommentId(status.comment_id);
+            if repo.client.edit_comment(pr_number, id, &comment).await? {
+                Some(id)
+            } else {
+                tracing::info!("Status comment {id} was deleted, creating a new one");
+                None
+            }
+        }
+        None => None,
+    };
+    let comment_id = match edited_id {
+        Some(id) => id,
+        None => repo.client.create_comment(pr_number, comment).await?.id,
+    };
+
+    history.push(summary);
+    if history.len() > MAX_HISTORY_ENTRIES {
+        history.drain(..history.len() - MAX_HISTORY_ENTRIES);
+    }
+    db.set_status_comment(
+        &pr,
+        &StatusCommentModel {
+            comment_id: comment_id.0,
+            history,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Posts a status comment about a change of the state of a PR that has already been stored.
+/// A failure to post the comment does not undo the change, so it does not fail the handler
+/// either, it is only reported.
+pub(super) async fn post_status_comment_best_effort(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_number: PullRequestNumber,
+    comment: Comment,
+) {
+    if let Err(error) = post_status_comment(repo, db, pr_number, comment).await {
+        repo.client.report_comment_failure(pr_number, &error);
+    }
+}
+
+async fn post_comment_minimizing_outdated(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_number: PullRequestNumber,
+    comment: Comment,
+) -> anyhow::Result<()> {
+    let kind = match comment.kind() {
+        Some(kind) if repo.config.load().minimize_outdated_comments => kind,
+        _ => return repo.client.post_comment(pr_number, comment).await,
+    };
+    let Some(pr) = db.get_pull_request(repo.repository(), pr_number).await? else {
+        return repo.client.post_comment(pr_number, comment).await;
+    };
+
+    let posted = repo.client.create_comment(pr_number, comment).await?;
+    if let Some(previous) = db.get_outdatable_comment(&pr, kind).await? {
+        // The new comment was already posted, so a failure to hide the old one is not fatal.
+        if let Err(error) = repo.client.minimize_comment(&previous).await {
+            tracing::warn!("Could not minimize outdated comment {previous}: {error:?}");
+        }
+    }
+    db.set_outdatable_comment(&pr, kind, &posted.node_id)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::handlers::TRY_BRANCH_NAME;
+    use crate::bors::handlers::trybuild::TRY_MERGE_BRANCH_NAME;
+    use crate::tests::mocks::{
+        BorsBuilder, BorsTester, GitHubState, Workflow, default_pr_number, run_test,
+    };
+
+    fn gh_state_with_status_comment() -> GitHubState {
+        GitHubState::default().with_default_config("rolling_status_comment = true")
+    }
+
+    #[sqlx::test]
+    async fn status_comment_edited(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_status_comment())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `default-user`"
+                );
+                tester.post_comment("@bors r-").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @r"
+                Commit pr-1-sha has been unapproved
+
+                <details><summary>History</summary>
+
+                - Commit pr-1-sha has been approved by `default-user`
+                </details>
+                ");
+                assert_eq!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_pr(default_pr_number())
+                        .bot_comments,
+                    vec![1]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn status_comment_recreated_after_deletion(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_status_comment())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .default_repo()
+                    .lock()
+                    .get_pr_mut(default_pr_number())
+                    .bot_comments
+                    .clear();
+                tester.post_comment("@bors r-").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @r"
+                Commit pr-1-sha has been unapproved
+
+                <details><summary>History</summary>
+
+                - Commit pr-1-sha has been approved by `default-user`
+                </details>
+                ");
+                assert_eq!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_pr(default_pr_number())
+                        .bot_comments,
+                    vec![2]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn status_comment_try_merge_conflict(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_status_comment())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester.create_branch(TRY_MERGE_BRANCH_NAME).merge_conflict = true;
+                tester.post_comment("@bors try").await?;
+                let comment = tester.get_comment().await?;
+                assert!(comment.starts_with(":lock: Merge conflict"));
+                assert!(comment.ends_with(
+                    "- Commit pr-1-sha has been approved by `default-user`\n</details>"
+                ));
+                assert_eq!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_pr(default_pr_number())
+                        .bot_comments,
+                    vec![1]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    /// Runs two try builds in a row.
+    async fn run_two_try_builds(tester: &mut BorsTester) -> anyhow::Result<()> {
+        tester.post_comment("@bors try").await?;
+        tester.expect_comments(1).await;
+        tester
+            .workflow_success(Workflow::from(tester.try_branch()).with_run_id(1))
+            .await?;
+        tester.expect_comments(1).await;
+
+        tester.get_branch_mut(TRY_BRANCH_NAME).reset_suites();
+        tester.post_comment("@bors try").await?;
+        tester.expect_comments(1).await;
+        tester
+            .workflow_success(Workflow::from(tester.try_branch()).with_run_id(2))
+            .await?;
+        tester.expect_comments(1).await;
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn outdated_try_comments_minimized(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            run_two_try_builds(&mut tester).await?;
+            let bot_comments = tester
+                .default_repo()
+                .lock()
+                .get_pr(default_pr_number())
+                .bot_comments
+                .clone();
+            assert_eq!(bot_comments.len(), 4);
+            // Both comments of the first try build (start and result) are outdated.
+            tester
+                .wait_for(|| async {
+                    Ok(tester
+                        .default_repo()
+                        .lock()
+                        .get_pr(default_pr_number())
+                        .minimized_comments
+                        == bot_comments[..2])
+                })
+                .await?;
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn outdated_try_comments_not_minimized_when_disabled(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(
+                GitHubState::default().with_default_config("minimize_outdated_comments = false"),
+            )
+            .run_test(|mut tester| async {
+                run_two_try_builds(&mut tester).await?;
+                assert!(
+                    tester
+                        .default_repo()
+                        .lock()
+                        .get_pr(default_pr_number())
+                        .minimized_comments
+                        .is_empty()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+}
@@ -0,0 +1,47 @@
+use crate::PgDbClient;
+use crate::bors::event::PullRequestSyncSummary;
+use crate::github::api::client::GithubRepositoryClient;
+
+/// Stores all open pull requests of the repository in the database.
+/// Pull requests that are already known are updated with their current state on GitHub.
+/// No comments are posted, so it is safe to run the synchronization repeatedly.
+pub async fn sync_pull_requests(
+    client: &GithubRepositoryClient,
+    db: &PgDbClient,
+) -> anyhow::Result<PullRequestSyncSummary> {
+    let prs = client.get_open_pull_requests().await?;
+
+    let mut summary = PullRequestSyncSummary {
+        created: 0,
+        updated: 0,
+    };
+    for open_pr in prs {
+        let pr = &open_pr.pr;
+        let created = db
+            .sync_pull_request(
+                client.repository(),
+                pr.number,
+                &pr.base.name,
+                pr.mergeable_state.clone().into(),
+                &pr.status,
+                &pr.author.username,
+                &pr.title,
+                &pr.head.sha.0,
+                &open_pr.labels,
+            )
+            .await?;
+        if created {
+            summary.created += 1;
+        } else {
+            summary.updated += 1;
+        }
+    }
+
+    tracing::info!(
+        "Synchronized pull requests of {}: {} created, {} updated",
+        client.repository(),
+        summary.created,
+        summary.updated
+    );
+    Ok(summary)
+}
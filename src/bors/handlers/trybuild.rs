@@ -3,19 +3,37 @@ use std::sync::Arc;
 use anyhow::{Context, anyhow};
 
 use crate::PgDbClient;
+use crate::bors::BorsResult;
 use crate::bors::Comment;
+use crate::bors::PullRequestStatus;
 use crate::bors::RepositoryState;
 use crate::bors::command::Parent;
+use crate::bors::comment::CommentKind;
 use crate::bors::comment::cant_find_last_parent_comment;
+use crate::bors::comment::merge_conflict_comment;
 use crate::bors::comment::no_try_build_in_progress_comment;
+use crate::bors::comment::queued_try_build_cancelled_comment;
 use crate::bors::comment::try_build_cancelled_comment;
-use crate::bors::comment::try_build_in_progress_comment;
+use crate::bors::comment::try_build_from_fork_forbidden_comment;
+use crate::bors::comment::try_build_queued_comment;
+use crate::bors::comment::try_build_superseded_comment;
+use crate::bors::comment::try_parent_not_found_comment;
+use crate::bors::comment::try_parent_unreachable_comment;
 use crate::bors::comment::unclean_try_build_cancelled_comment;
+use crate::bors::handlers::check_run::{complete_build_check_run, start_build_check_run};
+use crate::bors::handlers::commit_status::{
+    report_build_completed, report_build_queued, report_build_started,
+    report_queued_build_cancelled,
+};
 use crate::bors::handlers::labels::handle_label_trigger;
+use crate::bors::handlers::notification::notify_build_completed;
+use crate::bors::handlers::status_comment::post_status_comment;
+use crate::bors::template::{MessageKind, truncate_text};
+use crate::config::RepositoryConfig;
 use crate::database::RunId;
-use crate::database::{BuildModel, BuildStatus, PullRequestModel};
+use crate::database::{BuildModel, BuildStatus, Pagination, PullRequestModel};
 use crate::github::GithubRepoName;
-use crate::github::api::client::GithubRepositoryClient;
+use crate::github::api::client::{CommitReachability, GithubRepositoryClient};
 use crate::github::{
     CommitSha, GithubUser, LabelTrigger, MergeError, PullRequest, PullRequestNumber,
 };
@@ -36,8 +54,12 @@ pub(super) const TRY_BRANCH_NAME: &str = "automation/bors/try";
 /// Performs a so-called try build - merges the PR branch into a special branch designed
 /// for running CI checks.
 ///
-/// If `parent` is set, it will use it as a base commit for the merge.
+/// If `parent` is set, it will use it as a base commit for the merge. An explicit parent SHA
+/// can be abbreviated, and it has to be a part of the history of the base branch of the PR.
 /// Otherwise, it will use the latest commit on the main repository branch.
+///
+/// A try build of the PR that is still running is cancelled, because its result is no longer
+/// relevant.
 pub(super) async fn command_try_build(
     repo: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
@@ -45,26 +67,239 @@ pub(super) async fn command_try_build(
     author: &GithubUser,
     parent: Option<Parent>,
     jobs: Vec<String>,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     let repo = repo.as_ref();
     if !has_permission(repo, author, pr, &db, PermissionType::Try).await? {
-        deny_request(repo, pr, author, PermissionType::Try).await?;
-        return Ok(());
+        return Err(deny_request(repo, author, PermissionType::Try, "start try builds").await);
+    }
+
+    if !repo.config.load().try_builds_from_forks && pr.is_fork(repo.repository()) {
+        tracing::warn!(
+            "Try builds of PRs from forks are disabled, head repository: {:?}",
+            pr.head_repository
+        );
+        return Err(try_build_from_fork_forbidden_comment().into());
     }
 
+    let parent = match parent {
+        Some(Parent::CommitSha(parent)) => {
+            let reachability = repo
+                .client
+                .get_commit_reachability(&pr.base.name, &parent)
+                .await
+                .context("Cannot check try parent")?;
+            let resolved = match reachability {
+                // The parent might have been abbreviated, so the full SHA is used from now on
+                CommitReachability::Reachable(full_sha) => Ok(full_sha),
+                CommitReachability::Unreachable => {
+                    Err(try_parent_unreachable_comment(&parent, &pr.base.name))
+                }
+                CommitReachability::NotFound => Err(try_parent_not_found_comment(&parent)),
+            };
+            match resolved {
+                Ok(full_sha) => Some(Parent::CommitSha(full_sha)),
+                Err(comment) => {
+                    tracing::warn!("Rejecting try parent {parent}");
+                    return Err(comment.into());
+                }
+            }
+        }
+        parent => parent,
+    };
+
+    let _guard = repo.try_builds_lock.lock().await;
+
     // Create pr model based on CI repo, so we can retrieve the pr later when
     // the CI repo emits events
-    let pr_model = db
+    let mut pr_model = db
         .get_or_create_pull_request(
             repo.client.repository(),
             pr.number,
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await
         .context("Cannot find or create PR")?;
 
+    if let Some(build) = get_pending_build(&pr_model) {
+        if repo.config.load().cancel_superseded_try_builds {
+            cancel_superseded_try_build(repo, &db, pr.number, build).await?;
+            pr_model = db
+                .get_pull_request(repo.repository(), pr.number)
+                .await?
+                .context("Cannot find PR")?;
+        } else {
+            tracing::info!(
+                "Previous try build {} is left running alongside the new one",
+                build.commit_sha
+            );
+        }
+    }
+
+    // Make sure that the request is valid before it is queued or started
+    get_base_sha(&pr_model, parent.clone())?;
+
+    if let Some(builds_ahead) = try_build_queue_position(repo, &db, &pr_model).await? {
+        db.enqueue_try_build(&pr_model, parent.map(parent_to_db).as_deref(), &jobs)
+            .await?;
+        tracing::info!("Try build queued behind {builds_ahead} try build(s)");
+        report_build_queued(repo, &pr.head.sha).await;
+        post_status_comment(repo, &db, pr.number, try_build_queued_comment(builds_ahead)).await?;
+        return Ok(());
+    }
+
+    start_try_build(repo, &db, pr, pr_model, parent, jobs).await?;
+    Ok(())
+}
+
+/// Cancels a running try build that is superseded by a new try build of the same PR.
+/// If the build has finished in the meantime, it is left alone.
+async fn cancel_superseded_try_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_number: PullRequestNumber,
+    build: &BuildModel,
+) -> anyhow::Result<()> {
+    if !db.cancel_pending_build(build).await? {
+        tracing::info!(
+            "Try build {} has finished before it could be superseded",
+            build.commit_sha
+        );
+        return Ok(());
+    }
+    let workflow_urls = match cancel_build_workflows(&repo.client, db, build).await {
+        Ok(workflow_ids) => Some(
+            repo.client
+                .get_workflow_urls(workflow_ids.into_iter())
+                .collect(),
+        ),
+        Err(error) => {
+            tracing::error!(
+                "Could not cancel workflows for SHA {}: {error:?}",
+                build.commit_sha
+            );
+            None
+        }
+    };
+    tracing::info!("Try build {} superseded", build.commit_sha);
+    repo.client
+        .post_comment(
+            pr_number,
+            try_build_superseded_comment(&build.commit_sha, workflow_urls),
+        )
+        .await?;
+    notify_build_completed(repo, build, pr_number, BuildStatus::Cancelled);
+    complete_build_check_run(repo, db, build, BuildStatus::Cancelled).await;
+    report_build_completed(repo, build, pr_number, BuildStatus::Cancelled).await;
+    Ok(())
+}
+
+/// Returns the number of try builds that a new try build of the given PR would have to wait for,
+/// or `None` if it can be started right away.
+async fn try_build_queue_position(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr_model: &PullRequestModel,
+) -> anyhow::Result<Option<u64>> {
+    let Some(max_parallel) = repo.config.load().max_parallel_try_builds else {
+        return Ok(None);
+    };
+    let running = db
+        .count_pending_builds(repo.repository(), TRY_BRANCH_NAME)
+        .await?;
+    let queue = db.get_try_queue(repo.repository()).await?;
+    // Requests that are already queued go first, even if a slot is free at the moment.
+    if running < u64::from(max_parallel) && queue.is_empty() {
+        return Ok(None);
+    }
+    let queued_ahead = queue
+        .iter()
+        .take_while(|request| request.pull_request_id != pr_model.id)
+        .count() as u64;
+    Ok(Some(running + queued_ahead))
+}
+
+/// Starts queued try builds while there are free try build slots.
+/// Nothing is started while the repository is paused, the builds stay queued until it is resumed.
+pub(super) async fn start_queued_try_builds(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+) -> anyhow::Result<()> {
+    let _guard = repo.try_builds_lock.lock().await;
+    start_queued_try_builds_locked(repo, db).await
+}
+
+/// Same as [start_queued_try_builds], for callers that already hold the try builds lock of the
+/// repository.
+pub(super) async fn start_queued_try_builds_locked(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+) -> anyhow::Result<()> {
+    if repo.is_paused() {
+        tracing::info!("Not starting queued try builds, the repository is paused");
+        return Ok(());
+    }
+    // Only the oldest requests that fit into the free slots are needed
+    let page = match repo.config.load().max_parallel_try_builds {
+        Some(max_parallel) => {
+            let running = db
+                .count_pending_builds(repo.repository(), TRY_BRANCH_NAME)
+                .await?;
+            let free_slots = u64::from(max_parallel).saturating_sub(running);
+            if free_slots == 0 {
+                return Ok(());
+            }
+            Pagination::first(free_slots as u32)
+        }
+        None => Pagination::default(),
+    };
+    let queue = db.get_try_queue_page(repo.repository(), page).await?;
+
+    for request in queue {
+        // Another task might have started the request in the meantime
+        if !db.remove_queued_try_build(request.pull_request_id).await? {
+            continue;
+        }
+        let pr = repo
+            .client
+            .get_pull_request(request.pr_number)
+            .await
+            .with_context(|| format!("Cannot get information about PR {}", request.pr_number))?;
+        if pr.status == PullRequestStatus::Closed || pr.status == PullRequestStatus::Merged {
+            tracing::info!(
+                "Not starting queued try build of PR {}, it is no longer open",
+                pr.number
+            );
+            continue;
+        }
+        let pr_model = db
+            .get_or_create_pull_request(
+                repo.client.repository(),
+                pr.number,
+                &pr.base.name,
+                pr.mergeable_state.clone().into(),
+                &pr.status,
+                &pr.author.username,
+            )
+            .await
+            .context("Cannot find or create PR")?;
+        tracing::info!("Starting queued try build of PR {}", pr.number);
+        let parent = request.parent.as_deref().map(parent_from_db);
+        start_try_build(repo, db, &pr, pr_model, parent, request.jobs).await?;
+    }
+    Ok(())
+}
+
+async fn start_try_build(
+    repo: &RepositoryState,
+    db: &PgDbClient,
+    pr: &PullRequest,
+    pr_model: PullRequestModel,
+    parent: Option<Parent>,
+    jobs: Vec<String>,
+) -> anyhow::Result<()> {
     let base_sha = match get_base_sha(&pr_model, parent) {
         Ok(Some(base_sha)) => base_sha,
         Ok(None) => repo
@@ -72,6 +307,7 @@ pub(super) async fn command_try_build(
             .get_branch_sha(&pr.base.name)
             .await
             .context(format!("Cannot get SHA for branch {}", pr.base.name))?,
+        // Only reachable for queued builds, requests are validated before they are queued
         Err(comment) => {
             repo.client.post_comment(pr.number, comment).await?;
             return Ok(());
@@ -80,47 +316,88 @@ pub(super) async fn command_try_build(
 
     match attempt_merge(
         &repo.client,
+        TRY_MERGE_BRANCH_NAME,
         &pr.head.sha,
         &base_sha,
-        &auto_merge_commit_message(pr, repo.client.repository(), "<try>", jobs),
+        &auto_merge_commit_message(
+            &repo.config.load(),
+            pr,
+            repo.client.repository(),
+            "<try>",
+            &base_sha,
+            &jobs,
+        ),
     )
     .await?
     {
         MergeResult::Success(merge_sha) => {
             // If the merge was succesful, run CI with merged commit
-            run_try_build(&repo.client, &db, pr_model, merge_sha.clone(), base_sha).await?;
+            run_try_build(
+                &repo.client,
+                db,
+                pr_model,
+                pr.head.sha.clone(),
+                merge_sha.clone(),
+                base_sha,
+                &jobs,
+            )
+            .await?;
+            start_build_check_run(repo, db, TRY_BRANCH_NAME, &pr.head.sha, &merge_sha).await;
+            report_build_started(repo, TRY_BRANCH_NAME, pr.number, &pr.head.sha, &merge_sha).await;
 
             handle_label_trigger(repo, pr.number, LabelTrigger::TryBuildStarted).await?;
 
-            repo.client
-                .post_comment(pr.number, trying_build_comment(&pr.head.sha, &merge_sha))
-                .await
+            post_status_comment(
+                repo,
+                db,
+                pr.number,
+                trying_build_comment(&pr.head.sha, &merge_sha),
+            )
+            .await
         }
         MergeResult::Conflict => {
-            repo.client
-                .post_comment(pr.number, merge_conflict_comment(&pr.head.name))
-                .await
+            post_status_comment(repo, db, pr.number, merge_conflict_comment(&pr.head.name)).await
         }
     }
 }
 
-async fn attempt_merge(
+/// Representation of a try build parent in the try queue.
+fn parent_to_db(parent: Parent) -> String {
+    match parent {
+        Parent::CommitSha(sha) => sha.0,
+        Parent::Last => "last".to_string(),
+    }
+}
+
+fn parent_from_db(parent: &str) -> Parent {
+    match parent {
+        "last" => Parent::Last,
+        sha => Parent::CommitSha(CommitSha(sha.to_string())),
+    }
+}
+
+/// Sets `merge_branch` to `base_sha` and merges the PR commit `head_sha` into it.
+pub(super) async fn attempt_merge(
     client: &GithubRepositoryClient,
+    merge_branch: &str,
     head_sha: &CommitSha,
     base_sha: &CommitSha,
     merge_message: &str,
 ) -> anyhow::Result<MergeResult> {
     tracing::debug!("Attempting to merge with base SHA {base_sha}");
 
-    // First set the try branch to our base commit (either the selected parent or the main branch).
+    // First set the merge branch to our base commit (either the selected parent or the main
+    // branch).
     client
-        .set_branch_to_sha(TRY_MERGE_BRANCH_NAME, base_sha)
+        .set_branch_to_sha(merge_branch, base_sha)
         .await
-        .map_err(|error| anyhow!("Cannot set try merge branch to {}: {error:?}", base_sha.0))?;
+        .map_err(|error| anyhow!("Cannot set {merge_branch} to {}: {error:?}", base_sha.0))?;
 
-    // Then merge the PR commit into the try branch
+    // Then merge the PR commit into the merge branch.
+    // The commit is referenced by its SHA rather than by the head branch, because the head branch
+    // of a PR from a fork does not exist in this repository, while the commits of the PR do.
     match client
-        .merge_branches(TRY_MERGE_BRANCH_NAME, head_sha, merge_message)
+        .merge_branches(merge_branch, head_sha, merge_message)
         .await
     {
         Ok(merge_sha) => {
@@ -141,8 +418,10 @@ async fn run_try_build(
     client: &GithubRepositoryClient,
     db: &PgDbClient,
     pr_model: PullRequestModel,
+    head_sha: CommitSha,
     commit_sha: CommitSha,
     parent_sha: CommitSha,
+    jobs: &[String],
 ) -> anyhow::Result<()> {
     client
         .set_branch_to_sha(TRY_BRANCH_NAME, &commit_sha)
@@ -154,6 +433,8 @@ async fn run_try_build(
         TRY_BRANCH_NAME.to_string(),
         commit_sha,
         parent_sha,
+        head_sha,
+        jobs,
     )
     .await?;
 
@@ -161,25 +442,21 @@ async fn run_try_build(
     Ok(())
 }
 
-enum MergeResult {
+pub(super) enum MergeResult {
     Success(CommitSha),
     Conflict,
 }
 
+/// A running try build of the PR does not prevent a new one. It has either been cancelled, or
+/// it is left running alongside the new build if `cancel_superseded_try_builds` is disabled.
 fn get_base_sha(
     pr_model: &PullRequestModel,
     parent: Option<Parent>,
 ) -> Result<Option<CommitSha>, Comment> {
-    let last_parent = if let Some(ref build) = pr_model.try_build {
-        if build.status == BuildStatus::Pending {
-            tracing::warn!("Try build already in progress");
-            return Err(try_build_in_progress_comment());
-        } else {
-            Some(CommitSha(build.parent.clone()))
-        }
-    } else {
-        None
-    };
+    let last_parent = pr_model
+        .try_build
+        .as_ref()
+        .map(|build| CommitSha(build.parent.clone()));
 
     match parent.clone() {
         Some(parent) => match parent {
@@ -198,14 +475,15 @@ pub(super) async fn command_try_cancel(
     db: Arc<PgDbClient>,
     pr: &PullRequest,
     author: &GithubUser,
-) -> anyhow::Result<()> {
+) -> BorsResult<()> {
     let repo = repo.as_ref();
     if !has_permission(repo, author, pr, &db, PermissionType::Try).await? {
-        deny_request(repo, pr, author, PermissionType::Try).await?;
-        return Ok(());
+        return Err(deny_request(repo, author, PermissionType::Try, "cancel try builds").await);
     }
 
+    let _guard = repo.try_builds_lock.lock().await;
     let pr_number: PullRequestNumber = pr.number;
+    let head_sha = pr.head.sha.clone();
     let pr = db
         .get_or_create_pull_request(
             repo.client.repository(),
@@ -213,45 +491,55 @@ pub(super) async fn command_try_cancel(
             &pr.base.name,
             pr.mergeable_state.clone().into(),
             &pr.status,
+            &pr.author.username,
         )
         .await?;
 
-    let Some(build) = get_pending_build(pr) else {
-        tracing::warn!("No build found");
-        repo.client
-            .post_comment(pr_number, no_try_build_in_progress_comment())
-            .await?;
+    if db.remove_queued_try_build(pr.id).await? {
+        tracing::info!("Queued try build cancelled");
+        report_queued_build_cancelled(repo, &head_sha).await;
+        post_status_comment(repo, &db, pr_number, queued_try_build_cancelled_comment()).await?;
         return Ok(());
+    }
+
+    let Some(build) = get_pending_build(&pr) else {
+        tracing::warn!("No build found");
+        return Err(no_try_build_in_progress_comment().into());
     };
 
-    match cancel_build_workflows(&repo.client, db.as_ref(), &build).await {
+    match cancel_build_workflows(&repo.client, db.as_ref(), build).await {
         Err(error) => {
             tracing::error!(
                 "Could not cancel workflows for SHA {}: {error:?}",
                 build.commit_sha
             );
-            db.update_build_status(&build, BuildStatus::Cancelled)
+            db.update_build_status(build, BuildStatus::Cancelled)
                 .await?;
-            repo.client
-                .post_comment(pr_number, unclean_try_build_cancelled_comment())
-                .await?
+            post_status_comment(repo, &db, pr_number, unclean_try_build_cancelled_comment()).await?
         }
         Ok(workflow_ids) => {
-            db.update_build_status(&build, BuildStatus::Cancelled)
+            db.update_build_status(build, BuildStatus::Cancelled)
                 .await?;
             tracing::info!("Try build cancelled");
 
-            repo.client
-                .post_comment(
-                    pr_number,
-                    try_build_cancelled_comment(
-                        repo.client.get_workflow_urls(workflow_ids.into_iter()),
-                    ),
-                )
-                .await?
+            post_status_comment(
+                repo,
+                &db,
+                pr_number,
+                try_build_cancelled_comment(
+                    repo.client.get_workflow_urls(workflow_ids.into_iter()),
+                ),
+            )
+            .await?
         }
     };
+    notify_build_completed(repo, build, pr_number, BuildStatus::Cancelled);
+    complete_build_check_run(repo, &db, build, BuildStatus::Cancelled).await;
+    report_build_completed(repo, build, pr_number, BuildStatus::Cancelled).await;
 
+    if let Err(error) = start_queued_try_builds_locked(repo, &db).await {
+        tracing::error!("Could not start queued try builds: {error:?}");
+    }
     Ok(())
 }
 
@@ -267,31 +555,36 @@ pub async fn cancel_build_workflows(
     Ok(pending_workflows)
 }
 
-fn get_pending_build(pr: PullRequestModel) -> Option<BuildModel> {
+fn get_pending_build(pr: &PullRequestModel) -> Option<&BuildModel> {
     pr.try_build
-        .and_then(|b| (b.status == BuildStatus::Pending).then_some(b))
+        .as_ref()
+        .filter(|build| build.status == BuildStatus::Pending)
 }
 
-fn auto_merge_commit_message(
+pub(super) fn auto_merge_commit_message(
+    config: &RepositoryConfig,
     pr: &PullRequest,
     name: &GithubRepoName,
     reviewer: &str,
-    jobs: Vec<String>,
+    base_sha: &CommitSha,
+    jobs: &[String],
 ) -> String {
-    let pr_number = pr.number;
-    let mut message = format!(
-        r#"Auto merge of {repo_owner}/{repo_name}#{pr_number} - {pr_label}, r={reviewer}
-{pr_title}
-
-{pr_message}"#,
-        pr_label = pr.head_label,
-        pr_title = pr.title,
-        pr_message = pr.message,
-        repo_owner = name.owner(),
-        repo_name = name.name()
+    let body = truncate_text(&pr.message, config.merge_commit_body_limit);
+    let mut message = config.render_message(
+        MessageKind::MergeCommit,
+        &[
+            ("repo", &name.to_string()),
+            ("pr", &pr.number.to_string()),
+            ("branch", &pr.head_label),
+            ("approver", reviewer),
+            ("title", &pr.title),
+            ("body", &body),
+            ("head_sha", &pr.head.sha.0),
+            ("base_sha", &base_sha.0),
+        ],
     );
 
-    // if jobs is empty, try-job won't be added to the message
+    // Tells CI which jobs should be run, if jobs is empty, try-job won't be added to the message
     for job in jobs {
         message.push_str(&format!("\ntry-job: {}", job));
     }
@@ -302,51 +595,18 @@ fn trying_build_comment(head_sha: &CommitSha, merge_sha: &CommitSha) -> Comment
     Comment::new(format!(
         ":hourglass: Trying commit {head_sha} with merge {merge_sha}…"
     ))
-}
-
-fn merge_conflict_comment(branch: &str) -> Comment {
-    let message = format!(
-        r#":lock: Merge conflict
-
-This pull request and the master branch diverged in a way that cannot
- be automatically merged. Please rebase on top of the latest master
- branch, and let the reviewer approve again.
-
-<details><summary>How do I rebase?</summary>
-
-Assuming `self` is your fork and `upstream` is this repository,
- you can resolve the conflict following these steps:
-
-1. `git checkout {branch}` *(switch to your branch)*
-2. `git fetch upstream master` *(retrieve the latest master)*
-3. `git rebase upstream/master -p` *(rebase on top of it)*
-4. Follow the on-screen instruction to resolve conflicts (check `git status` if you got lost).
-5. `git push self {branch} --force-with-lease` *(update this PR)*
-
-You may also read
- [*Git Rebasing to Resolve Conflicts* by Drew Blessing](http://blessing.io/git/git-rebase/open-source/2015/08/23/git-rebasing-to-resolve-conflicts.html)
- for a short tutorial.
-
-Please avoid the ["**Resolve conflicts**" button](https://help.github.com/articles/resolving-a-merge-conflict-on-github/) on GitHub.
- It uses `git merge` instead of `git rebase` which makes the PR commit history more difficult to read.
-
-Sometimes step 4 will complete without asking for resolution. This is usually due to difference between how `Cargo.lock` conflict is
-handled during merge and rebase. This is normal, and you should still perform step 5 to update this PR.
-
-</details>
-"#
-    );
-    Comment::new(message)
+    .with_kind(CommentKind::TryBuildStarted)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, TRY_MERGE_BRANCH_NAME};
+    use crate::database::BuildStatus;
     use crate::database::operations::get_all_workflows;
-    use crate::github::CommitSha;
+    use crate::github::{CommitSha, GithubRepoName};
     use crate::tests::mocks::{
-        BorsBuilder, Comment, GitHubState, User, Workflow, WorkflowEvent, default_pr_number,
-        default_repo_name, run_test,
+        BorsBuilder, BorsTester, Comment, GitHubState, PullRequest, User, Workflow, WorkflowEvent,
+        WorkflowJob, default_pr_number, default_repo_name, mask_durations, run_test,
     };
 
     #[sqlx::test]
@@ -357,10 +617,14 @@ mod tests {
             tester.expect_comments(1).await;
             tester.workflow_success(tester.try_branch()).await?;
             insta::assert_snapshot!(
-                tester.get_comment().await?,
+                mask_durations(&tester.get_comment().await?),
                 @r###"
             :sunny: Try build successful
-            - [Workflow1](https://github.com/workflows/Workflow1/1) :white_check_mark:
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+
             Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
             <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
             "###
@@ -371,45 +635,604 @@ mod tests {
     }
 
     #[sqlx::test]
-    async fn try_failure(pool: sqlx::PgPool) {
-        run_test(pool.clone(), |mut tester| async {
-            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
-            tester.post_comment("@bors try").await?;
-            tester.expect_comments(1).await;
-            tester.workflow_failure(tester.try_branch()).await?;
-            insta::assert_snapshot!(
-                tester.get_comment().await?,
-                @r###"
-            :broken_heart: Test failed
-            - [Workflow1](https://github.com/workflows/Workflow1/1) :x:
-            "###
-            );
-            Ok(tester)
-        })
-        .await;
+    async fn try_failure(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_failure(tester.try_branch()).await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r###"
+            :broken_heart: Test failed
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :x: | [duration] |
+            "###
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_check_run_success(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            {
+                let repo = tester.default_repo();
+                let repo = repo.lock();
+                assert_eq!(repo.check_runs.len(), 1);
+                let check_run = &repo.check_runs[0];
+                assert_eq!(check_run.name, "bors");
+                assert_eq!(check_run.head_sha, "pr-1-sha");
+                assert_eq!(check_run.status, "in_progress");
+            }
+
+            tester.workflow_success(tester.try_branch()).await?;
+            tester.expect_comments(1).await;
+            let check_run = tester.default_repo().lock().check_runs[0].clone();
+            assert_eq!(check_run.status, "completed");
+            assert_eq!(check_run.conclusion, "success");
+            insta::assert_snapshot!(check_run.output.summary, @"Tested commit [merge-main-sha1-pr-1-sha-0](https://github.com/rust-lang/borstest/commit/merge-main-sha1-pr-1-sha-0).");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_check_run_failure(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_failure(tester.try_branch()).await?;
+            tester.expect_comments(1).await;
+            let check_run = tester.default_repo().lock().check_runs[0].clone();
+            assert_eq!(check_run.conclusion, "failure");
+            insta::assert_snapshot!(check_run.output.summary, @r"
+            Tested commit [merge-main-sha1-pr-1-sha-0](https://github.com/rust-lang/borstest/commit/merge-main-sha1-pr-1-sha-0).
+
+            Failed workflows:
+            - [Workflow1](https://github.com/workflows/Workflow1/1)
+            ");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_check_run_cancelled(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.post_comment("@bors try cancel").await?;
+            tester.expect_comments(1).await;
+            let check_run = tester.default_repo().lock().check_runs[0].clone();
+            assert_eq!(check_run.status, "completed");
+            assert_eq!(check_run.conclusion, "cancelled");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_check_run_disabled(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("report_check_run = false"))
+            .run_test(|mut tester| async {
+                tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+                assert!(tester.default_repo().lock().check_runs.is_empty());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    /// Makes `sha` a part of the history of the base branch, so that it can be a try parent.
+    fn add_main_ancestor(tester: &BorsTester, sha: &str) {
+        tester
+            .default_repo()
+            .lock()
+            .get_branch_by_name("main")
+            .unwrap()
+            .add_ancestor(sha);
+    }
+
+    fn commit_statuses(tester: &BorsTester) -> Vec<(String, String, String)> {
+        tester
+            .default_repo()
+            .lock()
+            .commit_statuses
+            .iter()
+            .map(|status| {
+                assert_eq!(status.context, "bors/try");
+                (
+                    status.sha.clone(),
+                    status.state.clone(),
+                    status.description.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn status(sha: &str, state: &str, description: &str) -> (String, String, String) {
+        (sha.to_string(), state.to_string(), description.to_string())
+    }
+
+    #[sqlx::test]
+    async fn try_commit_status_success(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            assert_eq!(
+                commit_statuses(&tester),
+                vec![
+                    status("pr-1-sha", "pending", "Try build in progress"),
+                    status(
+                        "merge-main-sha1-pr-1-sha-0",
+                        "pending",
+                        "Try build in progress"
+                    ),
+                ]
+            );
+
+            tester.workflow_success(tester.try_branch()).await?;
+            tester.expect_comments(1).await;
+            assert_eq!(
+                commit_statuses(&tester)[2..],
+                vec![
+                    status("pr-1-sha", "success", "Try build succeeded"),
+                    status(
+                        "merge-main-sha1-pr-1-sha-0",
+                        "success",
+                        "Try build succeeded"
+                    ),
+                ]
+            );
+            let repo = tester.default_repo();
+            let target_url = repo.lock().commit_statuses[3].target_url.clone();
+            assert_eq!(
+                target_url.as_deref(),
+                Some("https://github.com/rust-lang/borstest/pull/1")
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_commit_status_failure(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_failure(tester.try_branch()).await?;
+            tester.expect_comments(1).await;
+            assert_eq!(
+                commit_statuses(&tester)[2..],
+                vec![
+                    status("pr-1-sha", "failure", "Try build failed"),
+                    status("merge-main-sha1-pr-1-sha-0", "failure", "Try build failed"),
+                ]
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_commit_status_custom_context(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+commit_status_context = "ci/bors"
+queue_url = "https://bors.example.com/queue/borstest"
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                let repo = tester.default_repo();
+                let statuses = repo.lock().commit_statuses.clone();
+                assert!(
+                    statuses
+                        .iter()
+                        .all(|status| status.context == "ci/bors/try")
+                );
+                let merge_status = statuses
+                    .iter()
+                    .find(|status| status.sha == "merge-main-sha1-pr-1-sha-0")
+                    .unwrap();
+                assert_eq!(
+                    merge_status.target_url.as_deref(),
+                    Some("https://bors.example.com/queue/borstest#1")
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_failure_with_job_log(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.default_repo().lock().workflow_jobs.insert(
+                1,
+                vec![WorkflowJob::failed(
+                    10,
+                    "test",
+                    "2024-05-01T10:00:00.0000000Z running tests\n2024-05-01T10:00:01.0000000Z ##[error]Process completed with exit code 1.\n2024-05-01T10:00:02.0000000Z cleanup",
+                )],
+            );
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_failure(tester.try_branch()).await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r"
+            :broken_heart: Test failed
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :x: | [duration] |
+
+            <details><summary>Log of the failed job <code>test</code></summary>
+
+            ```
+            running tests
+            ##[error]Process completed with exit code 1.
+            ```
+
+            </details>
+            "
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_failure_job_log_unavailable(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            {
+                let repo = tester.default_repo();
+                let mut repo = repo.lock();
+                repo.workflow_jobs
+                    .insert(1, vec![WorkflowJob::failed(10, "test", "error")]);
+                repo.job_log_error = true;
+            }
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_failure(tester.try_branch()).await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r"
+            :broken_heart: Test failed
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :x: | [duration] |
+            "
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_no_permissions(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors try").with_author(User::unprivileged()))
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @r"
+                :lock: @unprivileged-user: You don't have permission to start try builds: the `try` permission is required, you have no permissions
+                PR authors can start try builds of their own PRs if `author_can_try` is enabled in the repository configuration.
+                "
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_only_requires_try_permission(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment(Comment::from("@bors try").with_author(User::try_user()))
+                .await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_merge_commit_message_template(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+[messages]
+merge_commit = "Merge #{pr}: {title}\n\nApproved by: {approver}\nHead: {head_sha}\nBase: {base_sha}"
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_branch(TRY_MERGE_BRANCH_NAME).get_commit_message(),
+                    @r"
+                Merge #1: PR #1
+
+                Approved by: <try>
+                Head: pr-1-sha
+                Base: main-sha1
+                "
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_jobs_in_merge_commit_message(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+[messages]
+merge_commit = "Merge #{pr}: {title}"
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try jobs=ci,lint").await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_branch(TRY_MERGE_BRANCH_NAME).get_commit_message(),
+                    @r"
+                Merge #1: PR #1
+                try-job: ci
+                try-job: lint
+                "
+                );
+                assert_eq!(
+                    tester
+                        .default_pr_db()
+                        .await?
+                        .unwrap()
+                        .try_build
+                        .unwrap()
+                        .jobs,
+                    vec!["ci".to_string(), "lint".to_string()]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_without_jobs_has_no_job_trailers(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            assert!(
+                !tester
+                    .get_branch(TRY_MERGE_BRANCH_NAME)
+                    .get_commit_message()
+                    .contains("try-job:")
+            );
+            assert!(
+                tester
+                    .default_pr_db()
+                    .await?
+                    .unwrap()
+                    .try_build
+                    .unwrap()
+                    .jobs
+                    .is_empty()
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_jobs_listed_in_summary(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try jobs=ci").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_success(tester.try_branch()).await?;
+            let comment = tester.get_comment().await?;
+            assert!(comment.contains("Requested jobs: `ci`"));
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_jobs_empty_name(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try jobs=ci,,lint").await?;
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Invalid command: Try job names must not be empty"
+            );
+            assert!(
+                tester
+                    .default_pr_db()
+                    .await?
+                    .is_none_or(|pr| pr.try_build.is_none())
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    fn gh_state_author_can_try(config: &str) -> GitHubState {
+        GitHubState::unauthorized_pr_author().with_default_config(config)
+    }
+
+    fn move_pr_to_fork(gh: &GitHubState) {
+        gh.default_repo()
+            .lock()
+            .get_pr_mut(default_pr_number())
+            .head_repo = GithubRepoName::new("fork-owner", "borstest");
+    }
+
+    #[sqlx::test]
+    async fn try_author_can_try_own_pr(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_author_can_try("author_can_try = true"))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+                );
+                tester.post_comment("@bors try cancel").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @r"
+                Try build cancelled.
+                Cancelled workflows:
+                ");
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_author_can_try_non_author(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_author_can_try("author_can_try = true"))
+            .run_test(|mut tester| async {
+                tester
+                    .post_comment(Comment::from("@bors try").with_author(User::unprivileged()))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @unprivileged-user: You don't have permission to start try builds: the `try` permission is required, you have no permissions"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_author_can_try_fork(pool: sqlx::PgPool) {
+        let gh = gh_state_author_can_try("author_can_try = true");
+        move_pr_to_fork(&gh);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":lock: @default-user: You don't have permission to start try builds: the `try` permission is required, you have no permissions"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_author_can_try_from_forks(pool: sqlx::PgPool) {
+        let gh = gh_state_author_can_try(
+            r#"
+author_can_try = true
+author_can_try_from_forks = true
+"#,
+        );
+        move_pr_to_fork(&gh);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_fork_pr(pool: sqlx::PgPool) {
+        let gh = GitHubState::default();
+        move_pr_to_fork(&gh);
+        let gh = BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+                );
+                Ok(tester)
+            })
+            .await;
+        gh.check_sha_history(
+            default_repo_name(),
+            TRY_MERGE_BRANCH_NAME,
+            &["main-sha1", "merge-main-sha1-pr-1-sha-0"],
+        );
+    }
+
+    #[sqlx::test]
+    async fn try_fork_pr_forbidden(pool: sqlx::PgPool) {
+        let gh = GitHubState::default().with_default_config("try_builds_from_forks = false");
+        move_pr_to_fork(&gh);
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":no_entry: Try builds of PRs from forks are disabled in this repository, because the try workflows would run the code of the fork with access to the secrets of this repository."
+                );
+                assert!(
+                    tester
+                        .default_pr_db()
+                        .await?
+                        .and_then(|pr| pr.try_build)
+                        .is_none()
+                );
+                Ok(tester)
+            })
+            .await;
     }
 
     #[sqlx::test]
-    async fn try_no_permissions(pool: sqlx::PgPool) {
-        run_test(pool, |mut tester| async {
-            tester
-                .post_comment(Comment::from("@bors try").with_author(User::unprivileged()))
-                .await?;
-            insta::assert_snapshot!(
-                tester.get_comment().await?,
-                @"@unprivileged-user: :key: Insufficient privileges: not in try users"
-            );
-            Ok(tester)
-        })
-        .await;
+    async fn try_non_fork_pr_with_forks_forbidden(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config("try_builds_from_forks = false"))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
+                );
+                Ok(tester)
+            })
+            .await;
     }
 
     #[sqlx::test]
-    async fn try_only_requires_try_permission(pool: sqlx::PgPool) {
+    async fn try_merge_comment(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
-            tester
-                .post_comment(Comment::from("@bors try").with_author(User::try_user()))
-                .await?;
+            tester.post_comment("@bors try").await?;
             insta::assert_snapshot!(
                 tester.get_comment().await?,
                 @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
@@ -420,16 +1243,26 @@ mod tests {
     }
 
     #[sqlx::test]
-    async fn try_merge_comment(pool: sqlx::PgPool) {
-        run_test(pool, |mut tester| async {
-            tester.post_comment("@bors try").await?;
-            insta::assert_snapshot!(
-                tester.get_comment().await?,
-                @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-0…"
-            );
-            Ok(tester)
-        })
-        .await;
+    async fn try_dry_run(pool: sqlx::PgPool) {
+        let gh = BorsBuilder::new(pool)
+            .dry_run()
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester
+                    .wait_for(|| async {
+                        let Some(pr) = tester.default_pr_db().await? else {
+                            return Ok(false);
+                        };
+                        Ok(pr.try_build.is_some())
+                    })
+                    .await?;
+                Ok(tester)
+            })
+            .await;
+        let repo = gh.get_repo(&default_repo_name());
+        let mut repo = repo.lock();
+        assert!(repo.get_branch_by_name(TRY_MERGE_BRANCH_NAME).is_none());
+        assert!(repo.get_branch_by_name(TRY_BRANCH_NAME).is_none());
     }
 
     #[sqlx::test]
@@ -455,6 +1288,7 @@ mod tests {
     #[sqlx::test]
     async fn try_merge_explicit_parent(pool: sqlx::PgPool) {
         let gh = run_test(pool, |mut tester| async {
+            add_main_ancestor(&tester, "ea9c1b050cc8b420c2c211d2177811e564a4dc60");
             tester
                 .post_comment("@bors try parent=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
                 .await?;
@@ -472,9 +1306,47 @@ mod tests {
         );
     }
 
+    #[sqlx::test]
+    async fn try_merge_abbreviated_parent(pool: sqlx::PgPool) {
+        let gh = run_test(pool, |mut tester| async {
+            add_main_ancestor(&tester, "ea9c1b050cc8b420c2c211d2177811e564a4dc60");
+            tester.post_comment("@bors try parent=ea9c1b0").await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @":hourglass: Trying commit pr-1-sha with merge merge-ea9c1b050cc8b420c2c211d2177811e564a4dc60-pr-1-sha-0…");
+            tester.workflow_success(tester.try_branch()).await?;
+            tester.expect_comments(1).await;
+            // The previous parent is stored with the full SHA
+            tester.post_comment("@bors try parent=last").await?;
+            tester.expect_comments(1).await;
+            Ok(tester)
+        })
+        .await;
+        gh.check_sha_history(
+            default_repo_name(),
+            TRY_MERGE_BRANCH_NAME,
+            &[
+                "ea9c1b050cc8b420c2c211d2177811e564a4dc60",
+                "merge-ea9c1b050cc8b420c2c211d2177811e564a4dc60-pr-1-sha-0",
+                "ea9c1b050cc8b420c2c211d2177811e564a4dc60",
+                "merge-ea9c1b050cc8b420c2c211d2177811e564a4dc60-pr-1-sha-1",
+            ],
+        );
+    }
+
+    #[sqlx::test]
+    async fn try_merge_abbreviated_parent_not_found(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try parent=ea9c1b0").await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @":exclamation: The try parent `ea9c1b0` does not exist in this repository.");
+            assert!(tester.default_pr_db().await?.is_none_or(|pr| pr.try_build.is_none()));
+            Ok(tester)
+        })
+        .await;
+    }
+
     #[sqlx::test]
     async fn try_merge_last_parent(pool: sqlx::PgPool) {
         let gh = run_test(pool, |mut tester| async {
+            add_main_ancestor(&tester, "ea9c1b050cc8b420c2c211d2177811e564a4dc60");
             tester
                 .post_comment("@bors try parent=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
                 .await?;
@@ -498,6 +1370,58 @@ mod tests {
         );
     }
 
+    #[sqlx::test]
+    async fn try_merge_parent_not_found(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .post_comment("@bors try parent=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
+                .await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @":exclamation: The try parent `ea9c1b050cc8b420c2c211d2177811e564a4dc60` does not exist in this repository.");
+            assert!(tester.default_pr_db().await?.is_none_or(|pr| pr.try_build.is_none()));
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_merge_parent_not_in_base_branch(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester
+                .create_branch("beta")
+                .set_to_sha("ea9c1b050cc8b420c2c211d2177811e564a4dc60");
+            tester
+                .post_comment("@bors try parent=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
+                .await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @":exclamation: The try parent `ea9c1b050cc8b420c2c211d2177811e564a4dc60` is not a part of the history of the `main` branch.");
+            assert!(tester.default_pr_db().await?.is_none_or(|pr| pr.try_build.is_none()));
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_merge_parent_base_head(pool: sqlx::PgPool) {
+        let gh = run_test(pool, |mut tester| async {
+            tester
+                .create_branch("main")
+                .set_to_sha("ea9c1b050cc8b420c2c211d2177811e564a4dc60");
+            tester
+                .post_comment("@bors try parent=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
+                .await?;
+            tester.expect_comments(1).await;
+            Ok(tester)
+        })
+        .await;
+        gh.check_sha_history(
+            default_repo_name(),
+            TRY_MERGE_BRANCH_NAME,
+            &[
+                "ea9c1b050cc8b420c2c211d2177811e564a4dc60",
+                "merge-ea9c1b050cc8b420c2c211d2177811e564a4dc60-pr-1-sha-0",
+            ],
+        );
+    }
+
     #[sqlx::test]
     async fn try_merge_last_parent_unknown(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
@@ -575,12 +1499,119 @@ mod tests {
     }
 
     #[sqlx::test]
-    async fn try_merge_active_build(pool: sqlx::PgPool) {
+    async fn try_supersedes_running_build(pool: sqlx::PgPool) {
+        let gh = run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester
+                .workflow_event(WorkflowEvent::started(
+                    Workflow::from(tester.try_branch()).with_run_id(123),
+                ))
+                .await?;
+            tester.post_comment("@bors try").await?;
+            insta::assert_snapshot!(tester.get_comment().await?, @r"
+            :warning: The previous try build (`merge-main-sha1-pr-1-sha-0`) was superseded by a new try build and cancelled.
+            Cancelled workflows:
+            - https://github.com/rust-lang/borstest/actions/runs/123
+            ");
+            insta::assert_snapshot!(tester.get_comment().await?, @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-1…");
+            Ok(tester)
+        })
+        .await;
+        gh.check_cancelled_workflows(default_repo_name(), &[123]);
+    }
+
+    #[sqlx::test]
+    async fn try_keep_running_build_if_not_cancelling_superseded(pool: sqlx::PgPool) {
+        let gh = BorsBuilder::new(pool)
+            .github(
+                GitHubState::default().with_default_config("cancel_superseded_try_builds = false"),
+            )
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .workflow_event(WorkflowEvent::started(
+                        Workflow::from(tester.try_branch()).with_run_id(123),
+                    ))
+                    .await?;
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(tester.get_comment().await?, @":hourglass: Trying commit pr-1-sha with merge merge-main-sha1-pr-1-sha-1…");
+                Ok(tester)
+            })
+            .await;
+        gh.check_cancelled_workflows(default_repo_name(), &[]);
+    }
+
+    #[sqlx::test]
+    async fn try_report_result_of_superseded_build(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+cancel_superseded_try_builds = false
+required_checks = ["Workflow1"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                let old_workflow = Workflow::from(tester.try_branch()).with_run_id(123);
+                tester
+                    .workflow_event(WorkflowEvent::started(old_workflow.clone()))
+                    .await?;
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+
+                tester
+                    .workflow_event(WorkflowEvent::success(old_workflow))
+                    .await?;
+                insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r###"
+                :sunny: Try build successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Workflow1](https://github.com/workflows/Workflow1/123) | :white_check_mark: | [duration] |
+
+                Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+                <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+                "###);
+
+                // The newer try build is still the current build of the PR
+                let pr = tester.default_pr_db().await?.unwrap();
+                let try_build = pr.try_build.unwrap();
+                assert_eq!(try_build.commit_sha, "merge-main-sha1-pr-1-sha-1");
+                assert_eq!(try_build.status, BuildStatus::Pending);
+                // The superseded build only reports its result on its own commit
+                let statuses = commit_statuses(&tester);
+                assert_eq!(
+                    statuses.last(),
+                    Some(&status(
+                        "merge-main-sha1-pr-1-sha-0",
+                        "success",
+                        "Try build succeeded"
+                    ))
+                );
+                assert_eq!(
+                    statuses.iter().rfind(|(sha, _, _)| sha == "pr-1-sha"),
+                    Some(&status("pr-1-sha", "pending", "Try build in progress"))
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_supersede_cancel_error(pool: sqlx::PgPool) {
         run_test(pool, |mut tester| async {
+            tester.default_repo().lock().workflow_cancel_error = true;
             tester.post_comment("@bors try").await?;
             tester.expect_comments(1).await;
+            tester
+                .workflow_event(WorkflowEvent::started(tester.try_branch()))
+                .await?;
             tester.post_comment("@bors try").await?;
-            insta::assert_snapshot!(tester.get_comment().await?, @":exclamation: A try build is currently in progress. You can cancel it using @bors try cancel.");
+            insta::assert_snapshot!(tester.get_comment().await?, @":warning: The previous try build (`merge-main-sha1-pr-1-sha-0`) was superseded by a new try build and cancelled. It was not possible to cancel some of its workflows.");
+            tester.expect_comments(1).await;
             Ok(tester)
         })
         .await;
@@ -602,9 +1633,13 @@ mod tests {
             tester
                 .workflow_success(Workflow::from(tester.try_branch()).with_run_id(2))
                 .await?;
-            insta::assert_snapshot!(tester.get_comment().await?, @r###"
+            insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r###"
             :sunny: Try build successful
-            - [Workflow1](https://github.com/workflows/Workflow1/2) :white_check_mark:
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/2) | :white_check_mark: | [duration] |
+
             Build commit: merge-main-sha1-pr-1-sha-1 (`merge-main-sha1-pr-1-sha-1`)
             <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-1"} -->
             "###);
@@ -812,4 +1847,198 @@ try_failed = ["+foo", "+bar", "-baz"]
             })
             .await;
     }
+
+    #[sqlx::test]
+    async fn try_build_modify_existing_and_missing_labels(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+[labels]
+try = ["+foo"]
+try_succeed = ["+foo", "-missing"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester.workflow_success(tester.try_branch()).await?;
+                insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r###"
+                :sunny: Try build successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+
+                Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+                <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+                "###);
+                let pr = tester
+                    .default_repo()
+                    .lock()
+                    .get_pr(default_pr_number())
+                    .clone();
+                pr.check_added_labels(&["foo", "foo"]);
+                pr.check_removed_labels(&["missing"]);
+                assert_eq!(pr.labels, vec!["foo"]);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    fn gh_state_with_try_limit() -> GitHubState {
+        let state = GitHubState::default().with_default_config(
+            r#"
+max_parallel_try_builds = 1
+"#,
+        );
+        state.default_repo().lock().pull_requests.insert(
+            2,
+            PullRequest::new(default_repo_name(), 2, User::default_pr_author(), false),
+        );
+        state
+    }
+
+    #[sqlx::test]
+    async fn try_queue_commit_status(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_try_limit())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try cancel"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+                assert_eq!(
+                    commit_statuses(&tester),
+                    vec![
+                        status("pr-1-sha", "pending", "Try build in progress"),
+                        status(
+                            "merge-main-sha1-pr-1-sha-0",
+                            "pending",
+                            "Try build in progress"
+                        ),
+                        status("pr-2-sha", "pending", "Try build queued"),
+                        status("pr-2-sha", "error", "Queued try build cancelled"),
+                    ]
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_queue_concurrent_requests(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_try_limit())
+            .run_test(|mut tester| async {
+                // The comments of different PRs are handled concurrently
+                tester.post_comment("@bors try").await?;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try"))
+                    .await?;
+                // Either of the requests can win, but only one of them can be started
+                let comments = [
+                    tester.get_pr_comment(1).await?,
+                    tester.get_pr_comment(2).await?,
+                ];
+                let started = comments
+                    .iter()
+                    .filter(|c| c.starts_with(":hourglass: Trying commit"))
+                    .count();
+                let queued = comments
+                    .iter()
+                    .filter(|c| c.starts_with(":hourglass_flowing_sand: Try build queued behind 1"))
+                    .count();
+                assert_eq!((started, queued), (1, 1), "{comments:?}");
+                assert_eq!(
+                    tester
+                        .db()
+                        .count_pending_builds(&default_repo_name(), TRY_BRANCH_NAME)
+                        .await?,
+                    1
+                );
+                assert_eq!(
+                    tester.db().get_try_queue(&default_repo_name()).await?.len(),
+                    1
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_queue_start_after_build_finishes(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_try_limit())
+            .run_test(|mut tester| async {
+                tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try"))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(2).await?,
+                    @":hourglass_flowing_sand: Try build queued behind 1 try build(s), it will be started once a try build finishes."
+                );
+
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(2).await?,
+                    @":hourglass: Trying commit pr-2-sha with merge merge-main-sha1-pr-2-sha-1…"
+                );
+                assert!(
+                    tester
+                        .db()
+                        .get_try_queue(&default_repo_name())
+                        .await?
+                        .is_empty()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_queue_cancel_queued_build(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_try_limit())
+            .run_test(|mut tester| async {
+                tester.create_branch(TRY_BRANCH_NAME).expect_suites(1);
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try"))
+                    .await?;
+                tester.get_pr_comment(2).await?;
+
+                tester
+                    .post_comment(Comment::new(default_repo_name(), 2, "@bors try cancel"))
+                    .await?;
+                insta::assert_snapshot!(
+                    tester.get_pr_comment(2).await?,
+                    @"Queued try build cancelled."
+                );
+
+                // The cancelled request is not started once the running build finishes
+                tester.workflow_success(tester.try_branch()).await?;
+                tester.expect_comments(1).await;
+                assert!(
+                    tester
+                        .db()
+                        .get_try_queue(&default_repo_name())
+                        .await?
+                        .is_empty()
+                );
+                Ok(tester)
+            })
+            .await;
+    }
 }
@@ -2,22 +2,41 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use crate::PgDbClient;
-use crate::bors::CheckSuiteStatus;
 use crate::bors::RepositoryState;
-use crate::bors::comment::{try_build_succeeded_comment, workflow_failed_comment};
-use crate::bors::event::{CheckSuiteCompleted, WorkflowCompleted, WorkflowStarted};
+use crate::bors::comment::{
+    forced_try_build_succeeded_comment, try_build_succeeded_comment, workflow_failed_comment,
+};
+use crate::bors::event::{
+    CheckRunCompleted, CheckSuiteCompleted, WorkflowCompleted, WorkflowStarted,
+};
+use crate::bors::handlers::auto_build::{AUTO_BRANCH_NAME, complete_merge_build};
+use crate::bors::handlers::chat::{ChatEvent, notify_chat};
+use crate::bors::handlers::check_run::complete_build_check_run;
+use crate::bors::handlers::commit_status::{
+    report_build_completed, report_superseded_build_completed,
+};
 use crate::bors::handlers::is_bors_observed_branch;
+use crate::bors::handlers::job_logs::load_failed_job_logs;
 use crate::bors::handlers::labels::handle_label_trigger;
-use crate::database::{BuildStatus, WorkflowStatus};
+use crate::bors::handlers::notification::notify_build_completed;
+use crate::bors::handlers::status_comment::post_status_comment;
+use crate::bors::handlers::trybuild::{cancel_build_workflows, start_queued_try_builds_locked};
+use crate::bors::{CheckSuite, CheckSuiteStatus};
+use crate::database::{
+    BuildStatus, WorkflowModel, WorkflowStatus, WorkflowStatusCounts, WorkflowType,
+};
 use crate::github::LabelTrigger;
 
 pub(super) async fn handle_workflow_started(
+    repo: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
     payload: WorkflowStarted,
 ) -> anyhow::Result<()> {
     if !is_bors_observed_branch(&payload.branch) {
         return Ok(());
     }
+    // A build that is being cancelled must not get new workflows that would not be cancelled
+    let _guard = repo.try_builds_lock.lock().await;
 
     tracing::info!(
         "Handling workflow started (name={}, url={}, branch={}, commit={})",
@@ -45,16 +64,46 @@ pub(super) async fn handle_workflow_started(
         return Ok(());
     }
 
-    tracing::info!("Storing workflow started into DB");
-    db.create_workflow(
-        &build,
-        payload.name,
-        payload.url,
-        payload.run_id.into(),
-        payload.workflow_type,
-        WorkflowStatus::Pending,
-    )
-    .await?;
+    let known_workflow = db
+        .get_workflows_for_build(&build)
+        .await?
+        .into_iter()
+        .any(|w| w.run_id.0 == *payload.run_id);
+    if !known_workflow {
+        tracing::info!("Storing workflow started into DB");
+        db.create_workflow(
+            &build,
+            payload.name,
+            payload.url,
+            payload.run_id.into(),
+            payload.workflow_type,
+            WorkflowStatus::Pending,
+        )
+        .await?;
+    }
+    if payload.run_attempt == 1 {
+        return Ok(());
+    }
+
+    // The workflow has been re-run, so it is pending again until the new attempt finishes
+    tracing::info!(
+        "Storing attempt {} of workflow into DB",
+        payload.run_attempt
+    );
+    if !db
+        .update_workflow_attempt_status(
+            *payload.run_id,
+            payload.run_attempt,
+            WorkflowStatus::Pending,
+        )
+        .await?
+    {
+        tracing::warn!(
+            "Ignoring outdated attempt {} of workflow {}",
+            payload.run_attempt,
+            payload.run_id
+        );
+    }
 
     Ok(())
 }
@@ -62,40 +111,140 @@ pub(super) async fn handle_workflow_started(
 pub(super) async fn handle_workflow_completed(
     repo: Arc<RepositoryState>,
     db: Arc<PgDbClient>,
-    mut payload: WorkflowCompleted,
+    payload: WorkflowCompleted,
 ) -> anyhow::Result<()> {
-    if !is_bors_observed_branch(&payload.branch) {
+    handle_workflows_completed(repo, db, vec![payload]).await
+}
+
+/// Handles several completed workflows of the same build at once. Their statuses are written to
+/// the database together and the completion of the build is only evaluated once, which avoids
+/// many round trips when GitHub delivers a burst of completed workflows.
+/// The workflows are updated in the given order.
+pub(super) async fn handle_workflows_completed(
+    repo: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    payloads: Vec<WorkflowCompleted>,
+) -> anyhow::Result<()> {
+    let Some(first) = payloads.first() else {
+        return Ok(());
+    };
+    if !is_bors_observed_branch(&first.branch) {
         return Ok(());
     }
+    debug_assert!(
+        payloads
+            .iter()
+            .all(|p| p.branch == first.branch && p.commit_sha == first.commit_sha),
+        "Workflows of different builds cannot be handled together"
+    );
+    let event = CheckSuiteCompleted {
+        repository: first.repository.clone(),
+        branch: first.branch.clone(),
+        commit_sha: first.commit_sha.clone(),
+    };
 
-    if let Some(running_time) = payload.running_time {
-        let running_time_as_duration =
-            chrono::Duration::to_std(&running_time).unwrap_or(Duration::from_secs(0));
-        if let Some(min_ci_time) = repo.config.load().min_ci_time {
-            if running_time_as_duration < min_ci_time {
-                payload.status = WorkflowStatus::Failure;
-                tracing::warn!(
-                    "Workflow running time is less than the minimum CI duration: {:?} < {:?}",
-                    running_time_as_duration,
-                    min_ci_time
-                );
+    let min_ci_time = repo.config.load().min_ci_time;
+    let updates: Vec<_> = payloads
+        .into_iter()
+        .map(|payload| {
+            let mut status = payload.status;
+            if let Some(running_time) = payload.running_time {
+                let running_time_as_duration =
+                    chrono::Duration::to_std(&running_time).unwrap_or(Duration::from_secs(0));
+                if let Some(min_ci_time) = min_ci_time {
+                    if running_time_as_duration < min_ci_time {
+                        status = WorkflowStatus::Failure;
+                        tracing::warn!(
+                            "Workflow running time is less than the minimum CI duration: {:?} < {:?}",
+                            running_time_as_duration,
+                            min_ci_time
+                        );
+                    }
+                }
+            } else {
+                tracing::warn!("Running time is not available.");
             }
-        }
-    } else {
-        tracing::warn!("Running time is not available.");
+
+            tracing::info!(
+                "Updating status of workflow {} (attempt {}) to {:?}",
+                payload.run_id,
+                payload.run_attempt,
+                status
+            );
+            (*payload.run_id, payload.run_attempt, status)
+        })
+        .collect();
+
+    // Try to complete the build
+    let _guard = repo.try_builds_lock.lock().await;
+    try_complete_build(repo.as_ref(), db.as_ref(), event, &updates).await
+}
+
+pub(super) async fn handle_check_run_completed(
+    repo: Arc<RepositoryState>,
+    db: Arc<PgDbClient>,
+    payload: CheckRunCompleted,
+) -> anyhow::Result<()> {
+    if !is_bors_observed_branch(&payload.branch) {
+        return Ok(());
+    }
+
+    tracing::info!(
+        "Handling check run completed (name={}, url={}, branch={}, commit={}, status={:?})",
+        payload.name,
+        payload.url,
+        payload.branch,
+        payload.commit_sha,
+        payload.status
+    );
+
+    let _guard = repo.try_builds_lock.lock().await;
+    let Some(build) = db
+        .find_build(
+            &payload.repository,
+            payload.branch.clone(),
+            payload.commit_sha.clone(),
+        )
+        .await?
+    else {
+        tracing::warn!("Build for check run not found");
+        return Ok(());
+    };
+    if build.status != BuildStatus::Pending {
+        tracing::warn!("Received check run completed for an already completed build");
+        return Ok(());
     }
 
-    tracing::info!("Updating status of workflow to {:?}", payload.status);
-    db.update_workflow_status(*payload.run_id, payload.status)
+    let known_workflow = db
+        .get_workflows_for_build(&build)
+        .await?
+        .into_iter()
+        .any(|w| w.run_id.0 == *payload.run_id);
+    if known_workflow {
+        tracing::info!("Updating status of check run to {:?}", payload.status);
+        db.update_workflow_status(*payload.run_id, payload.status)
+            .await?;
+    } else {
+        // Some tools report check runs that are already completed, so we might not have seen
+        // them being created.
+        tracing::info!("Storing completed check run into DB");
+        db.create_workflow(
+            &build,
+            payload.name,
+            payload.url,
+            payload.run_id.into(),
+            WorkflowType::Check,
+            payload.status,
+        )
         .await?;
+    }
 
-    // Try to complete the build
     let event = CheckSuiteCompleted {
         repository: payload.repository,
         branch: payload.branch,
         commit_sha: payload.commit_sha,
     };
-    try_complete_build(repo.as_ref(), db.as_ref(), event).await
+    try_complete_build(repo.as_ref(), db.as_ref(), event, &[]).await
 }
 
 pub(super) async fn handle_check_suite_completed(
@@ -112,18 +261,30 @@ pub(super) async fn handle_check_suite_completed(
         payload.branch,
         payload.commit_sha
     );
-    try_complete_build(repo.as_ref(), db.as_ref(), payload).await
+    let _guard = repo.try_builds_lock.lock().await;
+    try_complete_build(repo.as_ref(), db.as_ref(), payload, &[]).await
 }
 
 /// Try to complete a pending build.
+/// The statuses of `workflow_updates` are updated first, in the same database transaction that
+/// decides whether the build is completed.
+/// The caller has to hold the try builds lock of the repository. The merge queue lock is
+/// acquired here for merge builds, so that no other merge build is started before the completed
+/// one is merged.
 async fn try_complete_build(
     repo: &RepositoryState,
     db: &PgDbClient,
     payload: CheckSuiteCompleted,
+    workflow_updates: &[(u64, u32, WorkflowStatus)],
 ) -> anyhow::Result<()> {
     if !is_bors_observed_branch(&payload.branch) {
         return Ok(());
     }
+    let _merge_queue_guard = if payload.branch == AUTO_BRANCH_NAME {
+        Some(repo.merge_queue_lock.lock().await)
+    } else {
+        None
+    };
 
     let Some(build) = db
         .find_build(
@@ -140,37 +301,138 @@ async fn try_complete_build(
         return Ok(());
     };
 
-    // If the build has already been marked with a conclusion, ignore this event
     if build.status != BuildStatus::Pending {
+        let reruns = workflow_updates
+            .iter()
+            .filter(|(_, run_attempt, _)| *run_attempt > 1);
+        for (run_id, run_attempt, _) in reruns.clone() {
+            tracing::warn!(
+                "Ignoring attempt {run_attempt} of workflow {run_id}, the build has already been completed"
+            );
+        }
+        if !workflow_updates.is_empty() && reruns.count() == workflow_updates.len() {
+            return Ok(());
+        }
+    }
+
+    let required_checks = repo.config.load().required_checks.clone();
+    let checks = if required_checks.is_empty() && build.status == BuildStatus::Pending {
+        // Ask GitHub what are all the check suites attached to the given commit.
+        // This tells us for how many workflows we should wait.
+        repo.client
+            .get_check_suites_for_commit(&payload.branch, &payload.commit_sha)
+            .await?
+    } else {
+        vec![]
+    };
+
+    // Avoid loading all workflows if the build cannot be finished yet, which is the case for
+    // most workflow events. Required checks are matched by name, so they need the workflows.
+    let may_complete = |counts: &WorkflowStatusCounts| {
+        !required_checks.is_empty()
+            || (!has_pending_check_suite(&checks)
+                && counts.pending == 0
+                && counts.total() >= checks.len())
+    };
+    let Some((status, mut workflows)) = db
+        .update_workflow_and_complete_build(&build, workflow_updates, may_complete, |workflows| {
+            if required_checks.is_empty() {
+                check_suites_build_status(&checks, workflows)
+            } else {
+                required_checks_build_status(&required_checks, workflows)
+            }
+        })
+        .await?
+    else {
         return Ok(());
+    };
+    workflows.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let has_failure = status == BuildStatus::Failure;
+    if has_failure && !required_checks.is_empty() {
+        // The build has failed already, no need to wait for the rest of the workflows
+        if let Err(error) = cancel_build_workflows(&repo.client, db, &build).await {
+            tracing::error!(
+                "Could not cancel workflows for SHA {}: {error:?}",
+                build.commit_sha
+            );
+        }
+    }
+
+    // A try build slot was freed, so a queued try build can be started
+    if let Err(error) = start_queued_try_builds_locked(repo, db).await {
+        tracing::error!("Could not start queued try builds: {error:?}");
     }
 
     let Some(pr) = db.find_pr_by_build(&build).await? else {
         tracing::warn!("Cannot find PR for build {}", build.commit_sha);
         return Ok(());
     };
+    notify_build_completed(repo, &build, pr.number, status);
+    let is_merge_build = build.branch == AUTO_BRANCH_NAME;
+    if has_failure && is_merge_build {
+        notify_chat(
+            repo,
+            ChatEvent::MergeBuildFailed {
+                pr: pr.number,
+                workflows: &workflows,
+            },
+        );
+    }
+    complete_build_check_run(repo, db, &build, status).await;
+    if is_merge_build {
+        report_build_completed(repo, &build, pr.number, status).await;
+        return complete_merge_build(repo, db, &build, &pr, status, &workflows).await;
+    }
 
-    // Ask GitHub what are all the check suites attached to the given commit.
-    // This tells us for how many workflows we should wait.
-    let checks = repo
-        .client
-        .get_check_suites_for_commit(&payload.branch, &payload.commit_sha)
-        .await?;
+    // A try build that was left running after a newer try build of the PR was started (see
+    // `cancel_superseded_try_builds`) only reports its own result, the state of the PR
+    // belongs to the newer build.
+    let is_superseded = pr.try_build.as_ref().is_none_or(|b| b.id != build.id);
+    if is_superseded {
+        tracing::info!("Superseded try build {} has finished", build.commit_sha);
+        report_superseded_build_completed(repo, &build, pr.number, status).await;
+    } else {
+        report_build_completed(repo, &build, pr.number, status).await;
+        let trigger = if has_failure {
+            LabelTrigger::TryBuildFailed
+        } else {
+            LabelTrigger::TryBuildSucceeded
+        };
+        handle_label_trigger(repo, pr.number, trigger).await?;
+    }
 
-    // Some checks are still running, let's wait for the next event
-    if checks
-        .iter()
-        .any(|check| matches!(check.status, CheckSuiteStatus::Pending))
-    {
-        return Ok(());
+    let message = if !has_failure {
+        tracing::info!("Workflow succeeded");
+        if build.force {
+            forced_try_build_succeeded_comment(&workflows, payload.commit_sha, &build.jobs)
+        } else {
+            try_build_succeeded_comment(&workflows, payload.commit_sha, &build.jobs)
+        }
+    } else {
+        tracing::info!("Workflow failed");
+        let logs = load_failed_job_logs(repo, &workflows).await;
+        workflow_failed_comment(&workflows, &logs)
+    };
+    if is_superseded {
+        repo.client.post_comment(pr.number, message).await?;
+    } else {
+        post_status_comment(repo, db, pr.number, message).await?;
     }
 
-    let has_failure = checks
-        .iter()
-        .any(|check| matches!(check.status, CheckSuiteStatus::Failure));
+    Ok(())
+}
 
-    let mut workflows = db.get_workflows_for_build(&build).await?;
-    workflows.sort_by(|a, b| a.name.cmp(&b.name));
+/// Determines the final status of a build based on all check suites attached to its commit.
+/// Returns `None` if the build is not finished yet.
+fn check_suites_build_status(
+    checks: &[CheckSuite],
+    workflows: &[WorkflowModel],
+) -> Option<BuildStatus> {
+    // Some checks are still running, let's wait for the next event
+    if has_pending_check_suite(checks) {
+        return None;
+    }
 
     // If this happens, there is a race condition in GH webhooks and we haven't received a workflow
     // finished/failed event for some workflow yet. In this case, wait for that event before
@@ -181,36 +443,68 @@ async fn try_complete_build(
             .any(|w| w.status == WorkflowStatus::Pending)
     {
         tracing::warn!("All checks are finished, but some workflows are still pending");
-        return Ok(());
+        return None;
     }
 
-    let (status, trigger) = if has_failure {
-        (BuildStatus::Failure, LabelTrigger::TryBuildFailed)
+    if checks
+        .iter()
+        .any(|check| matches!(check.status, CheckSuiteStatus::Failure))
+        || workflows
+            .iter()
+            .any(|w| w.status == WorkflowStatus::ActionRequired)
+    {
+        Some(BuildStatus::Failure)
     } else {
-        (BuildStatus::Success, LabelTrigger::TryBuildSucceeded)
-    };
-    db.update_build_status(&build, status).await?;
+        Some(BuildStatus::Success)
+    }
+}
 
-    handle_label_trigger(repo, pr.number, trigger).await?;
+fn has_pending_check_suite(checks: &[CheckSuite]) -> bool {
+    checks
+        .iter()
+        .any(|check| matches!(check.status, CheckSuiteStatus::Pending))
+}
 
-    let message = if !has_failure {
-        tracing::info!("Workflow succeeded");
-        try_build_succeeded_comment(&workflows, payload.commit_sha)
-    } else {
-        tracing::info!("Workflow failed");
-        workflow_failed_comment(&workflows)
-    };
-    repo.client.post_comment(pr.number, message).await?;
+/// Determines the final status of a build based only on the given set of required checks.
+/// Workflows that are not required do not affect the result.
+/// Returns `None` if some required check is still pending or has not started yet.
+fn required_checks_build_status(
+    required_checks: &[String],
+    workflows: &[WorkflowModel],
+) -> Option<BuildStatus> {
+    let required = |w: &&WorkflowModel| required_checks.contains(&w.name);
+    if workflows.iter().filter(required).any(|w| {
+        matches!(
+            w.status,
+            WorkflowStatus::Failure | WorkflowStatus::ActionRequired
+        )
+    }) {
+        return Some(BuildStatus::Failure);
+    }
 
-    Ok(())
+    let all_succeeded = required_checks.iter().all(|check| {
+        let mut check_workflows = workflows.iter().filter(|w| &w.name == check).peekable();
+        check_workflows.peek().is_some()
+            && check_workflows
+                .all(|w| matches!(w.status, WorkflowStatus::Success | WorkflowStatus::Skipped))
+    });
+    all_succeeded.then_some(BuildStatus::Success)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use crate::PgDbClient;
     use crate::bors::handlers::trybuild::TRY_BRANCH_NAME;
-    use crate::database::WorkflowStatus;
     use crate::database::operations::get_all_workflows;
-    use crate::tests::mocks::{Branch, CheckSuite, Workflow, WorkflowEvent, run_test};
+    use crate::database::{
+        BuildStatus, WorkflowModel, WorkflowStatus, WorkflowStatusCounts, WorkflowType,
+    };
+    use crate::tests::mocks::{
+        BorsBuilder, Branch, CheckSuite, GitHubState, TestWorkflowStatus, User, Workflow,
+        WorkflowEvent, default_repo_name, mask_durations, run_test,
+    };
 
     #[sqlx::test]
     async fn workflow_started_unknown_build(pool: sqlx::PgPool) {
@@ -276,6 +570,132 @@ mod tests {
         assert_eq!(get_all_workflows(&pool).await.unwrap().len(), 2);
     }
 
+    #[sqlx::test]
+    async fn get_pending_workflows_older_than(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester
+                .workflow_event(WorkflowEvent::started(tester.try_branch()))
+                .await?;
+            Ok(tester)
+        })
+        .await;
+        let db = PgDbClient::new(pool);
+        let workflows = db
+            .get_pending_workflows_older_than(Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(workflows.len(), 1);
+        assert_eq!(workflows[0].build.branch, TRY_BRANCH_NAME);
+        assert!(
+            db.get_pending_workflows_older_than(Duration::from_secs(3600))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[sqlx::test]
+    async fn try_success_completed_check_run(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester
+                .check_run_completed(
+                    Workflow::from(tester.try_branch()).make_external(),
+                    TestWorkflowStatus::Success,
+                )
+                .await?;
+            insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r###"
+            :sunny: Try build successful
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://external-ci.com/workflows/1) | :white_check_mark: | [duration] |
+
+            Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+            <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+            "###);
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.workflow_type, WorkflowType::Check);
+    }
+
+    #[sqlx::test]
+    async fn try_failure_started_check_run(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            let workflow = Workflow::from(tester.try_branch()).make_external();
+            tester.check_run_created(workflow.clone()).await?;
+            tester
+                .check_run_completed(workflow, TestWorkflowStatus::Failure)
+                .await?;
+            insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r"
+            :broken_heart: Test failed
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://external-ci.com/workflows/1) | :x: | [duration] |
+            ");
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.workflow_type, WorkflowType::External);
+    }
+
+    #[sqlx::test]
+    async fn try_neutral_check_run_is_skipped(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            let workflow = Workflow::from(tester.try_branch()).make_external();
+            tester.check_run_created(workflow.clone()).await?;
+            tester
+                .check_run_completed_with_conclusion(workflow, "neutral")
+                .await?;
+            assert!(
+                tester
+                    .get_comment()
+                    .await?
+                    .starts_with(":sunny: Try build successful")
+            );
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.status, WorkflowStatus::Skipped);
+        assert_eq!(workflow.build.status, BuildStatus::Success);
+    }
+
+    #[sqlx::test]
+    async fn try_action_required_check_run_fails_build(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            let workflow = Workflow::from(tester.try_branch()).make_external();
+            tester.check_run_created(workflow.clone()).await?;
+            tester
+                .check_run_completed_with_conclusion(workflow, "action_required")
+                .await?;
+            assert!(
+                tester
+                    .get_comment()
+                    .await?
+                    .starts_with(":broken_heart: Test failed")
+            );
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.status, WorkflowStatus::ActionRequired);
+        assert_eq!(workflow.build.status, BuildStatus::Failure);
+    }
+
     #[sqlx::test]
     async fn try_check_suite_finished_missing_build(pool: sqlx::PgPool) {
         run_test(pool.clone(), |mut tester| async {
@@ -290,6 +710,194 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn try_rerun_after_failure_is_ignored(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester.workflow_failure(tester.try_branch()).await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r###"
+            :broken_heart: Test failed
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :x: | [duration] |
+            "###
+            );
+
+            let rerun = Workflow::from(tester.try_branch()).with_run_attempt(2);
+            tester
+                .workflow_event(WorkflowEvent::started(rerun.clone()))
+                .await?;
+            tester.workflow_event(WorkflowEvent::success(rerun)).await?;
+            tester
+                .check_suite(CheckSuite::completed(tester.try_branch()))
+                .await?;
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.status, WorkflowStatus::Failure);
+        assert_eq!(workflow.run_attempt, 1);
+        assert_eq!(workflow.build.status, BuildStatus::Failure);
+    }
+
+    #[sqlx::test]
+    async fn try_rerun_success_before_outdated_failure(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(2);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+
+            let workflow = Workflow::from(tester.try_branch());
+            let rerun = workflow.clone().with_run_attempt(2);
+            tester
+                .workflow_event(WorkflowEvent::started(workflow.clone()))
+                .await?;
+            tester
+                .workflow_event(WorkflowEvent::started(rerun.clone()))
+                .await?;
+            tester.workflow_event(WorkflowEvent::success(rerun)).await?;
+            // The failure of the first attempt arrives after the second attempt has succeeded
+            tester
+                .late_workflow_event(WorkflowEvent::failure(workflow))
+                .await?;
+            tester
+                .workflow_success(Workflow::from(tester.try_branch()).with_run_id(2))
+                .await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r###"
+            :sunny: Try build successful
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+            | [Workflow1](https://github.com/workflows/Workflow1/2) | :white_check_mark: | [duration] |
+
+            Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+            <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+            "###
+            );
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|w| w.run_id.0 == 1)
+            .unwrap();
+        assert_eq!(workflow.status, WorkflowStatus::Success);
+        assert_eq!(workflow.run_attempt, 2);
+    }
+
+    #[sqlx::test]
+    async fn try_skipped_workflow_does_not_block_build(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.create_branch(TRY_BRANCH_NAME).expect_suites(2);
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            tester
+                .workflow_success(Workflow::from(tester.try_branch()).with_run_id(1))
+                .await?;
+            let skipped = Workflow::from(tester.try_branch())
+                .with_name("Docs")
+                .with_run_id(2);
+            tester
+                .workflow_event(WorkflowEvent::started(skipped.clone()))
+                .await?;
+            tester
+                .workflow_event(WorkflowEvent::completed(skipped, "skipped"))
+                .await?;
+            tester
+                .check_suite(CheckSuite::completed(tester.try_branch()))
+                .await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r###"
+            :sunny: Try build successful
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+            | [Docs](https://github.com/workflows/Docs/2) | :fast_forward: | [duration] |
+
+            Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+            <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+            "###
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn try_neutral_workflow_is_skipped(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            let workflow = Workflow::from(tester.try_branch());
+            tester
+                .workflow_event(WorkflowEvent::started(workflow.clone()))
+                .await?;
+            tester
+                .workflow_event(WorkflowEvent::completed(workflow, "neutral"))
+                .await?;
+            tester
+                .check_suite(CheckSuite::completed(tester.try_branch()))
+                .await?;
+            assert!(
+                tester
+                    .get_comment()
+                    .await?
+                    .starts_with(":sunny: Try build successful")
+            );
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.status, WorkflowStatus::Skipped);
+        assert_eq!(workflow.build.status, BuildStatus::Success);
+    }
+
+    #[sqlx::test]
+    async fn try_action_required_workflow_fails_build(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            let workflow = Workflow::from(tester.try_branch());
+            tester
+                .workflow_event(WorkflowEvent::started(workflow.clone()))
+                .await?;
+            tester
+                .workflow_event(WorkflowEvent::completed(workflow, "action_required"))
+                .await?;
+            tester
+                .check_suite(CheckSuite::completed(tester.try_branch()))
+                .await?;
+            insta::assert_snapshot!(
+                mask_durations(&tester.get_comment().await?),
+                @r###"
+            :broken_heart: Test failed
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :raised_hand: | [duration] |
+
+            :raised_hand: [Workflow1](https://github.com/workflows/Workflow1/1) is waiting for a maintainer to approve the workflow run. Approve it on GitHub and then retry the build.
+            "###
+            );
+            Ok(tester)
+        })
+        .await;
+        let workflow = get_all_workflows(&pool).await.unwrap().pop().unwrap();
+        assert_eq!(workflow.status, WorkflowStatus::ActionRequired);
+        assert_eq!(workflow.build.status, BuildStatus::Failure);
+    }
+
     #[sqlx::test]
     async fn try_success_multiple_suites(pool: sqlx::PgPool) {
         run_test(pool.clone(), |mut tester| async {
@@ -303,11 +911,15 @@ mod tests {
                 .workflow_success(Workflow::from(tester.try_branch()).with_run_id(2))
                 .await?;
             insta::assert_snapshot!(
-                tester.get_comment().await?,
+                mask_durations(&tester.get_comment().await?),
                 @r###"
             :sunny: Try build successful
-            - [Workflow1](https://github.com/workflows/Workflow1/1) :white_check_mark:
-            - [Workflow1](https://github.com/workflows/Workflow1/2) :white_check_mark:
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+            | [Workflow1](https://github.com/workflows/Workflow1/2) | :white_check_mark: | [duration] |
+
             Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
             <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
             "###
@@ -330,11 +942,14 @@ mod tests {
                 .workflow_failure(Workflow::from(tester.try_branch()).with_run_id(2))
                 .await?;
             insta::assert_snapshot!(
-                tester.get_comment().await?,
+                mask_durations(&tester.get_comment().await?),
                 @r###"
             :broken_heart: Test failed
-            - [Workflow1](https://github.com/workflows/Workflow1/1) :white_check_mark:
-            - [Workflow1](https://github.com/workflows/Workflow1/2) :x:
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/2) | :x: | [duration] |
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
             "###
             );
             Ok(tester)
@@ -342,6 +957,61 @@ mod tests {
         .await;
     }
 
+    #[sqlx::test]
+    async fn try_forced_build_ignores_failure(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+required_checks = ["CI"]
+"#,
+            ))
+            .run_test(|mut tester| async {
+                tester
+                    .default_repo()
+                    .lock()
+                    .admins
+                    .push(User::default_pr_author().name);
+                tester.create_branch(TRY_BRANCH_NAME).expect_suites(2);
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester.post_comment("@bors r+ force").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .workflow_failure(
+                        Workflow::from(tester.try_branch())
+                            .with_name("Docs")
+                            .with_run_id(1),
+                    )
+                    .await?;
+                tester
+                    .workflow_success(
+                        Workflow::from(tester.try_branch())
+                            .with_name("CI")
+                            .with_run_id(2),
+                    )
+                    .await?;
+                insta::assert_snapshot!(
+                    mask_durations(&tester.get_comment().await?),
+                    @r###"
+                :sunny: Try build successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Docs](https://github.com/workflows/Docs/1) | :x: | [duration] |
+                | [CI](https://github.com/workflows/CI/2) | :white_check_mark: | [duration] |
+
+                Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+                :warning: **This build was forced**, failures of checks that are not required were ignored.
+                Ignored failures:
+                - [Docs](https://github.com/workflows/Docs/1)
+                <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+                "###
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
     #[sqlx::test]
     async fn try_suite_completed_received_before_workflow_completed(pool: sqlx::PgPool) {
         run_test(pool.clone(), |mut tester| async {
@@ -362,10 +1032,14 @@ mod tests {
                 .workflow_event(WorkflowEvent::success(branch))
                 .await?;
             insta::assert_snapshot!(
-                tester.get_comment().await?,
+                mask_durations(&tester.get_comment().await?),
                 @r###"
             :sunny: Try build successful
-            - [Workflow1](https://github.com/workflows/Workflow1/1) :white_check_mark:
+
+            | Workflow | Status | Duration |
+            |---|---|---|
+            | [Workflow1](https://github.com/workflows/Workflow1/1) | :white_check_mark: | [duration] |
+
             Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
             <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
             "###
@@ -390,4 +1064,294 @@ mod tests {
         })
         .await;
     }
+
+    fn gh_state_with_required_checks() -> GitHubState {
+        GitHubState::default().with_default_config(
+            r#"
+required_checks = ["CI"]
+"#,
+        )
+    }
+
+    #[sqlx::test]
+    async fn try_required_checks_ignore_optional_failure(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_required_checks())
+            .run_test(|mut tester| async move {
+                tester.create_branch(TRY_BRANCH_NAME).expect_suites(2);
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .workflow_failure(
+                        Workflow::from(tester.try_branch())
+                            .with_name("Docs")
+                            .with_run_id(1),
+                    )
+                    .await?;
+                tester
+                    .workflow_success(
+                        Workflow::from(tester.try_branch())
+                            .with_name("CI")
+                            .with_run_id(2),
+                    )
+                    .await?;
+                insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r###"
+                :sunny: Try build successful
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [Docs](https://github.com/workflows/Docs/1) | :x: | [duration] |
+                | [CI](https://github.com/workflows/CI/2) | :white_check_mark: | [duration] |
+
+                Build commit: merge-main-sha1-pr-1-sha-0 (`merge-main-sha1-pr-1-sha-0`)
+                <!-- homu: {"type":"TryBuildCompleted","merge_sha":"merge-main-sha1-pr-1-sha-0"} -->
+                "###);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_required_check_not_reported_keeps_build_pending(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(GitHubState::default().with_default_config(
+                r#"
+required_checks = ["CI", "Docs"]
+"#,
+            ))
+            .run_test(|mut tester| async move {
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .workflow_success(Workflow::from(tester.try_branch()).with_name("CI"))
+                    .await?;
+                // No comment is posted, the build waits for `Docs` (or for the timeout)
+                tester
+                    .wait_for(|| async {
+                        let Some(pr) = tester.default_pr_db().await? else {
+                            return Ok(false);
+                        };
+                        let Some(build) = pr.try_build else {
+                            return Ok(false);
+                        };
+                        let workflows = tester.db().get_workflows_for_build(&build).await?;
+                        Ok(workflows
+                            .iter()
+                            .any(|w| w.status == WorkflowStatus::Success))
+                    })
+                    .await?;
+                let build = tester.default_pr_db().await?.unwrap().try_build.unwrap();
+                assert_eq!(build.status, BuildStatus::Pending);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn try_required_check_failure_fails_build_immediately(pool: sqlx::PgPool) {
+        let gh = BorsBuilder::new(pool)
+            .github(gh_state_with_required_checks())
+            .run_test(|mut tester| async move {
+                tester.create_branch(TRY_BRANCH_NAME).expect_suites(2);
+                tester.post_comment("@bors try").await?;
+                tester.expect_comments(1).await;
+                tester
+                    .workflow_event(WorkflowEvent::started(
+                        Workflow::from(tester.try_branch())
+                            .with_name("Docs")
+                            .with_run_id(1),
+                    ))
+                    .await?;
+                tester
+                    .workflow_failure(
+                        Workflow::from(tester.try_branch())
+                            .with_name("CI")
+                            .with_run_id(2),
+                    )
+                    .await?;
+                insta::assert_snapshot!(mask_durations(&tester.get_comment().await?), @r"
+                :broken_heart: Test failed
+
+                | Workflow | Status | Duration |
+                |---|---|---|
+                | [CI](https://github.com/workflows/CI/2) | :x: | [duration] |
+                | [Docs](https://github.com/workflows/Docs/1) | :hourglass_flowing_sand: | - |
+                ");
+                Ok(tester)
+            })
+            .await;
+        gh.check_cancelled_workflows(default_repo_name(), &[1]);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_workflow_updates_complete_build_once(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            for run_id in [1, 2] {
+                tester
+                    .workflow_event(WorkflowEvent::started(
+                        Workflow::from(tester.try_branch()).with_run_id(run_id),
+                    ))
+                    .await?;
+            }
+            Ok(tester)
+        })
+        .await;
+
+        let db = PgDbClient::new(pool.clone());
+        let build = get_all_workflows(&pool).await.unwrap().pop().unwrap().build;
+        let complete = |workflows: &[WorkflowModel]| {
+            workflows
+                .iter()
+                .all(|w| w.status == WorkflowStatus::Success)
+                .then_some(BuildStatus::Success)
+        };
+        let (first, second) = tokio::join!(
+            db.update_workflow_and_complete_build(
+                &build,
+                &[(1, 1, WorkflowStatus::Success)],
+                |_| true,
+                complete
+            ),
+            db.update_workflow_and_complete_build(
+                &build,
+                &[(2, 1, WorkflowStatus::Success)],
+                |_| true,
+                complete
+            )
+        );
+        let completed = [first.unwrap(), second.unwrap()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].0, BuildStatus::Success);
+        assert!(
+            db.get_running_builds(&default_repo_name())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    async fn start_workflows(pool: sqlx::PgPool, run_ids: std::ops::RangeInclusive<u64>) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors try").await?;
+            tester.expect_comments(1).await;
+            for run_id in run_ids {
+                tester
+                    .workflow_event(WorkflowEvent::started(
+                        Workflow::from(tester.try_branch()).with_run_id(run_id),
+                    ))
+                    .await?;
+            }
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[tracing_test::traced_test]
+    #[sqlx::test]
+    async fn workflow_burst_updated_with_single_query(pool: sqlx::PgPool) {
+        start_workflows(pool.clone(), 1..=10).await;
+
+        let db = PgDbClient::new(pool.clone());
+        let build = get_all_workflows(&pool).await.unwrap().pop().unwrap().build;
+        let updates: Vec<_> = (1..=10)
+            .map(|run_id| (run_id, 1, WorkflowStatus::Success))
+            .collect();
+        let completed = db
+            .update_workflow_and_complete_build(
+                &build,
+                &updates,
+                |_| true,
+                |workflows| {
+                    workflows
+                        .iter()
+                        .all(|w| w.status == WorkflowStatus::Success)
+                        .then_some(BuildStatus::Success)
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(completed.unwrap().0, BuildStatus::Success);
+
+        logs_assert(|lines| {
+            let queries = lines
+                .iter()
+                .filter(|line| {
+                    line.contains("db_query:update_workflow_attempt_statuses")
+                        && line.contains("Starting operation")
+                })
+                .count();
+            match queries {
+                1 => Ok(()),
+                n => Err(format!("expected a single update query, found {n}")),
+            }
+        });
+    }
+
+    #[sqlx::test]
+    async fn workflow_updates_of_same_run_applied_in_order(pool: sqlx::PgPool) {
+        start_workflows(pool.clone(), 1..=2).await;
+
+        let db = PgDbClient::new(pool.clone());
+        let build = get_all_workflows(&pool).await.unwrap().pop().unwrap().build;
+        db.update_workflow_and_complete_build(
+            &build,
+            &[
+                (1, 1, WorkflowStatus::Failure),
+                (1, 1, WorkflowStatus::Success),
+                (2, 2, WorkflowStatus::Success),
+                (2, 1, WorkflowStatus::Failure),
+            ],
+            |_| true,
+            |_| None,
+        )
+        .await
+        .unwrap();
+
+        let workflows = get_all_workflows(&pool).await.unwrap();
+        for run_id in [1, 2] {
+            let workflow = workflows.iter().find(|w| w.run_id.0 == run_id).unwrap();
+            assert_eq!(workflow.status, WorkflowStatus::Success);
+        }
+    }
+
+    #[sqlx::test]
+    async fn load_workflows_only_if_build_may_complete(pool: sqlx::PgPool) {
+        start_workflows(pool.clone(), 1..=3).await;
+
+        let db = PgDbClient::new(pool.clone());
+        let build = get_all_workflows(&pool).await.unwrap().pop().unwrap().build;
+        let completed = db
+            .update_workflow_and_complete_build(
+                &build,
+                &[
+                    (1, 1, WorkflowStatus::Success),
+                    (2, 1, WorkflowStatus::Failure),
+                ],
+                |counts| {
+                    assert_eq!(
+                        *counts,
+                        WorkflowStatusCounts {
+                            pending: 1,
+                            success: 1,
+                            failure: 1,
+                            ..Default::default()
+                        }
+                    );
+                    false
+                },
+                |_| panic!("Workflows should not be loaded"),
+            )
+            .await
+            .unwrap();
+        assert!(completed.is_none());
+        assert_eq!(
+            db.get_workflow_status_counts(&build).await.unwrap().total(),
+            3
+        );
+    }
 }
@@ -0,0 +1,288 @@
+//! Queue of a repository in the format of the Homu queue page, so that dashboards written for
+//! Homu keep working against bors.
+use serde::Serialize;
+
+use crate::bors::RollupMode;
+use crate::bors::merge_queue::merge_queue_order;
+use crate::database::{BuildStatus, MergeableState, PullRequestModel};
+
+/// A single pull request of the queue, with the field names and values used by Homu.
+#[derive(Debug, Serialize)]
+pub struct HomuQueueEntry {
+    pub number: u64,
+    /// Homu status of the PR, see [homu_status].
+    pub status: &'static str,
+    pub priority: i32,
+    /// `None` if GitHub has not computed the mergeability of the PR yet.
+    pub mergeable: Option<bool>,
+    /// Reviewer(s) that have approved the PR, or an empty string.
+    pub approved_by: String,
+    /// Homu stored the rollup mode as a number: always = 1, maybe = 0, iffy = -1, never = -2.
+    pub rollup: i32,
+}
+
+/// Homu status of a PR whose merge build has the given status.
+/// Homu did not distinguish timeouts from failures, and it reported cancelled builds as
+/// errors.
+const BUILD_STATUSES: [(BuildStatus, &str); 5] = [
+    (BuildStatus::Pending, "pending"),
+    (BuildStatus::Success, "success"),
+    (BuildStatus::Failure, "failure"),
+    (BuildStatus::Timeouted, "failure"),
+    (BuildStatus::Cancelled, "error"),
+];
+
+/// Returns the open PRs in the order of the merge queue, converted to the format of Homu.
+pub fn homu_queue(mut prs: Vec<PullRequestModel>) -> Vec<HomuQueueEntry> {
+    prs.sort_by(merge_queue_order);
+    prs.iter()
+        .map(|pr| HomuQueueEntry {
+            number: pr.number.0,
+            status: homu_status(pr),
+            priority: pr.priority.unwrap_or(0),
+            mergeable: match pr.mergeable_state {
+                MergeableState::Mergeable => Some(true),
+                MergeableState::HasConflicts => Some(false),
+                MergeableState::Unknown => None,
+            },
+            approved_by: pr.approver().unwrap_or_default().to_string(),
+            rollup: match pr.rollup.unwrap_or(RollupMode::Maybe) {
+                RollupMode::Always => 1,
+                RollupMode::Maybe => 0,
+                RollupMode::Iffy => -1,
+                RollupMode::Never => -2,
+            },
+        })
+        .collect()
+}
+
+/// Maps the state of a PR to the Homu status: an empty string for PRs that are not approved,
+/// `approved` for approved PRs without a merge build, and the status of the merge build from
+/// [BUILD_STATUSES] otherwise.
+fn homu_status(pr: &PullRequestModel) -> &'static str {
+    if !pr.has_approval() {
+        return "";
+    }
+    let Some(build) = &pr.merge_build else {
+        return "approved";
+    };
+    BUILD_STATUSES
+        .iter()
+        .find(|(status, _)| *status == build.status)
+        .map(|(_, homu_status)| *homu_status)
+        .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use crate::bors::{PullRequestStatus, RollupMode};
+    use crate::database::{ApprovalInfo, BuildStatus, MergeableState, PgDbClient};
+    use crate::github::{CommitSha, PullRequestNumber};
+    use crate::tests::mocks::{default_repo_name, run_test};
+
+    struct SeededPr {
+        number: u64,
+        mergeable: MergeableState,
+        approval: Option<(Option<u32>, Option<RollupMode>)>,
+        merge_build: Option<BuildStatus>,
+    }
+
+    async fn seed_pr(db: &PgDbClient, seeded: SeededPr) -> anyhow::Result<()> {
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(
+                &repo,
+                PullRequestNumber(seeded.number),
+                "main",
+                seeded.mergeable,
+                &PullRequestStatus::Open,
+                "author",
+            )
+            .await?;
+        if let Some((priority, rollup)) = seeded.approval {
+            let approval = ApprovalInfo {
+                approver: "reviewer".to_string(),
+                sha: format!("pr-{}-sha", seeded.number),
+                base_sha: None,
+            };
+            db.approve(&pr, approval, priority, rollup).await?;
+        }
+        if let Some(status) = seeded.merge_build {
+            db.attach_merge_build(
+                &pr,
+                "automation/bors/auto".to_string(),
+                CommitSha(format!("merge-{}-sha", seeded.number)),
+                CommitSha("main-sha1".to_string()),
+                CommitSha(format!("pr-{}-sha", seeded.number)),
+            )
+            .await?;
+            let pr = db
+                .get_pull_request(&repo, PullRequestNumber(seeded.number))
+                .await?
+                .unwrap();
+            db.update_build_status(&pr.merge_build.unwrap(), status)
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn queue_json(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let db = tester.db();
+            let prs = [
+                (MergeableState::Unknown, None, None),
+                (
+                    MergeableState::Mergeable,
+                    Some((None, Some(RollupMode::Always))),
+                    None,
+                ),
+                (
+                    MergeableState::Mergeable,
+                    Some((Some(10), None)),
+                    Some(BuildStatus::Pending),
+                ),
+                (
+                    MergeableState::HasConflicts,
+                    Some((Some(5), Some(RollupMode::Never))),
+                    Some(BuildStatus::Success),
+                ),
+                (
+                    MergeableState::Mergeable,
+                    Some((None, Some(RollupMode::Iffy))),
+                    Some(BuildStatus::Failure),
+                ),
+                (
+                    MergeableState::Mergeable,
+                    Some((None, None)),
+                    Some(BuildStatus::Timeouted),
+                ),
+                (
+                    MergeableState::Mergeable,
+                    Some((None, None)),
+                    Some(BuildStatus::Cancelled),
+                ),
+            ];
+            for (number, (mergeable, approval, merge_build)) in prs.into_iter().enumerate() {
+                seed_pr(
+                    &db,
+                    SeededPr {
+                        number: number as u64 + 1,
+                        mergeable,
+                        approval,
+                        merge_build,
+                    },
+                )
+                .await?;
+            }
+            let (status, body) = tester.get_endpoint("/queue/rust-lang/borstest").await?;
+            assert_eq!(status, StatusCode::OK);
+            let queue: serde_json::Value = serde_json::from_str(&body)?;
+            insta::assert_snapshot!(serde_json::to_string_pretty(&queue)?, @r#"
+            [
+              {
+                "approved_by": "reviewer",
+                "mergeable": true,
+                "number": 3,
+                "priority": 10,
+                "rollup": 0,
+                "status": "pending"
+              },
+              {
+                "approved_by": "reviewer",
+                "mergeable": false,
+                "number": 4,
+                "priority": 5,
+                "rollup": -2,
+                "status": "success"
+              },
+              {
+                "approved_by": "",
+                "mergeable": null,
+                "number": 1,
+                "priority": 0,
+                "rollup": 0,
+                "status": ""
+              },
+              {
+                "approved_by": "reviewer",
+                "mergeable": true,
+                "number": 2,
+                "priority": 0,
+                "rollup": 1,
+                "status": "approved"
+              },
+              {
+                "approved_by": "reviewer",
+                "mergeable": true,
+                "number": 5,
+                "priority": 0,
+                "rollup": -1,
+                "status": "failure"
+              },
+              {
+                "approved_by": "reviewer",
+                "mergeable": true,
+                "number": 6,
+                "priority": 0,
+                "rollup": 0,
+                "status": "failure"
+              },
+              {
+                "approved_by": "reviewer",
+                "mergeable": true,
+                "number": 7,
+                "priority": 0,
+                "rollup": 0,
+                "status": "error"
+              }
+            ]
+            "#);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn queue_json_closed_prs(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let db = tester.db();
+            for number in [1, 2] {
+                seed_pr(
+                    &db,
+                    SeededPr {
+                        number,
+                        mergeable: MergeableState::Mergeable,
+                        approval: Some((None, None)),
+                        merge_build: None,
+                    },
+                )
+                .await?;
+            }
+            db.set_pr_status(
+                &default_repo_name(),
+                PullRequestNumber(1),
+                PullRequestStatus::Merged,
+            )
+            .await?;
+
+            let (status, body) = tester.get_endpoint("/queue/rust-lang/borstest").await?;
+            assert_eq!(status, StatusCode::OK);
+            insta::assert_snapshot!(body, @r#"[{"number":2,"status":"approved","priority":0,"mergeable":true,"approved_by":"reviewer","rollup":0}]"#);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn queue_json_invalid_repo_name(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester.get_endpoint("/queue/foo_bar/baz").await?;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            Ok(tester)
+        })
+        .await;
+    }
+}
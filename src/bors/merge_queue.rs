@@ -0,0 +1,303 @@
+//! Selection of the pull request that should be tested next by a merge build.
+//!
+//! The selection only depends on the state of the given PRs, so that the ordering can be tested
+//! without a database or GitHub. Merge builds of the selected PRs are started by the merge queue
+//! handlers.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::bors::PullRequestStatus;
+use crate::database::{BuildStatus, MergeableState, PullRequestModel};
+use crate::github::CommitSha;
+
+/// Returns the PR that should be tested next, or `None` if no PR is waiting in the queue.
+/// Only open PRs approved at their current head commit and without a running merge build are
+/// taken into account.
+/// PRs that GitHub reports to have merge conflicts are skipped, a merge build would fail
+/// anyway. They return to the queue once their mergeable state changes, if they are still
+/// approved.
+/// The PRs are ordered by [merge_queue_order].
+pub(crate) fn select_next_pr(prs: &[PullRequestModel]) -> Option<&PullRequestModel> {
+    prs.iter()
+        .filter(|pr| is_waiting_in_queue(pr))
+        .min_by(|a, b| merge_queue_order(a, b))
+}
+
+/// Same as [select_next_pr], but the authors of PRs with the same priority take turns: the PR of
+/// the author with the fewest recent merge builds (`builds_per_author`) is selected first.
+/// PRs with an unknown author are treated as if they had the same (empty) author.
+pub(crate) fn select_next_pr_fair<'a>(
+    prs: &'a [PullRequestModel],
+    builds_per_author: &HashMap<String, u64>,
+) -> Option<&'a PullRequestModel> {
+    let builds = |pr: &PullRequestModel| {
+        builds_per_author
+            .get(pr.author.as_deref().unwrap_or_default())
+            .copied()
+            .unwrap_or(0)
+    };
+    prs.iter()
+        .filter(|pr| is_waiting_in_queue(pr))
+        .min_by(|a, b| {
+            b.priority
+                .unwrap_or(0)
+                .cmp(&a.priority.unwrap_or(0))
+                .then_with(|| builds(a).cmp(&builds(b)))
+                .then_with(|| merge_queue_order(a, b))
+        })
+}
+
+/// Compares PRs in the order in which they should be merged: PRs with a higher priority
+/// go first (a missing priority counts as zero), then PRs that were created earlier. Ties are
+/// broken by the PR number, so that the order is always fully determined.
+pub(crate) fn merge_queue_order(a: &PullRequestModel, b: &PullRequestModel) -> Ordering {
+    b.priority
+        .unwrap_or(0)
+        .cmp(&a.priority.unwrap_or(0))
+        .then_with(|| a.created_at.cmp(&b.created_at))
+        .then_with(|| a.number.0.cmp(&b.number.0))
+}
+
+fn is_waiting_in_queue(pr: &PullRequestModel) -> bool {
+    pr.pr_status == PullRequestStatus::Open
+        && pr
+            .head_sha
+            .clone()
+            .is_some_and(|sha| pr.is_approved(&CommitSha(sha)))
+        && pr.mergeable_state != MergeableState::HasConflicts
+        && !pr
+            .merge_build
+            .as_ref()
+            .is_some_and(|build| build.status == BuildStatus::Pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use crate::bors::PullRequestStatus;
+    use crate::database::{
+        ApprovalInfo, ApprovalStatus, BuildModel, BuildStatus, MergeableState, PullRequestModel,
+    };
+    use crate::github::{GithubRepoName, PullRequestNumber};
+
+    use super::{select_next_pr, select_next_pr_fair};
+
+    fn time(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, 12, minute, 0).unwrap()
+    }
+
+    fn pr(number: u64, priority: Option<i32>, created_at: DateTime<Utc>) -> PullRequestModel {
+        PullRequestModel {
+            id: number as i32,
+            repository: GithubRepoName::new("foo", "bar"),
+            number: PullRequestNumber(number),
+            author: Some("author".to_string()),
+            title: None,
+            pr_status: PullRequestStatus::Open,
+            base_branch: "main".to_string(),
+            head_sha: Some(format!("pr-{number}-sha")),
+            labels: vec![],
+            mergeable_state: MergeableState::Mergeable,
+            approval_status: ApprovalStatus::Approved(ApprovalInfo {
+                approver: "reviewer".to_string(),
+                sha: format!("pr-{number}-sha"),
+                base_sha: None,
+            }),
+            delegated_permission: None,
+            priority,
+            rollup: None,
+            squash: false,
+            try_build: None,
+            merge_build: None,
+            created_at,
+        }
+    }
+
+    fn merge_build(status: BuildStatus) -> BuildModel {
+        BuildModel {
+            id: 1,
+            repository: GithubRepoName::new("foo", "bar"),
+            branch: "automation/bors/auto".to_string(),
+            commit_sha: "merge-sha".to_string(),
+            status,
+            parent: "main-sha".to_string(),
+            created_at: time(0),
+            force: false,
+            check_run_id: None,
+            head_sha: None,
+            completed_at: None,
+            branches_deleted: false,
+            pull_request_id: None,
+            jobs: vec![],
+        }
+    }
+
+    fn next(prs: &[PullRequestModel]) -> Option<u64> {
+        select_next_pr(prs).map(|pr| pr.number.0)
+    }
+
+    fn next_fair(prs: &[PullRequestModel], recent: &[(&str, u64)]) -> Option<u64> {
+        let recent = recent
+            .iter()
+            .map(|(author, count)| (author.to_string(), *count))
+            .collect::<HashMap<_, _>>();
+        select_next_pr_fair(prs, &recent).map(|pr| pr.number.0)
+    }
+
+    fn authored(mut pr: PullRequestModel, author: Option<&str>) -> PullRequestModel {
+        pr.author = author.map(|author| author.to_string());
+        pr
+    }
+
+    #[test]
+    fn empty_queue() {
+        assert_eq!(next(&[]), None);
+    }
+
+    #[test]
+    fn higher_priority_first() {
+        let prs = [
+            pr(1, None, time(0)),
+            pr(2, Some(10), time(5)),
+            pr(3, Some(5), time(1)),
+        ];
+        assert_eq!(next(&prs), Some(2));
+    }
+
+    #[test]
+    fn missing_priority_is_zero() {
+        let prs = [pr(1, Some(0), time(5)), pr(2, None, time(0))];
+        assert_eq!(next(&prs), Some(2));
+        let prs = [pr(1, Some(-1), time(0)), pr(2, None, time(5))];
+        assert_eq!(next(&prs), Some(2));
+    }
+
+    #[test]
+    fn older_first_on_priority_tie() {
+        let prs = [
+            pr(1, Some(3), time(10)),
+            pr(2, Some(3), time(2)),
+            pr(3, Some(1), time(0)),
+        ];
+        assert_eq!(next(&prs), Some(2));
+    }
+
+    #[test]
+    fn lower_number_first_on_full_tie() {
+        let prs = [
+            pr(7, Some(1), time(3)),
+            pr(4, Some(1), time(3)),
+            pr(5, Some(1), time(3)),
+        ];
+        assert_eq!(next(&prs), Some(4));
+    }
+
+    #[test]
+    fn independent_of_input_order() {
+        let mut prs = vec![
+            pr(3, None, time(1)),
+            pr(1, None, time(1)),
+            pr(2, Some(2), time(9)),
+            pr(4, Some(2), time(9)),
+        ];
+        assert_eq!(next(&prs), Some(2));
+        prs.reverse();
+        assert_eq!(next(&prs), Some(2));
+    }
+
+    #[test]
+    fn skip_unapproved_and_closed() {
+        let mut unapproved = pr(1, Some(10), time(0));
+        unapproved.approval_status = ApprovalStatus::NotApproved;
+        let mut closed = pr(2, Some(10), time(0));
+        closed.pr_status = PullRequestStatus::Closed;
+        let mut draft = pr(3, Some(10), time(0));
+        draft.pr_status = PullRequestStatus::Draft;
+        let prs = [unapproved, closed, draft, pr(4, None, time(5))];
+        assert_eq!(next(&prs), Some(4));
+    }
+
+    #[test]
+    fn skip_pr_approved_at_older_commit() {
+        let mut outdated = pr(1, Some(10), time(0));
+        outdated.head_sha = Some("pr-1-new-sha".to_string());
+        let mut unknown_head = pr(2, Some(10), time(0));
+        unknown_head.head_sha = None;
+        let prs = [outdated, unknown_head, pr(3, None, time(5))];
+        assert_eq!(next(&prs), Some(3));
+    }
+
+    #[test]
+    fn skip_pr_with_running_merge_build() {
+        let mut running = pr(1, Some(10), time(0));
+        running.merge_build = Some(merge_build(BuildStatus::Pending));
+        let mut failed = pr(2, Some(5), time(0));
+        failed.merge_build = Some(merge_build(BuildStatus::Failure));
+        let prs = [running, failed, pr(3, None, time(0))];
+        assert_eq!(next(&prs), Some(2));
+    }
+
+    #[test]
+    fn skip_pr_with_conflicts() {
+        let mut conflicting = pr(1, Some(10), time(0));
+        conflicting.mergeable_state = MergeableState::HasConflicts;
+        let mut unknown = pr(2, Some(5), time(0));
+        unknown.mergeable_state = MergeableState::Unknown;
+        let mut prs = vec![conflicting, unknown, pr(3, None, time(0))];
+        assert_eq!(next(&prs), Some(2));
+
+        // The PR returns to the queue once its conflicts are resolved
+        prs[0].mergeable_state = MergeableState::Mergeable;
+        assert_eq!(next(&prs), Some(1));
+    }
+
+    #[test]
+    fn nothing_to_select() {
+        let mut unapproved = pr(1, None, time(0));
+        unapproved.approval_status = ApprovalStatus::NotApproved;
+        assert_eq!(next(&[unapproved]), None);
+    }
+
+    #[test]
+    fn fair_prefers_author_with_fewer_recent_builds() {
+        let prs = [
+            authored(pr(1, None, time(0)), Some("alice")),
+            authored(pr(2, None, time(1)), Some("alice")),
+            authored(pr(3, None, time(2)), Some("bob")),
+        ];
+        assert_eq!(next_fair(&prs, &[]), Some(1));
+        assert_eq!(next_fair(&prs, &[("alice", 1)]), Some(3));
+        assert_eq!(next_fair(&prs, &[("alice", 2), ("bob", 2)]), Some(1));
+    }
+
+    #[test]
+    fn fair_prefers_higher_priority() {
+        let prs = [
+            authored(pr(1, None, time(0)), Some("bob")),
+            authored(pr(2, Some(5), time(1)), Some("alice")),
+        ];
+        assert_eq!(next_fair(&prs, &[("alice", 10)]), Some(2));
+    }
+
+    #[test]
+    fn fair_unknown_author_takes_turns() {
+        let prs = [
+            authored(pr(1, None, time(0)), None),
+            authored(pr(2, None, time(1)), Some("alice")),
+        ];
+        assert_eq!(next_fair(&prs, &[("", 1)]), Some(2));
+        assert_eq!(next_fair(&prs, &[("", 1), ("alice", 1)]), Some(1));
+    }
+
+    #[test]
+    fn fair_skips_prs_outside_of_queue() {
+        let mut unapproved = authored(pr(1, None, time(0)), Some("bob"));
+        unapproved.approval_status = ApprovalStatus::NotApproved;
+        let prs = [unapproved, authored(pr(2, None, time(1)), Some("alice"))];
+        assert_eq!(next_fair(&prs, &[("alice", 3)]), Some(2));
+    }
+}
@@ -1,27 +1,40 @@
 use std::fmt;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use arc_swap::ArcSwap;
 
 pub use command::CommandParser;
 pub use command::RollupMode;
 pub use comment::Comment;
-pub use context::BorsContext;
+pub use context::{BorsContext, RepositoryMap};
+pub use error::{BorsError, BorsResult, is_retryable_error};
 #[cfg(test)]
 pub use handlers::WAIT_FOR_REFRESH;
-pub use handlers::{handle_bors_global_event, handle_bors_repository_event};
+pub use handlers::{
+    handle_bors_global_event, handle_bors_repository_event, handle_bors_workflows_completed,
+    sync_pull_requests,
+};
+pub use homu_queue::{HomuQueueEntry, homu_queue};
+pub use rate_limit::CommandRateLimiter;
 use serde::Serialize;
 
 use crate::config::RepositoryConfig;
 use crate::github::GithubRepoName;
+use crate::github::GithubUser;
 use crate::github::api::client::GithubRepositoryClient;
-use crate::permissions::UserPermissions;
+use crate::permissions::{PermissionCache, PermissionGrantee, PermissionType, UserPermissions};
 
 mod command;
 pub mod comment;
 mod context;
+mod error;
 pub mod event;
 mod handlers;
+mod homu_queue;
+mod merge_queue;
+mod rate_limit;
+pub mod template;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum CheckSuiteStatus {
@@ -44,12 +57,100 @@ pub struct RepositoryState {
     pub client: GithubRepositoryClient,
     pub permissions: ArcSwap<UserPermissions>,
     pub config: ArcSwap<RepositoryConfig>,
+    /// Caches permission lookups that need a request to GitHub.
+    pub permission_cache: PermissionCache,
+    /// Limits how quickly users can issue commands, see `command_rate_limit` in the
+    /// repository configuration.
+    pub command_rate_limiter: CommandRateLimiter,
+    /// Is bors paused on the repository? It is stored in the database, this is a copy that can
+    /// be checked without a database query.
+    pub paused: AtomicBool,
+    /// Serializes the changes of the try builds of the repository (starting, queueing, cancelling
+    /// and completing them). They are triggered by events of different PRs that are handled
+    /// concurrently, and they decide what to do based on the number of running try builds.
+    pub try_builds_lock: tokio::sync::Mutex<()>,
+    /// Serializes the changes of the merge queue of the repository (starting merge builds and
+    /// merging PRs whose merge build has succeeded), so that only a single merge build runs at
+    /// a time. It can be acquired while holding `try_builds_lock`, but not the other way around.
+    pub merge_queue_lock: tokio::sync::Mutex<()>,
 }
 
 impl RepositoryState {
     pub fn repository(&self) -> &GithubRepoName {
         self.client.repository()
     }
+
+    /// While bors is paused on a repository, it does not execute commands that change its state
+    /// and it does not start queued builds.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Checks if the user has the given permission, either through the permission source or
+    /// through the users and teams listed in the repository configuration.
+    /// If the members of a team or the ID of a user cannot be resolved, they do not grant the
+    /// permission.
+    pub async fn user_has_permission(&self, user: &GithubUser, permission: PermissionType) -> bool {
+        if self
+            .permissions
+            .load()
+            .has_permission(user.id, permission.clone())
+        {
+            return true;
+        }
+
+        let config = self.config.load();
+        let grantees = match permission {
+            PermissionType::Review => &config.reviewers,
+            PermissionType::Try => &config.try_users,
+        };
+        for grantee in grantees {
+            match grantee {
+                // Users are matched by their ID, so that a renamed account does not keep the
+                // permission, and a new account with the old name does not receive it
+                PermissionGrantee::User(name) => {
+                    match self
+                        .permission_cache
+                        .get_user_id(&self.client, name, config.team_members_ttl)
+                        .await
+                    {
+                        Ok(Some(id)) if id == user.id => return true,
+                        Ok(_) => {}
+                        Err(error) => {
+                            tracing::error!(
+                                "Cannot resolve user {name}, it does not grant {permission} permission to {}: {error:?}",
+                                user.username
+                            );
+                        }
+                    }
+                }
+                PermissionGrantee::Team(team) => {
+                    match self
+                        .permission_cache
+                        .get_members(&self.client, team, config.team_members_ttl)
+                        .await
+                    {
+                        Ok(members) if members.contains(&user.id) => return true,
+                        Ok(_) => {}
+                        Err(error) => {
+                            tracing::error!(
+                                "Cannot resolve members of team {team}, it does not grant {permission} permission to {}: {error:?}",
+                                user.username
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Checks if the user has admin permission in the repository.
+    /// The permission is always loaded from GitHub, so that a revoked permission takes effect
+    /// immediately.
+    pub async fn is_admin(&self, user: &GithubUser) -> anyhow::Result<bool> {
+        self.client.is_repository_admin(&user.username).await
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize)]
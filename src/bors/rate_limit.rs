@@ -0,0 +1,199 @@
+//! Limits how quickly a single user can issue commands that change the state of a repository,
+//! so that a user cannot e.g. start a large number of try builds by spamming comments.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use octocrab::models::UserId;
+
+use crate::config::CommandRateLimit;
+
+/// Token bucket of a single user. Each command takes one token, and tokens are refilled at the
+/// configured rate, up to the configured burst.
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn tokens_at(&self, now: Instant, limit: &CommandRateLimit) -> f64 {
+        let refilled =
+            now.saturating_duration_since(self.updated_at).as_secs_f64() * refill_rate(limit);
+        (self.tokens + refilled).min(burst(limit))
+    }
+}
+
+/// Per-user rate limiter of the commands issued in a single repository.
+#[derive(Default)]
+pub struct CommandRateLimiter {
+    buckets: Mutex<HashMap<UserId, Bucket>>,
+}
+
+impl CommandRateLimiter {
+    /// Records a command of the given user.
+    /// Returns how long the user has to wait if they have exceeded the limit, in which case
+    /// the command should be rejected.
+    pub fn check(&self, user: UserId, limit: &CommandRateLimit) -> Result<(), Duration> {
+        self.check_at(user, limit, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        user: UserId,
+        limit: &CommandRateLimit,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        // Full buckets behave like missing ones, so they are dropped to keep the map small
+        buckets.retain(|_, bucket| bucket.tokens_at(now, limit) < burst(limit));
+
+        let tokens = buckets
+            .get(&user)
+            .map(|bucket| bucket.tokens_at(now, limit))
+            .unwrap_or(burst(limit));
+        if tokens < 1.0 {
+            let wait = (1.0 - tokens) / refill_rate(limit);
+            return Err(Duration::from_secs(wait.ceil() as u64));
+        }
+        buckets.insert(
+            user,
+            Bucket {
+                tokens: tokens - 1.0,
+                updated_at: now,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Number of tokens refilled per second.
+fn refill_rate(limit: &CommandRateLimit) -> f64 {
+    f64::from(limit.per_minute.get()) / 60.0
+}
+
+fn burst(limit: &CommandRateLimit) -> f64 {
+    f64::from(limit.burst.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::time::{Duration, Instant};
+
+    use octocrab::models::UserId;
+
+    use super::CommandRateLimiter;
+    use crate::config::CommandRateLimit;
+    use crate::tests::mocks::{BorsBuilder, GitHubState};
+
+    fn limit(per_minute: u32, burst: u32) -> CommandRateLimit {
+        CommandRateLimit {
+            per_minute: NonZeroU32::new(per_minute).unwrap(),
+            burst: NonZeroU32::new(burst).unwrap(),
+        }
+    }
+
+    #[test]
+    fn allow_burst() {
+        let limiter = CommandRateLimiter::default();
+        let limit = limit(6, 3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at(UserId(1), &limit, now), Ok(()));
+        }
+        assert_eq!(
+            limiter.check_at(UserId(1), &limit, now),
+            Err(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn refill_over_time() {
+        let limiter = CommandRateLimiter::default();
+        let limit = limit(6, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(UserId(1), &limit, now), Ok(()));
+        assert_eq!(
+            limiter.check_at(UserId(1), &limit, now + Duration::from_secs(4)),
+            Err(Duration::from_secs(6))
+        );
+        assert_eq!(
+            limiter.check_at(UserId(1), &limit, now + Duration::from_secs(10)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejected_command_does_not_take_token() {
+        let limiter = CommandRateLimiter::default();
+        let limit = limit(60, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(UserId(1), &limit, now), Ok(()));
+        for _ in 0..5 {
+            assert!(limiter.check_at(UserId(1), &limit, now).is_err());
+        }
+        assert_eq!(
+            limiter.check_at(UserId(1), &limit, now + Duration::from_secs(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn separate_users() {
+        let limiter = CommandRateLimiter::default();
+        let limit = limit(1, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(UserId(1), &limit, now), Ok(()));
+        assert!(limiter.check_at(UserId(1), &limit, now).is_err());
+        assert_eq!(limiter.check_at(UserId(2), &limit, now), Ok(()));
+    }
+
+    fn gh_state_with_rate_limit() -> GitHubState {
+        GitHubState::default().with_default_config(
+            r#"
+[command_rate_limit]
+per_minute = 1
+burst = 2
+"#,
+        )
+    }
+
+    #[sqlx::test]
+    async fn rate_limit_state_changing_commands(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_rate_limit())
+            .run_test(|mut tester| async {
+                tester.post_comment("@bors r+").await?;
+                tester.expect_comments(1).await;
+                tester.post_comment("@bors r-").await?;
+                tester.expect_comments(1).await;
+                tester.post_comment("@bors try").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @":snail: @default-user: You are issuing commands too quickly, please slow down. You can issue the next command in 1m 0s."
+                );
+                assert!(tester.default_pr_db().await?.unwrap().try_build.is_none());
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn rate_limit_ignores_read_only_commands(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .github(gh_state_with_rate_limit())
+            .run_test(|mut tester| async {
+                for _ in 0..3 {
+                    tester.post_comment("@bors ping").await?;
+                    tester.expect_comments(1).await;
+                }
+                tester.post_comment("@bors r+").await?;
+                insta::assert_snapshot!(
+                    tester.get_comment().await?,
+                    @"Commit pr-1-sha has been approved by `default-user`"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+}
@@ -0,0 +1,272 @@
+//! Customizable texts of standard messages posted by bors and of the commit messages of the
+//! merge commits that it creates.
+//!
+//! Templates contain named placeholders in braces (e.g. `{sha}`), which are replaced
+//! when the message is rendered. Literal braces can be written as `{{` and `}}`.
+use std::fmt;
+
+/// Standard message whose text can be overridden in the `[messages]` table of the repository
+/// configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// A PR was approved.
+    Approved,
+    /// The base branch of an approved PR was changed.
+    BaseChanged,
+    /// A commit was pushed to an approved PR.
+    Pushed,
+    /// Commit message of the merge commit of a single PR.
+    MergeCommit,
+    /// Commit message of the merge commit of a rollup of several PRs.
+    RollupMergeCommit,
+    /// Commit message of the single commit of a squashed PR.
+    SquashCommit,
+}
+
+impl MessageKind {
+    /// Placeholders that can be used in the template of this message.
+    pub fn placeholders(&self) -> &'static [&'static str] {
+        match self {
+            MessageKind::Approved => &["sha", "approver"],
+            MessageKind::BaseChanged => &["branch"],
+            MessageKind::Pushed => &["sha"],
+            MessageKind::MergeCommit => &[
+                "repo", "pr", "branch", "approver", "title", "body", "head_sha", "base_sha",
+            ],
+            MessageKind::RollupMergeCommit => &["repo", "approver", "count", "prs"],
+            MessageKind::SquashCommit => &["repo", "pr", "approver", "title", "body"],
+        }
+    }
+
+    /// Template that is used if the repository does not override it.
+    fn default_template(&self) -> &'static str {
+        match self {
+            MessageKind::Approved => "Commit {sha} has been approved by `{approver}`",
+            MessageKind::BaseChanged => {
+                ":warning: The base branch changed to `{branch}`, and the\nPR will need to be re-approved."
+            }
+            MessageKind::Pushed => {
+                ":warning: A new commit `{sha}` was pushed to the branch, the\nPR will need to be re-approved."
+            }
+            MessageKind::MergeCommit => {
+                "Auto merge of {repo}#{pr} - {branch}, r={approver}\n{title}\n\n{body}"
+            }
+            MessageKind::RollupMergeCommit => {
+                "Auto merge of {repo} - rollup of {count} pull requests, r={approver}\n\nSuccessful merges:\n{prs}"
+            }
+            MessageKind::SquashCommit => "{title} (#{pr})\n\n{body}\n\nr={approver}",
+        }
+    }
+
+    pub fn default_message_template(&self) -> MessageTemplate {
+        MessageTemplate::parse(self.default_template(), self.placeholders())
+            .expect("Default message template is invalid")
+    }
+}
+
+impl fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageKind::Approved => f.write_str("approved"),
+            MessageKind::BaseChanged => f.write_str("base_changed"),
+            MessageKind::Pushed => f.write_str("pushed"),
+            MessageKind::MergeCommit => f.write_str("merge_commit"),
+            MessageKind::RollupMergeCommit => f.write_str("rollup_merge_commit"),
+            MessageKind::SquashCommit => f.write_str("squash_commit"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder(String),
+}
+
+/// Shortens `text` to at most `max_chars` characters, marking the cut with an ellipsis.
+pub fn truncate_text(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    if max_chars == 0 {
+        return String::new();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// A parsed message template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl MessageTemplate {
+    /// Parses a template, checking that it only uses the given placeholders.
+    pub fn parse(template: &str, placeholders: &[&str]) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder `{{{name}`")),
+                        }
+                    }
+                    if !placeholders.contains(&name.as_str()) {
+                        let available = placeholders
+                            .iter()
+                            .map(|p| format!("`{{{p}}}`"))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        return Err(format!(
+                            "unknown placeholder `{{{name}}}` (available placeholders: {available})"
+                        ));
+                    }
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Placeholder(name));
+                }
+                '}' => return Err("unmatched `}`, use `}}` to write a literal brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Renders the template, replacing placeholders with the given values.
+    /// Values are inserted verbatim, so a value that contains braces is not interpreted as
+    /// another placeholder.
+    /// Placeholders without a value are replaced with an empty string.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => output.push_str(text),
+                TemplatePart::Placeholder(name) => {
+                    if let Some((_, value)) = values.iter().find(|(key, _)| key == name) {
+                        output.push_str(value);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::template::{MessageKind, MessageTemplate, truncate_text};
+
+    #[test]
+    fn render_placeholders() {
+        let template = MessageTemplate::parse("{a} and {b}, {a}!", &["a", "b"]).unwrap();
+        assert_eq!(template.render(&[("a", "1"), ("b", "2")]), "1 and 2, 1!");
+    }
+
+    #[test]
+    fn render_escaped_braces() {
+        let template = MessageTemplate::parse("{{{a}}} }}{{", &["a"]).unwrap();
+        assert_eq!(template.render(&[("a", "1")]), "{1} }{");
+    }
+
+    #[test]
+    fn parse_unknown_placeholder() {
+        assert_eq!(
+            MessageTemplate::parse("{sha} {branch}", &["sha", "approver"]).unwrap_err(),
+            "unknown placeholder `{branch}` (available placeholders: `{sha}`, `{approver}`)"
+        );
+    }
+
+    #[test]
+    fn parse_unclosed_placeholder() {
+        assert_eq!(
+            MessageTemplate::parse("foo {sha", &["sha"]).unwrap_err(),
+            "unclosed placeholder `{sha`"
+        );
+    }
+
+    #[test]
+    fn parse_unmatched_brace() {
+        assert!(MessageTemplate::parse("foo}", &[]).is_err());
+    }
+
+    #[test]
+    fn render_default_merge_commit() {
+        let message = MessageKind::MergeCommit
+            .default_message_template()
+            .render(&[
+                ("repo", "rust-lang/borstest"),
+                ("pr", "1"),
+                ("branch", "user:feature"),
+                ("approver", "reviewer"),
+                ("title", "Add feature"),
+                ("body", "Details {not a placeholder}"),
+            ]);
+        insta::assert_snapshot!(message, @r"
+        Auto merge of rust-lang/borstest#1 - user:feature, r=reviewer
+        Add feature
+
+        Details {not a placeholder}
+        ");
+    }
+
+    #[test]
+    fn render_default_rollup_merge_commit() {
+        let message = MessageKind::RollupMergeCommit
+            .default_message_template()
+            .render(&[
+                ("repo", "rust-lang/borstest"),
+                ("approver", "reviewer"),
+                ("count", "2"),
+                ("prs", " - #1 (Add feature)\n - #2 (Fix bug)"),
+            ]);
+        insta::assert_snapshot!(message, @r"
+        Auto merge of rust-lang/borstest - rollup of 2 pull requests, r=reviewer
+
+        Successful merges:
+         - #1 (Add feature)
+         - #2 (Fix bug)
+        ");
+    }
+
+    #[test]
+    fn truncate_long_text() {
+        assert_eq!(truncate_text("abcdef", 6), "abcdef");
+        assert_eq!(truncate_text("abcdef", 4), "abc…");
+        assert_eq!(truncate_text("ééé", 2), "é…");
+        assert_eq!(truncate_text("abc", 0), "");
+    }
+
+    #[test]
+    fn default_templates_are_valid() {
+        for kind in [
+            MessageKind::Approved,
+            MessageKind::BaseChanged,
+            MessageKind::Pushed,
+            MessageKind::MergeCommit,
+            MessageKind::RollupMergeCommit,
+        ] {
+            kind.default_message_template();
+        }
+    }
+}
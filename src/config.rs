@@ -1,10 +1,15 @@
 use std::collections::HashMap;
+use std::num::NonZeroU32;
 use std::time::Duration;
 
-use serde::de::Error;
+use serde::de::{Error, IntoDeserializer};
 use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use url::Url;
 
+use crate::bors::template::{MessageKind, MessageTemplate};
 use crate::github::{LabelModification, LabelTrigger};
+use crate::permissions::PermissionGrantee;
 
 pub const CONFIG_FILE_PATH: &str = "rust-bors.toml";
 
@@ -21,10 +26,405 @@ pub struct RepositoryConfig {
     pub labels: HashMap<LabelTrigger, Vec<LabelModification>>,
     #[serde(default, deserialize_with = "deserialize_duration_from_secs_opt")]
     pub min_ci_time: Option<Duration>,
+    /// Names of CI checks (workflows) that must succeed for a build to succeed.
+    /// If empty, all check suites attached to the build commit must succeed.
+    #[serde(default)]
+    pub required_checks: Vec<String>,
+    /// How long to wait for all required checks to be started before failing the build.
+    #[serde(
+        default = "default_required_checks_start_timeout",
+        deserialize_with = "deserialize_duration_from_secs"
+    )]
+    pub required_checks_start_timeout: Duration,
+    /// If enabled, approved PRs are tested one at a time by merge builds on the
+    /// `automation/bors/auto` branch, and merged into their base branch once their build
+    /// succeeds.
+    #[serde(default)]
+    pub merge_queue_enabled: bool,
+    /// Order in which approved PRs with the same priority are tested by the merge queue.
+    #[serde(default)]
+    pub queue_ordering: QueueOrdering,
+    /// How is a PR landed on its base branch once its merge build succeeds.
+    /// A PR can request to be squashed with the `@bors squash` command.
+    #[serde(default)]
+    pub merge_style: MergeStyle,
+    /// If set, branches created by bors are deleted automatically once their latest build has
+    /// finished and this much time has passed since the build has finished.
+    #[serde(default, deserialize_with = "deserialize_duration_from_secs_opt")]
+    pub clean_branches_after: Option<Duration>,
+    /// If set, a build whose only pending workflows are external CI checks fails once these
+    /// checks have been pending for this long, instead of waiting for the general `timeout`.
+    #[serde(default, deserialize_with = "deserialize_duration_from_secs_opt")]
+    pub external_ci_timeout: Option<Duration>,
+    /// If enabled, the branches of a try build that is cancelled because its PR was closed are
+    /// deleted, unless a newer build already uses them.
+    #[serde(default = "default_delete_try_branch_on_close")]
+    pub delete_try_branch_on_close: bool,
+    /// Maximum number of try builds that can run at the same time.
+    /// Further try build requests are queued until a running try build finishes.
+    #[serde(default)]
+    pub max_parallel_try_builds: Option<u32>,
+    /// If enabled, a running try build of a PR is cancelled when a new try build of the same PR
+    /// is requested. Otherwise, both builds are left to finish.
+    #[serde(default = "default_cancel_superseded_try_builds")]
+    pub cancel_superseded_try_builds: bool,
+    /// Users and teams (`@org/team`) that can approve PRs, in addition to the users loaded from
+    /// the permission source.
+    #[serde(default)]
+    pub reviewers: Vec<PermissionGrantee>,
+    /// Number of distinct reviewers that have to approve a PR before it is considered approved.
+    #[serde(default = "default_required_approvals")]
+    pub required_approvals: u32,
+    /// If enabled, an approving GitHub review of a user with the review permission approves
+    /// the PR like `r+`, and a review that requests changes unapproves it like `r-`.
+    #[serde(default)]
+    pub approve_on_review: bool,
+    /// Users and teams (`@org/team`) that can start try builds, in addition to the users loaded
+    /// from the permission source.
+    #[serde(default)]
+    pub try_users: Vec<PermissionGrantee>,
+    /// If enabled, the author of a PR can start and cancel try builds of their own PR, even
+    /// without the try permission. This does not apply to PRs from forks, unless
+    /// `author_can_try_from_forks` is also enabled.
+    #[serde(default)]
+    pub author_can_try: bool,
+    /// Allows authors of PRs from forks to start try builds, if `author_can_try` is enabled.
+    #[serde(default)]
+    pub author_can_try_from_forks: bool,
+    /// If disabled, try builds cannot be started on PRs from forks at all, not even by users
+    /// with the try permission.
+    #[serde(default = "default_try_builds_from_forks")]
+    pub try_builds_from_forks: bool,
+    /// How long are the members of teams and the IDs of users referenced in `reviewers` and
+    /// `try_users` cached. The cache is also cleared when the configuration changes or the
+    /// repository is reloaded.
+    #[serde(
+        default = "default_team_members_ttl",
+        deserialize_with = "deserialize_duration_from_secs"
+    )]
+    pub team_members_ttl: Duration,
+    /// If set, a JSON payload describing each finished build is POSTed to this URL.
+    #[serde(default, deserialize_with = "deserialize_url_opt")]
+    pub notification_url: Option<Url>,
+    /// Chat channels that are notified about failed merge builds, build timeouts and changes
+    /// of the tree state.
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// If enabled, changes of the state of a PR (approval, try builds, ...) are reported by
+    /// editing a single status comment, instead of posting a new comment for each change.
+    #[serde(default)]
+    pub rolling_status_comment: bool,
+    /// If enabled, a comment of bors about a try build is minimized as outdated once a newer
+    /// comment of the same kind is posted to the PR.
+    #[serde(default = "default_minimize_outdated_comments")]
+    pub minimize_outdated_comments: bool,
+    /// If enabled, commands that succeed without having anything to report (e.g. `p=` or
+    /// `rollup=`) are acknowledged with a 👍 reaction on the comment that contained them.
+    #[serde(default)]
+    pub reaction_ack: bool,
+    /// How many lines of the log of each failed Github Actions job are included in the comment
+    /// about a failed build. Set to zero to disable the log excerpts.
+    #[serde(default = "default_failed_job_log_lines")]
+    pub failed_job_log_lines: usize,
+    /// Maximum number of pull requests with an unknown mergeable state whose state is
+    /// re-checked on GitHub during a single periodic refresh. Set to zero to disable the re-check.
+    #[serde(default = "default_mergeable_refresh_limit")]
+    pub mergeable_refresh_limit: u32,
+    /// If set, approvals older than this many days are revoked by the periodic refresh, so that
+    /// the PR has to be reviewed again. Approving the PR again restarts the period.
+    #[serde(default)]
+    pub approval_expiry_days: Option<u32>,
+    /// If set, delegations older than this many days are revoked by the periodic refresh.
+    /// Delegating again restarts the period.
+    #[serde(default)]
+    pub delegation_expiry_days: Option<u32>,
+    /// If enabled, the result of each build is reported in a `bors` check run on the head commit
+    /// of its PR. Requires the app to have the checks write permission.
+    #[serde(default = "default_report_check_run")]
+    pub report_check_run: bool,
+    /// Context of the commit statuses that bors sets on the commits of builds and on the head
+    /// commits of their PRs. Branch protection rules can require a status with this context.
+    /// Try builds are reported under the `<context>/try` context, so that they cannot satisfy
+    /// such a rule.
+    #[serde(default = "default_commit_status_context")]
+    pub commit_status_context: String,
+    /// URL of a page that shows the queue of the repository. Commit statuses of builds link to
+    /// the entry of their PR on this page (`<url>#<PR number>`), or to the PR if it is not set.
+    #[serde(default, deserialize_with = "deserialize_url_opt")]
+    pub queue_url: Option<Url>,
+    /// Maximum number of characters of the PR description that is included in the message of
+    /// a merge commit. Longer descriptions are truncated.
+    #[serde(default = "default_merge_commit_body_limit")]
+    pub merge_commit_body_limit: usize,
+    /// Limit of commands that change the state of the repository (all commands except
+    /// `ping`, `help` and `info`) that a single user can issue.
+    /// Commands are not limited by default.
+    #[serde(default)]
+    pub command_rate_limit: Option<CommandRateLimit>,
+    /// Logins of users (typically other bots) whose comments are ignored, so that commands
+    /// quoted by them are not executed. Comments of the bors app itself are always ignored.
+    #[serde(default)]
+    pub ignored_users: Vec<String>,
+    /// If set, adding this label to a PR sets its rollup mode to `never`, and removing the
+    /// label resets the rollup mode to the default.
+    #[serde(default)]
+    pub no_rollup_label: Option<String>,
+    /// Custom templates of standard messages, which replace the built-in texts.
+    #[serde(default, deserialize_with = "deserialize_messages")]
+    pub messages: HashMap<MessageKind, MessageTemplate>,
+    /// SHA-256 hash of the configuration file contents.
+    /// It is not part of the file, it is filled in when the configuration is loaded.
+    #[serde(skip)]
+    pub source_hash: String,
+}
+
+/// Token bucket limit of the commands of a single user.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CommandRateLimit {
+    /// Number of commands per minute that a user can issue in the long run.
+    pub per_minute: NonZeroU32,
+    /// Number of commands that a user can issue in a quick succession.
+    pub burst: NonZeroU32,
+}
+
+/// Chat channels to which messages about the health of the queue are sent.
+#[derive(serde::Deserialize, Debug, Default, Clone)]
+pub struct NotificationsConfig {
+    /// If enabled, messages are posted to a Slack incoming webhook, or any other endpoint that
+    /// accepts Slack-compatible `{"text": "..."}` payloads.
+    /// The URL of the webhook contains its secret, so it is not part of the configuration file,
+    /// it is read from the `SLACK_WEBHOOK_URL` environment variable of the bors process.
+    #[serde(default)]
+    pub slack: bool,
+    /// Zulip stream to which messages are posted through the Zulip API.
+    #[serde(default)]
+    pub zulip: Option<ZulipConfig>,
+}
+
+impl NotificationsConfig {
+    pub fn is_empty(&self) -> bool {
+        !self.slack && self.zulip.is_none()
+    }
+}
+
+/// Zulip stream and topic to which messages are posted.
+/// The API key of the bot is not part of the configuration file, it is read from the
+/// `ZULIP_API_KEY` environment variable of the bors process.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct ZulipConfig {
+    /// Base URL of the Zulip organization, e.g. `https://rust-lang.zulipchat.com`.
+    #[serde(deserialize_with = "deserialize_url")]
+    pub url: Url,
+    /// Email of the Zulip bot that posts the messages.
+    pub bot_email: String,
+    pub stream: String,
+    pub topic: String,
+}
+
+/// Order in which approved PRs are tested by the merge queue.
+/// PRs with a higher priority always go first.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOrdering {
+    /// PRs are tested in the order in which they were created.
+    #[default]
+    Fifo,
+    /// PRs are interleaved by author, so that an author with many approved PRs does not starve
+    /// the others. PRs of authors that had fewer recent merge builds go first, ties are broken
+    /// by the order in which the PRs were created.
+    Fair,
+}
+
+/// How is a PR landed on its base branch once its merge build succeeds.
+/// Squash and rebase create new commits that have the same tree as the tested merge commit.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStyle {
+    /// The base branch is fast-forwarded to the tested merge commit.
+    #[default]
+    Merge,
+    /// The changes of the PR are landed as a single commit authored by the PR author, whose
+    /// only parent is the base branch.
+    Squash,
+    /// The commits of the PR are replayed on top of the base branch, one by one.
+    Rebase,
+}
+
+/// Top-level keys that are recognized in the configuration file.
+/// Has to be kept in sync with the fields of [RepositoryConfig].
+const CONFIG_KEYS: &[&str] = &[
+    "timeout",
+    "labels",
+    "min_ci_time",
+    "required_checks",
+    "required_checks_start_timeout",
+    "merge_queue_enabled",
+    "queue_ordering",
+    "merge_style",
+    "clean_branches_after",
+    "external_ci_timeout",
+    "delete_try_branch_on_close",
+    "max_parallel_try_builds",
+    "cancel_superseded_try_builds",
+    "reviewers",
+    "required_approvals",
+    "approve_on_review",
+    "try_users",
+    "author_can_try",
+    "author_can_try_from_forks",
+    "try_builds_from_forks",
+    "team_members_ttl",
+    "notification_url",
+    "notifications",
+    "rolling_status_comment",
+    "minimize_outdated_comments",
+    "reaction_ack",
+    "failed_job_log_lines",
+    "mergeable_refresh_limit",
+    "approval_expiry_days",
+    "delegation_expiry_days",
+    "report_check_run",
+    "commit_status_context",
+    "queue_url",
+    "merge_commit_body_limit",
+    "command_rate_limit",
+    "ignored_users",
+    "no_rollup_label",
+    "messages",
+];
+
+/// Keys that are recognized in the `notifications` table of the configuration file.
+/// Has to be kept in sync with the fields of [NotificationsConfig].
+const NOTIFICATIONS_KEYS: &[&str] = &["slack", "zulip"];
+
+/// Keys that are recognized in the `command_rate_limit` table of the configuration file.
+/// Has to be kept in sync with the fields of [CommandRateLimit].
+const COMMAND_RATE_LIMIT_KEYS: &[&str] = &["per_minute", "burst"];
+
+/// Label triggers that are recognized in the `labels` table of the configuration file.
+/// Has to be kept in sync with the `Trigger` enum in [deserialize_labels].
+const LABEL_TRIGGER_KEYS: &[&str] = &[
+    "approve",
+    "unapprove",
+    "try",
+    "try_succeed",
+    "try_failed",
+    "auto",
+    "merge_succeed",
+    "merge_failed",
+];
+
+impl RepositoryConfig {
+    /// Parses the configuration from the contents of a configuration file.
+    pub fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        let mut config: RepositoryConfig = toml::from_str(content)?;
+        config.source_hash = hex::encode(Sha256::digest(content.as_bytes()));
+        Ok(config)
+    }
+
+    /// Renders the given message, using the template configured for the repository or the
+    /// built-in one.
+    pub fn render_message(&self, kind: MessageKind, values: &[(&str, &str)]) -> String {
+        match self.messages.get(&kind) {
+            Some(template) => template.render(values),
+            None => kind.default_message_template().render(values),
+        }
+    }
+}
+
+/// Returns the keys of a configuration file that are not recognized by bors, and would thus be
+/// ignored. Keys in nested tables are returned in the `table.key` format.
+/// If `content` is not a valid TOML document, no keys are returned.
+pub fn find_unknown_config_keys(content: &str) -> Vec<String> {
+    let Ok(table) = content.parse::<toml::Table>() else {
+        return vec![];
+    };
+    let mut unknown = vec![];
+    for (key, value) in &table {
+        if !CONFIG_KEYS.contains(&key.as_str()) {
+            unknown.push(key.clone());
+        } else if key == "labels" {
+            if let Some(labels) = value.as_table() {
+                unknown.extend(
+                    labels
+                        .keys()
+                        .filter(|trigger| !LABEL_TRIGGER_KEYS.contains(&trigger.as_str()))
+                        .map(|trigger| format!("labels.{trigger}")),
+                );
+            }
+        } else if key == "notifications" {
+            if let Some(notifications) = value.as_table() {
+                unknown.extend(
+                    notifications
+                        .keys()
+                        .filter(|key| !NOTIFICATIONS_KEYS.contains(&key.as_str()))
+                        .map(|key| format!("notifications.{key}")),
+                );
+            }
+        } else if key == "command_rate_limit" {
+            if let Some(limit) = value.as_table() {
+                unknown.extend(
+                    limit
+                        .keys()
+                        .filter(|key| !COMMAND_RATE_LIMIT_KEYS.contains(&key.as_str()))
+                        .map(|key| format!("command_rate_limit.{key}")),
+                );
+            }
+        }
+    }
+    unknown
+}
+
+fn default_failed_job_log_lines() -> usize {
+    30
+}
+
+fn default_required_approvals() -> u32 {
+    1
+}
+
+fn default_cancel_superseded_try_builds() -> bool {
+    true
+}
+
+fn default_try_builds_from_forks() -> bool {
+    true
+}
+
+fn default_delete_try_branch_on_close() -> bool {
+    true
+}
+
+fn default_mergeable_refresh_limit() -> u32 {
+    20
+}
+
+fn default_minimize_outdated_comments() -> bool {
+    true
+}
+
+fn default_report_check_run() -> bool {
+    true
+}
+
+fn default_merge_commit_body_limit() -> usize {
+    4000
+}
+
+fn default_commit_status_context() -> String {
+    "bors".to_string()
+}
+
+fn default_team_members_ttl() -> Duration {
+    Duration::from_secs(300)
 }
 
 fn default_timeout() -> Duration {
-    Duration::from_secs(3600)
+    Duration::from_secs(4 * 3600)
+}
+
+fn default_required_checks_start_timeout() -> Duration {
+    Duration::from_secs(1800)
 }
 
 fn deserialize_duration_from_secs_opt<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
@@ -44,6 +444,26 @@ where
     Ok(Duration::from_secs(seconds))
 }
 
+fn deserialize_url<'de, D>(deserializer: D) -> Result<Url, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let url = String::deserialize(deserializer)?;
+    Url::parse(&url).map_err(|error| D::Error::custom(format!("Invalid URL `{url}`: {error}")))
+}
+
+fn deserialize_url_opt<'de, D>(deserializer: D) -> Result<Option<Url>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|url| {
+            Url::parse(&url)
+                .map_err(|error| D::Error::custom(format!("Invalid URL `{url}`: {error}")))
+        })
+        .transpose()
+}
+
 fn deserialize_labels<'de, D>(
     deserializer: D,
 ) -> Result<HashMap<LabelTrigger, Vec<LabelModification>>, D::Error>
@@ -58,6 +478,9 @@ where
         Try,
         TrySucceed,
         TryFailed,
+        Auto,
+        MergeSucceed,
+        MergeFailed,
     }
 
     impl From<Trigger> for LabelTrigger {
@@ -68,6 +491,9 @@ where
                 Trigger::Try => LabelTrigger::TryBuildStarted,
                 Trigger::TrySucceed => LabelTrigger::TryBuildSucceeded,
                 Trigger::TryFailed => LabelTrigger::TryBuildFailed,
+                Trigger::Auto => LabelTrigger::AutoBuildStarted,
+                Trigger::MergeSucceed => LabelTrigger::MergeSucceeded,
+                Trigger::MergeFailed => LabelTrigger::MergeFailed,
             }
         }
     }
@@ -112,7 +538,26 @@ where
         }
     }
 
-    let mut triggers = HashMap::<Trigger, Vec<Modification>>::deserialize(deserializer)?;
+    /// Unknown triggers are ignored, they are reported by [find_unknown_config_keys].
+    #[derive(Eq, PartialEq, Hash)]
+    struct KnownTrigger(Option<Trigger>);
+
+    impl<'de> serde::Deserialize<'de> for KnownTrigger {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let value: String = String::deserialize(deserializer)?;
+            let trigger: Result<Trigger, serde::de::value::Error> =
+                Trigger::deserialize(value.into_deserializer());
+            Ok(Self(trigger.ok()))
+        }
+    }
+
+    let mut triggers = HashMap::<KnownTrigger, Vec<Modification>>::deserialize(deserializer)?
+        .into_iter()
+        .filter_map(|(trigger, modifications)| Some((trigger.0?, modifications)))
+        .collect::<HashMap<_, _>>();
     // If there are any `approve` triggers, add `unapprove` triggers as well.
     if let Some(modifications) = triggers.get(&Trigger::Approve) {
         let unapprove_modifications = modifications
@@ -133,11 +578,35 @@ where
     Ok(triggers)
 }
 
+fn deserialize_messages<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<MessageKind, MessageTemplate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<MessageKind, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(kind, template)| {
+            MessageTemplate::parse(&template, kind.placeholders())
+                .map(|template| (kind, template))
+                .map_err(|error| {
+                    D::Error::custom(format!("Invalid template of message `{kind}`: {error}"))
+                })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::BTreeMap, time::Duration};
 
-    use crate::config::{RepositoryConfig, default_timeout};
+    use crate::bors::template::MessageKind;
+    use crate::config::{
+        MergeStyle, QueueOrdering, RepositoryConfig, default_required_checks_start_timeout,
+        default_timeout, find_unknown_config_keys,
+    };
+    use crate::github::{LabelModification, LabelTrigger};
+    use crate::permissions::{PermissionGrantee, TeamReference};
 
     #[test]
     fn deserialize_empty() {
@@ -167,6 +636,325 @@ mod tests {
         assert_eq!(config.min_ci_time, Some(Duration::from_secs(3600)));
     }
 
+    #[test]
+    fn deserialize_required_checks_empty() {
+        let content = "";
+        let config = load_config(content);
+        assert!(config.required_checks.is_empty());
+        assert_eq!(
+            config.required_checks_start_timeout,
+            default_required_checks_start_timeout()
+        );
+    }
+
+    #[test]
+    fn deserialize_required_checks() {
+        let content = r#"
+required_checks = ["CI", "test-windows"]
+required_checks_start_timeout = 600
+"#;
+        let config = load_config(content);
+        assert_eq!(config.required_checks, vec!["CI", "test-windows"]);
+        assert_eq!(
+            config.required_checks_start_timeout,
+            Duration::from_secs(600)
+        );
+    }
+
+    #[test]
+    fn deserialize_merge_queue_enabled() {
+        assert!(!load_config("").merge_queue_enabled);
+        assert!(load_config("merge_queue_enabled = true").merge_queue_enabled);
+    }
+
+    #[test]
+    fn deserialize_merge_style() {
+        assert_eq!(load_config("").merge_style, MergeStyle::Merge);
+        assert_eq!(
+            load_config(r#"merge_style = "squash""#).merge_style,
+            MergeStyle::Squash
+        );
+        assert_eq!(
+            load_config(r#"merge_style = "rebase""#).merge_style,
+            MergeStyle::Rebase
+        );
+    }
+
+    #[test]
+    fn deserialize_permission_grantees() {
+        let content = r#"
+reviewers = ["@rust-lang/compiler", "kobzol"]
+try_users = ["@rust-lang/contributors"]
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.reviewers,
+            vec![
+                PermissionGrantee::Team(TeamReference {
+                    org: "rust-lang".to_string(),
+                    slug: "compiler".to_string()
+                }),
+                PermissionGrantee::User("kobzol".to_string())
+            ]
+        );
+        assert_eq!(config.try_users.len(), 1);
+        assert_eq!(config.team_members_ttl, Duration::from_secs(300));
+    }
+
+    #[test]
+    fn deserialize_ignored_users() {
+        let config = load_config(r#"ignored_users = ["rustbot", "rust-highfive"]"#);
+        assert_eq!(config.ignored_users, vec!["rustbot", "rust-highfive"]);
+    }
+
+    #[test]
+    fn deserialize_no_rollup_label() {
+        let config = load_config(r#"no_rollup_label = "rollup=never""#);
+        assert_eq!(config.no_rollup_label.as_deref(), Some("rollup=never"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid team reference `@rust-lang/`")]
+    fn deserialize_invalid_team_reference() {
+        load_config(r#"reviewers = ["@rust-lang/"]"#);
+    }
+
+    #[test]
+    fn deserialize_notification_url() {
+        let config = load_config(r#"notification_url = "https://hooks.example.com/bors""#);
+        assert_eq!(
+            config.notification_url.unwrap().as_str(),
+            "https://hooks.example.com/bors"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid URL `foo`")]
+    fn deserialize_invalid_notification_url() {
+        load_config(r#"notification_url = "foo""#);
+    }
+
+    #[test]
+    fn deserialize_notifications() {
+        let content = r#"
+[notifications]
+slack = true
+
+[notifications.zulip]
+url = "https://rust-lang.zulipchat.com"
+bot_email = "bors-bot@rust-lang.zulipchat.com"
+stream = "t-infra"
+topic = "bors"
+"#;
+        let config = load_config(content);
+        assert!(config.notifications.slack);
+        let zulip = config.notifications.zulip.unwrap();
+        assert_eq!(zulip.url.as_str(), "https://rust-lang.zulipchat.com/");
+        assert_eq!(zulip.bot_email, "bors-bot@rust-lang.zulipchat.com");
+        assert_eq!(zulip.stream, "t-infra");
+        assert_eq!(zulip.topic, "bors");
+    }
+
+    #[test]
+    fn notifications_disabled_by_default() {
+        assert!(load_config("").notifications.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "missing field `topic`")]
+    fn deserialize_zulip_missing_topic() {
+        let content = r#"
+[notifications.zulip]
+url = "https://rust-lang.zulipchat.com"
+bot_email = "bors-bot@rust-lang.zulipchat.com"
+stream = "t-infra"
+"#;
+        load_config(content);
+    }
+
+    #[test]
+    fn deserialize_command_rate_limit() {
+        let content = r#"
+[command_rate_limit]
+per_minute = 5
+burst = 10
+"#;
+        let limit = load_config(content).command_rate_limit.unwrap();
+        assert_eq!(limit.per_minute.get(), 5);
+        assert_eq!(limit.burst.get(), 10);
+    }
+
+    #[test]
+    fn command_rate_limit_disabled_by_default() {
+        assert!(load_config("").command_rate_limit.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "nonzero")]
+    fn deserialize_command_rate_limit_zero() {
+        let content = r#"
+[command_rate_limit]
+per_minute = 0
+burst = 10
+"#;
+        load_config(content);
+    }
+
+    #[test]
+    fn minimize_outdated_comments_enabled_by_default() {
+        let config = load_config("");
+        assert!(config.minimize_outdated_comments);
+    }
+
+    #[test]
+    fn deserialize_minimize_outdated_comments() {
+        let config = load_config("minimize_outdated_comments = false");
+        assert!(!config.minimize_outdated_comments);
+    }
+
+    #[test]
+    fn report_check_run_enabled_by_default() {
+        let config = load_config("");
+        assert!(config.report_check_run);
+    }
+
+    #[test]
+    fn deserialize_report_check_run() {
+        let config = load_config("report_check_run = false");
+        assert!(!config.report_check_run);
+    }
+
+    #[test]
+    fn deserialize_commit_status_context() {
+        assert_eq!(load_config("").commit_status_context, "bors");
+        let config = load_config(r#"commit_status_context = "ci/bors""#);
+        assert_eq!(config.commit_status_context, "ci/bors");
+    }
+
+    #[test]
+    fn deserialize_queue_url() {
+        let config = load_config(r#"queue_url = "https://bors.example.com/queue/borstest""#);
+        assert_eq!(
+            config.queue_url.unwrap().as_str(),
+            "https://bors.example.com/queue/borstest"
+        );
+    }
+
+    #[test]
+    fn deserialize_required_approvals() {
+        assert_eq!(load_config("").required_approvals, 1);
+        assert_eq!(load_config("required_approvals = 2").required_approvals, 2);
+    }
+
+    #[test]
+    fn deserialize_approve_on_review() {
+        assert!(!load_config("").approve_on_review);
+        assert!(load_config("approve_on_review = true").approve_on_review);
+    }
+
+    #[test]
+    fn deserialize_queue_ordering() {
+        assert_eq!(load_config("").queue_ordering, QueueOrdering::Fifo);
+        assert_eq!(
+            load_config(r#"queue_ordering = "fair""#).queue_ordering,
+            QueueOrdering::Fair
+        );
+    }
+
+    #[test]
+    fn deserialize_failed_job_log_lines() {
+        assert_eq!(load_config("").failed_job_log_lines, 30);
+        assert_eq!(
+            load_config("failed_job_log_lines = 0").failed_job_log_lines,
+            0
+        );
+    }
+
+    #[test]
+    fn deserialize_external_ci_timeout() {
+        assert_eq!(load_config("").external_ci_timeout, None);
+        assert_eq!(
+            load_config("external_ci_timeout = 600").external_ci_timeout,
+            Some(Duration::from_secs(600))
+        );
+    }
+
+    #[test]
+    fn deserialize_cancel_superseded_try_builds() {
+        assert!(load_config("").cancel_superseded_try_builds);
+        assert!(!load_config("cancel_superseded_try_builds = false").cancel_superseded_try_builds);
+    }
+
+    #[test]
+    fn deserialize_try_builds_from_forks() {
+        assert!(load_config("").try_builds_from_forks);
+        assert!(!load_config("try_builds_from_forks = false").try_builds_from_forks);
+    }
+
+    #[test]
+    fn deserialize_delete_try_branch_on_close() {
+        assert!(load_config("").delete_try_branch_on_close);
+        assert!(!load_config("delete_try_branch_on_close = false").delete_try_branch_on_close);
+    }
+
+    #[test]
+    fn deserialize_mergeable_refresh_limit() {
+        assert_eq!(load_config("").mergeable_refresh_limit, 20);
+        assert_eq!(
+            load_config("mergeable_refresh_limit = 5").mergeable_refresh_limit,
+            5
+        );
+    }
+
+    #[test]
+    fn deserialize_approval_expiry_days() {
+        assert_eq!(load_config("").approval_expiry_days, None);
+        assert_eq!(
+            load_config("approval_expiry_days = 14").approval_expiry_days,
+            Some(14)
+        );
+    }
+
+    #[test]
+    fn deserialize_delegation_expiry_days() {
+        assert_eq!(load_config("").delegation_expiry_days, None);
+        assert_eq!(
+            load_config("delegation_expiry_days = 7").delegation_expiry_days,
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn deserialize_reaction_ack() {
+        assert!(!load_config("").reaction_ack);
+        assert!(load_config("reaction_ack = true").reaction_ack);
+    }
+
+    #[test]
+    fn deserialize_merge_labels() {
+        let content = r#"[labels]
+auto = ["+merging"]
+merge_succeed = ["-merging", "+merged"]
+merge_failed = ["-merging"]
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.labels.get(&LabelTrigger::AutoBuildStarted),
+            Some(&vec![LabelModification::Add("merging".to_string())])
+        );
+        assert_eq!(
+            config.labels.get(&LabelTrigger::MergeSucceeded),
+            Some(&vec![
+                LabelModification::Remove("merging".to_string()),
+                LabelModification::Add("merged".to_string())
+            ])
+        );
+        assert_eq!(
+            config.labels.get(&LabelTrigger::MergeFailed),
+            Some(&vec![LabelModification::Remove("merging".to_string())])
+        );
+    }
+
     #[test]
     fn deserialize_labels() {
         let content = r#"[labels]
@@ -212,6 +1000,24 @@ try_failed = []
         "###);
     }
 
+    #[test]
+    fn deserialize_labels_unknown_trigger() {
+        let content = r#"[labels]
+approved = ["+approved"]
+try = ["+foo"]
+"#;
+        let config = load_config(content);
+        insta::assert_debug_snapshot!(config.labels.into_iter().collect::<BTreeMap<_, _>>(), @r#"
+        {
+            TryBuildStarted: [
+                Add(
+                    "foo",
+                ),
+            ],
+        }
+        "#);
+    }
+
     #[test]
     #[should_panic(expected = "Label modification must start with `+` or `-`")]
     fn deserialize_labels_missing_prefix() {
@@ -221,6 +1027,126 @@ try = ["foo"]
         load_config(content);
     }
 
+    #[test]
+    fn deserialize_messages() {
+        let content = r#"[messages]
+approved = "{approver} approved {sha}"
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.render_message(
+                MessageKind::Approved,
+                &[("sha", "abcdef"), ("approver", "foo")]
+            ),
+            "foo approved abcdef"
+        );
+        assert_eq!(
+            config.render_message(MessageKind::Pushed, &[("sha", "abcdef")]),
+            ":warning: A new commit `abcdef` was pushed to the branch, the\nPR will need to be re-approved."
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid template of message `pushed`: unknown placeholder `{branch}` (available placeholders: `{sha}`)"
+    )]
+    fn deserialize_messages_unknown_placeholder() {
+        let content = r#"[messages]
+pushed = "Pushed to {branch}"
+"#;
+        load_config(content);
+    }
+
+    #[test]
+    fn deserialize_merge_commit_message() {
+        let content = r#"merge_commit_body_limit = 100
+
+[messages]
+merge_commit = "Merge #{pr} ({title}), r={approver}"
+"#;
+        let config = load_config(content);
+        assert_eq!(config.merge_commit_body_limit, 100);
+        assert_eq!(
+            config.render_message(
+                MessageKind::MergeCommit,
+                &[("pr", "1"), ("title", "Foo"), ("approver", "bar")]
+            ),
+            "Merge #1 (Foo), r=bar"
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid template of message `rollup_merge_commit`: unknown placeholder `{title}`"
+    )]
+    fn deserialize_rollup_merge_commit_unknown_placeholder() {
+        let content = r#"[messages]
+rollup_merge_commit = "Rollup: {title}"
+"#;
+        load_config(content);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown variant `merged`")]
+    fn deserialize_messages_unknown_kind() {
+        let content = r#"[messages]
+merged = "Merged"
+"#;
+        load_config(content);
+    }
+
+    #[test]
+    fn unknown_keys_valid_config() {
+        let content = r#"
+timeout = 3600
+required_checks = ["CI"]
+
+[labels]
+approve = ["+approved"]
+try_failed = ["-approved"]
+"#;
+        assert!(find_unknown_config_keys(content).is_empty());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let content = r#"
+timeout = 3600
+timout = 3600
+
+[labels]
+approve = ["+approved"]
+approved = ["+approved"]
+
+[notifications]
+slack_url = "https://hooks.slack.com/services/foo"
+
+[command_rate_limit]
+rate = 5
+"#;
+        assert_eq!(
+            find_unknown_config_keys(content),
+            vec![
+                "command_rate_limit.rate",
+                "labels.approved",
+                "notifications.slack_url",
+                "timout"
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_invalid_toml() {
+        assert!(find_unknown_config_keys("timeout = ").is_empty());
+    }
+
+    #[test]
+    fn example_config_is_valid() {
+        let content = include_str!("../rust-bors.example.toml");
+        RepositoryConfig::parse(content).unwrap();
+        assert!(find_unknown_config_keys(content).is_empty());
+    }
+
     fn load_config(config: &str) -> RepositoryConfig {
         toml::from_str(config).unwrap()
     }
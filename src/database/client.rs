@@ -1,22 +1,83 @@
-use sqlx::PgPool;
+use std::collections::HashMap;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use octocrab::models::CommentId;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::bors::comment::CommentKind;
 use crate::bors::{PullRequestStatus, RollupMode};
 use crate::database::{
-    BuildModel, BuildStatus, PullRequestModel, RepoModel, TreeState, WorkflowModel, WorkflowStatus,
-    WorkflowType,
+    BuildModel, BuildStatus, DelegatedPermission, DelegationModel, PullRequestModel,
+    QueuedEventModel, QueuedEventStatus, QueuedTryBuildModel, RepoModel, StatusCommentModel,
+    TreeState, WorkflowModel, WorkflowStatus, WorkflowStatusCounts, WorkflowType,
 };
 use crate::github::PullRequestNumber;
 use crate::github::{CommitSha, GithubRepoName};
 
 use super::operations::{
-    approve_pull_request, create_build, create_pull_request, create_workflow,
-    delegate_pull_request, find_build, find_pr_by_build, get_pull_request, get_repository,
-    get_running_builds, get_workflow_urls_for_build, get_workflows_for_build, set_pr_priority,
-    set_pr_rollup, set_pr_status, unapprove_pull_request, undelegate_pull_request,
-    update_build_status, update_mergeable_states_by_base_branch, update_pr_build_id,
-    update_workflow_status, upsert_pull_request, upsert_repository,
+    add_approval, approve_pull_request, count_pending_builds, count_recent_builds_by_author,
+    create_build, create_pull_request, create_repository, create_workflow, delegate_pull_request,
+    delete_processed_events, delete_repository, enqueue_try_build, find_build,
+    find_builds_by_commit, find_pr_by_build, finish_queued_events, get_approvers, get_delegations,
+    get_executed_command_count, get_latest_build_per_branch, get_open_prs, get_outdatable_comment,
+    get_pending_workflows_older_than, get_prs_approved_before,
+    get_prs_with_unknown_mergeable_state, get_pull_request, get_queued_event, get_queued_events,
+    get_repositories, get_repository, get_repository_config, get_running_builds,
+    get_status_comment, get_try_queue, get_workflow_status_counts, get_workflow_urls_for_build,
+    get_workflows_for_build, insert_queued_event, is_repository_paused, lock_build,
+    mark_build_branches_deleted, record_processed_comment, remove_approval, remove_approvals,
+    remove_queued_try_build, rename_repository, rename_repository_builds,
+    rename_repository_pull_requests, requeue_dead_event, set_build_check_run_id, set_build_force,
+    set_build_force_from_approval, set_build_head_sha, set_build_jobs, set_pr_approval_force,
+    set_pr_mergeable_checked_at, set_pr_mergeable_state, set_pr_priority, set_pr_rollup,
+    set_pr_squash, set_pr_status, set_repository_config, set_repository_paused,
+    start_queued_events_attempt, sync_pull_request, unapprove_pull_request,
+    undelegate_pull_request, update_build_status, update_mergeable_states_by_base_branch,
+    update_pr_build_id, update_pr_details, update_pr_merge_build_id,
+    update_workflow_attempt_status, update_workflow_attempt_statuses, update_workflow_status,
+    upsert_outdatable_comment, upsert_pull_request, upsert_repository, upsert_status_comment,
+};
+use super::{
+    ApprovalInfo, DbError, DbResult, MergeableState, Pagination, RepositoryRenameSummary, RunId,
 };
-use super::{ApprovalInfo, MergeableState, RunId};
+
+/// Maximum number of workflows returned by [PgDbClient::get_pending_workflows_older_than].
+const PENDING_WORKFLOWS_LIMIT: i64 = 100;
+/// Maximum number of attempts of an operation that fails because of a transient connection
+/// error, e.g. while the database is being restarted.
+const TRANSIENT_RETRY_ATTEMPTS: u32 = 3;
+/// Delay before the first retry of such an operation, it is doubled after each attempt.
+const TRANSIENT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Runs an operation and retries it a bounded number of times if it fails because of
+/// a transient connection error, so that e.g. a restart of the database does not fail the
+/// commands that are being handled at that moment. The pool reconnects on its own.
+///
+/// Only operations that can be safely repeated should be retried: reads, and starting
+/// a transaction. A write whose connection was lost might have already been applied.
+async fn retry_transient<T, F, Fut>(operation: &'static str, f: F) -> DbResult<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = DbResult<T>>,
+{
+    let mut delay = TRANSIENT_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(error) if error.is_transient() && attempt < TRANSIENT_RETRY_ATTEMPTS => {
+                tracing::warn!(
+                    "Database operation `{operation}` failed (attempt {attempt}/{TRANSIENT_RETRY_ATTEMPTS}), retrying in {delay:?}: {error}"
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
 /// Provides access to a database using sqlx operations.
 #[derive(Clone)]
@@ -29,55 +90,187 @@ impl PgDbClient {
         Self { pool }
     }
 
+    /// Starts a transaction of the given operation.
+    async fn begin(&self, operation: &'static str) -> DbResult<Transaction<'static, Postgres>> {
+        retry_transient(operation, || async {
+            self.pool
+                .begin()
+                .await
+                .map_err(|error| DbError::from(error).in_operation(operation))
+        })
+        .await
+    }
+
+    /// Runs `f` within a single database transaction, so that several operations are applied
+    /// atomically.
+    /// The transaction is committed if `f` succeeds, and rolled back if it returns an error.
+    ///
+    /// ```ignore
+    /// db.transaction(|tx| {
+    ///     Box::pin(async move {
+    ///         tx.approve(&pr, approval_info, None, None).await?;
+    ///         tx.set_build_force(&build, true).await
+    ///     })
+    /// })
+    /// .await?;
+    /// ```
+    pub async fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        E: From<DbError>,
+        F: for<'t> FnOnce(&'t mut PgDbTransaction) -> BoxFuture<'t, Result<T, E>>,
+    {
+        let mut tx = PgDbTransaction {
+            tx: self.begin("transaction").await?,
+        };
+        // If `f` fails, the transaction is rolled back when it is dropped
+        let value = f(&mut tx).await?;
+        tx.tx.commit().await.map_err(DbError::from)?;
+        Ok(value)
+    }
+
+    /// Records the approval of `approval_info.approver` and marks the pull request as approved.
     pub async fn approve(
         &self,
         pr: &PullRequestModel,
         approval_info: ApprovalInfo,
         priority: Option<u32>,
         rollup: Option<RollupMode>,
-    ) -> anyhow::Result<()> {
-        approve_pull_request(&self.pool, pr.id, approval_info, priority, rollup).await
+    ) -> DbResult<()> {
+        let mut tx = self.begin("approve").await?;
+        add_approval(&mut *tx, pr.id, &approval_info.approver, &approval_info.sha).await?;
+        approve_pull_request(
+            &mut *tx,
+            pr.id,
+            &approval_info.sha,
+            approval_info.base_sha.as_deref(),
+            priority,
+            rollup,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
     }
 
-    pub async fn unapprove(&self, pr: &PullRequestModel) -> anyhow::Result<()> {
-        unapprove_pull_request(&self.pool, pr.id).await
+    /// Removes the approval of the pull request, including the approvals of all reviewers.
+    pub async fn unapprove(&self, pr: &PullRequestModel) -> DbResult<()> {
+        let mut tx = self.begin("unapprove").await?;
+        unapprove_pull_request(&mut *tx, pr.id).await?;
+        remove_approvals(&mut *tx, pr.id).await?;
+        tx.commit().await?;
+        Ok(())
     }
 
-    pub async fn set_priority(&self, pr: &PullRequestModel, priority: u32) -> anyhow::Result<()> {
+    /// Returns the reviewers that have approved the pull request at the head commit `sha`.
+    pub async fn get_approvers(&self, pr: &PullRequestModel, sha: &str) -> DbResult<Vec<String>> {
+        retry_transient("get_approvers", || get_approvers(&self.pool, pr.id, sha)).await
+    }
+
+    pub async fn set_priority(&self, pr: &PullRequestModel, priority: u32) -> DbResult<()> {
         set_pr_priority(&self.pool, pr.id, priority).await
     }
 
-    pub async fn delegate(&self, pr: &PullRequestModel) -> anyhow::Result<()> {
-        delegate_pull_request(&self.pool, pr.id).await
+    /// Delegates the permission to the author of the PR, on behalf of the user `delegated_by`.
+    pub async fn delegate(
+        &self,
+        pr: &PullRequestModel,
+        permission: DelegatedPermission,
+        delegated_by: &str,
+    ) -> DbResult<()> {
+        delegate_pull_request(&self.pool, pr.id, permission, delegated_by).await
     }
 
-    pub async fn undelegate(&self, pr: &PullRequestModel) -> anyhow::Result<()> {
+    pub async fn undelegate(&self, pr: &PullRequestModel) -> DbResult<()> {
         undelegate_pull_request(&self.pool, pr.id).await
     }
 
+    /// Revokes the given delegation, e.g. because it has expired.
+    pub async fn remove_delegation(&self, delegation: &DelegationModel) -> DbResult<()> {
+        undelegate_pull_request(&self.pool, delegation.pull_request_id).await
+    }
+
+    /// Returns the delegations of the open PRs of the repository, ordered by PR number.
+    pub async fn get_delegations(&self, repo: &GithubRepoName) -> DbResult<Vec<DelegationModel>> {
+        retry_transient("get_delegations", || get_delegations(&self.pool, repo)).await
+    }
+
     pub async fn update_mergeable_states_by_base_branch(
         &self,
         repo: &GithubRepoName,
         base_branch: &str,
+        base_sha: &CommitSha,
         mergeable_state: MergeableState,
-    ) -> anyhow::Result<u64> {
-        update_mergeable_states_by_base_branch(&self.pool, repo, base_branch, mergeable_state).await
+    ) -> DbResult<u64> {
+        update_mergeable_states_by_base_branch(
+            &self.pool,
+            repo,
+            base_branch,
+            &base_sha.0,
+            mergeable_state,
+        )
+        .await
     }
 
-    pub async fn set_rollup(
+    pub async fn set_mergeable_state(
         &self,
         pr: &PullRequestModel,
-        rollup: RollupMode,
-    ) -> anyhow::Result<()> {
-        set_pr_rollup(&self.pool, pr.id, rollup).await
+        mergeable_state: MergeableState,
+    ) -> DbResult<()> {
+        set_pr_mergeable_state(&self.pool, pr.id, mergeable_state).await
+    }
+
+    /// Records that the mergeable state of the pull request was just checked on GitHub.
+    pub async fn mark_mergeable_state_checked(&self, pr: &PullRequestModel) -> DbResult<()> {
+        set_pr_mergeable_checked_at(&self.pool, pr.id).await
+    }
+
+    /// Returns at most `limit` open pull requests of the repository whose mergeable state is
+    /// unknown, starting with the ones that were not checked for the longest time.
+    pub async fn get_prs_with_unknown_mergeable_state(
+        &self,
+        repo: &GithubRepoName,
+        limit: u32,
+    ) -> DbResult<Vec<PullRequestModel>> {
+        get_prs_with_unknown_mergeable_state(&self.pool, repo, limit as i64).await
+    }
+
+    /// Returns the pull requests of the repository that were approved before `approved_before`.
+    pub async fn get_prs_approved_before(
+        &self,
+        repo: &GithubRepoName,
+        approved_before: DateTime<Utc>,
+    ) -> DbResult<Vec<PullRequestModel>> {
+        get_prs_approved_before(&self.pool, repo, approved_before).await
+    }
+
+    pub async fn set_rollup(&self, pr: &PullRequestModel, rollup: RollupMode) -> DbResult<()> {
+        set_pr_rollup(&self.pool, pr.id, Some(rollup)).await
+    }
+
+    /// Resets the rollup mode of the PR to the default.
+    pub async fn reset_rollup(&self, pr: &PullRequestModel) -> DbResult<()> {
+        set_pr_rollup(&self.pool, pr.id, None).await
+    }
+
+    /// Returns the open (or draft) pull requests of the repository.
+    pub async fn get_open_prs(&self, repo: &GithubRepoName) -> DbResult<Vec<PullRequestModel>> {
+        retry_transient("get_open_prs", || get_open_prs(&self.pool, repo)).await
+    }
+
+    /// Sets whether the PR is squashed when it is landed, regardless of the merge style of the
+    /// repository.
+    pub async fn set_squash(&self, pr: &PullRequestModel, squash: bool) -> DbResult<()> {
+        set_pr_squash(&self.pool, pr.id, squash).await
     }
 
     pub async fn get_pull_request(
         &self,
         repo: &GithubRepoName,
         pr_number: PullRequestNumber,
-    ) -> anyhow::Result<Option<PullRequestModel>> {
-        get_pull_request(&self.pool, repo, pr_number).await
+    ) -> DbResult<Option<PullRequestModel>> {
+        retry_transient("get_pull_request", || {
+            get_pull_request(&self.pool, repo, pr_number)
+        })
+        .await
     }
 
     pub async fn get_or_create_pull_request(
@@ -87,7 +280,8 @@ impl PgDbClient {
         base_branch: &str,
         mergeable_state: MergeableState,
         pr_status: &PullRequestStatus,
-    ) -> anyhow::Result<PullRequestModel> {
+        author: &str,
+    ) -> DbResult<PullRequestModel> {
         let pr = upsert_pull_request(
             &self.pool,
             repo,
@@ -95,19 +289,77 @@ impl PgDbClient {
             base_branch,
             mergeable_state,
             pr_status,
+            author,
         )
         .await?;
         Ok(pr)
     }
 
+    /// Stores the current state of a pull request on GitHub, creating the pull request if it
+    /// does not exist yet.
+    /// Returns `true` if the pull request was newly created.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn sync_pull_request(
+        &self,
+        repo: &GithubRepoName,
+        pr_number: PullRequestNumber,
+        base_branch: &str,
+        mergeable_state: MergeableState,
+        pr_status: &PullRequestStatus,
+        author: &str,
+        title: &str,
+        head_sha: &str,
+        labels: &[String],
+    ) -> DbResult<bool> {
+        sync_pull_request(
+            &self.pool,
+            repo,
+            pr_number,
+            base_branch,
+            mergeable_state,
+            pr_status,
+            author,
+            title,
+            head_sha,
+            labels,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_pull_request(
         &self,
         repo: &GithubRepoName,
         pr_number: PullRequestNumber,
         base_branch: &str,
         pr_status: PullRequestStatus,
-    ) -> anyhow::Result<()> {
-        create_pull_request(&self.pool, repo, pr_number, base_branch, pr_status).await
+        author: &str,
+        title: &str,
+        head_sha: &str,
+    ) -> DbResult<()> {
+        create_pull_request(
+            &self.pool,
+            repo,
+            pr_number,
+            base_branch,
+            pr_status,
+            author,
+            title,
+            head_sha,
+        )
+        .await
+    }
+
+    /// Updates the title, author and head SHA of the pull request, which can change when the PR
+    /// is edited or pushed to.
+    pub async fn update_pr_details(
+        &self,
+        pr: &PullRequestModel,
+        title: &str,
+        author: &str,
+        head_sha: &CommitSha,
+    ) -> DbResult<()> {
+        update_pr_details(&self.pool, pr.id, title, author, head_sha.as_ref()).await
     }
 
     pub async fn set_pr_status(
@@ -115,56 +367,179 @@ impl PgDbClient {
         repo: &GithubRepoName,
         pr_number: PullRequestNumber,
         pr_status: PullRequestStatus,
-    ) -> anyhow::Result<()> {
+    ) -> DbResult<()> {
         set_pr_status(&self.pool, repo, pr_number, pr_status).await
     }
 
-    pub async fn find_pr_by_build(
-        &self,
-        build: &BuildModel,
-    ) -> anyhow::Result<Option<PullRequestModel>> {
-        find_pr_by_build(&self.pool, build.id).await
+    /// Finds the pull request that a build belongs to, whether it is its try build, a try build
+    /// that was superseded by a newer one, or its merge build.
+    pub async fn find_pr_by_build(&self, build: &BuildModel) -> DbResult<Option<PullRequestModel>> {
+        retry_transient("find_pr_by_build", || {
+            find_pr_by_build(&self.pool, build.id)
+        })
+        .await
     }
 
+    /// Creates a try build of `commit_sha`, which tests the `head_sha` commit of the PR.
     pub async fn attach_try_build(
         &self,
         pr: PullRequestModel,
         branch: String,
         commit_sha: CommitSha,
         parent: CommitSha,
-    ) -> anyhow::Result<()> {
-        let mut tx = self.pool.begin().await?;
-        let build_id =
-            create_build(&mut *tx, &pr.repository, &branch, &commit_sha, &parent).await?;
+        head_sha: CommitSha,
+        jobs: &[String],
+    ) -> DbResult<()> {
+        let mut tx = self.begin("attach_try_build").await?;
+        let build_id = create_build(
+            &mut *tx,
+            &pr.repository,
+            &branch,
+            &commit_sha,
+            &parent,
+            Some(pr.id),
+        )
+        .await?;
+        set_build_head_sha(&mut *tx, build_id, &head_sha).await?;
+        if !jobs.is_empty() {
+            set_build_jobs(&mut *tx, build_id, jobs).await?;
+        }
         update_pr_build_id(&mut *tx, pr.id, build_id).await?;
         tx.commit().await?;
         Ok(())
     }
 
+    pub async fn attach_merge_build(
+        &self,
+        pr: &PullRequestModel,
+        branch: String,
+        commit_sha: CommitSha,
+        parent: CommitSha,
+        head_sha: CommitSha,
+    ) -> DbResult<()> {
+        let mut tx = self.begin("attach_merge_build").await?;
+        let build_id = create_build(
+            &mut *tx,
+            &pr.repository,
+            &branch,
+            &commit_sha,
+            &parent,
+            Some(pr.id),
+        )
+        .await?;
+        set_build_head_sha(&mut *tx, build_id, &head_sha).await?;
+        update_pr_merge_build_id(&mut *tx, pr.id, build_id).await?;
+        // A forced approval also forces the merge build of the PR
+        set_build_force_from_approval(&mut *tx, build_id, pr.id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
     pub async fn find_build(
         &self,
         repo: &GithubRepoName,
         branch: String,
         commit_sha: CommitSha,
-    ) -> anyhow::Result<Option<BuildModel>> {
-        find_build(&self.pool, repo, &branch, &commit_sha).await
+    ) -> DbResult<Option<BuildModel>> {
+        retry_transient("find_build", || {
+            find_build(&self.pool, repo, &branch, &commit_sha)
+        })
+        .await
+    }
+
+    /// Returns all builds at the given commit, ordered from the oldest one.
+    /// Useful when only the SHA of a build is known; the caller can then disambiguate the
+    /// builds by their branch.
+    pub async fn find_builds_by_commit(
+        &self,
+        repo: &GithubRepoName,
+        commit_sha: &CommitSha,
+    ) -> DbResult<Vec<BuildModel>> {
+        retry_transient("find_builds_by_commit", || {
+            find_builds_by_commit(&self.pool, repo, commit_sha)
+        })
+        .await
+    }
+
+    /// Returns all pending builds of the given repository, ordered from the oldest one.
+    pub async fn get_running_builds(&self, repo: &GithubRepoName) -> DbResult<Vec<BuildModel>> {
+        retry_transient("get_running_builds", || {
+            get_running_builds(&self.pool, repo, Pagination::default())
+        })
+        .await
+    }
+
+    /// Returns the given page of pending builds of the given repository, ordered from the
+    /// oldest one.
+    pub async fn get_running_builds_page(
+        &self,
+        repo: &GithubRepoName,
+        page: Pagination,
+    ) -> DbResult<Vec<BuildModel>> {
+        retry_transient("get_running_builds", || {
+            get_running_builds(&self.pool, repo, page)
+        })
+        .await
+    }
+
+    pub async fn count_pending_builds(&self, repo: &GithubRepoName, branch: &str) -> DbResult<u64> {
+        retry_transient("count_pending_builds", || {
+            count_pending_builds(&self.pool, repo, branch)
+        })
+        .await
+    }
+
+    /// Returns the number of recent builds on `branch` of each PR author of the given repository.
+    /// See [count_recent_builds_by_author] for details.
+    pub async fn count_recent_builds_by_author(
+        &self,
+        repo: &GithubRepoName,
+        branch: &str,
+        since: DateTime<Utc>,
+    ) -> DbResult<HashMap<String, u64>> {
+        count_recent_builds_by_author(&self.pool, repo, branch, since).await
     }
 
-    pub async fn get_running_builds(
+    /// Returns the most recent build of each branch of the given repository.
+    /// All branches that appear here have been created by bors.
+    pub async fn get_latest_build_per_branch(
         &self,
         repo: &GithubRepoName,
-    ) -> anyhow::Result<Vec<BuildModel>> {
-        get_running_builds(&self.pool, repo).await
+    ) -> DbResult<Vec<BuildModel>> {
+        retry_transient("get_latest_build_per_branch", || {
+            get_latest_build_per_branch(&self.pool, repo)
+        })
+        .await
     }
 
     pub async fn update_build_status(
         &self,
         build: &BuildModel,
         status: BuildStatus,
-    ) -> anyhow::Result<()> {
+    ) -> DbResult<()> {
         update_build_status(&self.pool, build.id, status).await
     }
 
+    /// Records that the branches of the build were deleted, so that they are not deleted again.
+    pub async fn mark_build_branches_deleted(&self, build: &BuildModel) -> DbResult<()> {
+        mark_build_branches_deleted(&self.pool, build.id).await
+    }
+
+    /// Marks the build as forced, so that failures of checks that are not required do not
+    /// fail it.
+    pub async fn set_build_force(&self, build: &BuildModel, force: bool) -> DbResult<()> {
+        set_build_force(&self.pool, build.id, force).await
+    }
+
+    /// Stores the ID of the check run that reports the result of the build.
+    pub async fn set_build_check_run_id(
+        &self,
+        build: &BuildModel,
+        check_run_id: u64,
+    ) -> DbResult<()> {
+        set_build_check_run_id(&self.pool, build.id, check_run_id as i64).await
+    }
+
     pub async fn create_workflow(
         &self,
         build: &BuildModel,
@@ -173,7 +548,7 @@ impl PgDbClient {
         run_id: RunId,
         workflow_type: WorkflowType,
         status: WorkflowStatus,
-    ) -> anyhow::Result<()> {
+    ) -> DbResult<()> {
         create_workflow(
             &self.pool,
             build.id,
@@ -190,28 +565,208 @@ impl PgDbClient {
         &self,
         run_id: u64,
         status: WorkflowStatus,
-    ) -> anyhow::Result<()> {
+    ) -> DbResult<()> {
         update_workflow_status(&self.pool, run_id, status).await
     }
 
-    pub async fn get_workflows_for_build(
+    /// Updates the status of the given attempt of a workflow run.
+    /// Returns `false` if a newer attempt of the workflow is already known.
+    pub async fn update_workflow_attempt_status(
+        &self,
+        run_id: u64,
+        run_attempt: u32,
+        status: WorkflowStatus,
+    ) -> DbResult<bool> {
+        update_workflow_attempt_status(&self.pool, run_id, run_attempt, status).await
+    }
+
+    /// Updates the statuses of the build's workflows and then lets `complete` decide the final
+    /// status of the build, based on the current state of all its workflows.
+    /// Each update is a `(run_id, run_attempt, status)` triple, all of them are written with a
+    /// single statement, in the given order. Updates of an outdated attempt of a workflow are
+    /// ignored, and so are re-runs of a workflow whose build has already been completed.
+    ///
+    /// Most workflow updates do not complete the build, so `may_complete` first decides, based
+    /// only on the number of workflows in each status, if the build could be completed. The
+    /// workflows themselves are only loaded for `complete` if it returns true.
+    ///
+    /// Everything happens in a single transaction that locks the build row, so that concurrent
+    /// workflow updates of the same build are serialized and the build is completed at most once.
+    /// Returns the new status of the build and its workflows if the build was completed.
+    pub async fn update_workflow_and_complete_build(
         &self,
         build: &BuildModel,
-    ) -> anyhow::Result<Vec<WorkflowModel>> {
-        get_workflows_for_build(&self.pool, build.id).await
+        workflow_updates: &[(u64, u32, WorkflowStatus)],
+        may_complete: impl FnOnce(&WorkflowStatusCounts) -> bool,
+        complete: impl FnOnce(&[WorkflowModel]) -> Option<BuildStatus>,
+    ) -> DbResult<Option<(BuildStatus, Vec<WorkflowModel>)>> {
+        let mut tx = self.begin("update_workflow_and_complete_build").await?;
+        let Some(build) = lock_build(&mut *tx, build.id).await? else {
+            return Ok(None);
+        };
+        // A re-run must not change the workflows of a build that has already been reported
+        let updates: Vec<_> = workflow_updates
+            .iter()
+            .copied()
+            .filter(|(_, run_attempt, _)| *run_attempt == 1 || build.status == BuildStatus::Pending)
+            .collect();
+        if !updates.is_empty() {
+            update_workflow_attempt_statuses(&mut *tx, &updates).await?;
+        }
+        // If the build has already been marked with a conclusion, do not change it
+        if build.status != BuildStatus::Pending {
+            tx.commit().await?;
+            return Ok(None);
+        }
+
+        let counts = get_workflow_status_counts(&mut *tx, build.id).await?;
+        if !may_complete(&counts) {
+            tx.commit().await?;
+            return Ok(None);
+        }
+        let workflows = get_workflows_for_build(&mut *tx, build.id).await?;
+        let Some(status) = complete(&workflows) else {
+            tx.commit().await?;
+            return Ok(None);
+        };
+        update_build_status(&mut *tx, build.id, status).await?;
+        tx.commit().await?;
+        Ok(Some((status, workflows)))
     }
 
-    pub async fn get_workflow_urls_for_build(
+    /// Marks a pending build as cancelled.
+    /// The build row is locked, so that a build that has been completed concurrently is not
+    /// cancelled. Returns `false` if the build was no longer pending.
+    pub async fn cancel_pending_build(&self, build: &BuildModel) -> DbResult<bool> {
+        let mut tx = self.begin("cancel_pending_build").await?;
+        let Some(build) = lock_build(&mut *tx, build.id).await? else {
+            return Ok(false);
+        };
+        if build.status != BuildStatus::Pending {
+            tx.commit().await?;
+            return Ok(false);
+        }
+        update_build_status(&mut *tx, build.id, BuildStatus::Cancelled).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn enqueue_try_build(
+        &self,
+        pr: &PullRequestModel,
+        parent: Option<&str>,
+        jobs: &[String],
+    ) -> DbResult<()> {
+        enqueue_try_build(&self.pool, pr.id, parent, jobs).await
+    }
+
+    pub async fn get_try_queue(&self, repo: &GithubRepoName) -> DbResult<Vec<QueuedTryBuildModel>> {
+        retry_transient("get_try_queue", || {
+            get_try_queue(&self.pool, repo, Pagination::default())
+        })
+        .await
+    }
+
+    /// Returns the given page of the try build queue of the given repository.
+    pub async fn get_try_queue_page(
+        &self,
+        repo: &GithubRepoName,
+        page: Pagination,
+    ) -> DbResult<Vec<QueuedTryBuildModel>> {
+        get_try_queue(&self.pool, repo, page).await
+    }
+
+    pub async fn remove_queued_try_build(&self, pr_id: i32) -> DbResult<bool> {
+        remove_queued_try_build(&self.pool, pr_id).await
+    }
+
+    /// Returns how many commands of the comment were executed while it had the given text hash.
+    pub async fn get_executed_command_count(
+        &self,
+        comment_id: CommentId,
+        body_hash: &str,
+    ) -> DbResult<usize> {
+        get_executed_command_count(&self.pool, comment_id.0, body_hash).await
+    }
+
+    /// Records that the first `executed_commands` commands of the comment were executed when it
+    /// had the given text hash.
+    pub async fn record_processed_comment(
+        &self,
+        comment_id: CommentId,
+        body_hash: &str,
+        executed_commands: usize,
+    ) -> DbResult<()> {
+        record_processed_comment(&self.pool, comment_id.0, body_hash, executed_commands).await
+    }
+
+    pub async fn get_status_comment(
+        &self,
+        pr: &PullRequestModel,
+    ) -> DbResult<Option<StatusCommentModel>> {
+        retry_transient("get_status_comment", || {
+            get_status_comment(&self.pool, pr.id)
+        })
+        .await
+    }
+
+    pub async fn set_status_comment(
+        &self,
+        pr: &PullRequestModel,
+        comment: &StatusCommentModel,
+    ) -> DbResult<()> {
+        upsert_status_comment(&self.pool, pr.id, comment).await
+    }
+
+    /// Returns the node ID of the latest comment of the given kind posted to the PR.
+    pub async fn get_outdatable_comment(
+        &self,
+        pr: &PullRequestModel,
+        kind: CommentKind,
+    ) -> DbResult<Option<String>> {
+        get_outdatable_comment(&self.pool, pr.id, kind).await
+    }
+
+    pub async fn set_outdatable_comment(
+        &self,
+        pr: &PullRequestModel,
+        kind: CommentKind,
+        node_id: &str,
+    ) -> DbResult<()> {
+        upsert_outdatable_comment(&self.pool, pr.id, kind, node_id).await
+    }
+
+    /// Returns the number of workflows of the build in each status.
+    /// Prefer this to [PgDbClient::get_workflows_for_build] if the workflows themselves are not
+    /// needed.
+    pub async fn get_workflow_status_counts(
+        &self,
+        build: &BuildModel,
+    ) -> DbResult<WorkflowStatusCounts> {
+        retry_transient("get_workflow_status_counts", || {
+            get_workflow_status_counts(&self.pool, build.id)
+        })
+        .await
+    }
+
+    pub async fn get_workflows_for_build(
         &self,
         build: &BuildModel,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> DbResult<Vec<WorkflowModel>> {
+        retry_transient("get_workflows_for_build", || {
+            get_workflows_for_build(&self.pool, build.id)
+        })
+        .await
+    }
+
+    pub async fn get_workflow_urls_for_build(&self, build: &BuildModel) -> DbResult<Vec<String>> {
         get_workflow_urls_for_build(&self.pool, build.id).await
     }
 
     pub async fn get_pending_workflows_for_build(
         &self,
         build: &BuildModel,
-    ) -> anyhow::Result<Vec<RunId>> {
+    ) -> DbResult<Vec<RunId>> {
         let workflows = self
             .get_workflows_for_build(build)
             .await?
@@ -224,15 +779,882 @@ impl PgDbClient {
         Ok(workflows)
     }
 
-    pub async fn repo_db(&self, repo: &GithubRepoName) -> anyhow::Result<Option<RepoModel>> {
+    /// Returns workflows that have been pending for longer than `age`, oldest first.
+    /// At most [PENDING_WORKFLOWS_LIMIT] workflows are returned.
+    pub async fn get_pending_workflows_older_than(
+        &self,
+        age: Duration,
+    ) -> DbResult<Vec<WorkflowModel>> {
+        // An age that is out of range cannot match any workflow
+        let created_before = chrono::Duration::from_std(age)
+            .ok()
+            .and_then(|age| Utc::now().checked_sub_signed(age))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        get_pending_workflows_older_than(&self.pool, created_before, PENDING_WORKFLOWS_LIMIT).await
+    }
+
+    pub async fn repo_db(&self, repo: &GithubRepoName) -> DbResult<Option<RepoModel>> {
         get_repository(&self.pool, repo).await
     }
 
+    /// Stores a repository managed by the bot, so that it can be enumerated by
+    /// [PgDbClient::get_repositories]. Does nothing if the repository is already stored.
+    pub async fn register_repository(&self, repo: &GithubRepoName) -> DbResult<()> {
+        create_repository(&self.pool, repo).await
+    }
+
+    /// Moves all pull requests and builds of the `old` repository to its `new` name, e.g. after
+    /// the repository was renamed or transferred on GitHub.
+    pub async fn rename_repository(
+        &self,
+        old: &GithubRepoName,
+        new: &GithubRepoName,
+    ) -> DbResult<RepositoryRenameSummary> {
+        let mut tx = self.begin("rename_repository").await?;
+        let pull_requests = rename_repository_pull_requests(&mut *tx, old, new).await?;
+        let builds = rename_repository_builds(&mut *tx, old, new).await?;
+        rename_repository(&mut *tx, old, new).await?;
+        // The old row is only left if the new repository had already been stored
+        delete_repository(&mut *tx, old).await?;
+        tx.commit().await?;
+        Ok(RepositoryRenameSummary {
+            pull_requests,
+            builds,
+        })
+    }
+
+    /// Returns the names of all repositories managed by the bot.
+    pub async fn get_repositories(&self) -> DbResult<Vec<GithubRepoName>> {
+        get_repositories(&self.pool).await
+    }
+
     pub async fn upsert_repository(
         &self,
         repo: &GithubRepoName,
         tree_state: TreeState,
-    ) -> anyhow::Result<()> {
+    ) -> DbResult<()> {
         upsert_repository(&self.pool, repo, tree_state).await
     }
+
+    /// Pauses or resumes bors on a repository. The state is kept across restarts.
+    pub async fn set_repository_paused(&self, repo: &GithubRepoName, paused: bool) -> DbResult<()> {
+        set_repository_paused(&self.pool, repo, paused).await
+    }
+
+    pub async fn is_repository_paused(&self, repo: &GithubRepoName) -> DbResult<bool> {
+        retry_transient("is_repository_paused", || {
+            is_repository_paused(&self.pool, repo)
+        })
+        .await
+    }
+
+    /// Stores the configuration file of a repository, so that it can be restored
+    /// without contacting GitHub.
+    pub async fn set_repository_config(
+        &self,
+        repo: &GithubRepoName,
+        config: &str,
+        config_sha: &str,
+    ) -> DbResult<()> {
+        set_repository_config(&self.pool, repo, config, config_sha).await
+    }
+
+    pub async fn get_repository_config(&self, repo: &GithubRepoName) -> DbResult<Option<String>> {
+        get_repository_config(&self.pool, repo).await
+    }
+
+    /// Stores a repository webhook event in the event queue. Returns the ID of the stored event,
+    /// or `None` if the delivery was already stored.
+    pub async fn insert_queued_event(
+        &self,
+        repo: &GithubRepoName,
+        delivery_id: &str,
+        event_type: &str,
+        payload: &[u8],
+    ) -> DbResult<Option<i32>> {
+        insert_queued_event(&self.pool, repo, delivery_id, event_type, payload).await
+    }
+
+    pub async fn get_queued_event(&self, id: i32) -> DbResult<Option<QueuedEventModel>> {
+        retry_transient("get_queued_event", || get_queued_event(&self.pool, id)).await
+    }
+
+    /// Returns the stored events with the given status, from the oldest one.
+    pub async fn get_queued_events(
+        &self,
+        status: QueuedEventStatus,
+    ) -> DbResult<Vec<QueuedEventModel>> {
+        retry_transient("get_queued_events", || {
+            get_queued_events(&self.pool, status)
+        })
+        .await
+    }
+
+    /// Records that the handling of the stored events has started.
+    pub async fn start_queued_events_attempt(&self, ids: &[i32]) -> DbResult<()> {
+        start_queued_events_attempt(&self.pool, ids).await
+    }
+
+    pub async fn mark_queued_events_processed(&self, ids: &[i32]) -> DbResult<()> {
+        finish_queued_events(&self.pool, ids, QueuedEventStatus::Processed, None).await
+    }
+
+    /// Moves the stored events to the dead-letter state, they are not handled again unless
+    /// they are retried by an admin.
+    pub async fn mark_queued_events_dead(&self, ids: &[i32], error: &str) -> DbResult<()> {
+        finish_queued_events(&self.pool, ids, QueuedEventStatus::Dead, Some(error)).await
+    }
+
+    /// Moves a dead event back to the pending state.
+    /// Returns `false` if there is no dead event with the given ID.
+    pub async fn requeue_dead_event(&self, id: i32) -> DbResult<bool> {
+        requeue_dead_event(&self.pool, id).await
+    }
+
+    /// Deletes the processed events that were handled before the given time.
+    pub async fn delete_processed_events(&self, processed_before: DateTime<Utc>) -> DbResult<u64> {
+        delete_processed_events(&self.pool, processed_before).await
+    }
+}
+
+/// A database transaction started by [PgDbClient::transaction].
+/// Provides variants of the [PgDbClient] operations that are executed within the transaction.
+pub struct PgDbTransaction {
+    tx: Transaction<'static, Postgres>,
+}
+
+impl PgDbTransaction {
+    /// Records the approval of `approval_info.approver` and marks the pull request as approved.
+    pub async fn approve(
+        &mut self,
+        pr: &PullRequestModel,
+        approval_info: ApprovalInfo,
+        priority: Option<u32>,
+        rollup: Option<RollupMode>,
+    ) -> DbResult<()> {
+        add_approval(
+            &mut *self.tx,
+            pr.id,
+            &approval_info.approver,
+            &approval_info.sha,
+        )
+        .await?;
+        approve_pull_request(
+            &mut *self.tx,
+            pr.id,
+            &approval_info.sha,
+            approval_info.base_sha.as_deref(),
+            priority,
+            rollup,
+        )
+        .await
+    }
+
+    /// Removes the approval of the pull request, but keeps the approvals of individual
+    /// reviewers.
+    pub async fn unapprove(&mut self, pr: &PullRequestModel) -> DbResult<()> {
+        unapprove_pull_request(&mut *self.tx, pr.id).await
+    }
+
+    /// Records the approval of a single reviewer at the head commit `sha`.
+    pub async fn add_approval(
+        &mut self,
+        pr: &PullRequestModel,
+        approver: &str,
+        sha: &str,
+    ) -> DbResult<()> {
+        add_approval(&mut *self.tx, pr.id, approver, sha).await
+    }
+
+    /// Removes the approval of a single reviewer.
+    /// Returns `false` if the reviewer has not approved the pull request.
+    pub async fn remove_approval(
+        &mut self,
+        pr: &PullRequestModel,
+        approver: &str,
+    ) -> DbResult<bool> {
+        remove_approval(&mut *self.tx, pr.id, approver).await
+    }
+
+    /// Returns the reviewers that have approved the pull request at the head commit `sha`.
+    pub async fn get_approvers(
+        &mut self,
+        pr: &PullRequestModel,
+        sha: &str,
+    ) -> DbResult<Vec<String>> {
+        get_approvers(&mut *self.tx, pr.id, sha).await
+    }
+
+    pub async fn set_build_force(&mut self, build: &BuildModel, force: bool) -> DbResult<()> {
+        set_build_force(&mut *self.tx, build.id, force).await
+    }
+
+    /// Records whether the approval of the pull request was forced (`@bors r+ force`), so that
+    /// its merge build is forced as well.
+    pub async fn set_approval_force(&mut self, pr: &PullRequestModel, force: bool) -> DbResult<()> {
+        set_pr_approval_force(&mut *self.tx, pr.id, force).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use chrono::Utc;
+    use sqlx::PgPool;
+
+    use crate::bors::PullRequestStatus;
+    use crate::database::{
+        ApprovalInfo, BuildStatus, DbError, DbResult, DelegatedPermission, MergeableState,
+        PgDbClient, PullRequestModel, QueuedEventStatus, RunId, WorkflowStatus, WorkflowType,
+    };
+    use crate::github::{CommitSha, GithubRepoName, PullRequestNumber};
+
+    use super::{TRANSIENT_RETRY_ATTEMPTS, retry_transient};
+
+    async fn create_pr(db: &PgDbClient) -> PullRequestModel {
+        db.get_or_create_pull_request(
+            &GithubRepoName::new("foo", "bar"),
+            PullRequestNumber(1),
+            "main",
+            MergeableState::Mergeable,
+            &PullRequestStatus::Open,
+            "author",
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn is_approved(db: &PgDbClient) -> bool {
+        db.get_pull_request(&GithubRepoName::new("foo", "bar"), PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap()
+            .has_approval()
+    }
+
+    fn approval_info() -> ApprovalInfo {
+        ApprovalInfo {
+            approver: "reviewer".to_string(),
+            sha: "sha".to_string(),
+            base_sha: Some("base-sha".to_string()),
+        }
+    }
+
+    async fn get_mergeable_state(db: &PgDbClient) -> MergeableState {
+        db.get_pull_request(&GithubRepoName::new("foo", "bar"), PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap()
+            .mergeable_state
+    }
+
+    async fn find_pr_of_build(db: &PgDbClient, branch: &str, sha: &str) -> PullRequestModel {
+        let build = db
+            .find_build(
+                &GithubRepoName::new("foo", "bar"),
+                branch.to_string(),
+                CommitSha(sha.to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        db.find_pr_by_build(&build).await.unwrap().unwrap()
+    }
+
+    #[sqlx::test]
+    async fn cancel_pending_build_only_once(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        db.attach_try_build(
+            pr,
+            "try".to_string(),
+            CommitSha("try-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+            &[],
+        )
+        .await
+        .unwrap();
+        let build = find_pr_of_build(&db, "try", "try-sha")
+            .await
+            .try_build
+            .unwrap();
+
+        assert!(db.cancel_pending_build(&build).await.unwrap());
+        assert!(!db.cancel_pending_build(&build).await.unwrap());
+        let build = find_pr_of_build(&db, "try", "try-sha")
+            .await
+            .try_build
+            .unwrap();
+        assert_eq!(build.status, BuildStatus::Cancelled);
+    }
+
+    #[sqlx::test]
+    async fn mergeable_state_kept_if_approved_base_did_not_move(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        db.approve(&pr, approval_info(), None, None).await.unwrap();
+
+        let repo = GithubRepoName::new("foo", "bar");
+        let updated = db
+            .update_mergeable_states_by_base_branch(
+                &repo,
+                "main",
+                &CommitSha("base-sha".to_string()),
+                MergeableState::Unknown,
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated, 0);
+        assert_eq!(get_mergeable_state(&db).await, MergeableState::Mergeable);
+
+        let updated = db
+            .update_mergeable_states_by_base_branch(
+                &repo,
+                "main",
+                &CommitSha("base-sha-2".to_string()),
+                MergeableState::Unknown,
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated, 1);
+        assert_eq!(get_mergeable_state(&db).await, MergeableState::Unknown);
+    }
+
+    #[sqlx::test]
+    async fn unknown_mergeable_states_checked_in_rotation(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let repo = GithubRepoName::new("foo", "bar");
+        for number in 1..=3 {
+            db.get_or_create_pull_request(
+                &repo,
+                PullRequestNumber(number),
+                "main",
+                MergeableState::Unknown,
+                &PullRequestStatus::Open,
+                "author",
+            )
+            .await
+            .unwrap();
+        }
+
+        let prs = db
+            .get_prs_with_unknown_mergeable_state(&repo, 2)
+            .await
+            .unwrap();
+        assert_eq!(pr_numbers(&prs), vec![1, 2]);
+        for pr in &prs {
+            db.mark_mergeable_state_checked(pr).await.unwrap();
+        }
+
+        // The PR that was not checked yet goes first
+        let prs = db
+            .get_prs_with_unknown_mergeable_state(&repo, 2)
+            .await
+            .unwrap();
+        assert_eq!(pr_numbers(&prs), vec![3, 1]);
+    }
+
+    fn pr_numbers(prs: &[PullRequestModel]) -> Vec<u64> {
+        prs.iter().map(|pr| pr.number.0).collect()
+    }
+
+    #[sqlx::test]
+    async fn find_pr_by_try_build(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        db.attach_try_build(
+            pr,
+            "try".to_string(),
+            CommitSha("try-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let pr = find_pr_of_build(&db, "try", "try-sha").await;
+        assert_eq!(pr.number.0, 1);
+        assert_eq!(pr.try_build.unwrap().head_sha.as_deref(), Some("head-sha"));
+        assert!(pr.merge_build.is_none());
+    }
+
+    #[sqlx::test]
+    async fn find_pr_by_merge_build(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        db.attach_merge_build(
+            &pr,
+            "auto".to_string(),
+            CommitSha("merge-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let pr = find_pr_of_build(&db, "auto", "merge-sha").await;
+        assert_eq!(pr.number.0, 1);
+        assert!(pr.try_build.is_none());
+        let merge_build = pr.merge_build.unwrap();
+        assert_eq!(merge_build.commit_sha, "merge-sha");
+        assert_eq!(merge_build.head_sha.as_deref(), Some("head-sha"));
+    }
+
+    #[sqlx::test]
+    async fn count_recent_builds_by_author(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        assert_eq!(pr.author.as_deref(), Some("author"));
+        let started_before = Utc::now() - chrono::Duration::minutes(1);
+        db.attach_merge_build(
+            &pr,
+            "auto".to_string(),
+            CommitSha("merge-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+        )
+        .await
+        .unwrap();
+        db.attach_try_build(
+            pr,
+            "try".to_string(),
+            CommitSha("try-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let repo = GithubRepoName::new("foo", "bar");
+        let counts = db
+            .count_recent_builds_by_author(&repo, "auto", started_before)
+            .await
+            .unwrap();
+        assert_eq!(counts.get("author"), Some(&1));
+        let counts = db
+            .count_recent_builds_by_author(&repo, "auto", Utc::now() + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        assert!(counts.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn create_pr_with_details(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let repo = GithubRepoName::new("foo", "bar");
+        db.create_pull_request(
+            &repo,
+            PullRequestNumber(1),
+            "main",
+            PullRequestStatus::Open,
+            "author",
+            "Fix the parser",
+            "head-sha",
+        )
+        .await
+        .unwrap();
+        let pr = db
+            .get_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pr.title.as_deref(), Some("Fix the parser"));
+        assert_eq!(pr.author.as_deref(), Some("author"));
+        assert_eq!(pr.head_sha.as_deref(), Some("head-sha"));
+    }
+
+    #[sqlx::test]
+    async fn update_pr_details(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        assert_eq!(pr.title, None);
+
+        db.update_pr_details(
+            &pr,
+            "New title",
+            "new-author",
+            &CommitSha("new-head-sha".to_string()),
+        )
+        .await
+        .unwrap();
+        let pr = db
+            .get_pull_request(&GithubRepoName::new("foo", "bar"), PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pr.title.as_deref(), Some("New title"));
+        assert_eq!(pr.author.as_deref(), Some("new-author"));
+        assert_eq!(pr.head_sha.as_deref(), Some("new-head-sha"));
+    }
+
+    #[sqlx::test]
+    async fn sync_pr_updates_details(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        let created = db
+            .sync_pull_request(
+                &pr.repository,
+                pr.number,
+                "main",
+                MergeableState::Unknown,
+                &PullRequestStatus::Open,
+                "author",
+                "Synchronized title",
+                "synced-sha",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert!(!created);
+        let pr = db
+            .get_pull_request(&pr.repository, pr.number)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pr.title.as_deref(), Some("Synchronized title"));
+        assert_eq!(pr.head_sha.as_deref(), Some("synced-sha"));
+    }
+
+    #[sqlx::test]
+    async fn transaction_commit(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        db.transaction(|tx| {
+            Box::pin(async move { tx.approve(&pr, approval_info(), None, None).await })
+        })
+        .await
+        .unwrap();
+        assert!(is_approved(&db).await);
+    }
+
+    #[sqlx::test]
+    async fn transaction_rollback_on_error(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        let result: Result<(), DbError> = db
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.approve(&pr, approval_info(), None, None).await?;
+                    Err(DbError::NotFound)
+                })
+            })
+            .await;
+        assert!(matches!(result, Err(DbError::NotFound)));
+        assert!(!is_approved(&db).await);
+    }
+
+    #[sqlx::test]
+    async fn reapproval_resets_approval_time(pool: PgPool) {
+        let db = PgDbClient::new(pool.clone());
+        let repo = GithubRepoName::new("foo", "bar");
+        let pr = create_pr(&db).await;
+        db.approve(&pr, approval_info(), None, None).await.unwrap();
+        sqlx::query("UPDATE pull_request SET approved_at = NOW() - INTERVAL '10 days'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let cutoff = Utc::now() - chrono::Duration::days(5);
+        let expired = db.get_prs_approved_before(&repo, cutoff).await.unwrap();
+        assert_eq!(expired.len(), 1);
+
+        db.approve(&pr, approval_info(), None, None).await.unwrap();
+        assert!(
+            db.get_prs_approved_before(&repo, cutoff)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        db.unapprove(&pr).await.unwrap();
+        assert!(
+            db.get_prs_approved_before(&repo, Utc::now() + chrono::Duration::days(1))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[sqlx::test]
+    async fn delegations_of_open_prs(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let repo = GithubRepoName::new("foo", "bar");
+        let pr = create_pr(&db).await;
+        db.delegate(&pr, DelegatedPermission::Try, "reviewer")
+            .await
+            .unwrap();
+
+        let delegations = db.get_delegations(&repo).await.unwrap();
+        assert_eq!(delegations.len(), 1);
+        assert_eq!(delegations[0].pr_number, PullRequestNumber(1));
+        assert_eq!(delegations[0].permission, DelegatedPermission::Try);
+        assert_eq!(delegations[0].delegated_by.as_deref(), Some("reviewer"));
+
+        db.set_pr_status(&repo, PullRequestNumber(1), PullRequestStatus::Closed)
+            .await
+            .unwrap();
+        assert!(db.get_delegations(&repo).await.unwrap().is_empty());
+
+        db.set_pr_status(&repo, PullRequestNumber(1), PullRequestStatus::Open)
+            .await
+            .unwrap();
+        db.remove_delegation(&delegations[0]).await.unwrap();
+        assert!(db.get_delegations(&repo).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn get_repositories(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        assert!(db.get_repositories().await.unwrap().is_empty());
+
+        let foo = GithubRepoName::new("rust-lang", "foo");
+        let bar = GithubRepoName::new("rust-lang", "bar");
+        db.register_repository(&foo).await.unwrap();
+        db.register_repository(&foo).await.unwrap();
+        db.set_repository_config(&bar, "", "hash").await.unwrap();
+        assert_eq!(db.get_repositories().await.unwrap(), vec![bar, foo]);
+    }
+
+    #[sqlx::test]
+    async fn repeated_delivery_is_queued_once(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let repo = GithubRepoName::new("foo", "bar");
+        let id = db
+            .insert_queued_event(&repo, "delivery", "issue_comment", b"{}")
+            .await
+            .unwrap();
+        assert!(id.is_some());
+        let repeated = db
+            .insert_queued_event(&repo, "delivery", "issue_comment", b"{}")
+            .await
+            .unwrap();
+        assert_eq!(repeated, None);
+        let events = db
+            .get_queued_events(QueuedEventStatus::Pending)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[sqlx::test]
+    async fn workflows_ordered_by_creation_with_completion_time(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let pr = create_pr(&db).await;
+        db.attach_try_build(
+            pr,
+            "try".to_string(),
+            CommitSha("try-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+            &[],
+        )
+        .await
+        .unwrap();
+        let build = find_pr_of_build(&db, "try", "try-sha")
+            .await
+            .try_build
+            .unwrap();
+        for (name, run_id) in [("Linux", 2), ("Windows", 1)] {
+            db.create_workflow(
+                &build,
+                name.to_string(),
+                format!("https://ci/{run_id}"),
+                RunId(run_id),
+                WorkflowType::Github,
+                WorkflowStatus::Pending,
+            )
+            .await
+            .unwrap();
+        }
+        db.update_workflow_status(1, WorkflowStatus::Success)
+            .await
+            .unwrap();
+
+        let workflows = db.get_workflows_for_build(&build).await.unwrap();
+        let names = workflows
+            .iter()
+            .map(|w| w.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Linux", "Windows"]);
+        assert!(workflows[0].completed_at.is_none());
+        assert!(workflows[1].completed_at.is_some());
+    }
+
+    #[sqlx::test]
+    async fn rename_repository_moves_rows(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let old = GithubRepoName::new("foo", "bar");
+        let new = GithubRepoName::new("baz", "bar");
+        let pr = create_pr(&db).await;
+        db.attach_try_build(
+            pr,
+            "try".to_string(),
+            CommitSha("try-sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+            &[],
+        )
+        .await
+        .unwrap();
+        db.register_repository(&old).await.unwrap();
+
+        let summary = db.rename_repository(&old, &new).await.unwrap();
+        assert_eq!(summary.pull_requests, 1);
+        assert_eq!(summary.builds, 1);
+
+        assert!(
+            db.get_pull_request(&old, PullRequestNumber(1))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let pr = db
+            .get_pull_request(&new, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pr.repository, new);
+        let build = pr.try_build.unwrap();
+        assert_eq!(build.repository, new);
+        assert!(
+            db.find_build(&new, "try".to_string(), CommitSha("try-sha".to_string()))
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(db.get_repositories().await.unwrap(), vec![new]);
+    }
+
+    #[sqlx::test]
+    async fn rename_repository_to_registered_name(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let old = GithubRepoName::new("foo", "bar");
+        let new = GithubRepoName::new("foo", "baz");
+        create_pr(&db).await;
+        db.register_repository(&old).await.unwrap();
+        db.register_repository(&new).await.unwrap();
+
+        let summary = db.rename_repository(&old, &new).await.unwrap();
+        assert_eq!(summary.pull_requests, 1);
+        assert_eq!(summary.builds, 0);
+        assert_eq!(db.get_repositories().await.unwrap(), vec![new]);
+    }
+
+    #[sqlx::test]
+    async fn find_builds_by_commit_across_branches(pool: PgPool) {
+        let db = PgDbClient::new(pool);
+        let repo = GithubRepoName::new("foo", "bar");
+        let pr = create_pr(&db).await;
+        db.attach_try_build(
+            pr,
+            "try".to_string(),
+            CommitSha("sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+            &[],
+        )
+        .await
+        .unwrap();
+        let pr = db
+            .get_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        db.attach_merge_build(
+            &pr,
+            "auto".to_string(),
+            CommitSha("sha".to_string()),
+            CommitSha("parent".to_string()),
+            CommitSha("head-sha".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let builds = db
+            .find_builds_by_commit(&repo, &CommitSha("sha".to_string()))
+            .await
+            .unwrap();
+        let branches = builds.iter().map(|b| b.branch.as_str()).collect::<Vec<_>>();
+        assert_eq!(branches, vec!["try", "auto"]);
+        assert!(
+            db.find_builds_by_commit(&repo, &CommitSha("other-sha".to_string()))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            db.find_builds_by_commit(
+                &GithubRepoName::new("foo", "baz"),
+                &CommitSha("sha".to_string())
+            )
+            .await
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_transient_error() {
+        let attempts = AtomicU32::new(0);
+        let result = retry_transient("test", || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(DbError::from(sqlx::Error::PoolTimedOut))
+            } else {
+                Ok(42)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_transient_error_gives_up() {
+        let attempts = AtomicU32::new(0);
+        let result: DbResult<()> = retry_transient("test", || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(DbError::from(sqlx::Error::PoolClosed))
+        })
+        .await;
+        assert!(result.unwrap_err().is_transient());
+        assert_eq!(attempts.load(Ordering::SeqCst), TRANSIENT_RETRY_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn do_not_retry_permanent_error() {
+        let attempts = AtomicU32::new(0);
+        let result: DbResult<()> = retry_transient("test", || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(DbError::NotFound)
+        })
+        .await;
+        assert!(matches!(result, Err(DbError::NotFound)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[sqlx::test]
+    async fn reconnect_after_dropped_connections(pool: PgPool) {
+        let db = PgDbClient::new(pool.clone());
+        create_pr(&db).await;
+
+        // Simulate a restart of the database by terminating all connections of the pool,
+        // including the one that runs the query
+        let error = sqlx::query(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = current_database()",
+        )
+        .execute(&pool)
+        .await
+        .unwrap_err();
+        assert!(DbError::from(error).is_transient());
+
+        let pr = db
+            .get_pull_request(&GithubRepoName::new("foo", "bar"), PullRequestNumber(1))
+            .await
+            .unwrap();
+        assert!(pr.is_some());
+    }
 }
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use thiserror::Error;
+
+pub type DbResult<T> = Result<T, DbError>;
+
+/// Number of operations that could not acquire a database connection since the start of
+/// the process.
+static ACQUIRE_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of operations that could not acquire a database connection since the
+/// start of the process.
+pub(crate) fn acquire_failure_count() -> u64 {
+    ACQUIRE_FAILURES.load(Ordering::Relaxed)
+}
+
+/// Error returned by database operations.
+///
+/// Callers that do not care about the kind of the error can simply convert it into
+/// `anyhow::Error` using `?`.
+#[derive(Error, Debug)]
+pub enum DbError {
+    /// A row that was expected to exist was not found.
+    #[error("Record not found")]
+    NotFound,
+    /// The operation violated a uniqueness or a foreign key constraint.
+    #[error("Constraint violation: {0}")]
+    Conflict(#[source] sqlx::Error),
+    /// The database could not be reached. The operation can be retried later.
+    #[error("Database connection error: {0}")]
+    Connection(#[source] sqlx::Error),
+    /// No connection could be acquired from the pool for the given operation, e.g. because all
+    /// connections were busy until the acquire timeout. The operation can be retried later.
+    #[error("Cannot acquire a database connection for `{operation}`: {source}")]
+    Acquire {
+        operation: &'static str,
+        #[source]
+        source: sqlx::Error,
+    },
+    #[error("Database error: {0}")]
+    Other(#[source] sqlx::Error),
+}
+
+impl DbError {
+    /// Returns true if the operation might succeed if it is retried later.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DbError::Connection(_) | DbError::Acquire { .. })
+    }
+
+    /// Attaches the name of the failed operation to errors caused by a failure to acquire
+    /// a connection from the pool. Other errors are returned unchanged.
+    pub(crate) fn in_operation(self, operation: &'static str) -> Self {
+        match self {
+            DbError::Connection(source @ (sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed)) => {
+                ACQUIRE_FAILURES.fetch_add(1, Ordering::Relaxed);
+                DbError::Acquire { operation, source }
+            }
+            error => error,
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            sqlx::Error::Database(db_error)
+                if matches!(
+                    db_error.kind(),
+                    sqlx::error::ErrorKind::UniqueViolation
+                        | sqlx::error::ErrorKind::ForeignKeyViolation
+                ) =>
+            {
+                DbError::Conflict(error)
+            }
+            sqlx::Error::Database(db_error)
+                if db_error
+                    .code()
+                    .is_some_and(|code| is_connection_loss(&code)) =>
+            {
+                DbError::Connection(error)
+            }
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Protocol(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => DbError::Connection(error),
+            _ => DbError::Other(error),
+        }
+    }
+}
+
+/// Returns true if the given SQLSTATE code reports that the connection was lost, e.g. because
+/// the database server is shutting down or restarting.
+fn is_connection_loss(code: &str) -> bool {
+    // Class 08 are connection exceptions, 57P01-57P03 are sent when the server shuts down
+    code.starts_with("08") || matches!(code, "57P01" | "57P02" | "57P03")
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::is_connection_loss;
+    use crate::database::DbError;
+    use crate::database::operations::create_build;
+    use crate::github::{CommitSha, GithubRepoName};
+
+    #[test]
+    fn map_row_not_found() {
+        assert!(matches!(
+            DbError::from(sqlx::Error::RowNotFound),
+            DbError::NotFound
+        ));
+    }
+
+    #[test]
+    fn convert_into_anyhow() {
+        let error: anyhow::Error = DbError::NotFound.into();
+        assert_eq!(error.to_string(), "Record not found");
+    }
+
+    #[test]
+    fn map_connection_errors() {
+        for error in [sqlx::Error::PoolTimedOut, sqlx::Error::PoolClosed] {
+            let error = DbError::from(error);
+            assert!(matches!(error, DbError::Connection(_)));
+            assert!(error.is_transient());
+        }
+    }
+
+    #[test]
+    fn connection_loss_codes() {
+        assert!(is_connection_loss("57P01"));
+        assert!(is_connection_loss("08006"));
+        assert!(!is_connection_loss("23505"));
+    }
+
+    #[test]
+    fn acquire_error_names_operation() {
+        let error = DbError::from(sqlx::Error::PoolTimedOut).in_operation("get_pull_request");
+        assert!(matches!(
+            error,
+            DbError::Acquire {
+                operation: "get_pull_request",
+                ..
+            }
+        ));
+        assert!(error.is_transient());
+        assert!(error.to_string().contains("`get_pull_request`"));
+
+        let error = DbError::NotFound.in_operation("get_pull_request");
+        assert!(matches!(error, DbError::NotFound));
+    }
+
+    #[sqlx::test]
+    async fn map_unique_violation(pool: PgPool) {
+        let repo = GithubRepoName::new("foo", "bar");
+        let sha = CommitSha("sha".to_string());
+        create_build(&pool, &repo, "branch", &sha, &sha, None)
+            .await
+            .unwrap();
+        let error = create_build(&pool, &repo, "branch", &sha, &sha, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DbError::Conflict(_)));
+        assert!(!error.is_transient());
+    }
+}
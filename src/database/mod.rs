@@ -3,15 +3,20 @@ use std::fmt::{Display, Formatter};
 
 use crate::{
     bors::{PullRequestStatus, RollupMode},
-    github::{GithubRepoName, PullRequestNumber},
+    github::{CommitSha, GithubRepoName, PullRequestNumber},
+    permissions::PermissionType,
 };
 use chrono::{DateTime, Utc};
-pub use client::PgDbClient;
+pub use client::{PgDbClient, PgDbTransaction};
+pub(crate) use error::acquire_failure_count;
+pub use error::{DbError, DbResult};
 use octocrab::models::pulls::MergeableState as OctocrabMergeableState;
+use serde::{Deserialize, Serialize};
 use sqlx::error::BoxDynError;
 use sqlx::{Database, Postgres};
 
 mod client;
+mod error;
 pub(crate) mod operations;
 
 type PrimaryKey = i32;
@@ -135,10 +140,14 @@ impl sqlx::Decode<'_, sqlx::Postgres> for PullRequestStatus {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ApprovalInfo {
-    /// The user who approved the pull request.
+    /// The user who approved the pull request. If several approvals were required, the
+    /// approvers are separated by commas.
     pub approver: String,
     /// The SHA of the commit that was approved.
     pub sha: String,
+    /// The SHA of the base branch at the time of the approval.
+    /// It is `None` for approvals that were stored before base branches were tracked.
+    pub base_sha: Option<String>,
 }
 
 /// Represents the approval status of a pull request.
@@ -150,19 +159,23 @@ pub enum ApprovalStatus {
 
 impl sqlx::Type<sqlx::Postgres> for ApprovalStatus {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
-        <(Option<String>, Option<String>) as sqlx::Type<sqlx::Postgres>>::type_info()
+        <(Option<String>, Option<String>, Option<String>) as sqlx::Type<sqlx::Postgres>>::type_info(
+        )
     }
 }
 
 impl<'r> sqlx::Decode<'r, sqlx::Postgres> for ApprovalStatus {
     fn decode(value: sqlx::postgres::PgValueRef<'r>) -> Result<Self, BoxDynError> {
-        let (approver, sha) =
-            <(Option<String>, Option<String>) as sqlx::Decode<sqlx::Postgres>>::decode(value)?;
+        let (approver, sha, base_sha) = <(Option<String>, Option<String>, Option<String>) as sqlx::Decode<
+            sqlx::Postgres,
+        >>::decode(value)?;
 
         match (approver, sha) {
-            (Some(approver), Some(sha)) => {
-                Ok(ApprovalStatus::Approved(ApprovalInfo { approver, sha }))
-            }
+            (Some(approver), Some(sha)) => Ok(ApprovalStatus::Approved(ApprovalInfo {
+                approver,
+                sha,
+                base_sha,
+            })),
             (None, None) => Ok(ApprovalStatus::NotApproved),
             (approver, sha) => Err(format!(
                 "Inconsistent approval state: approver={:?}, sha={:?}",
@@ -173,6 +186,38 @@ impl<'r> sqlx::Decode<'r, sqlx::Postgres> for ApprovalStatus {
     }
 }
 
+/// Permission that was delegated to the author of a pull request.
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+#[sqlx(rename_all = "snake_case")]
+pub enum DelegatedPermission {
+    /// The author can start try builds (`delegate=try`).
+    Try,
+    /// The author can approve the pull request and start try builds (`delegate+`).
+    Review,
+}
+
+impl DelegatedPermission {
+    /// Returns true if this delegation grants the given permission.
+    pub fn grants(&self, permission: PermissionType) -> bool {
+        match self {
+            DelegatedPermission::Review => true,
+            DelegatedPermission::Try => permission == PermissionType::Try,
+        }
+    }
+}
+
+/// A permission that was delegated to the author of an open pull request.
+pub struct DelegationModel {
+    pub pull_request_id: PrimaryKey,
+    pub pr_number: PullRequestNumber,
+    pub permission: DelegatedPermission,
+    /// GitHub username of the user that has delegated the permission.
+    /// Unknown for delegations that were created before it was recorded.
+    pub delegated_by: Option<String>,
+    pub delegated_at: DateTime<Utc>,
+}
+
 /// Describes if a pull request can be merged or not.
 #[derive(Debug, PartialEq, sqlx::Type)]
 #[sqlx(type_name = "TEXT")]
@@ -199,9 +244,10 @@ impl From<OctocrabMergeableState> for MergeableState {
 }
 
 /// Status of a GitHub build.
-#[derive(Debug, PartialEq, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
 #[sqlx(type_name = "TEXT")]
 #[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum BuildStatus {
     /// The build is still waiting for results.
     Pending,
@@ -215,6 +261,35 @@ pub enum BuildStatus {
     Timeouted,
 }
 
+/// Selects a range of rows returned by a query that supports pagination.
+/// The default value selects all rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pagination {
+    /// Maximum number of rows to return, or `None` to return all remaining rows.
+    pub limit: Option<u32>,
+    /// Number of rows to skip.
+    pub offset: u32,
+}
+
+impl Pagination {
+    /// Selects at most `limit` first rows.
+    pub fn first(limit: u32) -> Self {
+        Self {
+            limit: Some(limit),
+            offset: 0,
+        }
+    }
+
+    /// Postgres treats a `NULL` limit as no limit.
+    fn sql_limit(&self) -> Option<i64> {
+        self.limit.map(i64::from)
+    }
+
+    fn sql_offset(&self) -> i64 {
+        i64::from(self.offset)
+    }
+}
+
 /// Represents a single (merged) commit.
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "build")]
@@ -226,6 +301,22 @@ pub struct BuildModel {
     pub status: BuildStatus,
     pub parent: String,
     pub created_at: DateTime<Utc>,
+    /// When the build has finished, `None` while it is running.
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Whether the branches created by bors for the build have already been deleted.
+    pub branches_deleted: bool,
+    /// Failures of checks that are not required are ignored when deciding the build result.
+    pub force: bool,
+    /// ID of the PR that was tested by the build, it stays set when a newer build of the PR is
+    /// started. It is `None` for builds whose PR was deleted.
+    pub pull_request_id: Option<PrimaryKey>,
+    /// ID of the check run that reports the result of the build on the head commit of its PR.
+    pub check_run_id: Option<i64>,
+    /// SHA of the head commit of the PR that was tested by the build.
+    /// It is `None` for builds that were started before it was tracked.
+    pub head_sha: Option<String>,
+    /// CI jobs requested by `try jobs=...`, empty if all jobs were run.
+    pub jobs: Vec<String>,
 }
 
 /// Represents a pull request.
@@ -234,19 +325,43 @@ pub struct PullRequestModel {
     pub id: PrimaryKey,
     pub repository: GithubRepoName,
     pub number: PullRequestNumber,
+    /// GitHub username of the author of the PR.
+    /// It is `None` for PRs that were stored before authors were tracked.
+    pub author: Option<String>,
+    /// Title of the PR, as of the last event or synchronization that updated it.
+    /// It is `None` for PRs that were stored before titles were tracked.
+    pub title: Option<String>,
     pub pr_status: PullRequestStatus,
     pub base_branch: String,
+    /// SHA of the head commit of the PR, as of the last synchronization with GitHub.
+    /// It is `None` for PRs that were never synchronized.
+    pub head_sha: Option<String>,
+    /// Labels of the PR, as of the last synchronization with GitHub.
+    pub labels: Vec<String>,
     pub mergeable_state: MergeableState,
     pub approval_status: ApprovalStatus,
-    pub delegated: bool,
+    pub delegated_permission: Option<DelegatedPermission>,
     pub priority: Option<i32>,
     pub rollup: Option<RollupMode>,
+    /// Whether the PR is squashed when it is landed (`@bors squash`), regardless of the merge
+    /// style of the repository.
+    pub squash: bool,
     pub try_build: Option<BuildModel>,
+    pub merge_build: Option<BuildModel>,
     pub created_at: DateTime<Utc>,
 }
 
 impl PullRequestModel {
-    pub fn is_approved(&self) -> bool {
+    /// Returns true if the pull request was approved at the given head commit.
+    /// An approval is only valid for the commit that was approved, so it is invalidated by
+    /// a later push. Delegation alone does not approve the pull request.
+    pub fn is_approved(&self, head_sha: &CommitSha) -> bool {
+        self.approved_sha() == Some(head_sha.as_ref())
+    }
+
+    /// Returns true if the pull request has an approval, regardless of the commit it was
+    /// approved at.
+    pub fn has_approval(&self) -> bool {
         matches!(self.approval_status, ApprovalStatus::Approved(_))
     }
 
@@ -267,18 +382,26 @@ impl PullRequestModel {
 
 /// Describes whether a workflow is a Github Actions workflow or if it's a job from some external
 /// CI.
-#[derive(Debug, PartialEq, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
 #[sqlx(type_name = "TEXT")]
 #[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum WorkflowType {
+    /// Workflow run on Github Actions.
     Github,
+    /// Check run created by an external CI system, whose result is resolved through its check
+    /// suite.
     External,
+    /// Check run reported through the GitHub Checks API, which was first observed only once it
+    /// was completed.
+    Check,
 }
 
 /// Status of a workflow.
-#[derive(Debug, PartialEq, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
 #[sqlx(type_name = "TEXT")]
 #[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum WorkflowStatus {
     /// Workflow is running.
     Pending,
@@ -286,6 +409,13 @@ pub enum WorkflowStatus {
     Success,
     /// Workflow has failed.
     Failure,
+    /// Workflow did not run, e.g. because of a path filter, or it finished with a neutral
+    /// conclusion. It does not block the build.
+    Skipped,
+    /// Workflow run is waiting for a manual approval by a maintainer. It fails the build.
+    #[sqlx(rename = "action_required")]
+    #[serde(rename = "action_required")]
+    ActionRequired,
 }
 
 /// Represents a workflow run, coming either from Github Actions or from some external CI.
@@ -295,9 +425,82 @@ pub struct WorkflowModel {
     pub name: String,
     pub url: String,
     pub run_id: RunId,
+    /// Attempt of the workflow run, it is increased each time the workflow is re-run.
+    pub run_attempt: i32,
     pub workflow_type: WorkflowType,
     pub status: WorkflowStatus,
     pub created_at: DateTime<Utc>,
+    /// When did the workflow reach a final status.
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Number of workflows of a build in each status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WorkflowStatusCounts {
+    pub pending: usize,
+    pub success: usize,
+    pub failure: usize,
+    pub skipped: usize,
+    pub action_required: usize,
+}
+
+impl WorkflowStatusCounts {
+    /// Total number of workflows of the build.
+    pub fn total(&self) -> usize {
+        self.pending + self.success + self.failure + self.skipped + self.action_required
+    }
+}
+
+/// A try build request that is waiting until a try build slot becomes available.
+pub struct QueuedTryBuildModel {
+    pub id: PrimaryKey,
+    pub pull_request_id: PrimaryKey,
+    pub pr_number: PullRequestNumber,
+    /// Requested parent commit, either a commit SHA or `last`.
+    pub parent: Option<String>,
+    pub jobs: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// State of a webhook event stored in the event queue.
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+#[sqlx(rename_all = "lowercase")]
+pub enum QueuedEventStatus {
+    /// The event has not been handled yet, or its handling was interrupted.
+    Pending,
+    /// The event has been handled.
+    Processed,
+    /// Handling of the event has failed, it is only handled again if it is retried by an admin.
+    Dead,
+}
+
+/// A repository webhook event stored in the event queue, so that it is not lost if bors stops
+/// before the event is handled.
+pub struct QueuedEventModel {
+    pub id: PrimaryKey,
+    pub repository: GithubRepoName,
+    /// GUID of the webhook delivery.
+    pub delivery_id: String,
+    /// Type of the webhook event, from the `X-GitHub-Event` header.
+    pub event_type: String,
+    /// Body of the webhook, it is parsed again when the event is replayed.
+    pub payload: Vec<u8>,
+    pub status: QueuedEventStatus,
+    /// How many times the handling of the event was started.
+    pub attempts: i32,
+    /// Error of the last failed attempt to handle the event.
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A comment of the bot that is edited in place to show the current state of a PR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusCommentModel {
+    /// GitHub ID of the comment.
+    pub comment_id: u64,
+    /// Summaries of the previous states of the PR, from the oldest one.
+    pub history: Vec<String>,
 }
 
 /// Represents the state of a repository's tree.
@@ -338,10 +541,187 @@ impl sqlx::Decode<'_, sqlx::Postgres> for TreeState {
     }
 }
 
+/// Number of rows that were moved to the new name of a renamed repository.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepositoryRenameSummary {
+    pub pull_requests: u64,
+    pub builds: u64,
+}
+
 /// Represents a repository configuration.
 pub struct RepoModel {
     pub id: PrimaryKey,
     pub name: GithubRepoName,
     pub tree_state: TreeState,
+    /// Is bors paused on the repository? See `@bors pause`.
+    pub paused: bool,
     pub created_at: DateTime<Utc>,
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::bors::PullRequestStatus;
+    use std::fmt::Debug;
+
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+    use sqlx::{Decode, Encode, PgPool, Postgres, Type};
+
+    use crate::database::{
+        ApprovalInfo, ApprovalStatus, BuildStatus, DelegatedPermission, MergeableState,
+        PullRequestModel, WorkflowStatus, WorkflowType,
+    };
+    use crate::github::{CommitSha, GithubRepoName, PullRequestNumber};
+    use crate::permissions::PermissionType;
+
+    fn pr_model(
+        approval_status: ApprovalStatus,
+        delegated_permission: Option<DelegatedPermission>,
+    ) -> PullRequestModel {
+        PullRequestModel {
+            id: 1,
+            repository: GithubRepoName::new("foo", "bar"),
+            number: PullRequestNumber(1),
+            author: Some("author".to_string()),
+            title: None,
+            pr_status: PullRequestStatus::Open,
+            base_branch: "main".to_string(),
+            head_sha: None,
+            labels: vec![],
+            mergeable_state: MergeableState::Mergeable,
+            approval_status,
+            delegated_permission,
+            priority: None,
+            rollup: None,
+            squash: false,
+            try_build: None,
+            merge_build: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn approved_at(sha: &str) -> ApprovalStatus {
+        ApprovalStatus::Approved(ApprovalInfo {
+            approver: "reviewer".to_string(),
+            sha: sha.to_string(),
+            base_sha: None,
+        })
+    }
+
+    #[test]
+    fn approved_at_head() {
+        let pr = pr_model(approved_at("sha1"), None);
+        assert!(pr.is_approved(&CommitSha("sha1".to_string())));
+    }
+
+    #[test]
+    fn approved_then_pushed() {
+        let pr = pr_model(approved_at("sha1"), None);
+        assert!(!pr.is_approved(&CommitSha("sha2".to_string())));
+        assert!(pr.has_approval());
+    }
+
+    #[test]
+    fn never_approved() {
+        let pr = pr_model(ApprovalStatus::NotApproved, None);
+        assert!(!pr.is_approved(&CommitSha("sha1".to_string())));
+        assert!(!pr.has_approval());
+    }
+
+    #[test]
+    fn delegated_but_not_approved() {
+        let pr = pr_model(
+            ApprovalStatus::NotApproved,
+            Some(DelegatedPermission::Review),
+        );
+        assert!(!pr.is_approved(&CommitSha("sha1".to_string())));
+    }
+
+    #[test]
+    fn delegated_approval_at_head() {
+        let pr = pr_model(approved_at("sha1"), Some(DelegatedPermission::Review));
+        assert!(pr.is_approved(&CommitSha("sha1".to_string())));
+        assert!(!pr.is_approved(&CommitSha("sha2".to_string())));
+    }
+
+    #[test]
+    fn try_delegation_grants_only_try() {
+        let permission = DelegatedPermission::Try;
+        assert!(permission.grants(PermissionType::Try));
+        assert!(!permission.grants(PermissionType::Review));
+    }
+
+    #[test]
+    fn review_delegation_grants_try() {
+        let permission = DelegatedPermission::Review;
+        assert!(permission.grants(PermissionType::Try));
+        assert!(permission.grants(PermissionType::Review));
+    }
+
+    /// Checks that `value` is serialized by serde to the same string as the one stored in the
+    /// database, and that the string is deserialized back to `value`.
+    async fn assert_serde_matches_db<T>(pool: &PgPool, value: T)
+    where
+        T: Serialize
+            + DeserializeOwned
+            + PartialEq
+            + Debug
+            + Copy
+            + Send
+            + Unpin
+            + Type<Postgres>
+            + 'static,
+        for<'q> T: Encode<'q, Postgres> + Decode<'q, Postgres>,
+    {
+        let stored: String = sqlx::query_scalar("SELECT $1::TEXT")
+            .bind(value)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(value).unwrap(),
+            serde_json::Value::String(stored.clone())
+        );
+        assert_eq!(
+            serde_json::from_value::<T>(serde_json::Value::String(stored.clone())).unwrap(),
+            value
+        );
+        let loaded: T = sqlx::query_scalar("SELECT $1::TEXT")
+            .bind(stored)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(loaded, value);
+    }
+
+    #[sqlx::test]
+    async fn status_serde_matches_db(pool: PgPool) {
+        for status in [
+            BuildStatus::Pending,
+            BuildStatus::Success,
+            BuildStatus::Failure,
+            BuildStatus::Cancelled,
+            BuildStatus::Timeouted,
+        ] {
+            assert_serde_matches_db(&pool, status).await;
+        }
+        for status in [
+            WorkflowStatus::Pending,
+            WorkflowStatus::Success,
+            WorkflowStatus::Failure,
+            WorkflowStatus::Skipped,
+            WorkflowStatus::ActionRequired,
+        ] {
+            assert_serde_matches_db(&pool, status).await;
+        }
+        for workflow_type in [
+            WorkflowType::Github,
+            WorkflowType::External,
+            WorkflowType::Check,
+        ] {
+            assert_serde_matches_db(&pool, workflow_type).await;
+        }
+    }
+}
@@ -1,32 +1,55 @@
+use std::collections::HashMap;
+
 use chrono::DateTime;
 use chrono::Utc;
 use sqlx::postgres::PgExecutor;
 
 use crate::bors::PullRequestStatus;
 use crate::bors::RollupMode;
+use crate::bors::comment::CommentKind;
 use crate::database::BuildStatus;
 use crate::database::RepoModel;
 use crate::database::WorkflowModel;
 use crate::github::CommitSha;
 use crate::github::GithubRepoName;
 use crate::github::PullRequestNumber;
-use crate::utils::timing::measure_db_query;
+use crate::utils::timing;
 
-use super::ApprovalInfo;
 use super::ApprovalStatus;
 use super::BuildModel;
+use super::DbResult;
+use super::DelegatedPermission;
+use super::DelegationModel;
 use super::MergeableState;
+use super::Pagination;
 use super::PullRequestModel;
+use super::QueuedEventModel;
+use super::QueuedEventStatus;
+use super::QueuedTryBuildModel;
 use super::RunId;
+use super::StatusCommentModel;
 use super::TreeState;
 use super::WorkflowStatus;
+use super::WorkflowStatusCounts;
 use super::WorkflowType;
 
+/// Measures the duration of a database operation, and names the operation in the error if no
+/// connection could be acquired for it.
+async fn measure_db_query<T, F, Fut>(query_name: &'static str, f: F) -> DbResult<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = DbResult<T>>,
+{
+    timing::measure_db_query(query_name, f)
+        .await
+        .map_err(|error| error.in_operation(query_name))
+}
+
 pub(crate) async fn get_pull_request(
     executor: impl PgExecutor<'_>,
     repo: &GithubRepoName,
     pr_number: PullRequestNumber,
-) -> anyhow::Result<Option<PullRequestModel>> {
+) -> DbResult<Option<PullRequestModel>> {
     measure_db_query("get_pull_request", || async {
         let record = sqlx::query_as!(
             PullRequestModel,
@@ -36,19 +59,31 @@ pub(crate) async fn get_pull_request(
         pr.repository as "repository: GithubRepoName",
         pr.number as "number!: i64",
         (
-            pr.approved_by,
-            pr.approved_sha
+            (
+                SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+                FROM approval
+                WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+            ),
+            pr.approved_sha,
+            pr.approved_base_sha
         ) AS "approval_status!: ApprovalStatus",
         pr.status as "pr_status: PullRequestStatus", 
         pr.priority,
         pr.rollup as "rollup: RollupMode",
-        pr.delegated,
+        pr.squash,
+        pr.delegated_permission as "delegated_permission: DelegatedPermission",
+        pr.author,
+        pr.title,
         pr.base_branch,
+        pr.head_sha,
+        pr.labels,
         pr.mergeable_state as "mergeable_state: MergeableState",
         pr.created_at as "created_at: DateTime<Utc>",
-        build AS "try_build: BuildModel"
+        try_build AS "try_build: BuildModel",
+        merge_build AS "merge_build: BuildModel"
     FROM pull_request as pr
-    LEFT JOIN build ON pr.build_id = build.id
+    LEFT JOIN build AS try_build ON pr.build_id = try_build.id
+    LEFT JOIN build AS merge_build ON pr.merge_build_id = merge_build.id
     WHERE pr.repository = $1 AND
           pr.number = $2
     "#,
@@ -69,17 +104,46 @@ pub(crate) async fn create_pull_request(
     pr_number: PullRequestNumber,
     base_branch: &str,
     pr_status: PullRequestStatus,
-) -> anyhow::Result<()> {
+    author: &str,
+    title: &str,
+    head_sha: &str,
+) -> DbResult<()> {
     measure_db_query("create_pull_request", || async {
         sqlx::query!(
             r#"
-INSERT INTO pull_request (repository, number, base_branch, status)
-VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING
+INSERT INTO pull_request (repository, number, base_branch, status, author, title, head_sha)
+VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING
 "#,
             repo as &GithubRepoName,
             pr_number.0 as i32,
             base_branch,
             pr_status as PullRequestStatus,
+            author,
+            title,
+            head_sha,
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Updates the title, author and head SHA of a pull request from its current state on GitHub.
+pub(crate) async fn update_pr_details(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    title: &str,
+    author: &str,
+    head_sha: &str,
+) -> DbResult<()> {
+    measure_db_query("update_pr_details", || async {
+        sqlx::query!(
+            "UPDATE pull_request SET title = $2, author = $3, head_sha = $4 WHERE id = $1",
+            pr_id,
+            title,
+            author,
+            head_sha,
         )
         .execute(executor)
         .await?;
@@ -93,7 +157,7 @@ pub(crate) async fn set_pr_status(
     repo: &GithubRepoName,
     pr_number: PullRequestNumber,
     pr_status: PullRequestStatus,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("set_pr_status", || async {
         sqlx::query!(
             "UPDATE pull_request SET status = $3 WHERE repository = $1 AND number = $2",
@@ -115,18 +179,20 @@ pub(crate) async fn upsert_pull_request(
     base_branch: &str,
     mergeable_state: MergeableState,
     pr_status: &PullRequestStatus,
-) -> anyhow::Result<PullRequestModel> {
+    author: &str,
+) -> DbResult<PullRequestModel> {
     measure_db_query("upsert_pull_request", || async {
         let record = sqlx::query_as!(
             PullRequestModel,
             r#"
             WITH upserted_pr AS (
-                INSERT INTO pull_request (repository, number, base_branch, mergeable_state, status)
-                VALUES ($1, $2, $3, $4, $5)
+                INSERT INTO pull_request (repository, number, base_branch, mergeable_state, status, author)
+                VALUES ($1, $2, $3, $4, $5, $6)
                 ON CONFLICT (repository, number)
                 DO UPDATE SET
                     base_branch = $3,
-                    mergeable_state = $4
+                    mergeable_state = $4,
+                    author = $6
                 RETURNING *
             )
             SELECT
@@ -134,25 +200,38 @@ pub(crate) async fn upsert_pull_request(
                 pr.repository as "repository: GithubRepoName",
                 pr.number as "number!: i64",
                 (
-                    pr.approved_by,
-                    pr.approved_sha
+                    (
+                        SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+                        FROM approval
+                        WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+                    ),
+                    pr.approved_sha,
+                    pr.approved_base_sha
                 ) AS "approval_status!: ApprovalStatus",
                 pr.status as "pr_status: PullRequestStatus", 
                 pr.priority,
                 pr.rollup as "rollup: RollupMode",
-                pr.delegated,
+                pr.squash,
+                pr.delegated_permission as "delegated_permission: DelegatedPermission",
+                pr.author,
+                pr.title,
                 pr.base_branch,
+                pr.head_sha,
+                pr.labels,
                 pr.mergeable_state as "mergeable_state: MergeableState",
                 pr.created_at as "created_at: DateTime<Utc>",
-                build AS "try_build: BuildModel"
+                try_build AS "try_build: BuildModel",
+                merge_build AS "merge_build: BuildModel"
             FROM upserted_pr as pr
-            LEFT JOIN build ON pr.build_id = build.id
+            LEFT JOIN build AS try_build ON pr.build_id = try_build.id
+            LEFT JOIN build AS merge_build ON pr.merge_build_id = merge_build.id
             "#,
             repo as &GithubRepoName,
             pr_number.0 as i32,
             base_branch,
             mergeable_state as _,
             pr_status as &PullRequestStatus,
+            author,
         )
         .fetch_one(executor)
         .await?;
@@ -161,6 +240,61 @@ pub(crate) async fn upsert_pull_request(
     .await
 }
 
+/// Creates or updates a pull request with its current state on GitHub.
+/// An unknown mergeable state does not overwrite the stored one.
+/// Returns `true` if the pull request was newly created.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn sync_pull_request(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    pr_number: PullRequestNumber,
+    base_branch: &str,
+    mergeable_state: MergeableState,
+    pr_status: &PullRequestStatus,
+    author: &str,
+    title: &str,
+    head_sha: &str,
+    labels: &[String],
+) -> DbResult<bool> {
+    measure_db_query("sync_pull_request", || async {
+        let record = sqlx::query!(
+            r#"
+            INSERT INTO pull_request (repository, number, base_branch, mergeable_state, status, author, head_sha, labels, title)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (repository, number)
+            DO UPDATE SET
+                base_branch = $3,
+                mergeable_state = CASE
+                    WHEN $4 = 'unknown' THEN pull_request.mergeable_state
+                    ELSE $4
+                END,
+                status = $5,
+                author = $6,
+                head_sha = $7,
+                labels = $8,
+                title = $9
+            RETURNING (xmax = 0) AS "created!"
+            "#,
+            repo as &GithubRepoName,
+            pr_number.0 as i32,
+            base_branch,
+            mergeable_state as _,
+            pr_status as &PullRequestStatus,
+            author,
+            head_sha,
+            labels,
+            title,
+        )
+        .fetch_one(executor)
+        .await?;
+        Ok(record.created)
+    })
+    .await
+}
+
+/// Updates the mergeable state of pull requests targeting the given base branch, whose base
+/// branch has moved to `base_sha`.
+/// Pull requests that were approved while the base branch was already at `base_sha` are skipped.
 // FIXME:
 // 1) Add a database index on (repository, base_branch)
 // 2) Filter PRs by state (only update open PRs, once we have PR state tracking)
@@ -168,18 +302,22 @@ pub(crate) async fn update_mergeable_states_by_base_branch(
     executor: impl PgExecutor<'_>,
     repo: &GithubRepoName,
     base_branch: &str,
+    base_sha: &str,
     mergeable_state: MergeableState,
-) -> anyhow::Result<u64> {
+) -> DbResult<u64> {
     measure_db_query("update_mergeable_states_by_base_branch", || async {
         let result = sqlx::query!(
             r#"
             UPDATE pull_request
             SET mergeable_state = $1
-            WHERE repository = $2 AND base_branch = $3
+            WHERE repository = $2
+              AND base_branch = $3
+              AND approved_base_sha IS DISTINCT FROM $4
             "#,
             mergeable_state as _,
             repo as &GithubRepoName,
             base_branch,
+            base_sha,
         )
         .execute(executor)
         .await?;
@@ -189,27 +327,183 @@ pub(crate) async fn update_mergeable_states_by_base_branch(
     .await
 }
 
+pub(crate) async fn set_pr_mergeable_state(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    mergeable_state: MergeableState,
+) -> DbResult<()> {
+    measure_db_query("set_pr_mergeable_state", || async {
+        sqlx::query!(
+            "UPDATE pull_request SET mergeable_state = $1 WHERE id = $2",
+            mergeable_state as _,
+            pr_id,
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Records that the mergeable state of the given pull request was just checked on GitHub.
+pub(crate) async fn set_pr_mergeable_checked_at(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+) -> DbResult<()> {
+    measure_db_query("set_pr_mergeable_checked_at", || async {
+        sqlx::query!(
+            "UPDATE pull_request SET mergeable_checked_at = NOW() WHERE id = $1",
+            pr_id,
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Returns at most `limit` open (or draft) pull requests of the given repository whose
+/// mergeable state is unknown. PRs that were never checked go first, followed by the PRs that
+/// were checked the longest time ago, so that all PRs are eventually checked even if there are
+/// more than `limit` of them.
+pub(crate) async fn get_prs_with_unknown_mergeable_state(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    limit: i64,
+) -> DbResult<Vec<PullRequestModel>> {
+    measure_db_query("get_prs_with_unknown_mergeable_state", || async {
+        let records = sqlx::query_as!(
+            PullRequestModel,
+            r#"
+    SELECT
+        pr.id,
+        pr.repository as "repository: GithubRepoName",
+        pr.number as "number!: i64",
+        (
+            (
+                SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+                FROM approval
+                WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+            ),
+            pr.approved_sha,
+            pr.approved_base_sha
+        ) AS "approval_status!: ApprovalStatus",
+        pr.status as "pr_status: PullRequestStatus",
+        pr.priority,
+        pr.rollup as "rollup: RollupMode",
+        pr.squash,
+        pr.delegated_permission as "delegated_permission: DelegatedPermission",
+        pr.author,
+        pr.title,
+        pr.base_branch,
+        pr.head_sha,
+        pr.labels,
+        pr.mergeable_state as "mergeable_state: MergeableState",
+        pr.created_at as "created_at: DateTime<Utc>",
+        try_build AS "try_build: BuildModel",
+        merge_build AS "merge_build: BuildModel"
+    FROM pull_request as pr
+    LEFT JOIN build AS try_build ON pr.build_id = try_build.id
+    LEFT JOIN build AS merge_build ON pr.merge_build_id = merge_build.id
+    WHERE pr.repository = $1 AND
+          pr.mergeable_state = 'unknown' AND
+          pr.status IN ('open', 'draft')
+    ORDER BY pr.mergeable_checked_at NULLS FIRST, pr.number
+    LIMIT $2
+    "#,
+            repo as &GithubRepoName,
+            limit
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(records)
+    })
+    .await
+}
+
+/// Returns the approved pull requests of the given repository that were approved before
+/// `approved_before`, ordered by their number.
+pub(crate) async fn get_prs_approved_before(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    approved_before: DateTime<Utc>,
+) -> DbResult<Vec<PullRequestModel>> {
+    measure_db_query("get_prs_approved_before", || async {
+        let records = sqlx::query_as!(
+            PullRequestModel,
+            r#"
+    SELECT
+        pr.id,
+        pr.repository as "repository: GithubRepoName",
+        pr.number as "number!: i64",
+        (
+            (
+                SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+                FROM approval
+                WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+            ),
+            pr.approved_sha,
+            pr.approved_base_sha
+        ) AS "approval_status!: ApprovalStatus",
+        pr.status as "pr_status: PullRequestStatus",
+        pr.priority,
+        pr.rollup as "rollup: RollupMode",
+        pr.squash,
+        pr.delegated_permission as "delegated_permission: DelegatedPermission",
+        pr.author,
+        pr.title,
+        pr.base_branch,
+        pr.head_sha,
+        pr.labels,
+        pr.mergeable_state as "mergeable_state: MergeableState",
+        pr.created_at as "created_at: DateTime<Utc>",
+        try_build AS "try_build: BuildModel",
+        merge_build AS "merge_build: BuildModel"
+    FROM pull_request as pr
+    LEFT JOIN build AS try_build ON pr.build_id = try_build.id
+    LEFT JOIN build AS merge_build ON pr.merge_build_id = merge_build.id
+    WHERE pr.repository = $1 AND
+          pr.approved_sha IS NOT NULL AND
+          pr.approved_at < $2
+    ORDER BY pr.number
+    "#,
+            repo as &GithubRepoName,
+            approved_before
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(records)
+    })
+    .await
+}
+
+/// Marks the pull request as approved at the head commit `sha`. The reviewers that have approved
+/// it are stored separately, see [add_approval].
 pub(crate) async fn approve_pull_request(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
-    approval_info: ApprovalInfo,
+    sha: &str,
+    base_sha: Option<&str>,
     priority: Option<u32>,
     rollup: Option<RollupMode>,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     let priority_i32 = priority.map(|p| p as i32);
 
     measure_db_query("approve_pull_request", || async {
         sqlx::query!(
             r#"
 UPDATE pull_request
-SET approved_by = $1,
-    approved_sha = $2,
+SET approved_sha = $1,
+    approved_base_sha = $2,
+    approved_at = NOW(),
     priority = COALESCE($3, priority),
     rollup = COALESCE($4, rollup)
 WHERE id = $5
 "#,
-            approval_info.approver,
-            approval_info.sha,
+            sha,
+            base_sha,
             priority_i32,
             rollup as Option<RollupMode>,
             pr_id,
@@ -224,10 +518,17 @@ WHERE id = $5
 pub(crate) async fn unapprove_pull_request(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("unapprove_pull_request", || async {
         sqlx::query!(
-            "UPDATE pull_request SET approved_by = NULL, approved_sha = NULL WHERE id = $1",
+            r#"
+UPDATE pull_request
+SET approved_sha = NULL,
+    approved_force = FALSE,
+    approved_base_sha = NULL,
+    approved_at = NULL
+WHERE id = $1
+"#,
             pr_id
         )
         .execute(executor)
@@ -237,14 +538,107 @@ pub(crate) async fn unapprove_pull_request(
     .await
 }
 
+/// Records the approval of `approver` at the head commit `sha`, replacing their previous approval
+/// of the pull request.
+pub(crate) async fn add_approval(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    approver: &str,
+    sha: &str,
+) -> DbResult<()> {
+    measure_db_query("add_approval", || async {
+        sqlx::query!(
+            r#"
+INSERT INTO approval (pull_request_id, approver, sha)
+VALUES ($1, $2, $3)
+ON CONFLICT (pull_request_id, approver)
+DO UPDATE SET sha = $3, created_at = NOW()
+"#,
+            pr_id,
+            approver,
+            sha
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the reviewers that have approved the pull request at the head commit `sha`, in the
+/// order in which they approved it.
+pub(crate) async fn get_approvers(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    sha: &str,
+) -> DbResult<Vec<String>> {
+    measure_db_query("get_approvers", || async {
+        let approvers = sqlx::query_scalar!(
+            r#"
+SELECT approver
+FROM approval
+WHERE pull_request_id = $1 AND sha = $2
+ORDER BY created_at, id
+"#,
+            pr_id,
+            sha
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(approvers)
+    })
+    .await
+}
+
+/// Removes the approval of `approver`.
+/// Returns `false` if they have not approved the pull request.
+pub(crate) async fn remove_approval(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    approver: &str,
+) -> DbResult<bool> {
+    measure_db_query("remove_approval", || async {
+        let result = sqlx::query!(
+            "DELETE FROM approval WHERE pull_request_id = $1 AND approver = $2",
+            pr_id,
+            approver
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    })
+    .await
+}
+
+/// Removes the approvals of all reviewers of the pull request.
+pub(crate) async fn remove_approvals(executor: impl PgExecutor<'_>, pr_id: i32) -> DbResult<()> {
+    measure_db_query("remove_approvals", || async {
+        sqlx::query!("DELETE FROM approval WHERE pull_request_id = $1", pr_id)
+            .execute(executor)
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
 pub(crate) async fn delegate_pull_request(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
-) -> anyhow::Result<()> {
+    permission: DelegatedPermission,
+    delegated_by: &str,
+) -> DbResult<()> {
     measure_db_query("delegate_pull_request", || async {
         sqlx::query!(
-            "UPDATE pull_request SET delegated = TRUE WHERE id = $1",
+            r#"
+UPDATE pull_request
+SET delegated_permission = $2,
+    delegated_by = $3,
+    delegated_at = NOW()
+WHERE id = $1
+"#,
             pr_id,
+            permission as DelegatedPermission,
+            delegated_by
         )
         .execute(executor)
         .await?;
@@ -256,10 +650,16 @@ pub(crate) async fn delegate_pull_request(
 pub(crate) async fn undelegate_pull_request(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("undelegate_pull_request", || async {
         sqlx::query!(
-            "UPDATE pull_request SET delegated = FALSE WHERE id = $1",
+            r#"
+UPDATE pull_request
+SET delegated_permission = NULL,
+    delegated_by = NULL,
+    delegated_at = NULL
+WHERE id = $1
+"#,
             pr_id
         )
         .execute(executor)
@@ -269,10 +669,41 @@ pub(crate) async fn undelegate_pull_request(
     .await
 }
 
+/// Returns the delegations of the open (or draft) pull requests of the given repository, ordered
+/// by the number of the PR.
+pub(crate) async fn get_delegations(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<Vec<DelegationModel>> {
+    measure_db_query("get_delegations", || async {
+        let delegations = sqlx::query_as!(
+            DelegationModel,
+            r#"
+SELECT
+    pr.id as pull_request_id,
+    pr.number as "pr_number!: i64",
+    pr.delegated_permission as "permission!: DelegatedPermission",
+    pr.delegated_by,
+    pr.delegated_at as "delegated_at!: DateTime<Utc>"
+FROM pull_request as pr
+WHERE pr.repository = $1 AND
+      pr.delegated_permission IS NOT NULL AND
+      pr.status IN ('open', 'draft')
+ORDER BY pr.number
+"#,
+            repo as &GithubRepoName
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(delegations)
+    })
+    .await
+}
+
 pub(crate) async fn find_pr_by_build(
     executor: impl PgExecutor<'_>,
     build_id: i32,
-) -> anyhow::Result<Option<PullRequestModel>> {
+) -> DbResult<Option<PullRequestModel>> {
     measure_db_query("find_pr_by_build", || async {
         let record = sqlx::query_as!(
             PullRequestModel,
@@ -282,20 +713,32 @@ SELECT
     pr.repository as "repository: GithubRepoName",
     pr.number as "number!: i64",
     (
-        pr.approved_by,
-        pr.approved_sha
+        (
+            SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+            FROM approval
+            WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+        ),
+        pr.approved_sha,
+        pr.approved_base_sha
     ) AS "approval_status!: ApprovalStatus",
     pr.status as "pr_status: PullRequestStatus",  
-    pr.delegated,
+    pr.delegated_permission as "delegated_permission: DelegatedPermission",
+    pr.author,
+    pr.title,
     pr.priority,
     pr.base_branch,
+    pr.head_sha,
+    pr.labels,
     pr.mergeable_state as "mergeable_state: MergeableState",
     pr.rollup as "rollup: RollupMode",
+    pr.squash,
     pr.created_at as "created_at: DateTime<Utc>",
-    build AS "try_build: BuildModel"
+    try_build AS "try_build: BuildModel",
+    merge_build AS "merge_build: BuildModel"
 FROM pull_request as pr
-LEFT JOIN build ON pr.build_id = build.id
-WHERE build.id = $1
+LEFT JOIN build AS try_build ON pr.build_id = try_build.id
+LEFT JOIN build AS merge_build ON pr.merge_build_id = merge_build.id
+WHERE pr.id = (SELECT pull_request_id FROM build WHERE build.id = $1)
 "#,
             build_id
         )
@@ -311,7 +754,7 @@ pub(crate) async fn update_pr_build_id(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
     build_id: i32,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("update_pr_build_id", || async {
         sqlx::query!(
             "UPDATE pull_request SET build_id = $1 WHERE id = $2",
@@ -325,25 +768,98 @@ pub(crate) async fn update_pr_build_id(
     .await
 }
 
+pub(crate) async fn update_pr_merge_build_id(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    build_id: i32,
+) -> DbResult<()> {
+    measure_db_query("update_pr_merge_build_id", || async {
+        sqlx::query!(
+            "UPDATE pull_request SET merge_build_id = $1 WHERE id = $2",
+            build_id,
+            pr_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the open (or draft) pull requests of the repository.
+pub(crate) async fn get_open_prs(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<Vec<PullRequestModel>> {
+    measure_db_query("get_open_prs", || async {
+        let records = sqlx::query_as!(
+            PullRequestModel,
+            r#"
+SELECT
+    pr.id,
+    pr.repository as "repository: GithubRepoName",
+    pr.number as "number!: i64",
+    (
+        (
+            SELECT string_agg(approval.approver, ', ' ORDER BY approval.created_at, approval.id)
+            FROM approval
+            WHERE approval.pull_request_id = pr.id AND approval.sha = pr.approved_sha
+        ),
+        pr.approved_sha,
+        pr.approved_base_sha
+    ) AS "approval_status!: ApprovalStatus",
+    pr.status as "pr_status: PullRequestStatus",
+    pr.delegated_permission as "delegated_permission: DelegatedPermission",
+    pr.author,
+    pr.title,
+    pr.priority,
+    pr.base_branch,
+    pr.head_sha,
+    pr.labels,
+    pr.mergeable_state as "mergeable_state: MergeableState",
+    pr.rollup as "rollup: RollupMode",
+    pr.squash,
+    pr.created_at as "created_at: DateTime<Utc>",
+    try_build AS "try_build: BuildModel",
+    merge_build AS "merge_build: BuildModel"
+FROM pull_request as pr
+LEFT JOIN build AS try_build ON pr.build_id = try_build.id
+LEFT JOIN build AS merge_build ON pr.merge_build_id = merge_build.id
+WHERE pr.repository = $1 AND
+      pr.status IN ('open', 'draft')
+ORDER BY pr.number
+"#,
+            repo as &GithubRepoName
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(records)
+    })
+    .await
+}
+
 pub(crate) async fn create_build(
     executor: impl PgExecutor<'_>,
     repo: &GithubRepoName,
     branch: &str,
     commit_sha: &CommitSha,
     parent: &CommitSha,
-) -> anyhow::Result<i32> {
+    pr_id: Option<i32>,
+) -> DbResult<i32> {
     measure_db_query("create_build", || async {
         let build_id = sqlx::query_scalar!(
             r#"
-INSERT INTO build (repository, branch, commit_sha, parent, status)
-VALUES ($1, $2, $3, $4, $5)
+INSERT INTO build (repository, branch, commit_sha, parent, status, pull_request_id)
+VALUES ($1, $2, $3, $4, $5, $6)
 RETURNING id
 "#,
             repo as &GithubRepoName,
             branch,
             commit_sha.0,
             parent.0,
-            BuildStatus::Pending as BuildStatus
+            BuildStatus::Pending as BuildStatus,
+            pr_id
         )
         .fetch_one(executor)
         .await?;
@@ -357,7 +873,7 @@ pub(crate) async fn find_build(
     repo: &GithubRepoName,
     branch: &str,
     commit_sha: &CommitSha,
-) -> anyhow::Result<Option<BuildModel>> {
+) -> DbResult<Option<BuildModel>> {
     measure_db_query("find_build", || async {
         let build = sqlx::query_as!(
             BuildModel,
@@ -369,7 +885,14 @@ SELECT
     commit_sha,
     parent,
     status as "status: BuildStatus",
-    created_at as "created_at: DateTime<Utc>"
+    created_at as "created_at: DateTime<Utc>",
+    completed_at as "completed_at: DateTime<Utc>",
+    branches_deleted,
+    force,
+    pull_request_id,
+    check_run_id,
+    head_sha,
+    jobs
 FROM build
 WHERE repository = $1
     AND branch = $2
@@ -386,11 +909,13 @@ WHERE repository = $1
     .await
 }
 
-pub(crate) async fn get_running_builds(
+/// Returns all builds of the given repository at the given commit, regardless of their branch.
+pub(crate) async fn find_builds_by_commit(
     executor: impl PgExecutor<'_>,
     repo: &GithubRepoName,
-) -> anyhow::Result<Vec<BuildModel>> {
-    measure_db_query("get_running_builds", || async {
+    commit_sha: &CommitSha,
+) -> DbResult<Vec<BuildModel>> {
+    measure_db_query("find_builds_by_commit", || async {
         let builds = sqlx::query_as!(
             BuildModel,
             r#"
@@ -401,13 +926,21 @@ SELECT
     commit_sha,
     parent,
     status as "status: BuildStatus",
-    created_at as "created_at: DateTime<Utc>"
+    created_at as "created_at: DateTime<Utc>",
+    force,
+    check_run_id,
+    head_sha,
+    pull_request_id,
+    completed_at as "completed_at: DateTime<Utc>",
+    branches_deleted,
+    jobs
 FROM build
 WHERE repository = $1
-    AND status = $2
+    AND commit_sha = $2
+ORDER BY created_at, id
 "#,
             repo as &GithubRepoName,
-            BuildStatus::Pending as BuildStatus
+            commit_sha.0
         )
         .fetch_all(executor)
         .await?;
@@ -416,18 +949,289 @@ WHERE repository = $1
     .await
 }
 
-pub(crate) async fn update_build_status(
+/// Loads a build and locks its row until the end of the current transaction.
+pub(crate) async fn lock_build(
     executor: impl PgExecutor<'_>,
     build_id: i32,
-    status: BuildStatus,
-) -> anyhow::Result<()> {
-    measure_db_query("update_build_status", || async {
-        sqlx::query!(
-            "UPDATE build SET status = $1 WHERE id = $2",
-            status as _,
+) -> DbResult<Option<BuildModel>> {
+    measure_db_query("lock_build", || async {
+        let build = sqlx::query_as!(
+            BuildModel,
+            r#"
+SELECT
+    id,
+    repository as "repository: GithubRepoName",
+    branch,
+    commit_sha,
+    parent,
+    status as "status: BuildStatus",
+    created_at as "created_at: DateTime<Utc>",
+    completed_at as "completed_at: DateTime<Utc>",
+    branches_deleted,
+    force,
+    pull_request_id,
+    check_run_id,
+    head_sha,
+    jobs
+FROM build
+WHERE id = $1
+FOR UPDATE
+"#,
             build_id
         )
-        .execute(executor)
+        .fetch_optional(executor)
+        .await?;
+        Ok(build)
+    })
+    .await
+}
+
+/// Returns pending builds of the given repository, ordered from the oldest one.
+pub(crate) async fn get_running_builds(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    page: Pagination,
+) -> DbResult<Vec<BuildModel>> {
+    measure_db_query("get_running_builds", || async {
+        let builds = sqlx::query_as!(
+            BuildModel,
+            r#"
+SELECT
+    id,
+    repository as "repository: GithubRepoName",
+    branch,
+    commit_sha,
+    parent,
+    status as "status: BuildStatus",
+    created_at as "created_at: DateTime<Utc>",
+    completed_at as "completed_at: DateTime<Utc>",
+    branches_deleted,
+    force,
+    pull_request_id,
+    check_run_id,
+    head_sha,
+    jobs
+FROM build
+WHERE repository = $1
+    AND status = $2
+ORDER BY created_at, id
+LIMIT $3
+OFFSET $4
+"#,
+            repo as &GithubRepoName,
+            BuildStatus::Pending as BuildStatus,
+            page.sql_limit(),
+            page.sql_offset()
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(builds)
+    })
+    .await
+}
+
+/// Returns the number of pending builds on the given `branch` of the repository.
+pub(crate) async fn count_pending_builds(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    branch: &str,
+) -> DbResult<u64> {
+    measure_db_query("count_pending_builds", || async {
+        let count = sqlx::query_scalar!(
+            r#"
+SELECT COUNT(*) as "count!"
+FROM build
+WHERE repository = $1
+    AND branch = $2
+    AND status = $3
+"#,
+            repo as &GithubRepoName,
+            branch,
+            BuildStatus::Pending as BuildStatus
+        )
+        .fetch_one(executor)
+        .await?;
+        Ok(count as u64)
+    })
+    .await
+}
+
+/// Returns the most recent build of each branch of the given repository.
+pub(crate) async fn get_latest_build_per_branch(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<Vec<BuildModel>> {
+    measure_db_query("get_latest_build_per_branch", || async {
+        let builds = sqlx::query_as!(
+            BuildModel,
+            r#"
+SELECT DISTINCT ON (branch)
+    id,
+    repository as "repository: GithubRepoName",
+    branch,
+    commit_sha,
+    parent,
+    status as "status: BuildStatus",
+    created_at as "created_at: DateTime<Utc>",
+    completed_at as "completed_at: DateTime<Utc>",
+    branches_deleted,
+    force,
+    pull_request_id,
+    check_run_id,
+    head_sha,
+    jobs
+FROM build
+WHERE repository = $1
+ORDER BY branch, created_at DESC, id DESC
+"#,
+            repo as &GithubRepoName
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(builds)
+    })
+    .await
+}
+
+pub(crate) async fn mark_build_branches_deleted(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+) -> DbResult<()> {
+    measure_db_query("mark_build_branches_deleted", || async {
+        sqlx::query!(
+            "UPDATE build SET branches_deleted = TRUE WHERE id = $1",
+            build_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn update_build_status(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+    status: BuildStatus,
+) -> DbResult<()> {
+    measure_db_query("update_build_status", || async {
+        sqlx::query!(
+            r#"
+UPDATE build
+SET status = $1,
+    completed_at = CASE WHEN $1 = 'pending' THEN NULL ELSE NOW() END
+WHERE id = $2
+"#,
+            status as _,
+            build_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn set_build_force(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+    force: bool,
+) -> DbResult<()> {
+    measure_db_query("set_build_force", || async {
+        sqlx::query!("UPDATE build SET force = $1 WHERE id = $2", force, build_id)
+            .execute(executor)
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Marks the build as forced if the approval of the pull request was forced.
+pub(crate) async fn set_build_force_from_approval(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+    pr_id: i32,
+) -> DbResult<()> {
+    measure_db_query("set_build_force_from_approval", || async {
+        sqlx::query!(
+            r#"
+UPDATE build
+SET force = pull_request.approved_force
+FROM pull_request
+WHERE build.id = $1 AND pull_request.id = $2
+"#,
+            build_id,
+            pr_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn set_pr_approval_force(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    force: bool,
+) -> DbResult<()> {
+    measure_db_query("set_pr_approval_force", || async {
+        sqlx::query!(
+            "UPDATE pull_request SET approved_force = $1 WHERE id = $2",
+            force,
+            pr_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn set_build_check_run_id(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+    check_run_id: i64,
+) -> DbResult<()> {
+    measure_db_query("set_build_check_run_id", || async {
+        sqlx::query!(
+            "UPDATE build SET check_run_id = $1 WHERE id = $2",
+            check_run_id,
+            build_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn set_build_jobs(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+    jobs: &[String],
+) -> DbResult<()> {
+    measure_db_query("set_build_jobs", || async {
+        sqlx::query!("UPDATE build SET jobs = $1 WHERE id = $2", jobs, build_id)
+            .execute(executor)
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn set_build_head_sha(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+    head_sha: &CommitSha,
+) -> DbResult<()> {
+    measure_db_query("set_build_head_sha", || async {
+        sqlx::query!(
+            "UPDATE build SET head_sha = $1 WHERE id = $2",
+            head_sha.0,
+            build_id
+        )
+        .execute(executor)
         .await?;
         Ok(())
     })
@@ -442,12 +1246,12 @@ pub(crate) async fn create_workflow(
     run_id: RunId,
     workflow_type: WorkflowType,
     status: WorkflowStatus,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("create_workflow", || async {
         sqlx::query!(
             r#"
-INSERT INTO workflow (build_id, name, url, run_id, type, status)
-VALUES ($1, $2, $3, $4, $5, $6)
+INSERT INTO workflow (build_id, name, url, run_id, type, status, completed_at)
+VALUES ($1, $2, $3, $4, $5, $6, CASE WHEN $6 = 'pending' THEN NULL ELSE NOW() END)
 "#,
             build_id,
             name,
@@ -467,10 +1271,15 @@ pub(crate) async fn update_workflow_status(
     executor: impl PgExecutor<'_>,
     run_id: u64,
     status: WorkflowStatus,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("update_workflow_status", || async {
         sqlx::query!(
-            "UPDATE workflow SET status = $1 WHERE run_id = $2",
+            r#"
+UPDATE workflow
+SET status = $1,
+    completed_at = CASE WHEN $1 = 'pending' THEN NULL ELSE NOW() END
+WHERE run_id = $2
+"#,
             status as _,
             run_id as i64
         )
@@ -481,11 +1290,101 @@ pub(crate) async fn update_workflow_status(
     .await
 }
 
+/// Updates the status of the given attempt of a workflow run.
+/// Attempts older than the latest known attempt of the workflow are ignored.
+/// Returns `false` if the workflow was not updated.
+pub(crate) async fn update_workflow_attempt_status(
+    executor: impl PgExecutor<'_>,
+    run_id: u64,
+    run_attempt: u32,
+    status: WorkflowStatus,
+) -> DbResult<bool> {
+    measure_db_query("update_workflow_attempt_status", || async {
+        let result = sqlx::query!(
+            r#"
+UPDATE workflow
+SET status = $1,
+    run_attempt = $3,
+    completed_at = CASE WHEN $1 = 'pending' THEN NULL ELSE NOW() END
+WHERE run_id = $2 AND run_attempt <= $3
+"#,
+            status as _,
+            run_id as i64,
+            run_attempt as i32
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    })
+    .await
+}
+
+/// Updates the statuses of several workflow runs in a single statement.
+/// Each update is a `(run_id, run_attempt, status)` triple. The updates are applied as if they
+/// were performed one after another by [update_workflow_attempt_status], so attempts older than
+/// the latest known attempt of a workflow are ignored.
+/// Returns the number of updated workflows.
+pub(crate) async fn update_workflow_attempt_statuses(
+    executor: impl PgExecutor<'_>,
+    updates: &[(u64, u32, WorkflowStatus)],
+) -> DbResult<u64> {
+    // A single statement can only update each row once, so only the update of each workflow run
+    // that would be applied last is kept.
+    let mut latest: Vec<(u64, u32, WorkflowStatus)> = vec![];
+    for &update in updates {
+        match latest.iter_mut().find(|(run_id, _, _)| *run_id == update.0) {
+            Some(previous) if previous.1 <= update.1 => *previous = update,
+            Some(_) => {}
+            None => latest.push(update),
+        }
+    }
+    let run_ids: Vec<i64> = latest.iter().map(|(run_id, _, _)| *run_id as i64).collect();
+    let run_attempts: Vec<i32> = latest
+        .iter()
+        .map(|(_, attempt, _)| *attempt as i32)
+        .collect();
+    let statuses: Vec<String> = latest
+        .iter()
+        .map(|(_, _, status)| workflow_status_name(*status).to_string())
+        .collect();
+
+    measure_db_query("update_workflow_attempt_statuses", || async {
+        let result = sqlx::query!(
+            r#"
+UPDATE workflow
+SET status = updates.status,
+    run_attempt = updates.run_attempt,
+    completed_at = CASE WHEN updates.status = 'pending' THEN NULL ELSE NOW() END
+FROM UNNEST($1::BIGINT[], $2::INT[], $3::TEXT[]) AS updates(run_id, run_attempt, status)
+WHERE workflow.run_id = updates.run_id AND workflow.run_attempt <= updates.run_attempt
+"#,
+            &run_ids,
+            &run_attempts,
+            &statuses
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
+    })
+    .await
+}
+
+/// Name of the status as it is stored in the database.
+fn workflow_status_name(status: WorkflowStatus) -> &'static str {
+    match status {
+        WorkflowStatus::Pending => "pending",
+        WorkflowStatus::Success => "success",
+        WorkflowStatus::Failure => "failure",
+        WorkflowStatus::Skipped => "skipped",
+        WorkflowStatus::ActionRequired => "action_required",
+    }
+}
+
 pub(crate) async fn set_pr_priority(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
     priority: u32,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     measure_db_query("set_pr_priority", || async {
         sqlx::query!(
             "UPDATE pull_request SET priority = $1 WHERE id = $2",
@@ -502,12 +1401,30 @@ pub(crate) async fn set_pr_priority(
 pub(crate) async fn set_pr_rollup(
     executor: impl PgExecutor<'_>,
     pr_id: i32,
-    rollup: RollupMode,
-) -> anyhow::Result<()> {
+    rollup: Option<RollupMode>,
+) -> DbResult<()> {
     measure_db_query("set_pr_rollup", || async {
         sqlx::query!(
             "UPDATE pull_request SET rollup = $1 WHERE id = $2",
-            rollup as RollupMode,
+            rollup as Option<RollupMode>,
+            pr_id,
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn set_pr_squash(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    squash: bool,
+) -> DbResult<()> {
+    measure_db_query("set_pr_squash", || async {
+        sqlx::query!(
+            "UPDATE pull_request SET squash = $1 WHERE id = $2",
+            squash,
             pr_id,
         )
         .execute(executor)
@@ -517,10 +1434,45 @@ pub(crate) async fn set_pr_rollup(
     .await
 }
 
+/// Returns the workflows of a build, ordered by the time when they were started.
+/// Counts the workflows of a build in each status, without loading the workflows themselves.
+pub(crate) async fn get_workflow_status_counts(
+    executor: impl PgExecutor<'_>,
+    build_id: i32,
+) -> DbResult<WorkflowStatusCounts> {
+    measure_db_query("get_workflow_status_counts", || async {
+        let rows = sqlx::query!(
+            r#"
+SELECT status as "status: WorkflowStatus", COUNT(*) as "count!"
+FROM workflow
+WHERE build_id = $1
+GROUP BY status
+"#,
+            build_id
+        )
+        .fetch_all(executor)
+        .await?;
+
+        let mut counts = WorkflowStatusCounts::default();
+        for row in rows {
+            let count = row.count as usize;
+            match row.status {
+                WorkflowStatus::Pending => counts.pending += count,
+                WorkflowStatus::Success => counts.success += count,
+                WorkflowStatus::Failure => counts.failure += count,
+                WorkflowStatus::Skipped => counts.skipped += count,
+                WorkflowStatus::ActionRequired => counts.action_required += count,
+            }
+        }
+        Ok(counts)
+    })
+    .await
+}
+
 pub(crate) async fn get_workflows_for_build(
     executor: impl PgExecutor<'_>,
     build_id: i32,
-) -> anyhow::Result<Vec<WorkflowModel>> {
+) -> DbResult<Vec<WorkflowModel>> {
     measure_db_query("get_workflows_for_build", || async {
         let workflows = sqlx::query_as!(
             WorkflowModel,
@@ -530,9 +1482,11 @@ SELECT
     workflow.name,
     workflow.url,
     workflow.run_id,
+    workflow.run_attempt,
     workflow.type as "workflow_type: WorkflowType",
     workflow.status as "status: WorkflowStatus",
     workflow.created_at as "created_at: DateTime<Utc>",
+    workflow.completed_at as "completed_at: DateTime<Utc>",
     (
         build.id,
         build.repository,
@@ -540,11 +1494,16 @@ SELECT
         build.commit_sha,
         build.status,
         build.parent,
-        build.created_at
+        build.created_at,
+        build.force,
+        build.check_run_id,
+        build.head_sha,
+        build.jobs
     ) AS "build!: BuildModel"
 FROM workflow
     LEFT JOIN build ON workflow.build_id = build.id
 WHERE build.id = $1
+ORDER BY workflow.created_at, workflow.id
 "#,
             build_id
         )
@@ -558,7 +1517,7 @@ WHERE build.id = $1
 pub(crate) async fn get_workflow_urls_for_build(
     executor: impl PgExecutor<'_>,
     build_id: i32,
-) -> anyhow::Result<Vec<String>> {
+) -> DbResult<Vec<String>> {
     measure_db_query("get_workflow_urls_for_build", || async {
         let results = sqlx::query!(
             r#"
@@ -576,10 +1535,62 @@ WHERE build_id = $1
     .await
 }
 
+/// Returns at most `limit` workflows that are still pending and were created before `created_before`,
+/// ordered from the oldest one.
+pub(crate) async fn get_pending_workflows_older_than(
+    executor: impl PgExecutor<'_>,
+    created_before: DateTime<Utc>,
+    limit: i64,
+) -> DbResult<Vec<WorkflowModel>> {
+    measure_db_query("get_pending_workflows_older_than", || async {
+        let workflows = sqlx::query_as!(
+            WorkflowModel,
+            r#"
+SELECT
+    workflow.id,
+    workflow.name,
+    workflow.url,
+    workflow.run_id,
+    workflow.run_attempt,
+    workflow.type as "workflow_type: WorkflowType",
+    workflow.status as "status: WorkflowStatus",
+    workflow.created_at as "created_at: DateTime<Utc>",
+    workflow.completed_at as "completed_at: DateTime<Utc>",
+    (
+        build.id,
+        build.repository,
+        build.branch,
+        build.commit_sha,
+        build.status,
+        build.parent,
+        build.created_at,
+        build.force,
+        build.check_run_id,
+        build.head_sha,
+        build.jobs
+    ) AS "build!: BuildModel"
+FROM workflow
+    LEFT JOIN build ON workflow.build_id = build.id
+WHERE workflow.status = $1
+    AND workflow.created_at < $2
+ORDER BY workflow.created_at ASC
+LIMIT $3
+"#,
+            WorkflowStatus::Pending as WorkflowStatus,
+            created_before,
+            limit
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(workflows)
+    })
+    .await
+}
+
 #[cfg(test)]
 pub(crate) async fn get_all_workflows(
     executor: impl PgExecutor<'_>,
-) -> anyhow::Result<Vec<WorkflowModel>> {
+) -> DbResult<Vec<WorkflowModel>> {
     measure_db_query("get_all_workflows", || async {
         let workflows = sqlx::query_as!(
             WorkflowModel,
@@ -589,9 +1600,11 @@ SELECT
     workflow.name,
     workflow.url,
     workflow.run_id,
+    workflow.run_attempt,
     workflow.type as "workflow_type: WorkflowType",
     workflow.status as "status: WorkflowStatus",
     workflow.created_at as "created_at: DateTime<Utc>",
+    workflow.completed_at as "completed_at: DateTime<Utc>",
     (
         build.id,
         build.repository,
@@ -599,7 +1612,11 @@ SELECT
         build.commit_sha,
         build.status,
         build.parent,
-        build.created_at
+        build.created_at,
+        build.force,
+        build.check_run_id,
+        build.head_sha,
+        build.jobs
     ) AS "build!: BuildModel"
 FROM workflow
     LEFT JOIN build ON workflow.build_id = build.id
@@ -616,7 +1633,7 @@ FROM workflow
 pub(crate) async fn get_repository(
     executor: impl PgExecutor<'_>,
     repo: &GithubRepoName,
-) -> anyhow::Result<Option<RepoModel>> {
+) -> DbResult<Option<RepoModel>> {
     measure_db_query("get_repository", || async {
         let repo = sqlx::query_as!(
             RepoModel,
@@ -628,6 +1645,7 @@ pub(crate) async fn get_repository(
                 tree_state,
                 treeclosed_src
             ) AS "tree_state!: TreeState",
+            paused,
             created_at
         FROM repository
         WHERE name = $1
@@ -642,12 +1660,133 @@ pub(crate) async fn get_repository(
     .await
 }
 
+/// Stores a repository managed by the bot, if it was not stored yet.
+pub(crate) async fn create_repository(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<()> {
+    measure_db_query("create_repository", || async {
+        sqlx::query!(
+            r#"
+        INSERT INTO repository (name)
+        VALUES ($1)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+            repo as &GithubRepoName
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Moves the pull requests of the `old` repository to the `new` repository.
+/// Returns the number of moved pull requests.
+pub(crate) async fn rename_repository_pull_requests(
+    executor: impl PgExecutor<'_>,
+    old: &GithubRepoName,
+    new: &GithubRepoName,
+) -> DbResult<u64> {
+    measure_db_query("rename_repository_pull_requests", || async {
+        let result = sqlx::query!(
+            "UPDATE pull_request SET repository = $2 WHERE repository = $1",
+            old as &GithubRepoName,
+            new as &GithubRepoName
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
+    })
+    .await
+}
+
+/// Moves the builds of the `old` repository to the `new` repository.
+/// Returns the number of moved builds.
+pub(crate) async fn rename_repository_builds(
+    executor: impl PgExecutor<'_>,
+    old: &GithubRepoName,
+    new: &GithubRepoName,
+) -> DbResult<u64> {
+    measure_db_query("rename_repository_builds", || async {
+        let result = sqlx::query!(
+            "UPDATE build SET repository = $2 WHERE repository = $1",
+            old as &GithubRepoName,
+            new as &GithubRepoName
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
+    })
+    .await
+}
+
+/// Renames the stored `old` repository to `new`, keeping its tree state and configuration.
+/// Nothing is renamed if the `new` repository has already been stored.
+pub(crate) async fn rename_repository(
+    executor: impl PgExecutor<'_>,
+    old: &GithubRepoName,
+    new: &GithubRepoName,
+) -> DbResult<()> {
+    measure_db_query("rename_repository", || async {
+        sqlx::query!(
+            r#"
+        UPDATE repository SET name = $2
+        WHERE name = $1 AND NOT EXISTS (SELECT 1 FROM repository WHERE name = $2)
+        "#,
+            old as &GithubRepoName,
+            new as &GithubRepoName
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+pub(crate) async fn delete_repository(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<()> {
+    measure_db_query("delete_repository", || async {
+        sqlx::query!(
+            "DELETE FROM repository WHERE name = $1",
+            repo as &GithubRepoName
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the names of all stored repositories, ordered by name.
+pub(crate) async fn get_repositories(
+    executor: impl PgExecutor<'_>,
+) -> DbResult<Vec<GithubRepoName>> {
+    measure_db_query("get_repositories", || async {
+        let repos = sqlx::query_scalar!(
+            r#"
+        SELECT name as "name: GithubRepoName"
+        FROM repository
+        ORDER BY name
+        "#
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(repos)
+    })
+    .await
+}
+
 /// Updates the tree state of a repository.
 pub(crate) async fn upsert_repository(
     executor: impl PgExecutor<'_>,
     repo: &GithubRepoName,
     tree_state: TreeState,
-) -> anyhow::Result<()> {
+) -> DbResult<()> {
     let (priority, src) = match tree_state {
         TreeState::Open => (None, None),
         TreeState::Closed { priority, source } => (Some(priority as i32), Some(source)),
@@ -671,3 +1810,563 @@ pub(crate) async fn upsert_repository(
     })
     .await
 }
+
+/// Pauses or resumes bors on a repository.
+pub(crate) async fn set_repository_paused(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    paused: bool,
+) -> DbResult<()> {
+    measure_db_query("set_repository_paused", || async {
+        sqlx::query!(
+            r#"
+        INSERT INTO repository (name, paused)
+        VALUES ($1, $2)
+        ON CONFLICT (name)
+        DO UPDATE SET paused = EXCLUDED.paused
+        "#,
+            repo as &GithubRepoName,
+            paused
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Returns true if bors is paused on the repository.
+/// Repositories that are not stored are not paused.
+pub(crate) async fn is_repository_paused(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<bool> {
+    measure_db_query("is_repository_paused", || async {
+        let paused = sqlx::query_scalar!(
+            "SELECT paused FROM repository WHERE name = $1",
+            repo as &GithubRepoName
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(paused.unwrap_or(false))
+    })
+    .await
+}
+
+/// Stores the contents of the configuration file of a repository, along with its hash.
+pub(crate) async fn set_repository_config(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    config: &str,
+    config_sha: &str,
+) -> DbResult<()> {
+    measure_db_query("set_repository_config", || async {
+        sqlx::query!(
+            r#"
+        INSERT INTO repository (name, config, config_sha)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name)
+        DO UPDATE SET config = EXCLUDED.config, config_sha = EXCLUDED.config_sha
+        "#,
+            repo as &GithubRepoName,
+            config,
+            config_sha
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the last stored contents of the configuration file of a repository, if any.
+pub(crate) async fn get_repository_config(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+) -> DbResult<Option<String>> {
+    measure_db_query("get_repository_config", || async {
+        let config = sqlx::query_scalar!(
+            r#"
+        SELECT config
+        FROM repository
+        WHERE name = $1
+        "#,
+            repo as &GithubRepoName
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(config.flatten())
+    })
+    .await
+}
+
+/// Adds a try build request of the given PR to the try queue.
+/// If the PR is already queued, its request is updated, but it keeps its position in the queue.
+pub(crate) async fn enqueue_try_build(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    parent: Option<&str>,
+    jobs: &[String],
+) -> DbResult<()> {
+    measure_db_query("enqueue_try_build", || async {
+        sqlx::query!(
+            r#"
+INSERT INTO try_queue (pull_request_id, parent, jobs)
+VALUES ($1, $2, $3)
+ON CONFLICT (pull_request_id)
+DO UPDATE SET parent = EXCLUDED.parent, jobs = EXCLUDED.jobs
+"#,
+            pr_id,
+            parent,
+            jobs
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the queued try build requests of the given repository, in the order in which they
+/// should be started.
+pub(crate) async fn get_try_queue(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    page: Pagination,
+) -> DbResult<Vec<QueuedTryBuildModel>> {
+    measure_db_query("get_try_queue", || async {
+        let queue = sqlx::query_as!(
+            QueuedTryBuildModel,
+            r#"
+SELECT
+    queue.id,
+    queue.pull_request_id,
+    pr.number as "pr_number!: i64",
+    queue.parent,
+    queue.jobs,
+    queue.created_at as "created_at: DateTime<Utc>"
+FROM try_queue as queue
+JOIN pull_request as pr ON pr.id = queue.pull_request_id
+WHERE pr.repository = $1
+ORDER BY queue.created_at, queue.id
+LIMIT $2
+OFFSET $3
+"#,
+            repo as &GithubRepoName,
+            page.sql_limit(),
+            page.sql_offset()
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(queue)
+    })
+    .await
+}
+
+/// Returns the number of builds started on `branch` since `since` for each PR author of the
+/// given repository.
+/// Builds of PRs without a known author are counted under an empty name.
+pub(crate) async fn count_recent_builds_by_author(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    branch: &str,
+    since: DateTime<Utc>,
+) -> DbResult<HashMap<String, u64>> {
+    measure_db_query("count_recent_builds_by_author", || async {
+        let rows = sqlx::query!(
+            r#"
+SELECT COALESCE(pr.author, '') as "author!", COUNT(*) as "count!"
+FROM build
+JOIN pull_request as pr ON build.pull_request_id = pr.id
+WHERE build.repository = $1
+    AND build.branch = $2
+    AND build.created_at >= $3
+GROUP BY COALESCE(pr.author, '')
+"#,
+            repo as &GithubRepoName,
+            branch,
+            since
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.author, row.count as u64))
+            .collect())
+    })
+    .await
+}
+
+/// Returns the status comment of the given PR, if it has one.
+pub(crate) async fn get_status_comment(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+) -> DbResult<Option<StatusCommentModel>> {
+    measure_db_query("get_status_comment", || async {
+        let comment = sqlx::query!(
+            "SELECT comment_id, history FROM status_comment WHERE pull_request_id = $1",
+            pr_id
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|row| StatusCommentModel {
+            comment_id: row.comment_id as u64,
+            history: row.history,
+        });
+        Ok(comment)
+    })
+    .await
+}
+
+/// Stores the status comment of the given PR, replacing the previous one.
+pub(crate) async fn upsert_status_comment(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    comment: &StatusCommentModel,
+) -> DbResult<()> {
+    measure_db_query("upsert_status_comment", || async {
+        sqlx::query!(
+            r#"
+INSERT INTO status_comment (pull_request_id, comment_id, history)
+VALUES ($1, $2, $3)
+ON CONFLICT (pull_request_id)
+DO UPDATE SET comment_id = EXCLUDED.comment_id, history = EXCLUDED.history, updated_at = NOW()
+"#,
+            pr_id,
+            comment.comment_id as i64,
+            &comment.history
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the node ID of the latest comment of the given kind posted to the given PR.
+pub(crate) async fn get_outdatable_comment(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    kind: CommentKind,
+) -> DbResult<Option<String>> {
+    measure_db_query("get_outdatable_comment", || async {
+        let node_id = sqlx::query_scalar!(
+            "SELECT node_id FROM outdatable_comment WHERE pull_request_id = $1 AND kind = $2",
+            pr_id,
+            kind as CommentKind
+        )
+        .fetch_optional(executor)
+        .await?;
+        Ok(node_id)
+    })
+    .await
+}
+
+/// Stores the node ID of the latest comment of the given kind posted to the given PR,
+/// replacing the previous one.
+pub(crate) async fn upsert_outdatable_comment(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+    kind: CommentKind,
+    node_id: &str,
+) -> DbResult<()> {
+    measure_db_query("upsert_outdatable_comment", || async {
+        sqlx::query!(
+            r#"
+INSERT INTO outdatable_comment (pull_request_id, kind, node_id)
+VALUES ($1, $2, $3)
+ON CONFLICT (pull_request_id, kind)
+DO UPDATE SET node_id = EXCLUDED.node_id, created_at = NOW()
+"#,
+            pr_id,
+            kind as CommentKind,
+            node_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Removes the queued try build request of the given PR.
+/// Returns `true` if there was such a request.
+pub(crate) async fn remove_queued_try_build(
+    executor: impl PgExecutor<'_>,
+    pr_id: i32,
+) -> DbResult<bool> {
+    measure_db_query("remove_queued_try_build", || async {
+        let result = sqlx::query!("DELETE FROM try_queue WHERE pull_request_id = $1", pr_id)
+            .execute(executor)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    })
+    .await
+}
+
+/// Returns the hash of the body of the given comment at the time when its commands were last
+/// executed, or `None` if they were never executed.
+/// Returns how many commands of the comment were executed while it had a body with the given
+/// hash.
+pub(crate) async fn get_executed_command_count(
+    executor: impl PgExecutor<'_>,
+    comment_id: u64,
+    body_hash: &str,
+) -> DbResult<usize> {
+    measure_db_query("get_executed_command_count", || async {
+        let record = sqlx::query!(
+            "SELECT executed_commands FROM processed_comment WHERE comment_id = $1 AND body_hash = $2",
+            comment_id as i64,
+            body_hash
+        )
+        .fetch_optional(executor)
+        .await?;
+        Ok(record.map_or(0, |record| record.executed_commands as usize))
+    })
+    .await
+}
+
+/// Records that the first `executed_commands` commands of the given comment were executed when
+/// it had a body with the given hash.
+pub(crate) async fn record_processed_comment(
+    executor: impl PgExecutor<'_>,
+    comment_id: u64,
+    body_hash: &str,
+    executed_commands: usize,
+) -> DbResult<()> {
+    measure_db_query("record_processed_comment", || async {
+        sqlx::query!(
+            r#"
+INSERT INTO processed_comment (comment_id, body_hash, executed_commands)
+VALUES ($1, $2, $3)
+ON CONFLICT (comment_id)
+DO UPDATE SET body_hash = EXCLUDED.body_hash,
+              executed_commands = EXCLUDED.executed_commands,
+              processed_at = NOW()
+"#,
+            comment_id as i64,
+            body_hash,
+            executed_commands as i32
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Stores a repository webhook event in the event queue, in the pending state.
+/// Returns the ID of the stored event, or `None` if an event with the same delivery ID was
+/// already stored.
+pub(crate) async fn insert_queued_event(
+    executor: impl PgExecutor<'_>,
+    repo: &GithubRepoName,
+    delivery_id: &str,
+    event_type: &str,
+    payload: &[u8],
+) -> DbResult<Option<i32>> {
+    measure_db_query("insert_queued_event", || async {
+        let record = sqlx::query!(
+            r#"
+INSERT INTO event_queue (repository, delivery_id, event_type, payload)
+VALUES ($1, $2, $3, $4)
+ON CONFLICT (delivery_id) DO NOTHING
+RETURNING id
+"#,
+            repo as &GithubRepoName,
+            delivery_id,
+            event_type,
+            payload
+        )
+        .fetch_optional(executor)
+        .await?;
+        Ok(record.map(|record| record.id))
+    })
+    .await
+}
+
+pub(crate) async fn get_queued_event(
+    executor: impl PgExecutor<'_>,
+    id: i32,
+) -> DbResult<Option<QueuedEventModel>> {
+    measure_db_query("get_queued_event", || async {
+        let event = sqlx::query_as!(
+            QueuedEventModel,
+            r#"
+SELECT
+    id,
+    repository as "repository: GithubRepoName",
+    delivery_id,
+    event_type,
+    payload,
+    status as "status: QueuedEventStatus",
+    attempts,
+    error,
+    created_at as "created_at: DateTime<Utc>"
+FROM event_queue
+WHERE id = $1
+"#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?;
+        Ok(event)
+    })
+    .await
+}
+
+/// Returns the stored events with the given status, in the order in which they were received.
+pub(crate) async fn get_queued_events(
+    executor: impl PgExecutor<'_>,
+    status: QueuedEventStatus,
+) -> DbResult<Vec<QueuedEventModel>> {
+    measure_db_query("get_queued_events", || async {
+        let events = sqlx::query_as!(
+            QueuedEventModel,
+            r#"
+SELECT
+    id,
+    repository as "repository: GithubRepoName",
+    delivery_id,
+    event_type,
+    payload,
+    status as "status: QueuedEventStatus",
+    attempts,
+    error,
+    created_at as "created_at: DateTime<Utc>"
+FROM event_queue
+WHERE status = $1
+ORDER BY id
+"#,
+            status as QueuedEventStatus
+        )
+        .fetch_all(executor)
+        .await?;
+        Ok(events)
+    })
+    .await
+}
+
+/// Records that the handling of the given stored events has started.
+pub(crate) async fn start_queued_events_attempt(
+    executor: impl PgExecutor<'_>,
+    ids: &[i32],
+) -> DbResult<()> {
+    measure_db_query("start_queued_events_attempt", || async {
+        sqlx::query!(
+            "UPDATE event_queue SET attempts = attempts + 1 WHERE id = ANY($1)",
+            ids
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Marks the given stored events as processed or dead. The error is only stored for dead events.
+pub(crate) async fn finish_queued_events(
+    executor: impl PgExecutor<'_>,
+    ids: &[i32],
+    status: QueuedEventStatus,
+    error: Option<&str>,
+) -> DbResult<()> {
+    measure_db_query("finish_queued_events", || async {
+        sqlx::query!(
+            r#"
+UPDATE event_queue
+SET status = $2, error = $3, processed_at = NOW()
+WHERE id = ANY($1)
+"#,
+            ids,
+            status as QueuedEventStatus,
+            error
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Moves a dead event back to the pending state, so that it can be handled again.
+/// Returns `false` if there is no dead event with the given ID.
+pub(crate) async fn requeue_dead_event(executor: impl PgExecutor<'_>, id: i32) -> DbResult<bool> {
+    measure_db_query("requeue_dead_event", || async {
+        let result = sqlx::query!(
+            r#"
+UPDATE event_queue
+SET status = 'pending', attempts = 0, error = NULL, processed_at = NULL
+WHERE id = $1 AND status = 'dead'
+"#,
+            id
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    })
+    .await
+}
+
+/// Deletes the processed events that were handled before the given time.
+/// Returns the number of deleted events.
+pub(crate) async fn delete_processed_events(
+    executor: impl PgExecutor<'_>,
+    processed_before: DateTime<Utc>,
+) -> DbResult<u64> {
+    measure_db_query("delete_processed_events", || async {
+        let result = sqlx::query!(
+            "DELETE FROM event_queue WHERE status = 'processed' AND processed_at < $1",
+            processed_before
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use crate::database::Pagination;
+    use crate::database::operations::{create_build, get_running_builds};
+    use crate::github::{CommitSha, GithubRepoName};
+
+    #[sqlx::test]
+    async fn get_running_builds_paginated(pool: PgPool) {
+        let repo = GithubRepoName::new("foo", "bar");
+        for index in 0..5 {
+            let sha = CommitSha(format!("sha-{index}"));
+            create_build(&pool, &repo, "branch", &sha, &sha, None)
+                .await
+                .unwrap();
+        }
+
+        let all = get_running_builds(&pool, &repo, Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 5);
+
+        let page = get_running_builds(
+            &pool,
+            &repo,
+            Pagination {
+                limit: Some(2),
+                offset: 1,
+            },
+        )
+        .await
+        .unwrap();
+        let shas = page
+            .iter()
+            .map(|build| build.commit_sha.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(shas, vec!["sha-1", "sha-2"]);
+    }
+}
@@ -1,17 +1,142 @@
 use anyhow::Context;
-use octocrab::models::{App, Repository};
-use octocrab::{Error, Octocrab};
+use http::StatusCode;
+use octocrab::models::pulls::MergeableState;
+use octocrab::models::reactions::ReactionContent;
+use octocrab::models::{App, CommentId, Repository, UserId};
+use octocrab::params::repos::Reference;
+use octocrab::{Error, Octocrab, Page};
+use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tracing::log;
 
 use crate::bors::event::PullRequestComment;
 use crate::bors::{CheckSuite, CheckSuiteStatus, Comment};
 use crate::config::{CONFIG_FILE_PATH, RepositoryConfig};
 use crate::database::RunId;
-use crate::github::api::base_github_html_url;
-use crate::github::api::operations::{MergeError, merge_branches, set_branch_to_commit};
-use crate::github::{CommitSha, GithubRepoName, PullRequest, PullRequestNumber};
+use crate::github::api::operations::{
+    BranchUpdateError, MergeError, fast_forward_branch, merge_branches, set_branch_to_commit,
+};
+use crate::github::api::retry::{RequestKind, retry_request, retry_response};
+use crate::github::{
+    CheckRunConclusion, CommitSha, CommitStatusState, GithubRepoName, PullRequest,
+    PullRequestNumber,
+};
+use crate::permissions::TeamReference;
 use crate::utils::timing::measure_network_request;
 
+const MINIMIZE_COMMENT_MUTATION: &str = r#"
+mutation($node_id: ID!) {
+  minimizeComment(input: { subjectId: $node_id, classifier: OUTDATED }) {
+    minimizedComment {
+      isMinimized
+    }
+  }
+}
+"#;
+
+/// Number of comments that could not be posted since the start of the process.
+/// Comments rejected because the PR is locked are not counted.
+static FAILED_COMMENTS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of comments that could not be posted since the start of the process.
+pub fn failed_comment_count() -> u64 {
+    FAILED_COMMENTS.load(Ordering::Relaxed)
+}
+
+/// A job of a Github Actions workflow run.
+#[derive(serde::Deserialize, Debug)]
+pub struct WorkflowJob {
+    pub id: u64,
+    pub name: String,
+    pub conclusion: Option<String>,
+}
+
+/// Author of a commit in the git database of the repository.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct GitAuthor {
+    pub name: String,
+    pub email: String,
+    /// Time of the authorship in the ISO 8601 format.
+    /// If it is missing, GitHub uses the time when the commit is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+/// A commit in the git database of the repository.
+#[derive(Debug, Clone)]
+pub struct GitCommit {
+    pub sha: CommitSha,
+    /// SHA of the tree of the commit.
+    pub tree: String,
+    pub parents: Vec<CommitSha>,
+    pub message: String,
+    pub author: GitAuthor,
+}
+
+#[derive(serde::Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+/// Commit object returned by the git database API.
+#[derive(serde::Deserialize)]
+struct GitCommitObject {
+    sha: String,
+    tree: GitObject,
+    parents: Vec<GitObject>,
+    message: String,
+    author: GitAuthor,
+}
+
+impl From<GitCommitObject> for GitCommit {
+    fn from(commit: GitCommitObject) -> Self {
+        Self {
+            sha: CommitSha(commit.sha),
+            tree: commit.tree.sha,
+            parents: commit
+                .parents
+                .into_iter()
+                .map(|parent| CommitSha(parent.sha))
+                .collect(),
+            message: commit.message,
+            author: commit.author,
+        }
+    }
+}
+
+/// An open pull request along with its labels.
+#[derive(Debug)]
+pub struct OpenPullRequest {
+    pub pr: PullRequest,
+    pub labels: Vec<String>,
+}
+
+/// Relation of a commit to a branch of the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitReachability {
+    /// The commit is a part of the history of the branch.
+    /// Contains the full SHA of the commit, which might have been abbreviated in the request.
+    Reachable(CommitSha),
+    /// The commit exists, but it is not a part of the history of the branch.
+    Unreachable,
+    /// The commit does not exist in the repository.
+    NotFound,
+}
+
+/// Maximum number of times a PR is fetched while GitHub is still computing its mergeable state.
+const MERGEABLE_STATE_ATTEMPTS: u32 = 3;
+/// Delay between fetches of a PR whose mergeable state is still being computed.
+const MERGEABLE_STATE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// A comment that was posted by the bot.
+#[derive(Debug)]
+pub struct PostedComment {
+    pub id: CommentId,
+    /// GraphQL node ID of the comment.
+    pub node_id: String,
+}
+
 /// Provides access to a single app installation (repository) using the GitHub API.
 pub struct GithubRepositoryClient {
     app: App,
@@ -22,6 +147,11 @@ pub struct GithubRepositoryClient {
     // we must always have some owner of the repo.
     repo_name: GithubRepoName,
     repository: Repository,
+    /// Base URL of the GitHub web UI, used as a fallback when generating links.
+    github_html_url: String,
+    /// If set, operations that modify the state of the repository on GitHub are only logged
+    /// and reported as successful, without being performed.
+    dry_run: bool,
 }
 
 impl GithubRepositoryClient {
@@ -30,12 +160,16 @@ impl GithubRepositoryClient {
         client: Octocrab,
         repo_name: GithubRepoName,
         repository: Repository,
+        github_html_url: String,
+        dry_run: bool,
     ) -> Self {
         Self {
             app,
             client,
             repo_name,
             repository,
+            github_html_url,
+            dry_run,
         }
     }
 
@@ -47,28 +181,63 @@ impl GithubRepositoryClient {
         &self.repo_name
     }
 
+    /// Is the client running in dry-run mode?
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Returns `true` if the client is running in dry-run mode, in which case the described
+    /// mutation is logged and should not be performed.
+    fn skip_mutation(&self, description: impl FnOnce() -> String) -> bool {
+        if self.dry_run {
+            tracing::info!("Dry run ({}): {}", self.repo_name, description());
+        }
+        self.dry_run
+    }
+
+    /// Login of the bot user of the app (e.g. `bors[bot]`), which authors its comments.
+    /// `None` if the app does not have a slug.
+    pub fn bot_login(&self) -> Option<String> {
+        self.app.slug.as_ref().map(|slug| format!("{slug}[bot]"))
+    }
+
     /// Was the comment created by the bot?
     pub async fn is_comment_internal(&self, comment: &PullRequestComment) -> anyhow::Result<bool> {
-        Ok(comment.author.html_url == self.app.html_url)
+        Ok(comment.author.html_url == self.app.html_url
+            || self
+                .bot_login()
+                .is_some_and(|login| comment.author.username.eq_ignore_ascii_case(&login)))
     }
 
     /// Loads repository configuration from a file located at `[CONFIG_FILE_PATH]` in the main
     /// branch.
-    pub async fn load_config(&self) -> anyhow::Result<RepositoryConfig> {
+    /// Returns the parsed configuration along with the raw contents of the file.
+    pub async fn load_config(&self) -> anyhow::Result<(RepositoryConfig, String)> {
+        let content = self.load_config_content(None).await?;
+        let config = RepositoryConfig::parse(&content).map_err(|error| {
+            anyhow::anyhow!("Could not deserialize repository config: {error:?}")
+        })?;
+        Ok((config, content))
+    }
+
+    /// Loads the raw contents of the configuration file located at `[CONFIG_FILE_PATH]`.
+    /// If `git_ref` is not set, the file is loaded from the main branch.
+    pub async fn load_config_content(&self, git_ref: Option<&str>) -> anyhow::Result<String> {
         measure_network_request("load_config", || async {
-            let mut response = self
+            let mut request = self
                 .client
                 .repos(&self.repo_name.owner, &self.repo_name.name)
                 .get_content()
-                .path(CONFIG_FILE_PATH)
-                .send()
-                .await
-                .map_err(|error| {
-                    anyhow::anyhow!(
-                        "Could not fetch {CONFIG_FILE_PATH} from {}: {error:?}",
-                        self.repo_name
-                    )
-                })?;
+                .path(CONFIG_FILE_PATH);
+            if let Some(git_ref) = git_ref {
+                request = request.r#ref(git_ref);
+            }
+            let mut response = request.send().await.map_err(|error| {
+                anyhow::anyhow!(
+                    "Could not fetch {CONFIG_FILE_PATH} from {}: {error:?}",
+                    self.repo_name
+                )
+            })?;
 
             response
                 .take_items()
@@ -76,12 +245,6 @@ impl GithubRepositoryClient {
                 .next()
                 .and_then(|content| content.decoded_content())
                 .ok_or_else(|| anyhow::anyhow!("Configuration file not found"))
-                .and_then(|content| {
-                    let config: RepositoryConfig = toml::from_str(&content).map_err(|error| {
-                        anyhow::anyhow!("Could not deserialize repository config: {error:?}")
-                    })?;
-                    Ok(config)
-                })
         })
         .await
     }
@@ -90,15 +253,138 @@ impl GithubRepositoryClient {
     pub async fn get_branch_sha(&self, name: &str) -> anyhow::Result<CommitSha> {
         measure_network_request("get_branch_sha", || async {
             // https://docs.github.com/en/rest/branches/branches?apiVersion=2022-11-28#get-a-branch
-            let branch: octocrab::models::repos::Branch = self
+            let url = format!("/repos/{}/branches/{name}", self.repository());
+            let branch: octocrab::models::repos::Branch =
+                retry_request("get_branch_sha", RequestKind::Idempotent, || {
+                    self.client.get(url.as_str(), None::<&()>)
+                })
+                .await
+                .context("Cannot deserialize branch")?;
+            Ok(CommitSha(branch.commit.sha))
+        })
+        .await
+    }
+
+    /// Finds out if the commit `sha` is a part of the history of the given branch.
+    /// `sha` can be abbreviated, GitHub resolves it to a full SHA if it is unambiguous.
+    pub async fn get_commit_reachability(
+        &self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> anyhow::Result<CommitReachability> {
+        measure_network_request("get_commit_reachability", || async {
+            // https://docs.github.com/en/rest/commits/commits?apiVersion=2022-11-28#compare-two-commits
+            #[derive(serde::Deserialize)]
+            struct Commit {
+                sha: String,
+            }
+
+            #[derive(serde::Deserialize)]
+            struct Comparison {
+                status: String,
+                base_commit: Commit,
+            }
+
+            let url = format!("/repos/{}/compare/{sha}...{branch}", self.repository());
+            let result: Result<Comparison, Error> =
+                retry_request("get_commit_reachability", RequestKind::Idempotent, || {
+                    self.client.get(url.as_str(), Some(&[("per_page", "1")]))
+                })
+                .await;
+            match result {
+                // The branch contains the commit if it is ahead of it, or if it points to it
+                Ok(comparison) => Ok(match comparison.status.as_str() {
+                    "ahead" | "identical" => {
+                        CommitReachability::Reachable(CommitSha(comparison.base_commit.sha))
+                    }
+                    _ => CommitReachability::Unreachable,
+                }),
+                Err(Error::GitHub { source, .. })
+                    if source.status_code == StatusCode::NOT_FOUND =>
+                {
+                    Ok(CommitReachability::NotFound)
+                }
+                Err(error) => Err(error)
+                    .with_context(|| format!("Cannot compare commit {sha} with branch {branch}")),
+            }
+        })
+        .await
+    }
+
+    /// Returns true if the given user has admin permission in this repository.
+    pub async fn is_repository_admin(&self, username: &str) -> anyhow::Result<bool> {
+        measure_network_request("is_repository_admin", || async {
+            // https://docs.github.com/en/rest/collaborators/collaborators?apiVersion=2022-11-28#get-repository-permissions-for-a-user
+            #[derive(serde::Deserialize)]
+            struct PermissionResponse {
+                permission: String,
+            }
+
+            let url = format!(
+                "/repos/{}/collaborators/{username}/permission",
+                self.repository()
+            );
+            let response: PermissionResponse =
+                retry_request("is_repository_admin", RequestKind::Idempotent, || {
+                    self.client.get(url.as_str(), None::<&()>)
+                })
+                .await
+                .with_context(|| format!("Cannot load permission of user {username}"))?;
+            Ok(response.permission == "admin")
+        })
+        .await
+    }
+
+    /// Returns the ID of the GitHub user with the given username, or `None` if there is no such
+    /// user.
+    pub async fn get_user_id(&self, username: &str) -> anyhow::Result<Option<UserId>> {
+        measure_network_request("get_user_id", || async {
+            // https://docs.github.com/en/rest/users/users?apiVersion=2022-11-28#get-a-user
+            #[derive(serde::Deserialize)]
+            struct UserResponse {
+                id: UserId,
+            }
+
+            let result: Result<UserResponse, Error> = self
+                .client
+                .get(format!("/users/{username}").as_str(), None::<&()>)
+                .await;
+            match result {
+                Ok(user) => Ok(Some(user.id)),
+                Err(Error::GitHub { source, .. })
+                    if source.status_code == StatusCode::NOT_FOUND =>
+                {
+                    Ok(None)
+                }
+                Err(error) => Err(error).with_context(|| format!("Cannot load user {username}")),
+            }
+        })
+        .await
+    }
+
+    /// Returns the IDs of all members of the given team.
+    pub async fn get_team_members(&self, team: &TeamReference) -> anyhow::Result<Vec<UserId>> {
+        measure_network_request("get_team_members", || async {
+            // https://docs.github.com/en/rest/teams/members?apiVersion=2022-11-28#list-team-members
+            #[derive(serde::Deserialize)]
+            struct TeamMember {
+                id: UserId,
+            }
+
+            let page: Page<TeamMember> = self
                 .client
                 .get(
-                    format!("/repos/{}/branches/{name}", self.repository()).as_str(),
-                    None::<&()>,
+                    format!("/orgs/{}/teams/{}/members", team.org, team.slug).as_str(),
+                    Some(&[("per_page", "100")]),
                 )
                 .await
-                .context("Cannot deserialize branch")?;
-            Ok(CommitSha(branch.commit.sha))
+                .with_context(|| format!("Cannot load members of team {team}"))?;
+            let members = self
+                .client
+                .all_pages(page)
+                .await
+                .with_context(|| format!("Cannot load members of team {team}"))?;
+            Ok(members.into_iter().map(|member| member.id).collect())
         })
         .await
     }
@@ -106,15 +392,96 @@ impl GithubRepositoryClient {
     /// Resolve a pull request from this repository by it's number.
     pub async fn get_pull_request(&self, pr: PullRequestNumber) -> anyhow::Result<PullRequest> {
         measure_network_request("get_pull_request", || async {
-            let pr = self
-                .client
-                .pulls(self.repository().owner(), self.repository().name())
-                .get(pr.0)
+            // The error is kept as the source, so that handlers can find out if it is transient
+            let pr = retry_request("get_pull_request", RequestKind::Idempotent, || {
+                self.client
+                    .pulls(self.repository().owner(), self.repository().name())
+                    .get(pr.0)
+            })
+            .await
+            .with_context(|| format!("Could not get PR {}/{}", self.repository(), pr.0))?;
+            Ok(pr.into())
+        })
+        .await
+    }
+
+    /// Fetches a pull request and waits until GitHub has computed its mergeable state.
+    /// GitHub only computes the mergeable state lazily after it was first requested, so the PR
+    /// is fetched a few times while the state is unknown. If it is still unknown afterwards,
+    /// the PR is returned with the unknown state.
+    pub async fn get_pull_request_with_mergeable_state(
+        &self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<PullRequest> {
+        measure_network_request("get_pull_request_with_mergeable_state", || async {
+            let mut attempt = 1;
+            loop {
+                let pull_request: PullRequest =
+                    retry_request("get_pull_request", RequestKind::Idempotent, || {
+                        self.client
+                            .pulls(self.repository().owner(), self.repository().name())
+                            .get(pr.0)
+                    })
+                    .await
+                    .map_err(|error| {
+                        anyhow::anyhow!(
+                            "Could not get PR {}/{}: {error:?}",
+                            self.repository(),
+                            pr.0
+                        )
+                    })?
+                    .into();
+                if !matches!(pull_request.mergeable_state, MergeableState::Unknown)
+                    || attempt >= MERGEABLE_STATE_ATTEMPTS
+                {
+                    return Ok(pull_request);
+                }
+                tokio::time::sleep(MERGEABLE_STATE_RETRY_DELAY).await;
+                attempt += 1;
+            }
+        })
+        .await
+    }
+
+    /// Returns all open pull requests of this repository.
+    pub async fn get_open_pull_requests(&self) -> anyhow::Result<Vec<OpenPullRequest>> {
+        measure_network_request("get_open_pull_requests", || async {
+            let mut prs = vec![];
+            let mut page_number = 1u32;
+            loop {
+                let page = retry_request("get_open_pull_requests", RequestKind::Idempotent, || {
+                    self.client
+                        .pulls(self.repository().owner(), self.repository().name())
+                        .list()
+                        .state(octocrab::params::State::Open)
+                        .per_page(100)
+                        .page(page_number)
+                        .send()
+                })
                 .await
-                .map_err(|error| {
-                    anyhow::anyhow!("Could not get PR {}/{}: {error:?}", self.repository(), pr.0)
+                .with_context(|| {
+                    format!("Cannot load open pull requests of {}", self.repository())
                 })?;
-            Ok(pr.into())
+
+                let has_next = page.next.is_some();
+                prs.extend(page.items.into_iter().map(|pr| {
+                    let labels = pr
+                        .labels
+                        .iter()
+                        .flatten()
+                        .map(|label| label.name.clone())
+                        .collect();
+                    OpenPullRequest {
+                        pr: pr.into(),
+                        labels,
+                    }
+                }));
+                if !has_next {
+                    break;
+                }
+                page_number += 1;
+            }
+            Ok(prs)
         })
         .await
     }
@@ -126,25 +493,512 @@ impl GithubRepositoryClient {
         pr: PullRequestNumber,
         comment: Comment,
     ) -> anyhow::Result<()> {
+        self.create_comment(pr, comment).await?;
+        Ok(())
+    }
+
+    /// Post a comment that informs about a change that has already happened, e.g. that was
+    /// already stored in the database.
+    /// A failure to post the comment does not undo the change, so it is only reported by
+    /// [Self::report_comment_failure] instead of being returned.
+    pub async fn post_comment_best_effort(&self, pr: PullRequestNumber, comment: Comment) {
+        if let Err(error) = self.post_comment(pr, comment).await {
+            self.report_comment_failure(pr, &error);
+        }
+    }
+
+    /// Logs and counts a comment that could not be posted to the given PR.
+    /// Comments on locked PRs are rejected by GitHub, which is expected, so they are skipped
+    /// silently.
+    pub fn report_comment_failure(&self, pr: PullRequestNumber, error: &anyhow::Error) {
+        if is_locked_error(error) {
+            tracing::debug!(
+                "{} is locked, the comment was not posted",
+                self.format_pr(pr)
+            );
+            return;
+        }
+        FAILED_COMMENTS.fetch_add(1, Ordering::Relaxed);
+        tracing::error!("Cannot post comment to {}: {error:?}", self.format_pr(pr));
+    }
+
+    /// Post a comment to the pull request with the given number and return the created comment.
+    pub async fn create_comment(
+        &self,
+        pr: PullRequestNumber,
+        comment: Comment,
+    ) -> anyhow::Result<PostedComment> {
+        let text = comment.render_truncated(&self.get_pr_checks_url(pr));
+        if self.skip_mutation(|| format!("post comment to {}:\n{text}", self.format_pr(pr))) {
+            return Ok(PostedComment {
+                id: CommentId(0),
+                node_id: String::new(),
+            });
+        }
         measure_network_request("post_comment", || async {
+            // A comment might have been posted even though GitHub has responded with an error,
+            // so it is only retried once, to avoid posting it many times.
+            let comment = retry_request("post_comment", RequestKind::NonIdempotent, || {
+                self.client
+                    .issues(&self.repository().owner, &self.repository().name)
+                    .create_comment(pr.0, &text)
+            })
+            .await
+            .with_context(|| format!("Cannot post comment to {}", self.format_pr(pr)))?;
+            Ok(PostedComment {
+                id: comment.id,
+                node_id: comment.node_id,
+            })
+        })
+        .await
+    }
+
+    /// Add a reaction (e.g. `+1`) to an issue comment with the given ID.
+    pub async fn add_reaction(
+        &self,
+        comment_id: CommentId,
+        reaction: ReactionContent,
+    ) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("add reaction {reaction:?} to comment {comment_id}")) {
+            return Ok(());
+        }
+        measure_network_request("add_reaction", || async {
             self.client
                 .issues(&self.repository().owner, &self.repository().name)
-                .create_comment(pr.0, comment.render())
+                .create_comment_reaction(comment_id, reaction)
                 .await
-                .with_context(|| format!("Cannot post comment to {}", self.format_pr(pr)))?;
+                .with_context(|| format!("Cannot add reaction to comment {comment_id}"))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Minimize (hide) a comment with the given GraphQL node ID, marking it as outdated.
+    pub async fn minimize_comment(&self, node_id: &str) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("minimize comment {node_id}")) {
+            return Ok(());
+        }
+        measure_network_request("minimize_comment", || async {
+            self.graphql::<serde_json::Value>(
+                MINIMIZE_COMMENT_MUTATION,
+                serde_json::json!({ "node_id": node_id }),
+            )
+            .await
+            .with_context(|| format!("Cannot minimize comment {node_id}"))?;
             Ok(())
         })
         .await
     }
 
+    /// Send a GraphQL query to GitHub and return the `data` of the response.
+    async fn graphql<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        #[derive(serde::Deserialize)]
+        struct GraphQlResponse<T> {
+            data: Option<T>,
+            #[serde(default)]
+            errors: Vec<serde_json::Value>,
+        }
+
+        let response: GraphQlResponse<T> = self
+            .client
+            .graphql(&serde_json::json!({
+                "query": query,
+                "variables": variables,
+            }))
+            .await?;
+        if !response.errors.is_empty() {
+            return Err(anyhow::anyhow!(
+                "GraphQL request failed: {}",
+                serde_json::Value::from(response.errors)
+            ));
+        }
+        response
+            .data
+            .context("GraphQL response does not contain any data")
+    }
+
+    /// Replace the text of a comment posted by the bot to the pull request with the given number.
+    /// Returns `false` if the comment does not exist (e.g. because it was deleted).
+    pub async fn edit_comment(
+        &self,
+        pr: PullRequestNumber,
+        id: CommentId,
+        comment: &Comment,
+    ) -> anyhow::Result<bool> {
+        let text = comment.render_truncated(&self.get_pr_checks_url(pr));
+        if self.skip_mutation(|| format!("edit comment {id} on {}:\n{text}", self.format_pr(pr))) {
+            return Ok(true);
+        }
+        measure_network_request("edit_comment", || async {
+            let result = retry_request("edit_comment", RequestKind::Idempotent, || {
+                self.client
+                    .issues(&self.repository().owner, &self.repository().name)
+                    .update_comment(id, &text)
+            })
+            .await;
+            match result {
+                Ok(_) => Ok(true),
+                Err(Error::GitHub { source, .. })
+                    if source.status_code == StatusCode::NOT_FOUND =>
+                {
+                    log::trace!("Trying to edit comment {id} which does not exist");
+                    Ok(false)
+                }
+                Err(error) => Err(error).with_context(|| format!("Cannot edit comment {id}")),
+            }
+        })
+        .await
+    }
+
     /// Set the given branch to a commit with the given `sha`.
     pub async fn set_branch_to_sha(&self, branch: &str, sha: &CommitSha) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("set branch {branch} to {sha}")) {
+            return Ok(());
+        }
         measure_network_request("set_branch_to_sha", || async {
             Ok(set_branch_to_commit(self, branch.to_string(), sha).await?)
         })
         .await
     }
 
+    /// Loads a commit from the git database of the repository.
+    pub async fn get_git_commit(&self, sha: &CommitSha) -> anyhow::Result<GitCommit> {
+        measure_network_request("get_git_commit", || async {
+            // https://docs.github.com/en/rest/git/commits?apiVersion=2022-11-28#get-a-commit-object
+            let url = format!("/repos/{}/git/commits/{sha}", self.repository());
+            let commit: GitCommitObject =
+                retry_request("get_git_commit", RequestKind::Idempotent, || {
+                    self.client.get(url.as_str(), None::<&()>)
+                })
+                .await
+                .with_context(|| format!("Cannot load commit {sha}"))?;
+            Ok(commit.into())
+        })
+        .await
+    }
+
+    /// Creates a commit with the given `tree` and `parents` in the git database of the
+    /// repository and returns its SHA. No branch is updated.
+    /// If `author` is not set, the commit is authored by the bot.
+    pub async fn create_git_commit(
+        &self,
+        message: &str,
+        tree: &str,
+        parents: &[CommitSha],
+        author: Option<&GitAuthor>,
+    ) -> anyhow::Result<CommitSha> {
+        if self.skip_mutation(|| {
+            format!("create commit with tree {tree} and parents {parents:?}:\n{message}")
+        }) {
+            // No commit is created, so we make up a SHA that cannot be confused with a real one.
+            return Ok(CommitSha(format!("dry-run-commit-{tree}")));
+        }
+        measure_network_request("create_git_commit", || async {
+            // https://docs.github.com/en/rest/git/commits?apiVersion=2022-11-28#create-a-commit
+            let url = format!("/repos/{}/git/commits", self.repository());
+            let mut body = serde_json::json!({
+                "message": message,
+                "tree": tree,
+                "parents": parents.iter().map(|parent| parent.as_ref()).collect::<Vec<_>>(),
+            });
+            if let Some(author) = author {
+                body["author"] = serde_json::to_value(author)?;
+            }
+            // A repeated request only creates another commit that no branch points to.
+            let response = retry_response("create_git_commit", RequestKind::Idempotent, || {
+                self.client._post(url.as_str(), Some(&body))
+            })
+            .await
+            .context("Cannot create commit")?;
+            let status = response.status();
+            let text = self.client.body_to_string(response).await?;
+            if !status.is_success() {
+                anyhow::bail!("Cannot create commit ({status}): {text}");
+            }
+            let commit: GitObject =
+                serde_json::from_str(&text).context("Cannot parse created commit")?;
+            Ok(CommitSha(commit.sha))
+        })
+        .await
+    }
+
+    /// Returns the commits of the given PR, from the oldest one.
+    /// GitHub lists at most 250 commits of a PR.
+    pub async fn get_pull_request_commits(
+        &self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<GitCommit>> {
+        measure_network_request("get_pull_request_commits", || async {
+            // https://docs.github.com/en/rest/pulls/pulls?apiVersion=2022-11-28#list-commits-on-a-pull-request
+            #[derive(serde::Deserialize)]
+            struct CommitDetail {
+                tree: GitObject,
+                message: String,
+                author: GitAuthor,
+            }
+
+            #[derive(serde::Deserialize)]
+            struct PullRequestCommit {
+                sha: String,
+                commit: CommitDetail,
+                parents: Vec<GitObject>,
+            }
+
+            let url = format!("/repos/{}/pulls/{pr}/commits", self.repository());
+            let mut commits = vec![];
+            let mut page_number = 1u32;
+            loop {
+                let page_param = page_number.to_string();
+                let page: Vec<PullRequestCommit> =
+                    retry_request("get_pull_request_commits", RequestKind::Idempotent, || {
+                        self.client.get(
+                            url.as_str(),
+                            Some(&[("per_page", "100"), ("page", page_param.as_str())]),
+                        )
+                    })
+                    .await
+                    .with_context(|| format!("Cannot load commits of {}", self.format_pr(pr)))?;
+                let is_last = page.len() < 100;
+                commits.extend(page.into_iter().map(|commit| {
+                    GitCommit::from(GitCommitObject {
+                        sha: commit.sha,
+                        tree: commit.commit.tree,
+                        parents: commit.parents,
+                        message: commit.commit.message,
+                        author: commit.commit.author,
+                    })
+                }));
+                if is_last {
+                    break;
+                }
+                page_number += 1;
+            }
+            Ok(commits)
+        })
+        .await
+    }
+
+    /// Closes the given PR.
+    pub async fn close_pull_request(&self, pr: PullRequestNumber) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("close {}", self.format_pr(pr))) {
+            return Ok(());
+        }
+        measure_network_request("close_pull_request", || async {
+            // https://docs.github.com/en/rest/pulls/pulls?apiVersion=2022-11-28#update-a-pull-request
+            let url = format!("/repos/{}/pulls/{pr}", self.repository());
+            let body = serde_json::json!({ "state": "closed" });
+            let response = retry_response("close_pull_request", RequestKind::Idempotent, || {
+                self.client._patch(url.as_str(), Some(&body))
+            })
+            .await
+            .with_context(|| format!("Cannot close {}", self.format_pr(pr)))?;
+            let status = response.status();
+            if !status.is_success() {
+                let text = self.client.body_to_string(response).await?;
+                anyhow::bail!("Cannot close {} ({status}): {text}", self.format_pr(pr));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Creates a check run that is already completed with the given `conclusion`.
+    pub async fn create_check_run(
+        &self,
+        name: &str,
+        head_sha: &CommitSha,
+        conclusion: CheckRunConclusion,
+        title: &str,
+        summary: &str,
+    ) -> anyhow::Result<()> {
+        if self.skip_mutation(|| {
+            format!("create check run {name} ({conclusion:?}) for {head_sha}: {title}\n{summary}")
+        }) {
+            return Ok(());
+        }
+        measure_network_request("create_check_run", || async {
+            // https://docs.github.com/en/rest/checks/runs?apiVersion=2022-11-28#create-a-check-run
+            let url = format!("/repos/{}/check-runs", self.repository());
+            let response = self
+                .client
+                ._post(
+                    url,
+                    Some(&serde_json::json!({
+                        "name": name,
+                        "head_sha": head_sha.as_ref(),
+                        "status": "completed",
+                        "conclusion": conclusion,
+                        "output": {
+                            "title": title,
+                            "summary": summary,
+                        }
+                    })),
+                )
+                .await
+                .context("Cannot create check run")?;
+            let status = response.status();
+            if !status.is_success() {
+                let text = self.client.body_to_string(response).await?;
+                anyhow::bail!("Cannot create check run ({status}): {text}");
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Creates a check run that is in progress and returns its ID.
+    /// Returns `None` in dry-run mode, when no check run is created.
+    pub async fn start_check_run(
+        &self,
+        name: &str,
+        head_sha: &CommitSha,
+        title: &str,
+        summary: &str,
+    ) -> anyhow::Result<Option<u64>> {
+        if self.skip_mutation(|| format!("start check run {name} for {head_sha}: {title}")) {
+            return Ok(None);
+        }
+        measure_network_request("start_check_run", || async {
+            // https://docs.github.com/en/rest/checks/runs?apiVersion=2022-11-28#create-a-check-run
+            let url = format!("/repos/{}/check-runs", self.repository());
+            let body = serde_json::json!({
+                "name": name,
+                "head_sha": head_sha.as_ref(),
+                "status": "in_progress",
+                "output": {
+                    "title": title,
+                    "summary": summary,
+                }
+            });
+            let response = retry_response("start_check_run", RequestKind::NonIdempotent, || {
+                self.client._post(url.as_str(), Some(&body))
+            })
+            .await
+            .context("Cannot create check run")?;
+            let status = response.status();
+            let text = self.client.body_to_string(response).await?;
+            if !status.is_success() {
+                anyhow::bail!("Cannot create check run ({status}): {text}");
+            }
+            let check_run: serde_json::Value =
+                serde_json::from_str(&text).context("Cannot parse created check run")?;
+            let id = check_run["id"]
+                .as_u64()
+                .context("Created check run does not have an ID")?;
+            Ok(Some(id))
+        })
+        .await
+    }
+
+    /// Completes the check run with the given ID with the given `conclusion`.
+    pub async fn complete_check_run(
+        &self,
+        check_run_id: u64,
+        conclusion: CheckRunConclusion,
+        title: &str,
+        summary: &str,
+    ) -> anyhow::Result<()> {
+        if self.skip_mutation(|| {
+            format!("complete check run {check_run_id} ({conclusion:?}): {title}\n{summary}")
+        }) {
+            return Ok(());
+        }
+        measure_network_request("complete_check_run", || async {
+            // https://docs.github.com/en/rest/checks/runs?apiVersion=2022-11-28#update-a-check-run
+            let url = format!("/repos/{}/check-runs/{check_run_id}", self.repository());
+            let body = serde_json::json!({
+                "status": "completed",
+                "conclusion": conclusion,
+                "output": {
+                    "title": title,
+                    "summary": summary,
+                }
+            });
+            let response = retry_response("complete_check_run", RequestKind::Idempotent, || {
+                self.client._patch(url.as_str(), Some(&body))
+            })
+            .await
+            .context("Cannot update check run")?;
+            let status = response.status();
+            if !status.is_success() {
+                let text = self.client.body_to_string(response).await?;
+                anyhow::bail!("Cannot update check run ({status}): {text}");
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets a commit status with the given `context` on the given commit.
+    pub async fn set_commit_status(
+        &self,
+        sha: &CommitSha,
+        context: &str,
+        state: CommitStatusState,
+        description: &str,
+        target_url: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if self.skip_mutation(|| {
+            format!("set commit status {context} of {sha} to {state:?}: {description}")
+        }) {
+            return Ok(());
+        }
+        measure_network_request("set_commit_status", || async {
+            // https://docs.github.com/en/rest/commits/statuses?apiVersion=2022-11-28#create-a-commit-status
+            let url = format!("/repos/{}/statuses/{sha}", self.repository());
+            let body = serde_json::json!({
+                "state": state,
+                "description": description,
+                "context": context,
+                "target_url": target_url,
+            });
+            // A new status with the same context replaces the previous one, so the request can
+            // be safely repeated.
+            let response = retry_response("set_commit_status", RequestKind::Idempotent, || {
+                self.client._post(url.as_str(), Some(&body))
+            })
+            .await
+            .context("Cannot set commit status")?;
+            let status = response.status();
+            if !status.is_success() {
+                let text = self.client.body_to_string(response).await?;
+                anyhow::bail!("Cannot set commit status ({status}): {text}");
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Delete the given branch.
+    /// Returns `false` if the branch did not exist.
+    pub async fn delete_branch(&self, branch: &str) -> anyhow::Result<bool> {
+        if self.skip_mutation(|| format!("delete branch {branch}")) {
+            return Ok(true);
+        }
+        measure_network_request("delete_branch", || async {
+            let result = self
+                .client
+                .repos(self.repository().owner(), self.repository().name())
+                .delete_ref(&Reference::Branch(branch.to_string()))
+                .await;
+            match result {
+                Ok(_) => Ok(true),
+                Err(Error::GitHub { source, .. })
+                    if source.message.contains("Reference does not exist") =>
+                {
+                    log::trace!("Trying to delete branch {branch} which does not exist");
+                    Ok(false)
+                }
+                Err(error) => Err(error).with_context(|| format!("Cannot delete branch {branch}")),
+            }
+        })
+        .await
+    }
+
     /// Merge `head` into `base`. Returns the SHA of the merge commit.
     pub async fn merge_branches(
         &self,
@@ -152,12 +1006,34 @@ impl GithubRepositoryClient {
         head: &CommitSha,
         commit_message: &str,
     ) -> Result<CommitSha, MergeError> {
+        if self.skip_mutation(|| format!("merge {head} into {base}:\n{commit_message}")) {
+            // No merge commit is created, so we make up a SHA that cannot be confused with
+            // a real one.
+            return Ok(CommitSha(format!("dry-run-merge-{head}")));
+        }
         measure_network_request("merge_branches", || async {
             merge_branches(self, base, head, commit_message).await
         })
         .await
     }
 
+    /// Fast-forward the given branch to a commit with the given `sha`.
+    /// Fails with [BranchUpdateError::NotFastForward] if the branch has moved in the meantime,
+    /// so that the commit does not descend from it anymore.
+    pub async fn fast_forward_branch(
+        &self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> Result<(), BranchUpdateError> {
+        if self.skip_mutation(|| format!("fast-forward branch {branch} to {sha}")) {
+            return Ok(());
+        }
+        measure_network_request("fast_forward_branch", || async {
+            fast_forward_branch(self, branch.to_string(), sha).await
+        })
+        .await
+    }
+
     /// Find all check suites attached to the given commit and branch.
     pub async fn get_check_suites_for_commit(
         &self,
@@ -176,20 +1052,19 @@ impl GithubRepositoryClient {
                 check_suites: Vec<CheckSuitePayload>,
             }
 
-            let response: CheckSuiteResponse = self
-                .client
-                .get(
-                    format!(
-                        "/repos/{}/{}/commits/{}/check-suites",
-                        self.repo_name.owner(),
-                        self.repo_name.name(),
-                        sha.0
-                    )
-                    .as_str(),
-                    None::<&()>,
-                )
-                .await
-                .context("Cannot fetch CheckSuiteResponse")?;
+            let url = format!(
+                "/repos/{}/{}/commits/{}/check-suites",
+                self.repo_name.owner(),
+                self.repo_name.name(),
+                sha.0
+            );
+            let response: CheckSuiteResponse = retry_request(
+                "get_check_suites_for_commit",
+                RequestKind::Idempotent,
+                || self.client.get(url.as_str(), None::<&()>),
+            )
+            .await
+            .context("Cannot fetch CheckSuiteResponse")?;
 
             let suites = response
                 .check_suites
@@ -198,11 +1073,10 @@ impl GithubRepositoryClient {
                 .map(|suite| CheckSuite {
                     status: match suite.conclusion {
                         Some(status) => match status.as_str() {
-                            "success" => CheckSuiteStatus::Success,
-                            "failure" | "neutral" | "cancelled" | "skipped" | "timed_out"
-                            | "action_required" | "startup_failure" | "stale" => {
-                                CheckSuiteStatus::Failure
-                            }
+                            // Skipped suites do not block the build
+                            "success" | "neutral" | "skipped" => CheckSuiteStatus::Success,
+                            "failure" | "cancelled" | "timed_out" | "action_required"
+                            | "startup_failure" | "stale" => CheckSuiteStatus::Failure,
                             _ => {
                                 tracing::warn!(
                                     "Received unknown check suite status for {}/{}: {status}",
@@ -223,6 +1097,9 @@ impl GithubRepositoryClient {
 
     /// Cancels Github Actions workflows.
     pub async fn cancel_workflows(&self, run_ids: &[RunId]) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("cancel workflow runs {run_ids:?}")) {
+            return Ok(());
+        }
         measure_network_request("cancel_workflows", || async {
             let actions = self.client.actions();
 
@@ -243,17 +1120,88 @@ impl GithubRepositoryClient {
         .await
     }
 
+    /// Returns the jobs of the latest attempt of the given Github Actions workflow run that
+    /// have failed.
+    pub async fn get_failed_jobs(&self, run_id: RunId) -> anyhow::Result<Vec<WorkflowJob>> {
+        measure_network_request("get_failed_jobs", || async {
+            // https://docs.github.com/en/rest/actions/workflow-jobs?apiVersion=2022-11-28#list-jobs-for-a-workflow-run
+            #[derive(serde::Deserialize)]
+            struct JobsResponse {
+                jobs: Vec<WorkflowJob>,
+            }
+
+            let url = format!("/repos/{}/actions/runs/{run_id}/jobs", self.repository());
+            let response: JobsResponse =
+                retry_request("get_failed_jobs", RequestKind::Idempotent, || {
+                    self.client.get(
+                        url.as_str(),
+                        Some(&[("filter", "latest"), ("per_page", "100")]),
+                    )
+                })
+                .await
+                .with_context(|| format!("Cannot load jobs of workflow run {run_id}"))?;
+            Ok(response
+                .jobs
+                .into_iter()
+                .filter(|job| job.conclusion.as_deref() == Some("failure"))
+                .collect())
+        })
+        .await
+    }
+
+    /// Downloads the plain text log of the given Github Actions job.
+    pub async fn get_job_log(&self, job_id: u64) -> anyhow::Result<String> {
+        measure_network_request("get_job_log", || async {
+            // https://docs.github.com/en/rest/actions/workflow-jobs?apiVersion=2022-11-28#download-job-logs-for-a-workflow-run
+            let response = self
+                .client
+                ._get(format!(
+                    "/repos/{}/actions/jobs/{job_id}/logs",
+                    self.repository()
+                ))
+                .await
+                .with_context(|| format!("Cannot download log of job {job_id}"))?;
+            let status = response.status();
+            // GitHub usually redirects to a short-lived URL from which the log can be
+            // downloaded without authentication.
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(http::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .with_context(|| format!("Missing log location of job {job_id}"))?
+                    .to_string();
+                return Ok(reqwest::get(location)
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .with_context(|| format!("Cannot download log of job {job_id}"))?
+                    .text()
+                    .await?);
+            }
+            let text = self.client.body_to_string(response).await?;
+            if !status.is_success() {
+                anyhow::bail!("Cannot download log of job {job_id} ({status}): {text}");
+            }
+            Ok(text)
+        })
+        .await
+    }
+
     /// Add a set of labels to a PR.
     pub async fn add_labels(&self, pr: PullRequestNumber, labels: &[String]) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("add labels {labels:?} to {}", self.format_pr(pr))) {
+            return Ok(());
+        }
         measure_network_request("add_labels", || async {
             let client = self
                 .client
                 .issues(self.repository().owner(), self.repository().name());
             if !labels.is_empty() {
-                client
-                    .add_labels(pr.0, labels)
-                    .await
-                    .context("Cannot add label(s) to PR")?;
+                retry_request("add_labels", RequestKind::Idempotent, || {
+                    client.add_labels(pr.0, labels)
+                })
+                .await
+                .context("Cannot add label(s) to PR")?;
             }
 
             Ok(())
@@ -267,6 +1215,9 @@ impl GithubRepositoryClient {
         pr: PullRequestNumber,
         labels: &[String],
     ) -> anyhow::Result<()> {
+        if self.skip_mutation(|| format!("remove labels {labels:?} from {}", self.format_pr(pr))) {
+            return Ok(());
+        }
         measure_network_request("remove_labels", || async {
             let client = self
                 .client
@@ -301,13 +1252,22 @@ impl GithubRepositoryClient {
 
     /// Get a workflow url.
     pub fn get_workflow_url(&self, run_id: RunId) -> String {
-        let html_url = self
-            .repository
-            .html_url
-            .as_ref()
-            .map(|url| url.to_string())
-            .unwrap_or_else(|| format!("{}/{}", base_github_html_url(), self.repository(),));
-        format!("{html_url}/actions/runs/{run_id}")
+        format!("{}/actions/runs/{run_id}", self.html_url())
+    }
+
+    /// Get the url of a commit.
+    pub fn get_commit_url(&self, sha: &str) -> String {
+        format!("{}/commit/{sha}", self.html_url())
+    }
+
+    /// Get the url of a pull request.
+    pub fn get_pr_url(&self, pr: PullRequestNumber) -> String {
+        format!("{}/pull/{pr}", self.html_url())
+    }
+
+    /// Get the url of the page that lists the checks of a pull request.
+    pub fn get_pr_checks_url(&self, pr: PullRequestNumber) -> String {
+        format!("{}/pull/{pr}/checks", self.html_url())
     }
 
     /// Get workflow url for a list of workflows.
@@ -318,20 +1278,52 @@ impl GithubRepositoryClient {
         run_ids.map(|workflow_id| self.get_workflow_url(workflow_id))
     }
 
+    fn html_url(&self) -> String {
+        self.repository
+            .html_url
+            .as_ref()
+            .map(|url| url.to_string())
+            .unwrap_or_else(|| {
+                format!(
+                    "{}/{}",
+                    self.github_html_url.trim_end_matches('/'),
+                    self.repository()
+                )
+            })
+    }
+
     fn format_pr(&self, pr: PullRequestNumber) -> String {
         format!("{}/{}", self.repository(), pr)
     }
 }
 
+/// Returns true if GitHub has rejected a comment because the conversation of the PR is locked.
+fn is_locked_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<Error>(),
+            Some(Error::GitHub { source, .. })
+                if source.status_code == StatusCode::FORBIDDEN && source.message.contains("locked")
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::github::GithubRepoName;
-    use crate::github::api::load_repositories;
-    use crate::permissions::PermissionType;
-    use crate::tests::mocks::Permissions;
-    use crate::tests::mocks::{ExternalHttpMock, Repo};
+    use crate::bors::Comment;
+    use crate::database::RunId;
+    use crate::github::api::client::GithubRepositoryClient;
+    use crate::github::api::{create_github_client, load_repositories};
+    use crate::github::{CommitSha, CommitStatusState, GithubRepoName, PullRequestNumber};
+    use crate::permissions::{PermissionSource, PermissionType, TeamApiClient};
+    use crate::tests::mocks::{ExternalHttpMock, GitHubApp, Repo};
+    use crate::tests::mocks::{GITHUB_MOCK_PRIVATE_KEY, Permissions};
     use crate::tests::mocks::{GitHubState, User};
+    use crate::tests::mocks::{default_app_id, mock_app_repositories};
+    use octocrab::Octocrab;
     use octocrab::models::UserId;
+    use wiremock::matchers::{method, path, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
     async fn load_installed_repos() {
@@ -353,8 +1345,16 @@ mod tests {
         )
         .await;
         let client = mock.github_client();
-        let team_api_client = mock.team_api_client();
-        let mut repos = load_repositories(&client, &team_api_client).await.unwrap();
+        let permission_source = mock.permission_source();
+        let mut repos = load_repositories(
+            &client,
+            &permission_source,
+            "https://github.com",
+            None,
+            false,
+        )
+        .await
+        .unwrap();
         assert_eq!(repos.len(), 2);
 
         let repo = repos
@@ -373,4 +1373,197 @@ mod tests {
                 .has_permission(UserId(1), PermissionType::Review)
         );
     }
+
+    #[tokio::test]
+    async fn workflow_url_uses_configured_html_url() {
+        let mock = ExternalHttpMock::start(&GitHubState::default()).await;
+        let mut repos = load_repositories(
+            &mock.github_client(),
+            &mock.permission_source(),
+            "https://github.example.com/",
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        let repo = repos
+            .remove(&GithubRepoName::new("rust-lang", "borstest"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            repo.client.get_workflow_url(RunId(42)),
+            "https://github.example.com/rust-lang/borstest/actions/runs/42"
+        );
+    }
+
+    /// GitHub Enterprise Server exposes the API under a path prefix (`/api/v3`), so the client
+    /// must not assume that the API lives at the root of the host.
+    #[tokio::test]
+    async fn load_repositories_with_api_path_prefix() {
+        let server = MockServer::start().await;
+        let github = GitHubState::default();
+        mock_app_repositories(&github, &server, "/api/v3").await;
+        Mock::given(method("GET"))
+            .and(path_regex(
+                "^/v1/permissions/bors.borstest.(review|try).json$",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "github_ids": [1]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = create_github_client(
+            default_app_id().into(),
+            format!("{}/api/v3", server.uri()),
+            GITHUB_MOCK_PRIVATE_KEY.into(),
+        )
+        .unwrap();
+        let permission_source = PermissionSource::from(TeamApiClient::new(server.uri()));
+        let mut repos = load_repositories(
+            &client,
+            &permission_source,
+            "https://github.example.com",
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        let repo = repos
+            .remove(&GithubRepoName::new("rust-lang", "borstest"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(repo.client.repository().name(), "borstest");
+        assert!(
+            repo.permissions
+                .load()
+                .has_permission(UserId(1), PermissionType::Review)
+        );
+    }
+
+    fn client_for_server(server: &MockServer) -> GithubRepositoryClient {
+        let client = Octocrab::builder()
+            .base_uri(server.uri())
+            .unwrap()
+            .build()
+            .unwrap();
+        let app =
+            serde_json::from_value(serde_json::to_value(GitHubApp::default()).unwrap()).unwrap();
+        let repository = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "borstest",
+            "url": "https://api.github.com/repos/rust-lang/borstest",
+        }))
+        .unwrap();
+        GithubRepositoryClient::new(
+            app,
+            client,
+            GithubRepoName::new("rust-lang", "borstest"),
+            repository,
+            "https://github.com".to_string(),
+            false,
+        )
+    }
+
+    fn bad_gateway() -> ResponseTemplate {
+        ResponseTemplate::new(502).set_body_json(serde_json::json!({ "message": "Bad Gateway" }))
+    }
+
+    #[tokio::test]
+    async fn retry_get_after_server_error() {
+        let server = MockServer::start().await;
+        let branch_path = "/repos/rust-lang/borstest/branches/main";
+        Mock::given(method("GET"))
+            .and(path(branch_path))
+            .respond_with(bad_gateway())
+            .up_to_n_times(1)
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(branch_path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "main",
+                "commit": { "sha": "main-sha", "url": "https://github.com/commit/main-sha" },
+                "protected": false,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sha = client_for_server(&server)
+            .get_branch_sha("main")
+            .await
+            .unwrap();
+        assert_eq!(sha, CommitSha("main-sha".to_string()));
+    }
+
+    #[tokio::test]
+    async fn retry_status_update_after_server_error() {
+        let server = MockServer::start().await;
+        let status_path = "/repos/rust-lang/borstest/statuses/sha";
+        Mock::given(method("POST"))
+            .and(path(status_path))
+            .respond_with(bad_gateway())
+            .up_to_n_times(2)
+            .with_priority(1)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(status_path))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client_for_server(&server)
+            .set_commit_status(
+                &CommitSha("sha".to_string()),
+                "bors",
+                CommitStatusState::Pending,
+                "Try build in progress",
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn retry_comment_post_at_most_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/rust-lang/borstest/issues/1/comments"))
+            .respond_with(bad_gateway())
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let result = client_for_server(&server)
+            .post_comment(PullRequestNumber(1), Comment::new("Hello".to_string()))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn do_not_retry_client_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/rust-lang/borstest/branches/main"))
+            .respond_with(
+                ResponseTemplate::new(404)
+                    .set_body_json(serde_json::json!({ "message": "Branch not found" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(
+            client_for_server(&server)
+                .get_branch_sha("main")
+                .await
+                .is_err()
+        );
+    }
 }
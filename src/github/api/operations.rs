@@ -94,7 +94,7 @@ pub async fn set_branch_to_commit(
     sha: &CommitSha,
 ) -> Result<(), BranchUpdateError> {
     // Fast-path: assume that the branch exists
-    match update_branch(repo, branch_name.clone(), sha).await {
+    match update_branch(repo, branch_name.clone(), sha, true).await {
         Ok(_) => Ok(()),
         Err(BranchUpdateError::BranchNotFound(_)) => {
             // Branch does not exist yet, try to create it
@@ -107,6 +107,16 @@ pub async fn set_branch_to_commit(
     }
 }
 
+/// Updates the existing branch to the given commit `sha`, which has to be a descendant of
+/// the commit that the branch currently points to.
+pub async fn fast_forward_branch(
+    repo: &GithubRepositoryClient,
+    branch_name: String,
+    sha: &CommitSha,
+) -> Result<(), BranchUpdateError> {
+    update_branch(repo, branch_name, sha, false).await
+}
+
 async fn create_branch(
     repo: &GithubRepositoryClient,
     name: String,
@@ -124,17 +134,21 @@ async fn create_branch(
 pub enum BranchUpdateError {
     #[error("Branch {0} was not found")]
     BranchNotFound(String),
+    #[error("Branch {0} cannot be fast-forwarded")]
+    NotFastForward(String),
     #[error("IO error")]
     IOError(#[from] octocrab::Error),
     #[error("Unknown error: {0}")]
     Custom(String),
 }
 
-/// Force update the branch with the given `branch_name` to the given `sha`.
+/// Update the branch with the given `branch_name` to the given `sha`.
+/// Unless `force` is set, the update is rejected if it is not a fast-forward.
 async fn update_branch(
     repo: &GithubRepositoryClient,
     branch_name: String,
     sha: &CommitSha,
+    force: bool,
 ) -> Result<(), BranchUpdateError> {
     let url = format!(
         "/repos/{}/git/refs/{}",
@@ -150,20 +164,24 @@ async fn update_branch(
             url.as_str(),
             Some(&serde_json::json!({
                 "sha": sha.as_ref(),
-                "force": true
+                "force": force
             })),
         )
         .await?;
 
     let status = res.status();
+    let text = repo.client().body_to_string(res).await.unwrap_or_default();
     tracing::trace!(
         "Updating branch response: status={}, text={:?}",
         status,
-        repo.client().body_to_string(res).await
+        text
     );
 
     match status {
         StatusCode::OK => Ok(()),
+        StatusCode::UNPROCESSABLE_ENTITY if text.contains("not a fast forward") => {
+            Err(BranchUpdateError::NotFastForward(branch_name))
+        }
         _ => Err(BranchUpdateError::BranchNotFound(branch_name)),
     }
 }
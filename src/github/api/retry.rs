@@ -0,0 +1,300 @@
+//! Retrying of GitHub API requests that have failed because of a transient error (a server
+//! error or a rate limit).
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use http::{HeaderMap, StatusCode};
+use octocrab::Error;
+
+/// Maximum number of attempts of an idempotent request.
+pub(crate) const IDEMPOTENT_ATTEMPTS: u32 = 5;
+/// Maximum number of attempts of a non-idempotent request. Such requests (e.g. posting a
+/// comment) might have been performed even though GitHub responded with an error, so they are
+/// retried at most once, to avoid creating many duplicates.
+const NON_IDEMPOTENT_ATTEMPTS: u32 = 2;
+
+/// Delay before the first retry of a request. It is doubled after each attempt.
+#[cfg(not(test))]
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+#[cfg(test)]
+const INITIAL_DELAY: Duration = Duration::from_millis(10);
+/// Upper bound of the delay between two attempts, including delays requested by GitHub.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Number of GitHub requests that have been retried since the start of the process.
+static RETRIED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of GitHub requests that have been retried since the start of the process.
+pub fn retried_request_count() -> u64 {
+    RETRIED_REQUESTS.load(Ordering::Relaxed)
+}
+
+tokio::task_local! {
+    /// Set once a non-idempotent request has been sent by the current task, see
+    /// [track_non_idempotent_requests].
+    static NON_IDEMPOTENT_SENT: Cell<bool>;
+}
+
+/// Runs `future` and returns its output along with a flag that tells if it has sent any
+/// non-idempotent request (even one that has failed). Such work cannot be safely repeated from
+/// the start, because e.g. a comment would be posted twice.
+pub(crate) async fn track_non_idempotent_requests<F: Future>(future: F) -> (F::Output, bool) {
+    NON_IDEMPOTENT_SENT
+        .scope(Cell::new(false), async move {
+            let output = future.await;
+            (output, NON_IDEMPOTENT_SENT.with(Cell::get))
+        })
+        .await
+}
+
+/// Describes if a request can be safely performed several times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RequestKind {
+    /// Reads and updates that set some state to a given value.
+    Idempotent,
+    /// Requests that create something new on every invocation.
+    NonIdempotent,
+}
+
+impl RequestKind {
+    fn max_attempts(self) -> u32 {
+        match self {
+            RequestKind::Idempotent => IDEMPOTENT_ATTEMPTS,
+            RequestKind::NonIdempotent => NON_IDEMPOTENT_ATTEMPTS,
+        }
+    }
+}
+
+/// What should happen after a request has finished.
+enum RetryDecision {
+    Done,
+    /// Retry after the default exponential backoff.
+    Backoff,
+    /// Retry after the delay requested by GitHub.
+    After(Duration),
+}
+
+/// Performs a request through octocrab, retrying it if it has failed because of a server error
+/// or a rate limit.
+pub(crate) async fn retry_request<T, F, Fut>(
+    name: &str,
+    kind: RequestKind,
+    request: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    retry(name, kind, request, |result| match result {
+        Ok(_) => RetryDecision::Done,
+        Err(error) => decide_error(error),
+    })
+    .await
+}
+
+/// Performs a raw request (e.g. `Octocrab::_post`), retrying it if it has failed because of
+/// a server error or a rate limit.
+/// Unlike errors returned by octocrab, raw responses contain headers, so the `Retry-After`
+/// and `x-ratelimit-reset` headers of rate limited responses are honored.
+pub(crate) async fn retry_response<B, F, Fut>(
+    name: &str,
+    kind: RequestKind,
+    request: F,
+) -> Result<http::Response<B>, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<http::Response<B>, Error>>,
+{
+    retry(name, kind, request, |result| match result {
+        Ok(response) => decide_response(response.status(), response.headers()),
+        Err(error) => decide_error(error),
+    })
+    .await
+}
+
+async fn retry<T, F, Fut, D>(name: &str, kind: RequestKind, mut request: F, decide: D) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+    D: Fn(&T) -> RetryDecision,
+{
+    let max_attempts = kind.max_attempts();
+    let mut backoff = INITIAL_DELAY;
+    let mut attempt = 1;
+    loop {
+        if kind == RequestKind::NonIdempotent {
+            // Requests sent outside of `track_non_idempotent_requests` are not tracked
+            let _ = NON_IDEMPOTENT_SENT.try_with(|sent| sent.set(true));
+        }
+        let result = request().await;
+        let delay = match decide(&result) {
+            RetryDecision::Done => return result,
+            _ if attempt >= max_attempts => return result,
+            RetryDecision::Backoff => jitter(backoff),
+            RetryDecision::After(delay) => delay,
+        }
+        .min(MAX_DELAY);
+        RETRIED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            request = name,
+            attempt,
+            max_attempts,
+            delay_ms = delay.as_millis() as u64,
+            "GitHub request failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Returns true if a request that has failed with the given error (even after it was retried)
+/// might succeed if it is performed again later.
+pub(crate) fn is_retryable_github_error(error: &Error) -> bool {
+    !matches!(decide_error(error), RetryDecision::Done)
+}
+
+fn decide_error(error: &Error) -> RetryDecision {
+    match error {
+        Error::GitHub { source, .. }
+            if is_transient(source.status_code)
+                || is_rate_limit_message(source.status_code, &source.message) =>
+        {
+            RetryDecision::Backoff
+        }
+        _ => RetryDecision::Done,
+    }
+}
+
+fn decide_response(status: StatusCode, headers: &HeaderMap) -> RetryDecision {
+    // GitHub reports rate limits with 403 as well, but 403 also means that the app lacks
+    // a permission, so it is only retried if the headers say that a limit was hit.
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (headers.contains_key(http::header::RETRY_AFTER)
+                || header_value(headers, "x-ratelimit-remaining") == Some(0)));
+    if !rate_limited && !is_transient(status) {
+        return RetryDecision::Done;
+    }
+    match requested_delay(headers, SystemTime::now()) {
+        Some(delay) => RetryDecision::After(delay),
+        None => RetryDecision::Backoff,
+    }
+}
+
+/// Status codes of errors that are caused by a temporary problem on the side of GitHub.
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_rate_limit_message(status: StatusCode, message: &str) -> bool {
+    status == StatusCode::FORBIDDEN && message.to_lowercase().contains("rate limit")
+}
+
+/// Returns the delay that GitHub asks us to wait for before the next request, either through
+/// the `Retry-After` header (secondary rate limits), or through the `x-ratelimit-reset` header
+/// once the primary rate limit has been exhausted.
+fn requested_delay(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    if let Some(seconds) = header_value(headers, http::header::RETRY_AFTER.as_str()) {
+        return Some(Duration::from_secs(seconds));
+    }
+    if header_value(headers, "x-ratelimit-remaining") == Some(0) {
+        let reset = UNIX_EPOCH + Duration::from_secs(header_value(headers, "x-ratelimit-reset")?);
+        return Some(reset.duration_since(now).unwrap_or_default());
+    }
+    None
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Randomizes the delay to somewhere between a half and the full `delay`, so that requests
+/// that have failed at the same time are not retried at the same time.
+fn jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now());
+    delay / 2 + delay.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn headers(values: &[(&'static str, &str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| {
+                (
+                    http::HeaderName::from_static(name),
+                    HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delay_from_retry_after() {
+        let headers = headers(&[("retry-after", "30")]);
+        assert_eq!(
+            requested_delay(&headers, SystemTime::now()),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn delay_from_rate_limit_reset() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let headers = headers(&[
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "1042"),
+        ]);
+        assert_eq!(
+            requested_delay(&headers, now),
+            Some(Duration::from_secs(42))
+        );
+    }
+
+    #[test]
+    fn no_delay_with_remaining_rate_limit() {
+        let headers = headers(&[
+            ("x-ratelimit-remaining", "10"),
+            ("x-ratelimit-reset", "1042"),
+        ]);
+        assert_eq!(requested_delay(&headers, SystemTime::now()), None);
+    }
+
+    #[test]
+    fn forbidden_without_rate_limit_is_not_retried() {
+        assert!(matches!(
+            decide_response(StatusCode::FORBIDDEN, &HeaderMap::new()),
+            RetryDecision::Done
+        ));
+        assert!(matches!(
+            decide_response(
+                StatusCode::FORBIDDEN,
+                &headers(&[("x-ratelimit-remaining", "0")])
+            ),
+            RetryDecision::Backoff
+        ));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let delay = Duration::from_secs(8);
+        for _ in 0..100 {
+            let jittered = jitter(delay);
+            assert!(jittered >= delay / 2 && jittered <= delay);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Dispatching of events into lanes that are handled concurrently.
+//!
+//! Events with the same lane key are handled one after another, in the order in which they were
+//! received. Events with different keys are handled concurrently, but at most a given number of
+//! events is in flight at any time.
+//!
+//! Consecutive events of a lane can also be handled together in a single batch, see [Batching].
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::task::{JoinError, JoinSet};
+use tokio::time::Instant;
+
+/// Describes which consecutive events of a lane are handled together.
+pub(super) struct Batching<B> {
+    /// Returns the batch key of an event. Consecutive events of a lane with the same key are
+    /// handled in a single batch, events without a key are always handled alone.
+    pub key: B,
+    /// Maximum number of events in a batch. Each event of a batch counts towards the limit of
+    /// events in flight, so batches are never larger than that limit.
+    pub max_size: usize,
+    /// How long to wait for further events once a batch has been started. This bounds the
+    /// latency that batching adds to the handling of an event.
+    pub window: Duration,
+}
+
+/// Events waiting to be handled in a lane, along with the permits that they hold.
+struct Lane<T> {
+    events: Mutex<VecDeque<(T, OwnedSemaphorePermit)>>,
+    /// Notified whenever an event is pushed to the lane.
+    pushed: Notify,
+}
+
+type LaneRef<T> = Arc<Lane<T>>;
+
+/// Receives events from `rx` until the channel is closed and handles them with `handler`.
+/// Events are sorted into lanes by `lane_key`, each lane is handled by a separate task that
+/// exists only while the lane has some events to handle. `handler` receives a batch of events
+/// of the same lane, see [Batching].
+///
+/// At most `max_parallel` events are being handled or waiting in a lane at the same time.
+/// Once that limit is reached, no further events are received until some event is handled.
+///
+/// Returns once all received events have been handled. If a handler panics, the panic is
+/// propagated.
+pub(super) async fn dispatch_by_lane<T, K, BK, F, B, H, Fut>(
+    mut rx: mpsc::Receiver<T>,
+    max_parallel: usize,
+    lane_key: F,
+    batching: Batching<B>,
+    handler: H,
+) where
+    T: Send + 'static,
+    K: Eq + Hash + Clone + Send + 'static,
+    BK: PartialEq,
+    F: Fn(&T) -> K,
+    B: Fn(&T) -> Option<BK> + Clone + Send + Sync + 'static,
+    H: Fn(Vec<T>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let max_parallel = max_parallel.max(1);
+    let permits = Arc::new(Semaphore::new(max_parallel));
+    let batching = Arc::new(Batching {
+        key: batching.key,
+        max_size: batching.max_size.clamp(1, max_parallel),
+        window: batching.window,
+    });
+    let mut lanes: HashMap<K, LaneRef<T>> = HashMap::new();
+    let mut tasks: JoinSet<K> = JoinSet::new();
+    let mut receiving = true;
+
+    while receiving || !tasks.is_empty() {
+        tokio::select! {
+            Some(result) = tasks.join_next(), if !tasks.is_empty() => {
+                let key = unwrap_lane_result(result);
+                // Only the dispatcher pushes events into lanes, so nothing can be added to the
+                // queue between this check and the removal of the lane.
+                let lane = lanes[&key].clone();
+                if lane.events.lock().unwrap().is_empty() {
+                    lanes.remove(&key);
+                } else {
+                    tasks.spawn(handle_lane(key, lane, batching.clone(), handler.clone()));
+                }
+            }
+            event = rx.recv(), if receiving => {
+                let Some(event) = event else {
+                    receiving = false;
+                    continue;
+                };
+                let permit = permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("Event semaphore was closed");
+                let key = lane_key(&event);
+                match lanes.get(&key) {
+                    Some(lane) => {
+                        lane.events.lock().unwrap().push_back((event, permit));
+                        lane.pushed.notify_one();
+                    }
+                    None => {
+                        let lane = Arc::new(Lane {
+                            events: Mutex::new(VecDeque::from([(event, permit)])),
+                            pushed: Notify::new(),
+                        });
+                        lanes.insert(key.clone(), lane.clone());
+                        tasks.spawn(handle_lane(key, lane, batching.clone(), handler.clone()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Handles the events of a single lane until its queue is empty.
+async fn handle_lane<T, K, BK, B, H, Fut>(
+    key: K,
+    lane: LaneRef<T>,
+    batching: Arc<Batching<B>>,
+    handler: H,
+) -> K
+where
+    BK: PartialEq,
+    B: Fn(&T) -> Option<BK>,
+    H: Fn(Vec<T>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        let next = lane.events.lock().unwrap().pop_front();
+        let Some((event, permit)) = next else {
+            return key;
+        };
+        let (events, permits): (Vec<T>, Vec<OwnedSemaphorePermit>) = match (batching.key)(&event) {
+            Some(batch_key) => collect_batch(&lane, &batching, event, permit, batch_key).await,
+            None => (vec![event], vec![permit]),
+        }
+        .into_iter()
+        .unzip();
+        handler(events).await;
+        drop(permits);
+    }
+}
+
+/// Collects events that follow `event` in the lane and have the same batch key, until the batch
+/// is full, an event with a different key is encountered, or the batching window elapses.
+async fn collect_batch<T, BK, B>(
+    lane: &Lane<T>,
+    batching: &Batching<B>,
+    event: T,
+    permit: OwnedSemaphorePermit,
+    batch_key: BK,
+) -> Vec<(T, OwnedSemaphorePermit)>
+where
+    BK: PartialEq,
+    B: Fn(&T) -> Option<BK>,
+{
+    let deadline = Instant::now() + batching.window;
+    let mut batch = vec![(event, permit)];
+    while batch.len() < batching.max_size {
+        {
+            let mut events = lane.events.lock().unwrap();
+            while batch.len() < batching.max_size {
+                match events.front() {
+                    Some((next, _)) if (batching.key)(next).as_ref() == Some(&batch_key) => {
+                        batch.push(events.pop_front().unwrap());
+                    }
+                    // An event that cannot be batched has to be handled after the batch
+                    Some(_) => return batch,
+                    None => break,
+                }
+            }
+        }
+        if batch.len() >= batching.max_size
+            || tokio::time::timeout_at(deadline, lane.pushed.notified())
+                .await
+                .is_err()
+        {
+            break;
+        }
+    }
+    batch
+}
+
+fn unwrap_lane_result<K>(result: Result<K, JoinError>) -> K {
+    match result {
+        Ok(key) => key,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        Err(error) => panic!("Event lane was cancelled: {error:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+    use tokio::time::Instant;
+
+    use super::{Batching, dispatch_by_lane};
+
+    fn no_batching() -> Batching<fn(&(u32, u32)) -> Option<u32>> {
+        Batching {
+            key: |_| None,
+            max_size: 1,
+            window: Duration::ZERO,
+        }
+    }
+
+    fn channel_with(events: Vec<(u32, u32)>) -> mpsc::Receiver<(u32, u32)> {
+        let (tx, rx) = mpsc::channel(events.len().max(1));
+        for event in events {
+            tx.try_send(event).unwrap();
+        }
+        rx
+    }
+
+    /// Dispatches `events` (pairs of a lane and a sequence number) and returns the order in
+    /// which they were handled, along with the maximum number of events handled at once.
+    async fn dispatch(events: Vec<(u32, u32)>, max_parallel: usize) -> (Vec<(u32, u32)>, usize) {
+        let rx = channel_with(events);
+        let handled = Arc::new(Mutex::new(vec![]));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let handler = {
+            let handled = handled.clone();
+            let running = running.clone();
+            let max_running = max_running.clone();
+            move |events: Vec<(u32, u32)>| {
+                assert_eq!(events.len(), 1);
+                let (lane, seq) = events[0];
+                let handled = handled.clone();
+                let running = running.clone();
+                let max_running = max_running.clone();
+                async move {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    // Make the handling time differ between events, so that events of other
+                    // lanes overtake each other.
+                    let delay = u64::from((lane * 7 + seq * 3) % 5);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    handled.lock().unwrap().push((lane, seq));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        };
+        dispatch_by_lane(rx, max_parallel, |(lane, _)| *lane, no_batching(), handler).await;
+
+        let handled = handled.lock().unwrap().clone();
+        (handled, max_running.load(Ordering::SeqCst))
+    }
+
+    fn interleaved_events(lanes: u32, per_lane: u32) -> Vec<(u32, u32)> {
+        (0..per_lane)
+            .flat_map(|seq| (0..lanes).map(move |lane| (lane, seq)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn preserve_order_within_lane() {
+        let events = interleaved_events(5, 20);
+        let (handled, max_running) = dispatch(events.clone(), 4).await;
+        assert_eq!(handled.len(), events.len());
+        for lane in 0..5 {
+            let order: Vec<_> = handled
+                .iter()
+                .filter(|(l, _)| *l == lane)
+                .map(|(_, seq)| *seq)
+                .collect();
+            assert_eq!(order, (0..20).collect::<Vec<_>>());
+        }
+        assert!(max_running > 1, "lanes were not handled concurrently");
+        assert!(max_running <= 4, "more than 4 events were handled at once");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn preserve_order_within_lane_multi_thread() {
+        let (handled, _) = dispatch(interleaved_events(8, 25), 6).await;
+        for lane in 0..8 {
+            let order: Vec<_> = handled
+                .iter()
+                .filter(|(l, _)| *l == lane)
+                .map(|(_, seq)| *seq)
+                .collect();
+            assert_eq!(order, (0..25).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn single_slot_keeps_arrival_order() {
+        let events = interleaved_events(3, 5);
+        let (handled, max_running) = dispatch(events.clone(), 1).await;
+        assert_eq!(handled, events);
+        assert_eq!(max_running, 1);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "handler failed")]
+    async fn propagate_handler_panic() {
+        let (tx, rx) = mpsc::channel(1);
+        tx.send(1u32).await.unwrap();
+        drop(tx);
+        dispatch_by_lane(
+            rx,
+            2,
+            |event| *event,
+            Batching {
+                key: |_: &u32| None::<u32>,
+                max_size: 1,
+                window: Duration::ZERO,
+            },
+            |events: Vec<u32>| async move {
+                assert_ne!(events[0], 1, "handler failed");
+            },
+        )
+        .await;
+    }
+
+    /// Dispatches `events` (pairs of a lane and a sequence number) and returns the batches in
+    /// which they were handled. Events with an even sequence number can be batched together.
+    async fn dispatch_batches(
+        events: Vec<(u32, u32)>,
+        max_parallel: usize,
+        max_size: usize,
+    ) -> Vec<Vec<(u32, u32)>> {
+        let rx = channel_with(events);
+        let batches = Arc::new(Mutex::new(vec![]));
+        let handler = {
+            let batches = batches.clone();
+            move |events: Vec<(u32, u32)>| {
+                let batches = batches.clone();
+                async move {
+                    batches.lock().unwrap().push(events);
+                }
+            }
+        };
+        let batching = Batching {
+            key: |(lane, seq): &(u32, u32)| (seq % 2 == 0).then_some(*lane),
+            max_size,
+            window: Duration::from_millis(20),
+        };
+        dispatch_by_lane(rx, max_parallel, |(lane, _)| *lane, batching, handler).await;
+        batches.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn batch_consecutive_events() {
+        let events: Vec<_> = (0..10).map(|seq| (0, seq * 2)).collect();
+        let batches = dispatch_batches(events.clone(), 8, 4).await;
+        assert!(batches.len() < events.len(), "no events were batched");
+        assert!(batches.iter().all(|batch| batch.len() <= 4));
+        assert_eq!(batches.concat(), events);
+    }
+
+    #[tokio::test]
+    async fn batch_size_limited_by_max_parallel() {
+        let events: Vec<_> = (0..10).map(|seq| (0, seq * 2)).collect();
+        let batches = dispatch_batches(events.clone(), 1, 4).await;
+        assert!(batches.iter().all(|batch| batch.len() == 1));
+        assert_eq!(batches.concat(), events);
+    }
+
+    #[tokio::test]
+    async fn do_not_batch_across_other_events() {
+        let events = vec![(0, 0), (0, 2), (0, 1), (0, 4), (1, 0), (1, 2)];
+        let batches = dispatch_batches(events.clone(), 8, 8).await;
+        for batch in &batches {
+            assert!(batch.len() == 1 || batch.iter().all(|(_, seq)| seq % 2 == 0));
+            assert!(batch.iter().all(|(lane, _)| *lane == batch[0].0));
+        }
+        let lane_order = |lane: u32| -> Vec<_> {
+            batches
+                .concat()
+                .into_iter()
+                .filter(|(l, _)| *l == lane)
+                .collect()
+        };
+        assert_eq!(lane_order(0), vec![(0, 0), (0, 2), (0, 1), (0, 4)]);
+        assert_eq!(lane_order(1), vec![(1, 0), (1, 2)]);
+    }
+
+    #[tokio::test]
+    async fn batch_window_is_bounded() {
+        let start = Instant::now();
+        let batches = dispatch_batches(vec![(0, 0)], 8, 8).await;
+        assert_eq!(batches, vec![vec![(0, 0)]]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -5,7 +5,11 @@ pub enum LabelTrigger {
     Unapproved,
     TryBuildStarted,
     TryBuildSucceeded,
+    /// A try build has failed or timed out.
     TryBuildFailed,
+    AutoBuildStarted,
+    MergeSucceeded,
+    MergeFailed,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -7,16 +7,38 @@ use std::str::FromStr;
 use url::Url;
 
 pub mod api;
+mod dispatch;
 mod labels;
 pub mod server;
 mod webhook;
 
-pub use api::operations::MergeError;
+pub use api::operations::{BranchUpdateError, MergeError};
 pub use labels::{LabelModification, LabelTrigger};
-pub use webhook::WebhookSecret;
+pub use webhook::{DEFAULT_WEBHOOK_BODY_LIMIT, WebhookSecret};
 
 use crate::bors::PullRequestStatus;
 
+/// Conclusion of a check run created by bors.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckRunConclusion {
+    Success,
+    Neutral,
+    Failure,
+    Cancelled,
+    TimedOut,
+}
+
+/// State of a commit status set by bors.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitStatusState {
+    Pending,
+    Success,
+    Failure,
+    Error,
+}
+
 /// Unique identifier of a GitHub repository
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct GithubRepoName {
@@ -47,23 +69,84 @@ impl Display for GithubRepoName {
     }
 }
 
+/// Maximum length of a GitHub user or organization name.
+const MAX_OWNER_LENGTH: usize = 39;
+/// Maximum length of a GitHub repository name.
+const MAX_REPO_NAME_LENGTH: usize = 100;
+
 // This implementation should be kept in sync with the `Display`
 // implementation above.
 impl FromStr for GithubRepoName {
     type Err = String;
 
+    /// Parses a repository name in the `<owner>/<name>` format, checking that both parts only
+    /// contain characters that are allowed by GitHub.
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let mut parts = value.split('/');
-        let owner = parts
-            .next()
-            .ok_or_else(|| "GitHub repository name must not be empty".to_string())?;
-        let name = parts
-            .next()
-            .ok_or("GitHub repository name must be in the format `<owner>/<name>`")?;
+        let Some((owner, name)) = value.split_once('/') else {
+            return Err(format!(
+                "Invalid GitHub repository name `{value}`, it must be in the format `<owner>/<name>`"
+            ));
+        };
+        validate_owner(owner)
+            .map_err(|error| format!("Invalid owner of GitHub repository `{value}`: {error}"))?;
+        validate_repo_name(name)
+            .map_err(|error| format!("Invalid name of GitHub repository `{value}`: {error}"))?;
         Ok(Self::new(owner, name))
     }
 }
 
+/// User and organization names consist of alphanumeric characters and hyphens, and they cannot
+/// start with a hyphen.
+fn validate_owner(owner: &str) -> Result<(), String> {
+    if owner.is_empty() {
+        return Err("the owner is empty".to_string());
+    }
+    if owner.len() > MAX_OWNER_LENGTH {
+        return Err(format!(
+            "the owner is longer than {MAX_OWNER_LENGTH} characters"
+        ));
+    }
+    if owner.starts_with('-') {
+        return Err("the owner cannot start with a hyphen".to_string());
+    }
+    match owner
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-')
+    {
+        Some(c) => Err(format!("the owner contains an invalid character `{c}`")),
+        None => Ok(()),
+    }
+}
+
+/// Repository names consist of alphanumeric characters, hyphens, underscores and periods.
+fn validate_repo_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    if name.len() > MAX_REPO_NAME_LENGTH {
+        return Err(format!(
+            "the name is longer than {MAX_REPO_NAME_LENGTH} characters"
+        ));
+    }
+    if name == "." || name == ".." {
+        return Err(format!("`{name}` is a reserved name"));
+    }
+    match name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '-' | '_' | '.'))
+    {
+        Some(c) => Err(format!("the name contains an invalid character `{c}`")),
+        None => Ok(()),
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for GithubRepoName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct GithubUser {
     pub id: UserId,
@@ -81,9 +164,53 @@ impl From<octocrab::models::Author> for GithubUser {
     }
 }
 
+/// SHA of a git commit.
+/// SHAs received from GitHub are trusted and wrapped directly, SHAs entered by users should be
+/// validated by parsing them.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CommitSha(pub String);
 
+impl CommitSha {
+    /// Length of the abbreviated SHA shown in comments.
+    const SHORT_LENGTH: usize = 7;
+
+    /// Returns the abbreviated SHA, which is more readable in comments.
+    pub fn short(&self) -> &str {
+        self.0.get(..Self::SHORT_LENGTH).unwrap_or(&self.0)
+    }
+
+    /// Parses a full or an abbreviated hexadecimal commit SHA, which has to have at least as many
+    /// characters as the SHAs shown in comments.
+    /// The result might not identify a single commit, it has to be resolved by GitHub.
+    pub fn parse_abbreviated(value: &str) -> Result<Self, String> {
+        if !(Self::SHORT_LENGTH..=64).contains(&value.len()) {
+            return Err(format!(
+                "SHA must have between {} and 64 characters",
+                Self::SHORT_LENGTH
+            ));
+        }
+        if let Some(c) = value.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!("SHA contains an invalid character `{c}`"));
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl FromStr for CommitSha {
+    type Err = String;
+
+    /// Parses a full SHA-1 (40 characters) or SHA-256 (64 characters) hexadecimal commit SHA.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.len() != 40 && value.len() != 64 {
+            return Err("SHA must have exactly 40 or 64 characters".to_string());
+        }
+        if let Some(c) = value.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!("SHA contains an invalid character `{c}`"));
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
 impl From<String> for CommitSha {
     fn from(value: String) -> Self {
         Self(value)
@@ -118,6 +245,17 @@ pub struct PullRequest {
     pub message: String,
     pub author: GithubUser,
     pub status: PullRequestStatus,
+    /// Repository from which the PR originates. Differs from the base repository for PRs
+    /// from forks, and it is `None` if the head repository was deleted.
+    pub head_repository: Option<GithubRepoName>,
+}
+
+impl PullRequest {
+    /// Returns true if the PR does not originate from the given base repository.
+    /// PRs whose head repository was deleted are treated as coming from a fork.
+    pub fn is_fork(&self, base: &GithubRepoName) -> bool {
+        self.head_repository.as_ref() != Some(base)
+    }
 }
 
 impl From<octocrab::models::pulls::PullRequest> for PullRequest {
@@ -148,6 +286,10 @@ impl From<octocrab::models::pulls::PullRequest> for PullRequest {
             } else {
                 PullRequestStatus::Open
             },
+            head_repository: pr.head.repo.and_then(|repo| {
+                let owner = repo.owner?;
+                Some(GithubRepoName::new(&owner.login, &repo.name))
+            }),
         }
     }
 }
@@ -166,3 +308,119 @@ impl Display for PullRequestNumber {
         <u64 as Display>::fmt(&self.0, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::github::{CommitSha, GithubRepoName};
+
+    #[test]
+    fn parse_repo_name() {
+        let name: GithubRepoName = "Rust-Lang/rust.vim_2".parse().unwrap();
+        assert_eq!(name, GithubRepoName::new("rust-lang", "rust.vim_2"));
+        assert_eq!(name.to_string(), "rust-lang/rust.vim_2");
+    }
+
+    #[test]
+    fn parse_repo_name_missing_slash() {
+        assert_eq!(
+            "rust-lang".parse::<GithubRepoName>().unwrap_err(),
+            "Invalid GitHub repository name `rust-lang`, it must be in the format `<owner>/<name>`"
+        );
+    }
+
+    #[test]
+    fn parse_repo_name_empty_part() {
+        assert_eq!(
+            "/rust".parse::<GithubRepoName>().unwrap_err(),
+            "Invalid owner of GitHub repository `/rust`: the owner is empty"
+        );
+        assert_eq!(
+            "rust-lang/".parse::<GithubRepoName>().unwrap_err(),
+            "Invalid name of GitHub repository `rust-lang/`: the name is empty"
+        );
+    }
+
+    #[test]
+    fn parse_repo_name_illegal_characters() {
+        assert_eq!(
+            "rust_lang/rust".parse::<GithubRepoName>().unwrap_err(),
+            "Invalid owner of GitHub repository `rust_lang/rust`: the owner contains an invalid character `_`"
+        );
+        assert_eq!(
+            "rust-lang/rust/extra"
+                .parse::<GithubRepoName>()
+                .unwrap_err(),
+            "Invalid name of GitHub repository `rust-lang/rust/extra`: the name contains an invalid character `/`"
+        );
+        assert_eq!(
+            "rust-lang/ru st".parse::<GithubRepoName>().unwrap_err(),
+            "Invalid name of GitHub repository `rust-lang/ru st`: the name contains an invalid character ` `"
+        );
+        assert!("-rust/rust".parse::<GithubRepoName>().is_err());
+        assert!("rust-lang/..".parse::<GithubRepoName>().is_err());
+    }
+
+    #[test]
+    fn deserialize_repo_name() {
+        let name: GithubRepoName = serde_json::from_str(r#""rust-lang/rust""#).unwrap();
+        assert_eq!(name, GithubRepoName::new("rust-lang", "rust"));
+        assert!(serde_json::from_str::<GithubRepoName>(r#""rust""#).is_err());
+    }
+
+    #[test]
+    fn parse_commit_sha() {
+        let sha1 = "ea9c1b050cc8b420c2c211d2177811e564a4dc60";
+        assert_eq!(sha1.parse::<CommitSha>(), Ok(CommitSha(sha1.to_string())));
+        let sha256 = "a".repeat(64);
+        assert_eq!(sha256.parse::<CommitSha>(), Ok(CommitSha(sha256.clone())));
+    }
+
+    #[test]
+    fn parse_commit_sha_invalid_length() {
+        assert_eq!(
+            "ea9c1b0".parse::<CommitSha>().unwrap_err(),
+            "SHA must have exactly 40 or 64 characters"
+        );
+        assert!("a".repeat(41).parse::<CommitSha>().is_err());
+        assert!("".parse::<CommitSha>().is_err());
+    }
+
+    #[test]
+    fn parse_commit_sha_invalid_character() {
+        assert_eq!(
+            "ea9c1b050cc8b420c2c211d2177811e564a4dc6g"
+                .parse::<CommitSha>()
+                .unwrap_err(),
+            "SHA contains an invalid character `g`"
+        );
+    }
+
+    #[test]
+    fn parse_abbreviated_commit_sha() {
+        assert_eq!(
+            CommitSha::parse_abbreviated("ea9c1b0"),
+            Ok(CommitSha("ea9c1b0".to_string()))
+        );
+        let sha1 = "ea9c1b050cc8b420c2c211d2177811e564a4dc60";
+        assert_eq!(
+            CommitSha::parse_abbreviated(sha1),
+            Ok(CommitSha(sha1.to_string()))
+        );
+        assert_eq!(
+            CommitSha::parse_abbreviated("ea9c1b").unwrap_err(),
+            "SHA must have between 7 and 64 characters"
+        );
+        assert!(CommitSha::parse_abbreviated(&"a".repeat(65)).is_err());
+        assert_eq!(
+            CommitSha::parse_abbreviated("ea9c1bx").unwrap_err(),
+            "SHA contains an invalid character `x`"
+        );
+    }
+
+    #[test]
+    fn short_commit_sha() {
+        let sha = CommitSha("ea9c1b050cc8b420c2c211d2177811e564a4dc60".to_string());
+        assert_eq!(sha.short(), "ea9c1b0");
+        assert_eq!(CommitSha("abc".to_string()).short(), "abc");
+    }
+}
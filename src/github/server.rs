@@ -1,45 +1,140 @@
-use crate::bors::event::BorsEvent;
-use crate::bors::{BorsContext, handle_bors_global_event, handle_bors_repository_event};
-use crate::github::webhook::GitHubWebhook;
+use crate::bors::event::{BorsEvent, BorsRepositoryEvent, RepositoryEventDelivery};
+use crate::bors::{
+    BorsContext, RepositoryMap, handle_bors_global_event, handle_bors_repository_event,
+    handle_bors_workflows_completed, homu_queue, is_retryable_error,
+};
+use crate::database::{PgDbClient, QueuedEventModel, QueuedEventStatus, acquire_failure_count};
+use crate::github::GithubRepoName;
+use crate::github::api::client::failed_comment_count;
+use crate::github::api::retry::{retried_request_count, track_non_idempotent_requests};
+use crate::github::dispatch::{Batching, dispatch_by_lane};
 use crate::github::webhook::WebhookSecret;
-use crate::{BorsGlobalEvent, BorsRepositoryEvent, TeamApiClient};
+use crate::github::webhook::{GitHubWebhookDelivery, parse_webhook_payload};
+use crate::permissions::permission_cache_stats;
+use crate::{BorsGlobalEvent, PermissionSource};
 
 use anyhow::Error;
-use axum::Router;
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
 use octocrab::Octocrab;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
 use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
 use tower::limit::ConcurrencyLimitLayer;
 use tracing::{Instrument, Span};
 
+/// Maximum number of completed workflows of a single build that are handled at once.
+const WORKFLOW_BATCH_MAX_SIZE: usize = 32;
+/// How long to wait for further completed workflows of a build, before they are handled.
+const WORKFLOW_BATCH_WINDOW: Duration = Duration::from_millis(200);
+
+/// Maximum number of attempts to handle a repository event that fails with a retryable error,
+/// e.g. because GitHub was unavailable even after the failed request was retried.
+const EVENT_ATTEMPTS: u32 = 3;
+/// Delay before an event is handled again. It is doubled after each attempt.
+#[cfg(not(test))]
+const EVENT_RETRY_DELAY: Duration = Duration::from_secs(30);
+#[cfg(test)]
+const EVENT_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Response header of the queue page that tells if bors is paused on the repository.
+const PAUSED_HEADER: &str = "x-bors-paused";
+
+/// How long are handled events kept in the event queue.
+const PROCESSED_EVENT_RETENTION: chrono::Duration = chrono::Duration::days(7);
+
+/// Number of repository events whose handling was retried since the start of the process.
+static RETRIED_EVENTS: AtomicU64 = AtomicU64::new(0);
+/// Number of events whose handlers have failed since the start of the process.
+static FAILED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of repository events whose handling was retried since the start of the
+/// process.
+pub fn retried_event_count() -> u64 {
+    RETRIED_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of events whose handlers have failed since the start of the process.
+pub fn failed_event_count() -> u64 {
+    FAILED_EVENTS.load(Ordering::Relaxed)
+}
+
 /// Shared server state for all axum handlers.
 pub struct ServerState {
-    repository_event_queue: mpsc::Sender<BorsRepositoryEvent>,
+    repository_event_queue: mpsc::Sender<RepositoryEventDelivery>,
     global_event_queue: mpsc::Sender<BorsGlobalEvent>,
     webhook_secret: WebhookSecret,
+    /// Maximum size of a webhook body in bytes, larger webhooks are rejected.
+    webhook_body_limit: usize,
+    /// Bearer token that authenticates requests to the admin endpoints.
+    /// If it is not set, all admin requests are rejected.
+    admin_token: Option<SecretString>,
+    /// Database connection pool, used to check if the database is reachable and to serve
+    /// the queue.
+    db_pool: PgPool,
+    /// Is bors running in dry-run mode? It is reported by the health check.
+    dry_run: bool,
+    /// Repositories loaded by the bors process, their pause state is reported by the health
+    /// check.
+    repositories: RepositoryMap,
 }
 
 impl ServerState {
     pub fn new(
-        repository_event_queue: mpsc::Sender<BorsRepositoryEvent>,
+        repository_event_queue: mpsc::Sender<RepositoryEventDelivery>,
         global_event_queue: mpsc::Sender<BorsGlobalEvent>,
         webhook_secret: WebhookSecret,
+        webhook_body_limit: usize,
+        admin_token: Option<String>,
+        db_pool: PgPool,
+        dry_run: bool,
     ) -> Self {
         Self {
             repository_event_queue,
             global_event_queue,
             webhook_secret,
+            webhook_body_limit,
+            admin_token: admin_token.map(SecretString::from),
+            db_pool,
+            dry_run,
+            repositories: RepositoryMap::default(),
         }
     }
 
+    /// Shares the repositories of the bors process (see [BorsContext::repositories]) with the
+    /// server.
+    pub fn with_repositories(mut self, repositories: RepositoryMap) -> Self {
+        self.repositories = repositories;
+        self
+    }
+
     pub fn get_webhook_secret(&self) -> &WebhookSecret {
         &self.webhook_secret
     }
+
+    pub fn get_webhook_body_limit(&self) -> usize {
+        self.webhook_body_limit
+    }
+
+    /// Checks that the request contains the admin token in its `Authorization` header.
+    fn is_admin_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(admin_token) = &self.admin_token else {
+            return false;
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| token == admin_token.expose_secret())
+    }
 }
 
 pub type ServerStateRef = Arc<ServerState>;
@@ -48,54 +143,419 @@ pub fn create_app(state: ServerState) -> Router {
     Router::new()
         .route("/github", post(github_webhook_handler))
         .route("/health", get(health_handler))
+        .route("/ready", get(ready_handler))
+        .route("/queue/{owner}/{name}", get(queue_handler))
+        .route(
+            "/admin/repos/{owner}/{name}/reload",
+            post(admin_reload_repository_handler),
+        )
+        .route(
+            "/admin/repos/{owner}/{name}/sync",
+            post(admin_sync_repository_handler),
+        )
+        .route("/admin/events/dead", get(admin_dead_events_handler))
+        .route("/admin/events/{id}/retry", post(admin_retry_event_handler))
         .layer(ConcurrencyLimitLayer::new(100))
         .with_state(Arc::new(state))
 }
 
-async fn health_handler() -> impl IntoResponse {
-    (StatusCode::OK, "")
+/// Liveness check, it does not touch the database so that it keeps succeeding
+/// when the database is temporarily unavailable.
+/// The response also reports if bors is running in dry-run mode, how many GitHub requests
+/// had to be retried, how many comments could not be posted, how many events had to be retried
+/// or have failed, how effective the permission cache is, how busy the database pool is and
+/// which repositories are paused.
+async fn health_handler(State(state): State<ServerStateRef>) -> impl IntoResponse {
+    let (cache_hits, cache_misses) = permission_cache_stats();
+    let repositories: serde_json::Map<_, _> = state
+        .repositories
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, repo)| {
+            (
+                name.to_string(),
+                serde_json::json!({ "paused": repo.is_paused() }),
+            )
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "dry_run": state.dry_run,
+            "github_request_retries": retried_request_count(),
+            "failed_comments": failed_comment_count(),
+            "retried_events": retried_event_count(),
+            "failed_events": failed_event_count(),
+            "permission_cache_hits": cache_hits,
+            "permission_cache_misses": cache_misses,
+            "db_pool": db_pool_stats(&state.db_pool),
+            "repositories": repositories,
+        })),
+    )
+}
+
+/// Statistics of the database pool. sqlx does not expose the number of tasks waiting for
+/// a connection, so the number of operations that gave up waiting is reported instead.
+fn db_pool_stats(pool: &PgPool) -> serde_json::Value {
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+    serde_json::json!({
+        "size": size,
+        "idle": idle,
+        "in_use": size.saturating_sub(idle),
+        "max_connections": pool.options().get_max_connections(),
+        "acquire_failures": acquire_failure_count(),
+    })
+}
+
+/// Readiness check, it fails if the database is unreachable.
+async fn ready_handler(State(state): State<ServerStateRef>) -> impl IntoResponse {
+    match sqlx::query("SELECT 1").execute(&state.db_pool).await {
+        Ok(_) => (StatusCode::OK, ""),
+        Err(error) => {
+            tracing::warn!("Readiness check failed, database is unreachable: {error:?}");
+            (StatusCode::SERVICE_UNAVAILABLE, "")
+        }
+    }
+}
+
+/// Serves the open pull requests of a repository in the merge queue order, with the same
+/// fields as the queue of Homu, so that existing dashboards can read it.
+/// Whether bors is paused on the repository is reported in the [PAUSED_HEADER] header, so that
+/// the format of the body stays compatible with Homu.
+async fn queue_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+) -> Response {
+    let repository: GithubRepoName = match format!("{owner}/{name}").parse() {
+        Ok(repository) => repository,
+        Err(error) => return (StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    let db = PgDbClient::new(state.db_pool.clone());
+    match tokio::try_join!(
+        db.get_open_prs(&repository),
+        db.is_repository_paused(&repository)
+    ) {
+        Ok((prs, paused)) => (
+            StatusCode::OK,
+            [(PAUSED_HEADER, paused.to_string())],
+            Json(homu_queue(prs)),
+        )
+            .into_response(),
+        Err(error) => {
+            tracing::error!("Could not load the queue of {repository}: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
 }
 
 /// Axum handler that receives a webhook and sends it to a webhook channel.
+/// Repository events are stored in the event queue before the webhook is acknowledged, so that
+/// they are handled even if bors stops before handling them.
 pub async fn github_webhook_handler(
     State(state): State<ServerStateRef>,
-    GitHubWebhook(event): GitHubWebhook,
+    headers: HeaderMap,
+    GitHubWebhookDelivery { event, body }: GitHubWebhookDelivery,
 ) -> impl IntoResponse {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let delivery_id = header("x-github-delivery");
+    let event_type = header("x-github-event");
+    let span = webhook_span(&delivery_id, &event_type);
+
     match event {
         BorsEvent::Global(e) => match state.global_event_queue.send(e).await {
             Ok(_) => (StatusCode::OK, ""),
             Err(err) => {
-                tracing::error!("Could not send webhook global event: {err:?}");
+                span.in_scope(|| tracing::error!("Could not send webhook global event: {err:?}"));
                 (StatusCode::INTERNAL_SERVER_ERROR, "")
             }
         },
-        BorsEvent::Repository(e) => match state.repository_event_queue.send(e).await {
-            Ok(_) => (StatusCode::OK, ""),
-            Err(err) => {
-                tracing::error!("Could not send webhook repository event: {err:?}");
-                (StatusCode::INTERNAL_SERVER_ERROR, "")
+        BorsEvent::Repository(event) => {
+            record_event_span(&span, &event);
+            let db = PgDbClient::new(state.db_pool.clone());
+            // GitHub redelivers the webhook later if it is not acknowledged
+            let queue_id = match db
+                .insert_queued_event(event.repository(), &delivery_id, &event_type, &body)
+                .await
+            {
+                Ok(Some(id)) => id,
+                Ok(None) => {
+                    // The event is already handled (or will be) from the first delivery
+                    span.in_scope(|| tracing::info!("Ignoring a repeated webhook delivery"));
+                    return (StatusCode::OK, "");
+                }
+                Err(err) => {
+                    span.in_scope(|| tracing::error!("Could not store webhook event: {err:?}"));
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "");
+                }
+            };
+            let delivery = RepositoryEventDelivery {
+                event,
+                delivery_id,
+                event_type,
+                queue_id,
+                span: span.clone(),
+            };
+            match state.repository_event_queue.send(delivery).await {
+                Ok(_) => (StatusCode::OK, ""),
+                Err(err) => {
+                    span.in_scope(|| {
+                        tracing::error!("Could not send webhook repository event: {err:?}")
+                    });
+                    (StatusCode::INTERNAL_SERVER_ERROR, "")
+                }
             }
-        },
+        }
+    }
+}
+
+/// Axum handler that reloads the configuration and permissions of a single repository.
+async fn admin_reload_repository_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.is_admin_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let repository: GithubRepoName = match format!("{owner}/{name}").parse() {
+        Ok(repository) => repository,
+        Err(error) => return (StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let event = BorsGlobalEvent::ReloadRepository {
+        repository,
+        reply: reply_tx,
+    };
+    if let Err(err) = state.global_event_queue.send(event).await {
+        tracing::error!("Could not send repository reload event: {err:?}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    match reply_rx.await {
+        Ok(Some(Ok(summary))) => (StatusCode::OK, Json(summary)).into_response(),
+        Ok(Some(Err(error))) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Could not reload repository: {error}"),
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            tracing::error!("Repository reload was not handled: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Axum handler that backfills the open pull requests of a single repository into the database.
+async fn admin_sync_repository_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.is_admin_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let repository: GithubRepoName = match format!("{owner}/{name}").parse() {
+        Ok(repository) => repository,
+        Err(error) => return (StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let event = BorsGlobalEvent::SyncRepository {
+        repository,
+        reply: reply_tx,
+    };
+    if let Err(err) = state.global_event_queue.send(event).await {
+        tracing::error!("Could not send repository sync event: {err:?}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    match reply_rx.await {
+        Ok(Some(Ok(summary))) => (StatusCode::OK, Json(summary)).into_response(),
+        Ok(Some(Err(error))) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Could not sync repository: {error}"),
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            tracing::error!("Repository sync was not handled: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Creates the span of a webhook delivery. The repository and the PR of the event are recorded
+/// in it once the webhook is parsed.
+fn webhook_span(delivery_id: &str, event_type: &str) -> Span {
+    tracing::info_span!(
+        "Webhook",
+        delivery = delivery_id,
+        event = event_type,
+        repo = tracing::field::Empty,
+        pr = tracing::field::Empty,
+    )
+}
+
+fn record_event_span(span: &Span, event: &BorsRepositoryEvent) {
+    span.record("repo", event.repository().to_string());
+    if let Some(pr_number) = event.pr_number() {
+        span.record("pr", pr_number.0);
+    }
+}
+
+/// Parses a stored event again, so that it can be handled.
+fn queued_event_delivery(event: &QueuedEventModel) -> anyhow::Result<RepositoryEventDelivery> {
+    let Some(BorsEvent::Repository(parsed)) =
+        parse_webhook_payload(&event.event_type, &event.payload)?
+    else {
+        return Err(anyhow::anyhow!(
+            "Stored webhook does not contain a repository event"
+        ));
+    };
+    let span = webhook_span(&event.delivery_id, &event.event_type);
+    record_event_span(&span, &parsed);
+    Ok(RepositoryEventDelivery {
+        event: parsed,
+        delivery_id: event.delivery_id.clone(),
+        event_type: event.event_type.clone(),
+        queue_id: event.id,
+        span,
+    })
+}
+
+/// Axum handler that lists the stored events whose handling has failed.
+async fn admin_dead_events_handler(
+    State(state): State<ServerStateRef>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.is_admin_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let db = PgDbClient::new(state.db_pool.clone());
+    match db.get_queued_events(QueuedEventStatus::Dead).await {
+        Ok(events) => {
+            let events = events
+                .iter()
+                .map(|event| {
+                    serde_json::json!({
+                        "id": event.id,
+                        "repository": event.repository.to_string(),
+                        "delivery_id": event.delivery_id,
+                        "event_type": event.event_type,
+                        "attempts": event.attempts,
+                        "error": event.error,
+                        "created_at": event.created_at.to_rfc3339(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            (StatusCode::OK, Json(events)).into_response()
+        }
+        Err(error) => {
+            tracing::error!("Could not load dead events: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Axum handler that handles a dead event again.
+async fn admin_retry_event_handler(
+    State(state): State<ServerStateRef>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.is_admin_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let db = PgDbClient::new(state.db_pool.clone());
+    let event = match db.requeue_dead_event(id).await {
+        Ok(true) => db.get_queued_event(id).await,
+        Ok(false) => return StatusCode::NOT_FOUND.into_response(),
+        Err(error) => Err(error),
+    };
+    let event = match event {
+        Ok(Some(event)) => event,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!("Could not load event {id}: {error:?}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let delivery = match queued_event_delivery(&event) {
+        Ok(delivery) => delivery,
+        Err(error) => {
+            let error = format!("{error:#}");
+            if let Err(err) = db.mark_queued_events_dead(&[id], &error).await {
+                tracing::error!("Could not mark event {id} as dead: {err:?}");
+            }
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Cannot parse event: {error}"),
+            )
+                .into_response();
+        }
+    };
+    match state.repository_event_queue.send(delivery).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(err) => {
+            tracing::error!("Could not send retried event: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
     }
 }
 
 /// Creates a future with a Bors process that continuously receives webhook events and reacts to
 /// them.
+/// Events of a single PR (and events of a repository that do not concern any PR) are handled
+/// in the order in which they were received, while events of different PRs and repositories are
+/// handled concurrently. At most `max_parallel_events` repository events are handled (or waiting
+/// to be handled) at once.
+/// When the process starts, the stored events whose handling has not finished are replayed
+/// before any newly received event, see [replay_queued_events].
 pub fn create_bors_process(
     ctx: BorsContext,
     gh_client: Octocrab,
-    team_api: TeamApiClient,
+    permission_source: PermissionSource,
+    max_parallel_events: usize,
 ) -> (
-    mpsc::Sender<BorsRepositoryEvent>,
+    mpsc::Sender<RepositoryEventDelivery>,
     mpsc::Sender<BorsGlobalEvent>,
     impl Future<Output = ()>,
 ) {
-    let (repository_tx, repository_rx) = mpsc::channel::<BorsRepositoryEvent>(1024);
+    let (repository_tx, mut received_rx) = mpsc::channel::<RepositoryEventDelivery>(1024);
     let (global_tx, global_rx) = mpsc::channel::<BorsGlobalEvent>(1024);
 
+    let started_at = Utc::now();
     let service = async move {
         let ctx = Arc::new(ctx);
 
+        // The replayed events are handled before any event received by this process, which
+        // waits in the channel until the replay has finished
+        let (ordered_tx, repository_rx) = mpsc::channel::<RepositoryEventDelivery>(1024);
+        let db = ctx.db.clone();
+        tokio::spawn(async move {
+            replay_queued_events(db, ordered_tx.clone(), started_at).await;
+            while let Some(delivery) = received_rx.recv().await {
+                if ordered_tx.send(delivery).await.is_err() {
+                    return;
+                }
+            }
+        });
+
         // In tests, we shutdown these futures by dropping the channel sender,
         // In that case, we need to wait until both of these futures resolve,
         // to make sure that they are able to handle all the events in the queue
@@ -103,8 +563,8 @@ pub fn create_bors_process(
         #[cfg(test)]
         {
             tokio::join!(
-                consume_repository_events(ctx.clone(), repository_rx),
-                consume_global_events(ctx.clone(), global_rx, gh_client, team_api)
+                consume_repository_events(ctx.clone(), repository_rx, max_parallel_events),
+                consume_global_events(ctx.clone(), global_rx, gh_client, permission_source)
             );
         }
         // In real execution, the bot runs forever. If there is something that finishes
@@ -113,10 +573,10 @@ pub fn create_bors_process(
         #[cfg(not(test))]
         {
             tokio::select! {
-                _ = consume_repository_events(ctx.clone(), repository_rx) => {
+                _ = consume_repository_events(ctx.clone(), repository_rx, max_parallel_events) => {
                     tracing::error!("Repository event handling process has ended");
                 }
-                _ = consume_global_events(ctx.clone(), global_rx, gh_client, team_api) => {
+                _ = consume_global_events(ctx.clone(), global_rx, gh_client, permission_source) => {
                     tracing::error!("Global event handling process has ended");
                 }
             }
@@ -125,20 +585,209 @@ pub fn create_bors_process(
     (repository_tx, global_tx, service)
 }
 
+/// Sends the stored events whose handling has not finished (e.g. because bors was stopped while
+/// they were waiting to be handled) to the bors process again, in the order in which they were
+/// received. Only events stored before `started_at` are replayed, newer ones have been sent to
+/// the process by the webhook handler. They are only handled once the replayed events have been
+/// sent.
+///
+/// An event whose handling was already started [EVENT_ATTEMPTS] times is moved to the
+/// dead-letter state instead, so that an event that crashes bors is not replayed forever.
+async fn replay_queued_events(
+    db: Arc<PgDbClient>,
+    repository_tx: mpsc::Sender<RepositoryEventDelivery>,
+    started_at: DateTime<Utc>,
+) {
+    match db
+        .delete_processed_events(Utc::now() - PROCESSED_EVENT_RETENTION)
+        .await
+    {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Deleted {count} processed event(s) from the event queue"),
+        Err(error) => tracing::warn!("Cannot delete processed events: {error:?}"),
+    }
+
+    let mut events = match db.get_queued_events(QueuedEventStatus::Pending).await {
+        Ok(events) => events,
+        Err(error) => {
+            tracing::error!("Cannot load unprocessed events: {error:?}");
+            return;
+        }
+    };
+    events.retain(|event| event.created_at < started_at);
+    if !events.is_empty() {
+        tracing::info!("Replaying {} unprocessed event(s)", events.len());
+    }
+    for event in events {
+        let delivery = if event.attempts >= EVENT_ATTEMPTS as i32 {
+            Err(anyhow::anyhow!(
+                "Handling of the event was interrupted {} times",
+                event.attempts
+            ))
+        } else {
+            queued_event_delivery(&event)
+        };
+        match delivery {
+            Ok(delivery) => {
+                if repository_tx.send(delivery).await.is_err() {
+                    return;
+                }
+            }
+            Err(error) => {
+                tracing::error!("Cannot replay event {}: {error:?}", event.id);
+                mark_queued_events_dead(&db, &[event.id], &error).await;
+            }
+        }
+    }
+}
+
+/// Records that the handling of the given stored events has started.
+/// Failures to update the event queue are only logged, in the worst case the events are handled
+/// again after a restart.
+async fn start_queued_events_attempt(db: &PgDbClient, ids: &[i32]) {
+    if let Err(error) = db.start_queued_events_attempt(ids).await {
+        tracing::warn!("Cannot record attempt of events {ids:?}: {error:?}");
+    }
+}
+
+async fn mark_queued_events_processed(db: &PgDbClient, ids: &[i32]) {
+    if let Err(error) = db.mark_queued_events_processed(ids).await {
+        tracing::warn!("Cannot mark events {ids:?} as processed: {error:?}");
+    }
+}
+
+async fn mark_queued_events_dead(db: &PgDbClient, ids: &[i32], error: &Error) {
+    if let Err(db_error) = db.mark_queued_events_dead(ids, &format!("{error:#}")).await {
+        tracing::warn!("Cannot mark events {ids:?} as dead: {db_error:?}");
+    }
+}
+
 async fn consume_repository_events(
     ctx: Arc<BorsContext>,
-    mut repository_rx: mpsc::Receiver<BorsRepositoryEvent>,
+    repository_rx: mpsc::Receiver<RepositoryEventDelivery>,
+    max_parallel_events: usize,
 ) {
-    while let Some(event) = repository_rx.recv().await {
-        let ctx = ctx.clone();
+    dispatch_by_lane(
+        repository_rx,
+        max_parallel_events,
+        |delivery| {
+            (
+                delivery.event.repository().clone(),
+                delivery.event.pr_number().map(|pr| pr.0),
+            )
+        },
+        Batching {
+            // Bursts of completed workflows of a single build are handled at once
+            key: |delivery: &RepositoryEventDelivery| match &delivery.event {
+                BorsRepositoryEvent::WorkflowCompleted(payload) => {
+                    Some((payload.branch.clone(), payload.commit_sha.0.clone()))
+                }
+                _ => None,
+            },
+            max_size: WORKFLOW_BATCH_MAX_SIZE,
+            window: WORKFLOW_BATCH_WINDOW,
+        },
+        move |deliveries| handle_repository_events(ctx.clone(), deliveries),
+    )
+    .await;
+}
 
-        let span = tracing::info_span!("RepositoryEvent");
-        tracing::debug!("Received repository event: {event:#?}");
-        if let Err(error) = handle_bors_repository_event(event, ctx)
-            .instrument(span.clone())
-            .await
-        {
-            handle_root_error(span, error);
+async fn handle_repository_events(
+    ctx: Arc<BorsContext>,
+    mut deliveries: Vec<RepositoryEventDelivery>,
+) {
+    if deliveries.len() == 1 {
+        return handle_repository_event(ctx, deliveries.pop().unwrap()).await;
+    }
+
+    // Only completed workflows of a single build are batched, see `consume_repository_events`
+    let span = deliveries[0].span.clone();
+    let repository = deliveries[0].event.repository().clone();
+    let queue_ids = deliveries
+        .iter()
+        .map(|delivery| delivery.queue_id)
+        .collect::<Vec<_>>();
+    let delivery_ids = deliveries
+        .iter()
+        .map(|delivery| delivery.delivery_id.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let tags = [
+        ("repository", repository.to_string()),
+        ("event_type", "workflow_run".to_string()),
+        ("delivery_id", delivery_ids),
+    ];
+    let payloads = deliveries
+        .into_iter()
+        .filter_map(|delivery| match delivery.event {
+            BorsRepositoryEvent::WorkflowCompleted(payload) => Some(payload),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    span.in_scope(|| tracing::debug!("Received {} completed workflows", payloads.len()));
+    start_queued_events_attempt(&ctx.db, &queue_ids).await;
+    match handle_bors_workflows_completed(&repository, payloads, ctx.clone())
+        .instrument(span.clone())
+        .await
+    {
+        Ok(()) => mark_queued_events_processed(&ctx.db, &queue_ids).await,
+        Err(error) => {
+            mark_queued_events_dead(&ctx.db, &queue_ids, &error).await;
+            handle_root_error(span, error, &tags);
+        }
+    }
+}
+
+async fn handle_repository_event(ctx: Arc<BorsContext>, delivery: RepositoryEventDelivery) {
+    let RepositoryEventDelivery {
+        event,
+        delivery_id,
+        event_type,
+        queue_id,
+        span,
+    } = delivery;
+
+    let mut tags = vec![
+        ("repository", event.repository().to_string()),
+        ("event_type", event_type),
+        ("delivery_id", delivery_id),
+    ];
+    if let Some(pr_number) = event.pr_number() {
+        tags.push(("pr_number", pr_number.to_string()));
+    }
+
+    span.in_scope(|| tracing::debug!("Received repository event: {event:#?}"));
+    let mut delay = EVENT_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        start_queued_events_attempt(&ctx.db, &[queue_id]).await;
+        let (result, non_idempotent) = track_non_idempotent_requests(
+            handle_bors_repository_event(event.clone(), ctx.clone()).instrument(span.clone()),
+        )
+        .await;
+        match result {
+            Ok(()) => return mark_queued_events_processed(&ctx.db, &[queue_id]).await,
+            // Handling is only repeated if it has not done anything that cannot be repeated
+            // (e.g. posting a comment). The lane of the event is blocked while waiting, so that
+            // the events of a PR are still handled in order
+            Err(error)
+                if is_retryable_error(&error) && !non_idempotent && attempt < EVENT_ATTEMPTS =>
+            {
+                RETRIED_EVENTS.fetch_add(1, Ordering::Relaxed);
+                span.in_scope(|| {
+                    tracing::warn!(
+                        "Handling of event failed (attempt {attempt}/{EVENT_ATTEMPTS}), retrying in {delay:?}: {error:?}"
+                    )
+                });
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(error) => {
+                mark_queued_events_dead(&ctx.db, &[queue_id], &error).await;
+                return handle_root_error(span, error, &tags);
+            }
         }
     }
 }
@@ -147,24 +796,28 @@ async fn consume_global_events(
     ctx: Arc<BorsContext>,
     mut global_rx: mpsc::Receiver<BorsGlobalEvent>,
     gh_client: Octocrab,
-    team_api: TeamApiClient,
+    permission_source: PermissionSource,
 ) {
     while let Some(event) = global_rx.recv().await {
         let ctx = ctx.clone();
 
         let span = tracing::info_span!("GlobalEvent");
         tracing::debug!("Received global event: {event:#?}");
-        if let Err(error) = handle_bors_global_event(event, ctx, &gh_client, &team_api)
+        if let Err(error) = handle_bors_global_event(event, ctx, &gh_client, &permission_source)
             .instrument(span.clone())
             .await
         {
-            handle_root_error(span, error);
+            handle_root_error(span, error, &[("event_type", "global".to_string())]);
         }
     }
 }
 
+/// Logs and counts an error returned by an event handler.
+/// If the `sentry` feature is enabled, the error is also reported to Sentry along with the
+/// given tags, which describe the event that has failed.
 #[allow(unused_variables)]
-fn handle_root_error(span: Span, error: Error) {
+fn handle_root_error(span: Span, error: Error, tags: &[(&str, String)]) {
+    FAILED_EVENTS.fetch_add(1, Ordering::Relaxed);
     // In tests, we want to panic on all errors.
     #[cfg(test)]
     {
@@ -173,6 +826,492 @@ fn handle_root_error(span: Span, error: Error) {
     #[cfg(not(test))]
     {
         use crate::utils::logging::LogError;
+
+        #[cfg(feature = "sentry")]
+        crate::utils::sentry::report_error(&error, tags);
         span.log_error(error);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use http::{Request, StatusCode};
+    use sqlx::postgres::PgPoolOptions;
+    use tokio::sync::mpsc;
+    use tower::Service;
+
+    use crate::bors::PullRequestStatus;
+    use crate::database::QueuedEventStatus;
+    use crate::github::GithubRepoName;
+    use crate::github::server::{EVENT_ATTEMPTS, ServerState, create_app};
+    use crate::github::webhook::{DEFAULT_WEBHOOK_BODY_LIMIT, WebhookSecret};
+    use crate::tests::mocks::{
+        BorsBuilder, BorsTester, PullRequest, TEST_ADMIN_TOKEN, User, default_repo_name, run_test,
+    };
+    use crate::tests::webhook::{TEST_DELIVERY_ID, TEST_WEBHOOK_SECRET};
+
+    #[sqlx::test]
+    async fn admin_reload_missing_token(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester
+                .admin_reload_repository(default_repo_name(), None)
+                .await?;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_reload_wrong_token(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester
+                .admin_reload_repository(default_repo_name(), Some("wrong-token"))
+                .await?;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_reload_invalid_repo_name(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, body) = tester
+                .admin_reload_repository(
+                    GithubRepoName::new("foo_bar", "baz"),
+                    Some(TEST_ADMIN_TOKEN),
+                )
+                .await?;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            insta::assert_snapshot!(body, @"Invalid owner of GitHub repository `foo_bar/baz`: the owner contains an invalid character `_`");
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_reload_unknown_repo(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester
+                .admin_reload_repository(
+                    GithubRepoName::new("foo", "unknown"),
+                    Some(TEST_ADMIN_TOKEN),
+                )
+                .await?;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_reload_changed_config(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.default_repo().lock().config = "timeout = 7200".to_string();
+            let (status, body) = tester
+                .admin_reload_repository(default_repo_name(), Some(TEST_ADMIN_TOKEN))
+                .await?;
+            assert_eq!(status, StatusCode::OK);
+
+            let summary: serde_json::Value = serde_json::from_str(&body)?;
+            assert_ne!(summary["config_hash_before"], summary["config_hash_after"]);
+            assert_eq!(
+                summary["config_hash_after"],
+                "e768b62899268f94ffe43d1ff84abe29c6217b192e0fd1b99af5b0e67105633b"
+            );
+            assert_eq!(summary["reviewer_count_before"], 2);
+            assert_eq!(summary["reviewer_count_after"], 2);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_sync_unknown_repo(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester
+                .admin_sync_repository(
+                    GithubRepoName::new("foo", "unknown"),
+                    Some(TEST_ADMIN_TOKEN),
+                )
+                .await?;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_sync_missing_token(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester
+                .admin_sync_repository(default_repo_name(), None)
+                .await?;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_sync_backfills_open_prs(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let mut pr = PullRequest::new(default_repo_name(), 2, User::default_pr_author(), true);
+            pr.labels = vec!["T-compiler".to_string()];
+            tester.default_repo().lock().pull_requests.insert(2, pr);
+
+            let (status, body) = tester
+                .admin_sync_repository(default_repo_name(), Some(TEST_ADMIN_TOKEN))
+                .await?;
+            assert_eq!(status, StatusCode::OK);
+            insta::assert_snapshot!(body, @r#"{"created":2,"updated":0}"#);
+
+            let pr = tester.pr_db(default_repo_name(), 2).await?.unwrap();
+            assert_eq!(pr.pr_status, PullRequestStatus::Draft);
+            assert_eq!(pr.head_sha.as_deref(), Some("pr-2-sha"));
+            assert_eq!(pr.labels, vec!["T-compiler".to_string()]);
+
+            // Synchronization is idempotent
+            let (status, body) = tester
+                .admin_sync_repository(default_repo_name(), Some(TEST_ADMIN_TOKEN))
+                .await?;
+            assert_eq!(status, StatusCode::OK);
+            insta::assert_snapshot!(body, @r#"{"created":0,"updated":2}"#);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    /// Opens a new PR and returns the ID of its stored `opened` event, once it has been handled.
+    async fn open_pr_and_get_event_id(tester: &mut BorsTester) -> anyhow::Result<i32> {
+        tester.open_pr(default_repo_name(), false).await?;
+        processed_event_id(tester, "pull_request").await
+    }
+
+    /// Returns the ID of the stored event of the given type, once it has been handled.
+    async fn processed_event_id(tester: &BorsTester, event_type: &str) -> anyhow::Result<i32> {
+        let find_event = || async {
+            let events = tester
+                .db()
+                .get_queued_events(QueuedEventStatus::Processed)
+                .await?;
+            Ok::<_, anyhow::Error>(
+                events
+                    .iter()
+                    .find(|event| event.event_type == event_type)
+                    .map(|event| event.id),
+            )
+        };
+        tester
+            .wait_for(|| async { Ok(find_event().await?.is_some()) })
+            .await?;
+        Ok(find_event().await?.unwrap())
+    }
+
+    async fn wait_for_event_status(
+        tester: &BorsTester,
+        id: i32,
+        status: QueuedEventStatus,
+    ) -> anyhow::Result<()> {
+        tester
+            .wait_for(|| async {
+                let event = tester.db().get_queued_event(id).await?;
+                Ok(event.is_some_and(|event| event.status == status))
+            })
+            .await
+    }
+
+    #[sqlx::test]
+    async fn replay_stored_event_twice(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let id = open_pr_and_get_event_id(&mut tester).await?;
+            for _ in 0..2 {
+                tester
+                    .db()
+                    .mark_queued_events_dead(&[id], "Test failure")
+                    .await?;
+                let (status, _) = tester.admin_retry_event(id, Some(TEST_ADMIN_TOKEN)).await?;
+                assert_eq!(status, StatusCode::OK);
+                wait_for_event_status(&tester, id, QueuedEventStatus::Processed).await?;
+            }
+
+            let prs = tester.db().get_open_prs(&default_repo_name()).await?;
+            assert_eq!(prs.iter().filter(|pr| pr.number.0 == 2).count(), 1);
+            let event = tester.db().get_queued_event(id).await?.unwrap();
+            assert_eq!(event.attempts, 1);
+            assert_eq!(event.error, None);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn replay_comment_twice(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors ping").await?;
+            assert_eq!(tester.get_comment().await?, "Pong 🏓!");
+            let id = processed_event_id(&tester, "issue_comment").await?;
+            for _ in 0..2 {
+                tester
+                    .db()
+                    .mark_queued_events_dead(&[id], "Test failure")
+                    .await?;
+                let (status, _) = tester.admin_retry_event(id, Some(TEST_ADMIN_TOKEN)).await?;
+                assert_eq!(status, StatusCode::OK);
+                wait_for_event_status(&tester, id, QueuedEventStatus::Processed).await?;
+            }
+            // Returning here will make sure that ping was not answered again
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn replay_comment_skips_executed_commands(pool: sqlx::PgPool) {
+        run_test(pool.clone(), |mut tester| async move {
+            tester.post_comment("@bors ping\n@bors r+").await?;
+            tester.expect_comments(2).await;
+            let id = processed_event_id(&tester, "issue_comment").await?;
+            tester.post_comment("@bors r-").await?;
+            tester.expect_comments(1).await;
+
+            // Simulate that bors has stopped after executing the first command of the comment
+            sqlx::query("UPDATE processed_comment SET executed_commands = 1")
+                .execute(&pool)
+                .await?;
+            tester
+                .db()
+                .mark_queued_events_dead(&[id], "Test failure")
+                .await?;
+            let (status, _) = tester.admin_retry_event(id, Some(TEST_ADMIN_TOKEN)).await?;
+            assert_eq!(status, StatusCode::OK);
+            insta::assert_snapshot!(
+                tester.get_comment().await?,
+                @"Commit pr-1-sha has been approved by `default-user`"
+            );
+            // Returning here will make sure that ping was not answered again
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn replay_unprocessed_events_on_start(pool: sqlx::PgPool) {
+        let gh = run_test(pool.clone(), |mut tester| async {
+            open_pr_and_get_event_id(&mut tester).await?;
+            Ok(tester)
+        })
+        .await;
+
+        // Simulate that bors has stopped before the event was handled
+        sqlx::query("UPDATE event_queue SET status = 'pending', attempts = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM pull_request WHERE number = 2")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|tester| async {
+                tester
+                    .wait_for(|| async {
+                        Ok(tester.pr_db(default_repo_name(), 2).await?.is_some())
+                    })
+                    .await?;
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn dead_letter_repeatedly_interrupted_event(pool: sqlx::PgPool) {
+        let gh = run_test(pool.clone(), |mut tester| async {
+            open_pr_and_get_event_id(&mut tester).await?;
+            Ok(tester)
+        })
+        .await;
+
+        sqlx::query("UPDATE event_queue SET status = 'pending', attempts = $1")
+            .bind(EVENT_ATTEMPTS as i32)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        BorsBuilder::new(pool)
+            .github(gh)
+            .run_test(|mut tester| async {
+                let mut events = vec![];
+                while events.is_empty() {
+                    let (status, body) = tester.admin_dead_events(Some(TEST_ADMIN_TOKEN)).await?;
+                    assert_eq!(status, StatusCode::OK);
+                    events = serde_json::from_str::<Vec<serde_json::Value>>(&body)?;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                assert_eq!(events.len(), 1);
+                assert_eq!(events[0]["event_type"], "pull_request");
+                assert_eq!(events[0]["repository"], default_repo_name().to_string());
+                insta::assert_snapshot!(
+                    events[0]["error"].as_str().unwrap(),
+                    @"Handling of the event was interrupted 3 times"
+                );
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_dead_events_missing_token(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester.admin_dead_events(None).await?;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_retry_unknown_event(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester
+                .admin_retry_event(1000, Some(TEST_ADMIN_TOKEN))
+                .await?;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn admin_retry_processed_event(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let id = open_pr_and_get_event_id(&mut tester).await?;
+            let (status, _) = tester.admin_retry_event(id, Some(TEST_ADMIN_TOKEN)).await?;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[tracing_test::traced_test]
+    #[sqlx::test]
+    async fn webhook_delivery_span(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            tester.post_comment("@bors ping").await?;
+            tester.expect_comments(1).await;
+            Ok(tester)
+        })
+        .await;
+
+        logs_assert(|lines| {
+            let line = lines
+                .iter()
+                .find(|line| line.contains("Commands:"))
+                .ok_or("command handler did not log anything")?;
+            for field in [
+                format!("delivery={TEST_DELIVERY_ID}-"),
+                "event=issue_comment".to_string(),
+                format!("repo={}", default_repo_name()),
+                "pr=1".to_string(),
+            ] {
+                if !line.contains(&field) {
+                    return Err(format!("`{field}` not found in `{line}`"));
+                }
+            }
+            Ok(())
+        });
+    }
+
+    #[sqlx::test]
+    async fn health(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, body) = tester.get_endpoint("/health").await?;
+            assert_eq!(status, StatusCode::OK);
+            let health: serde_json::Value = serde_json::from_str(&body)?;
+            assert_eq!(health["dry_run"], false);
+            assert!(health["github_request_retries"].is_u64());
+            assert!(health["permission_cache_hits"].is_u64());
+            assert!(health["permission_cache_misses"].is_u64());
+            assert_eq!(
+                health["repositories"]["rust-lang/borstest"]["paused"],
+                false
+            );
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn health_db_pool(pool: sqlx::PgPool) {
+        let max_connections = pool.options().get_max_connections();
+        run_test(pool, |mut tester| async {
+            let (status, body) = tester.get_endpoint("/health").await?;
+            assert_eq!(status, StatusCode::OK);
+            let health: serde_json::Value = serde_json::from_str(&body)?;
+            let stats = &health["db_pool"];
+            let size = stats["size"].as_u64().unwrap();
+            let idle = stats["idle"].as_u64().unwrap();
+            assert!(idle <= size);
+            assert_eq!(stats["in_use"].as_u64().unwrap(), size - idle);
+            assert_eq!(stats["max_connections"], max_connections);
+            assert!(stats["acquire_failures"].is_u64());
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[sqlx::test]
+    async fn health_dry_run(pool: sqlx::PgPool) {
+        BorsBuilder::new(pool)
+            .dry_run()
+            .run_test(|mut tester| async {
+                let (status, body) = tester.get_endpoint("/health").await?;
+                assert_eq!(status, StatusCode::OK);
+                let health: serde_json::Value = serde_json::from_str(&body)?;
+                assert_eq!(health["dry_run"], true);
+                Ok(tester)
+            })
+            .await;
+    }
+
+    #[sqlx::test]
+    async fn ready(pool: sqlx::PgPool) {
+        run_test(pool, |mut tester| async {
+            let (status, _) = tester.get_endpoint("/ready").await?;
+            assert_eq!(status, StatusCode::OK);
+            Ok(tester)
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn ready_database_unreachable() {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(500))
+            .connect_lazy("postgres://bors@127.0.0.1:1/bors")
+            .unwrap();
+        let (repository_tx, _) = mpsc::channel(1);
+        let (global_tx, _) = mpsc::channel(1);
+        let mut app = create_app(ServerState::new(
+            repository_tx,
+            global_tx,
+            WebhookSecret::new(TEST_WEBHOOK_SECRET.to_string()),
+            DEFAULT_WEBHOOK_BODY_LIMIT,
+            None,
+            pool,
+            false,
+        ));
+
+        let request = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+        let response = app.call(request("/ready")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = app.call(request("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
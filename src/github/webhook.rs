@@ -1,27 +1,28 @@
 //! This module handles parsing webhooks and generating [`BorsEvent`]s from them.
 use std::fmt::Debug;
 
-use axum::RequestExt;
 use axum::body::Bytes;
 use axum::extract::FromRequest;
 use axum::http::request::Parts;
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
 use hmac::{Hmac, Mac};
+use http_body_util::LengthLimitError;
 use octocrab::models::events::payload::{
     IssueCommentEventAction, IssueCommentEventPayload, PullRequestEventChangesFrom,
     PullRequestReviewCommentEventAction, PullRequestReviewCommentEventPayload,
 };
-use octocrab::models::pulls::{PullRequest, Review};
+use octocrab::models::pulls::{PullRequest, Review, ReviewState};
 use octocrab::models::webhook_events::payload::PullRequestWebhookEventAction;
 use octocrab::models::{App, Author, CheckRun, Repository, RunId, workflows};
 use secrecy::{ExposeSecret, SecretString};
 use sha2::Sha256;
 
 use crate::bors::event::{
-    BorsEvent, BorsGlobalEvent, BorsRepositoryEvent, CheckSuiteCompleted, PullRequestClosed,
-    PullRequestComment, PullRequestConvertedToDraft, PullRequestEdited, PullRequestMerged,
-    PullRequestOpened, PullRequestPushed, PullRequestReadyForReview, PullRequestReopened,
-    PushToBranch, WorkflowCompleted, WorkflowStarted,
+    BorsEvent, BorsGlobalEvent, BorsRepositoryEvent, CheckRunCompleted, CheckSuiteCompleted,
+    PullRequestClosed, PullRequestComment, PullRequestCommentEdited, PullRequestConvertedToDraft,
+    PullRequestEdited, PullRequestLabeled, PullRequestMerged, PullRequestOpened, PullRequestPushed,
+    PullRequestReadyForReview, PullRequestReopened, PullRequestReviewed, PullRequestUnlabeled,
+    PushToBranch, ReviewVerdict, WorkflowCompleted, WorkflowStarted,
 };
 use crate::database::{WorkflowStatus, WorkflowType};
 use crate::github::server::ServerStateRef;
@@ -46,6 +47,19 @@ pub struct WebhookPushToBranchEvent {
     repository: Repository,
     #[serde(rename = "ref")]
     ref_field: String,
+    after: String,
+    #[serde(default)]
+    commits: Vec<WebhookPushCommit>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookPushCommit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
 }
 
 /// This struct is used to extract the repository and user from a GitHub webhook event.
@@ -62,6 +76,75 @@ pub struct WebhookWorkflowRun<'a> {
     repository: Repository,
 }
 
+/// The attempt of a workflow run, which is not exposed by `workflows::Run`.
+#[derive(serde::Deserialize, Debug)]
+struct WebhookWorkflowRunAttempt {
+    workflow_run: WorkflowRunAttempt,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WorkflowRunAttempt {
+    #[serde(default = "default_run_attempt")]
+    run_attempt: u32,
+}
+
+fn default_run_attempt() -> u32 {
+    1
+}
+
+/// Payload of the `repository` webhook, only the `renamed` and `transferred` actions are used.
+#[derive(serde::Deserialize, Debug)]
+struct WebhookRepositoryEvent {
+    action: String,
+    repository: Repository,
+    changes: Option<WebhookRepositoryChanges>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookRepositoryChanges {
+    repository: Option<WebhookRepositoryNameChange>,
+    owner: Option<WebhookRepositoryOwnerChange>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookRepositoryNameChange {
+    name: WebhookChangedValue<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookRepositoryOwnerChange {
+    from: WebhookPreviousOwner,
+}
+
+/// Changes of an edited issue comment.
+#[derive(serde::Deserialize, Debug)]
+struct WebhookIssueCommentEdit {
+    changes: Option<WebhookIssueCommentChanges>,
+    sender: Author,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookIssueCommentChanges {
+    body: Option<WebhookChangedValue<String>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookChangedValue<T> {
+    from: T,
+}
+
+/// The previous owner is either a user or an organization.
+#[derive(serde::Deserialize, Debug)]
+struct WebhookPreviousOwner {
+    user: Option<WebhookAccount>,
+    organization: Option<WebhookAccount>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WebhookAccount {
+    login: String,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct CheckRunInner {
     #[serde(flatten)]
@@ -107,9 +190,16 @@ struct WebhookPullRequestEvent {
     action: PullRequestWebhookEventAction,
     pull_request: PullRequest,
     changes: Option<WebhookPullRequestChanges>,
+    /// The added or removed label, for the `labeled` and `unlabeled` actions.
+    label: Option<WebhookLabel>,
     repository: Repository,
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct WebhookLabel {
+    name: String,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct WebhookPullRequestChanges {
     base: Option<WebhookPullRequestBaseChanges>,
@@ -124,7 +214,17 @@ struct WebhookPullRequestBaseChanges {
 #[derive(Debug)]
 pub struct GitHubWebhook(pub BorsEvent);
 
-const REQUEST_BODY_LIMIT: usize = 10 * 1024 * 1024;
+/// axum extractor for GitHub webhook events, along with the body of the webhook.
+/// The body is stored in the event queue, so that the event can be parsed again if it has to be
+/// replayed.
+#[derive(Debug)]
+pub struct GitHubWebhookDelivery {
+    pub event: BorsEvent,
+    pub body: Bytes,
+}
+
+/// Default maximum size of a webhook body, GitHub caps webhook payloads at 25 MB.
+pub const DEFAULT_WEBHOOK_BODY_LIMIT: usize = 25 * 1024 * 1024;
 
 /// Extracts a webhook event from a HTTP request.
 impl FromRequest<ServerStateRef> for GitHubWebhook {
@@ -134,14 +234,48 @@ impl FromRequest<ServerStateRef> for GitHubWebhook {
         request: axum::extract::Request,
         state: &ServerStateRef,
     ) -> Result<Self, Self::Rejection> {
-        let (parts, body) = request.with_limited_body().into_parts();
+        let delivery = GitHubWebhookDelivery::from_request(request, state).await?;
+        Ok(GitHubWebhook(delivery.event))
+    }
+}
+
+/// Extracts a webhook event and the body of the webhook from a HTTP request.
+impl FromRequest<ServerStateRef> for GitHubWebhookDelivery {
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        request: axum::extract::Request,
+        state: &ServerStateRef,
+    ) -> Result<Self, Self::Rejection> {
+        let (parts, body) = request.into_parts();
+        let body_limit = state.get_webhook_body_limit();
+
+        // Reject oversized requests before reading their body, if they announce their size
+        let content_length = parts
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if content_length.is_some_and(|length| length > body_limit) {
+            tracing::error!(
+                "Webhook body is too large ({} B, limit is {body_limit} B)",
+                content_length.unwrap_or_default()
+            );
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
 
         // Eagerly load body
-        let body: Bytes = axum::body::to_bytes(body, REQUEST_BODY_LIMIT)
+        let body: Bytes = axum::body::to_bytes(body, body_limit)
             .await
             .map_err(|error| {
-                tracing::error!("Parsing webhook body failed: {error:?}");
-                StatusCode::BAD_REQUEST
+                let error = error.into_inner();
+                if error.is::<LengthLimitError>() {
+                    tracing::error!("Webhook body is larger than the limit of {body_limit} B");
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else {
+                    tracing::error!("Parsing webhook body failed: {error:?}");
+                    StatusCode::BAD_REQUEST
+                }
             })?;
 
         // Verify that the request is valid
@@ -154,7 +288,7 @@ impl FromRequest<ServerStateRef> for GitHubWebhook {
         match parse_webhook_event(parts, &body) {
             Ok(Some(event)) => {
                 tracing::trace!("Received webhook event {event:?}");
-                Ok(GitHubWebhook(event))
+                Ok(GitHubWebhookDelivery { event, body })
             }
             Ok(None) => Err(StatusCode::OK),
             Err(error) => {
@@ -169,10 +303,17 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
     let Some(event_type) = request.headers.get("x-github-event") else {
         return Err(anyhow::anyhow!("x-github-event header not found"));
     };
+    parse_webhook_payload(event_type.to_str().unwrap_or_default(), body)
+}
 
+/// Parses the body of a webhook of the given event type (the `X-GitHub-Event` header).
+/// Returns `None` if the webhook does not produce any event.
+pub(crate) fn parse_webhook_payload(
+    event_type: &str,
+    body: &[u8],
+) -> anyhow::Result<Option<BorsEvent>> {
     tracing::trace!(
-        "Webhook: event_type `{}`, payload\n{}",
-        event_type.to_str().unwrap_or_default(),
+        "Webhook: event_type `{event_type}`, payload\n{}",
         std::str::from_utf8(body).unwrap_or_default()
     );
 
@@ -188,8 +329,9 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
         b"workflow_run" => parse_workflow_run_events(body),
         b"check_run" => parse_check_run_events(body),
         b"check_suite" => parse_check_suite_events(body),
+        b"repository" => parse_repository_events(body),
         _ => {
-            tracing::debug!("Ignoring unknown event type {:?}", event_type.to_str());
+            tracing::debug!("Ignoring unknown event type {event_type:?}");
             Ok(None)
         }
     }
@@ -205,8 +347,29 @@ fn parse_push_event(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
         return Ok(None);
     };
 
+    let is_default_branch = payload.repository.default_branch.as_deref() == Some(branch.as_str());
+    let mut changed_files: Vec<String> = payload
+        .commits
+        .into_iter()
+        .flat_map(|commit| {
+            commit
+                .added
+                .into_iter()
+                .chain(commit.removed)
+                .chain(commit.modified)
+        })
+        .collect();
+    changed_files.sort();
+    changed_files.dedup();
+
     Ok(Some(BorsEvent::Repository(
-        BorsRepositoryEvent::PushToBranch(PushToBranch { repository, branch }),
+        BorsRepositoryEvent::PushToBranch(PushToBranch {
+            repository,
+            branch,
+            is_default_branch,
+            commit_sha: CommitSha(payload.after),
+            changed_files,
+        }),
     )))
 }
 
@@ -215,13 +378,44 @@ fn parse_issue_comment_event(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     let repository_name = parse_repository_name(&repository.repository)?;
 
     let event: IssueCommentEventPayload = serde_json::from_slice(body)?;
-    if event.action == IssueCommentEventAction::Created {
-        let comment = parse_pr_comment(repository_name, event)
-            .map(BorsRepositoryEvent::Comment)
-            .map(BorsEvent::Repository);
-        Ok(comment)
-    } else {
-        Ok(None)
+    match event.action {
+        IssueCommentEventAction::Created => {
+            let comment = parse_pr_comment(repository_name, event)
+                .map(BorsRepositoryEvent::Comment)
+                .map(BorsEvent::Repository);
+            Ok(comment)
+        }
+        IssueCommentEventAction::Edited => {
+            // Only edits of the text can change the commands of the comment
+            let edit: WebhookIssueCommentEdit = serde_json::from_slice(body)?;
+            let Some(previous_text) = edit
+                .changes
+                .and_then(|changes| changes.body)
+                .map(|body| body.from)
+            else {
+                return Ok(None);
+            };
+            // Commands are executed with the permissions of the comment author, so edits
+            // made by anyone else (e.g. a repository admin) must not trigger them
+            if edit.sender.id != event.comment.user.id {
+                tracing::debug!(
+                    "Ignoring edit of comment {} by {}, who is not its author",
+                    event.comment.id,
+                    edit.sender.login
+                );
+                return Ok(None);
+            }
+            let comment = parse_pr_comment(repository_name, event)
+                .map(|comment| {
+                    BorsRepositoryEvent::CommentEdited(PullRequestCommentEdited {
+                        comment,
+                        previous_text,
+                    })
+                })
+                .map(BorsEvent::Repository);
+            Ok(comment)
+        }
+        _ => Ok(None),
     }
 }
 
@@ -290,20 +484,60 @@ fn parse_pull_request_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
                 pull_request: payload.pull_request.into(),
             }),
         ))),
+        PullRequestWebhookEventAction::Labeled | PullRequestWebhookEventAction::Unlabeled => {
+            let Some(label) = payload.label else {
+                return Err(anyhow::anyhow!(
+                    "Labeled pull request event should have `label` field"
+                ));
+            };
+            let event = if matches!(payload.action, PullRequestWebhookEventAction::Labeled) {
+                BorsRepositoryEvent::PullRequestLabeled(PullRequestLabeled {
+                    repository: repository_name,
+                    pull_request: payload.pull_request.into(),
+                    label: label.name,
+                })
+            } else {
+                BorsRepositoryEvent::PullRequestUnlabeled(PullRequestUnlabeled {
+                    repository: repository_name,
+                    pull_request: payload.pull_request.into(),
+                    label: label.name,
+                })
+            };
+            Ok(Some(BorsEvent::Repository(event)))
+        }
         _ => Ok(None),
     }
 }
 
 fn parse_pull_request_review_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     let payload: WebhookPullRequestReviewEvent = serde_json::from_slice(body)?;
-    if payload.action == "submitted" {
+    if payload.action != "submitted" {
+        return Ok(None);
+    }
+    let verdict = match payload.review.state {
+        Some(ReviewState::Approved) => Some(ReviewVerdict::Approved),
+        Some(ReviewState::ChangesRequested) => Some(ReviewVerdict::ChangesRequested),
+        _ => None,
+    };
+    let Some(verdict) = verdict else {
         let comment = parse_comment_from_pr_review(payload)?;
-        Ok(Some(BorsEvent::Repository(BorsRepositoryEvent::Comment(
+        return Ok(Some(BorsEvent::Repository(BorsRepositoryEvent::Comment(
             comment,
-        ))))
-    } else {
-        Ok(None)
-    }
+        ))));
+    };
+
+    let pull_request = payload.pull_request.clone().into();
+    let commit_sha = payload.review.commit_id.clone().map(CommitSha);
+    let comment = parse_comment_from_pr_review(payload)?;
+    Ok(Some(BorsEvent::Repository(
+        BorsRepositoryEvent::PullRequestReviewed(PullRequestReviewed {
+            repository: comment.repository.clone(),
+            pull_request,
+            verdict,
+            commit_sha,
+            comment,
+        }),
+    )))
 }
 fn parse_pull_request_review_comment_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     let repository: WebhookRepository = serde_json::from_slice(body)?;
@@ -322,6 +556,9 @@ fn parse_pull_request_review_comment_events(body: &[u8]) -> anyhow::Result<Optio
 
 fn parse_workflow_run_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     let payload: WebhookWorkflowRun = serde_json::from_slice(body)?;
+    let run_attempt = serde_json::from_slice::<WebhookWorkflowRunAttempt>(body)?
+        .workflow_run
+        .run_attempt;
     let repository_name = parse_repository_name(&payload.repository)?;
     let result = match payload.action {
         "requested" => Some(BorsEvent::Repository(BorsRepositoryEvent::WorkflowStarted(
@@ -331,6 +568,7 @@ fn parse_workflow_run_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
                 branch: payload.workflow_run.head_branch,
                 commit_sha: CommitSha(payload.workflow_run.head_sha),
                 run_id: RunId(payload.workflow_run.id.0),
+                run_attempt,
                 workflow_type: WorkflowType::Github,
                 url: payload.workflow_run.html_url.into(),
             },
@@ -350,11 +588,11 @@ fn parse_workflow_run_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
                     branch: payload.workflow_run.head_branch,
                     commit_sha: CommitSha(payload.workflow_run.head_sha),
                     run_id: RunId(payload.workflow_run.id.0),
+                    run_attempt,
                     running_time,
-                    status: match payload.workflow_run.conclusion.unwrap_or_default().as_str() {
-                        "success" => WorkflowStatus::Success,
-                        _ => WorkflowStatus::Failure,
-                    },
+                    status: parse_workflow_conclusion(
+                        &payload.workflow_run.conclusion.unwrap_or_default(),
+                    ),
                 }),
             ))
         }
@@ -373,22 +611,46 @@ fn parse_check_run_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     }
 
     let repository_name = parse_repository_name(&payload.repository)?;
-    if payload.action == "created" {
-        Ok(Some(BorsEvent::Repository(
+    let run_id = RunId(payload.check_run.check_run.id.map(|v| v.0).unwrap_or(0));
+    match payload.action {
+        "created" => Ok(Some(BorsEvent::Repository(
             BorsRepositoryEvent::WorkflowStarted(WorkflowStarted {
                 repository: repository_name,
                 name: payload.check_run.name.to_string(),
                 branch: payload.check_run.check_suite.head_branch,
                 commit_sha: CommitSha(payload.check_run.check_suite.head_sha),
-                run_id: RunId(payload.check_run.check_run.id.map(|v| v.0).unwrap_or(0)),
+                run_id,
+                run_attempt: 1,
                 workflow_type: WorkflowType::External,
                 url: payload.check_run.check_run.html_url.unwrap_or_default(),
             }),
-        )))
-    } else {
-        Ok(None)
+        ))),
+        "completed" => Ok(Some(BorsEvent::Repository(
+            BorsRepositoryEvent::CheckRunCompleted(CheckRunCompleted {
+                repository: repository_name,
+                name: payload.check_run.name.to_string(),
+                branch: payload.check_run.check_suite.head_branch,
+                commit_sha: CommitSha(payload.check_run.check_suite.head_sha),
+                run_id,
+                url: payload.check_run.check_run.html_url.unwrap_or_default(),
+                status: parse_workflow_conclusion(
+                    &payload.check_run.check_run.conclusion.unwrap_or_default(),
+                ),
+            }),
+        ))),
+        _ => Ok(None),
+    }
+}
+/// Maps the conclusion of a finished workflow run or check run to a workflow status.
+fn parse_workflow_conclusion(conclusion: &str) -> WorkflowStatus {
+    match conclusion {
+        "success" => WorkflowStatus::Success,
+        "skipped" | "neutral" => WorkflowStatus::Skipped,
+        "action_required" => WorkflowStatus::ActionRequired,
+        _ => WorkflowStatus::Failure,
     }
 }
+
 fn parse_check_suite_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     let payload: WebhookCheckSuite = serde_json::from_slice(body)?;
     let repository_name = parse_repository_name(&payload.repository)?;
@@ -405,6 +667,38 @@ fn parse_check_suite_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     }
 }
 
+fn parse_repository_events(body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+    let payload: WebhookRepositoryEvent = serde_json::from_slice(body)?;
+    let new_name = parse_repository_name(&payload.repository)?;
+    let old_name = match (payload.action.as_str(), payload.changes) {
+        (
+            "renamed",
+            Some(WebhookRepositoryChanges {
+                repository: Some(changes),
+                ..
+            }),
+        ) => GithubRepoName::new(new_name.owner(), &changes.name.from),
+        (
+            "transferred",
+            Some(WebhookRepositoryChanges {
+                owner: Some(changes),
+                ..
+            }),
+        ) => {
+            let Some(owner) = changes.from.organization.or(changes.from.user) else {
+                return Err(anyhow::anyhow!(
+                    "Previous owner of transferred repository {new_name} is missing"
+                ));
+            };
+            GithubRepoName::new(&owner.login, new_name.name())
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(BorsEvent::Global(
+        BorsGlobalEvent::RepositoryRenamed { old_name, new_name },
+    )))
+}
+
 fn parse_pr_review_comment(
     repo: GithubRepoName,
     payload: PullRequestReviewCommentEventPayload,
@@ -416,6 +710,8 @@ fn parse_pr_review_comment(
         pr_number: PullRequestNumber(payload.pull_request.number),
         text: payload.comment.body.unwrap_or_default(),
         html_url: payload.comment.html_url.to_string(),
+        comment_id: None,
+        created_at: Some(payload.comment.created_at),
     }
 }
 
@@ -431,6 +727,8 @@ fn parse_comment_from_pr_review(
         pr_number: PullRequestNumber(payload.pull_request.number),
         text: payload.review.body.unwrap_or_default(),
         html_url: payload.review.html_url.to_string(),
+        comment_id: None,
+        created_at: payload.review.submitted_at,
     })
 }
 
@@ -450,6 +748,8 @@ fn parse_pr_comment(
         text: payload.comment.body.unwrap_or_default(),
         pr_number: PullRequestNumber(payload.issue.number),
         html_url: payload.comment.html_url.to_string(),
+        comment_id: Some(payload.comment.id),
+        created_at: Some(payload.comment.created_at),
     })
 }
 
@@ -487,14 +787,18 @@ fn verify_gh_signature(
 
 #[cfg(test)]
 mod tests {
+    use axum::body::Body;
     use axum::extract::FromRequest;
+    use axum::http::{Request, header};
     use hyper::StatusCode;
+    use sqlx::PgPool;
     use tokio::sync::mpsc;
 
-    use crate::bors::event::{BorsEvent, BorsGlobalEvent};
+    use crate::bors::event::{BorsEvent, BorsGlobalEvent, BorsRepositoryEvent, ReviewVerdict};
+    use crate::github::CommitSha;
     use crate::github::server::{ServerState, ServerStateRef};
     use crate::github::webhook::GitHubWebhook;
-    use crate::github::webhook::WebhookSecret;
+    use crate::github::webhook::{DEFAULT_WEBHOOK_BODY_LIMIT, WebhookSecret};
     use crate::tests::io::load_test_file;
     use crate::tests::webhook::{TEST_WEBHOOK_SECRET, create_webhook_request};
 
@@ -518,6 +822,32 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn repository_renamed() {
+        let Ok(GitHubWebhook(BorsEvent::Global(BorsGlobalEvent::RepositoryRenamed {
+            old_name,
+            new_name,
+        }))) = check_webhook("webhook/repository-renamed.json", "repository").await
+        else {
+            panic!("Repository rename was not parsed");
+        };
+        assert_eq!(old_name.to_string(), "kobzol/bors-kindergarten");
+        assert_eq!(new_name.to_string(), "kobzol/bors-playground");
+    }
+
+    #[tokio::test]
+    async fn repository_transferred() {
+        let Ok(GitHubWebhook(BorsEvent::Global(BorsGlobalEvent::RepositoryRenamed {
+            old_name,
+            new_name,
+        }))) = check_webhook("webhook/repository-transferred.json", "repository").await
+        else {
+            panic!("Repository transfer was not parsed");
+        };
+        assert_eq!(old_name.to_string(), "kobzol/bors-kindergarten");
+        assert_eq!(new_name.to_string(), "rust-lang/bors-kindergarten");
+    }
+
     #[tokio::test]
     async fn push_to_branch() {
         insta::assert_debug_snapshot!(
@@ -533,6 +863,13 @@ mod tests {
                                     name: "bors-kindergarten",
                                 },
                                 branch: "main",
+                                is_default_branch: true,
+                                commit_sha: CommitSha(
+                                    "bc7370e473896a94d40a7dff71f197a3ff0208f5",
+                                ),
+                                changed_files: [
+                                    "test.txt",
+                                ],
                             },
                         ),
                     ),
@@ -582,6 +919,74 @@ mod tests {
                             ),
                             text: "hello bors",
                             html_url: "https://github.com/Kobzol/bors-kindergarten/pull/5#issuecomment-1420770715",
+                            comment_id: Some(
+                                CommentId(
+                                    1420770715,
+                                ),
+                            ),
+                            created_at: Some(
+                                2023-02-07T13:23:42Z,
+                            ),
+                        },
+                    ),
+                ),
+            ),
+        )
+        "#
+        );
+    }
+
+    #[tokio::test]
+    async fn issue_comment_edited() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/issue-comment-edited.json", "issue_comment").await,
+            @r#"
+        Ok(
+            GitHubWebhook(
+                Repository(
+                    CommentEdited(
+                        PullRequestCommentEdited {
+                            comment: PullRequestComment {
+                                repository: GithubRepoName {
+                                    owner: "kobzol",
+                                    name: "bors-kindergarten",
+                                },
+                                author: GithubUser {
+                                    id: UserId(
+                                        4539057,
+                                    ),
+                                    username: "Kobzol",
+                                    html_url: Url {
+                                        scheme: "https",
+                                        cannot_be_a_base: false,
+                                        username: "",
+                                        password: None,
+                                        host: Some(
+                                            Domain(
+                                                "github.com",
+                                            ),
+                                        ),
+                                        port: None,
+                                        path: "/Kobzol",
+                                        query: None,
+                                        fragment: None,
+                                    },
+                                },
+                                pr_number: PullRequestNumber(
+                                    5,
+                                ),
+                                text: "@bors r+",
+                                html_url: "https://github.com/Kobzol/bors-kindergarten/pull/5#issuecomment-1420770715",
+                                comment_id: Some(
+                                    CommentId(
+                                        1420770715,
+                                    ),
+                                ),
+                                created_at: Some(
+                                    2023-02-07T13:23:42Z,
+                                ),
+                            },
+                            previous_text: "hello bors",
                         },
                     ),
                 ),
@@ -647,6 +1052,12 @@ mod tests {
                                     },
                                 },
                                 status: Open,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "vohoanglong0107",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                             from_base_sha: Some(
                                 CommitSha(
@@ -718,6 +1129,12 @@ mod tests {
                                     },
                                 },
                                 status: Open,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "vohoanglong0107",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                         },
                     ),
@@ -768,6 +1185,10 @@ mod tests {
                             ),
                             text: "review comment",
                             html_url: "https://github.com/Kobzol/bors-kindergarten/pull/6#pullrequestreview-1476702458",
+                            comment_id: None,
+                            created_at: Some(
+                                2023-06-13T09:32:36Z,
+                            ),
                         },
                     ),
                 ),
@@ -777,6 +1198,30 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn pull_request_review_approved() {
+        let Ok(GitHubWebhook(BorsEvent::Repository(BorsRepositoryEvent::PullRequestReviewed(
+            review,
+        )))) = check_webhook(
+            "webhook/pull-request-review-approved.json",
+            "pull_request_review",
+        )
+        .await
+        else {
+            panic!("Approving review was not parsed");
+        };
+        assert_eq!(review.verdict, ReviewVerdict::Approved);
+        assert_eq!(review.pull_request.number.0, 6);
+        assert_eq!(
+            review.commit_sha,
+            Some(CommitSha(
+                "dd929458c62ac2a2958a5dadde1465732dff6f90".to_string()
+            ))
+        );
+        assert_eq!(review.comment.author.username, "Kobzol");
+        assert_eq!(review.comment.text, "Looks good");
+    }
+
     #[tokio::test]
     async fn pull_request_opened() {
         insta::assert_debug_snapshot!(
@@ -833,6 +1278,12 @@ mod tests {
                                     },
                                 },
                                 status: Open,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "sakib25800",
+                                        name: "bors-test",
+                                    },
+                                ),
                             },
                             draft: false,
                         },
@@ -900,6 +1351,12 @@ mod tests {
                                     },
                                 },
                                 status: Closed,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                         },
                     ),
@@ -966,6 +1423,12 @@ mod tests {
                                     },
                                 },
                                 status: Merged,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                         },
                     ),
@@ -1032,6 +1495,12 @@ mod tests {
                                     },
                                 },
                                 status: Open,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                         },
                     ),
@@ -1098,6 +1567,12 @@ mod tests {
                                     },
                                 },
                                 status: Draft,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                             draft: true,
                         },
@@ -1165,6 +1640,12 @@ mod tests {
                                     },
                                 },
                                 status: Draft,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                         },
                     ),
@@ -1231,6 +1712,12 @@ mod tests {
                                     },
                                 },
                                 status: Open,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
                             },
                         },
                     ),
@@ -1241,6 +1728,79 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn pull_request_labeled() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/pull-request-labeled.json", "pull_request").await,
+            @r#"
+        Ok(
+            GitHubWebhook(
+                Repository(
+                    PullRequestLabeled(
+                        PullRequestLabeled {
+                            repository: GithubRepoName {
+                                owner: "geetanshjuneja",
+                                name: "test-bors",
+                            },
+                            pull_request: PullRequest {
+                                number: PullRequestNumber(
+                                    5,
+                                ),
+                                head_label: "geetanshjuneja:test",
+                                head: Branch {
+                                    name: "test",
+                                    sha: CommitSha(
+                                        "4964158cdea899629716b4f0c0e2f5ab72e3cb4a",
+                                    ),
+                                },
+                                base: Branch {
+                                    name: "main",
+                                    sha: CommitSha(
+                                        "07eb9b80644df5476cb7d9bb23918d9e64b2dc35",
+                                    ),
+                                },
+                                title: "test",
+                                mergeable_state: Clean,
+                                message: "Creating draft pr",
+                                author: GithubUser {
+                                    id: UserId(
+                                        72911296,
+                                    ),
+                                    username: "geetanshjuneja",
+                                    html_url: Url {
+                                        scheme: "https",
+                                        cannot_be_a_base: false,
+                                        username: "",
+                                        password: None,
+                                        host: Some(
+                                            Domain(
+                                                "github.com",
+                                            ),
+                                        ),
+                                        port: None,
+                                        path: "/geetanshjuneja",
+                                        query: None,
+                                        fragment: None,
+                                    },
+                                },
+                                status: Open,
+                                head_repository: Some(
+                                    GithubRepoName {
+                                        owner: "geetanshjuneja",
+                                        name: "test-bors",
+                                    },
+                                ),
+                            },
+                            label: "rollup=never",
+                        },
+                    ),
+                ),
+            ),
+        )
+        "#
+        );
+    }
+
     #[tokio::test]
     async fn pull_request_review_comment() {
         insta::assert_debug_snapshot!(
@@ -1281,6 +1841,10 @@ mod tests {
                             ),
                             text: "Foo",
                             html_url: "https://github.com/Kobzol/bors-kindergarten/pull/6#discussion_r1227824551",
+                            comment_id: None,
+                            created_at: Some(
+                                2023-06-13T09:30:51Z,
+                            ),
                         },
                     ),
                 ),
@@ -1312,6 +1876,7 @@ mod tests {
                             run_id: RunId(
                                 4900979074,
                             ),
+                            run_attempt: 1,
                             workflow_type: Github,
                             url: "https://github.com/Kobzol/bors-kindergarten/actions/runs/4900979074",
                         },
@@ -1344,6 +1909,7 @@ mod tests {
                             run_id: RunId(
                                 4900979072,
                             ),
+                            run_attempt: 1,
                             status: Failure,
                             running_time: Some(
                                 TimeDelta {
@@ -1382,6 +1948,7 @@ mod tests {
                             run_id: RunId(
                                 13293850093,
                             ),
+                            run_attempt: 1,
                             workflow_type: External,
                             url: "https://github.com/Kobzol/bors-kindergarten/runs/13293850093",
                         },
@@ -1393,6 +1960,39 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn check_run_completed_external() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/check-run-completed-external.json", "check_run").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                Repository(
+                    CheckRunCompleted(
+                        CheckRunCompleted {
+                            repository: GithubRepoName {
+                                owner: "kobzol",
+                                name: "bors-kindergarten",
+                            },
+                            name: "check",
+                            branch: "automation/bors/try-merge",
+                            commit_sha: CommitSha(
+                                "3d5258c8dd4fce72a4ea67387499fe69ea410928",
+                            ),
+                            run_id: RunId(
+                                13293850093,
+                            ),
+                            url: "https://github.com/Kobzol/bors-kindergarten/runs/13293850093",
+                            status: Success,
+                        },
+                    ),
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
     #[tokio::test]
     async fn check_run_created_gha() {
         assert!(matches!(
@@ -1414,16 +2014,52 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn webhook_body_over_limit() {
+        let body = load_test_file("webhook/push.json");
+        let request = create_webhook_request("push", &body);
+        assert_eq!(
+            receive_webhook(request, 16).await.unwrap_err(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn webhook_content_length_over_limit() {
+        let body = load_test_file("webhook/push.json");
+        let mut request = create_webhook_request("push", &body);
+        request.headers_mut().insert(
+            header::CONTENT_LENGTH,
+            (DEFAULT_WEBHOOK_BODY_LIMIT + 1).into(),
+        );
+        assert_eq!(
+            receive_webhook(request, DEFAULT_WEBHOOK_BODY_LIMIT)
+                .await
+                .unwrap_err(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
     async fn check_webhook(file: &str, event: &str) -> Result<GitHubWebhook, StatusCode> {
         let body = load_test_file(file);
         let request = create_webhook_request(event, &body);
+        receive_webhook(request, DEFAULT_WEBHOOK_BODY_LIMIT).await
+    }
 
+    async fn receive_webhook(
+        request: Request<Body>,
+        body_limit: usize,
+    ) -> Result<GitHubWebhook, StatusCode> {
         let (repository_tx, _) = mpsc::channel(1024);
         let (global_tx, _) = mpsc::channel(1024);
         let server_ref = ServerStateRef::new(ServerState::new(
             repository_tx,
             global_tx,
             WebhookSecret::new(TEST_WEBHOOK_SECRET.to_string()),
+            body_limit,
+            None,
+            PgPool::connect_lazy("postgres://localhost/bors").unwrap(),
+            false,
         ));
         GitHubWebhook::from_request(request, &server_ref).await
     }
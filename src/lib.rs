@@ -8,15 +8,22 @@ mod github;
 mod permissions;
 mod utils;
 
-pub use bors::{BorsContext, CommandParser, event::BorsGlobalEvent, event::BorsRepositoryEvent};
-pub use database::PgDbClient;
+pub use bors::{
+    BorsContext, CommandParser, event::BorsGlobalEvent, event::BorsRepositoryEvent,
+    sync_pull_requests,
+};
+pub use database::{PgDbClient, PgDbTransaction};
 pub use github::{
-    WebhookSecret,
+    DEFAULT_WEBHOOK_BODY_LIMIT, GithubRepoName, WebhookSecret,
     api::create_github_client,
+    api::load_command_prefix,
     api::load_repositories,
+    api::load_repository_client,
+    api::parse_private_key,
+    api::read_private_key_file,
     server::{ServerState, create_app, create_bors_process},
 };
-pub use permissions::TeamApiClient;
+pub use permissions::{HttpPermissionClient, PermissionSource, TeamApiClient};
 
 #[cfg(test)]
 mod tests;
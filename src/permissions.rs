@@ -1,9 +1,16 @@
+use arc_swap::ArcSwap;
 use octocrab::models::UserId;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::github::GithubRepoName;
+use crate::github::api::client::GithubRepositoryClient;
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum PermissionType {
@@ -22,6 +29,7 @@ impl fmt::Display for PermissionType {
     }
 }
 
+#[derive(Clone)]
 pub struct UserPermissions {
     review_users: HashSet<UserId>,
     try_users: HashSet<UserId>,
@@ -41,6 +49,221 @@ impl UserPermissions {
             PermissionType::Try => self.try_users.contains(&user_id),
         }
     }
+
+    /// Returns the number of users that have the given permission.
+    pub fn user_count(&self, permission: PermissionType) -> usize {
+        match permission {
+            PermissionType::Review => self.review_users.len(),
+            PermissionType::Try => self.try_users.len(),
+        }
+    }
+}
+
+/// A user or a team that is granted a permission in the repository configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PermissionGrantee {
+    /// GitHub username.
+    User(String),
+    /// Team of a GitHub organization, written as `@org/team`.
+    Team(TeamReference),
+}
+
+impl FromStr for PermissionGrantee {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_prefix('@') {
+            Some(reference) => match reference.split_once('/') {
+                Some((org, slug)) if !org.is_empty() && !slug.is_empty() => {
+                    Ok(PermissionGrantee::Team(TeamReference {
+                        org: org.to_string(),
+                        slug: slug.to_string(),
+                    }))
+                }
+                Some(_) => Err(format!(
+                    "Invalid team reference `{value}`, expected `@<org>/<team>`"
+                )),
+                None if !reference.is_empty() => Ok(PermissionGrantee::User(reference.to_string())),
+                None => Err("Empty user name".to_string()),
+            },
+            None if !value.is_empty() => Ok(PermissionGrantee::User(value.to_string())),
+            None => Err("Empty user name".to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PermissionGrantee {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Reference to a team of a GitHub organization.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TeamReference {
+    pub org: String,
+    pub slug: String,
+}
+
+impl fmt::Display for TeamReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}/{}", self.org, self.slug)
+    }
+}
+
+/// Number of permission lookups that were served from a [PermissionCache].
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+/// Number of permission lookups that had to be loaded from GitHub.
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of hits and misses of all permission caches since the start of the
+/// process.
+pub fn permission_cache_stats() -> (u64, u64) {
+    (
+        CACHE_HITS.load(Ordering::Relaxed),
+        CACHE_MISSES.load(Ordering::Relaxed),
+    )
+}
+
+/// A cached value along with the generation of the cache in which it was loaded.
+#[derive(Clone)]
+struct CacheEntry<T> {
+    value: T,
+    loaded_at: Instant,
+    generation: u64,
+}
+
+/// Caches permission lookups that need a request to GitHub: the members of teams and the IDs of
+/// users that are referenced in the repository configuration.
+/// Entries expire after a TTL. All entries are invalidated at once with [PermissionCache::invalidate],
+/// e.g. when the configuration of the repository is reloaded.
+///
+/// The admin permission is not cached, because losing it is not announced by any webhook, and
+/// it guards the most sensitive commands.
+#[derive(Default)]
+pub struct PermissionCache {
+    /// Incremented on each invalidation. Entries of older generations are ignored.
+    generation: AtomicU64,
+    teams: CacheMap<TeamReference, HashSet<UserId>>,
+    user_ids: CacheMap<String, Option<UserId>>,
+}
+
+/// Cached entries, they are read much more often than they are modified.
+type CacheMap<K, V> = ArcSwap<HashMap<K, CacheEntry<V>>>;
+
+impl PermissionCache {
+    /// Returns the members of the given team.
+    /// Members are loaded from GitHub if they are not cached or if they are older than `ttl`.
+    pub async fn get_members(
+        &self,
+        client: &GithubRepositoryClient,
+        team: &TeamReference,
+        ttl: Duration,
+    ) -> anyhow::Result<HashSet<UserId>> {
+        get_or_load(&self.teams, &self.generation, team, ttl, || async {
+            Ok(client.get_team_members(team).await?.into_iter().collect())
+        })
+        .await
+    }
+
+    /// Returns the ID of the user with the given username, or `None` if the user does not exist.
+    /// The ID is loaded from GitHub if it is not cached or if it is older than `ttl`.
+    pub async fn get_user_id(
+        &self,
+        client: &GithubRepositoryClient,
+        username: &str,
+        ttl: Duration,
+    ) -> anyhow::Result<Option<UserId>> {
+        // GitHub usernames are case-insensitive
+        let key = username.to_lowercase();
+        get_or_load(&self.user_ids, &self.generation, &key, ttl, || {
+            client.get_user_id(username)
+        })
+        .await
+    }
+
+    /// Drops all cached entries, including the ones that are being loaded at the moment.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.teams.store(Arc::default());
+        self.user_ids.store(Arc::default());
+    }
+}
+
+async fn get_or_load<K, V, F, Fut>(
+    entries: &CacheMap<K, V>,
+    generation: &AtomicU64,
+    key: &K,
+    ttl: Duration,
+    load: F,
+) -> anyhow::Result<V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = anyhow::Result<V>>,
+{
+    let current = generation.load(Ordering::SeqCst);
+    let cached = entries
+        .load()
+        .get(key)
+        .filter(|entry| entry.generation == current && entry.loaded_at.elapsed() < ttl)
+        .map(|entry| entry.value.clone());
+    if let Some(value) = cached {
+        CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(value);
+    }
+
+    CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+    let value = load().await?;
+    let entry = CacheEntry {
+        value: value.clone(),
+        loaded_at: Instant::now(),
+        generation: current,
+    };
+    entries.rcu(|cached| {
+        let mut cached = HashMap::clone(cached);
+        // The cache could have been invalidated while the value was being loaded, in which
+        // case the value might already be outdated.
+        if generation.load(Ordering::SeqCst) == current {
+            cached.insert(key.clone(), entry.clone());
+        }
+        cached
+    });
+    Ok(value)
+}
+
+/// Source from which the review and try permissions of repositories are loaded.
+pub enum PermissionSource {
+    /// The Rust Team API.
+    TeamApi(TeamApiClient),
+    /// An HTTP endpoint that serves a single JSON file with permissions for each repository.
+    Http(HttpPermissionClient),
+}
+
+impl PermissionSource {
+    pub(crate) async fn load_permissions(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<UserPermissions> {
+        match self {
+            PermissionSource::TeamApi(client) => client.load_permissions(repo).await,
+            PermissionSource::Http(client) => client.load_permissions(repo).await,
+        }
+    }
+}
+
+impl From<TeamApiClient> for PermissionSource {
+    fn from(client: TeamApiClient) -> Self {
+        PermissionSource::TeamApi(client)
+    }
+}
+
+impl From<HttpPermissionClient> for PermissionSource {
+    fn from(client: HttpPermissionClient) -> Self {
+        PermissionSource::Http(client)
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -115,3 +338,327 @@ impl Default for TeamApiClient {
         Self::new("https://team-api.infra.rust-lang.org")
     }
 }
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct RepositoryPermissionsResponse {
+    review: HashSet<UserId>,
+    #[serde(rename = "try")]
+    try_users: HashSet<UserId>,
+}
+
+struct CachedPermissions {
+    permissions: UserPermissions,
+    loaded_at: Instant,
+    refreshing: bool,
+}
+
+/// Loads permissions from `{base_url}/permissions/{repo}.json`, which contains the GitHub IDs of
+/// users that can review and try, e.g. `{"review": [1, 2], "try": [1, 2, 3]}`.
+///
+/// Responses are cached for `ttl`. Once the cached permissions of a repository become stale,
+/// they are still returned, while fresh permissions are loaded in the background. If the
+/// refresh fails, the stale permissions are kept.
+#[derive(Clone)]
+pub struct HttpPermissionClient {
+    base_url: String,
+    ttl: Duration,
+    cache: Arc<Mutex<HashMap<GithubRepoName, CachedPermissions>>>,
+}
+
+impl HttpPermissionClient {
+    pub fn new(base_url: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            base_url: base_url.into(),
+            ttl,
+            cache: Default::default(),
+        }
+    }
+
+    async fn load_permissions(&self, repo: &GithubRepoName) -> anyhow::Result<UserPermissions> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(cached) = cache.get_mut(repo) {
+                if cached.loaded_at.elapsed() >= self.ttl && !cached.refreshing {
+                    cached.refreshing = true;
+                    let client = self.clone();
+                    let repo = repo.clone();
+                    tokio::spawn(async move { client.refresh(&repo).await });
+                }
+                return Ok(cached.permissions.clone());
+            }
+        }
+
+        let permissions = self.fetch(repo).await?;
+        self.store(repo, permissions.clone());
+        Ok(permissions)
+    }
+
+    async fn refresh(&self, repo: &GithubRepoName) {
+        match self.fetch(repo).await {
+            Ok(permissions) => self.store(repo, permissions),
+            Err(error) => {
+                tracing::warn!(
+                    "Cannot refresh permissions for repository {repo}, using stale permissions: {error:?}"
+                );
+                if let Some(cached) = self.cache.lock().unwrap().get_mut(repo) {
+                    cached.refreshing = false;
+                }
+            }
+        }
+    }
+
+    fn store(&self, repo: &GithubRepoName, permissions: UserPermissions) {
+        self.cache.lock().unwrap().insert(
+            repo.clone(),
+            CachedPermissions {
+                permissions,
+                loaded_at: Instant::now(),
+                refreshing: false,
+            },
+        );
+    }
+
+    async fn fetch(&self, repo: &GithubRepoName) -> anyhow::Result<UserPermissions> {
+        tracing::info!("Loading permissions for repository {repo}");
+
+        let url = format!("{}/permissions/{}.json", self.base_url, repo.name());
+        let response = reqwest::get(url)
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|error| anyhow::anyhow!("Cannot load permissions: {error:?}"))?
+            .json::<RepositoryPermissionsResponse>()
+            .await
+            .map_err(|error| anyhow::anyhow!("Cannot deserialize permissions: {error:?}"))?;
+        Ok(UserPermissions::new(response.review, response.try_users))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use crate::permissions::{PermissionCache, PermissionGrantee, TeamReference, get_or_load};
+
+    use octocrab::models::UserId;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::github::GithubRepoName;
+    use crate::permissions::{HttpPermissionClient, PermissionType, UserPermissions};
+
+    fn repo() -> GithubRepoName {
+        GithubRepoName::new("rust-lang", "borstest")
+    }
+
+    async fn mock_permissions(server: &MockServer, review: &[u64], try_users: &[u64]) {
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/permissions/borstest.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "review": review,
+                "try": try_users
+            })))
+            .mount(server)
+            .await;
+    }
+
+    async fn mock_error(server: &MockServer) {
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/permissions/borstest.json"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(server)
+            .await;
+    }
+
+    fn can_review(permissions: &UserPermissions, user: u64) -> bool {
+        permissions.has_permission(UserId(user), PermissionType::Review)
+    }
+
+    /// Waits until the background refresh stores permissions where `user` can review.
+    async fn wait_for_reviewer(client: &HttpPermissionClient, user: u64) {
+        for _ in 0..100 {
+            let permissions = client.load_permissions(&repo()).await.unwrap();
+            if can_review(&permissions, user) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Permissions were not refreshed");
+    }
+
+    #[test]
+    fn parse_permission_grantee() {
+        assert_eq!(
+            "@rust-lang/compiler".parse::<PermissionGrantee>(),
+            Ok(PermissionGrantee::Team(TeamReference {
+                org: "rust-lang".to_string(),
+                slug: "compiler".to_string()
+            }))
+        );
+        assert_eq!(
+            "@kobzol".parse::<PermissionGrantee>(),
+            Ok(PermissionGrantee::User("kobzol".to_string()))
+        );
+        assert_eq!(
+            "kobzol".parse::<PermissionGrantee>(),
+            Ok(PermissionGrantee::User("kobzol".to_string()))
+        );
+        assert!("@rust-lang/".parse::<PermissionGrantee>().is_err());
+        assert!("".parse::<PermissionGrantee>().is_err());
+    }
+
+    #[tokio::test]
+    async fn load_http_permissions() {
+        let server = MockServer::start().await;
+        mock_permissions(&server, &[1], &[1, 2]).await;
+
+        let client = HttpPermissionClient::new(server.uri(), Duration::from_secs(3600));
+        let permissions = client.load_permissions(&repo()).await.unwrap();
+        assert!(can_review(&permissions, 1));
+        assert!(!can_review(&permissions, 2));
+        assert!(permissions.has_permission(UserId(2), PermissionType::Try));
+    }
+
+    #[tokio::test]
+    async fn load_http_permissions_error() {
+        let server = MockServer::start().await;
+        mock_error(&server).await;
+
+        let client = HttpPermissionClient::new(server.uri(), Duration::from_secs(3600));
+        assert!(client.load_permissions(&repo()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_fresh_permissions() {
+        let server = MockServer::start().await;
+        mock_permissions(&server, &[1], &[]).await;
+
+        let client = HttpPermissionClient::new(server.uri(), Duration::from_secs(3600));
+        client.load_permissions(&repo()).await.unwrap();
+
+        mock_permissions(&server, &[2], &[]).await;
+        let permissions = client.load_permissions(&repo()).await.unwrap();
+        assert!(can_review(&permissions, 1));
+        assert!(!can_review(&permissions, 2));
+    }
+
+    #[tokio::test]
+    async fn refresh_stale_permissions_in_background() {
+        let server = MockServer::start().await;
+        mock_permissions(&server, &[1], &[]).await;
+
+        let client = HttpPermissionClient::new(server.uri(), Duration::ZERO);
+        client.load_permissions(&repo()).await.unwrap();
+
+        mock_permissions(&server, &[2], &[]).await;
+        // Stale permissions are returned while the refresh is running
+        let permissions = client.load_permissions(&repo()).await.unwrap();
+        assert!(can_review(&permissions, 1));
+        wait_for_reviewer(&client, 2).await;
+    }
+
+    #[tokio::test]
+    async fn keep_stale_permissions_if_refresh_fails() {
+        let server = MockServer::start().await;
+        mock_permissions(&server, &[1], &[]).await;
+
+        let client = HttpPermissionClient::new(server.uri(), Duration::ZERO);
+        client.load_permissions(&repo()).await.unwrap();
+
+        mock_error(&server).await;
+        for _ in 0..3 {
+            let permissions = client.load_permissions(&repo()).await.unwrap();
+            assert!(can_review(&permissions, 1));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The refresh is retried once the endpoint works again
+        mock_permissions(&server, &[2], &[]).await;
+        wait_for_reviewer(&client, 2).await;
+    }
+
+    async fn load_user_id(
+        cache: &PermissionCache,
+        user: &str,
+        ttl: Duration,
+        loads: &AtomicUsize,
+        id: u64,
+    ) -> u64 {
+        get_or_load(
+            &cache.user_ids,
+            &cache.generation,
+            &user.to_string(),
+            ttl,
+            || async {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(UserId(id)))
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .0
+    }
+
+    #[tokio::test]
+    async fn cache_hit_within_ttl() {
+        let cache = PermissionCache::default();
+        let loads = AtomicUsize::new(0);
+        let ttl = Duration::from_secs(60);
+        assert_eq!(load_user_id(&cache, "user", ttl, &loads, 1).await, 1);
+        assert_eq!(load_user_id(&cache, "user", ttl, &loads, 2).await, 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        assert_eq!(load_user_id(&cache, "other", ttl, &loads, 2).await, 2);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_expires_after_ttl() {
+        let cache = PermissionCache::default();
+        let loads = AtomicUsize::new(0);
+        assert_eq!(
+            load_user_id(&cache, "user", Duration::ZERO, &loads, 1).await,
+            1
+        );
+        assert_eq!(
+            load_user_id(&cache, "user", Duration::ZERO, &loads, 2).await,
+            2
+        );
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_invalidate() {
+        let cache = PermissionCache::default();
+        let loads = AtomicUsize::new(0);
+        let ttl = Duration::from_secs(60);
+        assert_eq!(load_user_id(&cache, "user", ttl, &loads, 1).await, 1);
+        cache.invalidate();
+        assert_eq!(load_user_id(&cache, "user", ttl, &loads, 2).await, 2);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_ignore_value_loaded_during_invalidation() {
+        let cache = PermissionCache::default();
+        let ttl = Duration::from_secs(60);
+        let value = get_or_load(
+            &cache.user_ids,
+            &cache.generation,
+            &"user".to_string(),
+            ttl,
+            || {
+                cache.invalidate();
+                async { Ok(Some(UserId(1))) }
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(value, Some(UserId(1)));
+        assert!(cache.user_ids.load().is_empty());
+    }
+}
@@ -13,19 +13,23 @@ use super::user::GitHubUser;
 pub(crate) struct AppHandler {}
 
 impl AppHandler {
-    pub(super) async fn mount(&self, mock_server: &MockServer) {
+    /// Mounts the endpoints under the given path prefix, which is empty for the API of
+    /// github.com.
+    pub(super) async fn mount(&self, mock_server: &MockServer, prefix: &str) {
         Mock::given(method("GET"))
-            .and(path("/app"))
+            .and(path(format!("{prefix}/app")))
             .respond_with(ResponseTemplate::new(200).set_body_json(GitHubApp::default()))
             .mount(mock_server)
             .await;
         Mock::given(method("GET"))
-            .and(path("/app/installations"))
+            .and(path(format!("{prefix}/app/installations")))
             .respond_with(ResponseTemplate::new(200).set_body_json(vec![Installation::default()]))
             .mount(mock_server)
             .await;
         Mock::given(method("POST"))
-            .and(path_regex("^/app/installations/\\d+/access_tokens$"))
+            .and(path_regex(format!(
+                "^{prefix}/app/installations/\\d+/access_tokens$"
+            )))
             .respond_with(ResponseTemplate::new(200).set_body_json(InstallationToken::default()))
             .mount(mock_server)
             .await;
@@ -38,6 +42,7 @@ impl AppHandler {
 pub struct GitHubApp {
     id: u64,
     node_id: String,
+    slug: String,
     pub owner: GitHubUser,
     name: String,
     external_url: Url,
@@ -51,6 +56,7 @@ impl Default for GitHubApp {
         GitHubApp {
             id: default_app_id(),
             node_id: "1234".to_string(),
+            slug: "bors".to_string(),
             owner: GitHubUser::default(),
             name: "bors".to_string(),
             // same as bors user html_url
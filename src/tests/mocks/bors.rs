@@ -1,5 +1,7 @@
 use anyhow::Context;
 use axum::Router;
+use axum::body::Body;
+use http::{HeaderMap, Request, StatusCode};
 use parking_lot::lock_api::MappedMutexGuard;
 use parking_lot::{Mutex, MutexGuard, RawMutex};
 use serde::Serialize;
@@ -13,7 +15,7 @@ use tokio::task::JoinHandle;
 use tower::Service;
 
 use crate::bors::{RollupMode, WAIT_FOR_REFRESH};
-use crate::database::{BuildStatus, PullRequestModel};
+use crate::database::{BuildStatus, DelegatedPermission, PullRequestModel};
 use crate::github::api::load_repositories;
 use crate::github::{GithubRepoName, PullRequestNumber};
 use crate::tests::mocks::comment::{Comment, GitHubIssueCommentEventPayload};
@@ -26,16 +28,23 @@ use crate::tests::mocks::{
 };
 use crate::tests::webhook::{TEST_WEBHOOK_SECRET, create_webhook_request};
 use crate::{
-    BorsContext, BorsGlobalEvent, CommandParser, PgDbClient, ServerState, WebhookSecret,
-    create_app, create_bors_process,
+    BorsContext, BorsGlobalEvent, CommandParser, DEFAULT_WEBHOOK_BODY_LIMIT, PgDbClient,
+    ServerState, WebhookSecret, create_app, create_bors_process,
 };
 
-use super::pull_request::{GitHubPullRequestEventPayload, PullRequestChangeEvent};
+use super::pull_request::{
+    GitHubPullRequestEventPayload, GitHubPullRequestReviewCommentEventPayload,
+    GitHubPullRequestReviewEventPayload, GitHubPushEventPayload, PullRequestChangeEvent,
+};
 use super::repository::PullRequest;
 
+const GITHUB_HTML_URL: &str = "https://github.com";
+pub const TEST_ADMIN_TOKEN: &str = "admin-token";
+
 pub struct BorsBuilder {
     github: GitHubState,
     pool: PgPool,
+    dry_run: bool,
 }
 
 impl BorsBuilder {
@@ -43,6 +52,7 @@ impl BorsBuilder {
         Self {
             pool,
             github: Default::default(),
+            dry_run: false,
         }
     }
 
@@ -50,6 +60,13 @@ impl BorsBuilder {
         Self { github, ..self }
     }
 
+    pub fn dry_run(self) -> Self {
+        Self {
+            dry_run: true,
+            ..self
+        }
+    }
+
     /// This closure is used to ensure that the test has to return `BorsTester`
     /// to us, so that we can call `finish()` on it. Without that call, we couldn't
     /// ensure that some async task within the bors process hasn't crashed.
@@ -62,7 +79,7 @@ impl BorsBuilder {
     ) -> GitHubState {
         // We return `tester` and `bors` separately, so that we can finish `bors`
         // even if `f` returns a result, for better error propagation.
-        let (tester, bors) = BorsTester::new(self.pool, self.github).await;
+        let (tester, bors) = BorsTester::new(self.pool, self.github, self.dry_run).await;
         match f(tester).await {
             Ok(tester) => tester.finish(bors).await,
             Err(error) => {
@@ -85,6 +102,10 @@ pub async fn run_test<
     BorsBuilder::new(pool).run_test(f).await
 }
 
+/// Comments posted by the tester get IDs starting after this one, so that they do not collide
+/// with comments posted by bors.
+const FIRST_POSTED_COMMENT_ID: u64 = 1_000_000;
+
 /// Represents a running bors web application. This structure should be used
 /// in tests to test interaction with the bot.
 ///
@@ -97,32 +118,56 @@ pub struct BorsTester {
     db: Arc<PgDbClient>,
     // Sender for bors global events
     global_tx: Sender<BorsGlobalEvent>,
+    // ID of the last comment posted by the tester, across all PRs
+    next_comment_id: u64,
+    // ID of the last comment posted by the tester on each PR
+    posted_comments: HashMap<(GithubRepoName, u64), u64>,
 }
 
 impl BorsTester {
-    async fn new(pool: PgPool, github: GitHubState) -> (Self, JoinHandle<()>) {
+    async fn new(pool: PgPool, github: GitHubState, dry_run: bool) -> (Self, JoinHandle<()>) {
         let mock = ExternalHttpMock::start(&github).await;
-        let db = Arc::new(PgDbClient::new(pool));
-
-        let loaded_repos = load_repositories(&mock.github_client(), &mock.team_api_client())
-            .await
-            .unwrap();
+        let db = Arc::new(PgDbClient::new(pool.clone()));
+
+        let loaded_repos = load_repositories(
+            &mock.github_client(),
+            &mock.permission_source(),
+            GITHUB_HTML_URL,
+            Some(db.as_ref()),
+            dry_run,
+        )
+        .await
+        .unwrap();
         let mut repos = HashMap::default();
         for (name, repo) in loaded_repos {
             let repo = repo.unwrap();
             repos.insert(name, Arc::new(repo));
         }
 
-        let ctx = BorsContext::new(CommandParser::new("@bors".to_string()), db.clone(), repos);
+        let ctx = BorsContext::new(
+            CommandParser::new("@bors".to_string()),
+            db.clone(),
+            repos,
+            GITHUB_HTML_URL.to_string(),
+            dry_run,
+        );
+        let repositories = ctx.repositories.clone();
 
+        // Tests send webhooks of different PRs and branches without waiting until the previous
+        // ones are handled, so they rely on all events being handled in the order of arrival.
         let (repository_tx, global_tx, bors_process) =
-            create_bors_process(ctx, mock.github_client(), mock.team_api_client());
+            create_bors_process(ctx, mock.github_client(), mock.permission_source(), 1);
 
         let state = ServerState::new(
             repository_tx,
             global_tx.clone(),
             WebhookSecret::new(TEST_WEBHOOK_SECRET.to_string()),
-        );
+            DEFAULT_WEBHOOK_BODY_LIMIT,
+            Some(TEST_ADMIN_TOKEN.to_string()),
+            pool,
+            dry_run,
+        )
+        .with_repositories(repositories);
         let app = create_app(state);
         let bors = tokio::spawn(bors_process);
         (
@@ -132,6 +177,8 @@ impl BorsTester {
                 github,
                 db,
                 global_tx,
+                next_comment_id: FIRST_POSTED_COMMENT_ID,
+                posted_comments: HashMap::default(),
             },
             bors,
         )
@@ -160,9 +207,10 @@ impl BorsTester {
         repo: GithubRepoName,
         number: u64,
     ) -> anyhow::Result<Option<PullRequestModel>> {
-        self.db()
+        Ok(self
+            .db()
             .get_pull_request(&repo, PullRequestNumber(number))
-            .await
+            .await?)
     }
 
     pub async fn default_pr_db(&self) -> anyhow::Result<Option<PullRequestModel>> {
@@ -208,17 +256,104 @@ impl BorsTester {
 
     /// Wait until the next bot comment is received on the default repo and the default PR.
     pub async fn get_comment(&mut self) -> anyhow::Result<String> {
+        self.get_pr_comment(default_pr_number()).await
+    }
+
+    /// Returns the next comment posted to the given PR of the default repository.
+    pub async fn get_pr_comment(&mut self, pr_number: u64) -> anyhow::Result<String> {
         Ok(self
             .http_mock
             .gh_server
-            .get_comment(Repo::default().name, default_pr_number())
+            .get_comment(Repo::default().name, pr_number)
             .await?
             .content)
     }
 
     //-- Generation of GitHub events --//
     pub async fn post_comment<C: Into<Comment>>(&mut self, comment: C) -> anyhow::Result<()> {
-        self.webhook_comment(comment.into()).await
+        let mut comment = comment.into();
+        let id = *comment.comment_id.get_or_insert_with(|| {
+            self.next_comment_id += 1;
+            self.next_comment_id
+        });
+        self.posted_comments
+            .insert((comment.repo.clone(), comment.pr), id);
+        self.webhook_comment(comment).await
+    }
+
+    /// Edits the text of a comment that was previously posted with `previous_content`.
+    pub async fn edit_comment<C: Into<Comment>>(
+        &mut self,
+        comment: C,
+        previous_content: &str,
+    ) -> anyhow::Result<()> {
+        let comment = comment.into();
+        let editor = comment.author.clone();
+        self.edit_comment_as(comment, previous_content, editor)
+            .await
+    }
+
+    /// Edits a comment on behalf of `editor`, who does not have to be the comment author.
+    pub async fn edit_comment_as<C: Into<Comment>>(
+        &mut self,
+        comment: C,
+        previous_content: &str,
+        editor: User,
+    ) -> anyhow::Result<()> {
+        // Without an explicit ID, the last comment posted on the PR is edited
+        let mut comment = comment.into();
+        if comment.comment_id.is_none() {
+            comment.comment_id = self
+                .posted_comments
+                .get(&(comment.repo.clone(), comment.pr))
+                .copied();
+        }
+        self.send_webhook(
+            "issue_comment",
+            Box::from(
+                GitHubIssueCommentEventPayload::edited(comment, previous_content)
+                    .with_sender(editor),
+            ),
+        )
+        .await
+    }
+
+    /// Submits a GitHub review of the latest commit of the given PR of the default repository.
+    /// `state` is the state of the review, e.g. `approved` or `changes_requested`.
+    pub async fn submit_review(
+        &mut self,
+        pr_number: u64,
+        author: User,
+        state: &str,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        let pr = self.default_repo().lock().get_pr(pr_number).clone();
+        self.send_webhook(
+            "pull_request_review",
+            GitHubPullRequestReviewEventPayload::new(pr, author, state, body),
+        )
+        .await
+    }
+
+    /// Posts a review comment on a changed line of the given PR of the default repository.
+    pub async fn post_review_comment(
+        &mut self,
+        pr_number: u64,
+        author: User,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        let (pr, id) = {
+            let repo = self.default_repo();
+            let mut repo = repo.lock();
+            let pr = repo.get_pr_mut(pr_number);
+            let id = pr.next_comment_id();
+            (pr.clone(), id)
+        };
+        self.send_webhook(
+            "pull_request_review_comment",
+            GitHubPullRequestReviewCommentEventPayload::new(pr, author, id, body),
+        )
+        .await
     }
 
     pub async fn refresh(&self) {
@@ -236,11 +371,15 @@ impl BorsTester {
             .get_branch_by_name(&event.workflow.head_branch)
         {
             match &event.event {
-                WorkflowEventKind::Started => {}
+                WorkflowEventKind::Started => {
+                    if event.workflow.run_attempt > 1 {
+                        branch.suite_restarted();
+                    }
+                }
                 WorkflowEventKind::Completed { status } => {
                     let status = match status.as_str() {
-                        "success" => TestWorkflowStatus::Success,
-                        "failure" => TestWorkflowStatus::Failure,
+                        "success" | "skipped" | "neutral" => TestWorkflowStatus::Success,
+                        "failure" | "action_required" => TestWorkflowStatus::Failure,
                         _ => unreachable!(),
                     };
                     branch.suite_finished(status);
@@ -250,6 +389,12 @@ impl BorsTester {
         self.webhook_workflow(event).await
     }
 
+    /// Sends a workflow event that was delivered late by GitHub, so it does not change the state
+    /// of the check suites of its branch.
+    pub async fn late_workflow_event(&mut self, event: WorkflowEvent) -> anyhow::Result<()> {
+        self.webhook_workflow(event).await
+    }
+
     /// Performs all necessary events to complete a single workflow (start, success/fail,
     /// check suite completed).
     #[inline]
@@ -286,6 +431,57 @@ impl BorsTester {
             .await
     }
 
+    /// Sends an event about a created check run of an external workflow.
+    pub async fn check_run_created<W: Into<Workflow>>(
+        &mut self,
+        workflow: W,
+    ) -> anyhow::Result<()> {
+        self.webhook_external_workflow(workflow.into()).await
+    }
+
+    /// Sends an event about a completed check run of an external workflow.
+    pub async fn check_run_completed<W: Into<Workflow>>(
+        &mut self,
+        workflow: W,
+        status: TestWorkflowStatus,
+    ) -> anyhow::Result<()> {
+        let conclusion = match status {
+            TestWorkflowStatus::Success => "success",
+            TestWorkflowStatus::Failure => "failure",
+        };
+        self.check_run_completed_with_conclusion(workflow, conclusion)
+            .await
+    }
+
+    /// Completes an external check run with the given GitHub conclusion, e.g. `neutral`.
+    pub async fn check_run_completed_with_conclusion<W: Into<Workflow>>(
+        &mut self,
+        workflow: W,
+        conclusion: &str,
+    ) -> anyhow::Result<()> {
+        let workflow = workflow.into();
+        if let Some(branch) = self
+            .github
+            .get_repo(&workflow.repository)
+            .lock()
+            .get_branch_by_name(&workflow.head_branch)
+        {
+            let status = match conclusion {
+                "success" | "skipped" | "neutral" => TestWorkflowStatus::Success,
+                "failure" | "action_required" | "timed_out" | "cancelled" => {
+                    TestWorkflowStatus::Failure
+                }
+                _ => unreachable!(),
+            };
+            branch.suite_finished(status);
+        }
+        self.send_webhook(
+            "check_run",
+            GitHubCheckRunEventPayload::completed(workflow, conclusion),
+        )
+        .await
+    }
+
     pub async fn check_suite<C: Into<CheckSuite>>(&mut self, check_suite: C) -> anyhow::Result<()> {
         self.webhook_check_suite(check_suite.into()).await
     }
@@ -398,6 +594,56 @@ impl BorsTester {
         Ok(())
     }
 
+    /// Adds a label to the PR and sends the corresponding `labeled` webhook.
+    pub async fn add_pr_label(
+        &mut self,
+        repo_name: GithubRepoName,
+        pr_number: u64,
+        label: &str,
+    ) -> anyhow::Result<()> {
+        let pr = {
+            let repo = self.github.get_repo(&repo_name);
+            let mut repo = repo.lock();
+            let pr = repo
+                .pull_requests
+                .get_mut(&pr_number)
+                .expect("PR must exist before being labeled");
+            pr.labels.push(label.to_string());
+            pr.clone()
+        };
+        self.send_webhook(
+            "pull_request",
+            GitHubPullRequestEventPayload::new(pr, "labeled", None).with_label(label),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a label from the PR and sends the corresponding `unlabeled` webhook.
+    pub async fn remove_pr_label(
+        &mut self,
+        repo_name: GithubRepoName,
+        pr_number: u64,
+        label: &str,
+    ) -> anyhow::Result<()> {
+        let pr = {
+            let repo = self.github.get_repo(&repo_name);
+            let mut repo = repo.lock();
+            let pr = repo
+                .pull_requests
+                .get_mut(&pr_number)
+                .expect("PR must exist before being unlabeled");
+            pr.labels.retain(|l| l != label);
+            pr.clone()
+        };
+        self.send_webhook(
+            "pull_request",
+            GitHubPullRequestEventPayload::new(pr, "unlabeled", None).with_label(label),
+        )
+        .await?;
+        Ok(())
+    }
+
     pub async fn merge_pr(
         &mut self,
         repo_name: GithubRepoName,
@@ -475,6 +721,123 @@ impl BorsTester {
         .await
     }
 
+    /// Pushes a new commit that modifies `modified_files` to the given branch of the default
+    /// repository.
+    pub async fn push_to_branch(
+        &mut self,
+        branch: &str,
+        modified_files: &[&str],
+    ) -> anyhow::Result<()> {
+        let sha = {
+            let repo = self.default_repo();
+            let mut repo = repo.lock();
+            let counter = repo.get_next_pr_push_counter();
+            let sha = format!("{branch}-commit-{counter}");
+            if let Some(branch) = repo.get_branch_by_name(branch) {
+                branch.set_to_sha(&sha);
+            }
+            sha
+        };
+        self.send_webhook(
+            "push",
+            GitHubPushEventPayload::new(branch, &sha, modified_files),
+        )
+        .await
+    }
+
+    /// Calls the admin endpoint for reloading the given repository, optionally authenticated
+    /// with the given token. Returns the status code and the response body.
+    pub async fn admin_reload_repository(
+        &mut self,
+        repo: GithubRepoName,
+        token: Option<&str>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let mut request = Request::post(format!("/admin/repos/{repo}/reload"));
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        self.send_request(request.body(Body::empty())?).await
+    }
+
+    pub async fn admin_sync_repository(
+        &mut self,
+        repo: GithubRepoName,
+        token: Option<&str>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let mut request = Request::post(format!("/admin/repos/{repo}/sync"));
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        self.send_request(request.body(Body::empty())?).await
+    }
+
+    pub async fn admin_dead_events(
+        &mut self,
+        token: Option<&str>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let mut request = Request::get("/admin/events/dead");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        self.send_request(request.body(Body::empty())?).await
+    }
+
+    pub async fn admin_retry_event(
+        &mut self,
+        id: i32,
+        token: Option<&str>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let mut request = Request::post(format!("/admin/events/{id}/retry"));
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        self.send_request(request.body(Body::empty())?).await
+    }
+
+    /// Sends a `GET` request to the given path of the bors web server.
+    /// Returns the status code and the response body.
+    pub async fn get_endpoint(&mut self, path: &str) -> anyhow::Result<(StatusCode, String)> {
+        self.send_request(Request::get(path).body(Body::empty())?)
+            .await
+    }
+
+    /// Sends a `GET` request to the given path of the bors web server.
+    /// Returns the status code, the response headers and the response body.
+    pub async fn get_endpoint_with_headers(
+        &mut self,
+        path: &str,
+    ) -> anyhow::Result<(StatusCode, HeaderMap, String)> {
+        self.send_request_with_headers(Request::get(path).body(Body::empty())?)
+            .await
+    }
+
+    async fn send_request(
+        &mut self,
+        request: Request<Body>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let (status, _, body) = self.send_request_with_headers(request).await?;
+        Ok((status, body))
+    }
+
+    async fn send_request_with_headers(
+        &mut self,
+        request: Request<Body>,
+    ) -> anyhow::Result<(StatusCode, HeaderMap, String)> {
+        let response = self
+            .app
+            .call(request)
+            .await
+            .context("Cannot send request")?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = String::from_utf8(
+            axum::body::to_bytes(response.into_body(), 10 * 1024 * 1024)
+                .await?
+                .to_vec(),
+        )?;
+        Ok((status, headers, body))
+    }
+
     //-- Test assertions --//
     /// Expect that `count` comments will be received, without checking their contents.
     pub async fn expect_comments(&mut self, count: u64) {
@@ -622,6 +985,12 @@ impl PullRequestProxy {
         self
     }
 
+    #[track_caller]
+    pub fn expect_squash(&self, squash: bool) -> &Self {
+        assert_eq!(self.require_db_pr().squash, squash);
+        self
+    }
+
     #[track_caller]
     pub fn expect_approved_by(&self, approved_by: &str) -> &Self {
         assert_eq!(self.require_db_pr().approver(), Some(approved_by));
@@ -631,7 +1000,7 @@ impl PullRequestProxy {
 
     #[track_caller]
     pub fn expect_unapproved(&self) -> &Self {
-        assert!(!self.require_db_pr().is_approved());
+        assert!(!self.require_db_pr().has_approval());
         self
     }
 
@@ -642,8 +1011,8 @@ impl PullRequestProxy {
     }
 
     #[track_caller]
-    pub fn expect_delegated(&self) -> &Self {
-        assert!(self.require_db_pr().delegated);
+    pub fn expect_delegated(&self, permission: DelegatedPermission) -> &Self {
+        assert_eq!(self.require_db_pr().delegated_permission, Some(permission));
         self
     }
 
@@ -1,5 +1,5 @@
 use chrono::Utc;
-use octocrab::models::events::payload::{IssueCommentEventAction, IssueCommentEventChanges};
+use octocrab::models::events::payload::IssueCommentEventAction;
 use octocrab::models::issues::IssueStateReason;
 use octocrab::models::{Author, CommentId, IssueId, IssueState, Label};
 use serde::Serialize;
@@ -16,7 +16,8 @@ pub struct Comment {
     pub pr: u64,
     pub author: User,
     pub content: String,
-    pub comment_id: u64,
+    /// ID of the comment, comments posted by the tester get a new ID unless it is set.
+    pub comment_id: Option<u64>,
 }
 
 impl Comment {
@@ -26,7 +27,7 @@ impl Comment {
             pr,
             author: User::default_pr_author(),
             content: content.to_string(),
-            comment_id: 1, // comment_id will be updated by the mock server
+            comment_id: None,
         }
     }
 
@@ -36,7 +37,7 @@ impl Comment {
 
     pub fn with_id(self, id: u64) -> Self {
         Self {
-            comment_id: id,
+            comment_id: Some(id),
             ..self
         }
     }
@@ -55,15 +56,50 @@ pub struct GitHubIssueCommentEventPayload {
     action: IssueCommentEventAction,
     issue: GitHubIssue,
     comment: GitHubComment,
-    changes: Option<IssueCommentEventChanges>,
+    changes: Option<GitHubIssueCommentChanges>,
+    sender: GitHubUser,
+}
+
+impl GitHubIssueCommentEventPayload {
+    /// Creates the payload of a comment whose text was edited from `previous_content`.
+    pub fn edited(comment: Comment, previous_content: &str) -> Self {
+        Self {
+            action: IssueCommentEventAction::Edited,
+            changes: Some(GitHubIssueCommentChanges {
+                body: GitHubChangedValue {
+                    from: previous_content.to_string(),
+                },
+            }),
+            ..Self::from(comment)
+        }
+    }
+
+    /// Sets the user that caused the event, which is the comment author by default.
+    pub fn with_sender(self, sender: User) -> Self {
+        Self {
+            sender: sender.into(),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GitHubIssueCommentChanges {
+    body: GitHubChangedValue,
+}
+
+#[derive(Serialize)]
+struct GitHubChangedValue {
+    from: String,
 }
 
 impl From<Comment> for GitHubIssueCommentEventPayload {
     fn from(value: Comment) -> Self {
+        let comment_id = value.comment_id.expect("Comment ID has not been assigned");
         let time = Utc::now();
         let html_url = format!(
             "https://github.com/{}/pull/{}#issuecomment-{}",
-            value.repo, value.pr, value.comment_id
+            value.repo, value.pr, comment_id
         );
         let url = Url::parse(&html_url).unwrap();
         Self {
@@ -101,18 +137,19 @@ impl From<Comment> for GitHubIssueCommentEventPayload {
                 updated_at: time,
             },
             comment: GitHubComment {
-                id: CommentId(value.comment_id),
-                node_id: value.comment_id.to_string(),
+                id: CommentId(comment_id),
+                node_id: comment_id.to_string(),
                 url: url.clone(),
                 html_url: url,
                 body: Some(value.content.clone()),
                 body_text: Some(value.content.clone()),
                 body_html: Some(value.content.clone()),
-                user: value.author.into(),
+                user: value.author.clone().into(),
                 created_at: time,
                 author_association: "OWNER".to_string(),
             },
             changes: None,
+            sender: value.author.into(),
         }
     }
 }
@@ -146,6 +183,11 @@ struct GitHubIssue {
     updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Returns the GraphQL node ID of a comment created through the mocked REST API.
+pub(super) fn comment_node_id(repo: &GithubRepoName, pr: u64, comment_id: u64) -> String {
+    format!("IC_{repo}#{pr}-{comment_id}")
+}
+
 // Copied from octocrab, since its version if #[non_exhaustive]
 #[derive(Serialize)]
 pub(super) struct GitHubComment {
@@ -163,15 +205,16 @@ pub(super) struct GitHubComment {
 
 impl From<Comment> for GitHubComment {
     fn from(value: Comment) -> Self {
+        let comment_id = value.comment_id.expect("Comment ID has not been assigned");
         let time = Utc::now();
         let html_url = format!(
             "https://github.com/{}/pull/{}#issuecomment-{}",
-            value.repo, value.pr, value.comment_id
+            value.repo, value.pr, comment_id
         );
         let url = Url::parse(&html_url).unwrap();
         Self {
-            id: CommentId(value.comment_id),
-            node_id: value.comment_id.to_string(),
+            id: CommentId(comment_id),
+            node_id: comment_node_id(&value.repo, value.pr, comment_id),
             url: url.clone(),
             html_url: url,
             body: Some(value.content.clone()),
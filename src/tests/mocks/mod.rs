@@ -7,18 +7,27 @@ use regex::Regex;
 use wiremock::matchers::{method, path_regex};
 use wiremock::{Mock, Request, ResponseTemplate};
 
-use crate::TeamApiClient;
+use crate::PermissionSource;
 use crate::github::GithubRepoName;
 use crate::tests::mocks::github::GitHubMockServer;
 use crate::tests::mocks::permissions::TeamApiMockServer;
 
+pub use app::GitHubApp;
+pub use app::default_app_id;
 pub use bors::BorsBuilder;
+pub use bors::BorsTester;
+pub use bors::TEST_ADMIN_TOKEN;
 pub use bors::run_test;
 pub use comment::Comment;
+pub use github::GITHUB_MOCK_PRIVATE_KEY;
+pub use github::mock_app_repositories;
 pub use permissions::Permissions;
 pub use pull_request::default_pr_number;
 pub use repository::Branch;
+pub use repository::CheckRunData;
+pub use repository::PullRequest;
 pub use repository::Repo;
+pub use repository::WorkflowJob;
 pub use repository::default_branch_name;
 pub use repository::default_repo_name;
 pub use user::User;
@@ -127,11 +136,18 @@ impl ExternalHttpMock {
         self.gh_server.client()
     }
 
-    pub fn team_api_client(&self) -> TeamApiClient {
-        self.team_api_server.client()
+    pub fn permission_source(&self) -> PermissionSource {
+        self.team_api_server.client().into()
     }
 }
 
+/// Replaces durations of workflows in the table of a build result comment with `[duration]`,
+/// because they depend on how fast the test runs.
+pub fn mask_durations(comment: &str) -> String {
+    let regex = Regex::new(r"(?m)\| (\d+[hms] ?)+ \|$").unwrap();
+    regex.replace_all(comment, "| [duration] |").into_owned()
+}
+
 /// Create a mock that dynamically responds to its requests using the given function `f`.
 /// It is expected that the path will be a regex, which will be parsed when a request is received,
 /// and matched capture groups will be passed as a second argument to `f`.
@@ -1,6 +1,6 @@
 use super::{
     Repo, User,
-    comment::{Comment, GitHubComment},
+    comment::{Comment, GitHubComment, comment_node_id},
     default_repo_name, dynamic_mock_req,
     repository::GitHubRepository,
     user::GitHubUser,
@@ -36,10 +36,14 @@ pub async fn mock_pull_requests(
         Mock::given(method("GET"))
             .and(path(format!("/repos/{repo_name}/pulls/{pr_number}")))
             .respond_with(move |_: &Request| {
-                let pull_request_error = repo_clone.lock().pull_request_error;
-                if pull_request_error {
+                let mut repo = repo_clone.lock();
+                if repo.pull_request_error {
                     ResponseTemplate::new(500)
-                } else if let Some(pr) = repo_clone.lock().pull_requests.get(&pr_number) {
+                } else if repo.pull_request_failures > 0 {
+                    repo.pull_request_failures -= 1;
+                    ResponseTemplate::new(502)
+                        .set_body_json(serde_json::json!({ "message": "Bad Gateway" }))
+                } else if let Some(pr) = repo.pull_requests.get(&pr_number) {
                     ResponseTemplate::new(200).set_body_json(GitHubPullRequest::from(pr.clone()))
                 } else {
                     ResponseTemplate::new(404)
@@ -48,9 +52,90 @@ pub async fn mock_pull_requests(
             .mount(mock_server)
             .await;
 
+        let repo_clone = repo.clone();
+        Mock::given(method("PATCH"))
+            .and(path(format!("/repos/{repo_name}/pulls/{pr_number}")))
+            .respond_with(move |request: &Request| {
+                let mut repo = repo_clone.lock();
+                let data: serde_json::Value = request.body_json().unwrap();
+                let pr = repo.get_pr_mut(pr_number);
+                if data["state"] == "closed" {
+                    pr.status = PullRequestStatus::Closed;
+                }
+                ResponseTemplate::new(200).set_body_json(GitHubPullRequest::from(pr.clone()))
+            })
+            .mount(mock_server)
+            .await;
+
+        mock_pr_commits(repo.clone(), repo_name.clone(), pr_number, mock_server).await;
         mock_pr_comments(repo.clone(), pr_number, comments_tx.clone(), mock_server).await;
         mock_pr_labels(repo.clone(), repo_name.clone(), pr_number, mock_server).await;
     }
+
+    // List open pull requests
+    let repo_clone = repo.clone();
+    Mock::given(method("GET"))
+        .and(path(format!("/repos/{repo_name}/pulls")))
+        .respond_with(move |_: &Request| {
+            let repo = repo_clone.lock();
+            let prs: Vec<GitHubPullRequest> = repo
+                .pull_requests
+                .values()
+                .filter(|pr| {
+                    matches!(
+                        pr.status,
+                        PullRequestStatus::Open | PullRequestStatus::Draft
+                    )
+                })
+                .map(|pr| GitHubPullRequest::from(pr.clone()))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(prs)
+        })
+        .mount(mock_server)
+        .await;
+    mock_comment_edits(repo, comments_tx, mock_server).await;
+}
+
+/// Lists the commits of the PR. Each commit is a child of the preceding commit of the PR, the
+/// oldest commit is a child of the base branch.
+async fn mock_pr_commits(
+    repo: Arc<Mutex<Repo>>,
+    repo_name: GithubRepoName,
+    pr_number: u64,
+    mock_server: &MockServer,
+) {
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/repos/{repo_name}/pulls/{pr_number}/commits"
+        )))
+        .respond_with(move |_: &Request| {
+            let repo = repo.lock();
+            let pr = repo.get_pr(pr_number);
+            let mut parent = pr.base_branch.sha.clone();
+            let commits: Vec<serde_json::Value> = pr
+                .commits()
+                .into_iter()
+                .map(|sha| {
+                    let commit = serde_json::json!({
+                        "sha": sha,
+                        "commit": {
+                            "tree": { "sha": repo.get_tree(&sha) },
+                            "message": format!("Commit {sha}"),
+                            "author": {
+                                "name": pr.author.name,
+                                "email": format!("{}@example.com", pr.author.name),
+                            },
+                        },
+                        "parents": [{ "sha": parent }],
+                    });
+                    parent = sha;
+                    commit
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(commits)
+        })
+        .mount(mock_server)
+        .await;
 }
 
 async fn mock_pr_comments(
@@ -72,8 +157,22 @@ async fn mock_pr_comments(
 
             let comment_payload: CommentCreatePayload = req.body_json().unwrap();
             let mut repo = repo.lock();
+            if repo.comment_error {
+                repo.rejected_comments.push(comment_payload.body);
+                return ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                    "message": "Not Found",
+                    "documentation_url": "https://docs.github.com/rest"
+                }));
+            }
             let pr = repo.pull_requests.get_mut(&pr_number).unwrap();
+            if pr.locked {
+                return ResponseTemplate::new(403).set_body_json(serde_json::json!({
+                    "message": "Unable to create comment because issue is locked.",
+                    "documentation_url": "https://docs.github.com/articles/locking-conversations/"
+                }));
+            }
             let comment_id = pr.next_comment_id();
+            pr.bot_comments.push(comment_id);
 
             let comment = Comment::new(repo_name.clone(), pr_number, &comment_payload.body)
                 .with_author(User::bors_bot())
@@ -89,6 +188,104 @@ async fn mock_pr_comments(
         .await;
 }
 
+/// Edited comments are sent to `comments_tx`, same as newly created comments.
+async fn mock_comment_edits(
+    repo: Arc<Mutex<Repo>>,
+    comments_tx: Sender<Comment>,
+    mock_server: &MockServer,
+) {
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |req: &Request, [comment_id]: [&str; 1]| {
+            #[derive(Deserialize)]
+            struct CommentUpdatePayload {
+                body: String,
+            }
+
+            let comment_payload: CommentUpdatePayload = req.body_json().unwrap();
+            let comment_id: u64 = comment_id.parse().unwrap();
+            let repo = repo.lock();
+            let Some(pr) = repo
+                .pull_requests
+                .values()
+                .find(|pr| pr.bot_comments.contains(&comment_id))
+            else {
+                return ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                    "message": "Not Found",
+                    "documentation_url": "https://docs.github.com/rest/issues/comments"
+                }));
+            };
+
+            let comment = Comment::new(repo.name.clone(), pr.number.0, &comment_payload.body)
+                .with_author(User::bors_bot())
+                .with_id(comment_id);
+            comments_tx.try_send(comment.clone()).unwrap();
+            ResponseTemplate::new(200).set_body_json(GitHubComment::from(comment))
+        },
+        "PATCH",
+        format!("/repos/{repo_name}/issues/comments/(.*)"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
+/// Handles the GraphQL `minimizeComment` mutation, recording the minimized comments in
+/// [PullRequest::minimized_comments].
+pub async fn mock_minimize_comment(repos: Vec<Arc<Mutex<Repo>>>, mock_server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/graphql"))
+        .respond_with(move |req: &Request| {
+            #[derive(Deserialize)]
+            struct GraphQlRequest {
+                query: String,
+                variables: serde_json::Value,
+            }
+
+            let request: GraphQlRequest = req.body_json().unwrap();
+            let node_id = request.variables["node_id"].as_str().unwrap_or_default();
+            if !request.query.contains("minimizeComment")
+                || !request.query.contains("classifier: OUTDATED")
+            {
+                return graphql_error(&format!("Unexpected GraphQL query {}", request.query));
+            }
+
+            for repo in &repos {
+                let mut repo = repo.lock();
+                let repo_name = repo.name.clone();
+                for pr in repo.pull_requests.values_mut() {
+                    let comment_id = pr
+                        .bot_comments
+                        .iter()
+                        .copied()
+                        .find(|&id| comment_node_id(&repo_name, pr.number.0, id) == node_id);
+                    if let Some(comment_id) = comment_id {
+                        pr.minimized_comments.push(comment_id);
+                        return ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                            "data": {
+                                "minimizeComment": {
+                                    "minimizedComment": { "isMinimized": true }
+                                }
+                            }
+                        }));
+                    }
+                }
+            }
+            graphql_error(&format!(
+                "Could not resolve to a node with the global id of '{node_id}'"
+            ))
+        })
+        .mount(mock_server)
+        .await;
+}
+
+/// GitHub reports GraphQL errors with a successful status code.
+fn graphql_error(message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "data": null,
+        "errors": [{ "message": message }]
+    }))
+}
+
 async fn mock_pr_labels(
     repo: Arc<Mutex<Repo>>,
     repo_name: GithubRepoName,
@@ -113,20 +310,17 @@ async fn mock_pr_labels(
                 return ResponseTemplate::new(404);
             };
             pr.added_labels.extend(data.labels.clone());
+            for label in data.labels {
+                if !pr.labels.contains(&label) {
+                    pr.labels.push(label);
+                }
+            }
 
-            let labels: Vec<GitHubLabel> = data
+            // GitHub returns all labels of the issue
+            let labels: Vec<GitHubLabel> = pr
                 .labels
-                .into_iter()
-                .map(|label| GitHubLabel {
-                    id: 1.into(),
-                    node_id: "".to_string(),
-                    url: format!("https://github.com/labels/{label}")
-                        .parse()
-                        .unwrap(),
-                    name: label.to_string(),
-                    color: "blue".to_string(),
-                    default: false,
-                })
+                .iter()
+                .map(|label| GitHubLabel::new(label))
                 .collect();
             ResponseTemplate::new(200).set_body_json(labels)
         })
@@ -141,6 +335,13 @@ async fn mock_pr_labels(
                 return ResponseTemplate::new(404);
             };
             pr.removed_labels.push(label_name.to_string());
+            if !pr.labels.iter().any(|label| label == label_name) {
+                return ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                    "message": "Label does not exist",
+                    "documentation_url": "https://docs.github.com/rest/issues/labels"
+                }));
+            }
+            pr.labels.retain(|label| label != label_name);
 
             ResponseTemplate::new(200).set_body_json::<&[GitHubLabel]>(&[])
         },
@@ -168,6 +369,7 @@ pub struct GitHubPullRequest {
 
     head: Box<GitHubHead>,
     base: Box<GitHubBase>,
+    labels: Vec<GitHubLabel>,
 
     user: GitHubUser,
 }
@@ -179,7 +381,7 @@ impl From<PullRequest> for GitHubPullRequest {
             user: pr.author.clone().into(),
             url: "https://test.com".to_string(),
             id: number + 1000,
-            title: format!("PR #{number}"),
+            title: pr.title,
             body: format!("Description of PR #{number}"),
             mergeable_state: pr.mergeable_state,
             draft: pr.status == PullRequestStatus::Draft,
@@ -188,11 +390,17 @@ impl From<PullRequest> for GitHubPullRequest {
                 label: format!("pr-{number}"),
                 ref_field: format!("pr-{number}"),
                 sha: pr.head_sha,
+                repo: pr.head_repo.into(),
             }),
             base: Box::new(GitHubBase {
                 ref_field: pr.base_branch.get_name().to_string(),
                 sha: pr.base_branch.get_sha().to_string(),
             }),
+            labels: pr
+                .labels
+                .iter()
+                .map(|label| GitHubLabel::new(label))
+                .collect(),
             merged_at: pr.merged_at,
         }
     }
@@ -204,6 +412,7 @@ struct GitHubHead {
     #[serde(rename = "ref")]
     ref_field: String,
     sha: String,
+    repo: GitHubRepository,
 }
 
 #[derive(Serialize)]
@@ -223,11 +432,26 @@ struct GitHubLabel {
     default: bool,
 }
 
+impl GitHubLabel {
+    fn new(name: &str) -> Self {
+        Self {
+            id: 1.into(),
+            node_id: "".to_string(),
+            url: format!("https://github.com/labels/{name}").parse().unwrap(),
+            name: name.to_string(),
+            color: "blue".to_string(),
+            default: false,
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub(super) struct GitHubPullRequestEventPayload {
     action: String,
     pull_request: GitHubPullRequest,
     changes: Option<GitHubPullRequestChanges>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<GitHubLabel>,
     repository: GitHubRepository,
 }
 
@@ -242,9 +466,174 @@ impl GitHubPullRequestEventPayload {
             action: action.to_string(),
             pull_request: pull_request.into(),
             changes: changes.map(Into::into),
+            label: None,
             repository: repository.into(),
         }
     }
+
+    /// Sets the label of a `labeled` or `unlabeled` event.
+    pub fn with_label(self, label: &str) -> Self {
+        Self {
+            label: Some(GitHubLabel::new(label)),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(super) struct GitHubPullRequestReviewEventPayload {
+    action: String,
+    pull_request: GitHubPullRequest,
+    review: GitHubReview,
+    repository: GitHubRepository,
+    sender: GitHubUser,
+}
+
+impl GitHubPullRequestReviewEventPayload {
+    pub fn new(pull_request: PullRequest, author: User, state: &str, body: &str) -> Self {
+        let repository = pull_request.repo.clone();
+        let html_url = format!(
+            "https://github.com/{repository}/pull/{}#pullrequestreview-1",
+            pull_request.number.0
+        );
+        GitHubPullRequestReviewEventPayload {
+            action: "submitted".to_string(),
+            review: GitHubReview {
+                id: 1,
+                node_id: "1".to_string(),
+                html_url: Url::parse(&html_url).unwrap(),
+                user: author.clone().into(),
+                body: body.to_string(),
+                commit_id: pull_request.head_sha.clone(),
+                state: state.to_string(),
+                submitted_at: Utc::now(),
+            },
+            pull_request: pull_request.into(),
+            repository: repository.into(),
+            sender: author.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GitHubReview {
+    id: u64,
+    node_id: String,
+    html_url: Url,
+    user: GitHubUser,
+    body: String,
+    commit_id: String,
+    state: String,
+    submitted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub(super) struct GitHubPullRequestReviewCommentEventPayload {
+    action: String,
+    pull_request: GitHubPullRequest,
+    comment: GitHubReviewComment,
+    repository: GitHubRepository,
+    sender: GitHubUser,
+}
+
+impl GitHubPullRequestReviewCommentEventPayload {
+    /// Creates a review comment attached to a line of a file changed by the PR.
+    pub fn new(pull_request: PullRequest, author: User, id: u64, body: &str) -> Self {
+        let repository = pull_request.repo.clone();
+        let url = |path: String| Url::parse(&path).unwrap();
+        let pr_url = url(format!(
+            "https://api.github.com/repos/{repository}/pulls/{}",
+            pull_request.number.0
+        ));
+        let html_url = url(format!(
+            "https://github.com/{repository}/pull/{}#discussion_r{id}",
+            pull_request.number.0
+        ));
+        let comment_url = url(format!(
+            "https://api.github.com/repos/{repository}/pulls/comments/{id}"
+        ));
+        let time = Utc::now();
+        GitHubPullRequestReviewCommentEventPayload {
+            action: "created".to_string(),
+            comment: GitHubReviewComment {
+                url: comment_url.clone(),
+                pull_request_review_id: 1,
+                id,
+                node_id: id.to_string(),
+                diff_hunk: "@@ -0,0 +1,1 @@\n+fn main() {}".to_string(),
+                path: "src/main.rs".to_string(),
+                commit_id: pull_request.head_sha.clone(),
+                original_commit_id: pull_request.head_sha.clone(),
+                user: author.clone().into(),
+                body: body.to_string(),
+                created_at: time,
+                updated_at: time,
+                html_url: html_url.clone(),
+                pull_request_url: pr_url.clone(),
+                author_association: "OWNER".to_string(),
+                links: GitHubReviewCommentLinks {
+                    self_link: GitHubLink { href: comment_url },
+                    html: GitHubLink { href: html_url },
+                    pull_request: GitHubLink { href: pr_url },
+                },
+                start_line: None,
+                original_start_line: None,
+                start_side: None,
+                line: Some(1),
+                original_line: Some(1),
+                side: "RIGHT".to_string(),
+                position: Some(1),
+                original_position: Some(1),
+                subject_type: "line".to_string(),
+            },
+            pull_request: pull_request.into(),
+            repository: repository.into(),
+            sender: author.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GitHubReviewComment {
+    url: Url,
+    pull_request_review_id: u64,
+    id: u64,
+    node_id: String,
+    diff_hunk: String,
+    path: String,
+    commit_id: String,
+    original_commit_id: String,
+    user: GitHubUser,
+    body: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    html_url: Url,
+    pull_request_url: Url,
+    author_association: String,
+    #[serde(rename = "_links")]
+    links: GitHubReviewCommentLinks,
+    start_line: Option<u64>,
+    original_start_line: Option<u64>,
+    start_side: Option<String>,
+    line: Option<u64>,
+    original_line: Option<u64>,
+    side: String,
+    position: Option<u64>,
+    original_position: Option<u64>,
+    subject_type: String,
+}
+
+#[derive(Serialize)]
+struct GitHubReviewCommentLinks {
+    #[serde(rename = "self")]
+    self_link: GitHubLink,
+    html: GitHubLink,
+    pull_request: GitHubLink,
+}
+
+#[derive(Serialize)]
+struct GitHubLink {
+    href: Url,
 }
 
 #[derive(Serialize)]
@@ -288,14 +677,28 @@ pub struct GitHubPushEventPayload {
     pub repository: GitHubRepository,
     #[serde(rename = "ref")]
     pub ref_field: String,
+    pub after: String,
+    pub commits: Vec<GitHubPushCommit>,
+}
+
+#[derive(Serialize)]
+pub struct GitHubPushCommit {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
 }
 
 impl GitHubPushEventPayload {
-    #[allow(unused)]
-    pub fn new(branch_name: &str) -> Self {
+    pub fn new(branch_name: &str, sha: &str, modified_files: &[&str]) -> Self {
         GitHubPushEventPayload {
             repository: default_repo_name().into(),
             ref_field: format!("refs/heads/{branch_name}"),
+            after: sha.to_string(),
+            commits: vec![GitHubPushCommit {
+                added: vec![],
+                removed: vec![],
+                modified: modified_files.iter().map(|f| f.to_string()).collect(),
+            }],
         }
     }
 }
@@ -29,26 +29,52 @@ use super::user::{GitHubUser, User};
 pub struct PullRequest {
     pub number: PullRequestNumber,
     pub repo: GithubRepoName,
+    /// Labels currently attached to the PR.
+    pub labels: Vec<String>,
     pub added_labels: Vec<String>,
     pub removed_labels: Vec<String>,
     pub comment_counter: u64,
+    /// IDs of comments posted by the bot that still exist.
+    pub bot_comments: Vec<u64>,
+    /// IDs of comments that were minimized through the GraphQL API.
+    pub minimized_comments: Vec<u64>,
     pub head_sha: String,
+    /// SHAs of the commits of the PR that precede its head commit, from the oldest one.
+    pub previous_commits: Vec<String>,
+    pub title: String,
     pub author: User,
     pub base_branch: Branch,
     pub mergeable_state: MergeableState,
     pub status: PullRequestStatus,
     pub merged_at: Option<DateTime<Utc>>,
+    /// Repository from which the PR originates, differs from `repo` for PRs from forks.
+    pub head_repo: GithubRepoName,
+    /// Comments cannot be posted to locked PRs.
+    pub locked: bool,
 }
 
 impl PullRequest {
+    /// SHAs of all commits of the PR, from the oldest one.
+    pub fn commits(&self) -> Vec<String> {
+        let mut commits = self.previous_commits.clone();
+        commits.push(self.head_sha.clone());
+        commits
+    }
+
     pub fn new(repo: GithubRepoName, number: u64, author: User, is_draft: bool) -> Self {
         Self {
             number: PullRequestNumber(number),
+            head_repo: repo.clone(),
             repo,
+            labels: Vec::new(),
             added_labels: Vec::new(),
             removed_labels: Vec::new(),
             comment_counter: 0,
+            bot_comments: Vec::new(),
+            minimized_comments: Vec::new(),
             head_sha: format!("pr-{number}-sha"),
+            previous_commits: Vec::new(),
+            title: format!("PR #{number}"),
             author,
             base_branch: Branch::default(),
             mergeable_state: MergeableState::Clean,
@@ -58,6 +84,7 @@ impl PullRequest {
                 PullRequestStatus::Open
             },
             merged_at: None,
+            locked: false,
         }
     }
 }
@@ -115,7 +142,96 @@ pub struct Repo {
     pub pull_requests: HashMap<u64, PullRequest>,
     // Cause pull request fetch to fail.
     pub pull_request_error: bool,
+    /// Number of the following pull request fetches that fail with a server error.
+    pub pull_request_failures: u32,
     pub pr_push_counter: u64,
+    /// Names of users with admin permission in the repository.
+    pub admins: Vec<String>,
+    /// Check runs created by bors.
+    pub check_runs: Vec<CheckRunData>,
+    /// Commit statuses set by bors, in the order in which they were set.
+    pub commit_statuses: Vec<CommitStatusData>,
+    /// Members of teams of the organization that owns the repository, keyed by team slug.
+    pub teams: HashMap<String, Vec<User>>,
+    /// Reactions added to issue comments, as `(comment ID, reaction)` pairs.
+    pub comment_reactions: Vec<(u64, String)>,
+    /// Jobs of Github Actions workflow runs, keyed by run ID.
+    pub workflow_jobs: HashMap<u64, Vec<WorkflowJob>>,
+    // Cause job log downloads to fail.
+    pub job_log_error: bool,
+    // Cause posting of comments to fail.
+    pub comment_error: bool,
+    /// Bodies of comments whose posting failed because of `comment_error`.
+    pub rejected_comments: Vec<String>,
+    /// Commits created through the git database API and by merges, keyed by SHA.
+    pub git_commits: HashMap<String, GitCommitData>,
+    pub git_commit_counter: u64,
+}
+
+/// A commit stored in the git database of the mocked repository.
+///
+/// Trees are modeled as the sorted SHAs of the commits whose changes they contain, joined by
+/// `+`, so that trees created by different means (e.g. by a merge or by replaying the commits
+/// of a PR) can be compared.
+#[derive(Clone, Debug)]
+pub struct GitCommitData {
+    pub tree: String,
+    pub parents: Vec<String>,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+}
+
+/// A job of a Github Actions workflow run, along with its log.
+#[derive(Clone)]
+pub struct WorkflowJob {
+    pub id: u64,
+    pub name: String,
+    pub conclusion: String,
+    pub log: String,
+}
+
+impl WorkflowJob {
+    pub fn failed(id: u64, name: &str, log: &str) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            conclusion: "failure".to_string(),
+            log: log.to_string(),
+        }
+    }
+}
+
+/// A check run created through the GitHub API.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CheckRunData {
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+    pub head_sha: String,
+    pub status: String,
+    /// Empty while the check run is not completed.
+    #[serde(default)]
+    pub conclusion: String,
+    pub output: CheckRunOutputData,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CheckRunOutputData {
+    pub title: String,
+    pub summary: String,
+}
+
+/// A commit status set through the GitHub API.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CommitStatusData {
+    /// Filled in from the request path.
+    #[serde(default)]
+    pub sha: String,
+    pub state: String,
+    pub description: String,
+    pub context: String,
+    pub target_url: Option<String>,
 }
 
 impl Repo {
@@ -129,7 +245,19 @@ impl Repo {
             cancelled_workflows: vec![],
             workflow_cancel_error: false,
             pull_request_error: false,
+            pull_request_failures: 0,
             pr_push_counter: 0,
+            admins: vec![],
+            check_runs: vec![],
+            commit_statuses: vec![],
+            teams: HashMap::default(),
+            comment_reactions: vec![],
+            workflow_jobs: HashMap::default(),
+            job_log_error: false,
+            comment_error: false,
+            rejected_comments: vec![],
+            git_commits: HashMap::default(),
+            git_commit_counter: 0,
         }
     }
 
@@ -164,6 +292,23 @@ impl Repo {
         self.cancelled_workflows.push(run_id);
     }
 
+    /// Returns the tree of the given commit.
+    /// Commits of PRs contain the changes of all preceding commits of the PR, commits that are
+    /// not known to the mock (e.g. the commits of branches) contain only their own changes.
+    pub fn get_tree(&self, sha: &str) -> String {
+        if let Some(commit) = self.git_commits.get(sha) {
+            return commit.tree.clone();
+        }
+        self.pull_requests
+            .values()
+            .find_map(|pr| {
+                let commits = pr.commits();
+                let index = commits.iter().position(|commit| commit == sha)?;
+                Some(commits[..=index].join("+"))
+            })
+            .unwrap_or_else(|| sha.to_string())
+    }
+
     pub fn get_next_pr_push_counter(&mut self) -> u64 {
         self.pr_push_counter += 1;
         self.pr_push_counter
@@ -236,6 +381,10 @@ impl Branch {
     pub fn get_sha(&self) -> &str {
         &self.sha
     }
+
+    pub fn get_commit_message(&self) -> &str {
+        &self.commit_message
+    }
     pub fn get_suites(&self) -> &[CheckSuiteStatus] {
         &self.suite_statuses
     }
@@ -260,12 +409,27 @@ impl Branch {
             self.name
         );
     }
+    /// Marks a finished suite as pending again, e.g. because its workflow has been re-run.
+    pub fn suite_restarted(&mut self) {
+        if let Some(suite) = self
+            .suite_statuses
+            .iter_mut()
+            .find(|suite| !matches!(suite, CheckSuiteStatus::Pending))
+        {
+            *suite = CheckSuiteStatus::Pending;
+        }
+    }
     pub fn reset_suites(&mut self) {
         for suite in self.suite_statuses.iter_mut() {
             *suite = CheckSuiteStatus::Pending;
         }
     }
 
+    /// Adds a commit to the history of the branch, before all of its other commits.
+    pub fn add_ancestor(&mut self, sha: &str) {
+        self.sha_history.insert(0, sha.to_string());
+    }
+
     pub fn set_to_sha(&mut self, sha: &str) {
         self.sha_history.push(self.sha.clone());
         self.sha = sha.to_string();
@@ -292,7 +456,7 @@ pub fn default_branch_sha() -> &'static str {
     "main-sha1"
 }
 
-pub async fn mock_repo_list(github: &GitHubState, mock_server: &MockServer) {
+pub async fn mock_repo_list(github: &GitHubState, mock_server: &MockServer, prefix: &str) {
     let repos = GitHubRepositories {
         total_count: github.repos.len() as u64,
         repositories: github
@@ -306,18 +470,56 @@ pub async fn mock_repo_list(github: &GitHubState, mock_server: &MockServer) {
                     owner: User::new(index as u64, repo.name.owner()).into(),
                     name: repo.name.name().to_string(),
                     url: format!("https://{}.foo", repo.name.name()).parse().unwrap(),
+                    default_branch: default_branch_name().to_string(),
                 }
             })
             .collect(),
     };
 
     Mock::given(method("GET"))
-        .and(path("/installation/repositories"))
+        .and(path(format!("{prefix}/installation/repositories")))
         .respond_with(ResponseTemplate::new(200).set_body_json(repos))
         .mount(mock_server)
         .await;
 }
 
+/// Resolves usernames to users. Besides the predefined test users, users that have permissions
+/// in a repository or that are members of one of its teams are known.
+pub async fn mock_users(github: &GitHubState, mock_server: &MockServer) {
+    let repos: Vec<Arc<Mutex<Repo>>> = github.repos.values().cloned().collect();
+    dynamic_mock_req(
+        move |_req: &Request, [username]: [&str; 1]| {
+            let mut users = vec![
+                User::default_pr_author(),
+                User::bors_bot(),
+                User::unprivileged(),
+                User::try_user(),
+                User::reviewer(),
+            ];
+            for repo in &repos {
+                let repo = repo.lock();
+                users.extend(repo.permissions.users.keys().cloned());
+                users.extend(repo.teams.values().flatten().cloned());
+            }
+            match users
+                .into_iter()
+                .find(|user| user.name.eq_ignore_ascii_case(username))
+            {
+                Some(user) => ResponseTemplate::new(200)
+                    .set_body_json(GitHubUser::new(&user.name, user.github_id)),
+                None => ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                    "message": "Not Found",
+                    "documentation_url": "https://docs.github.com/rest/users/users#get-a-user"
+                })),
+            }
+        },
+        "GET",
+        "^/users/([^/]+)$".to_string(),
+    )
+    .mount(mock_server)
+    .await;
+}
+
 pub async fn mock_repo(
     repo: Arc<Mutex<Repo>>,
     comments_tx: Sender<Comment>,
@@ -326,14 +528,24 @@ pub async fn mock_repo(
     mock_pull_requests(repo.clone(), comments_tx, mock_server).await;
     mock_branches(repo.clone(), mock_server).await;
     mock_cancel_workflow(repo.clone(), mock_server).await;
-    mock_config(repo, mock_server).await;
+    mock_workflow_jobs(repo.clone(), mock_server).await;
+    mock_create_check_run(repo.clone(), mock_server).await;
+    mock_update_check_run(repo.clone(), mock_server).await;
+    mock_commit_statuses(repo.clone(), mock_server).await;
+    mock_comment_reactions(repo.clone(), mock_server).await;
+    mock_collaborator_permission(repo.clone(), mock_server).await;
+    mock_team_members(repo.clone(), mock_server).await;
+    mock_config(repo, mock_server, "").await;
 }
 
 async fn mock_branches(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
     mock_get_branch(repo.clone(), mock_server).await;
     mock_create_branch(repo.clone(), mock_server).await;
     mock_update_branch(repo.clone(), mock_server).await;
+    mock_delete_branch(repo.clone(), mock_server).await;
     mock_merge_branch(repo.clone(), mock_server).await;
+    mock_compare_commits(repo.clone(), mock_server).await;
+    mock_git_commits(repo.clone(), mock_server).await;
     mock_check_suites(repo, mock_server).await;
 }
 
@@ -357,6 +569,195 @@ async fn mock_cancel_workflow(repo: Arc<Mutex<Repo>>, mock_server: &MockServer)
     .await;
 }
 
+async fn mock_workflow_jobs(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    let repo_clone = repo.clone();
+    dynamic_mock_req(
+        move |_req: &Request, [run_id]: [&str; 1]| {
+            let run_id: u64 = run_id.parse().unwrap();
+            let jobs = repo_clone
+                .lock()
+                .workflow_jobs
+                .get(&run_id)
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|job| {
+                    serde_json::json!({
+                        "id": job.id,
+                        "name": job.name,
+                        "conclusion": job.conclusion,
+                    })
+                })
+                .collect::<Vec<_>>();
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "total_count": jobs.len(),
+                "jobs": jobs,
+            }))
+        },
+        "GET",
+        format!("^/repos/{repo_name}/actions/runs/([0-9]+)/jobs$"),
+    )
+    .mount(mock_server)
+    .await;
+
+    dynamic_mock_req(
+        move |_req: &Request, [job_id]: [&str; 1]| {
+            let job_id: u64 = job_id.parse().unwrap();
+            let repo = repo.lock();
+            if repo.job_log_error {
+                return ResponseTemplate::new(500);
+            }
+            let log = repo
+                .workflow_jobs
+                .values()
+                .flatten()
+                .find(|job| job.id == job_id)
+                .map(|job| job.log.clone());
+            match log {
+                Some(log) => ResponseTemplate::new(200).set_body_string(log),
+                None => ResponseTemplate::new(404),
+            }
+        },
+        "GET",
+        format!("^/repos/{repo_name}/actions/jobs/([0-9]+)/logs$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
+async fn mock_create_check_run(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    Mock::given(method("POST"))
+        .and(path(format!("/repos/{repo_name}/check-runs")))
+        .respond_with(move |request: &Request| {
+            let mut data: CheckRunData = request.body_json().unwrap();
+            let mut repo = repo.lock();
+            data.id = repo.check_runs.len() as u64 + 1;
+            let id = data.id;
+            repo.check_runs.push(data);
+            ResponseTemplate::new(201).set_body_json(serde_json::json!({ "id": id }))
+        })
+        .mount(mock_server)
+        .await;
+}
+
+async fn mock_update_check_run(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    #[derive(serde::Deserialize)]
+    struct UpdateCheckRunPayload {
+        status: String,
+        conclusion: String,
+        output: CheckRunOutputData,
+    }
+
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |req: &Request, [check_run_id]: [&str; 1]| {
+            let payload: UpdateCheckRunPayload = req.body_json().unwrap();
+            let check_run_id: u64 = check_run_id.parse().unwrap();
+            let mut repo = repo.lock();
+            let Some(check_run) = repo.check_runs.iter_mut().find(|c| c.id == check_run_id) else {
+                return ResponseTemplate::new(404);
+            };
+            check_run.status = payload.status;
+            check_run.conclusion = payload.conclusion;
+            check_run.output = payload.output;
+            ResponseTemplate::new(200)
+        },
+        "PATCH",
+        format!("^/repos/{repo_name}/check-runs/([0-9]+)$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
+async fn mock_commit_statuses(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |req: &Request, [sha]: [&str; 1]| {
+            let mut status: CommitStatusData = req.body_json().unwrap();
+            status.sha = sha.to_string();
+            repo.lock().commit_statuses.push(status);
+            ResponseTemplate::new(201)
+        },
+        "POST",
+        format!("^/repos/{repo_name}/statuses/(.*)$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
+async fn mock_comment_reactions(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |req: &Request, [comment_id]: [&str; 1]| {
+            #[derive(serde::Deserialize)]
+            struct ReactionPayload {
+                content: String,
+            }
+
+            let payload: ReactionPayload = req.body_json().unwrap();
+            let comment_id: u64 = comment_id.parse().unwrap();
+            let mut repo = repo.lock();
+            repo.comment_reactions
+                .push((comment_id, payload.content.clone()));
+            let reaction_id = repo.comment_reactions.len();
+            ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "id": reaction_id,
+                "node_id": format!("REA_{reaction_id}"),
+                "user": GitHubUser::from(User::bors_bot()),
+                "content": payload.content,
+                "created_at": Utc::now(),
+            }))
+        },
+        "POST",
+        format!("^/repos/{repo_name}/issues/comments/([0-9]+)/reactions$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
+async fn mock_collaborator_permission(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |_req: &Request, [username]: [&str; 1]| {
+            let permission = if repo.lock().admins.iter().any(|admin| admin == username) {
+                "admin"
+            } else {
+                "write"
+            };
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "permission": permission
+            }))
+        },
+        "GET",
+        format!("^/repos/{repo_name}/collaborators/(.*)/permission$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
+async fn mock_team_members(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let org = repo.lock().name.owner().to_string();
+    dynamic_mock_req(
+        move |_req: &Request, [team]: [&str; 1]| {
+            let repo = repo.lock();
+            let Some(members) = repo.teams.get(team) else {
+                return ResponseTemplate::new(404);
+            };
+            let members = members
+                .iter()
+                .map(|user| GitHubUser::new(&user.name, user.github_id))
+                .collect::<Vec<_>>();
+            ResponseTemplate::new(200).set_body_json(members)
+        },
+        "GET",
+        format!("^/orgs/{org}/teams/(.*)/members$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
 async fn mock_get_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
     let repo_name = repo.lock().name.clone();
     dynamic_mock_req(
@@ -441,6 +842,8 @@ async fn mock_update_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
             #[derive(serde::Deserialize)]
             struct SetRefRequest {
                 sha: String,
+                #[serde(default)]
+                force: bool,
             }
 
             let data: SetRefRequest = req.body_json().unwrap();
@@ -448,6 +851,14 @@ async fn mock_update_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
             let sha = data.sha;
             match repo.get_branch_by_name(branch_name) {
                 Some(branch) => {
+                    // Commits created by the mock contain the SHA of their first parent, which is
+                    // the only kind of descendant that the mock recognizes
+                    if !data.force && !sha.contains(&branch.sha) {
+                        return ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                            "message": "Update is not a fast forward",
+                            "documentation_url": "https://docs.github.com/rest/git/refs#update-a-reference"
+                        }));
+                    }
                     // Update branch
                     branch.set_to_sha(&sha);
                 }
@@ -465,6 +876,27 @@ async fn mock_update_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
     .await;
 }
 
+async fn mock_delete_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |_req: &Request, [branch_name]: [&str; 1]| {
+            let mut repo = repo.lock();
+            if repo.get_branch_by_name(branch_name).is_none() {
+                return ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                    "message": "Reference does not exist",
+                    "documentation_url": "https://docs.github.com/rest/git/refs#delete-a-reference"
+                }));
+            }
+            repo.branches.retain(|branch| branch.name != branch_name);
+            ResponseTemplate::new(204)
+        },
+        "DELETE",
+        format!("^/repos/{repo_name}/git/refs/heads/(.*)$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
 async fn mock_merge_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
     Mock::given(method("POST"))
         .and(path(format!("/repos/{}/merges", repo.lock().name)))
@@ -502,9 +934,22 @@ async fn mock_merge_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
                 "merge-{}-{head_sha}-{}",
                 base_branch.sha, base_branch.merge_counter
             );
+            let base_sha = base_branch.sha.clone();
             base_branch.merge_counter += 1;
             base_branch.set_to_sha(&merge_sha);
-            base_branch.commit_message = data.commit_message;
+            base_branch.commit_message = data.commit_message.clone();
+
+            let tree = merge_trees(&repo.get_tree(&base_sha), &repo.get_tree(&head_sha));
+            repo.git_commits.insert(
+                merge_sha.clone(),
+                GitCommitData {
+                    tree,
+                    parents: vec![base_sha, head_sha],
+                    message: data.commit_message,
+                    author_name: "bors".to_string(),
+                    author_email: "bors@example.com".to_string(),
+                },
+            );
 
             #[derive(serde::Serialize)]
             struct MergeResponse {
@@ -517,6 +962,127 @@ async fn mock_merge_branch(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
         .await;
 }
 
+/// Returns a tree that contains the changes of both given trees.
+fn merge_trees(a: &str, b: &str) -> String {
+    let commits: std::collections::BTreeSet<&str> = a.split('+').chain(b.split('+')).collect();
+    commits.into_iter().collect::<Vec<_>>().join("+")
+}
+
+async fn mock_git_commits(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    let repo_clone = repo.clone();
+    dynamic_mock_req(
+        move |_req: &Request, [sha]: [&str; 1]| {
+            let repo = repo_clone.lock();
+            let tree = repo.get_tree(sha);
+            let commit = repo.git_commits.get(sha).cloned().unwrap_or(GitCommitData {
+                tree,
+                parents: vec![],
+                message: String::new(),
+                author_name: String::new(),
+                author_email: String::new(),
+            });
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "sha": sha,
+                "tree": { "sha": commit.tree },
+                "parents": commit.parents.iter().map(|sha| serde_json::json!({ "sha": sha })).collect::<Vec<_>>(),
+                "message": commit.message,
+                "author": { "name": commit.author_name, "email": commit.author_email },
+            }))
+        },
+        "GET",
+        format!("^/repos/{repo_name}/git/commits/(.*)$"),
+    )
+    .mount(mock_server)
+    .await;
+
+    Mock::given(method("POST"))
+        .and(path(format!("/repos/{repo_name}/git/commits")))
+        .respond_with(move |request: &Request| {
+            let mut repo = repo.lock();
+
+            #[derive(serde::Deserialize)]
+            struct Author {
+                name: String,
+                email: String,
+            }
+
+            #[derive(serde::Deserialize)]
+            struct CreateCommitRequest {
+                message: String,
+                tree: String,
+                parents: Vec<String>,
+                author: Option<Author>,
+            }
+
+            let data: CreateCommitRequest = request.body_json().unwrap();
+            let counter = repo.git_commit_counter;
+            repo.git_commit_counter += 1;
+            let sha = match data.parents.first() {
+                Some(parent) => format!("commit-{counter}-{parent}"),
+                None => format!("commit-{counter}"),
+            };
+            let (author_name, author_email) = match data.author {
+                Some(author) => (author.name, author.email),
+                None => ("bors".to_string(), "bors@example.com".to_string()),
+            };
+            repo.git_commits.insert(
+                sha.clone(),
+                GitCommitData {
+                    tree: data.tree,
+                    parents: data.parents,
+                    message: data.message,
+                    author_name,
+                    author_email,
+                },
+            );
+            ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": sha }))
+        })
+        .mount(mock_server)
+        .await;
+}
+
+/// Compares a commit with a branch. The branch is ahead of the commit if the commit is in its
+/// SHA history, and it has diverged from commits that are only known from other branches.
+/// The commit can be abbreviated, it is resolved to the first known SHA with the same prefix.
+async fn mock_compare_commits(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
+    let repo_name = repo.lock().name.clone();
+    dynamic_mock_req(
+        move |_req: &Request, [base, head]: [&str; 2]| {
+            let mut repo = repo.lock();
+            let base = repo
+                .branches
+                .iter()
+                .flat_map(|branch| branch.get_sha_history())
+                .find(|sha| sha.starts_with(base));
+            let Some(branch) = repo.get_branch_by_name(head) else {
+                return ResponseTemplate::new(404);
+            };
+            let Some(base) = base else {
+                return ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                    "message": "Not Found",
+                    "documentation_url": "https://docs.github.com/rest/commits/commits#compare-two-commits"
+                }));
+            };
+            let status = if branch.sha == base {
+                "identical"
+            } else if branch.get_sha_history().contains(&base) {
+                "ahead"
+            } else {
+                "diverged"
+            };
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": status,
+                "base_commit": { "sha": base }
+            }))
+        },
+        "GET",
+        format!("^/repos/{repo_name}/compare/(.*)\\.\\.\\.(.*)$"),
+    )
+    .mount(mock_server)
+    .await;
+}
+
 async fn mock_check_suites(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
     #[derive(serde::Serialize)]
     struct CheckSuitePayload {
@@ -559,22 +1125,19 @@ async fn mock_check_suites(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
     .await;
 }
 
-async fn mock_config(repo: Arc<Mutex<Repo>>, mock_server: &MockServer) {
-    // Extracted into a block to avoid holding the lock over an await point
-    let mock = {
-        let repo = repo.lock();
-        Mock::given(method("GET"))
-            .and(path(format!(
-                "/repos/{}/contents/rust-bors.toml",
-                repo.name
-            )))
-            .respond_with(
-                ResponseTemplate::new(200)
-                    .set_body_json(GitHubContent::new("rust-bors.toml", &repo.config)),
-            )
-            .mount(mock_server)
-    };
-    mock.await;
+pub async fn mock_config(repo: Arc<Mutex<Repo>>, mock_server: &MockServer, prefix: &str) {
+    let repo_name = repo.lock().name.clone();
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "{prefix}/repos/{repo_name}/contents/rust-bors.toml"
+        )))
+        .respond_with(move |_: &Request| {
+            let repo = repo.lock();
+            ResponseTemplate::new(200)
+                .set_body_json(GitHubContent::new("rust-bors.toml", &repo.config))
+        })
+        .mount(mock_server)
+        .await;
 }
 
 /// Represents all repositories for an installation
@@ -591,6 +1154,7 @@ pub struct GitHubRepository {
     name: String,
     url: Url,
     owner: GitHubUser,
+    default_branch: String,
 }
 
 impl From<GithubRepoName> for GitHubRepository {
@@ -600,6 +1164,7 @@ impl From<GithubRepoName> for GitHubRepository {
             name: value.name().to_string(),
             owner: GitHubUser::new(value.owner(), 1001),
             url: format!("https://github.com/{}", value).parse().unwrap(),
+            default_branch: default_branch_name().to_string(),
         }
     }
 }
@@ -17,6 +17,11 @@ impl User {
         Self::new(102, "bors-bot")
     }
 
+    /// Bot user of the bors app, as it appears in the comments posted by the app.
+    pub fn bors_app_bot() -> Self {
+        Self::new(106, "bors[bot]")
+    }
+
     /// User that should not have any privileges.
     pub fn unprivileged() -> Self {
         Self::new(103, "unprivileged-user")
@@ -70,9 +70,13 @@ impl WorkflowEvent {
         }
     }
     pub fn failure<W: Into<Workflow>>(workflow: W) -> Self {
+        Self::completed(workflow, "failure")
+    }
+    /// Creates an event of a workflow that has finished with the given GitHub conclusion.
+    pub fn completed<W: Into<Workflow>>(workflow: W, conclusion: &str) -> Self {
         Self {
             event: WorkflowEventKind::Completed {
-                status: "failure".to_string(),
+                status: conclusion.to_string(),
             },
             workflow: workflow.into(),
         }
@@ -90,6 +94,7 @@ pub struct Workflow {
     pub repository: GithubRepoName,
     name: String,
     run_id: u64,
+    pub run_attempt: u64,
     pub head_branch: String,
     head_sha: String,
     pub external: bool,
@@ -99,6 +104,18 @@ impl Workflow {
     pub fn with_run_id(self, run_id: u64) -> Self {
         Self { run_id, ..self }
     }
+    pub fn with_run_attempt(self, run_attempt: u64) -> Self {
+        Self {
+            run_attempt,
+            ..self
+        }
+    }
+    pub fn with_name(self, name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..self
+        }
+    }
     pub fn make_external(mut self) -> Self {
         self.external = true;
         self
@@ -109,6 +126,7 @@ impl Workflow {
             repository: default_repo_name(),
             name: "Workflow1".to_string(),
             run_id: 1,
+            run_attempt: 1,
             head_branch: branch.get_name().to_string(),
             head_sha: branch.get_sha().to_string(),
             external: false,
@@ -160,6 +178,7 @@ impl From<WorkflowEvent> for GitHubWorkflowEventPayload {
                 head_branch: workflow.head_branch,
                 head_sha: workflow.head_sha,
                 run_number: 0,
+                run_attempt: workflow.run_attempt as i64,
                 event: "".to_string(),
                 status: "".to_string(),
                 conclusion: match event {
@@ -205,19 +224,25 @@ pub struct GitHubCheckRunEventPayload {
     repository: GitHubRepository,
 }
 
-impl From<Workflow> for GitHubCheckRunEventPayload {
-    fn from(workflow: Workflow) -> Self {
+impl GitHubCheckRunEventPayload {
+    /// Creates a payload for a check run that has been completed with the given conclusion.
+    pub fn completed(workflow: Workflow, conclusion: &str) -> Self {
+        Self::new(workflow, "completed", Some(conclusion.to_string()))
+    }
+
+    fn new(workflow: Workflow, action: &str, conclusion: Option<String>) -> Self {
         assert!(workflow.external);
 
         let mut app = GitHubApp::default();
         // We need the owner not to be GitHub
         app.owner = User::new(1234, "external-ci").into();
         Self {
-            action: "created".to_string(),
+            action: action.to_string(),
             check_run: GitHubCheckRunInner {
                 check_run: GitHubCheckRun {
                     id: workflow.run_id.into(),
                     html_url: format!("https://external-ci.com/workflows/{}", workflow.run_id),
+                    conclusion,
                 },
                 name: workflow.name,
                 check_suite: GitHubCheckSuiteInner {
@@ -231,6 +256,12 @@ impl From<Workflow> for GitHubCheckRunEventPayload {
     }
 }
 
+impl From<Workflow> for GitHubCheckRunEventPayload {
+    fn from(workflow: Workflow) -> Self {
+        Self::new(workflow, "created", None)
+    }
+}
+
 #[derive(Serialize)]
 struct GitHubCheckRunInner {
     #[serde(flatten)]
@@ -244,6 +275,8 @@ struct GitHubCheckRunInner {
 struct GitHubCheckRun {
     id: CheckRunId,
     html_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conclusion: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -255,6 +288,7 @@ struct GitHubWorkflowRun {
     head_branch: String,
     head_sha: String,
     run_number: i64,
+    run_attempt: i64,
     event: String,
     status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
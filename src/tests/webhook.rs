@@ -1,11 +1,18 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use axum::body::Body;
 use hmac::{Hmac, Mac};
 use http::Request;
 use sha2::Sha256;
 
 pub const TEST_WEBHOOK_SECRET: &str = "ABCDEF";
+pub const TEST_DELIVERY_ID: &str = "72d3162e-cc78-11e3-81ab-4c9367dc0958";
+
+/// Repeated deliveries are ignored, so each test webhook gets its own delivery ID.
+static NEXT_DELIVERY: AtomicU64 = AtomicU64::new(1);
 
 pub fn create_webhook_request(event: &str, body: &str) -> Request<Body> {
+    let delivery = NEXT_DELIVERY.fetch_add(1, Ordering::Relaxed);
     let mut mac = Hmac::<Sha256>::new_from_slice(TEST_WEBHOOK_SECRET.as_bytes()).unwrap();
     mac.update(body.as_bytes());
     let signature = hex::encode(mac.finalize().into_bytes());
@@ -14,6 +21,10 @@ pub fn create_webhook_request(event: &str, body: &str) -> Request<Body> {
 
     Request::post("/github")
         .header("x-github-event", event)
+        .header(
+            "x-github-delivery",
+            format!("{TEST_DELIVERY_ID}-{delivery}"),
+        )
         .header("x-hub-signature-256", signature)
         .header("Content-Type", "application/json")
         .body(Body::from(body.to_string()))
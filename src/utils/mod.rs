@@ -1,2 +1,4 @@
 pub mod logging;
+#[cfg(feature = "sentry")]
+pub mod sentry;
 pub mod timing;
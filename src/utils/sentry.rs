@@ -0,0 +1,14 @@
+use anyhow::Error;
+
+/// Reports an error to Sentry, with the given tags attached to it.
+/// It does nothing if Sentry was not initialized.
+pub fn report_error(error: &Error, tags: &[(&str, String)]) {
+    sentry::with_scope(
+        |scope| {
+            for (key, value) in tags {
+                scope.set_tag(key, value);
+            }
+        },
+        || sentry::integrations::anyhow::capture_anyhow(error),
+    );
+}